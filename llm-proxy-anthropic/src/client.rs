@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use llm_proxy_core::{
+    upstream, AuthScheme, ClientProvider, Error, LLMClient, ProviderCapabilities, RequestContext,
+    Result, TokenProvider, UrlProvider,
+};
+use llm_proxy_openai::ChatCompletionRequest;
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::{
+    translate::{to_anthropic_request, to_openai_response, StreamTranslator},
+    types::{ErrorResponse, MessagesRequest},
+};
+
+/// Version of the Messages API sent in the `anthropic-version` header
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Name of the upstream service in errors
+const UPSTREAM: &str = "Anthropic";
+
+/// `max_tokens` sent when a request doesn't set it, as the Messages API requires it
+pub const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+/// Anthropic implementation of `LLMClient` for `OpenAI` chat completion requests.
+///
+/// Requests are translated into the Messages API format and responses are
+/// translated back, so clients written against the `OpenAI` API can use
+/// Claude models without changes.
+#[derive(Clone)]
+pub struct AnthropicClient {
+    client: Arc<dyn ClientProvider>,
+    token: Arc<dyn TokenProvider>,
+    url: Arc<dyn UrlProvider>,
+    auth_scheme: AuthScheme,
+    headers: Vec<(String, String)>,
+    default_max_tokens: u32,
+    capabilities: ProviderCapabilities,
+}
+
+impl AnthropicClient {
+    /// Create a new Anthropic client with the given providers
+    pub fn new(
+        client_provider: Arc<dyn ClientProvider>,
+        token_provider: Arc<dyn TokenProvider>,
+        url_provider: Arc<dyn UrlProvider>,
+    ) -> Self {
+        Self {
+            client: client_provider,
+            token: token_provider,
+            url: url_provider,
+            auth_scheme: AuthScheme::x_api_key(),
+            headers: Vec::new(),
+            default_max_tokens: DEFAULT_MAX_TOKENS,
+            capabilities: ProviderCapabilities::default(),
+        }
+    }
+
+    /// Replace the provider of the HTTP client used for upstream requests
+    #[must_use]
+    pub fn with_client_provider(mut self, client_provider: Arc<dyn ClientProvider>) -> Self {
+        self.client = client_provider;
+        self
+    }
+
+    /// Set how the API token is sent to the upstream service
+    #[must_use]
+    pub fn with_auth_scheme(mut self, auth_scheme: AuthScheme) -> Self {
+        self.auth_scheme = auth_scheme;
+        self
+    }
+
+    /// Send a static header with every request, e.g. `anthropic-beta`
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the `max_tokens` sent when a request doesn't set it
+    #[must_use]
+    pub const fn with_default_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.default_max_tokens = max_tokens;
+        self
+    }
+
+    /// Declare what the upstream backend supports, e.g. its models
+    #[must_use]
+    pub fn with_capabilities(mut self, capabilities: ProviderCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Build the upstream HTTP request, including authentication and headers
+    fn build_request(
+        &self,
+        request: &MessagesRequest,
+        client: &reqwest::Client,
+        token: &str,
+        url: String,
+    ) -> reqwest::RequestBuilder {
+        let mut builder = self
+            .auth_scheme
+            .apply(client.post(url), token)
+            .header("anthropic-version", ANTHROPIC_VERSION);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        builder.json(request)
+    }
+}
+
+#[async_trait]
+impl LLMClient<ChatCompletionRequest> for AnthropicClient {
+    async fn execute(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        self.execute_with_context(request, &RequestContext::new())
+            .await
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.capabilities.clone()
+    }
+
+    async fn execute_with_context(
+        &self,
+        request: ChatCompletionRequest,
+        context: &RequestContext,
+    ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        // 1. Translate the request
+        let request = to_anthropic_request(&request, self.default_max_tokens)?;
+
+        // 2. Get dependencies
+        let client = self
+            .client
+            .get_client()
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get HTTP client: {e}")))?;
+        let token = self
+            .token
+            .get_token_for(context)
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get API token: {e}")))?;
+        let url = self
+            .url
+            .get_url()
+            .map_err(|e| Error::LLMError(format!("Failed to get API URL: {e}")))?;
+
+        // 3. Send request
+        let builder = self.build_request(&request, &client, &token, url);
+        let rejected = Some((self.token.as_ref(), token.as_str()));
+        let response =
+            upstream::send_request::<ErrorResponse>(UPSTREAM, builder, None, rejected).await?;
+
+        // 4. Translate the response based on streaming flag
+        let (tx, rx) = mpsc::channel(100);
+        let stream = request.stream;
+        info!("The request is streaming: {}", stream);
+        tokio::spawn(async move {
+            if stream {
+                upstream::forward_stream(UPSTREAM, response, StreamTranslator::new(), &tx).await;
+            } else {
+                upstream::forward_response(UPSTREAM, response, to_openai_response, &tx).await;
+            }
+        });
+
+        Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AnthropicUrlProvider;
+
+    struct MockClientProvider;
+    struct MockTokenProvider;
+
+    #[async_trait]
+    impl ClientProvider for MockClientProvider {
+        async fn get_client(&self) -> Result<reqwest::Client> {
+            Ok(reqwest::Client::new())
+        }
+    }
+
+    #[async_trait]
+    impl TokenProvider for MockTokenProvider {
+        async fn get_token(&self) -> Result<String> {
+            Ok("sk-ant-test".to_string())
+        }
+    }
+
+    #[test]
+    fn test_request_headers() {
+        let client = AnthropicClient::new(
+            Arc::new(MockClientProvider),
+            Arc::new(MockTokenProvider),
+            Arc::new(AnthropicUrlProvider::messages()),
+        )
+        .with_header("anthropic-beta", "prompt-caching-2024-07-31");
+
+        let chat = ChatCompletionRequest::new_block("claude-sonnet-4-5".to_string(), vec![]);
+        let request = to_anthropic_request(&chat, DEFAULT_MAX_TOKENS).expect("Failed to translate");
+        let http_request = client
+            .build_request(
+                &request,
+                &reqwest::Client::new(),
+                "sk-ant-test",
+                "https://api.anthropic.com/v1/messages".to_string(),
+            )
+            .build()
+            .expect("Failed to build request");
+
+        let headers = http_request.headers();
+        assert_eq!(headers["x-api-key"], "sk-ant-test");
+        assert_eq!(headers["anthropic-version"], ANTHROPIC_VERSION);
+        assert_eq!(headers["anthropic-beta"], "prompt-caching-2024-07-31");
+        assert!(headers.get("Authorization").is_none());
+    }
+}
@@ -0,0 +1,58 @@
+//! Provider factory for the Anthropic Messages API, registered as `anthropic`.
+
+use async_trait::async_trait;
+use llm_proxy_core::{Pipeline, ProviderContext, ProviderFactory, ProviderRegistry, Result};
+use llm_proxy_openai::ChatCompletionRequest;
+use serde::Deserialize;
+
+use crate::{
+    create_chat_client, create_chat_client_with_url_provider, create_pipeline_with_client,
+};
+
+/// Register the factory of this crate in `registry`
+pub fn register(registry: &mut ProviderRegistry<ChatCompletionRequest>) {
+    registry.register("anthropic", AnthropicFactory);
+}
+
+/// Settings of an Anthropic backend
+#[derive(Debug, Deserialize, Clone)]
+pub struct AnthropicSettings {
+    /// `max_tokens` sent when a request doesn't set it
+    #[serde(default)]
+    pub default_max_tokens: Option<u32>,
+}
+
+/// Factory for the Anthropic Messages API, with [`AnthropicSettings`] as settings
+pub struct AnthropicFactory;
+
+#[async_trait]
+impl ProviderFactory<ChatCompletionRequest> for AnthropicFactory {
+    async fn create_pipeline(
+        &self,
+        context: ProviderContext,
+    ) -> Result<Pipeline<ChatCompletionRequest>> {
+        let settings: AnthropicSettings = context.settings()?;
+        let token_provider = context.require_token_provider()?;
+
+        let mut client = match &context.url_provider {
+            Some(url_provider) => {
+                create_chat_client_with_url_provider(token_provider, url_provider.clone())
+            }
+            None => create_chat_client(token_provider, Some(&context.base_url)),
+        };
+        if let Some(client_provider) = &context.client_provider {
+            client = client.with_client_provider(client_provider.clone());
+        }
+        if let Some(auth_scheme) = &context.auth_scheme {
+            client = client.with_auth_scheme(auth_scheme.clone());
+        }
+        for (name, value) in &context.headers {
+            client = client.with_header(name, value);
+        }
+        if let Some(max_tokens) = settings.default_max_tokens {
+            client = client.with_default_max_tokens(max_tokens);
+        }
+        client = client.with_capabilities(context.capabilities);
+        Ok(create_pipeline_with_client(vec![], client))
+    }
+}
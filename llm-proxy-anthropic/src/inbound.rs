@@ -0,0 +1,548 @@
+//! Serving Messages API clients from `OpenAI` chat completion backends.
+//!
+//! The reverse of [`translate`](crate::translate), for clients speaking the
+//! Anthropic Messages API to a proxy whose pipelines use the `OpenAI` format:
+//!
+//! - [`to_openai_request`] converts an inbound Messages API request into an
+//!   `OpenAI` chat completion request.
+//! - [`to_messages_response`] converts an `OpenAI` chat completion into a
+//!   Messages API response.
+//! - [`ChunkTranslator`] converts the server-sent events of a streamed chat
+//!   completion into Messages API stream events.
+
+use bytes::Bytes;
+use serde_json::{json, Map, Value};
+
+use crate::types::{ContentBlock, ImageSource, MessagesRequest, ToolChoice};
+
+/// Convert a Messages API request into an `OpenAI` chat completion request.
+///
+/// The system prompt becomes a system message, `tool_use` blocks become tool
+/// calls of the assistant, and `tool_result` blocks become `tool` messages
+/// preceding the rest of their user turn. Block types without an `OpenAI`
+/// equivalent, such as thinking, are dropped.
+#[must_use]
+pub fn to_openai_request(request: MessagesRequest) -> Value {
+    let mut messages = Vec::new();
+    if let Some(system) = request.system {
+        messages.push(json!({ "role": "system", "content": system }));
+    }
+    for message in request.messages {
+        if message.role == "assistant" {
+            messages.push(assistant_message(message.content));
+        } else {
+            messages.extend(user_messages(message.content));
+        }
+    }
+
+    let mut body = Map::new();
+    body.insert("model".to_string(), json!(request.model));
+    body.insert("messages".to_string(), Value::Array(messages));
+    body.insert("max_tokens".to_string(), json!(request.max_tokens));
+    body.insert("stream".to_string(), json!(request.stream));
+    if let Some(temperature) = request.temperature {
+        body.insert("temperature".to_string(), json!(temperature));
+    }
+    if let Some(top_p) = request.top_p {
+        body.insert("top_p".to_string(), json!(top_p));
+    }
+    if !request.stop_sequences.is_empty() {
+        body.insert("stop".to_string(), json!(request.stop_sequences));
+    }
+    if !request.tools.is_empty() {
+        let tools = request
+            .tools
+            .into_iter()
+            .map(|tool| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.input_schema,
+                    },
+                })
+            })
+            .collect();
+        body.insert("tools".to_string(), Value::Array(tools));
+    }
+    if let Some(choice) = request.tool_choice {
+        body.insert("tool_choice".to_string(), tool_choice(choice));
+    }
+    if let Some(metadata) = request.metadata {
+        body.insert("user".to_string(), json!(metadata.user_id));
+    }
+    Value::Object(body)
+}
+
+/// The `tool` messages and user message of a user turn
+fn user_messages(blocks: Vec<ContentBlock>) -> Vec<Value> {
+    let mut messages = Vec::new();
+    let mut parts = Vec::new();
+    for block in blocks {
+        match block {
+            ContentBlock::Text { text } => parts.push(json!({ "type": "text", "text": text })),
+            ContentBlock::Image { source } => parts.push(json!({
+                "type": "image_url",
+                "image_url": { "url": image_url(source) },
+            })),
+            ContentBlock::ToolResult {
+                tool_use_id,
+                content,
+            } => messages.push(json!({
+                "role": "tool",
+                "tool_call_id": tool_use_id,
+                "content": content,
+            })),
+            ContentBlock::ToolUse { .. } | ContentBlock::Other(_) => {}
+        }
+    }
+    let content = match parts.as_slice() {
+        [] => return messages,
+        [part] if part["type"] == "text" => part["text"].clone(),
+        _ => Value::Array(parts),
+    };
+    messages.push(json!({ "role": "user", "content": content }));
+    messages
+}
+
+/// The assistant message of an assistant turn, with its tool calls
+fn assistant_message(blocks: Vec<ContentBlock>) -> Value {
+    let mut text = String::new();
+    let mut tool_calls = Vec::new();
+    for block in blocks {
+        match block {
+            ContentBlock::Text { text: part } => text.push_str(&part),
+            ContentBlock::ToolUse { id, name, input } => tool_calls.push(json!({
+                "id": id,
+                "type": "function",
+                "function": { "name": name, "arguments": input.to_string() },
+            })),
+            _ => {}
+        }
+    }
+    let mut message = json!({
+        "role": "assistant",
+        "content": (!text.is_empty()).then_some(text),
+    });
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+    message
+}
+
+/// The URL of an image, a `data:` URL for inline images
+fn image_url(source: ImageSource) -> String {
+    match source {
+        ImageSource::Base64 { media_type, data } => format!("data:{media_type};base64,{data}"),
+        ImageSource::Url { url } => url,
+    }
+}
+
+/// The `tool_choice` parameter for a Messages API tool choice
+fn tool_choice(choice: ToolChoice) -> Value {
+    match choice {
+        ToolChoice::Auto => json!("auto"),
+        ToolChoice::Any => json!("required"),
+        ToolChoice::None => json!("none"),
+        ToolChoice::Tool { name } => json!({ "type": "function", "function": { "name": name } }),
+    }
+}
+
+/// The Anthropic stop reason for an `OpenAI` finish reason
+fn stop_reason(finish_reason: &str) -> &'static str {
+    match finish_reason {
+        "length" => "max_tokens",
+        "tool_calls" | "function_call" => "tool_use",
+        "content_filter" => "refusal",
+        _ => "end_turn",
+    }
+}
+
+/// The input of a tool call from its JSON arguments, empty if they aren't valid
+fn tool_input(arguments: &Value) -> Value {
+    arguments
+        .as_str()
+        .and_then(|arguments| serde_json::from_str(arguments).ok())
+        .unwrap_or_else(|| json!({}))
+}
+
+/// Convert an `OpenAI` chat completion into a Messages API response
+#[must_use]
+pub fn to_messages_response(completion: &Value) -> Value {
+    let choice = &completion["choices"][0];
+    let message = &choice["message"];
+    let mut content = Vec::new();
+    if let Some(text) = message["content"].as_str().filter(|text| !text.is_empty()) {
+        content.push(json!({ "type": "text", "text": text }));
+    }
+    for call in message["tool_calls"].as_array().into_iter().flatten() {
+        content.push(json!({
+            "type": "tool_use",
+            "id": call["id"],
+            "name": call["function"]["name"],
+            "input": tool_input(&call["function"]["arguments"]),
+        }));
+    }
+    json!({
+        "id": completion["id"],
+        "type": "message",
+        "role": "assistant",
+        "model": completion["model"],
+        "content": content,
+        "stop_reason": choice["finish_reason"].as_str().map(stop_reason),
+        "stop_sequence": null,
+        "usage": {
+            "input_tokens": completion["usage"]["prompt_tokens"].as_u64().unwrap_or_default(),
+            "output_tokens": completion["usage"]["completion_tokens"].as_u64().unwrap_or_default(),
+        },
+    })
+}
+
+/// A content block being streamed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpenBlock {
+    Text,
+    /// A tool call, by its index in the `OpenAI` chunks
+    ToolUse(u64),
+}
+
+/// Converts the chunks of a streamed `OpenAI` chat completion into Messages API events.
+///
+/// Feed the raw bytes of the stream to [`ChunkTranslator::push`] as they
+/// arrive; chunks may be split across reads. Events are returned as
+/// server-sent events with their `event` type. The message ends with the
+/// `data: [DONE]` line, or with [`ChunkTranslator::finish`] for streams that
+/// end without one.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_anthropic::inbound::ChunkTranslator;
+///
+/// let mut translator = ChunkTranslator::new();
+/// let events = translator.push(
+///     b"data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"}}]}\n\n",
+/// );
+/// // message_start, content_block_start and content_block_delta
+/// assert_eq!(events.len(), 3);
+/// ```
+#[derive(Debug, Default)]
+pub struct ChunkTranslator {
+    started: bool,
+    finished: bool,
+    /// Index of the next content block
+    blocks: usize,
+    open: Option<OpenBlock>,
+    stop_reason: Option<&'static str>,
+    input_tokens: u64,
+    output_tokens: u64,
+    /// Bytes of an incomplete line
+    buffer: Vec<u8>,
+}
+
+impl ChunkTranslator {
+    /// Create a translator for a new response
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Translate the next bytes of the stream
+    ///
+    /// Lines that aren't chunks, such as comments, are skipped.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Bytes> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else {
+                continue;
+            };
+            if data == "[DONE]" {
+                events.extend(self.finish());
+            } else if let Ok(chunk) = serde_json::from_str::<Value>(data) {
+                self.translate(&chunk, &mut events);
+            }
+        }
+        events
+    }
+
+    /// End the message, if it hasn't ended yet
+    pub fn finish(&mut self) -> Vec<Bytes> {
+        if self.finished {
+            return Vec::new();
+        }
+        self.finished = true;
+        let mut events = Vec::new();
+        if !self.started {
+            self.start(&json!({}), &mut events);
+        }
+        self.close_block(&mut events);
+        events.push(event(
+            "message_delta",
+            &json!({
+                "type": "message_delta",
+                "delta": { "stop_reason": self.stop_reason.unwrap_or("end_turn"), "stop_sequence": null },
+                "usage": { "output_tokens": self.output_tokens },
+            }),
+        ));
+        events.push(event("message_stop", &json!({ "type": "message_stop" })));
+        events
+    }
+
+    /// An `error` event for a stream that failed
+    #[must_use]
+    pub fn error(message: &str) -> Bytes {
+        event(
+            "error",
+            &json!({ "type": "error", "error": { "type": "api_error", "message": message } }),
+        )
+    }
+
+    fn translate(&mut self, chunk: &Value, events: &mut Vec<Bytes>) {
+        if let Some(usage) = chunk.get("usage").filter(|usage| usage.is_object()) {
+            self.input_tokens = usage["prompt_tokens"].as_u64().unwrap_or(self.input_tokens);
+            self.output_tokens = usage["completion_tokens"]
+                .as_u64()
+                .unwrap_or(self.output_tokens);
+        }
+        if !self.started {
+            self.start(chunk, events);
+        }
+        let Some(choice) = chunk["choices"].get(0) else {
+            return;
+        };
+        let delta = &choice["delta"];
+        if let Some(text) = delta["content"].as_str().filter(|text| !text.is_empty()) {
+            self.open_block(
+                OpenBlock::Text,
+                &json!({ "type": "text", "text": "" }),
+                events,
+            );
+            events.push(self.delta(&json!({ "type": "text_delta", "text": text })));
+        }
+        for call in delta["tool_calls"].as_array().into_iter().flatten() {
+            let index = call["index"].as_u64().unwrap_or_default();
+            if self.open != Some(OpenBlock::ToolUse(index)) {
+                let block = json!({
+                    "type": "tool_use",
+                    "id": call["id"],
+                    "name": call["function"]["name"],
+                    "input": {},
+                });
+                self.open_block(OpenBlock::ToolUse(index), &block, events);
+            }
+            if let Some(arguments) = call["function"]["arguments"]
+                .as_str()
+                .filter(|arguments| !arguments.is_empty())
+            {
+                events.push(self.delta(&json!({
+                    "type": "input_json_delta",
+                    "partial_json": arguments,
+                })));
+            }
+        }
+        if let Some(finish_reason) = choice["finish_reason"].as_str() {
+            self.stop_reason = Some(stop_reason(finish_reason));
+        }
+    }
+
+    fn start(&mut self, chunk: &Value, events: &mut Vec<Bytes>) {
+        self.started = true;
+        events.push(event(
+            "message_start",
+            &json!({
+                "type": "message_start",
+                "message": {
+                    "id": chunk["id"],
+                    "type": "message",
+                    "role": "assistant",
+                    "model": chunk["model"],
+                    "content": [],
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": { "input_tokens": self.input_tokens, "output_tokens": 0 },
+                },
+            }),
+        ));
+    }
+
+    /// Start a block of `kind`, unless it is the one open already
+    fn open_block(&mut self, kind: OpenBlock, block: &Value, events: &mut Vec<Bytes>) {
+        if self.open == Some(kind) {
+            return;
+        }
+        self.close_block(events);
+        self.open = Some(kind);
+        events.push(event(
+            "content_block_start",
+            &json!({ "type": "content_block_start", "index": self.blocks, "content_block": block }),
+        ));
+    }
+
+    fn close_block(&mut self, events: &mut Vec<Bytes>) {
+        if self.open.take().is_some() {
+            events.push(event(
+                "content_block_stop",
+                &json!({ "type": "content_block_stop", "index": self.blocks }),
+            ));
+            self.blocks += 1;
+        }
+    }
+
+    fn delta(&self, delta: &Value) -> Bytes {
+        event(
+            "content_block_delta",
+            &json!({ "type": "content_block_delta", "index": self.blocks, "delta": delta }),
+        )
+    }
+}
+
+/// A server-sent event of type `name`
+fn event(name: &str, data: &Value) -> Bytes {
+    Bytes::from(format!("event: {name}\ndata: {data}\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event_types(events: &[Bytes]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| {
+                let event = std::str::from_utf8(event).ok()?;
+                Some(event.lines().next()?.strip_prefix("event: ")?.to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_request_translation() {
+        let request: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "system": [{ "type": "text", "text": "Be brief." }],
+            "messages": [
+                { "role": "user", "content": "Weather in Paris?" },
+                { "role": "assistant", "content": [
+                    { "type": "text", "text": "Checking." },
+                    { "type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": { "city": "Paris" } },
+                ] },
+                { "role": "user", "content": [
+                    { "type": "tool_result", "tool_use_id": "toolu_1", "content": [{ "type": "text", "text": "Sunny" }] },
+                    { "type": "text", "text": "Thanks" },
+                ] },
+            ],
+            "tools": [{ "name": "get_weather", "input_schema": { "type": "object" } }],
+            "tool_choice": { "type": "any" },
+            "stop_sequences": ["END"],
+            "stream": true,
+        }))
+        .expect("Invalid request");
+
+        let body = to_openai_request(request);
+        assert_eq!(body["max_tokens"], 1024);
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["stop"], json!(["END"]));
+        assert_eq!(body["tool_choice"], "required");
+        assert_eq!(body["tools"][0]["function"]["name"], "get_weather");
+        let messages = body["messages"].as_array().expect("No messages");
+        let roles: Vec<_> = messages.iter().map(|m| m["role"].as_str()).collect();
+        assert_eq!(
+            roles,
+            [
+                Some("system"),
+                Some("user"),
+                Some("assistant"),
+                Some("tool"),
+                Some("user")
+            ]
+        );
+        assert_eq!(messages[0]["content"], "Be brief.");
+        assert_eq!(messages[2]["content"], "Checking.");
+        assert_eq!(
+            messages[2]["tool_calls"][0]["function"]["arguments"],
+            "{\"city\":\"Paris\"}"
+        );
+        assert_eq!(messages[3]["tool_call_id"], "toolu_1");
+        assert_eq!(messages[3]["content"], "Sunny");
+        assert_eq!(messages[4]["content"], "Thanks");
+    }
+
+    #[test]
+    fn test_response_translation() {
+        let response = to_messages_response(&json!({
+            "id": "chatcmpl-1",
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" },
+                    }],
+                },
+                "finish_reason": "tool_calls",
+            }],
+            "usage": { "prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15 },
+        }));
+        assert_eq!(response["type"], "message");
+        assert_eq!(response["stop_reason"], "tool_use");
+        assert_eq!(response["content"][0]["type"], "tool_use");
+        assert_eq!(response["content"][0]["input"]["city"], "Paris");
+        assert_eq!(response["usage"]["input_tokens"], 10);
+        assert_eq!(response["usage"]["output_tokens"], 5);
+    }
+
+    #[test]
+    fn test_stream_translation() {
+        let stream = concat!(
+            "data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n\n",
+            "data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Let me check.\"}}]}\n\n",
+            "data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"get_weather\",\"arguments\":\"\"}}]}}]}\n\n",
+            "data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"{\\\"city\\\":\\\"Paris\\\"}\"}}]}}]}\n\n",
+            "data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"tool_calls\"}],\"usage\":{\"prompt_tokens\":10,\"completion_tokens\":7}}\n\n",
+            "data: [DONE]\n\n",
+        );
+
+        // Split the stream in the middle of a chunk
+        let mut translator = ChunkTranslator::new();
+        let (first, second) = stream.as_bytes().split_at(150);
+        let mut events = translator.push(first);
+        events.extend(translator.push(second));
+        events.extend(translator.finish());
+
+        assert_eq!(
+            event_types(&events),
+            [
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        let data = |index: usize| -> Value {
+            let event = std::str::from_utf8(&events[index]).expect("Invalid UTF-8");
+            let data = event.lines().nth(1).expect("No data");
+            serde_json::from_str(data.trim_start_matches("data: ")).expect("Invalid event")
+        };
+        assert_eq!(data(0)["message"]["id"], "c1");
+        assert_eq!(data(2)["delta"]["text"], "Let me check.");
+        assert_eq!(data(4)["index"], 1);
+        assert_eq!(data(4)["content_block"]["name"], "get_weather");
+        assert_eq!(data(5)["delta"]["partial_json"], "{\"city\":\"Paris\"}");
+        assert_eq!(data(7)["delta"]["stop_reason"], "tool_use");
+        assert_eq!(data(7)["usage"]["output_tokens"], 7);
+    }
+}
@@ -0,0 +1,138 @@
+//! # LLM Proxy Anthropic
+//!
+//! This crate routes `OpenAI`-format chat completion requests to Anthropic's
+//! Messages API, so existing `OpenAI` SDK clients can use Claude models by
+//! changing only the proxy configuration.
+//!
+//! ## Components
+//!
+//! ### Client
+//! The [`client`] module provides [`AnthropicClient`], an `LLMClient` for
+//! [`ChatCompletionRequest`]s that sends them to the Messages API and answers
+//! in the `OpenAI` format, both for streaming and non-streaming requests.
+//!
+//! ### Inbound
+//! The [`inbound`] module serves clients of the Messages API from `OpenAI`
+//! chat completion pipelines, converting their requests, responses and
+//! stream events.
+//!
+//! ### Factory
+//! The [`factory`] module registers the `anthropic` provider in a
+//! [`ProviderRegistry`](llm_proxy_core::ProviderRegistry).
+//!
+//! ### Translate
+//! The [`translate`] module converts requests, responses and stream events
+//! between the two formats, including system prompts, images and tool calls.
+//!
+//! ### Types
+//! The [`types`] module defines the Messages API requests, responses and
+//! stream events.
+//!
+//! ## Example Usage
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use llm_proxy_anthropic::create_chat_pipeline;
+//! use llm_proxy_openai::EnvTokenProvider;
+//!
+//! # async fn example() -> llm_proxy_core::Result<()> {
+//! let pipeline = create_chat_pipeline(
+//!     vec![],
+//!     Arc::new(EnvTokenProvider::new("ANTHROPIC_API_KEY")),
+//!     None,
+//! );
+//!
+//! // Requests and responses use the OpenAI chat completions format
+//! let request = bytes::Bytes::from(
+//!     r#"{"model": "claude-sonnet-4-5", "messages": [{"role": "user", "content": "Hi"}]}"#,
+//! );
+//! let response = pipeline.execute(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Configuration
+//!
+//! ```toml
+//! [llm.claude]
+//! provider = "anthropic"
+//! type = "chat"
+//! base_url = "https://api.anthropic.com/v1"
+//! token_env = "ANTHROPIC_API_KEY"
+//! supports_streaming = true
+//! additional_config = { default_max_tokens = 8192 }
+//! ```
+
+pub mod client;
+pub mod factory;
+pub mod inbound;
+pub mod providers;
+pub mod translate;
+pub mod types;
+
+use std::sync::Arc;
+
+use llm_proxy_core::{Pipeline, Processor, ProcessorChain, TokenProvider, UrlProvider};
+use llm_proxy_openai::{
+    providers::StaticClientProvider, ChatCompletionRequest, OpenAIRequestParser,
+};
+
+pub use client::{AnthropicClient, ANTHROPIC_VERSION, DEFAULT_MAX_TOKENS};
+pub use inbound::{to_messages_response, to_openai_request, ChunkTranslator};
+pub use providers::AnthropicUrlProvider;
+pub use translate::{to_anthropic_request, to_openai_response, StreamTranslator};
+
+/// Create a new pipeline that serves `OpenAI` chat completion requests with Claude.
+///
+/// # Arguments
+/// * `processors` - List of processors to apply to requests
+/// * `token_provider` - Provider of the Anthropic API key
+/// * `base_url` - Optional base URL for the API (default: "<https://api.anthropic.com/v1/messages>")
+#[must_use]
+pub fn create_chat_pipeline(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    token_provider: Arc<dyn TokenProvider>,
+    base_url: Option<&str>,
+) -> Pipeline<ChatCompletionRequest> {
+    create_pipeline_with_client(processors, create_chat_client(token_provider, base_url))
+}
+
+/// Create an Anthropic client for the Messages API.
+///
+/// The client can be customized further (e.g. with `anthropic-beta` headers)
+/// before being passed to [`create_pipeline_with_client`].
+#[must_use]
+pub fn create_chat_client(
+    token_provider: Arc<dyn TokenProvider>,
+    base_url: Option<&str>,
+) -> AnthropicClient {
+    let url_provider =
+        Arc::new(base_url.map_or_else(AnthropicUrlProvider::messages, AnthropicUrlProvider::new));
+    create_chat_client_with_url_provider(token_provider, url_provider)
+}
+
+/// Create an Anthropic client whose request URL is chosen by `url_provider`.
+#[must_use]
+pub fn create_chat_client_with_url_provider(
+    token_provider: Arc<dyn TokenProvider>,
+    url_provider: Arc<dyn UrlProvider>,
+) -> AnthropicClient {
+    let client_provider = Arc::new(StaticClientProvider::new());
+    AnthropicClient::new(client_provider, token_provider, url_provider)
+}
+
+/// Create a chat completion pipeline around an existing Anthropic client.
+///
+/// Requests are parsed in the `OpenAI` format, so processors written for
+/// `OpenAI` requests apply unchanged.
+#[must_use]
+pub fn create_pipeline_with_client(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    llm_client: AnthropicClient,
+) -> Pipeline<ChatCompletionRequest> {
+    let parser = Arc::new(OpenAIRequestParser::new());
+    let processor_chain = Arc::new(ProcessorChain::new(processors));
+
+    Pipeline::new(parser, processor_chain, Arc::new(llm_client))
+}
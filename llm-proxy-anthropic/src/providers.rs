@@ -0,0 +1,56 @@
+use llm_proxy_core::{Result, UrlProvider};
+
+/// Provider that returns the URL of an Anthropic Messages API endpoint
+pub struct AnthropicUrlProvider {
+    endpoint: String,
+}
+
+impl AnthropicUrlProvider {
+    /// Create a provider for the Messages API at `base_url`
+    ///
+    /// Both the API base (`https://api.anthropic.com/v1`) and the full
+    /// endpoint (`https://api.anthropic.com/v1/messages`) are accepted.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into();
+        let base_url = base_url.trim_end_matches('/');
+        let endpoint = if base_url.ends_with("/messages") {
+            base_url.to_string()
+        } else {
+            format!("{base_url}/messages")
+        };
+        Self { endpoint }
+    }
+
+    /// Create a provider for the Anthropic Messages API
+    #[must_use]
+    pub fn messages() -> Self {
+        Self::new("https://api.anthropic.com/v1/messages")
+    }
+}
+
+impl UrlProvider for AnthropicUrlProvider {
+    fn get_url(&self) -> Result<String> {
+        Ok(self.endpoint.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_provider() {
+        for base_url in [
+            "https://api.anthropic.com/v1",
+            "https://api.anthropic.com/v1/",
+            "https://api.anthropic.com/v1/messages",
+        ] {
+            assert_eq!(
+                AnthropicUrlProvider::new(base_url)
+                    .get_url()
+                    .expect("Failed to get URL"),
+                "https://api.anthropic.com/v1/messages"
+            );
+        }
+    }
+}
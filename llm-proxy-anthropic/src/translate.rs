@@ -0,0 +1,550 @@
+//! Translation between the `OpenAI` chat completions format and the Anthropic
+//! Messages API.
+//!
+//! - [`to_anthropic_request`] converts an inbound `OpenAI` chat completion
+//!   request into a Messages API request.
+//! - [`to_openai_response`] converts a Messages API response into an `OpenAI`
+//!   chat completion.
+//! - [`StreamTranslator`] converts the server-sent events of a streaming
+//!   Messages API response into `OpenAI` chat completion chunks.
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use llm_proxy_core::{unix_now, upstream::ChunkTranslator, Error, Result};
+use llm_proxy_openai::{ChatCompletionRequest, ContentPart, Message, MessageContent};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::types::{
+    AnthropicMessage, BlockDelta, ContentBlock, ImageSource, MessagesRequest, MessagesResponse,
+    Metadata, StreamEvent, Tool, ToolChoice,
+};
+
+/// A tool definition in an `OpenAI` request
+#[derive(Deserialize)]
+struct OpenAITool {
+    function: OpenAIFunction,
+}
+
+/// A function definition in an `OpenAI` request
+#[derive(Deserialize)]
+struct OpenAIFunction {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    parameters: Option<Value>,
+}
+
+/// The `stop` parameter, either a single sequence or a list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stop {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Convert an `OpenAI` chat completion request into a Messages API request.
+///
+/// System and developer messages become the system prompt, tool calls and
+/// tool results become `tool_use` and `tool_result` blocks, and image URLs
+/// become image blocks. Consecutive messages of the same role are merged, as
+/// the Messages API requires turns to alternate. `default_max_tokens` is used
+/// when the request doesn't limit the number of tokens, since the Messages
+/// API requires a limit.
+///
+/// # Errors
+///
+/// This function will return an error if a message has an unknown role, a
+/// tool result doesn't reference a tool call, or tool call arguments are not
+/// valid JSON.
+pub fn to_anthropic_request(
+    request: &ChatCompletionRequest,
+    default_max_tokens: u32,
+) -> Result<MessagesRequest> {
+    let mut system = Vec::new();
+    let mut messages: Vec<AnthropicMessage> = Vec::new();
+    for message in &request.messages {
+        let (role, content) = match message.role.as_str() {
+            "system" | "developer" => {
+                if let Some(content) = &message.content {
+                    system.push(content.text());
+                }
+                continue;
+            }
+            "user" => ("user", user_blocks(message)),
+            "assistant" => ("assistant", assistant_blocks(message)?),
+            "tool" | "function" => ("user", vec![tool_result(message)?]),
+            role => {
+                return Err(Error::ParseError(format!(
+                    "Unsupported message role: {role}"
+                )))
+            }
+        };
+        if content.is_empty() {
+            continue;
+        }
+        match messages.last_mut() {
+            Some(last) if last.role == role => last.content.extend(content),
+            _ => messages.push(AnthropicMessage {
+                role: role.to_string(),
+                content,
+            }),
+        }
+    }
+
+    let mut tools: Vec<Tool> = request
+        .param::<Vec<OpenAITool>>("tools")
+        .unwrap_or_default()
+        .into_iter()
+        .map(|tool| to_tool(tool.function))
+        .collect();
+    tools.extend(request.functions.iter().flatten().map(|function| {
+        to_tool(OpenAIFunction {
+            name: function.name.clone(),
+            description: Some(function.description.clone()),
+            parameters: Some(function.parameters.clone()),
+        })
+    }));
+
+    Ok(MessagesRequest {
+        model: request.model.clone(),
+        messages,
+        system: (!system.is_empty()).then(|| system.join("\n\n")),
+        max_tokens: request
+            .max_tokens
+            .or_else(|| request.param("max_completion_tokens"))
+            .unwrap_or(default_max_tokens),
+        stream: request.stream,
+        temperature: request.temperature,
+        top_p: request.param("top_p"),
+        stop_sequences: match request.param("stop") {
+            Some(Stop::One(stop)) => vec![stop],
+            Some(Stop::Many(stops)) => stops,
+            None => Vec::new(),
+        },
+        tools,
+        tool_choice: request
+            .additional_params
+            .get("tool_choice")
+            .and_then(to_tool_choice),
+        metadata: request.param("user").map(|user_id| Metadata { user_id }),
+    })
+}
+
+/// Content blocks of a user message
+fn user_blocks(message: &Message) -> Vec<ContentBlock> {
+    match &message.content {
+        None => Vec::new(),
+        Some(MessageContent::Text(text)) => vec![ContentBlock::Text { text: text.clone() }],
+        Some(MessageContent::Parts(parts)) => parts
+            .iter()
+            .filter_map(|part| match part {
+                ContentPart::Text { text } => Some(ContentBlock::Text { text: text.clone() }),
+                ContentPart::ImageUrl { image_url } => Some(ContentBlock::Image {
+                    source: image_source(&image_url.url),
+                }),
+                ContentPart::Other(_) => None,
+            })
+            .collect(),
+    }
+}
+
+/// Content blocks of an assistant message, including its tool calls
+fn assistant_blocks(message: &Message) -> Result<Vec<ContentBlock>> {
+    let mut blocks = Vec::new();
+    if let Some(text) = message.content.as_ref().map(MessageContent::text) {
+        if !text.is_empty() {
+            blocks.push(ContentBlock::Text { text });
+        }
+    }
+    for call in message.tool_calls.iter().flatten() {
+        blocks.push(ContentBlock::ToolUse {
+            id: call.id.clone(),
+            name: call.function.name.clone(),
+            input: tool_input(&call.function.arguments)?,
+        });
+    }
+    // Legacy function calls have no ID; the function name links them to their result
+    if let Some(call) = &message.function_call {
+        blocks.push(ContentBlock::ToolUse {
+            id: call.name.clone(),
+            name: call.name.clone(),
+            input: tool_input(&call.arguments)?,
+        });
+    }
+    Ok(blocks)
+}
+
+/// The result block of a `tool` or legacy `function` message
+fn tool_result(message: &Message) -> Result<ContentBlock> {
+    let tool_use_id = message
+        .tool_call_id
+        .as_ref()
+        .or(message.name.as_ref())
+        .ok_or_else(|| Error::ParseError("Tool message without tool_call_id".to_string()))?;
+    Ok(ContentBlock::ToolResult {
+        tool_use_id: tool_use_id.clone(),
+        content: message
+            .content
+            .as_ref()
+            .map(MessageContent::text)
+            .unwrap_or_default(),
+    })
+}
+
+/// Parse the JSON arguments of a tool call
+fn tool_input(arguments: &str) -> Result<Value> {
+    if arguments.trim().is_empty() {
+        return Ok(json!({}));
+    }
+    serde_json::from_str(arguments)
+        .map_err(|e| Error::ParseError(format!("Invalid tool call arguments: {e}")))
+}
+
+/// The source of an image referenced by URL or `data:` URL
+fn image_source(url: &str) -> ImageSource {
+    url.strip_prefix("data:")
+        .and_then(|data_url| data_url.split_once(";base64,"))
+        .map_or_else(
+            || ImageSource::Url {
+                url: url.to_string(),
+            },
+            |(media_type, data)| ImageSource::Base64 {
+                media_type: media_type.to_string(),
+                data: data.to_string(),
+            },
+        )
+}
+
+/// Convert an `OpenAI` function definition into a tool
+fn to_tool(function: OpenAIFunction) -> Tool {
+    Tool {
+        name: function.name,
+        description: function.description,
+        input_schema: function
+            .parameters
+            .unwrap_or_else(|| json!({"type": "object", "properties": {}})),
+    }
+}
+
+/// Convert the `tool_choice` parameter
+fn to_tool_choice(choice: &Value) -> Option<ToolChoice> {
+    match choice.as_str() {
+        Some("auto") => Some(ToolChoice::Auto),
+        Some("required") => Some(ToolChoice::Any),
+        Some("none") => Some(ToolChoice::None),
+        Some(_) => None,
+        None => choice["function"]["name"]
+            .as_str()
+            .map(|name| ToolChoice::Tool {
+                name: name.to_string(),
+            }),
+    }
+}
+
+/// The `OpenAI` finish reason for an Anthropic stop reason
+fn finish_reason(stop_reason: &str) -> &'static str {
+    match stop_reason {
+        "max_tokens" => "length",
+        "tool_use" => "tool_calls",
+        "refusal" => "content_filter",
+        _ => "stop",
+    }
+}
+
+/// Convert a Messages API response into an `OpenAI` chat completion
+#[must_use]
+pub fn to_openai_response(response: MessagesResponse) -> Value {
+    let mut text = String::new();
+    let mut tool_calls = Vec::new();
+    for block in response.content {
+        match block {
+            ContentBlock::Text { text: part } => text.push_str(&part),
+            ContentBlock::ToolUse { id, name, input } => tool_calls.push(json!({
+                "id": id,
+                "type": "function",
+                "function": {"name": name, "arguments": input.to_string()},
+            })),
+            _ => {}
+        }
+    }
+
+    let mut message = json!({
+        "role": "assistant",
+        "content": (!text.is_empty()).then_some(text),
+    });
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+
+    let usage = response.usage;
+    json!({
+        "id": response.id,
+        "object": "chat.completion",
+        "created": unix_now(),
+        "model": response.model,
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": response.stop_reason.as_deref().map(finish_reason),
+        }],
+        "usage": {
+            "prompt_tokens": usage.input_tokens,
+            "completion_tokens": usage.output_tokens,
+            "total_tokens": usage.input_tokens + usage.output_tokens,
+        },
+    })
+}
+
+/// Converts a streaming Messages API response into `OpenAI` chat completion chunks.
+///
+/// Feed the raw bytes of the response to [`StreamTranslator::push`] as they
+/// arrive; events may be split across reads. The translated chunks are
+/// returned as server-sent events in the format of the `OpenAI` API, ending
+/// with `data: [DONE]`.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_anthropic::translate::StreamTranslator;
+///
+/// let mut translator = StreamTranslator::new();
+/// let events = translator
+///     .push(b"event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n")
+///     .expect("valid event");
+/// assert_eq!(events.len(), 1);
+/// ```
+#[derive(Debug, Default)]
+pub struct StreamTranslator {
+    id: String,
+    model: String,
+    created: u64,
+    /// Index of the `OpenAI` tool call for the index of each `tool_use` block
+    tool_calls: HashMap<usize, usize>,
+    /// Bytes of an incomplete line
+    buffer: Vec<u8>,
+}
+
+impl StreamTranslator {
+    /// Create a translator for a new response
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            created: unix_now(),
+            ..Self::default()
+        }
+    }
+
+    /// Translate the next bytes of the event stream
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an event can't be parsed or the
+    /// stream reports an error.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let event: StreamEvent = serde_json::from_str(data.trim()).map_err(|e| {
+                Error::LLMError(format!("Failed to parse Anthropic stream event: {e}"))
+            })?;
+            if let Some(data) = self.translate(event)? {
+                events.push(Bytes::from(format!("data: {data}\n\n")));
+            }
+        }
+        Ok(events)
+    }
+
+    /// Translate one event into the data of an `OpenAI` server-sent event
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the event reports an error.
+    pub fn translate(&mut self, event: StreamEvent) -> Result<Option<String>> {
+        let chunk = match event {
+            StreamEvent::MessageStart { message } => {
+                self.id = message.id;
+                self.model = message.model;
+                self.chunk(&json!({"role": "assistant", "content": ""}), None)
+            }
+            StreamEvent::ContentBlockStart {
+                index,
+                content_block: ContentBlock::ToolUse { id, name, .. },
+            } => {
+                let call = self.tool_calls.len();
+                self.tool_calls.insert(index, call);
+                self.chunk(
+                    &json!({"tool_calls": [{
+                        "index": call,
+                        "id": id,
+                        "type": "function",
+                        "function": {"name": name, "arguments": ""},
+                    }]}),
+                    None,
+                )
+            }
+            StreamEvent::ContentBlockDelta {
+                delta: BlockDelta::TextDelta { text },
+                ..
+            } => self.chunk(&json!({ "content": text }), None),
+            StreamEvent::ContentBlockDelta {
+                index,
+                delta: BlockDelta::InputJsonDelta { partial_json },
+            } => {
+                let call = self.tool_calls.get(&index).copied().unwrap_or_default();
+                self.chunk(
+                    &json!({"tool_calls": [{
+                        "index": call,
+                        "function": {"arguments": partial_json},
+                    }]}),
+                    None,
+                )
+            }
+            StreamEvent::MessageDelta { delta, .. } => self.chunk(
+                &json!({}),
+                Some(finish_reason(
+                    delta.stop_reason.as_deref().unwrap_or_default(),
+                )),
+            ),
+            StreamEvent::MessageStop => return Ok(Some("[DONE]".to_string())),
+            StreamEvent::Error { error } => {
+                return Err(Error::LLMError(format!(
+                    "Anthropic stream error: {} ({})",
+                    error.message, error.error_type
+                )))
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(chunk.to_string()))
+    }
+
+    /// An `OpenAI` chat completion chunk
+    fn chunk(&self, delta: &Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        })
+    }
+}
+
+impl ChunkTranslator for StreamTranslator {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        Self::push(self, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_translation() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "Weather here?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBOR"}},
+                ]},
+                {"role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"},
+                }]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "Sunny"},
+                {"role": "user", "content": "Thanks"},
+            ],
+            "tools": [{"type": "function", "function": {"name": "get_weather"}}],
+            "tool_choice": "required",
+            "stop": "END",
+        }))
+        .expect("Invalid request");
+
+        let translated = to_anthropic_request(&request, 1024).expect("Failed to translate");
+        assert_eq!(translated.system.as_deref(), Some("Be brief."));
+        assert_eq!(translated.max_tokens, 1024);
+        assert_eq!(translated.stop_sequences, ["END"]);
+        assert_eq!(translated.tool_choice, Some(ToolChoice::Any));
+        assert_eq!(translated.tools[0].input_schema["type"], "object");
+
+        let roles: Vec<_> = translated
+            .messages
+            .iter()
+            .map(|m| m.role.as_str())
+            .collect();
+        assert_eq!(roles, ["user", "assistant", "user"]);
+        assert_eq!(
+            translated.messages[0].content[1],
+            ContentBlock::Image {
+                source: ImageSource::Base64 {
+                    media_type: "image/png".to_string(),
+                    data: "iVBOR".to_string(),
+                }
+            }
+        );
+        assert_eq!(
+            translated.messages[1].content[0],
+            ContentBlock::ToolUse {
+                id: "call_1".to_string(),
+                name: "get_weather".to_string(),
+                input: json!({"city": "Paris"}),
+            }
+        );
+        // The tool result and the following user message form one turn
+        assert_eq!(translated.messages[2].content.len(), 2);
+    }
+
+    #[test]
+    fn test_stream_translation() {
+        let stream = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"model\":\"claude-sonnet-4-5\",\"content\":[],\"stop_reason\":null,\"usage\":{\"input_tokens\":10}}}\n\n",
+            "event: content_block_start\n",
+            "data: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"tool_use\",\"id\":\"toolu_1\",\"name\":\"get_weather\",\"input\":{}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"city\\\"\"}}\n\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"tool_use\"},\"usage\":{\"output_tokens\":5}}\n\n",
+            "event: message_stop\n",
+            "data: {\"type\":\"message_stop\"}\n\n",
+        );
+
+        // Split the stream in the middle of an event
+        let mut translator = StreamTranslator::new();
+        let (first, second) = stream.as_bytes().split_at(100);
+        let mut events = translator.push(first).expect("Failed to translate");
+        events.extend(translator.push(second).expect("Failed to translate"));
+
+        let chunks: Vec<Value> = events[..events.len() - 1]
+            .iter()
+            .map(|event| {
+                let data = std::str::from_utf8(event).expect("Invalid UTF-8");
+                serde_json::from_str(data.trim().trim_start_matches("data: "))
+                    .expect("Invalid chunk")
+            })
+            .collect();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks[0]["id"], "msg_1");
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        let call = &chunks[1]["choices"][0]["delta"]["tool_calls"][0];
+        assert_eq!(call["id"], "toolu_1");
+        assert_eq!(call["function"]["name"], "get_weather");
+        assert_eq!(
+            chunks[2]["choices"][0]["delta"]["tool_calls"][0]["function"]["arguments"],
+            "{\"city\""
+        );
+        assert_eq!(chunks[3]["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(events[events.len() - 1], "data: [DONE]\n\n");
+    }
+}
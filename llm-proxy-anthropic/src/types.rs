@@ -0,0 +1,307 @@
+use llm_proxy_core::upstream::UpstreamError;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// A request to the Anthropic Messages API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessagesRequest {
+    /// The model to use (e.g., "claude-sonnet-4-5")
+    pub model: String,
+    /// The conversation, alternating between user and assistant turns
+    pub messages: Vec<AnthropicMessage>,
+    /// System prompt, sent either as text or as text blocks
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "optional_text"
+    )]
+    pub system: Option<String>,
+    /// Maximum tokens to generate; required by the API
+    pub max_tokens: u32,
+    /// Whether to stream the response as server-sent events
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+    /// Temperature for response randomness
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Nucleus sampling threshold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Sequences that stop generation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequences: Vec<String>,
+    /// Tools the model may call
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    /// How the model should use the tools
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    /// Request metadata, such as an ID of the end user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Metadata>,
+}
+
+/// A message in a Messages API request or response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnthropicMessage {
+    /// Either "user" or "assistant"
+    pub role: String,
+    /// The content blocks of the message, sent either as blocks or as text
+    #[serde(deserialize_with = "blocks")]
+    pub content: Vec<ContentBlock>,
+}
+
+/// A block of message content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock {
+    /// Text content
+    Text {
+        /// The text
+        text: String,
+    },
+    /// An image input
+    Image {
+        /// Where the image is read from
+        source: ImageSource,
+    },
+    /// A call of a tool by the model
+    ToolUse {
+        /// ID of the call, referenced by the matching tool result
+        id: String,
+        /// Name of the tool
+        name: String,
+        /// Arguments of the call
+        input: serde_json::Value,
+    },
+    /// The result of a tool call, sent in a user turn
+    ToolResult {
+        /// ID of the call this is the result of
+        tool_use_id: String,
+        /// Output of the tool, sent either as text or as text blocks
+        #[serde(default, deserialize_with = "text")]
+        content: String,
+    },
+    /// Any other block type (e.g. extended thinking), passed through untouched
+    #[serde(untagged)]
+    Other(serde_json::Value),
+}
+
+/// The source of an image input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageSource {
+    /// Inline base64 encoded image data
+    Base64 {
+        /// MIME type of the image, e.g. `image/png`
+        media_type: String,
+        /// The base64 encoded image
+        data: String,
+    },
+    /// An image fetched by the API from a URL
+    Url {
+        /// URL of the image
+        url: String,
+    },
+}
+
+/// A tool the model may call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tool {
+    /// Name of the tool
+    pub name: String,
+    /// Description of what the tool does
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Arguments the tool accepts, in JSON Schema format
+    pub input_schema: serde_json::Value,
+}
+
+/// How the model should use the provided tools
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// The model decides whether to call a tool
+    Auto,
+    /// The model must call one of the tools
+    Any,
+    /// The model must call the named tool
+    Tool {
+        /// Name of the tool
+        name: String,
+    },
+    /// The model must not call any tool
+    None,
+}
+
+/// Metadata about a request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Metadata {
+    /// Opaque identifier of the end user
+    pub user_id: String,
+}
+
+/// A non-streaming response from the Messages API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessagesResponse {
+    /// Unique ID of the message
+    pub id: String,
+    /// The model that generated the message
+    pub model: String,
+    /// The generated content
+    pub content: Vec<ContentBlock>,
+    /// Why generation stopped (e.g. "`end_turn`", "`max_tokens`", "`tool_use`")
+    pub stop_reason: Option<String>,
+    /// Token usage of the request
+    pub usage: Usage,
+}
+
+/// Token usage reported by the Messages API
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Usage {
+    /// Tokens in the prompt
+    #[serde(default)]
+    pub input_tokens: u32,
+    /// Tokens generated
+    #[serde(default)]
+    pub output_tokens: u32,
+}
+
+/// A server-sent event of a streaming Messages API response
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// Start of the message, with its ID and model
+    MessageStart {
+        /// The message, without content
+        message: MessagesResponse,
+    },
+    /// Start of a content block
+    ContentBlockStart {
+        /// Index of the block in the message
+        index: usize,
+        /// The block, without its streamed content
+        content_block: ContentBlock,
+    },
+    /// Streamed content of a block
+    ContentBlockDelta {
+        /// Index of the block in the message
+        index: usize,
+        /// The streamed content
+        delta: BlockDelta,
+    },
+    /// End of a content block
+    ContentBlockStop {
+        /// Index of the block in the message
+        index: usize,
+    },
+    /// Final details of the message, such as the stop reason
+    MessageDelta {
+        /// The changed fields
+        delta: MessageDelta,
+        /// Cumulative token usage
+        #[serde(default)]
+        usage: Usage,
+    },
+    /// End of the message
+    MessageStop,
+    /// Keep-alive event
+    Ping,
+    /// An error that occurred during streaming
+    Error {
+        /// The error details
+        error: ErrorDetails,
+    },
+}
+
+/// Streamed content of a content block
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BlockDelta {
+    /// Text appended to a text block
+    TextDelta {
+        /// The text
+        text: String,
+    },
+    /// Part of the JSON arguments of a tool call
+    InputJsonDelta {
+        /// The partial JSON
+        partial_json: String,
+    },
+    /// Any other delta type (e.g. thinking), ignored
+    #[serde(other)]
+    Other,
+}
+
+/// Fields of a message changed at the end of a stream
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageDelta {
+    /// Why generation stopped
+    pub stop_reason: Option<String>,
+}
+
+/// Error response from the Anthropic API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    /// The error details
+    pub error: ErrorDetails,
+}
+
+impl UpstreamError for ErrorResponse {
+    fn message(self) -> String {
+        self.error.message
+    }
+}
+
+/// Details of an error from the Anthropic API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorDetails {
+    /// The type of error
+    #[serde(rename = "type")]
+    pub error_type: String,
+    /// The error message
+    pub message: String,
+}
+
+/// Content sent either as text or as content blocks
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextOrBlocks {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
+impl TextOrBlocks {
+    fn into_blocks(self) -> Vec<ContentBlock> {
+        match self {
+            Self::Text(text) => vec![ContentBlock::Text { text }],
+            Self::Blocks(blocks) => blocks,
+        }
+    }
+
+    fn into_text(self) -> String {
+        match self {
+            Self::Text(text) => text,
+            Self::Blocks(blocks) => blocks
+                .into_iter()
+                .filter_map(|block| match block {
+                    ContentBlock::Text { text } => Some(text),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+fn blocks<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<ContentBlock>, D::Error> {
+    TextOrBlocks::deserialize(deserializer).map(TextOrBlocks::into_blocks)
+}
+
+fn text<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    TextOrBlocks::deserialize(deserializer).map(TextOrBlocks::into_text)
+}
+
+fn optional_text<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Option::<TextOrBlocks>::deserialize(deserializer)
+        .map(|content| content.map(TextOrBlocks::into_text))
+}
@@ -0,0 +1,197 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use llm_proxy_core::{
+    upstream, ClientProvider, Error, LLMClient, ProviderCapabilities, RequestSigner, Result,
+};
+use llm_proxy_openai::ChatCompletionRequest;
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::{
+    translate::{to_converse_request, to_openai_response, StreamTranslator},
+    types::{ConverseRequest, ConverseResponse, ErrorResponse},
+};
+
+/// Name of the upstream service in errors
+const UPSTREAM: &str = "Bedrock";
+
+/// Response header carrying the ID of a Bedrock request
+const REQUEST_ID_HEADER: &str = "x-amzn-requestid";
+
+/// Bedrock implementation of `LLMClient` for `OpenAI` chat completion requests.
+///
+/// Requests are translated into the Converse API format and sent to the
+/// `Converse` or `ConverseStream` endpoint of the requested model, signed
+/// with AWS `SigV4`. Responses are translated back into the `OpenAI` format.
+pub struct BedrockClient {
+    client: Arc<dyn ClientProvider>,
+    signer: Arc<dyn RequestSigner>,
+    endpoint: String,
+    headers: Vec<(String, String)>,
+    capabilities: ProviderCapabilities,
+}
+
+impl BedrockClient {
+    /// Create a client for the Bedrock runtime at `endpoint`, signing requests with `signer`
+    pub fn new(
+        client_provider: Arc<dyn ClientProvider>,
+        signer: Arc<dyn RequestSigner>,
+        endpoint: impl Into<String>,
+    ) -> Self {
+        Self {
+            client: client_provider,
+            signer,
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            headers: Vec::new(),
+            capabilities: ProviderCapabilities::default(),
+        }
+    }
+
+    /// The Bedrock runtime endpoint of an AWS region
+    #[must_use]
+    pub fn runtime_endpoint(region: &str) -> String {
+        format!("https://bedrock-runtime.{region}.amazonaws.com")
+    }
+
+    /// Replace the provider of the HTTP client used for upstream requests
+    #[must_use]
+    pub fn with_client_provider(mut self, client_provider: Arc<dyn ClientProvider>) -> Self {
+        self.client = client_provider;
+        self
+    }
+
+    /// Send a static header with every request
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Declare what the upstream backend supports, e.g. its models
+    #[must_use]
+    pub fn with_capabilities(mut self, capabilities: ProviderCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// The URL of the Converse or `ConverseStream` endpoint for `model`
+    fn url(&self, model: &str, stream: bool) -> String {
+        // Model IDs and ARNs contain `:` and `/`, which must be escaped in the path
+        let model: String = model
+            .bytes()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    char::from(byte).to_string()
+                }
+                _ => format!("%{byte:02X}"),
+            })
+            .collect();
+        let operation = if stream {
+            "converse-stream"
+        } else {
+            "converse"
+        };
+        format!("{}/model/{model}/{operation}", self.endpoint)
+    }
+
+    /// Send a signed request to Bedrock and get response
+    async fn send_request(
+        &self,
+        request: &ConverseRequest,
+        client: reqwest::Client,
+        url: String,
+    ) -> Result<reqwest::Response> {
+        let mut builder = client.post(url);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let signer = Some(self.signer.as_ref());
+        upstream::send_request::<ErrorResponse>(UPSTREAM, builder.json(request), signer, None).await
+    }
+}
+
+#[async_trait]
+impl LLMClient<ChatCompletionRequest> for BedrockClient {
+    async fn execute(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        // 1. Translate the request
+        let converse_request = to_converse_request(&request)?;
+        let url = self.url(&request.model, request.stream);
+
+        // 2. Get dependencies
+        let client = self
+            .client
+            .get_client()
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get HTTP client: {e}")))?;
+
+        // 3. Send request
+        let response = self.send_request(&converse_request, client, url).await?;
+        let id = response
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
+        // 4. Translate the response based on streaming flag
+        let (tx, rx) = mpsc::channel(100);
+        let (stream, model) = (request.stream, request.model);
+        info!("The request is streaming: {}", stream);
+        tokio::spawn(async move {
+            if stream {
+                let translator = StreamTranslator::new(id, model);
+                upstream::forward_stream(UPSTREAM, response, translator, &tx).await;
+            } else {
+                let translate =
+                    |response: ConverseResponse| to_openai_response(response, &id, &model);
+                upstream::forward_response(UPSTREAM, response, translate, &tx).await;
+            }
+        });
+
+        Ok(rx)
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.capabilities.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockClientProvider;
+    struct MockSigner;
+
+    #[async_trait]
+    impl ClientProvider for MockClientProvider {
+        async fn get_client(&self) -> Result<reqwest::Client> {
+            Ok(reqwest::Client::new())
+        }
+    }
+
+    #[async_trait]
+    impl RequestSigner for MockSigner {
+        async fn sign(&self, _request: &mut reqwest::Request) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_url_escapes_model_id() {
+        let client = BedrockClient::new(
+            Arc::new(MockClientProvider),
+            Arc::new(MockSigner),
+            BedrockClient::runtime_endpoint("us-east-1"),
+        );
+        assert_eq!(
+            client.url("anthropic.claude-3-5-sonnet-20240620-v1:0", true),
+            "https://bedrock-runtime.us-east-1.amazonaws.com/model/anthropic.claude-3-5-sonnet-20240620-v1%3A0/converse-stream"
+        );
+    }
+}
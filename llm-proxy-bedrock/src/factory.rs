@@ -0,0 +1,57 @@
+//! Provider factory for AWS Bedrock, registered as `bedrock`.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use llm_proxy_core::{
+    providers::SigV4RequestSigner, Pipeline, ProviderContext, ProviderFactory, ProviderRegistry,
+    Result,
+};
+use llm_proxy_openai::ChatCompletionRequest;
+use serde::Deserialize;
+
+use crate::{create_chat_client, create_pipeline_with_client, SIGNING_SERVICE};
+
+/// Register the factory of this crate in `registry`
+pub fn register(registry: &mut ProviderRegistry<ChatCompletionRequest>) {
+    registry.register("bedrock", BedrockFactory);
+}
+
+/// Settings of a Bedrock backend
+#[derive(Debug, Deserialize, Clone)]
+pub struct BedrockSettings {
+    /// AWS region requests are signed for; defaults to the region of the environment
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+/// Factory for Bedrock runtimes, with [`BedrockSettings`] as settings
+///
+/// Requests are signed with AWS credentials instead of carrying an API token,
+/// and sent to `base_url`, the runtime endpoint.
+pub struct BedrockFactory;
+
+#[async_trait]
+impl ProviderFactory<ChatCompletionRequest> for BedrockFactory {
+    fn requires_token(&self) -> bool {
+        false
+    }
+
+    async fn create_pipeline(
+        &self,
+        context: ProviderContext,
+    ) -> Result<Pipeline<ChatCompletionRequest>> {
+        let settings: BedrockSettings = context.settings()?;
+        let signer = SigV4RequestSigner::from_env(SIGNING_SERVICE, settings.region).await;
+
+        let mut client = create_chat_client(Arc::new(signer), &context.base_url);
+        if let Some(client_provider) = &context.client_provider {
+            client = client.with_client_provider(client_provider.clone());
+        }
+        for (name, value) in &context.headers {
+            client = client.with_header(name, value);
+        }
+        client = client.with_capabilities(context.capabilities);
+        Ok(create_pipeline_with_client(vec![], client))
+    }
+}
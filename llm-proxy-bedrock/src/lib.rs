@@ -0,0 +1,105 @@
+//! # LLM Proxy Bedrock
+//!
+//! This crate serves `OpenAI`-format chat completion requests with models on
+//! AWS Bedrock, through the Converse and `ConverseStream` APIs.
+//!
+//! ## Components
+//!
+//! ### Client
+//! The [`client`] module provides [`BedrockClient`], an `LLMClient` for
+//! [`ChatCompletionRequest`]s that signs requests with AWS `SigV4` and
+//! answers in the `OpenAI` format, both for streaming and non-streaming
+//! requests.
+//!
+//! ### Factory
+//! The [`factory`] module registers the `bedrock` provider in a
+//! [`ProviderRegistry`](llm_proxy_core::ProviderRegistry).
+//!
+//! ### Translate
+//! The [`translate`] module converts requests and responses between the two
+//! formats, and decodes the binary event stream of streaming responses.
+//!
+//! ### Types
+//! The [`types`] module defines the Converse requests, responses and stream
+//! events.
+//!
+//! ## Example Usage
+//!
+//! ```rust,no_run
+//! use llm_proxy_bedrock::create_chat_pipeline;
+//!
+//! # async fn example() -> llm_proxy_core::Result<()> {
+//! // Credentials come from the standard AWS credential chain
+//! let pipeline = create_chat_pipeline(vec![], "us-east-1").await;
+//!
+//! let request = bytes::Bytes::from(
+//!     r#"{"model": "amazon.nova-lite-v1:0", "messages": [{"role": "user", "content": "Hi"}]}"#,
+//! );
+//! let response = pipeline.execute(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Configuration
+//!
+//! ```toml
+//! [llm.bedrock]
+//! provider = "bedrock"
+//! type = "chat"
+//! base_url = "https://bedrock-runtime.us-east-1.amazonaws.com"
+//! token_env = ""
+//! supports_streaming = true
+//! additional_config = { region = "us-east-1" }
+//! ```
+
+pub mod client;
+pub mod factory;
+pub mod translate;
+pub mod types;
+
+use std::sync::Arc;
+
+use llm_proxy_core::{
+    providers::SigV4RequestSigner, Pipeline, Processor, ProcessorChain, RequestSigner,
+};
+use llm_proxy_openai::{
+    providers::StaticClientProvider, ChatCompletionRequest, OpenAIRequestParser,
+};
+
+pub use client::BedrockClient;
+pub use translate::{to_converse_request, to_openai_response, StreamTranslator};
+
+/// Signing name of the Bedrock runtime service
+pub const SIGNING_SERVICE: &str = "bedrock";
+
+/// Create a new pipeline that serves `OpenAI` chat completion requests with Bedrock models.
+///
+/// Requests are signed with credentials from the standard AWS credential chain.
+#[must_use]
+pub async fn create_chat_pipeline(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    region: &str,
+) -> Pipeline<ChatCompletionRequest> {
+    let signer = SigV4RequestSigner::from_env(SIGNING_SERVICE, Some(region.to_string())).await;
+    let client = create_chat_client(Arc::new(signer), &BedrockClient::runtime_endpoint(region));
+    create_pipeline_with_client(processors, client)
+}
+
+/// Create a Bedrock client for the runtime at `endpoint`, signing requests with `signer`.
+#[must_use]
+pub fn create_chat_client(signer: Arc<dyn RequestSigner>, endpoint: &str) -> BedrockClient {
+    let client_provider = Arc::new(StaticClientProvider::new());
+    BedrockClient::new(client_provider, signer, endpoint)
+}
+
+/// Create a chat completion pipeline around an existing Bedrock client.
+#[must_use]
+pub fn create_pipeline_with_client(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    llm_client: BedrockClient,
+) -> Pipeline<ChatCompletionRequest> {
+    let parser = Arc::new(OpenAIRequestParser::new());
+    let processor_chain = Arc::new(ProcessorChain::new(processors));
+
+    Pipeline::new(parser, processor_chain, Arc::new(llm_client))
+}
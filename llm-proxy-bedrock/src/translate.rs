@@ -0,0 +1,556 @@
+//! Translation between the `OpenAI` chat completions format and the Bedrock
+//! Converse API.
+//!
+//! - [`to_converse_request`] converts an inbound `OpenAI` chat completion
+//!   request into a Converse request.
+//! - [`to_openai_response`] converts a Converse response into an `OpenAI`
+//!   chat completion.
+//! - [`StreamTranslator`] converts the event stream of a `ConverseStream`
+//!   response into `OpenAI` chat completion chunks.
+
+use std::collections::HashMap;
+
+use aws_smithy_eventstream::frame::{DecodedFrame, MessageFrameDecoder};
+use aws_smithy_types::{event_stream::Message as EventMessage, str_bytes::StrBytes};
+use bytes::{Bytes, BytesMut};
+use llm_proxy_core::{unix_now, upstream::ChunkTranslator, Error, Result};
+use llm_proxy_openai::{ChatCompletionRequest, ContentPart, Message, MessageContent};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+use crate::types::{
+    ContentBlock, ContentBlockDelta, ContentBlockStart, ConverseMessage, ConverseRequest,
+    ConverseResponse, ErrorResponse, ImageBlock, ImageSource, InferenceConfig, InputSchema,
+    MessageStop, SystemBlock, Tool, ToolChoice, ToolConfig, ToolResultBlock, ToolResultContent,
+    ToolSpec, ToolUseBlock,
+};
+
+/// A tool definition in an `OpenAI` request
+#[derive(Deserialize)]
+struct OpenAITool {
+    function: OpenAIFunction,
+}
+
+/// A function definition in an `OpenAI` request
+#[derive(Deserialize)]
+struct OpenAIFunction {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    parameters: Option<Value>,
+}
+
+/// The `stop` parameter, either a single sequence or a list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stop {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Convert an `OpenAI` chat completion request into a Converse request.
+///
+/// System and developer messages become system prompts, tool calls and tool
+/// results become `toolUse` and `toolResult` blocks, and inline images become
+/// image blocks. Consecutive messages of the same role are merged, as the
+/// Converse API requires turns to alternate.
+///
+/// # Errors
+///
+/// This function will return an error if a message has an unknown role or
+/// references an image by URL, which Bedrock can't fetch, or if tool call
+/// arguments are not valid JSON.
+pub fn to_converse_request(request: &ChatCompletionRequest) -> Result<ConverseRequest> {
+    let mut system = Vec::new();
+    let mut messages: Vec<ConverseMessage> = Vec::new();
+    for message in &request.messages {
+        let (role, content) = match message.role.as_str() {
+            "system" | "developer" => {
+                if let Some(content) = &message.content {
+                    system.push(SystemBlock {
+                        text: content.text(),
+                    });
+                }
+                continue;
+            }
+            "user" => ("user", user_blocks(message)?),
+            "assistant" => ("assistant", assistant_blocks(message)?),
+            "tool" | "function" => ("user", vec![tool_result(message)?]),
+            role => {
+                return Err(Error::ParseError(format!(
+                    "Unsupported message role: {role}"
+                )))
+            }
+        };
+        if content.is_empty() {
+            continue;
+        }
+        match messages.last_mut() {
+            Some(last) if last.role == role => last.content.extend(content),
+            _ => messages.push(ConverseMessage {
+                role: role.to_string(),
+                content,
+            }),
+        }
+    }
+
+    let mut tools: Vec<Tool> = request
+        .param::<Vec<OpenAITool>>("tools")
+        .unwrap_or_default()
+        .into_iter()
+        .map(|tool| to_tool(tool.function))
+        .collect();
+    tools.extend(request.functions.iter().flatten().map(|function| {
+        to_tool(OpenAIFunction {
+            name: function.name.clone(),
+            description: Some(function.description.clone()),
+            parameters: Some(function.parameters.clone()),
+        })
+    }));
+    let tool_choice = request.additional_params.get("tool_choice");
+    // Converse has no way to disable tools other than not sending them
+    let tool_config = (!tools.is_empty() && tool_choice.is_none_or(|choice| choice != "none"))
+        .then(|| ToolConfig {
+            tools,
+            tool_choice: tool_choice.and_then(to_tool_choice),
+        });
+
+    Ok(ConverseRequest {
+        messages,
+        system,
+        inference_config: InferenceConfig {
+            max_tokens: request
+                .max_tokens
+                .or_else(|| request.param("max_completion_tokens")),
+            temperature: request.temperature,
+            top_p: request.param("top_p"),
+            stop_sequences: match request.param("stop") {
+                Some(Stop::One(stop)) => vec![stop],
+                Some(Stop::Many(stops)) => stops,
+                None => Vec::new(),
+            },
+        },
+        tool_config,
+    })
+}
+
+/// Content blocks of a user message
+fn user_blocks(message: &Message) -> Result<Vec<ContentBlock>> {
+    match &message.content {
+        None => Ok(Vec::new()),
+        Some(MessageContent::Text(text)) => Ok(vec![ContentBlock::Text(text.clone())]),
+        Some(MessageContent::Parts(parts)) => parts
+            .iter()
+            .filter_map(|part| match part {
+                ContentPart::Text { text } => Some(Ok(ContentBlock::Text(text.clone()))),
+                ContentPart::ImageUrl { image_url } => Some(image_block(&image_url.url)),
+                ContentPart::Other(_) => None,
+            })
+            .collect(),
+    }
+}
+
+/// Content blocks of an assistant message, including its tool calls
+fn assistant_blocks(message: &Message) -> Result<Vec<ContentBlock>> {
+    let mut blocks = Vec::new();
+    if let Some(text) = message.content.as_ref().map(MessageContent::text) {
+        if !text.is_empty() {
+            blocks.push(ContentBlock::Text(text));
+        }
+    }
+    for call in message.tool_calls.iter().flatten() {
+        blocks.push(ContentBlock::ToolUse(ToolUseBlock {
+            tool_use_id: call.id.clone(),
+            name: call.function.name.clone(),
+            input: tool_input(&call.function.arguments)?,
+        }));
+    }
+    // Legacy function calls have no ID; the function name links them to their result
+    if let Some(call) = &message.function_call {
+        blocks.push(ContentBlock::ToolUse(ToolUseBlock {
+            tool_use_id: call.name.clone(),
+            name: call.name.clone(),
+            input: tool_input(&call.arguments)?,
+        }));
+    }
+    Ok(blocks)
+}
+
+/// The result block of a `tool` or legacy `function` message
+fn tool_result(message: &Message) -> Result<ContentBlock> {
+    let tool_use_id = message
+        .tool_call_id
+        .as_ref()
+        .or(message.name.as_ref())
+        .ok_or_else(|| Error::ParseError("Tool message without tool_call_id".to_string()))?;
+    Ok(ContentBlock::ToolResult(ToolResultBlock {
+        tool_use_id: tool_use_id.clone(),
+        content: vec![ToolResultContent::Text(
+            message
+                .content
+                .as_ref()
+                .map(MessageContent::text)
+                .unwrap_or_default(),
+        )],
+    }))
+}
+
+/// Parse the JSON arguments of a tool call
+fn tool_input(arguments: &str) -> Result<Value> {
+    if arguments.trim().is_empty() {
+        return Ok(json!({}));
+    }
+    serde_json::from_str(arguments)
+        .map_err(|e| Error::ParseError(format!("Invalid tool call arguments: {e}")))
+}
+
+/// An image block for an image sent as a `data:` URL
+fn image_block(url: &str) -> Result<ContentBlock> {
+    let (media_type, data) = url
+        .strip_prefix("data:")
+        .and_then(|data_url| data_url.split_once(";base64,"))
+        .ok_or_else(|| {
+            Error::Unsupported("Bedrock only accepts images sent as base64 data URLs".to_string())
+        })?;
+    Ok(ContentBlock::Image(ImageBlock {
+        format: media_type
+            .strip_prefix("image/")
+            .unwrap_or(media_type)
+            .to_string(),
+        source: ImageSource {
+            bytes: data.to_string(),
+        },
+    }))
+}
+
+/// Convert an `OpenAI` function definition into a tool
+fn to_tool(function: OpenAIFunction) -> Tool {
+    Tool {
+        tool_spec: ToolSpec {
+            name: function.name,
+            description: function.description,
+            input_schema: InputSchema {
+                json: function
+                    .parameters
+                    .unwrap_or_else(|| json!({"type": "object", "properties": {}})),
+            },
+        },
+    }
+}
+
+/// Convert the `tool_choice` parameter
+fn to_tool_choice(choice: &Value) -> Option<ToolChoice> {
+    match choice.as_str() {
+        Some("auto") => Some(ToolChoice::Auto {}),
+        Some("required") => Some(ToolChoice::Any {}),
+        Some(_) => None,
+        None => choice["function"]["name"]
+            .as_str()
+            .map(|name| ToolChoice::Tool {
+                name: name.to_string(),
+            }),
+    }
+}
+
+/// The `OpenAI` finish reason for a Bedrock stop reason
+fn finish_reason(stop_reason: &str) -> &'static str {
+    match stop_reason {
+        "max_tokens" => "length",
+        "tool_use" => "tool_calls",
+        "guardrail_intervened" | "content_filtered" => "content_filter",
+        _ => "stop",
+    }
+}
+
+/// Convert a Converse response into an `OpenAI` chat completion.
+///
+/// Converse responses carry neither an ID nor the model, so both are passed in.
+#[must_use]
+pub fn to_openai_response(response: ConverseResponse, id: &str, model: &str) -> Value {
+    let mut text = String::new();
+    let mut tool_calls = Vec::new();
+    for block in response.output.message.content {
+        match block {
+            ContentBlock::Text(part) => text.push_str(&part),
+            ContentBlock::ToolUse(call) => tool_calls.push(json!({
+                "id": call.tool_use_id,
+                "type": "function",
+                "function": {"name": call.name, "arguments": call.input.to_string()},
+            })),
+            _ => {}
+        }
+    }
+
+    let mut message = json!({
+        "role": "assistant",
+        "content": (!text.is_empty()).then_some(text),
+    });
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+
+    let usage = response.usage;
+    json!({
+        "id": id,
+        "object": "chat.completion",
+        "created": unix_now(),
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason(&response.stop_reason),
+        }],
+        "usage": {
+            "prompt_tokens": usage.input_tokens,
+            "completion_tokens": usage.output_tokens,
+            "total_tokens": usage.total_tokens,
+        },
+    })
+}
+
+/// Converts a `ConverseStream` response into `OpenAI` chat completion chunks.
+///
+/// `ConverseStream` responses use the binary AWS event stream encoding. Feed
+/// the raw bytes of the response to [`StreamTranslator::push`] as they arrive;
+/// events may be split across reads. The translated chunks are returned as
+/// server-sent events in the format of the `OpenAI` API, ending with
+/// `data: [DONE]`.
+#[derive(Debug)]
+pub struct StreamTranslator {
+    id: String,
+    model: String,
+    created: u64,
+    /// Index of the `OpenAI` tool call for the index of each `toolUse` block
+    tool_calls: HashMap<usize, usize>,
+    decoder: MessageFrameDecoder,
+    /// Bytes of an incomplete event
+    buffer: BytesMut,
+}
+
+impl StreamTranslator {
+    /// Create a translator for a response with the given ID and model
+    pub fn new(id: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            model: model.into(),
+            created: unix_now(),
+            tool_calls: HashMap::new(),
+            decoder: MessageFrameDecoder::new(),
+            buffer: BytesMut::new(),
+        }
+    }
+
+    /// Translate the next bytes of the event stream
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an event can't be decoded or the
+    /// stream reports an exception.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let DecodedFrame::Complete(message) = self
+            .decoder
+            .decode_frame(&mut self.buffer)
+            .map_err(|e| Error::LLMError(format!("Failed to decode Bedrock event: {e}")))?
+        {
+            for data in self.translate(&message)? {
+                events.push(Bytes::from(format!("data: {data}\n\n")));
+            }
+        }
+        Ok(events)
+    }
+
+    /// Translate one event into the data of `OpenAI` server-sent events
+    fn translate(&mut self, message: &EventMessage) -> Result<Vec<String>> {
+        if header(message, ":message-type") == Some("exception") {
+            let details = serde_json::from_slice::<ErrorResponse>(message.payload()).map_or_else(
+                |_| String::from_utf8_lossy(message.payload()).into_owned(),
+                |e| e.message,
+            );
+            return Err(Error::LLMError(format!(
+                "Bedrock stream error: {} ({})",
+                details,
+                header(message, ":exception-type").unwrap_or("unknown")
+            )));
+        }
+
+        let chunk = match header(message, ":event-type") {
+            Some("messageStart") => self.chunk(&json!({"role": "assistant", "content": ""}), None),
+            Some("contentBlockStart") => {
+                let event: ContentBlockStart = payload(message)?;
+                let Some(tool_use) = event.start.tool_use else {
+                    return Ok(Vec::new());
+                };
+                let call = self.tool_calls.len();
+                self.tool_calls.insert(event.content_block_index, call);
+                self.chunk(
+                    &json!({"tool_calls": [{
+                        "index": call,
+                        "id": tool_use.tool_use_id,
+                        "type": "function",
+                        "function": {"name": tool_use.name, "arguments": ""},
+                    }]}),
+                    None,
+                )
+            }
+            Some("contentBlockDelta") => {
+                let event: ContentBlockDelta = payload(message)?;
+                if let Some(text) = event.delta.text {
+                    self.chunk(&json!({ "content": text }), None)
+                } else if let Some(tool_use) = event.delta.tool_use {
+                    let call = self
+                        .tool_calls
+                        .get(&event.content_block_index)
+                        .copied()
+                        .unwrap_or_default();
+                    self.chunk(
+                        &json!({"tool_calls": [{
+                            "index": call,
+                            "function": {"arguments": tool_use.input},
+                        }]}),
+                        None,
+                    )
+                } else {
+                    return Ok(Vec::new());
+                }
+            }
+            Some("messageStop") => {
+                let event: MessageStop = payload(message)?;
+                let chunk = self.chunk(&json!({}), Some(finish_reason(&event.stop_reason)));
+                return Ok(vec![chunk.to_string(), "[DONE]".to_string()]);
+            }
+            _ => return Ok(Vec::new()),
+        };
+        Ok(vec![chunk.to_string()])
+    }
+
+    /// An `OpenAI` chat completion chunk
+    fn chunk(&self, delta: &Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        })
+    }
+}
+
+impl ChunkTranslator for StreamTranslator {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        Self::push(self, bytes)
+    }
+}
+
+/// The value of a string header of an event
+fn header<'a>(message: &'a EventMessage, name: &str) -> Option<&'a str> {
+    message
+        .headers()
+        .iter()
+        .find(|header| header.name().as_str() == name)
+        .and_then(|header| header.value().as_string().ok())
+        .map(StrBytes::as_str)
+}
+
+/// Parse the JSON payload of an event
+fn payload<T: DeserializeOwned>(message: &EventMessage) -> Result<T> {
+    serde_json::from_slice(message.payload())
+        .map_err(|e| Error::LLMError(format!("Failed to parse Bedrock event: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_smithy_eventstream::frame::write_message_to;
+    use aws_smithy_types::event_stream::{Header, HeaderValue};
+
+    use super::*;
+
+    fn event(event_type: &'static str, payload: &Value) -> Vec<u8> {
+        let message = EventMessage::new(payload.to_string())
+            .add_header(Header::new(
+                ":message-type",
+                HeaderValue::String("event".into()),
+            ))
+            .add_header(Header::new(
+                ":event-type",
+                HeaderValue::String(event_type.into()),
+            ));
+        let mut frame = Vec::new();
+        write_message_to(&message, &mut frame).expect("Failed to encode event");
+        frame
+    }
+
+    #[test]
+    fn test_request_translation() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "anthropic.claude-3-5-sonnet-20240620-v1:0",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "What is this?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBOR"}},
+                ]},
+                {"role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "lookup", "arguments": "{\"q\":\"cat\"}"},
+                }]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "A cat"},
+            ],
+            "max_tokens": 256,
+            "tools": [{"type": "function", "function": {"name": "lookup"}}],
+        }))
+        .expect("Invalid request");
+
+        let translated = to_converse_request(&request).expect("Failed to translate");
+        let body = serde_json::to_value(&translated).expect("Failed to serialize");
+        assert_eq!(body["system"], json!([{"text": "Be brief."}]));
+        assert_eq!(body["inferenceConfig"], json!({"maxTokens": 256}));
+        assert_eq!(
+            body["messages"][0]["content"][1],
+            json!({"image": {"format": "png", "source": {"bytes": "iVBOR"}}})
+        );
+        assert_eq!(
+            body["messages"][1]["content"][0],
+            json!({"toolUse": {"toolUseId": "call_1", "name": "lookup", "input": {"q": "cat"}}})
+        );
+        assert_eq!(
+            body["messages"][2]["content"][0]["toolResult"]["content"],
+            json!([{"text": "A cat"}])
+        );
+        assert_eq!(
+            body["toolConfig"]["tools"][0]["toolSpec"]["inputSchema"]["json"]["type"],
+            "object"
+        );
+    }
+
+    #[test]
+    fn test_stream_translation() {
+        let mut stream = event("messageStart", &json!({"role": "assistant"}));
+        stream.extend(event(
+            "contentBlockDelta",
+            &json!({"contentBlockIndex": 0, "delta": {"text": "Hello"}}),
+        ));
+        stream.extend(event("messageStop", &json!({"stopReason": "end_turn"})));
+        stream.extend(event(
+            "metadata",
+            &json!({"usage": {"inputTokens": 3, "outputTokens": 1, "totalTokens": 4}}),
+        ));
+
+        // Split the stream in the middle of an event
+        let mut translator = StreamTranslator::new("req-1", "amazon.nova-lite-v1:0");
+        let (first, second) = stream.split_at(50);
+        let mut events = translator.push(first).expect("Failed to translate");
+        events.extend(translator.push(second).expect("Failed to translate"));
+
+        assert_eq!(events.len(), 4);
+        let chunk: Value = serde_json::from_slice(&events[1][6..]).expect("Invalid chunk");
+        assert_eq!(chunk["id"], "req-1");
+        assert_eq!(chunk["choices"][0]["delta"]["content"], "Hello");
+        let last: Value = serde_json::from_slice(&events[2][6..]).expect("Invalid chunk");
+        assert_eq!(last["choices"][0]["finish_reason"], "stop");
+        assert_eq!(events[3], "data: [DONE]\n\n");
+    }
+}
@@ -0,0 +1,284 @@
+use llm_proxy_core::upstream::UpstreamError;
+use serde::{Deserialize, Serialize};
+
+/// A request to the Bedrock Converse and `ConverseStream` APIs
+///
+/// The model is part of the URL, not the body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConverseRequest {
+    /// The conversation, alternating between user and assistant turns
+    pub messages: Vec<ConverseMessage>,
+    /// System prompts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub system: Vec<SystemBlock>,
+    /// Inference parameters
+    pub inference_config: InferenceConfig,
+    /// Tools the model may call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_config: Option<ToolConfig>,
+}
+
+/// A message in a Converse request or response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConverseMessage {
+    /// Either "user" or "assistant"
+    pub role: String,
+    /// The content blocks of the message
+    pub content: Vec<ContentBlock>,
+}
+
+/// A block of message content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ContentBlock {
+    /// Text content
+    Text(String),
+    /// An image input
+    Image(ImageBlock),
+    /// A call of a tool by the model
+    ToolUse(ToolUseBlock),
+    /// The result of a tool call, sent in a user turn
+    ToolResult(ToolResultBlock),
+    /// Any other block type (e.g. reasoning content), passed through untouched
+    #[serde(untagged)]
+    Other(serde_json::Value),
+}
+
+/// An image input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageBlock {
+    /// Image format: "png", "jpeg", "gif" or "webp"
+    pub format: String,
+    /// The image data
+    pub source: ImageSource,
+}
+
+/// Source of an image input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageSource {
+    /// The base64 encoded image
+    pub bytes: String,
+}
+
+/// A call of a tool by the model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolUseBlock {
+    /// ID of the call, referenced by the matching tool result
+    pub tool_use_id: String,
+    /// Name of the tool
+    pub name: String,
+    /// Arguments of the call
+    pub input: serde_json::Value,
+}
+
+/// The result of a tool call
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolResultBlock {
+    /// ID of the call this is the result of
+    pub tool_use_id: String,
+    /// Output of the tool
+    pub content: Vec<ToolResultContent>,
+}
+
+/// Output of a tool
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ToolResultContent {
+    /// Text output
+    Text(String),
+}
+
+/// A system prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemBlock {
+    /// The prompt
+    pub text: String,
+}
+
+/// Inference parameters of a Converse request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InferenceConfig {
+    /// Maximum tokens to generate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Temperature for response randomness
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Nucleus sampling threshold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Sequences that stop generation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequences: Vec<String>,
+}
+
+/// Tools the model may call and how it should use them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolConfig {
+    /// The tools
+    pub tools: Vec<Tool>,
+    /// How the model should use the tools
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+}
+
+/// A tool the model may call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tool {
+    /// Specification of the tool
+    pub tool_spec: ToolSpec,
+}
+
+/// Specification of a tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolSpec {
+    /// Name of the tool
+    pub name: String,
+    /// Description of what the tool does
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Arguments the tool accepts
+    pub input_schema: InputSchema,
+}
+
+/// The arguments a tool accepts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputSchema {
+    /// Arguments in JSON Schema format
+    pub json: serde_json::Value,
+}
+
+/// How the model should use the provided tools
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ToolChoice {
+    /// The model decides whether to call a tool
+    Auto {},
+    /// The model must call one of the tools
+    Any {},
+    /// The model must call the named tool
+    Tool {
+        /// Name of the tool
+        name: String,
+    },
+}
+
+/// A response from the Converse API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConverseResponse {
+    /// The generated output
+    pub output: ConverseOutput,
+    /// Why generation stopped (e.g. "`end_turn`", "`max_tokens`", "`tool_use`")
+    pub stop_reason: String,
+    /// Token usage of the request
+    #[serde(default)]
+    pub usage: Usage,
+}
+
+/// The output of a Converse request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConverseOutput {
+    /// The generated message
+    pub message: ConverseMessage,
+}
+
+/// Token usage reported by Bedrock
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Usage {
+    /// Tokens in the prompt
+    #[serde(default)]
+    pub input_tokens: u32,
+    /// Tokens generated
+    #[serde(default)]
+    pub output_tokens: u32,
+    /// Tokens in total
+    #[serde(default)]
+    pub total_tokens: u32,
+}
+
+/// Start of a content block in a `ConverseStream` response
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentBlockStart {
+    /// Index of the block in the message
+    pub content_block_index: usize,
+    /// Details of the block; only set for tool calls
+    pub start: BlockStart,
+}
+
+/// Details of a content block that starts
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockStart {
+    /// Start of a tool call; not set for other block types
+    pub tool_use: Option<ToolUseStart>,
+}
+
+/// Start of a tool call
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolUseStart {
+    /// ID of the call
+    pub tool_use_id: String,
+    /// Name of the tool
+    pub name: String,
+}
+
+/// Streamed content of a content block
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentBlockDelta {
+    /// Index of the block in the message
+    pub content_block_index: usize,
+    /// The streamed content
+    pub delta: BlockDelta,
+}
+
+/// Streamed content of a content block; other delta types (e.g. reasoning
+/// content) leave both fields unset
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockDelta {
+    /// Text appended to a text block
+    pub text: Option<String>,
+    /// Part of the JSON arguments of a tool call
+    pub tool_use: Option<ToolUseDelta>,
+}
+
+/// Part of the arguments of a tool call
+#[derive(Debug, Clone, Deserialize)]
+pub struct ToolUseDelta {
+    /// The partial JSON
+    pub input: String,
+}
+
+/// End of a message in a `ConverseStream` response
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageStop {
+    /// Why generation stopped
+    pub stop_reason: String,
+}
+
+/// Error response from Bedrock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    /// The error message
+    #[serde(alias = "Message")]
+    pub message: String,
+}
+
+impl UpstreamError for ErrorResponse {
+    fn message(self) -> String {
+        self.message
+    }
+}
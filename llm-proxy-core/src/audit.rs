@@ -0,0 +1,71 @@
+//! Implementations of [`AuditStore`](crate::AuditStore).
+//!
+//! The database stores keep transcripts in a `transcripts` table, created on
+//! first use, indexed by timestamp:
+//!
+//! - [`SqliteAuditStore`] (with the `sqlite` feature), with the request and
+//!   response as JSON text
+//! - [`PostgresAuditStore`] (with the `postgres` feature), with the request
+//!   and response as `JSONB`
+//!
+//! [`S3Archiver`] (with the `s3` feature) archives transcripts in batches to
+//! S3-compatible object storage instead.
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "s3")]
+pub mod s3;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresAuditStore;
+#[cfg(feature = "s3")]
+pub use s3::S3Archiver;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteAuditStore;
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+use crate::{AuditQuery, Error};
+
+/// Name of the table transcripts are kept in
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+const TABLE: &str = "transcripts";
+
+/// Wrap an error of the database
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn store_error(e: &sqlx::Error) -> Error {
+    Error::ProcessError(format!("Audit store error: {e}"))
+}
+
+/// Start a `SELECT` of the transcripts matching `query`, newest first
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn select<'a, DB: sqlx::Database>(columns: &str, query: &AuditQuery) -> sqlx::QueryBuilder<'a, DB>
+where
+    i64: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+    String: sqlx::Encode<'a, DB> + sqlx::Type<DB>,
+{
+    let mut builder = sqlx::QueryBuilder::new(format!("SELECT {columns} FROM {TABLE} WHERE 1 = 1"));
+    if let Some(since) = query.since {
+        builder.push(" AND timestamp >= ").push_bind(to_i64(since));
+    }
+    if let Some(until) = query.until {
+        builder.push(" AND timestamp < ").push_bind(to_i64(until));
+    }
+    if let Some(tenant) = &query.tenant {
+        builder.push(" AND tenant = ").push_bind(tenant.clone());
+    }
+    if let Some(model) = &query.model {
+        builder.push(" AND model = ").push_bind(model.clone());
+    }
+    builder.push(" ORDER BY timestamp DESC");
+    if let Some(limit) = query.limit {
+        builder.push(" LIMIT ").push_bind(i64::from(limit));
+    }
+    builder
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+fn to_i64(timestamp: u64) -> i64 {
+    i64::try_from(timestamp).unwrap_or(i64::MAX)
+}
@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+use sqlx::{
+    postgres::{PgPool, PgRow},
+    Postgres, Row,
+};
+use tokio::sync::OnceCell;
+
+use super::{select, store_error, to_i64, TABLE};
+use crate::{types::Result, AuditQuery, AuditStore, Error, Transcript};
+
+/// Audit store keeping transcripts in a Postgres database
+///
+/// # Example
+///
+/// ```rust
+/// # fn example() -> llm_proxy_core::Result<()> {
+/// use llm_proxy_core::audit::PostgresAuditStore;
+///
+/// let store = PostgresAuditStore::new("postgres://proxy@localhost/audit")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PostgresAuditStore {
+    pool: PgPool,
+    schema: OnceCell<()>,
+}
+
+impl PostgresAuditStore {
+    /// Create a store in the database at `url`, connected on first use
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `url` isn't a valid Postgres URL.
+    pub fn new(url: &str) -> Result<Self> {
+        let pool = PgPool::connect_lazy(url).map_err(|e| store_error(&e))?;
+        Ok(Self::from_pool(pool))
+    }
+
+    /// Create a store in the database of `pool`
+    #[must_use]
+    pub const fn from_pool(pool: PgPool) -> Self {
+        Self {
+            pool,
+            schema: OnceCell::const_new(),
+        }
+    }
+
+    /// Create the table and its index unless they exist
+    async fn ensure_schema(&self) -> Result<()> {
+        self.schema
+            .get_or_try_init(|| async {
+                sqlx::query(&format!(
+                    "CREATE TABLE IF NOT EXISTS {TABLE} (
+                        id TEXT PRIMARY KEY,
+                        timestamp BIGINT NOT NULL,
+                        route TEXT,
+                        tenant TEXT,
+                        model TEXT,
+                        request JSONB NOT NULL,
+                        response JSONB NOT NULL
+                    )"
+                ))
+                .execute(&self.pool)
+                .await
+                .map_err(|e| store_error(&e))?;
+                sqlx::query(&format!(
+                    "CREATE INDEX IF NOT EXISTS {TABLE}_timestamp ON {TABLE} (timestamp)"
+                ))
+                .execute(&self.pool)
+                .await
+                .map_err(|e| store_error(&e))?;
+                Ok::<_, Error>(())
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuditStore for PostgresAuditStore {
+    async fn record(&self, transcript: &Transcript) -> Result<()> {
+        self.ensure_schema().await?;
+        sqlx::query(&format!(
+            "INSERT INTO {TABLE} (id, timestamp, route, tenant, model, request, response)
+             VALUES ($1, $2, $3, $4, $5, $6::jsonb, $7::jsonb)"
+        ))
+        .bind(&transcript.id)
+        .bind(to_i64(transcript.timestamp))
+        .bind(&transcript.route)
+        .bind(&transcript.tenant)
+        .bind(&transcript.model)
+        .bind(transcript.request.to_string())
+        .bind(transcript.response.to_string())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| store_error(&e))?;
+        Ok(())
+    }
+
+    async fn query(&self, query: &AuditQuery) -> Result<Vec<Transcript>> {
+        self.ensure_schema().await?;
+        select::<Postgres>(
+            "id, timestamp, route, tenant, model, request::text AS request, \
+             response::text AS response",
+            query,
+        )
+        .build()
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| store_error(&e))?
+        .iter()
+        .map(transcript)
+        .collect()
+    }
+}
+
+fn transcript(row: &PgRow) -> Result<Transcript> {
+    let column = |e| store_error(&e);
+    Ok(Transcript {
+        id: row.try_get("id").map_err(column)?,
+        timestamp: u64::try_from(row.try_get::<i64, _>("timestamp").map_err(column)?)
+            .unwrap_or_default(),
+        route: row.try_get("route").map_err(column)?,
+        tenant: row.try_get("tenant").map_err(column)?,
+        model: row.try_get("model").map_err(column)?,
+        request: serde_json::from_str(row.try_get("request").map_err(column)?)?,
+        response: serde_json::from_str(row.try_get("response").map_err(column)?)?,
+    })
+}
@@ -0,0 +1,371 @@
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    mem,
+    sync::{Arc, Weak},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_s3::{error::DisplayErrorContext, primitives::ByteStream, Client};
+use flate2::{write::GzEncoder, Compression};
+use tokio::sync::{Mutex, OnceCell};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::{types::Result, AuditQuery, AuditStore, Error, Transcript};
+
+/// Number of transcripts from which a batch is uploaded without waiting
+const DEFAULT_BATCH_SIZE: usize = 1000;
+
+/// Archive of transcripts in S3-compatible object storage.
+///
+/// Recorded transcripts are buffered and uploaded in batches, once the batch
+/// size is reached and on the schedule set with [`S3Archiver::start`]. Each
+/// batch is split by day and route into gzip-compressed JSON lines objects,
+/// partitioned like `<prefix>date=2024-05-01/route=v1-chat-completions/
+/// <timestamp>-<uuid>.jsonl.gz`. With a retention, older objects under the
+/// prefix are deleted on the same schedule. Archives can't be queried, and
+/// transcripts not uploaded yet are lost when the proxy stops.
+///
+/// Requests are authenticated with the standard AWS credential chain; other
+/// S3-compatible services, like `MinIO`, are reached through their endpoint.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+///
+/// use llm_proxy_core::audit::S3Archiver;
+///
+/// # async fn example() {
+/// let archiver = S3Archiver::new("llm-transcripts")
+///     .with_prefix("proxy/")
+///     .with_retention(Duration::from_secs(90 * 24 * 60 * 60))
+///     .start(Duration::from_secs(300));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct S3Archiver {
+    bucket: String,
+    prefix: String,
+    endpoint: Option<String>,
+    region: Option<String>,
+    batch_size: usize,
+    retention: Option<Duration>,
+    client: OnceCell<Client>,
+    buffer: Mutex<Vec<Transcript>>,
+}
+
+impl S3Archiver {
+    /// Create an archiver uploading to `bucket`
+    #[must_use]
+    pub fn new(bucket: impl Into<String>) -> Self {
+        Self {
+            bucket: bucket.into(),
+            prefix: String::new(),
+            endpoint: None,
+            region: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            retention: None,
+            client: OnceCell::new(),
+            buffer: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Upload through `client` instead of one configured from the environment
+    #[must_use]
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = OnceCell::new_with(Some(client));
+        self
+    }
+
+    /// Put the objects under `prefix`, e.g. `proxy/`
+    #[must_use]
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Upload to the S3-compatible service at `endpoint`, addressing buckets by path
+    #[must_use]
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint.into());
+        self
+    }
+
+    /// Override the region of the environment
+    #[must_use]
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    /// Set the number of transcripts from which a batch is uploaded right away
+    #[must_use]
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Delete objects older than `retention`
+    #[must_use]
+    pub const fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    /// Upload the buffered transcripts and apply the retention every
+    /// `interval`, until the archiver is dropped
+    #[must_use]
+    pub fn start(self, interval: Duration) -> Arc<Self> {
+        let archiver = Arc::new(self);
+        let weak = Arc::downgrade(&archiver);
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                let Some(archiver) = Weak::upgrade(&weak) else {
+                    break;
+                };
+                if let Err(e) = archiver.flush().await {
+                    warn!(error = %e, "Failed to archive transcripts");
+                }
+                if let Err(e) = archiver.expire().await {
+                    warn!(error = %e, "Failed to delete expired transcript archives");
+                }
+            }
+        });
+        archiver
+    }
+
+    async fn client(&self) -> &Client {
+        self.client
+            .get_or_init(|| async {
+                let mut loader = aws_config::defaults(BehaviorVersion::latest());
+                if let Some(region) = &self.region {
+                    loader = loader.region(Region::new(region.clone()));
+                }
+                let mut config = aws_sdk_s3::config::Builder::from(&loader.load().await);
+                if let Some(endpoint) = &self.endpoint {
+                    config = config.endpoint_url(endpoint).force_path_style(true);
+                }
+                Client::from_conf(config.build())
+            })
+            .await
+    }
+
+    /// Upload the buffered transcripts
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an object can't be uploaded;
+    /// the transcripts of the objects not uploaded are buffered again.
+    pub async fn flush(&self) -> Result<()> {
+        let transcripts = mem::take(&mut *self.buffer.lock().await);
+        if transcripts.is_empty() {
+            return Ok(());
+        }
+        let mut partitions: BTreeMap<String, Vec<Transcript>> = BTreeMap::new();
+        for transcript in transcripts {
+            partitions
+                .entry(partition(&transcript))
+                .or_default()
+                .push(transcript);
+        }
+        let mut partitions = partitions.into_iter();
+        while let Some((partition, transcripts)) = partitions.next() {
+            if let Err(e) = self.upload(&partition, &transcripts).await {
+                self.buffer.lock().await.extend(
+                    transcripts
+                        .into_iter()
+                        .chain(partitions.flat_map(|(_, transcripts)| transcripts)),
+                );
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn upload(&self, partition: &str, transcripts: &[Transcript]) -> Result<()> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        for transcript in transcripts {
+            serde_json::to_writer(&mut encoder, transcript)?;
+            encoder.write_all(b"\n")?;
+        }
+        let body = encoder.finish()?;
+        let first = transcripts
+            .iter()
+            .map(|transcript| transcript.timestamp)
+            .min()
+            .unwrap_or_default();
+        let key = format!(
+            "{}{partition}/{first}-{}.jsonl.gz",
+            self.prefix,
+            Uuid::new_v4()
+        );
+        self.client()
+            .await
+            .put_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .content_type("application/x-ndjson")
+            .content_encoding("gzip")
+            .body(ByteStream::from(body))
+            .send()
+            .await
+            .map_err(|e| {
+                Error::ProcessError(format!(
+                    "Failed to upload {key}: {}",
+                    DisplayErrorContext(e)
+                ))
+            })?;
+        debug!(key, transcripts = transcripts.len(), "Archived transcripts");
+        Ok(())
+    }
+
+    /// Delete the objects under the prefix older than the retention
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the objects can't be listed or
+    /// deleted.
+    pub async fn expire(&self) -> Result<()> {
+        let Some(retention) = self.retention else {
+            return Ok(());
+        };
+        let cutoff = SystemTime::now()
+            .checked_sub(retention)
+            .and_then(|cutoff| cutoff.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |cutoff| {
+                i64::try_from(cutoff.as_secs()).unwrap_or(i64::MAX)
+            });
+        let client = self.client().await;
+        let mut pages = client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(&self.prefix)
+            .into_paginator()
+            .send();
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|e| {
+                Error::ProcessError(format!(
+                    "Failed to list transcript archives: {}",
+                    DisplayErrorContext(e)
+                ))
+            })?;
+            let expired = page.contents().iter().filter(|object| {
+                object
+                    .last_modified()
+                    .is_some_and(|modified| modified.secs() < cutoff)
+            });
+            for key in expired.filter_map(|object| object.key()) {
+                client
+                    .delete_object()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .send()
+                    .await
+                    .map_err(|e| {
+                        Error::ProcessError(format!(
+                            "Failed to delete {key}: {}",
+                            DisplayErrorContext(e)
+                        ))
+                    })?;
+                debug!(key, "Deleted expired transcript archive");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuditStore for S3Archiver {
+    async fn record(&self, transcript: &Transcript) -> Result<()> {
+        let mut buffer = self.buffer.lock().await;
+        buffer.push(transcript.clone());
+        let full = buffer.len() >= self.batch_size;
+        drop(buffer);
+        if full {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn query(&self, _query: &AuditQuery) -> Result<Vec<Transcript>> {
+        Err(Error::Unsupported(
+            "Transcript archives can't be queried".to_string(),
+        ))
+    }
+}
+
+/// The partition of `transcript`, like `date=2024-05-01/route=v1-chat-completions`
+fn partition(transcript: &Transcript) -> String {
+    let route = transcript
+        .route
+        .as_deref()
+        .map(|route| {
+            route
+                .trim_matches('/')
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                .collect::<String>()
+        })
+        .filter(|route| !route.is_empty())
+        .unwrap_or_else(|| "none".to_string());
+    format!("date={}/route={route}", date(transcript.timestamp))
+}
+
+/// The UTC date of `timestamp`, in milliseconds since the Unix epoch
+fn date(timestamp: u64) -> String {
+    // Days to civil date, from Howard Hinnant's `civil_from_days`
+    let days = timestamp / 86_400_000 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn test_partition() {
+        let transcript = |timestamp, route: Option<&str>| Transcript {
+            id: "transcript".to_string(),
+            timestamp,
+            route: route.map(ToString::to_string),
+            tenant: None,
+            model: None,
+            request: Value::Null,
+            response: Value::Null,
+        };
+        assert_eq!(
+            partition(&transcript(0, Some("/v1/chat/completions"))),
+            "date=1970-01-01/route=v1-chat-completions"
+        );
+        assert_eq!(
+            partition(&transcript(1_709_251_199_000, None)),
+            "date=2024-02-29/route=none"
+        );
+        assert_eq!(
+            partition(&transcript(1_735_689_600_000, Some("/"))),
+            "date=2025-01-01/route=none"
+        );
+    }
+}
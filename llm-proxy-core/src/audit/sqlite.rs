@@ -0,0 +1,184 @@
+use async_trait::async_trait;
+use sqlx::{
+    sqlite::{SqlitePool, SqliteRow},
+    Row, Sqlite,
+};
+use tokio::sync::OnceCell;
+
+use super::{select, store_error, to_i64, TABLE};
+use crate::{types::Result, AuditQuery, AuditStore, Error, Transcript};
+
+/// Audit store keeping transcripts in a `SQLite` database
+///
+/// # Example
+///
+/// ```rust
+/// # fn example() -> llm_proxy_core::Result<()> {
+/// use llm_proxy_core::audit::SqliteAuditStore;
+///
+/// let store = SqliteAuditStore::new("sqlite://audit.db?mode=rwc")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SqliteAuditStore {
+    pool: SqlitePool,
+    schema: OnceCell<()>,
+}
+
+impl SqliteAuditStore {
+    /// Create a store in the database at `url`, connected on first use
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `url` isn't a valid `SQLite` URL.
+    pub fn new(url: &str) -> Result<Self> {
+        let pool = SqlitePool::connect_lazy(url).map_err(|e| store_error(&e))?;
+        Ok(Self::from_pool(pool))
+    }
+
+    /// Create a store in the database of `pool`
+    #[must_use]
+    pub const fn from_pool(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            schema: OnceCell::const_new(),
+        }
+    }
+
+    /// Create the table and its index unless they exist
+    async fn ensure_schema(&self) -> Result<()> {
+        self.schema
+            .get_or_try_init(|| async {
+                sqlx::query(&format!(
+                    "CREATE TABLE IF NOT EXISTS {TABLE} (
+                        id TEXT PRIMARY KEY,
+                        timestamp INTEGER NOT NULL,
+                        route TEXT,
+                        tenant TEXT,
+                        model TEXT,
+                        request TEXT NOT NULL,
+                        response TEXT NOT NULL
+                    )"
+                ))
+                .execute(&self.pool)
+                .await
+                .map_err(|e| store_error(&e))?;
+                sqlx::query(&format!(
+                    "CREATE INDEX IF NOT EXISTS {TABLE}_timestamp ON {TABLE} (timestamp)"
+                ))
+                .execute(&self.pool)
+                .await
+                .map_err(|e| store_error(&e))?;
+                Ok::<_, Error>(())
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AuditStore for SqliteAuditStore {
+    async fn record(&self, transcript: &Transcript) -> Result<()> {
+        self.ensure_schema().await?;
+        sqlx::query(&format!(
+            "INSERT INTO {TABLE} (id, timestamp, route, tenant, model, request, response)
+             VALUES (?, ?, ?, ?, ?, ?, ?)"
+        ))
+        .bind(&transcript.id)
+        .bind(to_i64(transcript.timestamp))
+        .bind(&transcript.route)
+        .bind(&transcript.tenant)
+        .bind(&transcript.model)
+        .bind(transcript.request.to_string())
+        .bind(transcript.response.to_string())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| store_error(&e))?;
+        Ok(())
+    }
+
+    async fn query(&self, query: &AuditQuery) -> Result<Vec<Transcript>> {
+        self.ensure_schema().await?;
+        select::<Sqlite>(
+            "id, timestamp, route, tenant, model, request, response",
+            query,
+        )
+        .build()
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| store_error(&e))?
+        .iter()
+        .map(transcript)
+        .collect()
+    }
+}
+
+fn transcript(row: &SqliteRow) -> Result<Transcript> {
+    let column = |e| store_error(&e);
+    Ok(Transcript {
+        id: row.try_get("id").map_err(column)?,
+        timestamp: u64::try_from(row.try_get::<i64, _>("timestamp").map_err(column)?)
+            .unwrap_or_default(),
+        route: row.try_get("route").map_err(column)?,
+        tenant: row.try_get("tenant").map_err(column)?,
+        model: row.try_get("model").map_err(column)?,
+        request: serde_json::from_str(row.try_get("request").map_err(column)?)?,
+        response: serde_json::from_str(row.try_get("response").map_err(column)?)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_and_query() {
+        // A single connection, since every connection has its own in-memory database
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_lazy("sqlite::memory:")
+            .expect("Failed to create pool");
+        let store = SqliteAuditStore::from_pool(pool);
+        for (index, (tenant, model)) in [("acme", "gpt-4o"), ("acme", "o1"), ("globex", "gpt-4o")]
+            .into_iter()
+            .enumerate()
+        {
+            store
+                .record(&Transcript {
+                    id: format!("transcript-{index}"),
+                    timestamp: 1_000 + index as u64,
+                    route: Some("/v1/chat/completions".to_string()),
+                    tenant: Some(tenant.to_string()),
+                    model: Some(model.to_string()),
+                    request: json!({"model": model}),
+                    response: json!({"choices": []}),
+                })
+                .await
+                .expect("Failed to record transcript");
+        }
+
+        let transcripts = store
+            .query(&AuditQuery::new().with_model("gpt-4o"))
+            .await
+            .expect("Failed to query transcripts");
+        let ids: Vec<_> = transcripts.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["transcript-2", "transcript-0"]);
+        assert_eq!(transcripts[1].request, json!({"model": "gpt-4o"}));
+
+        let transcripts = store
+            .query(
+                &AuditQuery::new()
+                    .with_tenant("acme")
+                    .with_since(1_001)
+                    .with_limit(5),
+            )
+            .await
+            .expect("Failed to query transcripts");
+        assert_eq!(transcripts.len(), 1);
+        assert_eq!(transcripts[0].model.as_deref(), Some("o1"));
+    }
+}
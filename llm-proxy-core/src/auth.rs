@@ -0,0 +1,71 @@
+/// How an API token is attached to upstream requests.
+///
+/// Providers differ in where they expect credentials: `OpenAI` uses a bearer
+/// token, Azure `OpenAI` an `api-key` header, Anthropic an `x-api-key`
+/// header and some APIs a query parameter. Clients take an `AuthScheme` per
+/// backend instead of hard-coding one, so the same client can talk to any of
+/// them.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::AuthScheme;
+///
+/// let client = reqwest::Client::new();
+/// let request = AuthScheme::x_api_key()
+///     .apply(client.post("https://api.anthropic.com/v1/messages"), "sk-ant-...")
+///     .build()
+///     .expect("valid request");
+/// assert_eq!(request.headers()["x-api-key"], "sk-ant-...");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum AuthScheme {
+    /// `Authorization: Bearer <token>`
+    #[default]
+    Bearer,
+    /// The token as the value of a header, e.g. `api-key: <token>`
+    Header(String),
+    /// The token as a query parameter, e.g. `?key=<token>`
+    QueryParam(String),
+}
+
+impl AuthScheme {
+    /// The `api-key` header used by Azure `OpenAI`
+    #[must_use]
+    pub fn api_key_header() -> Self {
+        Self::Header("api-key".to_string())
+    }
+
+    /// The `x-api-key` header used by Anthropic
+    #[must_use]
+    pub fn x_api_key() -> Self {
+        Self::Header("x-api-key".to_string())
+    }
+
+    /// Attach `token` to a request
+    pub fn apply(&self, builder: reqwest::RequestBuilder, token: &str) -> reqwest::RequestBuilder {
+        match self {
+            Self::Bearer => builder.bearer_auth(token),
+            Self::Header(name) => builder.header(name, token),
+            Self::QueryParam(name) => builder.query(&[(name, token)]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_param() {
+        let request = AuthScheme::QueryParam("key".to_string())
+            .apply(
+                reqwest::Client::new().post("https://example.com/v1/models?alt=sse"),
+                "secret",
+            )
+            .build()
+            .expect("Failed to build request");
+        assert_eq!(request.url().query(), Some("alt=sse&key=secret"));
+        assert!(request.headers().get("Authorization").is_none());
+    }
+}
@@ -0,0 +1,220 @@
+use serde_json::Value;
+
+use crate::{types::Result, Error, LLMRequest};
+
+/// What an LLM backend supports.
+///
+/// Exposed by [`LLMClient::capabilities`](crate::LLMClient::capabilities) so
+/// that routing and validation can rely on declared capabilities instead of
+/// naming conventions. The default declares no restrictions; clients narrow
+/// it down with the `with_*` and `without_*` builders.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::ProviderCapabilities;
+///
+/// let capabilities = ProviderCapabilities::new()
+///     .without_vision()
+///     .with_max_context_tokens(32_768)
+///     .with_models(["llama-3.1-8b-instruct"]);
+/// assert!(capabilities.supports_model("llama-3.1-8b-instruct"));
+/// assert!(!capabilities.supports_model("gpt-4o"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderCapabilities {
+    /// Whether responses can be streamed
+    pub supports_streaming: bool,
+    /// Whether requests can define tools for the model to call
+    pub supports_tools: bool,
+    /// Whether messages can contain images
+    pub supports_vision: bool,
+    /// Size of the context window in tokens, if known
+    pub max_context_tokens: Option<u32>,
+    /// Models served by the backend; any model is accepted when empty
+    pub models: Vec<String>,
+}
+
+impl Default for ProviderCapabilities {
+    fn default() -> Self {
+        Self {
+            supports_streaming: true,
+            supports_tools: true,
+            supports_vision: true,
+            max_context_tokens: None,
+            models: Vec::new(),
+        }
+    }
+}
+
+impl ProviderCapabilities {
+    /// Declare a backend without restrictions
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declare that responses cannot be streamed
+    #[must_use]
+    pub const fn without_streaming(mut self) -> Self {
+        self.supports_streaming = false;
+        self
+    }
+
+    /// Declare that tool calling is not supported
+    #[must_use]
+    pub const fn without_tools(mut self) -> Self {
+        self.supports_tools = false;
+        self
+    }
+
+    /// Declare that image inputs are not supported
+    #[must_use]
+    pub const fn without_vision(mut self) -> Self {
+        self.supports_vision = false;
+        self
+    }
+
+    /// Set the size of the context window in tokens
+    #[must_use]
+    pub const fn with_max_context_tokens(mut self, tokens: u32) -> Self {
+        self.max_context_tokens = Some(tokens);
+        self
+    }
+
+    /// Restrict the backend to the given models
+    #[must_use]
+    pub fn with_models(mut self, models: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.models = models.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Whether the backend serves `model`
+    #[must_use]
+    pub fn supports_model(&self, model: &str) -> bool {
+        self.models.is_empty() || self.models.iter().any(|m| m == model)
+    }
+
+    /// Check that a request only uses supported features.
+    ///
+    /// Tools and images are detected in the `OpenAI` chat format, through the
+    /// `tools` field and `image_url` content parts.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an [`Error::Unsupported`] naming the first
+    /// unsupported feature the request uses.
+    pub fn validate<T: LLMRequest>(&self, request: &T) -> Result<()> {
+        if !self.models.is_empty() {
+            let model = request.model()?;
+            if !self.supports_model(&model) {
+                return Err(Error::Unsupported(format!("Model {model} is not served")));
+            }
+        }
+        if !self.supports_streaming && request.stream()? {
+            return Err(Error::Unsupported("Streaming is not supported".to_string()));
+        }
+        if let (Some(max_tokens), Some(context)) = (request.max_tokens(), self.max_context_tokens) {
+            if max_tokens > context {
+                return Err(Error::Unsupported(format!(
+                    "max_tokens {max_tokens} exceeds the context window of {context} tokens"
+                )));
+            }
+        }
+
+        if !self.supports_tools || !self.supports_vision {
+            let body = request.to_value()?;
+            if !self.supports_tools
+                && body["tools"]
+                    .as_array()
+                    .is_some_and(|tools| !tools.is_empty())
+            {
+                return Err(Error::Unsupported("Tools are not supported".to_string()));
+            }
+            if !self.supports_vision && contains_image(&body["messages"]) {
+                return Err(Error::Unsupported(
+                    "Image inputs are not supported".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether any message has an `image_url` content part
+fn contains_image(messages: &Value) -> bool {
+    messages
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|message| message["content"].as_array())
+        .flatten()
+        .any(|part| part["type"] == "image_url")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(serde::Deserialize)]
+    struct JsonRequest(Value);
+
+    impl LLMRequest for JsonRequest {
+        fn messages(&self) -> Result<Value> {
+            Ok(self.0["messages"].clone())
+        }
+        fn model(&self) -> Result<String> {
+            Ok(self.0["model"].as_str().unwrap_or_default().to_string())
+        }
+        fn stream(&self) -> Result<bool> {
+            Ok(self.0["stream"].as_bool().unwrap_or_default())
+        }
+        fn max_tokens(&self) -> Option<u32> {
+            None
+        }
+        fn to_map(&self) -> Result<HashMap<String, Value>> {
+            Ok(serde_json::from_value(self.0.clone())?)
+        }
+        fn to_value(&self) -> Result<Value> {
+            Ok(self.0.clone())
+        }
+        fn to_bytes(&self) -> Result<bytes::Bytes> {
+            Ok(serde_json::to_vec(&self.0)?.into())
+        }
+    }
+
+    #[test]
+    fn test_default_accepts_everything() {
+        let request = JsonRequest(json!({
+            "model": "gpt-4o",
+            "stream": true,
+            "tools": [{"type": "function", "function": {"name": "f"}}],
+        }));
+        assert!(ProviderCapabilities::new().validate(&request).is_ok());
+    }
+
+    #[test]
+    fn test_unsupported_features_are_rejected() {
+        let capabilities = ProviderCapabilities::new()
+            .without_vision()
+            .with_models(["llava"]);
+        let image = JsonRequest(json!({
+            "model": "llava",
+            "messages": [{"role": "user", "content": [
+                {"type": "text", "text": "What is this?"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,"}},
+            ]}],
+        }));
+        assert!(matches!(
+            capabilities.validate(&image),
+            Err(Error::Unsupported(_))
+        ));
+
+        let other_model = JsonRequest(json!({"model": "gpt-4o", "messages": []}));
+        assert!(capabilities.validate(&other_model).is_err());
+    }
+}
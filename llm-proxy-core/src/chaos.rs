@@ -0,0 +1,459 @@
+//! Scripted faults for chaos testing.
+//!
+//! A [`FaultInjectingLLMClient`] wraps a real client and breaks its responses
+//! as a [`FaultScenario`] says: it delays the first byte, cuts streams off,
+//! sends malformed events or truncated JSON, or fails requests with an error
+//! status. Running the proxy against it shows whether retries, failover and
+//! error handling cope with what upstream services do in practice.
+//!
+//! Scenarios are JSON files listing the fault of each request in order:
+//!
+//! ```json
+//! {
+//!   "repeat": true,
+//!   "faults": [
+//!     {"type": "delay_first_byte", "delay_ms": 2000},
+//!     {"type": "disconnect", "after_chunks": 3},
+//!     {"type": "malformed_sse", "after_chunks": 1},
+//!     {"type": "status", "status": 429},
+//!     {"type": "truncated_json"},
+//!     {"type": "none"}
+//!   ]
+//! }
+//! ```
+//!
+//! This module requires the `mock` feature.
+
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::{
+    types::{ResponseStream, Result},
+    Error, LLMClient, LLMRequest, ProviderCapabilities, RequestContext,
+};
+
+/// Line sent by a [`Fault::MalformedSse`] unless configured otherwise
+pub const DEFAULT_MALFORMED_LINE: &str = "data: {\"choices\": [{\"delta\": ";
+
+/// A fault injected into the response to a request
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Fault {
+    /// Leave the response alone
+    None,
+    /// Wait before sending the first byte of the response
+    DelayFirstByte {
+        /// Milliseconds to wait
+        delay_ms: u64,
+    },
+    /// End the response with a connection error after some chunks
+    Disconnect {
+        /// Number of chunks sent before the error
+        #[serde(default)]
+        after_chunks: usize,
+    },
+    /// Send an event that isn't valid JSON after some chunks, then the rest
+    /// of the response
+    MalformedSse {
+        /// Number of chunks sent before the malformed event
+        #[serde(default)]
+        after_chunks: usize,
+        /// The malformed line, [`DEFAULT_MALFORMED_LINE`] by default
+        #[serde(default = "default_malformed_line")]
+        line: String,
+    },
+    /// Fail the request without calling the upstream service, as if it
+    /// answered with an error status
+    Status {
+        /// The HTTP status, e.g. 429 or 500
+        status: u16,
+        /// Message of the error
+        #[serde(default)]
+        message: Option<String>,
+    },
+    /// Send the first half of a chunk after some chunks, then end the
+    /// response; without streaming, this cuts the completion's JSON in half
+    TruncatedJson {
+        /// Number of chunks sent whole
+        #[serde(default)]
+        after_chunks: usize,
+    },
+}
+
+fn default_malformed_line() -> String {
+    DEFAULT_MALFORMED_LINE.to_string()
+}
+
+/// The faults injected into responses, one per request in order
+///
+/// Requests after the last fault are left alone, unless the scenario repeats.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct FaultScenario {
+    /// The fault of each request
+    #[serde(default)]
+    pub faults: Vec<Fault>,
+    /// Start over once every fault was injected
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+impl FaultScenario {
+    /// Create a scenario injecting `faults` once, in order
+    #[must_use]
+    pub const fn new(faults: Vec<Fault>) -> Self {
+        Self {
+            faults,
+            repeat: false,
+        }
+    }
+
+    /// Set whether the scenario starts over once every fault was injected
+    #[must_use]
+    pub const fn with_repeat(mut self, repeat: bool) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Load a scenario from a JSON file
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be read or isn't a valid scenario.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read(path).map_err(|e| {
+            Error::ConfigError(format!(
+                "Failed to read fault scenario {}: {e}",
+                path.display()
+            ))
+        })?;
+        serde_json::from_slice(&content).map_err(|e| {
+            Error::ConfigError(format!("Invalid fault scenario {}: {e}", path.display()))
+        })
+    }
+
+    /// The fault of the request numbered `request_number`, starting at 0
+    fn fault(&self, request_number: usize) -> Option<&Fault> {
+        if self.repeat && !self.faults.is_empty() {
+            return self.faults.get(request_number % self.faults.len());
+        }
+        self.faults.get(request_number)
+    }
+}
+
+/// An `LLMClient` injecting the faults of a [`FaultScenario`] into the
+/// responses of another client.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use llm_proxy_core::{
+///     chaos::{Fault, FaultInjectingLLMClient, FaultScenario},
+///     LLMClient, LLMRequest,
+/// };
+///
+/// fn flaky<T: LLMRequest>(client: Arc<dyn LLMClient<T>>) -> FaultInjectingLLMClient<T> {
+///     let scenario = FaultScenario::new(vec![
+///         Fault::Status { status: 503, message: None },
+///         Fault::Disconnect { after_chunks: 2 },
+///     ])
+///     .with_repeat(true);
+///     FaultInjectingLLMClient::new(client, scenario)
+/// }
+/// ```
+pub struct FaultInjectingLLMClient<T: LLMRequest> {
+    inner: Arc<dyn LLMClient<T>>,
+    scenario: FaultScenario,
+    /// Number of requests received so far
+    served: AtomicUsize,
+}
+
+impl<T: LLMRequest> FaultInjectingLLMClient<T> {
+    /// Create a client injecting the faults of `scenario` into the responses
+    /// of `inner`
+    pub const fn new(inner: Arc<dyn LLMClient<T>>, scenario: FaultScenario) -> Self {
+        Self {
+            inner,
+            scenario,
+            served: AtomicUsize::new(0),
+        }
+    }
+}
+
+/// The error of a request failed with `status`, worded like those of clients
+fn status_error(status: u16, message: Option<&str>) -> Error {
+    let status = reqwest::StatusCode::from_u16(status)
+        .map_or_else(|_| status.to_string(), |status| status.to_string());
+    Error::LLMError(format!(
+        "Upstream request failed: {} ({status})",
+        message.unwrap_or("Injected fault")
+    ))
+}
+
+/// The error ending a stream cut off by a [`Fault::Disconnect`]
+fn disconnect_error() -> Error {
+    Error::LLMError("Connection reset (injected fault)".to_string())
+}
+
+/// Forward `stream` to `tx` with `fault` injected
+async fn inject(fault: Fault, mut stream: ResponseStream, tx: mpsc::Sender<Result<Bytes>>) {
+    let after_chunks = match &fault {
+        Fault::DelayFirstByte { delay_ms } => {
+            tokio::time::sleep(Duration::from_millis(*delay_ms)).await;
+            usize::MAX
+        }
+        Fault::Disconnect { after_chunks }
+        | Fault::MalformedSse { after_chunks, .. }
+        | Fault::TruncatedJson { after_chunks } => *after_chunks,
+        Fault::None | Fault::Status { .. } => usize::MAX,
+    };
+    let mut sent = 0;
+    while let Some(item) = stream.recv().await {
+        if sent == after_chunks {
+            match &fault {
+                Fault::Disconnect { .. } => {
+                    let _ = tx.send(Err(disconnect_error())).await;
+                    return;
+                }
+                Fault::TruncatedJson { .. } => {
+                    let _ = tx
+                        .send(item.map(|chunk| chunk.slice(..chunk.len() / 2)))
+                        .await;
+                    return;
+                }
+                Fault::MalformedSse { line, .. } => {
+                    let malformed = Bytes::from(format!("{line}\n\n"));
+                    if tx.send(Ok(malformed)).await.is_err() {
+                        return;
+                    }
+                }
+                Fault::None | Fault::DelayFirstByte { .. } | Fault::Status { .. } => {}
+            }
+        }
+        sent += 1;
+        if tx.send(item).await.is_err() {
+            return;
+        }
+    }
+    // A response shorter than planned is still cut off
+    if let Fault::Disconnect { .. } = fault {
+        let _ = tx.send(Err(disconnect_error())).await;
+    }
+}
+
+#[async_trait]
+impl<T: LLMRequest + 'static> LLMClient<T> for FaultInjectingLLMClient<T> {
+    async fn execute(&self, request: T) -> Result<ResponseStream> {
+        self.execute_with_context(request, &RequestContext::new())
+            .await
+    }
+
+    async fn execute_with_context(
+        &self,
+        request: T,
+        context: &RequestContext,
+    ) -> Result<ResponseStream>
+    where
+        T: 'async_trait,
+    {
+        let request_number = self.served.fetch_add(1, Ordering::SeqCst);
+        let fault = match self.scenario.fault(request_number) {
+            None | Some(Fault::None) => {
+                return self.inner.execute_with_context(request, context).await
+            }
+            Some(Fault::Status { status, message }) => {
+                return Err(status_error(*status, message.as_deref()))
+            }
+            Some(fault) => fault.clone(),
+        };
+        let stream = self.inner.execute_with_context(request, context).await?;
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(inject(fault, stream, tx));
+        Ok(rx)
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.inner.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Instant};
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::mock::{MockLLMClient, MockResponse};
+
+    #[derive(Deserialize)]
+    struct ChatRequest {
+        model: String,
+        messages: Value,
+        #[serde(default)]
+        stream: bool,
+    }
+
+    impl LLMRequest for ChatRequest {
+        fn messages(&self) -> Result<Value> {
+            Ok(self.messages.clone())
+        }
+
+        fn model(&self) -> Result<String> {
+            Ok(self.model.clone())
+        }
+
+        fn stream(&self) -> Result<bool> {
+            Ok(self.stream)
+        }
+
+        fn max_tokens(&self) -> Option<u32> {
+            None
+        }
+
+        fn to_map(&self) -> Result<HashMap<String, Value>> {
+            Ok(HashMap::new())
+        }
+
+        fn to_value(&self) -> Result<Value> {
+            Ok(json!({"model": self.model, "messages": self.messages, "stream": self.stream}))
+        }
+
+        fn to_bytes(&self) -> Result<Bytes> {
+            Ok(Bytes::from(self.to_value()?.to_string()))
+        }
+    }
+
+    fn request(stream: bool) -> ChatRequest {
+        ChatRequest {
+            model: "mock-model".to_string(),
+            messages: json!([{"role": "user", "content": "Hi"}]),
+            stream,
+        }
+    }
+
+    async fn collect(mut rx: ResponseStream) -> Vec<std::result::Result<Bytes, String>> {
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event.map_err(|e| e.to_string()));
+        }
+        events
+    }
+
+    fn client(faults: Vec<Fault>) -> FaultInjectingLLMClient<ChatRequest> {
+        let mock =
+            MockLLMClient::new().with_response(MockResponse::Text("One two three".to_string()));
+        FaultInjectingLLMClient::new(Arc::new(mock), FaultScenario::new(faults))
+    }
+
+    #[test]
+    fn test_scenario_file_format() {
+        let scenario: FaultScenario = serde_json::from_value(json!({
+            "repeat": true,
+            "faults": [
+                {"type": "status", "status": 429},
+                {"type": "malformed_sse", "after_chunks": 1},
+            ],
+        }))
+        .expect("Invalid scenario");
+        assert_eq!(
+            scenario.fault(2),
+            Some(&Fault::Status {
+                status: 429,
+                message: None
+            })
+        );
+        assert_eq!(
+            scenario.fault(3),
+            Some(&Fault::MalformedSse {
+                after_chunks: 1,
+                line: DEFAULT_MALFORMED_LINE.to_string()
+            })
+        );
+        assert_eq!(scenario.with_repeat(false).fault(2), None);
+    }
+
+    #[tokio::test]
+    async fn test_stream_faults() {
+        // The mock streams one chunk per word, a finish chunk and [DONE]
+        let events = collect(
+            client(vec![Fault::Disconnect { after_chunks: 2 }])
+                .execute(request(true))
+                .await
+                .expect("Failed to execute request"),
+        )
+        .await;
+        assert_eq!(events.len(), 3);
+        assert!(events[2]
+            .as_ref()
+            .expect_err("The stream ends with an error")
+            .contains("Connection reset"));
+
+        let events = collect(
+            client(vec![Fault::MalformedSse {
+                after_chunks: 1,
+                line: "data: {".to_string(),
+            }])
+            .execute(request(true))
+            .await
+            .expect("Failed to execute request"),
+        )
+        .await;
+        assert_eq!(events.len(), 6);
+        assert_eq!(events[1], Ok(Bytes::from("data: {\n\n")));
+    }
+
+    #[tokio::test]
+    async fn test_request_faults() {
+        let client = client(vec![
+            Fault::Status {
+                status: 429,
+                message: None,
+            },
+            Fault::TruncatedJson { after_chunks: 0 },
+            Fault::DelayFirstByte { delay_ms: 50 },
+        ]);
+
+        let error = client
+            .execute(request(false))
+            .await
+            .expect_err("The request fails");
+        assert!(error.to_string().contains("429 Too Many Requests"));
+
+        let events = collect(
+            client
+                .execute(request(false))
+                .await
+                .expect("Failed to execute request"),
+        )
+        .await;
+        assert_eq!(events.len(), 1);
+        let body = events[0].as_ref().expect("Truncated chunk");
+        assert!(serde_json::from_slice::<Value>(body).is_err());
+
+        let start = Instant::now();
+        let events = collect(
+            client
+                .execute(request(false))
+                .await
+                .expect("Failed to execute request"),
+        )
+        .await;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        let body = events[0].as_ref().expect("Complete response");
+        assert!(serde_json::from_slice::<Value>(body).is_ok());
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use crate::{Error, Result};
+
+/// Header carrying the ID that correlates a request across the logs of the
+/// client, the proxy and the backend
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Information about an inbound request that is not part of its body.
+///
+/// The context travels alongside the request through the pipeline so that
+/// components can make per-request decisions, e.g. a token provider picking
+/// a different upstream key for a given model or tenant.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::RequestContext;
+///
+/// let context = RequestContext::new()
+///     .with_route("/v1/chat/completions")
+///     .with_tenant("acme");
+/// assert_eq!(context.tenant.as_deref(), Some("acme"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestContext {
+    /// ID of the request, taken from its `X-Request-Id` header or generated
+    pub request_id: Option<String>,
+    /// ID of the route that received the request, e.g. its path prefix
+    pub route: Option<String>,
+    /// Model requested by the client, filled in once the request is parsed
+    pub model: Option<String>,
+    /// Tenant the request belongs to
+    pub tenant: Option<String>,
+    /// ID of the proxy-issued key the client authenticated with
+    pub client_key: Option<String>,
+    /// Free-form attributes set by the server or by processors
+    pub attributes: HashMap<String, String>,
+    /// Headers of the inbound request, with lowercase names
+    pub headers: HashMap<String, String>,
+    /// Headers added to the response to the client, e.g. warnings set by
+    /// processors
+    pub response_headers: Vec<(String, String)>,
+    /// Path of the upstream URL, replacing the path of the backend's URL,
+    /// for routes mapping their paths to different upstream paths
+    pub upstream_path: Option<String>,
+    /// Inbound headers forwarded to the upstream request, by their upstream
+    /// names, for routes with `forward_headers`
+    pub upstream_headers: Vec<(String, String)>,
+}
+
+impl RequestContext {
+    /// Create an empty context
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the ID of the request
+    #[must_use]
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    /// Set the route that received the request
+    #[must_use]
+    pub fn with_route(mut self, route: impl Into<String>) -> Self {
+        self.route = Some(route.into());
+        self
+    }
+
+    /// Send the request to `path` on the backend's host
+    #[must_use]
+    pub fn with_upstream_path(mut self, path: impl Into<String>) -> Self {
+        self.upstream_path = Some(path.into());
+        self
+    }
+
+    /// Forward a header to the upstream request
+    #[must_use]
+    pub fn with_upstream_header(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.upstream_headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the requested model
+    #[must_use]
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Set the tenant the request belongs to
+    #[must_use]
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    /// Set the ID of the key the client authenticated with
+    #[must_use]
+    pub fn with_client_key(mut self, client_key: impl Into<String>) -> Self {
+        self.client_key = Some(client_key.into());
+        self
+    }
+
+    /// Set a free-form attribute
+    #[must_use]
+    pub fn with_attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set a header of the inbound request
+    #[must_use]
+    pub fn with_header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.insert(name.to_lowercase(), value.into());
+        self
+    }
+
+    /// The inbound request's header `name`, looked up case-insensitively
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
+
+    /// Add a header to the response to the client
+    pub fn add_response_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.response_headers.push((name.into(), value.into()));
+    }
+
+    /// The backend's `url`, with its path replaced by the upstream path if
+    /// one is set; its query is kept
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an upstream path is set and `url`
+    /// is not a valid URL.
+    pub fn upstream_url(&self, url: String) -> Result<String> {
+        let Some(path) = &self.upstream_path else {
+            return Ok(url);
+        };
+        let mut url = reqwest::Url::parse(&url)
+            .map_err(|e| Error::ConfigError(format!("Invalid upstream URL {url}: {e}")))?;
+        url.set_path(path);
+        Ok(url.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upstream_url_replaces_path() {
+        let url = "https://example.openai.azure.com/openai/chat?api-version=2024-06-01";
+        assert_eq!(
+            RequestContext::new()
+                .upstream_url(url.to_string())
+                .expect("Invalid URL"),
+            url
+        );
+        assert_eq!(
+            RequestContext::new()
+                .with_upstream_path("/openai/deployments/gpt-4o/chat/completions")
+                .upstream_url(url.to_string())
+                .expect("Invalid URL"),
+            "https://example.openai.azure.com/openai/deployments/gpt-4o/chat/completions?api-version=2024-06-01"
+        );
+    }
+}
@@ -0,0 +1,39 @@
+//! Implementations of [`ConversationStore`](crate::ConversationStore).
+//!
+//! The database stores keep turns in a `conversation_turns` table, created on
+//! first use, indexed by session:
+//!
+//! - [`SqliteConversationStore`] (with the `sqlite` feature), with the
+//!   messages and reply as JSON text
+//! - [`PostgresConversationStore`] (with the `postgres` feature), with the
+//!   messages and reply as `JSONB`
+//!
+//! [`RedisConversationStore`] (with the `redis` feature) keeps each
+//! conversation in a list instead, which can expire after a while.
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresConversationStore;
+#[cfg(feature = "redis")]
+pub use redis::RedisConversationStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteConversationStore;
+
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "redis"))]
+use crate::Error;
+
+/// Name of the table turns are kept in
+#[cfg(any(feature = "sqlite", feature = "postgres"))]
+const TABLE: &str = "conversation_turns";
+
+/// Wrap an error of the store
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "redis"))]
+fn store_error(e: &impl std::fmt::Display) -> Error {
+    Error::ProcessError(format!("Conversation store error: {e}"))
+}
@@ -0,0 +1,124 @@
+use async_trait::async_trait;
+use sqlx::{
+    postgres::{PgPool, PgRow},
+    Row,
+};
+use tokio::sync::OnceCell;
+
+use super::{store_error, TABLE};
+use crate::{types::Result, ConversationStore, Error, Turn};
+
+/// Conversation store keeping turns in a Postgres database
+///
+/// # Example
+///
+/// ```rust
+/// # fn example() -> llm_proxy_core::Result<()> {
+/// use llm_proxy_core::conversation::PostgresConversationStore;
+///
+/// let store = PostgresConversationStore::new("postgres://proxy@localhost/conversations")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PostgresConversationStore {
+    pool: PgPool,
+    schema: OnceCell<()>,
+}
+
+impl PostgresConversationStore {
+    /// Create a store in the database at `url`, connected on first use
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `url` isn't a valid Postgres URL.
+    pub fn new(url: &str) -> Result<Self> {
+        let pool = PgPool::connect_lazy(url).map_err(|e| store_error(&e))?;
+        Ok(Self::from_pool(pool))
+    }
+
+    /// Create a store in the database of `pool`
+    #[must_use]
+    pub const fn from_pool(pool: PgPool) -> Self {
+        Self {
+            pool,
+            schema: OnceCell::const_new(),
+        }
+    }
+
+    /// Create the table and its index unless they exist
+    async fn ensure_schema(&self) -> Result<()> {
+        self.schema
+            .get_or_try_init(|| async {
+                sqlx::query(&format!(
+                    "CREATE TABLE IF NOT EXISTS {TABLE} (
+                        id BIGSERIAL PRIMARY KEY,
+                        session TEXT NOT NULL,
+                        timestamp BIGINT NOT NULL,
+                        messages JSONB NOT NULL,
+                        reply JSONB NOT NULL
+                    )"
+                ))
+                .execute(&self.pool)
+                .await
+                .map_err(|e| store_error(&e))?;
+                sqlx::query(&format!(
+                    "CREATE INDEX IF NOT EXISTS {TABLE}_session ON {TABLE} (session, id)"
+                ))
+                .execute(&self.pool)
+                .await
+                .map_err(|e| store_error(&e))?;
+                Ok::<_, Error>(())
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ConversationStore for PostgresConversationStore {
+    async fn append(&self, session: &str, turn: &Turn) -> Result<()> {
+        self.ensure_schema().await?;
+        sqlx::query(&format!(
+            "INSERT INTO {TABLE} (session, timestamp, messages, reply)
+             VALUES ($1, $2, $3::jsonb, $4::jsonb)"
+        ))
+        .bind(session)
+        .bind(i64::try_from(turn.timestamp).unwrap_or(i64::MAX))
+        .bind(serde_json::to_string(&turn.messages)?)
+        .bind(turn.reply.to_string())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| store_error(&e))?;
+        Ok(())
+    }
+
+    async fn history(&self, session: &str, limit: Option<u32>) -> Result<Vec<Turn>> {
+        self.ensure_schema().await?;
+        // The newest turns are selected, then put back in order
+        let mut turns = sqlx::query(&format!(
+            "SELECT timestamp, messages::text AS messages, reply::text AS reply FROM {TABLE}
+             WHERE session = $1 ORDER BY id DESC LIMIT $2"
+        ))
+        .bind(session)
+        .bind(limit.map(i64::from))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| store_error(&e))?
+        .iter()
+        .map(turn)
+        .collect::<Result<Vec<_>>>()?;
+        turns.reverse();
+        Ok(turns)
+    }
+}
+
+fn turn(row: &PgRow) -> Result<Turn> {
+    let column = |e| store_error(&e);
+    Ok(Turn {
+        timestamp: u64::try_from(row.try_get::<i64, _>("timestamp").map_err(column)?)
+            .unwrap_or_default(),
+        messages: serde_json::from_str(row.try_get("messages").map_err(column)?)?,
+        reply: serde_json::from_str(row.try_get("reply").map_err(column)?)?,
+    })
+}
@@ -0,0 +1,119 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use redis::{aio::ConnectionManager, AsyncCommands, Client};
+use tokio::sync::OnceCell;
+
+use super::store_error;
+use crate::{types::Result, ConversationStore, Turn};
+
+/// Default prefix of the keys of conversations
+pub const DEFAULT_KEY_PREFIX: &str = "llm-proxy:conversation:";
+
+/// Conversation store keeping each conversation in a Redis list of JSON turns
+///
+/// A conversation can expire once it has been idle for a while, see
+/// [`with_ttl`](Self::with_ttl).
+///
+/// # Example
+///
+/// ```rust
+/// # fn example() -> llm_proxy_core::Result<()> {
+/// use std::time::Duration;
+///
+/// use llm_proxy_core::conversation::RedisConversationStore;
+///
+/// let store = RedisConversationStore::new("redis://localhost:6379")?
+///     .with_ttl(Duration::from_secs(24 * 60 * 60));
+/// # Ok(())
+/// # }
+/// ```
+pub struct RedisConversationStore {
+    client: Client,
+    connection: OnceCell<ConnectionManager>,
+    key_prefix: String,
+    ttl: Option<Duration>,
+}
+
+impl RedisConversationStore {
+    /// Create a store in the Redis server at `url`, connected on first use
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `url` isn't a valid Redis URL.
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            client: Client::open(url).map_err(|e| store_error(&e))?,
+            connection: OnceCell::new(),
+            key_prefix: DEFAULT_KEY_PREFIX.to_string(),
+            ttl: None,
+        })
+    }
+
+    /// Prefix the keys of conversations with `prefix` instead of
+    /// [`DEFAULT_KEY_PREFIX`]
+    #[must_use]
+    pub fn with_key_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.key_prefix = prefix.into();
+        self
+    }
+
+    /// Delete conversations that had no new turn for `ttl`
+    #[must_use]
+    pub const fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// The connection to the server, which reconnects when it is lost
+    async fn connection(&self) -> Result<ConnectionManager> {
+        self.connection
+            .get_or_try_init(|| async {
+                ConnectionManager::new(self.client.clone())
+                    .await
+                    .map_err(|e| store_error(&e))
+            })
+            .await
+            .cloned()
+    }
+
+    fn key(&self, session: &str) -> String {
+        format!("{}{session}", self.key_prefix)
+    }
+}
+
+#[async_trait]
+impl ConversationStore for RedisConversationStore {
+    async fn append(&self, session: &str, turn: &Turn) -> Result<()> {
+        let mut connection = self.connection().await?;
+        let key = self.key(session);
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .rpush(&key, serde_json::to_string(turn)?)
+            .ignore();
+        if let Some(ttl) = self.ttl {
+            let seconds = i64::try_from(ttl.as_secs().max(1)).unwrap_or(i64::MAX);
+            pipe.expire(&key, seconds).ignore();
+        }
+        pipe.query_async::<()>(&mut connection)
+            .await
+            .map_err(|e| store_error(&e))
+    }
+
+    async fn history(&self, session: &str, limit: Option<u32>) -> Result<Vec<Turn>> {
+        if limit == Some(0) {
+            return Ok(Vec::new());
+        }
+        let mut connection = self.connection().await?;
+        // Negative indexes count from the end of the list
+        let start = limit.map_or(0, |limit| -isize::try_from(limit).unwrap_or(isize::MAX));
+        let turns: Vec<String> = connection
+            .lrange(self.key(session), start, -1)
+            .await
+            .map_err(|e| store_error(&e))?;
+        turns
+            .iter()
+            .map(|turn| Ok(serde_json::from_str(turn)?))
+            .collect()
+    }
+}
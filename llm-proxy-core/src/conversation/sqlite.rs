@@ -0,0 +1,183 @@
+use async_trait::async_trait;
+use sqlx::{
+    sqlite::{SqlitePool, SqliteRow},
+    Row,
+};
+use tokio::sync::OnceCell;
+
+use super::{store_error, TABLE};
+use crate::{types::Result, ConversationStore, Error, Turn};
+
+/// Conversation store keeping turns in a `SQLite` database
+///
+/// # Example
+///
+/// ```rust
+/// # fn example() -> llm_proxy_core::Result<()> {
+/// use llm_proxy_core::conversation::SqliteConversationStore;
+///
+/// let store = SqliteConversationStore::new("sqlite://conversations.db?mode=rwc")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SqliteConversationStore {
+    pool: SqlitePool,
+    schema: OnceCell<()>,
+}
+
+impl SqliteConversationStore {
+    /// Create a store in the database at `url`, connected on first use
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `url` isn't a valid `SQLite` URL.
+    pub fn new(url: &str) -> Result<Self> {
+        let pool = SqlitePool::connect_lazy(url).map_err(|e| store_error(&e))?;
+        Ok(Self::from_pool(pool))
+    }
+
+    /// Create a store in the database of `pool`
+    #[must_use]
+    pub const fn from_pool(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            schema: OnceCell::const_new(),
+        }
+    }
+
+    /// Create the table and its index unless they exist
+    async fn ensure_schema(&self) -> Result<()> {
+        self.schema
+            .get_or_try_init(|| async {
+                sqlx::query(&format!(
+                    "CREATE TABLE IF NOT EXISTS {TABLE} (
+                        id INTEGER PRIMARY KEY AUTOINCREMENT,
+                        session TEXT NOT NULL,
+                        timestamp INTEGER NOT NULL,
+                        messages TEXT NOT NULL,
+                        reply TEXT NOT NULL
+                    )"
+                ))
+                .execute(&self.pool)
+                .await
+                .map_err(|e| store_error(&e))?;
+                sqlx::query(&format!(
+                    "CREATE INDEX IF NOT EXISTS {TABLE}_session ON {TABLE} (session, id)"
+                ))
+                .execute(&self.pool)
+                .await
+                .map_err(|e| store_error(&e))?;
+                Ok::<_, Error>(())
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ConversationStore for SqliteConversationStore {
+    async fn append(&self, session: &str, turn: &Turn) -> Result<()> {
+        self.ensure_schema().await?;
+        sqlx::query(&format!(
+            "INSERT INTO {TABLE} (session, timestamp, messages, reply) VALUES (?, ?, ?, ?)"
+        ))
+        .bind(session)
+        .bind(i64::try_from(turn.timestamp).unwrap_or(i64::MAX))
+        .bind(serde_json::to_string(&turn.messages)?)
+        .bind(turn.reply.to_string())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| store_error(&e))?;
+        Ok(())
+    }
+
+    async fn history(&self, session: &str, limit: Option<u32>) -> Result<Vec<Turn>> {
+        self.ensure_schema().await?;
+        // The newest turns are selected, then put back in order
+        let mut turns = sqlx::query(&format!(
+            "SELECT timestamp, messages, reply FROM {TABLE}
+             WHERE session = ? ORDER BY id DESC LIMIT ?"
+        ))
+        .bind(session)
+        .bind(limit.map_or(-1, i64::from))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| store_error(&e))?
+        .iter()
+        .map(turn)
+        .collect::<Result<Vec<_>>>()?;
+        turns.reverse();
+        Ok(turns)
+    }
+}
+
+fn turn(row: &SqliteRow) -> Result<Turn> {
+    let column = |e| store_error(&e);
+    Ok(Turn {
+        timestamp: u64::try_from(row.try_get::<i64, _>("timestamp").map_err(column)?)
+            .unwrap_or_default(),
+        messages: serde_json::from_str(row.try_get("messages").map_err(column)?)?,
+        reply: serde_json::from_str(row.try_get("reply").map_err(column)?)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_append_and_read_history() {
+        // A single connection, since every connection has its own in-memory database
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_lazy("sqlite::memory:")
+            .expect("Failed to create pool");
+        let store = SqliteConversationStore::from_pool(pool);
+        for (index, (session, content)) in [("a", "Hi"), ("b", "Hello"), ("a", "How are you?")]
+            .into_iter()
+            .enumerate()
+        {
+            store
+                .append(
+                    session,
+                    &Turn {
+                        timestamp: 1_000 + index as u64,
+                        messages: vec![json!({"role": "user", "content": content})],
+                        reply: json!({"role": "assistant", "content": format!("Re: {content}")}),
+                    },
+                )
+                .await
+                .expect("Failed to append turn");
+        }
+
+        let history = store
+            .history("a", None)
+            .await
+            .expect("Failed to read history");
+        let replies: Vec<_> = history.iter().map(|turn| &turn.reply["content"]).collect();
+        assert_eq!(replies, ["Re: Hi", "Re: How are you?"]);
+
+        let history = store
+            .history("a", Some(1))
+            .await
+            .expect("Failed to read history");
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].timestamp, 1_002);
+        assert_eq!(
+            history[0].clone().into_messages().collect::<Vec<_>>(),
+            [
+                json!({"role": "user", "content": "How are you?"}),
+                json!({"role": "assistant", "content": "Re: How are you?"}),
+            ]
+        );
+        assert!(store
+            .history("c", None)
+            .await
+            .expect("Failed to read history")
+            .is_empty());
+    }
+}
@@ -17,6 +17,23 @@ pub enum Error {
     JsonError(serde_json::Error),
     IoError(std::io::Error),
     AuthenticationError(String),
+    /// Every provider of a chained token provider failed
+    TokenProvidersExhausted(Vec<TokenAttempt>),
+    /// The request uses a feature the LLM backend doesn't support
+    Unsupported(String),
+    /// A processor refused the request, e.g. because of its content
+    Rejected(String),
+    /// The request is malformed, e.g. a tool definition isn't a valid schema
+    InvalidRequest(String),
+}
+
+/// A failed attempt to get a token from one provider of a chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenAttempt {
+    /// Name of the provider that was tried
+    pub provider: String,
+    /// Why the provider failed
+    pub error: String,
 }
 
 impl std::error::Error for Error {}
@@ -33,6 +50,17 @@ impl fmt::Display for Error {
             Self::JsonError(e) => write!(f, "JSON error: {e}"),
             Self::IoError(e) => write!(f, "IO error: {e}"),
             Self::AuthenticationError(e) => write!(f, "AuthenticationError error: {e}"),
+            Self::Unsupported(msg) => write!(f, "Unsupported request: {msg}"),
+            Self::Rejected(msg) => write!(f, "Request rejected: {msg}"),
+            Self::InvalidRequest(msg) => write!(f, "Invalid request: {msg}"),
+            Self::TokenProvidersExhausted(attempts) => {
+                write!(f, "No token provider succeeded")?;
+                for (index, attempt) in attempts.iter().enumerate() {
+                    let separator = if index == 0 { ": " } else { "; " };
+                    write!(f, "{separator}{} ({})", attempt.provider, attempt.error)?;
+                }
+                Ok(())
+            }
         }
     }
 }
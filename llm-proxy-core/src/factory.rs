@@ -0,0 +1,270 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    types::Result, AuthScheme, ClientProvider, Error, LLMRequest, Pipeline, ProviderCapabilities,
+    TokenProvider, UrlProvider,
+};
+
+/// Everything a [`ProviderFactory`] needs to build a pipeline for one configured backend.
+///
+/// The server resolves the parts shared by all providers (tokens, endpoints,
+/// HTTP clients, headers) from its configuration; the factory applies those
+/// its client supports and reads its own settings with
+/// [`ProviderContext::settings`].
+#[derive(Clone)]
+pub struct ProviderContext {
+    /// Base URL of the backend
+    pub base_url: String,
+    /// Provider-specific settings, e.g. the Azure deployment
+    pub settings: serde_json::Value,
+    /// Provider of the API token, if the backend is configured with one
+    pub token_provider: Option<Arc<dyn TokenProvider>>,
+    /// Provider of the request URL, replacing `base_url`, e.g. for load balancing
+    pub url_provider: Option<Arc<dyn UrlProvider>>,
+    /// Provider of the HTTP client, replacing the default client
+    pub client_provider: Option<Arc<dyn ClientProvider>>,
+    /// How the API token is sent, replacing the provider's default
+    pub auth_scheme: Option<AuthScheme>,
+    /// Static headers sent with every request
+    pub headers: Vec<(String, String)>,
+    /// Provider-specific defaults outside of `settings`, e.g. `openai.organization`
+    pub attributes: HashMap<String, String>,
+    /// What the backend supports
+    pub capabilities: ProviderCapabilities,
+}
+
+impl ProviderContext {
+    /// Create a context for the backend at `base_url`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            settings: serde_json::Value::Null,
+            token_provider: None,
+            url_provider: None,
+            client_provider: None,
+            auth_scheme: None,
+            headers: Vec::new(),
+            attributes: HashMap::new(),
+            capabilities: ProviderCapabilities::default(),
+        }
+    }
+
+    /// Set the provider-specific settings
+    #[must_use]
+    pub fn with_settings(mut self, settings: serde_json::Value) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    /// Set the provider of the API token
+    #[must_use]
+    pub fn with_token_provider(mut self, token_provider: Arc<dyn TokenProvider>) -> Self {
+        self.token_provider = Some(token_provider);
+        self
+    }
+
+    /// Set the provider of the request URL
+    #[must_use]
+    pub fn with_url_provider(mut self, url_provider: Arc<dyn UrlProvider>) -> Self {
+        self.url_provider = Some(url_provider);
+        self
+    }
+
+    /// Set the provider of the HTTP client
+    #[must_use]
+    pub fn with_client_provider(mut self, client_provider: Arc<dyn ClientProvider>) -> Self {
+        self.client_provider = Some(client_provider);
+        self
+    }
+
+    /// Set how the API token is sent
+    #[must_use]
+    pub fn with_auth_scheme(mut self, auth_scheme: AuthScheme) -> Self {
+        self.auth_scheme = Some(auth_scheme);
+        self
+    }
+
+    /// Send a static header with every request
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set a provider-specific attribute
+    #[must_use]
+    pub fn with_attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.into(), value.into());
+        self
+    }
+
+    /// Declare what the backend supports
+    #[must_use]
+    pub fn with_capabilities(mut self, capabilities: ProviderCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Deserialize the provider-specific settings; missing settings are read as `{}`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the settings don't match `T`.
+    pub fn settings<T: DeserializeOwned>(&self) -> Result<T> {
+        let settings = if self.settings.is_null() {
+            serde_json::Value::Object(serde_json::Map::new())
+        } else {
+            self.settings.clone()
+        };
+        serde_json::from_value(settings)
+            .map_err(|e| Error::ConfigError(format!("Invalid provider settings: {e}")))
+    }
+
+    /// The provider of the API token, for providers that can't work without one
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no token provider is set.
+    pub fn require_token_provider(&self) -> Result<Arc<dyn TokenProvider>> {
+        self.token_provider
+            .clone()
+            .ok_or_else(|| Error::ConfigError("The provider requires an API token".to_string()))
+    }
+}
+
+/// Builds pipelines for one kind of backend, e.g. Anthropic or Ollama.
+///
+/// Provider crates implement this trait and register their factories in a
+/// [`ProviderRegistry`] under the name used in configuration, so the server
+/// can create pipelines for any registered backend without knowing about it.
+#[async_trait]
+pub trait ProviderFactory<T: LLMRequest>: Send + Sync {
+    /// Whether the backend needs an API token; local servers often don't
+    fn requires_token(&self) -> bool {
+        true
+    }
+
+    /// Create a pipeline for the backend described by `context`
+    async fn create_pipeline(&self, context: ProviderContext) -> Result<Pipeline<T>>;
+}
+
+/// Provider factories keyed by the provider name used in configuration.
+///
+/// # Example
+///
+/// ```rust
+/// # use async_trait::async_trait;
+/// # use llm_proxy_core::{LLMRequest, Pipeline, Result};
+/// use llm_proxy_core::factory::{ProviderContext, ProviderFactory, ProviderRegistry};
+///
+/// struct EchoFactory;
+///
+/// #[async_trait]
+/// impl<T: LLMRequest> ProviderFactory<T> for EchoFactory {
+///     async fn create_pipeline(&self, _context: ProviderContext) -> Result<Pipeline<T>> {
+///         unimplemented!()
+///     }
+/// }
+///
+/// # #[derive(serde::Deserialize)]
+/// # struct MyRequest;
+/// # impl LLMRequest for MyRequest {
+/// #     fn messages(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
+/// #     fn model(&self) -> Result<String> { Ok("model".to_string()) }
+/// #     fn stream(&self) -> Result<bool> { Ok(false) }
+/// #     fn max_tokens(&self) -> Option<u32> { None }
+/// #     fn to_map(&self) -> Result<std::collections::HashMap<String, serde_json::Value>> { Ok(std::collections::HashMap::new()) }
+/// #     fn to_value(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
+/// #     fn to_bytes(&self) -> Result<bytes::Bytes> { Ok(bytes::Bytes::new()) }
+/// # }
+/// let registry = ProviderRegistry::<MyRequest>::new().with_provider("echo", EchoFactory);
+/// assert!(registry.get("echo").is_some());
+/// assert!(registry.get("openai").is_none());
+/// ```
+pub struct ProviderRegistry<T: LLMRequest> {
+    factories: HashMap<String, Arc<dyn ProviderFactory<T>>>,
+}
+
+impl<T: LLMRequest> ProviderRegistry<T> {
+    /// Create an empty registry
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Register `factory` under `name`, replacing any factory registered before
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        factory: impl ProviderFactory<T> + 'static,
+    ) -> &mut Self {
+        self.factories.insert(name.into(), Arc::new(factory));
+        self
+    }
+
+    /// Register `factory` under `name`
+    #[must_use]
+    pub fn with_provider(
+        mut self,
+        name: impl Into<String>,
+        factory: impl ProviderFactory<T> + 'static,
+    ) -> Self {
+        self.register(name, factory);
+        self
+    }
+
+    /// The factory registered under `name`
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<Arc<dyn ProviderFactory<T>>> {
+        self.factories.get(name).cloned()
+    }
+
+    /// Names of all registered providers, sorted
+    #[must_use]
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.factories.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+impl<T: LLMRequest> Default for ProviderRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, serde::Deserialize, PartialEq, Eq)]
+    struct Settings {
+        deployment: String,
+        #[serde(default)]
+        api_version: Option<String>,
+    }
+
+    #[test]
+    fn test_settings() {
+        let context = ProviderContext::new("https://example.com")
+            .with_settings(serde_json::json!({"deployment": "gpt-4o", "timeout_secs": 30}));
+        assert_eq!(
+            context.settings::<Settings>().expect("Invalid settings"),
+            Settings {
+                deployment: "gpt-4o".to_string(),
+                api_version: None,
+            }
+        );
+
+        // Missing settings are read as an empty object
+        let context = ProviderContext::new("https://example.com");
+        assert!(context.settings::<Settings>().is_err());
+        assert!(context.require_token_provider().is_err());
+    }
+}
@@ -0,0 +1,417 @@
+//! Recorded upstream responses for hermetic tests.
+//!
+//! A [`RecordingLLMClient`] wraps a real client and saves every response it
+//! returns, chunk by chunk with the time between chunks, to a fixture file.
+//! A [`ReplayingLLMClient`] later answers the same requests from these files,
+//! so processors and SSE handling can be tested against real responses
+//! without network access or API keys.
+//!
+//! Fixtures are JSON files named after the SHA-256 hash of the request, as
+//! returned by [`fixture_key`], in a directory shared by both clients.
+//!
+//! This module requires the `mock` feature.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::{
+    types::{ResponseStream, Result},
+    Error, LLMClient, LLMRequest, ProviderCapabilities, RequestContext,
+};
+
+/// A recorded response to a request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fixture {
+    /// The request, as sent to the client
+    pub request: Value,
+    /// The error the request failed with before any output, if it did
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The chunks of the response, in order
+    #[serde(default)]
+    pub events: Vec<FixtureEvent>,
+}
+
+/// A chunk of a recorded response, or the error that ended it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FixtureEvent {
+    /// Bytes of the response
+    Chunk {
+        /// Milliseconds since the previous event, or since the request
+        delay_ms: u64,
+        /// The bytes, as text
+        data: String,
+    },
+    /// The error that ended the response
+    Error {
+        /// Milliseconds since the previous event, or since the request
+        delay_ms: u64,
+        /// Message of the error
+        message: String,
+    },
+}
+
+impl FixtureEvent {
+    const fn delay_ms(&self) -> u64 {
+        match self {
+            Self::Chunk { delay_ms, .. } | Self::Error { delay_ms, .. } => *delay_ms,
+        }
+    }
+}
+
+/// The key of the fixture of `request`: the hex SHA-256 of its JSON
+///
+/// # Errors
+///
+/// Returns an error if the request can't be converted to JSON.
+pub fn fixture_key<T: LLMRequest>(request: &T) -> Result<String> {
+    Ok(hex::encode(Sha256::digest(
+        request.to_value()?.to_string().as_bytes(),
+    )))
+}
+
+/// The path of the fixture with `key` in `dir`
+fn fixture_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{key}.json"))
+}
+
+/// The message `e` is replayed with, so LLM errors round-trip unchanged
+fn error_message(e: &Error) -> String {
+    match e {
+        Error::LLMError(message) => message.clone(),
+        e => e.to_string(),
+    }
+}
+
+/// Milliseconds since `since`
+fn elapsed_ms(since: Instant) -> u64 {
+    u64::try_from(since.elapsed().as_millis()).unwrap_or(u64::MAX)
+}
+
+/// An `LLMClient` saving the responses of another client as fixtures.
+///
+/// Responses are forwarded unchanged. A fixture is written once its response
+/// ended, before the stream closes, so a test reading the whole response can
+/// rely on the file being there. Failing to write a fixture is logged.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use llm_proxy_core::{fixture::RecordingLLMClient, LLMClient, LLMRequest};
+///
+/// fn record<T: LLMRequest + 'static>(client: Arc<dyn LLMClient<T>>) -> RecordingLLMClient<T> {
+///     RecordingLLMClient::new(client, "tests/fixtures")
+/// }
+/// ```
+pub struct RecordingLLMClient<T: LLMRequest> {
+    inner: Arc<dyn LLMClient<T>>,
+    dir: PathBuf,
+}
+
+impl<T: LLMRequest> RecordingLLMClient<T> {
+    /// Create a client recording the responses of `inner` in `dir`, which is
+    /// created if needed
+    pub fn new(inner: Arc<dyn LLMClient<T>>, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            dir: dir.into(),
+        }
+    }
+}
+
+/// Write `fixture` to `path`, creating its directory
+async fn save(path: &Path, fixture: &Fixture) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(path, serde_json::to_vec_pretty(fixture)?).await?;
+    Ok(())
+}
+
+#[async_trait]
+impl<T: LLMRequest + 'static> LLMClient<T> for RecordingLLMClient<T> {
+    async fn execute(&self, request: T) -> Result<ResponseStream> {
+        self.execute_with_context(request, &RequestContext::new())
+            .await
+    }
+
+    async fn execute_with_context(
+        &self,
+        request: T,
+        context: &RequestContext,
+    ) -> Result<ResponseStream>
+    where
+        T: 'async_trait,
+    {
+        let path = fixture_path(&self.dir, &fixture_key(&request)?);
+        let mut fixture = Fixture {
+            request: request.to_value()?,
+            error: None,
+            events: Vec::new(),
+        };
+        let mut last = Instant::now();
+        let mut stream = match self.inner.execute_with_context(request, context).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                fixture.error = Some(error_message(&e));
+                if let Err(e) = save(&path, &fixture).await {
+                    warn!(error = %e, path = %path.display(), "Failed to write fixture");
+                }
+                return Err(e);
+            }
+        };
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            while let Some(item) = stream.recv().await {
+                let delay_ms = elapsed_ms(last);
+                last = Instant::now();
+                fixture.events.push(match &item {
+                    Ok(chunk) => FixtureEvent::Chunk {
+                        delay_ms,
+                        data: String::from_utf8_lossy(chunk).into_owned(),
+                    },
+                    Err(e) => FixtureEvent::Error {
+                        delay_ms,
+                        message: error_message(e),
+                    },
+                });
+                if tx.send(item).await.is_err() {
+                    // The client stopped reading; the response is incomplete
+                    return;
+                }
+            }
+            if let Err(e) = save(&path, &fixture).await {
+                warn!(error = %e, path = %path.display(), "Failed to write fixture");
+            }
+        });
+        Ok(rx)
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.inner.capabilities()
+    }
+}
+
+/// An `LLMClient` answering requests with the fixtures recorded for them.
+///
+/// Chunks are sent with their recorded delays, unless timing is turned off
+/// to run tests as fast as possible. Requests without a fixture fail.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::fixture::ReplayingLLMClient;
+///
+/// let client = ReplayingLLMClient::new("tests/fixtures").with_timing(false);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReplayingLLMClient {
+    dir: PathBuf,
+    timing: bool,
+    capabilities: ProviderCapabilities,
+}
+
+impl ReplayingLLMClient {
+    /// Create a client replaying the fixtures in `dir` with their timing
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            timing: true,
+            capabilities: ProviderCapabilities::default(),
+        }
+    }
+
+    /// Set whether chunks are sent with their recorded delays
+    #[must_use]
+    pub const fn with_timing(mut self, timing: bool) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Declare what the recorded backend supports, e.g. its models
+    #[must_use]
+    pub fn with_capabilities(mut self, capabilities: ProviderCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// The fixture recorded for the request with `key`
+    async fn fixture(&self, key: &str) -> Result<Fixture> {
+        let path = fixture_path(&self.dir, key);
+        let content = tokio::fs::read(&path)
+            .await
+            .map_err(|e| Error::LLMError(format!("No fixture at {}: {e}", path.display())))?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+}
+
+#[async_trait]
+impl<T: LLMRequest + 'static> LLMClient<T> for ReplayingLLMClient {
+    async fn execute(&self, request: T) -> Result<ResponseStream> {
+        let fixture = self.fixture(&fixture_key(&request)?).await?;
+        if let Some(message) = fixture.error {
+            return Err(Error::LLMError(message));
+        }
+        let timing = self.timing;
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            for event in fixture.events {
+                if timing && event.delay_ms() > 0 {
+                    tokio::time::sleep(Duration::from_millis(event.delay_ms())).await;
+                }
+                let item = match event {
+                    FixtureEvent::Chunk { data, .. } => Ok(Bytes::from(data)),
+                    FixtureEvent::Error { message, .. } => Err(Error::LLMError(message)),
+                };
+                if tx.send(item).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(rx)
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.capabilities.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::mock::{MockLLMClient, MockResponse};
+
+    #[derive(Deserialize)]
+    struct ChatRequest {
+        model: String,
+        messages: Value,
+        #[serde(default)]
+        stream: bool,
+    }
+
+    impl LLMRequest for ChatRequest {
+        fn messages(&self) -> Result<Value> {
+            Ok(self.messages.clone())
+        }
+
+        fn model(&self) -> Result<String> {
+            Ok(self.model.clone())
+        }
+
+        fn stream(&self) -> Result<bool> {
+            Ok(self.stream)
+        }
+
+        fn max_tokens(&self) -> Option<u32> {
+            None
+        }
+
+        fn to_map(&self) -> Result<HashMap<String, Value>> {
+            Ok(HashMap::new())
+        }
+
+        fn to_value(&self) -> Result<Value> {
+            Ok(json!({"model": self.model, "messages": self.messages, "stream": self.stream}))
+        }
+
+        fn to_bytes(&self) -> Result<Bytes> {
+            Ok(Bytes::from(self.to_value()?.to_string()))
+        }
+    }
+
+    fn request(content: &str) -> ChatRequest {
+        ChatRequest {
+            model: "mock-model".to_string(),
+            messages: json!([{"role": "user", "content": content}]),
+            stream: true,
+        }
+    }
+
+    async fn collect(mut rx: ResponseStream) -> Vec<std::result::Result<Bytes, String>> {
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event.map_err(|e| e.to_string()));
+        }
+        events
+    }
+
+    fn fixture_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("llm-proxy-fixtures-{name}-{}", std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn test_records_and_replays() {
+        let dir = fixture_dir("replay");
+        let mock = MockLLMClient::new()
+            .with_response(MockResponse::StreamError {
+                text: "One two three".to_string(),
+                after_tokens: 2,
+                message: "Connection reset".to_string(),
+            })
+            .with_response(MockResponse::Error("Rate limited".to_string()))
+            .with_token_delay(Duration::from_millis(20));
+        let recording = RecordingLLMClient::new(Arc::new(mock), &dir);
+
+        let recorded = collect(
+            recording
+                .execute(request("Hi"))
+                .await
+                .expect("Failed to execute request"),
+        )
+        .await;
+        let error = recording
+            .execute(request("Again"))
+            .await
+            .expect_err("The error is forwarded");
+
+        let fixture: Fixture = serde_json::from_slice(
+            &std::fs::read(fixture_path(
+                &dir,
+                &fixture_key(&request("Hi")).expect("Invalid request"),
+            ))
+            .expect("No fixture written"),
+        )
+        .expect("Invalid fixture");
+        assert_eq!(fixture.request["messages"][0]["content"], "Hi");
+        assert!(fixture.events[1].delay_ms() >= 20);
+
+        let replaying = ReplayingLLMClient::new(&dir).with_timing(false);
+        let replayed = collect(
+            replaying
+                .execute(request("Hi"))
+                .await
+                .expect("Failed to replay request"),
+        )
+        .await;
+        assert_eq!(replayed, recorded);
+        let replayed_error = LLMClient::<ChatRequest>::execute(&replaying, request("Again"))
+            .await
+            .expect_err("The error is replayed");
+        assert_eq!(replayed_error.to_string(), error.to_string());
+        assert!(
+            LLMClient::<ChatRequest>::execute(&replaying, request("Unknown"))
+                .await
+                .is_err()
+        );
+
+        std::fs::remove_dir_all(&dir).expect("Failed to remove fixtures");
+    }
+}
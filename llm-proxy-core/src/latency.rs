@@ -0,0 +1,175 @@
+//! Latency of streamed responses.
+//!
+//! A [`StreamTimer`] follows a streamed response from the moment the request
+//! is sent, and records how quickly the backend produced it as metrics:
+//!
+//! - `llm_proxy_time_to_first_token_seconds`: until the first generated output
+//! - `llm_proxy_inter_chunk_seconds`: between chunks of generated output
+//! - `llm_proxy_stream_duration_seconds`: until the end of the stream
+//! - `llm_proxy_tokens_per_second`: output tokens over the generation time
+//!
+//! All labeled by `model`. The same figures are recorded on the current span,
+//! in the fields `time_to_first_token_ms`, `duration_ms`, `tokens` and
+//! `tokens_per_second` when it declares them.
+
+use std::time::{Duration, Instant};
+
+use tracing::{debug, Span};
+
+/// Figures of a streamed response
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamStats {
+    /// Time until the first chunk of generated output
+    pub time_to_first_token: Option<Duration>,
+    /// Time until the end of the stream
+    pub duration: Duration,
+    /// Output tokens, as reported by the backend or else counted as chunks
+    pub tokens: u64,
+    /// Output tokens per second between the first and the last chunk
+    pub tokens_per_second: Option<f64>,
+}
+
+/// Times a streamed response; see the [module documentation](self)
+#[derive(Debug)]
+pub struct StreamTimer {
+    model: String,
+    started: Instant,
+    first_token: Option<Instant>,
+    last_token: Option<Instant>,
+    chunks: u64,
+    tokens: Option<u64>,
+}
+
+impl StreamTimer {
+    /// Start timing the response of a request for `model`, sent now
+    #[must_use]
+    pub fn start(model: impl Into<String>) -> Self {
+        Self::started_at(model, Instant::now())
+    }
+
+    /// Start timing the response of a request for `model`, sent at `started`
+    #[must_use]
+    pub fn started_at(model: impl Into<String>, started: Instant) -> Self {
+        Self {
+            model: model.into(),
+            started,
+            first_token: None,
+            last_token: None,
+            chunks: 0,
+            tokens: None,
+        }
+    }
+
+    /// Record the arrival of a chunk with generated output
+    pub fn output_chunk(&mut self) {
+        self.output_chunk_at(Instant::now());
+    }
+
+    fn output_chunk_at(&mut self, now: Instant) {
+        if let Some(last) = self.last_token {
+            metrics::histogram!("llm_proxy_inter_chunk_seconds", "model" => self.model.clone())
+                .record(now.saturating_duration_since(last));
+        }
+        self.first_token.get_or_insert(now);
+        self.last_token = Some(now);
+        self.chunks += 1;
+    }
+
+    /// Set the output tokens reported by the backend, e.g. in `usage`
+    pub const fn set_tokens(&mut self, tokens: u64) {
+        self.tokens = Some(tokens);
+    }
+
+    /// The figures of the response so far
+    #[must_use]
+    pub fn stats(&self) -> StreamStats {
+        self.stats_at(Instant::now())
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn stats_at(&self, now: Instant) -> StreamStats {
+        let tokens = self.tokens.unwrap_or(self.chunks);
+        let generation = self
+            .first_token
+            .zip(self.last_token)
+            .map(|(first, last)| last.saturating_duration_since(first))
+            .filter(|generation| !generation.is_zero());
+        StreamStats {
+            time_to_first_token: self
+                .first_token
+                .map(|first| first.saturating_duration_since(self.started)),
+            duration: now.saturating_duration_since(self.started),
+            tokens,
+            tokens_per_second: generation
+                .map(|generation| tokens as f64 / generation.as_secs_f64()),
+        }
+    }
+
+    /// End the stream, recording its figures as metrics and on the current span
+    pub fn finish(self) -> StreamStats {
+        let stats = self.stats();
+        let model = self.model;
+        if let Some(time_to_first_token) = stats.time_to_first_token {
+            metrics::histogram!("llm_proxy_time_to_first_token_seconds", "model" => model.clone())
+                .record(time_to_first_token);
+        }
+        metrics::histogram!("llm_proxy_stream_duration_seconds", "model" => model.clone())
+            .record(stats.duration);
+        if let Some(tokens_per_second) = stats.tokens_per_second {
+            metrics::histogram!("llm_proxy_tokens_per_second", "model" => model.clone())
+                .record(tokens_per_second);
+        }
+
+        let span = Span::current();
+        if let Some(time_to_first_token) = stats.time_to_first_token {
+            span.record(
+                "time_to_first_token_ms",
+                duration_millis(time_to_first_token),
+            );
+        }
+        span.record("duration_ms", duration_millis(stats.duration));
+        span.record("tokens", stats.tokens);
+        if let Some(tokens_per_second) = stats.tokens_per_second {
+            span.record("tokens_per_second", tokens_per_second);
+        }
+        debug!(
+            model,
+            time_to_first_token = ?stats.time_to_first_token,
+            duration = ?stats.duration,
+            tokens = stats.tokens,
+            tokens_per_second = ?stats.tokens_per_second,
+            "Stream finished"
+        );
+        stats
+    }
+}
+
+fn duration_millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_stats() {
+        let started = Instant::now();
+        let mut timer = StreamTimer::started_at("gpt-4o", started);
+        assert_eq!(timer.stats_at(started).time_to_first_token, None);
+
+        timer.output_chunk_at(started + Duration::from_millis(300));
+        timer.output_chunk_at(started + Duration::from_millis(400));
+        timer.output_chunk_at(started + Duration::from_millis(800));
+        let stats = timer.stats_at(started + Duration::from_secs(1));
+        assert_eq!(stats.time_to_first_token, Some(Duration::from_millis(300)));
+        assert_eq!(stats.duration, Duration::from_secs(1));
+        assert_eq!(stats.tokens, 3);
+        assert_eq!(stats.tokens_per_second, Some(6.0));
+
+        // Counts reported by the backend take precedence over chunks
+        timer.set_tokens(5);
+        let stats = timer.stats_at(started + Duration::from_secs(1));
+        assert_eq!(stats.tokens_per_second, Some(10.0));
+    }
+}
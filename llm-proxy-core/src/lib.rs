@@ -32,22 +32,64 @@
 //! ### Provider Integration
 //! - [`LLMClient`]: Handles communication with specific LLM providers
 //! - [`LLMRequest`]: Defines the interface for structured requests
+//! - [`ProviderCapabilities`]: Describes what a provider supports, like streaming, tools and models
+//!
+//! ### Provider Factories
+//! - [`ProviderFactory`]: Builds pipelines for one kind of backend from a [`ProviderContext`]
+//! - [`ProviderRegistry`]: Maps the provider names used in configuration to factories
 //!
 //! ### Supporting Components
 //! - [`TokenProvider`]: Manages API tokens and authentication
 //! - [`UrlProvider`]: Provides service endpoints
 //! - [`ClientProvider`]: Configures HTTP clients
+//! - [`RequestSigner`]: Signs requests for services that require signatures, like AWS
+//! - [`AuthScheme`]: Attaches tokens to requests as a bearer token, header or query parameter
+//!
+//! The [`redact`] module keeps API keys and tokens out of logs.
+//!
+//! The [`latency`] module times streamed responses, recording time to first
+//! token, gaps between chunks and tokens per second as metrics.
+//!
+//! An [`AuditStore`] persists request/response [`Transcript`]s for
+//! compliance reviews; the [`audit`] module provides `SQLite` and Postgres
+//! stores (with the `sqlite` and `postgres` features) and an archiver to S3
+//! (with the `s3` feature).
+//!
+//! A [`ConversationStore`] keeps the [`Turn`]s of conversations by session,
+//! so clients can leave their history to the proxy; the [`conversation`]
+//! module provides `SQLite`, Postgres and Redis stores (with the `sqlite`,
+//! `postgres` and `redis` features).
+//!
+//! The [`pricing`] module prices models per million tokens and computes the
+//! cost of requests from their usage.
+//!
+//! With the `mock` feature, the `mock` module provides `MockLLMClient`, which
+//! answers with scripted responses instead of calling a service, for tests
+//! and demos, the `fixture` module records the responses of a real service
+//! to files and replays them, and the `chaos` module injects scripted faults
+//! into the responses of a client.
+//!
+//! The [`providers`] module contains provider-agnostic implementations of these
+//! traits, such as a rotating pool of API keys.
+//!
+//! ### Request Context
+//! A [`RequestContext`] carries per-request information that isn't part of the
+//! request body, like the request ID, route, model and tenant, to the LLM
+//! client and its providers. A [`TenantResolver`] maps the key a client authenticates with
+//! to the tenant stored in the context, and a [`ClientKeyStore`] looks up the
+//! identity of the proxy-issued key a client authenticates with.
 //!
 //! ## Example Usage
 //!
 //! ```rust
 //! # use std::sync::Arc;
-//! # use anyhow::Result;
+//! # use llm_proxy_core::Result;
 //! # use bytes::Bytes;
 //! # use async_trait::async_trait;
 //! # use llm_proxy_core::{Pipeline, Processor, LLMClient, RequestParser, ResponseStream, LLMRequest};
 //! # use tokio::sync::mpsc;
 //! #
+//! # #[derive(serde::Deserialize)]
 //! # struct MyRequest;
 //! # impl LLMRequest for MyRequest {
 //! #     fn messages(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
@@ -56,6 +98,7 @@
 //! #     fn max_tokens(&self) -> Option<u32> { None }
 //! #     fn to_map(&self) -> Result<std::collections::HashMap<String, serde_json::Value>> { Ok(std::collections::HashMap::new()) }
 //! #     fn to_value(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
+//! #     fn to_bytes(&self) -> Result<bytes::Bytes> { Ok(bytes::Bytes::new()) }
 //! # }
 //! #
 //! # struct MyRequestParser;
@@ -86,7 +129,7 @@
 //! # async fn example() -> Result<()> {
 //! // Create pipeline components
 //! let parser = Arc::new(MyRequestParser);
-//! let processors = vec![Arc::new(MyProcessor)];
+//! let processors: Vec<Arc<dyn Processor<MyRequest>>> = vec![Arc::new(MyProcessor)];
 //! let processor_chain = Arc::new(llm_proxy_core::ProcessorChain::new(processors));
 //! let llm_client = Arc::new(MyLLMClient);
 //!
@@ -95,7 +138,7 @@
 //!
 //! // Process request
 //! let request = Bytes::from("{}");
-//! let response_stream = pipeline.execute(request).await?;
+//! let mut response_stream = pipeline.execute(request).await?;
 //!
 //! // Handle response stream
 //! while let Some(chunk) = response_stream.recv().await {
@@ -108,17 +151,48 @@
 //! # }
 //! ```
 
+pub mod audit;
+pub mod auth;
+pub mod capabilities;
+#[cfg(feature = "mock")]
+pub mod chaos;
+pub mod context;
+pub mod conversation;
 pub mod error;
+pub mod factory;
+#[cfg(feature = "mock")]
+pub mod fixture;
+pub mod latency;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod pipeline;
+pub mod pricing;
+pub mod providers;
+pub mod redact;
+pub mod retry;
+pub mod telemetry;
 pub mod traits;
 pub mod types;
+pub mod upstream;
 
-pub use error::Error;
+pub use auth::AuthScheme;
+pub use capabilities::ProviderCapabilities;
+pub use context::{RequestContext, REQUEST_ID_HEADER};
+pub use error::{Error, TokenAttempt};
+pub use factory::{ProviderContext, ProviderFactory, ProviderRegistry};
+pub use latency::{StreamStats, StreamTimer};
+#[cfg(feature = "mock")]
+pub use mock::{MockLLMClient, MockResponse};
 pub use pipeline::Pipeline;
+pub use pricing::{ModelPrice, Pricing};
+pub use retry::RetryPolicy;
 pub use traits::{
-    client::ClientProvider, client::LLMClient, client::TokenProvider, client::UrlProvider,
-    processor::Processor, processor::ProcessorChain, request::LLMRequest, request::LLMResponse,
-    request::RequestParser,
+    audit::AuditQuery, audit::AuditStore, audit::Transcript, client::ClientProvider,
+    client::EndpointStatus, client::KeyStatus, client::LLMClient, client::RequestSigner,
+    client::TokenProvider, client::UrlProvider, client_key::ClientKey, client_key::ClientKeyStore,
+    conversation::ConversationStore, conversation::Turn, processor::Processor,
+    processor::ProcessorChain, request::LLMRequest, request::LLMResponse, request::RequestParser,
+    stream::StreamProcessor, tenant::Tenant, tenant::TenantResolver,
 };
 pub use types::*;
 
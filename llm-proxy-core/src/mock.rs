@@ -0,0 +1,489 @@
+//! A scripted [`LLMClient`] for tests and demos.
+//!
+//! [`MockLLMClient`] answers requests without an upstream service, in the
+//! `OpenAI` chat completions format the proxy serves: with scripted
+//! [`MockResponse`]s in order, then with a template rendered from the
+//! request. Streaming requests receive the answer word by word, with a
+//! configurable delay between words, and responses can inject errors before
+//! or in the middle of a stream. Every request is recorded, so tests can
+//! assert on what reached the client.
+//!
+//! This module requires the `mock` feature.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, PoisonError,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::{
+    types::{unix_now, ResponseStream, Result},
+    Error, LLMClient, LLMRequest, ProviderCapabilities,
+};
+
+/// Template answering requests once the script is exhausted, unless replaced
+pub const DEFAULT_TEMPLATE: &str = "This is a mock response to: {last_message}";
+
+/// A scripted answer of a [`MockLLMClient`]
+///
+/// In configuration, responses are written as `{ text = "..." }`,
+/// `{ template = "..." }`, `{ error = "..." }` or
+/// `{ stream_error = { text = "...", after_tokens = 3, message = "..." } }`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MockResponse {
+    /// Answer with this text
+    Text(String),
+    /// Answer with this template rendered for the request, see
+    /// [`MockLLMClient::with_template`]
+    Template(String),
+    /// Fail the request before any output, with this message
+    Error(String),
+    /// Stream the first `after_tokens` words of `text`, then fail with `message`
+    ///
+    /// Non-streaming requests fail right away.
+    StreamError {
+        /// The answer that is cut off
+        text: String,
+        /// Number of words sent before the error
+        after_tokens: usize,
+        /// Message of the error
+        message: String,
+    },
+}
+
+/// An `LLMClient` that answers with scripted or generated responses.
+///
+/// Requests are answered with the scripted responses in order; once the
+/// script is exhausted, with the template. Keep an `Arc` of a client handed
+/// to a pipeline to inspect its [`requests`](MockLLMClient::requests).
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use llm_proxy_core::mock::{MockLLMClient, MockResponse};
+///
+/// let client = MockLLMClient::new()
+///     .with_response(MockResponse::Text("Hello!".to_string()))
+///     .with_response(MockResponse::Error("Rate limited".to_string()))
+///     .with_template("You said: {last_message}")
+///     .with_token_delay(Duration::from_millis(20));
+/// ```
+#[derive(Debug)]
+pub struct MockLLMClient {
+    script: Vec<MockResponse>,
+    template: String,
+    token_delay: Duration,
+    capabilities: ProviderCapabilities,
+    /// Number of requests answered so far
+    served: AtomicUsize,
+    /// Requests received so far, as JSON
+    received: Mutex<Vec<Value>>,
+}
+
+impl Default for MockLLMClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockLLMClient {
+    /// Create a client answering every request with [`DEFAULT_TEMPLATE`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            script: Vec::new(),
+            template: DEFAULT_TEMPLATE.to_string(),
+            token_delay: Duration::ZERO,
+            capabilities: ProviderCapabilities::default(),
+            served: AtomicUsize::new(0),
+            received: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Add a response to the end of the script
+    #[must_use]
+    pub fn with_response(mut self, response: MockResponse) -> Self {
+        self.script.push(response);
+        self
+    }
+
+    /// Add responses to the end of the script
+    #[must_use]
+    pub fn with_responses(mut self, responses: impl IntoIterator<Item = MockResponse>) -> Self {
+        self.script.extend(responses);
+        self
+    }
+
+    /// Set the template answering requests once the script is exhausted
+    ///
+    /// The placeholders `{model}`, `{last_message}`, `{message_count}` and
+    /// `{request_number}` (starting at 1) are replaced with the values of the
+    /// request.
+    #[must_use]
+    pub fn with_template(mut self, template: impl Into<String>) -> Self {
+        self.template = template.into();
+        self
+    }
+
+    /// Set the delay between two streamed words
+    #[must_use]
+    pub const fn with_token_delay(mut self, token_delay: Duration) -> Self {
+        self.token_delay = token_delay;
+        self
+    }
+
+    /// Declare what the mock supports, e.g. its models
+    #[must_use]
+    pub fn with_capabilities(mut self, capabilities: ProviderCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// The requests received so far, as JSON, in order
+    pub fn requests(&self) -> Vec<Value> {
+        self.received
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// The response to the request numbered `request_number`, starting at 0
+    fn response_for(&self, request_number: usize) -> MockResponse {
+        self.script
+            .get(request_number)
+            .cloned()
+            .unwrap_or_else(|| MockResponse::Template(self.template.clone()))
+    }
+}
+
+/// The text of a message's `content`, which is a string or a list of parts
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => String::new(),
+    }
+}
+
+/// Render `template` for a request
+fn render(template: &str, model: &str, messages: &[Value], request_number: usize) -> String {
+    let last_message = messages
+        .last()
+        .map(|message| content_text(&message["content"]))
+        .unwrap_or_default();
+    let values = [
+        ("model", model.to_string()),
+        ("last_message", last_message),
+        ("message_count", messages.len().to_string()),
+        ("request_number", (request_number + 1).to_string()),
+    ];
+    values
+        .iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{name}}}"), value)
+        })
+}
+
+/// Split text into words, each keeping the whitespace that follows it
+fn tokens(text: &str) -> Vec<&str> {
+    text.split_inclusive(char::is_whitespace).collect()
+}
+
+/// Usage counting words as tokens
+fn usage(messages: &[Value], completion: &str) -> Value {
+    let prompt_tokens: usize = messages
+        .iter()
+        .map(|message| content_text(&message["content"]).split_whitespace().count())
+        .sum();
+    let completion_tokens = completion.split_whitespace().count();
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    })
+}
+
+/// The events of a streamed answer
+struct MockStream {
+    id: String,
+    model: String,
+    created: u64,
+}
+
+impl MockStream {
+    fn chunk(&self, delta: &Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        })
+    }
+
+    fn event(chunk: &Value) -> Bytes {
+        Bytes::from(format!("data: {chunk}\n\n"))
+    }
+
+    /// Send `words`, then either the end of the stream or `error`
+    async fn send(
+        self,
+        words: Vec<String>,
+        usage: Value,
+        error: Option<String>,
+        token_delay: Duration,
+        tx: mpsc::Sender<Result<Bytes>>,
+    ) {
+        for (index, word) in words.iter().enumerate() {
+            if index > 0 && !token_delay.is_zero() {
+                tokio::time::sleep(token_delay).await;
+            }
+            let delta = if index == 0 {
+                json!({"role": "assistant", "content": word})
+            } else {
+                json!({"content": word})
+            };
+            if tx
+                .send(Ok(Self::event(&self.chunk(&delta, None))))
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+
+        let end = error.map_or_else(
+            || {
+                let mut last = self.chunk(&json!({}), Some("stop"));
+                last["usage"] = usage;
+                vec![
+                    Ok(Self::event(&last)),
+                    Ok(Bytes::from_static(b"data: [DONE]\n\n")),
+                ]
+            },
+            |message| vec![Err(Error::LLMError(message))],
+        );
+        for event in end {
+            if tx.send(event).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<T: LLMRequest + 'static> LLMClient<T> for MockLLMClient {
+    async fn execute(&self, request: T) -> Result<ResponseStream> {
+        let request_number = self.served.fetch_add(1, Ordering::SeqCst);
+        self.received
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(request.to_value()?);
+
+        let model = request.model()?;
+        let stream = request.stream()?;
+        let messages = match request.messages()? {
+            Value::Array(messages) => messages,
+            _ => Vec::new(),
+        };
+        let (text, error) = match self.response_for(request_number) {
+            MockResponse::Text(text) => (text, None),
+            MockResponse::Template(template) => {
+                (render(&template, &model, &messages, request_number), None)
+            }
+            MockResponse::Error(message) => return Err(Error::LLMError(message)),
+            MockResponse::StreamError { .. } if !stream => {
+                return Err(Error::LLMError(
+                    "Mock stream error on a non-streaming request".to_string(),
+                ))
+            }
+            MockResponse::StreamError {
+                text,
+                after_tokens,
+                message,
+            } => {
+                let text = tokens(&text)
+                    .into_iter()
+                    .take(after_tokens)
+                    .collect::<Vec<_>>()
+                    .concat();
+                (text, Some(message))
+            }
+        };
+
+        let id = format!("chatcmpl-mock-{}", request_number + 1);
+        let usage = usage(&messages, &text);
+        let (tx, rx) = mpsc::channel(100);
+        if stream {
+            let words = tokens(&text).into_iter().map(str::to_string).collect();
+            let mock_stream = MockStream {
+                id,
+                model,
+                created: unix_now(),
+            };
+            tokio::spawn(mock_stream.send(words, usage, error, self.token_delay, tx));
+        } else {
+            let response = json!({
+                "id": id,
+                "object": "chat.completion",
+                "created": unix_now(),
+                "model": model,
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": text},
+                    "finish_reason": "stop",
+                }],
+                "usage": usage,
+            });
+            tx.send(Ok(Bytes::from(response.to_string())))
+                .await
+                .map_err(|e| Error::LLMError(format!("Failed to send mock response: {e}")))?;
+        }
+        Ok(rx)
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.capabilities.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct ChatRequest {
+        model: String,
+        messages: Value,
+        #[serde(default)]
+        stream: bool,
+    }
+
+    impl LLMRequest for ChatRequest {
+        fn messages(&self) -> Result<Value> {
+            Ok(self.messages.clone())
+        }
+
+        fn model(&self) -> Result<String> {
+            Ok(self.model.clone())
+        }
+
+        fn stream(&self) -> Result<bool> {
+            Ok(self.stream)
+        }
+
+        fn max_tokens(&self) -> Option<u32> {
+            None
+        }
+
+        fn to_map(&self) -> Result<HashMap<String, Value>> {
+            Ok(HashMap::new())
+        }
+
+        fn to_value(&self) -> Result<Value> {
+            Ok(json!({"model": self.model, "messages": self.messages, "stream": self.stream}))
+        }
+
+        fn to_bytes(&self) -> Result<Bytes> {
+            Ok(Bytes::from(self.to_value()?.to_string()))
+        }
+    }
+
+    fn request(content: &str, stream: bool) -> ChatRequest {
+        ChatRequest {
+            model: "mock-model".to_string(),
+            messages: json!([{"role": "user", "content": content}]),
+            stream,
+        }
+    }
+
+    async fn collect(mut rx: ResponseStream) -> Vec<Result<Bytes>> {
+        let mut events = Vec::new();
+        while let Some(event) = rx.recv().await {
+            events.push(event);
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn test_script_then_template() {
+        let client = MockLLMClient::new()
+            .with_response(MockResponse::Text("Scripted".to_string()))
+            .with_response(MockResponse::Error("Rate limited".to_string()))
+            .with_template("{model} #{request_number}: {last_message}");
+
+        let events = collect(client.execute(request("Hi", false)).await.expect("Failed")).await;
+        let response: Value =
+            serde_json::from_slice(events[0].as_ref().expect("Failed")).expect("Invalid JSON");
+        assert_eq!(response["choices"][0]["message"]["content"], "Scripted");
+
+        let error = client
+            .execute(request("Hi", false))
+            .await
+            .expect_err("Scripted errors are returned");
+        assert!(error.to_string().contains("Rate limited"));
+
+        let events = collect(
+            client
+                .execute(request("Hello there", false))
+                .await
+                .expect("Failed"),
+        )
+        .await;
+        let response: Value =
+            serde_json::from_slice(events[0].as_ref().expect("Failed")).expect("Invalid JSON");
+        assert_eq!(
+            response["choices"][0]["message"]["content"],
+            "mock-model #3: Hello there"
+        );
+        assert_eq!(response["usage"]["prompt_tokens"], 2);
+        assert_eq!(client.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_with_injected_error() {
+        let client = MockLLMClient::new()
+            .with_response(MockResponse::Text("One two three".to_string()))
+            .with_response(MockResponse::StreamError {
+                text: "One two three".to_string(),
+                after_tokens: 2,
+                message: "Connection reset".to_string(),
+            });
+
+        let events = collect(client.execute(request("Hi", true)).await.expect("Failed")).await;
+        // One chunk per word, the finish chunk and [DONE]
+        assert_eq!(events.len(), 5);
+        let first: Value = serde_json::from_slice(&events[0].as_ref().expect("Failed")[6..])
+            .expect("Invalid chunk");
+        assert_eq!(first["choices"][0]["delta"]["content"], "One ");
+        assert_eq!(first["choices"][0]["delta"]["role"], "assistant");
+        let last: Value = serde_json::from_slice(&events[3].as_ref().expect("Failed")[6..])
+            .expect("Invalid chunk");
+        assert_eq!(last["usage"]["completion_tokens"], 3);
+
+        let events = collect(client.execute(request("Hi", true)).await.expect("Failed")).await;
+        assert_eq!(events.len(), 3);
+        assert!(events[2]
+            .as_ref()
+            .expect_err("The stream ends with the error")
+            .to_string()
+            .contains("Connection reset"));
+    }
+}
@@ -1,12 +1,14 @@
 use std::sync::Arc;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, instrument, warn};
 use uuid::Uuid;
 
 use crate::{
     traits::{
-        client::LLMClient, processor::ProcessorChain, request::LLMRequest, request::RequestParser,
+        client::LLMClient, processor::Processor, processor::ProcessorChain, request::LLMRequest,
+        request::RequestParser, stream::StreamProcessor,
     },
     types::{ResponseStream, Result},
+    ProviderCapabilities, RequestContext, RetryPolicy,
 };
 
 /// Pipeline for handling LLM proxy requests.
@@ -33,12 +35,13 @@ use crate::{
 ///
 /// ```rust
 /// # use std::sync::Arc;
-/// # use anyhow::Result;
+/// # use llm_proxy_core::Result;
 /// # use bytes::Bytes;
 /// # use async_trait::async_trait;
 /// # use llm_proxy_core::{Pipeline, RequestParser, ProcessorChain, LLMClient, ResponseStream, LLMRequest};
 /// # use tokio::sync::mpsc;
 /// #
+/// # #[derive(serde::Deserialize)]
 /// # struct MyRequest;
 /// # impl LLMRequest for MyRequest {
 /// #     fn messages(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
@@ -47,6 +50,7 @@ use crate::{
 /// #     fn max_tokens(&self) -> Option<u32> { None }
 /// #     fn to_map(&self) -> Result<std::collections::HashMap<String, serde_json::Value>> { Ok(std::collections::HashMap::new()) }
 /// #     fn to_value(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
+/// #     fn to_bytes(&self) -> Result<bytes::Bytes> { Ok(bytes::Bytes::new()) }
 /// # }
 /// #
 /// # struct MyRequestParser;
@@ -85,7 +89,7 @@ use crate::{
 /// # );
 /// // Create components
 /// let parser = Arc::new(MyRequestParser);
-/// let processors = vec![
+/// let processors: Vec<Arc<dyn llm_proxy_core::Processor<MyRequest>>> = vec![
 ///     Arc::new(MyProcessor),
 /// ];
 /// let processor_chain = Arc::new(ProcessorChain::new(processors));
@@ -116,6 +120,8 @@ pub struct Pipeline<T: LLMRequest> {
     parser: Arc<dyn RequestParser<T>>,
     processor_chain: Arc<ProcessorChain<T>>,
     llm_client: Arc<dyn LLMClient<T>>,
+    stream_processors: Vec<Arc<dyn StreamProcessor>>,
+    retry_policy: Option<RetryPolicy>,
     trace_id: Uuid,
 }
 
@@ -132,12 +138,13 @@ impl<T: LLMRequest> Pipeline<T> {
     ///
     /// ```rust
     /// # use std::sync::Arc;
-    /// # use anyhow::Result;
+    /// # use llm_proxy_core::Result;
     /// # use bytes::Bytes;
     /// # use async_trait::async_trait;
     /// # use llm_proxy_core::{Pipeline, RequestParser, ProcessorChain, LLMClient, ResponseStream, LLMRequest};
     /// # use tokio::sync::mpsc;
     /// #
+    /// # #[derive(serde::Deserialize)]
     /// # struct MyRequest;
     /// # impl LLMRequest for MyRequest {
     /// #     fn messages(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
@@ -146,6 +153,7 @@ impl<T: LLMRequest> Pipeline<T> {
     /// #     fn max_tokens(&self) -> Option<u32> { None }
     /// #     fn to_map(&self) -> Result<std::collections::HashMap<String, serde_json::Value>> { Ok(std::collections::HashMap::new()) }
     /// #     fn to_value(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
+    /// #     fn to_bytes(&self) -> Result<bytes::Bytes> { Ok(bytes::Bytes::new()) }
     /// # }
     /// #
     /// # struct MyRequestParser;
@@ -198,10 +206,61 @@ impl<T: LLMRequest> Pipeline<T> {
             parser,
             processor_chain,
             llm_client,
+            stream_processors: Vec::new(),
+            retry_policy: None,
             trace_id: Uuid::new_v4(),
         }
     }
 
+    /// Run `processors`, in order, before the processors of the pipeline
+    ///
+    /// This adds processors to a pipeline built elsewhere, e.g. the processors
+    /// configured for a route to the pipeline of a provider factory.
+    #[must_use]
+    pub fn with_processors(mut self, processors: Vec<Arc<dyn Processor<T>>>) -> Self
+    where
+        T: 'static,
+    {
+        if processors.is_empty() {
+            return self;
+        }
+        let mut chain = processors;
+        chain.push(self.processor_chain);
+        self.processor_chain = Arc::new(ProcessorChain::new(chain));
+        self
+    }
+
+    /// Run `processors`, in order, on the response stream of the LLM client
+    #[must_use]
+    pub fn with_stream_processors(mut self, processors: Vec<Arc<dyn StreamProcessor>>) -> Self {
+        self.stream_processors.extend(processors);
+        self
+    }
+
+    /// Replace the LLM client with the one `wrap` makes of it, e.g. a
+    /// decorator recording or disturbing its responses
+    #[must_use]
+    pub fn map_client(
+        mut self,
+        wrap: impl FnOnce(Arc<dyn LLMClient<T>>) -> Arc<dyn LLMClient<T>>,
+    ) -> Self {
+        self.llm_client = wrap(self.llm_client);
+        self
+    }
+
+    /// Send requests the LLM client failed again, as `policy` says
+    #[must_use]
+    pub const fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Capabilities of the pipeline's LLM client
+    #[must_use]
+    pub fn capabilities(&self) -> ProviderCapabilities {
+        self.llm_client.capabilities()
+    }
+
     /// Execute the pipeline with the given request body.
     ///
     /// # Arguments
@@ -219,8 +278,32 @@ impl<T: LLMRequest> Pipeline<T> {
     /// * The request processing fails
     /// * The LLM request fails
     /// * The response processing fails
-    #[allow(clippy::cognitive_complexity)]
     pub async fn execute(&self, request_body: bytes::Bytes) -> Result<ResponseStream> {
+        self.execute_with_context(request_body, &mut RequestContext::new())
+            .await
+    }
+
+    /// Execute the pipeline with the given request body and request context.
+    ///
+    /// The context's model is filled in from the parsed request if it isn't
+    /// set, and the context is passed on to the processors, which may add
+    /// attributes and response headers to it, and then to the LLM client. The
+    /// response stream passes through the stream processors, in order.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if:
+    /// * The request parsing fails
+    /// * The request processing fails
+    /// * The request uses a feature the LLM client doesn't support
+    /// * The LLM request fails
+    #[allow(clippy::cognitive_complexity)]
+    #[instrument(name = "pipeline", skip_all, fields(trace_id = %self.trace_id))]
+    pub async fn execute_with_context(
+        &self,
+        request_body: bytes::Bytes,
+        context: &mut RequestContext,
+    ) -> Result<ResponseStream> {
         info!(
             trace_id = %self.trace_id,
             request_size = request_body.len(),
@@ -234,15 +317,61 @@ impl<T: LLMRequest> Pipeline<T> {
             "Request parsed"
         );
 
+        if context.model.is_none() {
+            context.model = parsed_request.model().ok();
+        }
+
         // 2. Process Request
-        let processed_request = self.processor_chain.execute(parsed_request).await?;
+        let processed_request = self
+            .processor_chain
+            .execute_with_context(parsed_request, context)
+            .await?;
         debug!(
             trace_id = %self.trace_id,
+            attributes = ?context.attributes,
             "Request processed through chain"
         );
 
-        // 3. Forward to LLM
-        let response_stream = match self.llm_client.execute(processed_request).await {
+        // 3. Check the request against what the LLM supports
+        self.llm_client
+            .capabilities()
+            .validate(&processed_request)?;
+
+        // Stream processors see the request as it is sent, and retries send
+        // it again from its serialized form
+        let retries = self.retry_policy.map_or(0, |policy| policy.max_retries());
+        let sent_request = if self.stream_processors.is_empty() && retries == 0 {
+            serde_json::Value::Null
+        } else {
+            processed_request.to_value()?
+        };
+
+        // 4. Forward to LLM
+        let mut result = self
+            .llm_client
+            .execute_with_context(processed_request, context)
+            .await;
+        for retry in 1..=retries {
+            let error = match &result {
+                Err(e) if RetryPolicy::retries(e) => e,
+                _ => break,
+            };
+            let backoff = self
+                .retry_policy
+                .map(|policy| policy.backoff(retry))
+                .unwrap_or_default();
+            warn!(
+                trace_id = %self.trace_id,
+                error = %error,
+                retry,
+                backoff_ms = u64::try_from(backoff.as_millis()).unwrap_or(u64::MAX),
+                "LLM request failed, retrying"
+            );
+            tokio::time::sleep(backoff).await;
+            let request = serde_json::from_value(sent_request.clone())?;
+            result = self.llm_client.execute_with_context(request, context).await;
+        }
+        let response_stream = match result {
             Ok(stream) => stream,
             Err(e) => {
                 error!(
@@ -254,6 +383,14 @@ impl<T: LLMRequest> Pipeline<T> {
             }
         };
 
+        // 5. Process the response stream
+        let response_stream = self
+            .stream_processors
+            .iter()
+            .fold(response_stream, |stream, processor| {
+                processor.process_stream(&sent_request, context, stream)
+            });
+
         info!(
             trace_id = %self.trace_id,
             "Pipeline execution completed successfully"
@@ -267,7 +404,7 @@ mod tests {
     use std::collections::HashMap;
 
     use super::*;
-    use crate::{LLMRequest, Processor};
+    use crate::{Error, LLMRequest, Processor};
     use async_trait::async_trait;
     use bytes::Bytes;
     use serde::Deserialize;
@@ -277,7 +414,7 @@ mod tests {
     struct MockRequestParser;
 
     #[derive(Clone, Deserialize)]
-    struct MockRequest;
+    struct MockRequest {}
 
     impl LLMRequest for MockRequest {
         fn messages(&self) -> Result<Value> {
@@ -301,7 +438,7 @@ mod tests {
                 .as_object()
                 .expect("Failed to convert JSON to object")
                 .into_iter()
-                .map(|(k, v)| (k.to_string(), v.clone()))
+                .map(|(k, v)| (k.clone(), v.clone()))
                 .collect())
         }
 
@@ -317,7 +454,7 @@ mod tests {
     #[async_trait]
     impl RequestParser<MockRequest> for MockRequestParser {
         async fn parse(&self, _body: Bytes) -> Result<MockRequest> {
-            Ok(MockRequest)
+            Ok(MockRequest {})
         }
     }
 
@@ -360,4 +497,126 @@ mod tests {
             .expect("Failed to unwrap response");
         assert_eq!(response, Bytes::from("test response"));
     }
+
+    struct RejectingProcessor;
+
+    #[async_trait]
+    impl Processor<MockRequest> for RejectingProcessor {
+        async fn process(&self, _request: MockRequest) -> Result<MockRequest> {
+            Err(Error::ProcessError("rejected".to_string()))
+        }
+    }
+
+    struct UppercaseStreamProcessor;
+
+    impl StreamProcessor for UppercaseStreamProcessor {
+        fn process_stream(
+            &self,
+            _request: &Value,
+            _context: &RequestContext,
+            mut stream: ResponseStream,
+        ) -> ResponseStream {
+            let (tx, rx) = mpsc::channel(1);
+            tokio::spawn(async move {
+                while let Some(Ok(chunk)) = stream.recv().await {
+                    let chunk = Bytes::from(chunk.to_ascii_uppercase());
+                    if tx.send(Ok(chunk)).await.is_err() {
+                        break;
+                    }
+                }
+            });
+            rx
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_processors_run() {
+        let pipeline = Pipeline::new(
+            Arc::new(MockRequestParser),
+            Arc::new(ProcessorChain::new(vec![Arc::new(MockProcessor)])),
+            Arc::new(MockLLMClient),
+        )
+        .with_stream_processors(vec![Arc::new(UppercaseStreamProcessor)]);
+
+        let mut rx = pipeline
+            .execute(Bytes::from("test"))
+            .await
+            .expect("Failed to execute pipeline");
+        let response = rx
+            .recv()
+            .await
+            .expect("Failed to receive response")
+            .expect("Failed to unwrap response");
+        assert_eq!(response, Bytes::from("TEST RESPONSE"));
+    }
+
+    #[tokio::test]
+    async fn test_added_processors_run() {
+        let pipeline = Pipeline::new(
+            Arc::new(MockRequestParser),
+            Arc::new(ProcessorChain::new(vec![Arc::new(MockProcessor)])),
+            Arc::new(MockLLMClient),
+        )
+        .with_processors(vec![Arc::new(RejectingProcessor)]);
+
+        let error = pipeline
+            .execute(Bytes::from("test"))
+            .await
+            .expect_err("The added processor rejects the request");
+        assert!(error.to_string().contains("rejected"));
+    }
+
+    /// Fails with an LLM error until it has been called `failures` times
+    struct FlakyLLMClient {
+        failures: usize,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait]
+    impl LLMClient<MockRequest> for FlakyLLMClient {
+        async fn execute(&self, request: MockRequest) -> Result<ResponseStream> {
+            let call = self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if call < self.failures {
+                return Err(Error::LLMError("upstream unavailable".to_string()));
+            }
+            MockLLMClient.execute(request).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_requests_are_retried() {
+        let client = Arc::new(FlakyLLMClient {
+            failures: 2,
+            calls: std::sync::atomic::AtomicUsize::new(0),
+        });
+        let policy = RetryPolicy::new(2).with_initial_backoff(std::time::Duration::ZERO);
+        let pipeline = Pipeline::new(
+            Arc::new(MockRequestParser),
+            Arc::new(ProcessorChain::new(vec![Arc::new(MockProcessor)])),
+            client.clone(),
+        )
+        .with_retry_policy(policy);
+
+        let result = pipeline.execute(Bytes::from("test")).await;
+        assert!(result.is_ok());
+        assert_eq!(client.calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        let client = Arc::new(FlakyLLMClient {
+            failures: 3,
+            calls: std::sync::atomic::AtomicUsize::new(0),
+        });
+        let pipeline = Pipeline::new(
+            Arc::new(MockRequestParser),
+            Arc::new(ProcessorChain::new(vec![Arc::new(MockProcessor)])),
+            client.clone(),
+        )
+        .with_retry_policy(policy);
+
+        let error = pipeline
+            .execute(Bytes::from("test"))
+            .await
+            .expect_err("The retries are exhausted");
+        assert!(error.to_string().contains("upstream unavailable"));
+        assert_eq!(client.calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
 }
@@ -0,0 +1,147 @@
+//! Prices of models and the cost of requests.
+//!
+//! A [`Pricing`] table maps model name prefixes to a [`ModelPrice`] and
+//! computes what a request cost from its token usage. [`Pricing::builtin`]
+//! holds the list prices of common hosted models; since prices change and
+//! contracts differ, entries can be overridden or added with
+//! [`Pricing::with_price`].
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Price of a model, in US dollars per million tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    /// Price of a million prompt tokens
+    pub input: f64,
+    /// Price of a million completion tokens
+    pub output: f64,
+}
+
+impl ModelPrice {
+    /// Create a price from the US dollars per million prompt and completion tokens
+    #[must_use]
+    pub const fn new(input: f64, output: f64) -> Self {
+        Self { input, output }
+    }
+
+    /// Cost of `prompt_tokens` and `completion_tokens`, in US dollars
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn cost(&self, prompt_tokens: u64, completion_tokens: u64) -> f64 {
+        (prompt_tokens as f64).mul_add(self.input, completion_tokens as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// List prices of [`Pricing::builtin`], in US dollars per million prompt and
+/// completion tokens
+const BUILTIN_PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o", 2.5, 10.0),
+    ("gpt-4o-mini", 0.15, 0.6),
+    ("gpt-4.1", 2.0, 8.0),
+    ("gpt-4.1-mini", 0.4, 1.6),
+    ("gpt-4.1-nano", 0.1, 0.4),
+    ("gpt-4-turbo", 10.0, 30.0),
+    ("gpt-4", 30.0, 60.0),
+    ("gpt-3.5-turbo", 0.5, 1.5),
+    ("o1", 15.0, 60.0),
+    ("o1-mini", 1.1, 4.4),
+    ("o3", 2.0, 8.0),
+    ("o3-mini", 1.1, 4.4),
+    ("o4-mini", 1.1, 4.4),
+    ("claude-opus-4", 15.0, 75.0),
+    ("claude-sonnet-4", 3.0, 15.0),
+    ("claude-3-opus", 15.0, 75.0),
+    ("claude-3-7-sonnet", 3.0, 15.0),
+    ("claude-3-5-sonnet", 3.0, 15.0),
+    ("claude-3-5-haiku", 0.8, 4.0),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("gemini-2.0-flash", 0.1, 0.4),
+    ("gemini-1.5-pro", 1.25, 5.0),
+    ("gemini-1.5-flash", 0.075, 0.3),
+];
+
+/// Prices of models, looked up by the longest prefix of their name.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::pricing::{ModelPrice, Pricing};
+///
+/// let pricing = Pricing::builtin().with_price("ft:gpt-4o-mini", ModelPrice::new(0.3, 1.2));
+/// assert_eq!(pricing.cost("gpt-4o-2024-08-06", 1_000, 500), Some(0.0075));
+/// assert_eq!(pricing.cost("my-local-model", 1_000, 500), None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pricing {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl Pricing {
+    /// Create an empty table
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a table with the list prices of common `OpenAI`, Anthropic and
+    /// Gemini models
+    #[must_use]
+    pub fn builtin() -> Self {
+        BUILTIN_PRICES
+            .iter()
+            .fold(Self::new(), |pricing, &(prefix, input, output)| {
+                pricing.with_price(prefix, ModelPrice::new(input, output))
+            })
+    }
+
+    /// Price the models whose name starts with `prefix` at `price`, replacing
+    /// any price for the same prefix
+    #[must_use]
+    pub fn with_price(mut self, prefix: impl Into<String>, price: ModelPrice) -> Self {
+        self.prices.insert(prefix.into(), price);
+        self
+    }
+
+    /// The price of `model`, by the longest matching prefix
+    #[must_use]
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        self.prices
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, price)| *price)
+    }
+
+    /// Cost of a request for `model` with the given usage, in US dollars, if
+    /// the model has a price
+    #[must_use]
+    pub fn cost(&self, model: &str, prompt_tokens: u64, completion_tokens: u64) -> Option<f64> {
+        self.price(model)
+            .map(|price| price.cost(prompt_tokens, completion_tokens))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_longest_prefix_wins() {
+        let pricing = Pricing::builtin();
+        assert_eq!(
+            pricing.price("gpt-4o-mini-2024-07-18"),
+            Some(ModelPrice::new(0.15, 0.6))
+        );
+        assert_eq!(
+            pricing.price("gpt-4-0613"),
+            Some(ModelPrice::new(30.0, 60.0))
+        );
+        assert_eq!(pricing.cost("o1-mini", 1_000_000, 0), Some(1.1));
+
+        let pricing = pricing.with_price("gpt-4o", ModelPrice::new(2.0, 8.0));
+        assert_eq!(pricing.cost("gpt-4o", 1_000_000, 1_000_000), Some(10.0));
+    }
+}
@@ -0,0 +1,55 @@
+//! Provider-agnostic implementations of the supporting provider traits.
+//!
+//! The types in this module implement [`TokenProvider`](crate::TokenProvider),
+//! [`UrlProvider`](crate::UrlProvider), [`ClientProvider`](crate::ClientProvider),
+//! [`RequestSigner`](crate::RequestSigner) and [`ClientKeyStore`](crate::ClientKeyStore)
+//! in ways that are useful for any LLM backend, and can be combined with the
+//! provider-specific implementations shipped by the provider crates.
+
+#[cfg(feature = "aws")]
+pub mod aws_secrets;
+pub mod cached;
+pub mod chained;
+pub mod client_keys;
+pub mod configurable;
+pub mod discovery;
+pub mod file;
+#[cfg(feature = "gcp")]
+pub mod gcp;
+pub mod health_checked;
+pub mod instrumented;
+pub mod key_pool;
+#[cfg(feature = "keyring")]
+pub mod keychain;
+pub mod load_balancing;
+pub mod mtls;
+pub mod proxied;
+pub mod registry;
+#[cfg(feature = "aws")]
+pub mod sigv4;
+pub mod tenant;
+
+#[cfg(feature = "aws")]
+pub use aws_secrets::{AwsSecretSource, AwsSecretTokenProvider};
+pub use cached::CachedTokenProvider;
+pub use chained::ChainedTokenProvider;
+#[cfg(feature = "sqlite")]
+pub use client_keys::SqliteClientKeyStore;
+pub use client_keys::{hash_key, FileClientKeyStore, StaticClientKeyStore};
+pub use configurable::{ClientSettings, ConfigurableClientProvider};
+pub use discovery::{DiscoverySource, DiscoveryUrlProvider};
+pub use file::FileTokenProvider;
+#[cfg(feature = "gcp")]
+pub use gcp::{GcpCredentials, GcpTokenProvider};
+pub use health_checked::{HealthCheckedUrlProvider, HealthListener};
+pub use instrumented::InstrumentedClientProvider;
+pub use key_pool::{load_keys, KeyPoolTokenProvider, PooledKey};
+#[cfg(feature = "keyring")]
+pub use keychain::KeyringTokenProvider;
+pub use load_balancing::{LoadBalancingUrlProvider, WeightedEndpoint};
+pub use mtls::{MtlsClientProvider, TlsSettings};
+pub use proxied::{ProxiedClientProvider, ProxySettings};
+pub use registry::TokenRegistry;
+#[cfg(feature = "aws")]
+pub use sigv4::SigV4RequestSigner;
+pub use tenant::StaticTenantResolver;
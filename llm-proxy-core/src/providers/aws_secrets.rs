@@ -0,0 +1,319 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use aws_credential_types::provider::ProvideCredentials;
+use aws_sigv4::{
+    http_request::{sign, SignableBody, SignableRequest, SigningSettings},
+    sign::v4,
+};
+use aws_smithy_runtime_api::client::identity::Identity;
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::redact::SecretString;
+use crate::{types::Result, Error, TokenProvider};
+
+/// Default interval after which the secret is fetched again
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_mins(5);
+
+/// Where an [`AwsSecretTokenProvider`] reads its API key from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AwsSecretSource {
+    /// A Secrets Manager secret
+    SecretsManager {
+        /// Name or ARN of the secret
+        secret_id: String,
+        /// Field holding the key, for secrets stored as JSON objects
+        json_key: Option<String>,
+    },
+    /// An SSM Parameter Store parameter, decrypted if it's a `SecureString`
+    SsmParameter {
+        /// Name or ARN of the parameter
+        name: String,
+    },
+}
+
+impl AwsSecretSource {
+    /// Service name, API target and request body of the lookup
+    fn request(&self) -> (&'static str, &'static str, Value) {
+        match self {
+            Self::SecretsManager { secret_id, .. } => (
+                "secretsmanager",
+                "secretsmanager.GetSecretValue",
+                json!({ "SecretId": secret_id }),
+            ),
+            Self::SsmParameter { name } => (
+                "ssm",
+                "AmazonSSM.GetParameter",
+                json!({ "Name": name, "WithDecryption": true }),
+            ),
+        }
+    }
+
+    /// Extract the API key from the lookup response
+    fn extract_token(&self, response: &Value) -> Result<String> {
+        let value = match self {
+            Self::SecretsManager { json_key, .. } => {
+                let secret = response["SecretString"].as_str().ok_or_else(|| {
+                    Error::AuthenticationError("Secret has no string value".to_string())
+                })?;
+                match json_key {
+                    Some(key) => serde_json::from_str::<Value>(secret)?[key.as_str()]
+                        .as_str()
+                        .ok_or_else(|| {
+                            Error::AuthenticationError(format!("Secret has no string field {key}"))
+                        })?
+                        .to_string(),
+                    None => secret.to_string(),
+                }
+            }
+            Self::SsmParameter { .. } => response["Parameter"]["Value"]
+                .as_str()
+                .ok_or_else(|| Error::AuthenticationError("Parameter has no value".to_string()))?
+                .to_string(),
+        };
+        Ok(value.trim().to_string())
+    }
+}
+
+/// A fetched key and when it was fetched
+#[derive(Debug)]
+struct CachedSecret {
+    token: SecretString,
+    fetched_at: Option<Instant>,
+}
+
+/// Token provider that reads the API key from AWS Secrets Manager or SSM Parameter Store.
+///
+/// Requests are authenticated with the standard AWS credential chain, so on
+/// EC2, ECS or EKS the instance or task role is used and no key has to be
+/// stored in environment variables. The key is cached and fetched again once
+/// the refresh interval elapses, or after the upstream service rejects it.
+/// If a refresh fails, the previously fetched key keeps being used.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use llm_proxy_core::providers::{AwsSecretSource, AwsSecretTokenProvider};
+///
+/// # async fn example() -> llm_proxy_core::Result<()> {
+/// let provider = AwsSecretTokenProvider::from_env(
+///     AwsSecretSource::SecretsManager {
+///         secret_id: "prod/openai".to_string(),
+///         json_key: Some("api_key".to_string()),
+///     },
+///     None,
+/// )
+/// .await;
+/// provider.refresh().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct AwsSecretTokenProvider {
+    client: reqwest::Client,
+    config: SdkConfig,
+    source: AwsSecretSource,
+    refresh_interval: Duration,
+    cached: Mutex<Option<CachedSecret>>,
+}
+
+impl AwsSecretTokenProvider {
+    /// Create a provider using the given AWS configuration
+    #[must_use]
+    pub fn new(config: SdkConfig, source: AwsSecretSource) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            config,
+            source,
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Create a provider using the AWS configuration of the environment,
+    /// optionally overriding its region
+    pub async fn from_env(source: AwsSecretSource, region: Option<String>) -> Self {
+        let mut loader = aws_config::defaults(BehaviorVersion::latest());
+        if let Some(region) = region {
+            loader = loader.region(Region::new(region));
+        }
+        Self::new(loader.load().await, source)
+    }
+
+    /// Set how often the key is fetched again
+    #[must_use]
+    pub const fn with_refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.refresh_interval = refresh_interval;
+        self
+    }
+
+    /// Set the HTTP client used to call AWS
+    #[must_use]
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Fetch the key now and cache it
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the key cannot be fetched.
+    pub async fn refresh(&self) -> Result<String> {
+        let token = self.fetch().await?;
+        *self.cached.lock().await = Some(CachedSecret {
+            token: SecretString::new(token.clone()),
+            fetched_at: Some(Instant::now()),
+        });
+        Ok(token)
+    }
+
+    /// Call the AWS API holding the key
+    async fn fetch(&self) -> Result<String> {
+        let region = self
+            .config
+            .region()
+            .ok_or_else(|| Error::ConfigError("AWS region is not configured".to_string()))?;
+        let credentials = self
+            .config
+            .credentials_provider()
+            .ok_or_else(|| {
+                Error::AuthenticationError("No AWS credentials are configured".to_string())
+            })?
+            .provide_credentials()
+            .await
+            .map_err(|e| {
+                Error::AuthenticationError(format!("Failed to load AWS credentials: {e}"))
+            })?;
+        let identity: Identity = credentials.into();
+
+        let (service, target, body) = self.source.request();
+        let url = format!("https://{service}.{region}.amazonaws.com/");
+        let body = serde_json::to_vec(&body)?;
+        let headers = [
+            ("content-type", "application/x-amz-json-1.1"),
+            ("x-amz-target", target),
+        ];
+
+        let signing_params = v4::SigningParams::builder()
+            .identity(&identity)
+            .region(region.as_ref())
+            .name(service)
+            .time(SystemTime::now())
+            .settings(SigningSettings::default())
+            .build()
+            .map_err(|e| Error::AuthenticationError(format!("Invalid signing parameters: {e}")))?
+            .into();
+        let signable = SignableRequest::new(
+            "POST",
+            url.as_str(),
+            headers.iter().copied(),
+            SignableBody::Bytes(&body),
+        )
+        .and_then(|request| sign(request, &signing_params))
+        .map_err(|e| Error::AuthenticationError(format!("Failed to sign AWS request: {e}")))?;
+        let (instructions, _signature) = signable.into_parts();
+
+        let mut request = self.client.post(&url);
+        for (name, value) in headers.iter().copied().chain(instructions.headers()) {
+            request = request.header(name, value);
+        }
+        let response =
+            request.body(body).send().await.map_err(|e| {
+                Error::AuthenticationError(format!("Failed to call {service}: {e}"))
+            })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(Error::AuthenticationError(format!(
+                "{service} returned {status}: {message}"
+            )));
+        }
+
+        let response: Value = response
+            .json()
+            .await
+            .map_err(|e| Error::AuthenticationError(format!("Invalid {service} response: {e}")))?;
+        debug!(service, "Fetched API key from AWS");
+        self.source.extract_token(&response)
+    }
+}
+
+#[async_trait]
+impl TokenProvider for AwsSecretTokenProvider {
+    async fn get_token(&self) -> Result<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some(secret) = cached.as_ref() {
+            if secret
+                .fetched_at
+                .is_some_and(|fetched_at| fetched_at.elapsed() < self.refresh_interval)
+            {
+                return Ok(secret.token.expose().to_string());
+            }
+        }
+
+        match self.fetch().await {
+            Ok(token) => {
+                *cached = Some(CachedSecret {
+                    token: SecretString::new(token.clone()),
+                    fetched_at: Some(Instant::now()),
+                });
+                Ok(token)
+            }
+            Err(e) => match cached.as_ref() {
+                Some(secret) => {
+                    warn!(error = %e, "Failed to refresh API key from AWS, using cached key");
+                    Ok(secret.token.expose().to_string())
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    async fn report_rejection(&self, token: &str, status: u16) {
+        if !matches!(status, 401 | 403) {
+            return;
+        }
+        // The key may have been rotated; fetch it again on the next request
+        if let Some(secret) = self.cached.lock().await.as_mut() {
+            if secret.token.expose() == token {
+                secret.fetched_at = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_secrets_manager_json_key() {
+        let source = AwsSecretSource::SecretsManager {
+            secret_id: "prod/openai".to_string(),
+            json_key: Some("api_key".to_string()),
+        };
+        let response = json!({ "SecretString": "{\"api_key\": \"sk-test\"}" });
+        assert_eq!(
+            source.extract_token(&response).expect("Failed to extract"),
+            "sk-test"
+        );
+    }
+
+    #[test]
+    fn test_extract_ssm_parameter() {
+        let source = AwsSecretSource::SsmParameter {
+            name: "/openai/key".to_string(),
+        };
+        let response = json!({ "Parameter": { "Value": "sk-test\n" } });
+        assert_eq!(
+            source.extract_token(&response).expect("Failed to extract"),
+            "sk-test"
+        );
+        assert!(source.extract_token(&json!({})).is_err());
+    }
+}
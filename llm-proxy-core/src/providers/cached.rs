@@ -0,0 +1,196 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::redact::SecretString;
+use crate::{types::Result, TokenProvider};
+
+/// A memoized token and when it was fetched
+#[derive(Debug)]
+struct CachedToken {
+    token: SecretString,
+    fetched_at: Instant,
+}
+
+/// Token provider decorator that memoizes the token of an inner provider.
+///
+/// Tokens are reused for `ttl`. Once a token is older than `ttl - refresh_ahead`,
+/// the next request still gets the cached token while a background task fetches
+/// a new one, so expensive providers (secret stores, `OAuth2` token endpoints)
+/// don't add latency to requests. Only an expired or missing token is fetched
+/// inline. A token the upstream service refuses as invalid (401 or 403) is
+/// dropped from the cache; other rejections, such as rate limits, keep it.
+/// The request context is not part of the cache key, so only wrap providers
+/// that return the same token for every request.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use llm_proxy_core::providers::{CachedTokenProvider, FileTokenProvider};
+///
+/// # fn example() -> llm_proxy_core::Result<()> {
+/// let provider = CachedTokenProvider::new(
+///     FileTokenProvider::new("/var/run/secrets/openai/api-key")?,
+///     Duration::from_mins(5),
+/// )
+/// .with_refresh_ahead(Duration::from_mins(1));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CachedTokenProvider<T> {
+    inner: Arc<T>,
+    ttl: Duration,
+    refresh_ahead: Duration,
+    cached: Arc<Mutex<Option<CachedToken>>>,
+    refreshing: Arc<AtomicBool>,
+}
+
+impl<T: TokenProvider + 'static> CachedTokenProvider<T> {
+    /// Cache tokens of `inner` for `ttl`, without refreshing ahead of expiry
+    #[must_use]
+    pub fn new(inner: T, ttl: Duration) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            ttl,
+            refresh_ahead: Duration::ZERO,
+            cached: Arc::new(Mutex::new(None)),
+            refreshing: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Refresh tokens in the background once they are within `refresh_ahead` of expiring
+    #[must_use]
+    pub const fn with_refresh_ahead(mut self, refresh_ahead: Duration) -> Self {
+        self.refresh_ahead = refresh_ahead;
+        self
+    }
+
+    /// Fetch a new token in a background task, unless one is already being fetched
+    fn spawn_refresh(&self) {
+        if self.refreshing.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let inner = self.inner.clone();
+        let cached = self.cached.clone();
+        let refreshing = self.refreshing.clone();
+        tokio::spawn(async move {
+            match inner.get_token().await {
+                Ok(token) => {
+                    *cached.lock().await = Some(CachedToken {
+                        token: SecretString::new(token),
+                        fetched_at: Instant::now(),
+                    });
+                    debug!("Refreshed cached token ahead of expiry");
+                }
+                Err(e) => warn!(error = %e, "Failed to refresh cached token"),
+            }
+            refreshing.store(false, Ordering::Release);
+        });
+    }
+}
+
+#[async_trait]
+impl<T: TokenProvider + 'static> TokenProvider for CachedTokenProvider<T> {
+    async fn get_token(&self) -> Result<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref() {
+            let age = token.fetched_at.elapsed();
+            if age < self.ttl {
+                if age >= self.ttl.saturating_sub(self.refresh_ahead) {
+                    self.spawn_refresh();
+                }
+                return Ok(token.token.expose().to_string());
+            }
+        }
+
+        // Fetch while holding the lock, so concurrent requests wait for one fetch
+        let token = self.inner.get_token().await?;
+        *cached = Some(CachedToken {
+            token: SecretString::new(token.clone()),
+            fetched_at: Instant::now(),
+        });
+        drop(cached);
+        Ok(token)
+    }
+
+    async fn report_rejection(&self, token: &str, status: u16) {
+        if matches!(status, 401 | 403) {
+            let mut cached = self.cached.lock().await;
+            if cached
+                .as_ref()
+                .is_some_and(|cached| cached.token.expose() == token)
+            {
+                *cached = None;
+            }
+        }
+        self.inner.report_rejection(token, status).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    /// Provider returning a new token on every call
+    #[derive(Default)]
+    struct CountingProvider {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TokenProvider for CountingProvider {
+        async fn get_token(&self) -> Result<String> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(format!("token-{call}"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_is_memoized() {
+        let provider =
+            CachedTokenProvider::new(CountingProvider::default(), Duration::from_mins(1));
+        assert_eq!(provider.get_token().await.expect("No token"), "token-1");
+        assert_eq!(provider.get_token().await.expect("No token"), "token-1");
+
+        provider.report_rejection("token-1", 401).await;
+        assert_eq!(provider.get_token().await.expect("No token"), "token-2");
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_keeps_token() {
+        let provider =
+            CachedTokenProvider::new(CountingProvider::default(), Duration::from_mins(1));
+        assert_eq!(provider.get_token().await.expect("No token"), "token-1");
+
+        provider.report_rejection("token-1", 429).await;
+        assert_eq!(provider.get_token().await.expect("No token"), "token-1");
+        provider.report_rejection("token-1", 403).await;
+        assert_eq!(provider.get_token().await.expect("No token"), "token-2");
+    }
+
+    #[tokio::test]
+    async fn test_refreshes_ahead_of_expiry() {
+        let provider =
+            CachedTokenProvider::new(CountingProvider::default(), Duration::from_mins(1))
+                .with_refresh_ahead(Duration::from_mins(1));
+        assert_eq!(provider.get_token().await.expect("No token"), "token-1");
+
+        // Within the refresh window: the cached token is served while a new one is fetched
+        assert_eq!(provider.get_token().await.expect("No token"), "token-1");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(provider.get_token().await.expect("No token"), "token-2");
+    }
+}
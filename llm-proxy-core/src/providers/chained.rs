@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::debug;
+
+use crate::{error::TokenAttempt, types::Result, Error, RequestContext, TokenProvider};
+
+/// Token provider that tries several providers in order and returns the first token found.
+///
+/// Useful to fall back between credential sources, e.g. an environment
+/// variable in development and a mounted secret file in production. If every
+/// provider fails, the error lists each provider that was tried and why it
+/// failed. Rejections are reported to all providers, so the provider that
+/// issued the token can react to it.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+/// # use async_trait::async_trait;
+/// # use llm_proxy_core::{Result, TokenProvider};
+/// use llm_proxy_core::providers::ChainedTokenProvider;
+///
+/// # struct EnvProvider;
+/// # #[async_trait]
+/// # impl TokenProvider for EnvProvider {
+/// #     async fn get_token(&self) -> Result<String> { Ok("sk-env".to_string()) }
+/// # }
+/// # struct FileProvider;
+/// # #[async_trait]
+/// # impl TokenProvider for FileProvider {
+/// #     async fn get_token(&self) -> Result<String> { Ok("sk-file".to_string()) }
+/// # }
+/// let provider = ChainedTokenProvider::new()
+///     .with_provider("env", Arc::new(EnvProvider))
+///     .with_provider("file", Arc::new(FileProvider));
+/// ```
+#[derive(Default)]
+pub struct ChainedTokenProvider {
+    providers: Vec<(String, Arc<dyn TokenProvider>)>,
+}
+
+impl ChainedTokenProvider {
+    /// Create an empty chain
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a provider to the chain; `name` identifies it in errors
+    #[must_use]
+    pub fn with_provider(
+        mut self,
+        name: impl Into<String>,
+        provider: Arc<dyn TokenProvider>,
+    ) -> Self {
+        self.providers.push((name.into(), provider));
+        self
+    }
+}
+
+#[async_trait]
+impl TokenProvider for ChainedTokenProvider {
+    async fn get_token(&self) -> Result<String> {
+        self.get_token_for(&RequestContext::new()).await
+    }
+
+    async fn get_token_for(&self, context: &RequestContext) -> Result<String> {
+        let mut attempts = Vec::new();
+        for (name, provider) in &self.providers {
+            match provider.get_token_for(context).await {
+                Ok(token) => {
+                    debug!(provider = %name, "Resolved token from chained provider");
+                    return Ok(token);
+                }
+                Err(e) => attempts.push(TokenAttempt {
+                    provider: name.clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+        Err(Error::TokenProvidersExhausted(attempts))
+    }
+
+    async fn report_rejection(&self, token: &str, status: u16) {
+        for (_, provider) in &self.providers {
+            provider.report_rejection(token, status).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedProvider(Option<&'static str>);
+
+    #[async_trait]
+    impl TokenProvider for FixedProvider {
+        async fn get_token(&self) -> Result<String> {
+            self.0
+                .map(str::to_string)
+                .ok_or_else(|| Error::ConfigError("not configured".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_returns_first_success() {
+        let provider = ChainedTokenProvider::new()
+            .with_provider("env", Arc::new(FixedProvider(None)))
+            .with_provider("file", Arc::new(FixedProvider(Some("sk-file"))))
+            .with_provider("vault", Arc::new(FixedProvider(Some("sk-vault"))));
+        assert_eq!(provider.get_token().await.expect("No token"), "sk-file");
+    }
+
+    #[tokio::test]
+    async fn test_reports_all_attempts() {
+        let provider = ChainedTokenProvider::new()
+            .with_provider("env", Arc::new(FixedProvider(None)))
+            .with_provider("file", Arc::new(FixedProvider(None)));
+
+        let Err(Error::TokenProvidersExhausted(attempts)) = provider.get_token().await else {
+            panic!("Expected all providers to fail");
+        };
+        let names: Vec<_> = attempts.iter().map(|a| a.provider.as_str()).collect();
+        assert_eq!(names, ["env", "file"]);
+    }
+}
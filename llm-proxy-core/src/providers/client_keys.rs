@@ -0,0 +1,263 @@
+//! Implementations of [`ClientKeyStore`](crate::ClientKeyStore).
+//!
+//! The stores keep the SHA-256 hashes of keys, as returned by [`hash_key`],
+//! so that a leaked store doesn't leak the keys:
+//!
+//! - [`StaticClientKeyStore`], with keys from configuration
+//! - [`FileClientKeyStore`], with keys from a JSON file, reloaded when it changes
+//! - [`SqliteClientKeyStore`] (with the `sqlite` feature), with keys in a
+//!   `client_keys` table, so keys can be issued and revoked without a restart
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteClientKeyStore;
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use async_trait::async_trait;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::{types::Result, ClientKey, ClientKeyStore, Error};
+
+/// The hex-encoded SHA-256 hash of `key`, as kept by the stores
+#[must_use]
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key))
+}
+
+/// Client key store backed by a fixed set of keys, e.g. from configuration
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::{providers::StaticClientKeyStore, ClientKey};
+///
+/// let store = StaticClientKeyStore::new().with_key(
+///     "pk-ci-0123456789",
+///     ClientKey {
+///         id: "ci-bot".to_string(),
+///         tenant: Some("acme".to_string()),
+///     },
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StaticClientKeyStore {
+    /// Identities by key hash
+    keys: HashMap<String, ClientKey>,
+}
+
+impl StaticClientKeyStore {
+    /// Create a store without keys
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept `key` as `identity`
+    #[must_use]
+    pub fn with_key(self, key: &str, identity: ClientKey) -> Self {
+        self.with_key_hash(hash_key(key), identity)
+    }
+
+    /// Accept the key with hash `key_sha256`, see [`hash_key`], as `identity`
+    #[must_use]
+    pub fn with_key_hash(mut self, key_sha256: impl Into<String>, identity: ClientKey) -> Self {
+        self.keys
+            .insert(key_sha256.into().to_ascii_lowercase(), identity);
+        self
+    }
+}
+
+#[async_trait]
+impl ClientKeyStore for StaticClientKeyStore {
+    async fn lookup(&self, key: &str) -> Result<Option<ClientKey>> {
+        Ok(self.keys.get(&hash_key(key)).cloned())
+    }
+}
+
+/// An entry of a key file
+#[derive(Debug, Deserialize)]
+struct KeyFileEntry {
+    id: String,
+    key_sha256: String,
+    #[serde(default)]
+    tenant: Option<String>,
+}
+
+/// Client key store reading keys from a JSON file and reloading them when it changes.
+///
+/// The file holds an array of keys with their identity and hash, e.g.
+/// `[{"id": "ci-bot", "key_sha256": "9f86d0...", "tenant": "acme"}]`, so it
+/// can be managed by a deployment tool or mounted from a secret. The parent
+/// directory is watched, as for
+/// [`FileTokenProvider`](crate::providers::FileTokenProvider). If the file
+/// becomes unreadable or invalid, the last loaded keys keep being accepted.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use llm_proxy_core::providers::FileClientKeyStore;
+///
+/// let store = FileClientKeyStore::new("/etc/llm-proxy/client-keys.json")?.watch()?;
+/// # Ok::<(), llm_proxy_core::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct FileClientKeyStore {
+    path: PathBuf,
+    store: Arc<RwLock<StaticClientKeyStore>>,
+    /// Kept alive so that the file stays watched
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl FileClientKeyStore {
+    /// Create a store reading the keys from `path`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be read or parsed.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let store = read_keys(&path)?;
+        Ok(Self {
+            path,
+            store: Arc::new(RwLock::new(store)),
+            _watcher: None,
+        })
+    }
+
+    /// Watch the file and reload the keys whenever it changes
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be watched.
+    pub fn watch(self) -> Result<Self> {
+        let path = self.path.clone();
+        let store = self.store.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if event.is_ok() {
+                    reload(&path, &store);
+                }
+            })
+            .map_err(|e| Error::ConfigError(format!("Failed to watch client key file: {e}")))?;
+
+        let directory = self
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        watcher
+            .watch(directory, RecursiveMode::NonRecursive)
+            .map_err(|e| Error::ConfigError(format!("Failed to watch client key file: {e}")))?;
+
+        Ok(Self {
+            _watcher: Some(watcher),
+            ..self
+        })
+    }
+}
+
+/// Read the keys stored in `path`
+fn read_keys(path: &Path) -> Result<StaticClientKeyStore> {
+    let entries: Vec<KeyFileEntry> = serde_json::from_str(&std::fs::read_to_string(path)?)
+        .map_err(|e| {
+            Error::ConfigError(format!("Invalid client key file {}: {e}", path.display()))
+        })?;
+    Ok(entries
+        .into_iter()
+        .fold(StaticClientKeyStore::new(), |store, entry| {
+            store.with_key_hash(
+                entry.key_sha256,
+                ClientKey {
+                    id: entry.id,
+                    tenant: entry.tenant,
+                },
+            )
+        }))
+}
+
+/// Replace the loaded keys with those in `path`, keeping the old keys on failure
+fn reload(path: &Path, store: &RwLock<StaticClientKeyStore>) {
+    match read_keys(path) {
+        Ok(keys) => {
+            let count = keys.keys.len();
+            *store.write().unwrap_or_else(PoisonError::into_inner) = keys;
+            info!(path = %path.display(), count, "Reloaded client keys from file");
+        }
+        Err(e) => warn!(path = %path.display(), error = %e, "Failed to reload client key file"),
+    }
+}
+
+#[async_trait]
+impl ClientKeyStore for FileClientKeyStore {
+    async fn lookup(&self, key: &str) -> Result<Option<ClientKey>> {
+        let key_sha256 = hash_key(key);
+        Ok(self
+            .store
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys
+            .get(&key_sha256)
+            .cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_static_store_resolves_known_keys_only() {
+        let identity = ClientKey {
+            id: "ci-bot".to_string(),
+            tenant: Some("acme".to_string()),
+        };
+        let store = StaticClientKeyStore::new().with_key("pk-ci", identity.clone());
+
+        assert_eq!(
+            store.lookup("pk-ci").await.expect("Failed to look up"),
+            Some(identity)
+        );
+        assert!(store
+            .lookup("pk-other")
+            .await
+            .expect("Failed to look up")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_file_store_keeps_keys_on_invalid_reload() {
+        let path =
+            std::env::temp_dir().join(format!("llm-proxy-client-keys-{}.json", std::process::id()));
+        let entries = serde_json::json!([
+            { "id": "ci-bot", "key_sha256": hash_key("pk-ci") },
+            { "id": "acme-app", "key_sha256": hash_key("pk-acme").to_uppercase(), "tenant": "acme" },
+        ]);
+        std::fs::write(&path, entries.to_string()).expect("Failed to write key file");
+        let store = FileClientKeyStore::new(&path).expect("Failed to create store");
+
+        let identity = store.lookup("pk-acme").await.expect("Failed to look up");
+        assert_eq!(
+            identity.and_then(|identity| identity.tenant).as_deref(),
+            Some("acme")
+        );
+
+        std::fs::write(&path, "not json").expect("Failed to write key file");
+        reload(&path, &store.store);
+        assert!(store
+            .lookup("pk-ci")
+            .await
+            .expect("Failed to look up")
+            .is_some());
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,160 @@
+use async_trait::async_trait;
+use sqlx::{sqlite::SqlitePool, Row};
+use tokio::sync::OnceCell;
+
+use super::hash_key;
+use crate::{types::Result, ClientKey, ClientKeyStore, Error};
+
+/// Client key store keeping key hashes in a `client_keys` table of a `SQLite` database
+///
+/// The table, created on first use, has the columns `key_sha256` (see
+/// [`hash_key`]), `id`, `tenant` and `revoked`; keys are issued by inserting
+/// a row and revoked by setting `revoked` to 1, taking effect immediately.
+///
+/// # Example
+///
+/// ```rust
+/// # fn example() -> llm_proxy_core::Result<()> {
+/// use llm_proxy_core::providers::SqliteClientKeyStore;
+///
+/// let store = SqliteClientKeyStore::new("sqlite://client-keys.db?mode=rwc")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SqliteClientKeyStore {
+    pool: SqlitePool,
+    schema: OnceCell<()>,
+}
+
+impl SqliteClientKeyStore {
+    /// Create a store in the database at `url`, connected on first use
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `url` isn't a valid `SQLite` URL.
+    pub fn new(url: &str) -> Result<Self> {
+        let pool = SqlitePool::connect_lazy(url).map_err(|e| store_error(&e))?;
+        Ok(Self::from_pool(pool))
+    }
+
+    /// Create a store in the database of `pool`
+    #[must_use]
+    pub const fn from_pool(pool: SqlitePool) -> Self {
+        Self {
+            pool,
+            schema: OnceCell::const_new(),
+        }
+    }
+
+    /// Issue `key` as `identity`, replacing an earlier entry of the key
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the key cannot be stored.
+    pub async fn insert(&self, key: &str, identity: &ClientKey) -> Result<()> {
+        self.ensure_schema().await?;
+        sqlx::query(
+            "INSERT OR REPLACE INTO client_keys (key_sha256, id, tenant, revoked)
+             VALUES (?, ?, ?, 0)",
+        )
+        .bind(hash_key(key))
+        .bind(&identity.id)
+        .bind(&identity.tenant)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| store_error(&e))?;
+        Ok(())
+    }
+
+    /// Create the table unless it exists
+    async fn ensure_schema(&self) -> Result<()> {
+        self.schema
+            .get_or_try_init(|| async {
+                sqlx::query(
+                    "CREATE TABLE IF NOT EXISTS client_keys (
+                        key_sha256 TEXT PRIMARY KEY,
+                        id TEXT NOT NULL,
+                        tenant TEXT,
+                        revoked INTEGER NOT NULL DEFAULT 0
+                    )",
+                )
+                .execute(&self.pool)
+                .await
+                .map_err(|e| store_error(&e))?;
+                Ok::<_, Error>(())
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ClientKeyStore for SqliteClientKeyStore {
+    async fn lookup(&self, key: &str) -> Result<Option<ClientKey>> {
+        self.ensure_schema().await?;
+        let row =
+            sqlx::query("SELECT id, tenant FROM client_keys WHERE key_sha256 = ? AND revoked = 0")
+                .bind(hash_key(key))
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|e| store_error(&e))?;
+        row.map(|row| {
+            Ok(ClientKey {
+                id: row.try_get("id").map_err(|e| store_error(&e))?,
+                tenant: row.try_get("tenant").map_err(|e| store_error(&e))?,
+            })
+        })
+        .transpose()
+    }
+}
+
+/// Wrap an error of the database
+fn store_error(e: &sqlx::Error) -> Error {
+    Error::ProcessError(format!("Client key store error: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_issued_keys_until_revoked() {
+        // A single connection, since every connection has its own in-memory database
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_lazy("sqlite::memory:")
+            .expect("Failed to create pool");
+        let store = SqliteClientKeyStore::from_pool(pool);
+        let identity = ClientKey {
+            id: "ci-bot".to_string(),
+            tenant: None,
+        };
+        store
+            .insert("pk-ci", &identity)
+            .await
+            .expect("Failed to insert key");
+
+        assert_eq!(
+            store.lookup("pk-ci").await.expect("Failed to look up"),
+            Some(identity)
+        );
+        assert!(store
+            .lookup("pk-other")
+            .await
+            .expect("Failed to look up")
+            .is_none());
+
+        sqlx::query("UPDATE client_keys SET revoked = 1")
+            .execute(&store.pool)
+            .await
+            .expect("Failed to revoke key");
+        assert!(store
+            .lookup("pk-ci")
+            .await
+            .expect("Failed to look up")
+            .is_none());
+    }
+}
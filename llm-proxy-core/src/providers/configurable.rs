@@ -0,0 +1,243 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use super::{ProxySettings, TlsSettings};
+use crate::{types::Result, ClientProvider, Error};
+
+/// Settings of the HTTP client used for upstream requests.
+///
+/// Unset options keep the `reqwest` defaults.
+#[derive(Debug, Clone, Default)]
+pub struct ClientSettings {
+    /// Maximum time to establish a connection
+    pub connect_timeout: Option<Duration>,
+    /// Maximum time between two reads of a response; applies to each chunk
+    /// of a streamed response rather than to the whole response
+    pub read_timeout: Option<Duration>,
+    /// Maximum time for a whole request, including reading the response
+    pub timeout: Option<Duration>,
+    /// Maximum number of idle connections kept per host
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Time after which idle connections are closed
+    pub pool_idle_timeout: Option<Duration>,
+    /// Use HTTP/2 without negotiating it first, for servers that only speak HTTP/2
+    pub http2_prior_knowledge: bool,
+    /// Interval of TCP keepalive probes
+    pub tcp_keepalive: Option<Duration>,
+    /// Value of the `User-Agent` header
+    pub user_agent: Option<String>,
+    /// Forward proxy for all requests
+    pub proxy: Option<ProxySettings>,
+    /// Client certificate and trusted CAs
+    pub tls: Option<TlsSettings>,
+}
+
+impl ClientSettings {
+    /// Create settings that keep all `reqwest` defaults
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum time to establish a connection
+    #[must_use]
+    pub const fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum time between two reads of a response
+    #[must_use]
+    pub const fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum time for a whole request
+    #[must_use]
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the maximum number of idle connections kept per host
+    #[must_use]
+    pub const fn with_pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Set the time after which idle connections are closed
+    #[must_use]
+    pub const fn with_pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Use HTTP/2 without negotiating it first
+    #[must_use]
+    pub const fn with_http2_prior_knowledge(mut self) -> Self {
+        self.http2_prior_knowledge = true;
+        self
+    }
+
+    /// Send TCP keepalive probes at the given interval
+    #[must_use]
+    pub const fn with_tcp_keepalive(mut self, interval: Duration) -> Self {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Set the value of the `User-Agent` header
+    #[must_use]
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Send all requests through a forward proxy
+    #[must_use]
+    pub fn with_proxy(mut self, proxy: ProxySettings) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Use a client certificate and/or custom CAs
+    #[must_use]
+    pub fn with_tls(mut self, tls: TlsSettings) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Build a client with these settings
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the proxy or TLS settings are invalid
+    /// or the client cannot be built.
+    pub fn build_client(&self) -> Result<reqwest::Client> {
+        self.client_builder()?
+            .build()
+            .map_err(|e| Error::ConfigError(format!("Failed to create HTTP client: {e}")))
+    }
+
+    /// Create a client builder configured with these settings, for further customization
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the proxy or TLS settings are invalid.
+    pub fn client_builder(&self) -> Result<reqwest::ClientBuilder> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(interval) = self.tcp_keepalive {
+            builder = builder.tcp_keepalive(interval);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = proxy.apply(builder)?;
+        }
+        if let Some(tls) = &self.tls {
+            builder = tls.apply(builder)?;
+        }
+        Ok(builder)
+    }
+}
+
+/// Client provider whose HTTP client is tuned through [`ClientSettings`].
+///
+/// Exposes the connection settings that matter for long-running LLM
+/// requests, like timeouts (a read timeout suits streamed responses better
+/// than a total timeout), connection pooling, HTTP/2 and TCP keepalive, and
+/// combines them with the proxy and TLS settings of
+/// [`ProxiedClientProvider`](super::ProxiedClientProvider) and
+/// [`MtlsClientProvider`](super::MtlsClientProvider).
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+/// use llm_proxy_core::providers::{ClientSettings, ConfigurableClientProvider};
+///
+/// # fn example() -> llm_proxy_core::Result<()> {
+/// let provider = ConfigurableClientProvider::new(
+///     &ClientSettings::new()
+///         .with_connect_timeout(Duration::from_secs(5))
+///         .with_read_timeout(Duration::from_secs(60))
+///         .with_pool_max_idle_per_host(32)
+///         .with_user_agent("my-gateway/1.0"),
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConfigurableClientProvider {
+    client: reqwest::Client,
+}
+
+impl ConfigurableClientProvider {
+    /// Create a provider whose client uses the given settings
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the client cannot be built, see
+    /// [`ClientSettings::build_client`].
+    pub fn new(settings: &ClientSettings) -> Result<Self> {
+        Ok(Self {
+            client: settings.build_client()?,
+        })
+    }
+}
+
+#[async_trait]
+impl ClientProvider for ConfigurableClientProvider {
+    async fn get_client(&self) -> Result<reqwest::Client> {
+        Ok(self.client.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_settings_build() {
+        let settings = ClientSettings::new()
+            .with_connect_timeout(Duration::from_secs(5))
+            .with_read_timeout(Duration::from_secs(30))
+            .with_timeout(Duration::from_mins(10))
+            .with_pool_max_idle_per_host(8)
+            .with_pool_idle_timeout(Duration::from_secs(90))
+            .with_http2_prior_knowledge()
+            .with_tcp_keepalive(Duration::from_secs(30))
+            .with_user_agent("test-agent")
+            .with_proxy(ProxySettings::new("http://127.0.0.1:3128"));
+        assert!(ConfigurableClientProvider::new(&settings).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_tls_is_rejected() {
+        let settings =
+            ClientSettings::new().with_tls(TlsSettings::new().with_ca_bundle("/nonexistent"));
+        assert!(ConfigurableClientProvider::new(&settings).is_err());
+    }
+}
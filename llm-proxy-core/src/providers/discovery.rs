@@ -0,0 +1,361 @@
+use std::{
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
+};
+
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use super::{LoadBalancingUrlProvider, WeightedEndpoint};
+use crate::redact::SecretString;
+use crate::{types::Result, Error, UrlProvider};
+
+/// Where a [`DiscoveryUrlProvider`] looks up upstream instances
+#[derive(Debug, Clone)]
+pub enum DiscoverySource {
+    /// DNS SRV records, e.g. `_http._tcp.vllm.default.svc.cluster.local`
+    #[cfg(feature = "dns")]
+    DnsSrv {
+        /// Name of the SRV record
+        name: String,
+    },
+    /// Passing instances of a service in a Consul catalog
+    Consul {
+        /// Address of the Consul HTTP API, e.g. `http://127.0.0.1:8500`
+        address: String,
+        /// Name of the service
+        service: String,
+        /// Only use instances with this tag
+        tag: Option<String>,
+        /// ACL token sent as `X-Consul-Token`
+        token: Option<SecretString>,
+    },
+}
+
+/// Entry of a Consul `/v1/health/service` response
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulEntry {
+    node: ConsulNode,
+    service: ConsulService,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulNode {
+    address: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulService {
+    #[serde(default)]
+    address: String,
+    port: u16,
+    #[serde(default)]
+    weights: Option<ConsulWeights>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ConsulWeights {
+    passing: u32,
+}
+
+/// Everything needed to look up instances, shared with the refresh task
+#[derive(Clone)]
+struct Discovery {
+    source: DiscoverySource,
+    scheme: String,
+    path: String,
+    client: reqwest::Client,
+    balancer: Arc<RwLock<Arc<LoadBalancingUrlProvider>>>,
+}
+
+impl Discovery {
+    /// Look up instances and replace the current endpoints with them.
+    ///
+    /// The previous endpoints are kept when the lookup fails or finds nothing.
+    async fn refresh(&self) -> Result<usize> {
+        let endpoints = self.lookup().await?;
+        if endpoints.is_empty() {
+            return Err(Error::ConfigError(format!(
+                "Service discovery found no instances in {:?}",
+                self.source
+            )));
+        }
+
+        let count = endpoints.len();
+        debug!(instances = count, "Refreshed discovered endpoints");
+        *self
+            .balancer
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) =
+            Arc::new(LoadBalancingUrlProvider::new(endpoints));
+        Ok(count)
+    }
+
+    async fn lookup(&self) -> Result<Vec<WeightedEndpoint>> {
+        match &self.source {
+            #[cfg(feature = "dns")]
+            DiscoverySource::DnsSrv { name } => self.lookup_srv(name).await,
+            DiscoverySource::Consul {
+                address,
+                service,
+                tag,
+                token,
+            } => {
+                let mut request = self
+                    .client
+                    .get(format!(
+                        "{}/v1/health/service/{service}",
+                        address.trim_end_matches('/')
+                    ))
+                    .query(&[("passing", "true")]);
+                if let Some(tag) = tag {
+                    request = request.query(&[("tag", tag)]);
+                }
+                if let Some(token) = token {
+                    request = request.header("X-Consul-Token", token.expose());
+                }
+
+                let entries: Vec<ConsulEntry> = request
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .map_err(|e| Error::ConfigError(format!("Consul lookup failed: {e}")))?
+                    .json()
+                    .await
+                    .map_err(|e| Error::ConfigError(format!("Invalid Consul response: {e}")))?;
+                Ok(self.consul_endpoints(entries))
+            }
+        }
+    }
+
+    #[cfg(feature = "dns")]
+    async fn lookup_srv(&self, name: &str) -> Result<Vec<WeightedEndpoint>> {
+        use hickory_resolver::proto::rr::rdata::SRV;
+
+        let resolver = hickory_resolver::TokioAsyncResolver::tokio_from_system_conf()
+            .map_err(|e| Error::ConfigError(format!("Failed to create DNS resolver: {e}")))?;
+        let records = resolver
+            .srv_lookup(name)
+            .await
+            .map_err(|e| Error::ConfigError(format!("SRV lookup for {name} failed: {e}")))?;
+
+        // Only the most preferred priority group receives traffic
+        let Some(priority) = records.iter().map(SRV::priority).min() else {
+            return Ok(Vec::new());
+        };
+        Ok(records
+            .iter()
+            .filter(|srv| srv.priority() == priority)
+            .map(|srv| {
+                let host = srv.target().to_utf8();
+                WeightedEndpoint::new(self.url(host.trim_end_matches('.'), srv.port()))
+                    .with_weight(u32::from(srv.weight()).max(1))
+            })
+            .collect())
+    }
+
+    fn consul_endpoints(&self, entries: Vec<ConsulEntry>) -> Vec<WeightedEndpoint> {
+        entries
+            .into_iter()
+            .map(|entry| {
+                let host = if entry.service.address.is_empty() {
+                    entry.node.address
+                } else {
+                    entry.service.address
+                };
+                let weight = entry.service.weights.map_or(1, |weights| weights.passing);
+                WeightedEndpoint::new(self.url(&host, entry.service.port)).with_weight(weight)
+            })
+            .collect()
+    }
+
+    fn url(&self, host: &str, port: u16) -> String {
+        format!("{}://{host}:{port}{}", self.scheme, self.path)
+    }
+}
+
+/// URL provider that discovers upstream endpoints from DNS SRV records or Consul.
+///
+/// Instances are looked up again every refresh interval, so instances can be
+/// added to or removed from a self-hosted inference fleet without changing
+/// the proxy configuration. Requests are spread across the discovered
+/// instances with weighted round-robin using the SRV or Consul weights, see
+/// [`LoadBalancingUrlProvider`]. If a lookup fails, the previously discovered
+/// instances keep being used.
+///
+/// Endpoint URLs are built as `{scheme}://{host}:{port}{path}`. DNS SRV
+/// lookups require the `dns` feature.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use llm_proxy_core::providers::{DiscoverySource, DiscoveryUrlProvider};
+///
+/// # async fn example() -> llm_proxy_core::Result<()> {
+/// let provider = DiscoveryUrlProvider::new(DiscoverySource::Consul {
+///     address: "http://127.0.0.1:8500".to_string(),
+///     service: "vllm".to_string(),
+///     tag: None,
+///     token: None,
+/// })
+/// .with_path("/v1/chat/completions");
+/// provider.refresh().await?;
+/// let provider = provider.spawn_refresh();
+/// # Ok(())
+/// # }
+/// ```
+pub struct DiscoveryUrlProvider {
+    discovery: Discovery,
+    refresh_interval: Duration,
+    refresh_task: Option<JoinHandle<()>>,
+}
+
+impl DiscoveryUrlProvider {
+    /// Create a provider for `source`, refreshed every 30 seconds
+    #[must_use]
+    pub fn new(source: DiscoverySource) -> Self {
+        Self {
+            discovery: Discovery {
+                source,
+                scheme: "http".to_string(),
+                path: String::new(),
+                client: reqwest::Client::new(),
+                balancer: Arc::new(RwLock::new(Arc::new(LoadBalancingUrlProvider::new(
+                    Vec::new(),
+                )))),
+            },
+            refresh_interval: Duration::from_secs(30),
+            refresh_task: None,
+        }
+    }
+
+    /// Set the URL scheme of discovered endpoints (default: `http`)
+    #[must_use]
+    pub fn with_scheme(mut self, scheme: impl Into<String>) -> Self {
+        self.discovery.scheme = scheme.into();
+        self
+    }
+
+    /// Set the path appended to discovered endpoints, e.g. `/v1/chat/completions`
+    #[must_use]
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.discovery.path = path.into();
+        self
+    }
+
+    /// Set how often instances are looked up again
+    #[must_use]
+    pub const fn with_refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    /// Set the HTTP client used for Consul lookups
+    #[must_use]
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.discovery.client = client;
+        self
+    }
+
+    /// Look up instances now, returning how many were found.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the lookup fails or finds no instances.
+    pub async fn refresh(&self) -> Result<usize> {
+        self.discovery.refresh().await
+    }
+
+    /// Look up instances every refresh interval in a background task.
+    ///
+    /// Must be called from within a Tokio runtime.
+    #[must_use]
+    pub fn spawn_refresh(mut self) -> Self {
+        if let Some(task) = self.refresh_task.take() {
+            task.abort();
+        }
+
+        let discovery = self.discovery.clone();
+        let interval = self.refresh_interval;
+        self.refresh_task = Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = discovery.refresh().await {
+                    warn!(error = %e, "Failed to refresh discovered endpoints");
+                }
+            }
+        }));
+        self
+    }
+}
+
+impl Drop for DiscoveryUrlProvider {
+    fn drop(&mut self) {
+        if let Some(task) = self.refresh_task.take() {
+            task.abort();
+        }
+    }
+}
+
+impl UrlProvider for DiscoveryUrlProvider {
+    fn get_url(&self) -> Result<String> {
+        let balancer = self
+            .discovery
+            .balancer
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        balancer
+            .get_url()
+            .map_err(|_| Error::LLMError("No upstream instance has been discovered".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consul_provider() -> DiscoveryUrlProvider {
+        DiscoveryUrlProvider::new(DiscoverySource::Consul {
+            address: "http://127.0.0.1:8500".to_string(),
+            service: "vllm".to_string(),
+            tag: None,
+            token: None,
+        })
+        .with_path("/v1/chat/completions")
+    }
+
+    #[test]
+    fn test_consul_endpoints() {
+        let entries: Vec<ConsulEntry> = serde_json::from_str(
+            r#"[
+                {"Node": {"Address": "10.0.0.1"}, "Service": {"Address": "", "Port": 8000}},
+                {"Node": {"Address": "10.0.0.2"}, "Service": {"Address": "10.1.0.2", "Port": 8001, "Weights": {"Passing": 3, "Warning": 1}}}
+            ]"#,
+        )
+        .expect("Failed to parse");
+
+        let provider = consul_provider();
+        let endpoints = provider.discovery.consul_endpoints(entries);
+        let urls: Vec<_> = endpoints.iter().map(|e| e.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "http://10.0.0.1:8000/v1/chat/completions",
+                "http://10.1.0.2:8001/v1/chat/completions",
+            ]
+        );
+        assert_eq!(endpoints[1].weight, 3);
+    }
+
+    #[test]
+    fn test_no_url_before_discovery() {
+        assert!(consul_provider().get_url().is_err());
+    }
+}
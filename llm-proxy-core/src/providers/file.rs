@@ -0,0 +1,162 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use async_trait::async_trait;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use tracing::{info, warn};
+
+use crate::{types::Result, Error, TokenProvider};
+
+/// Token provider that reads the API key from a file and reloads it when the file changes.
+///
+/// Intended for keys mounted from a secret store, such as Kubernetes secrets:
+/// the parent directory is watched, so keys rotated through atomic symlink
+/// swaps are picked up as well as keys rewritten in place. The file is also
+/// re-read when the upstream service rejects the current key. If the file
+/// becomes unreadable or empty, the last loaded key keeps being used.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use llm_proxy_core::providers::FileTokenProvider;
+///
+/// let provider = FileTokenProvider::new("/var/run/secrets/openai/api-key")?
+///     .watch()?;
+/// # Ok::<(), llm_proxy_core::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct FileTokenProvider {
+    path: PathBuf,
+    token: Arc<RwLock<String>>,
+    /// Kept alive so that the file stays watched
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl FileTokenProvider {
+    /// Create a provider reading the key from `path`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be read or is empty.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let token = read_token(&path)?;
+        Ok(Self {
+            path,
+            token: Arc::new(RwLock::new(token)),
+            _watcher: None,
+        })
+    }
+
+    /// Watch the file and reload the key whenever it changes
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be watched.
+    pub fn watch(self) -> Result<Self> {
+        let path = self.path.clone();
+        let token = self.token.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if event.is_ok() {
+                    reload(&path, &token);
+                }
+            })
+            .map_err(|e| Error::ConfigError(format!("Failed to watch key file: {e}")))?;
+
+        let directory = self
+            .path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        watcher
+            .watch(directory, RecursiveMode::NonRecursive)
+            .map_err(|e| Error::ConfigError(format!("Failed to watch key file: {e}")))?;
+
+        Ok(Self {
+            _watcher: Some(watcher),
+            ..self
+        })
+    }
+}
+
+/// Read and trim the key stored in `path`
+fn read_token(path: &Path) -> Result<String> {
+    let token = std::fs::read_to_string(path)?.trim().to_string();
+    if token.is_empty() {
+        return Err(Error::AuthenticationError(format!(
+            "Key file {} is empty",
+            path.display()
+        )));
+    }
+    Ok(token)
+}
+
+/// Replace the cached key with the contents of `path`, keeping the old key on failure
+fn reload(path: &Path, token: &RwLock<String>) {
+    match read_token(path) {
+        Ok(new_token) => {
+            let mut current = token.write().unwrap_or_else(PoisonError::into_inner);
+            if *current != new_token {
+                *current = new_token;
+                drop(current);
+                info!(path = %path.display(), "Reloaded API key from file");
+            }
+        }
+        Err(e) => warn!(path = %path.display(), error = %e, "Failed to reload API key file"),
+    }
+}
+
+#[async_trait]
+impl TokenProvider for FileTokenProvider {
+    async fn get_token(&self) -> Result<String> {
+        Ok(self
+            .token
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone())
+    }
+
+    async fn report_rejection(&self, _token: &str, status: u16) {
+        if matches!(status, 401 | 403) {
+            reload(&self.path, &self.token);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("llm-proxy-{name}-{}", std::process::id()));
+        std::fs::write(&path, contents).expect("Failed to write key file");
+        path
+    }
+
+    #[tokio::test]
+    async fn test_reads_trimmed_key() {
+        let path = key_file("read", "sk-test\n");
+        let provider = FileTokenProvider::new(&path).expect("Failed to create provider");
+        assert_eq!(provider.get_token().await.expect("No token"), "sk-test");
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_reloads_after_rejection() {
+        let path = key_file("rotate", "sk-old");
+        let provider = FileTokenProvider::new(&path).expect("Failed to create provider");
+
+        std::fs::write(&path, "sk-new").expect("Failed to rotate key");
+        provider.report_rejection("sk-old", 401).await;
+        assert_eq!(provider.get_token().await.expect("No token"), "sk-new");
+
+        // An emptied file keeps the last good key
+        std::fs::write(&path, "").expect("Failed to empty key file");
+        provider.report_rejection("sk-new", 401).await;
+        assert_eq!(provider.get_token().await.expect("No token"), "sk-new");
+        std::fs::remove_file(path).ok();
+    }
+}
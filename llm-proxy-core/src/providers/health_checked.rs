@@ -0,0 +1,406 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use super::{LoadBalancingUrlProvider, WeightedEndpoint};
+use crate::{types::Result, EndpointStatus, Error, TokenProvider, UrlProvider};
+
+/// Default path probed on each endpoint
+pub const DEFAULT_PROBE_PATH: &str = "/health";
+
+/// Notified when endpoints of a [`HealthCheckedUrlProvider`] change health
+pub trait HealthListener: Send + Sync {
+    /// The endpoint with `url` was marked unavailable after `failures`
+    /// consecutive failed probes, the last one failing with `error`
+    fn unhealthy(&self, url: &str, failures: u32, error: &Error);
+
+    /// The endpoint with `url` passes its health check again
+    fn healthy(&self, _url: &str) {}
+}
+
+/// Health of a single endpoint
+#[derive(Debug)]
+struct EndpointHealth {
+    url: String,
+    healthy: AtomicBool,
+    consecutive_failures: AtomicU32,
+}
+
+/// Everything the background probe task needs
+struct Prober {
+    client: reqwest::Client,
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    probe_path: String,
+    timeout: Duration,
+    failure_threshold: u32,
+    health: Arc<Vec<EndpointHealth>>,
+    listener: Option<Arc<dyn HealthListener>>,
+}
+
+impl Prober {
+    /// Probe every endpoint once and record the results
+    async fn probe_all(&self) {
+        for endpoint in self.health.iter() {
+            let result = self.probe(&endpoint.url).await;
+            record(
+                endpoint,
+                result,
+                self.failure_threshold,
+                self.listener.as_deref(),
+            );
+        }
+    }
+
+    /// Send a probe request to the endpoint serving `url`
+    async fn probe(&self, url: &str) -> Result<()> {
+        let probe_url = reqwest::Url::parse(url)
+            .and_then(|url| url.join(&self.probe_path))
+            .map_err(|e| Error::ConfigError(format!("Invalid endpoint URL {url}: {e}")))?;
+
+        let mut request = self.client.get(probe_url).timeout(self.timeout);
+        if let Some(token_provider) = &self.token_provider {
+            request = request.bearer_auth(token_provider.get_token().await?);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| Error::LLMError(format!("Health probe failed: {e}")))?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::LLMError(format!(
+                "Health probe returned {}",
+                response.status()
+            )))
+        }
+    }
+}
+
+/// Update an endpoint's health with a probe result, reporting transitions
+fn record(
+    endpoint: &EndpointHealth,
+    result: Result<()>,
+    failure_threshold: u32,
+    listener: Option<&dyn HealthListener>,
+) {
+    let url = endpoint.url.clone();
+    match result {
+        Ok(()) => {
+            endpoint.consecutive_failures.store(0, Ordering::Relaxed);
+            if !endpoint.healthy.swap(true, Ordering::AcqRel) {
+                info!(endpoint = %url, "Upstream endpoint is healthy again");
+                if let Some(listener) = listener {
+                    listener.healthy(&url);
+                }
+                metrics::counter!(
+                    "llm_proxy_endpoint_health_transitions_total",
+                    "endpoint" => url.clone(),
+                    "state" => "healthy"
+                )
+                .increment(1);
+            }
+            metrics::gauge!("llm_proxy_endpoint_healthy", "endpoint" => url).set(1.0);
+        }
+        Err(e) => {
+            let failures = endpoint
+                .consecutive_failures
+                .fetch_add(1, Ordering::Relaxed)
+                + 1;
+            if failures < failure_threshold {
+                return;
+            }
+            if endpoint.healthy.swap(false, Ordering::AcqRel) {
+                warn!(endpoint = %url, error = %e, failures, "Upstream endpoint is unhealthy");
+                if let Some(listener) = listener {
+                    listener.unhealthy(&url, failures, &e);
+                }
+                metrics::counter!(
+                    "llm_proxy_endpoint_health_transitions_total",
+                    "endpoint" => url.clone(),
+                    "state" => "unhealthy"
+                )
+                .increment(1);
+            }
+            metrics::gauge!("llm_proxy_endpoint_healthy", "endpoint" => url).set(0.0);
+        }
+    }
+}
+
+/// URL provider that only hands out endpoints passing an active health check.
+///
+/// Each endpoint is probed with a `GET` request on an interval, by default on
+/// [`DEFAULT_PROBE_PATH`] of the endpoint's host; use
+/// [`with_probe_path`](Self::with_probe_path) to probe e.g. `/v1/models`
+/// instead. An endpoint is marked unavailable after a number of consecutive
+/// failed probes and available again after the first successful one. Healthy
+/// endpoints are rotated with weighted round-robin, see
+/// [`LoadBalancingUrlProvider`].
+///
+/// Health transitions are logged and exported through the `metrics` crate as
+/// the `llm_proxy_endpoint_healthy` gauge and the
+/// `llm_proxy_endpoint_health_transitions_total` counter, labelled by endpoint,
+/// and reported to the [`HealthListener`] set with
+/// [`with_listener`](Self::with_listener).
+///
+/// Endpoints are assumed healthy until probed. Probing starts with
+/// [`spawn_probes`](Self::spawn_probes) and stops when the provider is dropped.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use llm_proxy_core::providers::{HealthCheckedUrlProvider, WeightedEndpoint};
+///
+/// # async fn example() {
+/// let provider = HealthCheckedUrlProvider::new(vec![
+///     WeightedEndpoint::new("http://vllm-0:8000/v1/chat/completions"),
+///     WeightedEndpoint::new("http://vllm-1:8000/v1/chat/completions"),
+/// ])
+/// .with_interval(Duration::from_secs(10))
+/// .spawn_probes();
+/// # }
+/// ```
+pub struct HealthCheckedUrlProvider {
+    balancer: LoadBalancingUrlProvider,
+    health: Arc<Vec<EndpointHealth>>,
+    client: reqwest::Client,
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    probe_path: String,
+    interval: Duration,
+    timeout: Duration,
+    failure_threshold: u32,
+    listener: Option<Arc<dyn HealthListener>>,
+    probe_task: Option<JoinHandle<()>>,
+}
+
+impl HealthCheckedUrlProvider {
+    /// Create a provider for the given endpoints, probed every 30 seconds
+    #[must_use]
+    pub fn new(endpoints: Vec<WeightedEndpoint>) -> Self {
+        let health = endpoints
+            .iter()
+            .map(|endpoint| EndpointHealth {
+                url: endpoint.url.clone(),
+                healthy: AtomicBool::new(true),
+                consecutive_failures: AtomicU32::new(0),
+            })
+            .collect();
+
+        Self {
+            balancer: LoadBalancingUrlProvider::new(endpoints),
+            health: Arc::new(health),
+            client: reqwest::Client::new(),
+            token_provider: None,
+            probe_path: DEFAULT_PROBE_PATH.to_string(),
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(5),
+            failure_threshold: 2,
+            listener: None,
+            probe_task: None,
+        }
+    }
+
+    /// Probe this path instead of [`DEFAULT_PROBE_PATH`]; relative paths are
+    /// resolved against the endpoint URL
+    #[must_use]
+    pub fn with_probe_path(mut self, path: impl Into<String>) -> Self {
+        self.probe_path = path.into();
+        self
+    }
+
+    /// Set how often endpoints are probed
+    #[must_use]
+    pub const fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set how long a probe may take before it counts as failed
+    #[must_use]
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how many consecutive probes must fail before an endpoint is marked unavailable
+    #[must_use]
+    pub const fn with_failure_threshold(mut self, failures: u32) -> Self {
+        self.failure_threshold = failures;
+        self
+    }
+
+    /// Authenticate probes with a bearer token, e.g. when probing `/v1/models`
+    #[must_use]
+    pub fn with_token_provider(mut self, token_provider: Arc<dyn TokenProvider>) -> Self {
+        self.token_provider = Some(token_provider);
+        self
+    }
+
+    /// Set the HTTP client used for probes
+    #[must_use]
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Report health transitions of endpoints to `listener`
+    #[must_use]
+    pub fn with_listener(mut self, listener: Arc<dyn HealthListener>) -> Self {
+        self.listener = Some(listener);
+        self
+    }
+
+    /// Start probing endpoints in a background task.
+    ///
+    /// Must be called from within a Tokio runtime.
+    #[must_use]
+    pub fn spawn_probes(mut self) -> Self {
+        if let Some(task) = self.probe_task.take() {
+            task.abort();
+        }
+
+        let prober = self.prober();
+        let interval = self.interval;
+        self.probe_task = Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                prober.probe_all().await;
+            }
+        }));
+        self
+    }
+
+    /// Probe every endpoint once, without waiting for the next interval
+    pub async fn check_now(&self) {
+        self.prober().probe_all().await;
+    }
+
+    /// Whether the endpoint with `url` currently passes its health check
+    #[must_use]
+    pub fn is_healthy(&self, url: &str) -> bool {
+        self.health
+            .iter()
+            .any(|endpoint| endpoint.url == url && endpoint.healthy.load(Ordering::Acquire))
+    }
+
+    fn prober(&self) -> Prober {
+        Prober {
+            client: self.client.clone(),
+            token_provider: self.token_provider.clone(),
+            probe_path: self.probe_path.clone(),
+            timeout: self.timeout,
+            failure_threshold: self.failure_threshold,
+            health: self.health.clone(),
+            listener: self.listener.clone(),
+        }
+    }
+}
+
+impl Drop for HealthCheckedUrlProvider {
+    fn drop(&mut self) {
+        if let Some(task) = self.probe_task.take() {
+            task.abort();
+        }
+    }
+}
+
+impl UrlProvider for HealthCheckedUrlProvider {
+    fn get_url(&self) -> Result<String> {
+        self.balancer
+            .next_endpoint(|index| self.health[index].healthy.load(Ordering::Acquire))
+            .map(|index| self.balancer.endpoints()[index].url.clone())
+            .ok_or_else(|| Error::LLMError("No healthy upstream endpoint available".to_string()))
+    }
+
+    fn endpoint_status(&self) -> Vec<EndpointStatus> {
+        self.health
+            .iter()
+            .map(|endpoint| EndpointStatus {
+                url: endpoint.url.clone(),
+                healthy: endpoint.healthy.load(Ordering::Acquire),
+                consecutive_failures: endpoint.consecutive_failures.load(Ordering::Acquire),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unhealthy_after_threshold() {
+        let provider = HealthCheckedUrlProvider::new(vec![
+            WeightedEndpoint::new("http://a/v1/chat/completions"),
+            WeightedEndpoint::new("http://b/v1/chat/completions"),
+        ]);
+        let failure = || Err(Error::LLMError("down".to_string()));
+
+        record(&provider.health[0], failure(), 2, None);
+        assert!(provider.is_healthy("http://a/v1/chat/completions"));
+        record(&provider.health[0], failure(), 2, None);
+        assert!(!provider.is_healthy("http://a/v1/chat/completions"));
+
+        for _ in 0..3 {
+            assert_eq!(
+                provider.get_url().expect("No URL"),
+                "http://b/v1/chat/completions"
+            );
+        }
+
+        record(&provider.health[0], Ok(()), 2, None);
+        assert!(provider.is_healthy("http://a/v1/chat/completions"));
+    }
+
+    #[test]
+    fn test_listener_notified_of_transitions() {
+        #[derive(Default)]
+        struct Transitions(std::sync::Mutex<Vec<String>>);
+
+        impl HealthListener for Transitions {
+            fn unhealthy(&self, url: &str, failures: u32, _error: &Error) {
+                let transition = format!("{url} unhealthy after {failures}");
+                self.0.lock().expect("Poisoned").push(transition);
+            }
+
+            fn healthy(&self, url: &str) {
+                self.0
+                    .lock()
+                    .expect("Poisoned")
+                    .push(format!("{url} healthy"));
+            }
+        }
+
+        let provider = HealthCheckedUrlProvider::new(vec![WeightedEndpoint::new("http://a/v1")]);
+        let transitions = Transitions::default();
+        for _ in 0..3 {
+            let failure = Err(Error::LLMError("down".to_string()));
+            record(&provider.health[0], failure, 2, Some(&transitions));
+        }
+        record(&provider.health[0], Ok(()), 2, Some(&transitions));
+        record(&provider.health[0], Ok(()), 2, Some(&transitions));
+
+        assert_eq!(
+            *transitions.0.lock().expect("Poisoned"),
+            ["http://a/v1 unhealthy after 2", "http://a/v1 healthy"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_endpoint_is_removed() {
+        let provider =
+            HealthCheckedUrlProvider::new(vec![WeightedEndpoint::new("http://127.0.0.1:1/v1")])
+                .with_failure_threshold(1);
+        provider.check_now().await;
+        assert!(provider.get_url().is_err());
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use async_trait::async_trait;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use super::ClientSettings;
+use crate::{types::Result, ClientProvider, Error};
+
+/// Resolver that times DNS lookups done through the system resolver
+#[derive(Debug)]
+struct TimedResolver;
+
+impl Resolve for TimedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let started = Instant::now();
+            let addrs = tokio::net::lookup_host(format!("{host}:0")).await?;
+            metrics::histogram!("llm_proxy_upstream_dns_seconds", "host" => host)
+                .record(started.elapsed());
+
+            let addrs: Addrs = Box::new(addrs);
+            Ok(addrs)
+        })
+    }
+}
+
+/// Connector layer that times the establishment of new connections
+#[derive(Debug, Clone)]
+struct ConnectMetricsLayer {
+    upstream: Arc<str>,
+}
+
+impl<S> Layer<S> for ConnectMetricsLayer {
+    type Service = ConnectMetrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConnectMetrics {
+            inner,
+            upstream: self.upstream.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ConnectMetrics<S> {
+    inner: S,
+    upstream: Arc<str>,
+}
+
+impl<S, R> Service<R> for ConnectMetrics<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = std::result::Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: R) -> Self::Future {
+        let upstream = self.upstream.to_string();
+        let started = Instant::now();
+        let connecting = self.inner.call(request);
+        Box::pin(async move {
+            let result = connecting.await;
+            let outcome = if result.is_ok() { "ok" } else { "error" };
+            metrics::histogram!("llm_proxy_upstream_connect_seconds", "upstream" => upstream.clone())
+                .record(started.elapsed());
+            metrics::counter!(
+                "llm_proxy_upstream_connections_total",
+                "upstream" => upstream,
+                "outcome" => outcome
+            )
+            .increment(1);
+            result
+        })
+    }
+}
+
+/// Client provider that records connection-level metrics for an upstream.
+///
+/// Builds its client from [`ClientSettings`] and instruments how connections
+/// to the upstream are established, to tell slow DNS, slow handshakes and
+/// poor connection reuse apart from a slow model. Recorded through the
+/// `metrics` facade:
+///
+/// - `llm_proxy_upstream_dns_seconds{host}`: duration of DNS lookups
+/// - `llm_proxy_upstream_connect_seconds{upstream}`: time to open a new
+///   connection, including DNS, TCP and TLS handshakes
+/// - `llm_proxy_upstream_connections_total{upstream, outcome}`: new
+///   connections, successful or not
+/// - `llm_proxy_upstream_requests_total{upstream}`: clients handed out;
+///   clients fetch one per request, so the share of requests that reused a
+///   pooled connection is `1 - connections / requests`
+///
+/// The system resolver is used for DNS lookups.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::providers::{ClientSettings, InstrumentedClientProvider};
+///
+/// # fn example() -> llm_proxy_core::Result<()> {
+/// let provider = InstrumentedClientProvider::new(&ClientSettings::new(), "api.openai.com")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct InstrumentedClientProvider {
+    client: reqwest::Client,
+    upstream: String,
+}
+
+impl InstrumentedClientProvider {
+    /// Create a provider whose client uses `settings`, labelling metrics with `upstream`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the client cannot be built, see
+    /// [`ClientSettings::build_client`].
+    pub fn new(settings: &ClientSettings, upstream: impl Into<String>) -> Result<Self> {
+        let upstream = upstream.into();
+        let client = settings
+            .client_builder()?
+            .dns_resolver(Arc::new(TimedResolver))
+            .connector_layer(ConnectMetricsLayer {
+                upstream: upstream.as_str().into(),
+            })
+            .build()
+            .map_err(|e| Error::ConfigError(format!("Failed to create HTTP client: {e}")))?;
+        Ok(Self { client, upstream })
+    }
+}
+
+#[async_trait]
+impl ClientProvider for InstrumentedClientProvider {
+    async fn get_client(&self) -> Result<reqwest::Client> {
+        metrics::counter!("llm_proxy_upstream_requests_total", "upstream" => self.upstream.clone())
+            .increment(1);
+        Ok(self.client.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolves_through_system_resolver() {
+        let name: Name = "localhost".parse().expect("Invalid name");
+        let addrs: Vec<_> = TimedResolver
+            .resolve(name)
+            .await
+            .expect("Failed to resolve")
+            .collect();
+        assert!(addrs.iter().all(|addr| addr.ip().is_loopback()));
+        assert!(!addrs.is_empty());
+    }
+
+    #[test]
+    fn test_builds_with_settings() {
+        let settings = ClientSettings::new().with_user_agent("test-agent");
+        assert!(InstrumentedClientProvider::new(&settings, "localhost").is_ok());
+    }
+}
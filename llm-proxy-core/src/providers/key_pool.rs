@@ -0,0 +1,315 @@
+use std::{
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use tracing::{debug, warn};
+
+use crate::{redact::SecretString, types::Result, Error, KeyStatus, TokenProvider};
+
+/// Length of the window used for per-key request rate limits
+const RATE_WINDOW: Duration = Duration::from_mins(1);
+
+/// Default quarantine for keys rejected with 401/403
+pub const DEFAULT_UNAUTHORIZED_QUARANTINE: Duration = Duration::from_mins(10);
+
+/// Default quarantine for keys rejected with 429
+pub const DEFAULT_RATE_LIMITED_QUARANTINE: Duration = Duration::from_mins(1);
+
+/// A single API key in a [`KeyPoolTokenProvider`]
+#[derive(Debug, Clone)]
+pub struct PooledKey {
+    /// The API key
+    pub token: SecretString,
+    /// Relative share of requests this key receives
+    pub weight: u32,
+    /// Maximum number of requests per minute sent with this key
+    pub max_requests_per_minute: Option<u32>,
+}
+
+impl PooledKey {
+    /// Create a key with weight 1 and no rate limit
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: SecretString::new(token),
+            weight: 1,
+            max_requests_per_minute: None,
+        }
+    }
+
+    /// Set the relative share of requests this key receives
+    #[must_use]
+    pub const fn with_weight(mut self, weight: u32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Limit the number of requests per minute sent with this key
+    #[must_use]
+    pub const fn with_max_requests_per_minute(mut self, limit: u32) -> Self {
+        self.max_requests_per_minute = Some(limit);
+        self
+    }
+}
+
+/// Rotation and health state of a pooled key
+#[derive(Debug)]
+struct KeyState {
+    current_weight: i64,
+    quarantined_until: Option<Instant>,
+    window_start: Instant,
+    window_requests: u32,
+}
+
+/// Token provider that rotates requests across a pool of weighted API keys.
+///
+/// Keys are selected per request with smooth weighted round-robin. Keys that
+/// the upstream service rejects (reported through
+/// [`TokenProvider::report_rejection`]) are quarantined for a while: keys
+/// rejected with 401/403 are assumed revoked, keys rejected with 429 are
+/// assumed rate limited. Keys with a per-minute request limit are skipped
+/// once the limit is reached.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::providers::{KeyPoolTokenProvider, PooledKey};
+///
+/// let provider = KeyPoolTokenProvider::new(vec![
+///     PooledKey::new("sk-primary").with_weight(3),
+///     PooledKey::new("sk-secondary").with_max_requests_per_minute(60),
+/// ]);
+/// ```
+#[derive(Debug)]
+pub struct KeyPoolTokenProvider {
+    keys: Vec<PooledKey>,
+    state: Mutex<Vec<KeyState>>,
+    unauthorized_quarantine: Duration,
+    rate_limited_quarantine: Duration,
+}
+
+impl KeyPoolTokenProvider {
+    /// Create a pool from the given keys
+    #[must_use]
+    pub fn new(keys: Vec<PooledKey>) -> Self {
+        let now = Instant::now();
+        let state = keys
+            .iter()
+            .map(|_| KeyState {
+                current_weight: 0,
+                quarantined_until: None,
+                window_start: now,
+                window_requests: 0,
+            })
+            .collect();
+
+        Self {
+            keys,
+            state: Mutex::new(state),
+            unauthorized_quarantine: DEFAULT_UNAUTHORIZED_QUARANTINE,
+            rate_limited_quarantine: DEFAULT_RATE_LIMITED_QUARANTINE,
+        }
+    }
+
+    /// Load a pool from a file containing one key per line.
+    ///
+    /// See [`load_keys`] for the file format.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file cannot be read or a weight is invalid.
+    pub fn from_file(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        Ok(Self::new(load_keys(path)?))
+    }
+
+    /// Set how long keys stay quarantined after a 401/403 and after a 429
+    #[must_use]
+    pub const fn with_quarantine(mut self, unauthorized: Duration, rate_limited: Duration) -> Self {
+        self.unauthorized_quarantine = unauthorized;
+        self.rate_limited_quarantine = rate_limited;
+        self
+    }
+
+    /// Pick the next available key, updating rotation and rate state
+    fn next_key(&self, now: Instant) -> Option<usize> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut total_weight = 0;
+        let mut selected: Option<usize> = None;
+        for (index, key) in self.keys.iter().enumerate() {
+            let key_state = &mut state[index];
+            if key_state.quarantined_until.is_some_and(|until| until > now) {
+                continue;
+            }
+            key_state.quarantined_until = None;
+
+            if now.duration_since(key_state.window_start) >= RATE_WINDOW {
+                key_state.window_start = now;
+                key_state.window_requests = 0;
+            }
+            if key
+                .max_requests_per_minute
+                .is_some_and(|limit| key_state.window_requests >= limit)
+            {
+                continue;
+            }
+
+            key_state.current_weight += i64::from(key.weight);
+            total_weight += i64::from(key.weight);
+            if selected.is_none_or(|best| state[index].current_weight > state[best].current_weight)
+            {
+                selected = Some(index);
+            }
+        }
+
+        let index = selected?;
+        state[index].current_weight -= total_weight;
+        state[index].window_requests += 1;
+        drop(state);
+        Some(index)
+    }
+}
+
+/// Read pooled keys from a file containing one key per line.
+///
+/// Each line has the form `<key> [weight]`; empty lines and lines starting
+/// with `#` are ignored.
+///
+/// # Errors
+///
+/// This function will return an error if the file cannot be read or a weight is invalid.
+pub fn load_keys(path: impl AsRef<std::path::Path>) -> Result<Vec<PooledKey>> {
+    let contents = std::fs::read_to_string(path)?;
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let token = fields.next().unwrap_or_default();
+            let weight = fields
+                .next()
+                .map_or(Ok(1), str::parse)
+                .map_err(|e| Error::ConfigError(format!("Invalid key weight in key file: {e}")))?;
+            Ok(PooledKey::new(token).with_weight(weight))
+        })
+        .collect()
+}
+
+#[async_trait]
+impl TokenProvider for KeyPoolTokenProvider {
+    async fn get_token(&self) -> Result<String> {
+        let index = self.next_key(Instant::now()).ok_or_else(|| {
+            Error::AuthenticationError("All keys in the key pool are unavailable".to_string())
+        })?;
+        debug!(key_index = index, "Selected key from pool");
+        Ok(self.keys[index].token.expose().to_string())
+    }
+
+    async fn report_rejection(&self, token: &str, status: u16) {
+        let quarantine = match status {
+            401 | 403 => self.unauthorized_quarantine,
+            429 => self.rate_limited_quarantine,
+            _ => return,
+        };
+        let Some(index) = self.keys.iter().position(|key| key.token.expose() == token) else {
+            return;
+        };
+
+        warn!(
+            key_index = index,
+            status,
+            quarantine_secs = quarantine.as_secs(),
+            "Quarantining pooled key after upstream rejection"
+        );
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state[index].quarantined_until = Some(Instant::now() + quarantine);
+    }
+
+    fn key_status(&self) -> Vec<KeyStatus> {
+        let now = Instant::now();
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        self.keys
+            .iter()
+            .zip(state.iter())
+            .enumerate()
+            .map(|(index, (key, key_state))| KeyStatus {
+                index,
+                weight: key.weight,
+                max_requests_per_minute: key.max_requests_per_minute,
+                requests_this_minute: if now.duration_since(key_state.window_start) < RATE_WINDOW {
+                    key_state.window_requests
+                } else {
+                    0
+                },
+                quarantined_secs: key_state
+                    .quarantined_until
+                    .filter(|until| *until > now)
+                    .map(|until| until.duration_since(now).as_secs()),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool() -> KeyPoolTokenProvider {
+        KeyPoolTokenProvider::new(vec![
+            PooledKey::new("key-a").with_weight(3),
+            PooledKey::new("key-b"),
+        ])
+    }
+
+    #[tokio::test]
+    async fn test_weighted_rotation() {
+        let provider = pool();
+        let mut picks = Vec::new();
+        for _ in 0..8 {
+            picks.push(provider.get_token().await.expect("Failed to get token"));
+        }
+        assert_eq!(picks.iter().filter(|token| *token == "key-a").count(), 6);
+        assert_eq!(picks.iter().filter(|token| *token == "key-b").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_key_is_quarantined() {
+        let provider = pool();
+        provider.report_rejection("key-a", 429).await;
+        for _ in 0..4 {
+            assert_eq!(provider.get_token().await.expect("No token"), "key-b");
+        }
+
+        provider.report_rejection("key-b", 401).await;
+        assert!(matches!(
+            provider.get_token().await,
+            Err(Error::AuthenticationError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_key_is_skipped() {
+        let provider = KeyPoolTokenProvider::new(vec![
+            PooledKey::new("key-a").with_max_requests_per_minute(1),
+            PooledKey::new("key-b").with_max_requests_per_minute(1),
+        ]);
+        assert!(provider.get_token().await.is_ok());
+        assert!(provider.get_token().await.is_ok());
+        assert!(provider.get_token().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_key_status() {
+        let provider = pool();
+        provider.get_token().await.expect("No token");
+        provider.report_rejection("key-b", 429).await;
+
+        let status = provider.key_status();
+        assert_eq!(status[0].weight, 3);
+        assert_eq!(status[0].requests_this_minute, 1);
+        assert_eq!(status[0].quarantined_secs, None);
+        assert!(status[1].quarantined_secs.is_some());
+    }
+}
@@ -0,0 +1,115 @@
+use async_trait::async_trait;
+use tokio::sync::Mutex;
+
+use crate::redact::SecretString;
+use crate::{types::Result, Error, TokenProvider};
+
+/// Token provider that reads the API key from the OS keychain.
+///
+/// Uses the macOS Keychain, the Windows Credential Manager, or the Secret
+/// Service (e.g. GNOME Keyring, `KWallet`) with a fallback to the kernel
+/// keyring on Linux. Meant for developers running the proxy locally, so API
+/// keys don't have to be kept in environment variables or config files. The
+/// key is read on first use and cached until the upstream service rejects it.
+///
+/// Store a key with the platform tools, e.g. on macOS:
+///
+/// ```text
+/// security add-generic-password -s llm-proxy -a openai -w sk-...
+/// ```
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::providers::KeyringTokenProvider;
+///
+/// let provider = KeyringTokenProvider::new("llm-proxy", "openai");
+/// ```
+#[derive(Debug)]
+pub struct KeyringTokenProvider {
+    service: String,
+    user: String,
+    cached: Mutex<Option<SecretString>>,
+}
+
+impl KeyringTokenProvider {
+    /// Read the password stored for `user` under `service`
+    pub fn new(service: impl Into<String>, user: impl Into<String>) -> Self {
+        Self {
+            service: service.into(),
+            user: user.into(),
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Read the key from the keychain; keychain access may block, e.g. on an unlock prompt
+    async fn read(&self) -> Result<String> {
+        let service = self.service.clone();
+        let user = self.user.clone();
+        let password = tokio::task::spawn_blocking(move || {
+            keyring::Entry::new(&service, &user)?.get_password()
+        })
+        .await
+        .map_err(|e| Error::Other(e.into()))?
+        .map_err(|e| {
+            Error::AuthenticationError(format!(
+                "Failed to read keychain entry {}/{}: {e}",
+                self.service, self.user
+            ))
+        })?;
+
+        let token = password.trim().to_string();
+        if token.is_empty() {
+            return Err(Error::AuthenticationError(format!(
+                "Keychain entry {}/{} is empty",
+                self.service, self.user
+            )));
+        }
+        Ok(token)
+    }
+}
+
+#[async_trait]
+impl TokenProvider for KeyringTokenProvider {
+    async fn get_token(&self) -> Result<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref() {
+            return Ok(token.expose().to_string());
+        }
+
+        let token = self.read().await?;
+        *cached = Some(SecretString::new(token.clone()));
+        drop(cached);
+        Ok(token)
+    }
+
+    async fn report_rejection(&self, token: &str, status: u16) {
+        if status != 401 && status != 403 {
+            return;
+        }
+        // The key may have been rotated in the keychain; read it again on the next request
+        let mut cached = self.cached.lock().await;
+        if cached
+            .as_ref()
+            .is_some_and(|cached| cached.expose() == token)
+        {
+            *cached = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_missing_entry() {
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+
+        let provider = KeyringTokenProvider::new("llm-proxy-test", "missing");
+        let Err(e) = provider.get_token().await else {
+            panic!("Expected a missing keychain entry to fail");
+        };
+        assert!(e.to_string().contains("llm-proxy-test/missing"));
+    }
+}
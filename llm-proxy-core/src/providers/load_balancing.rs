@@ -0,0 +1,131 @@
+use std::sync::{Mutex, PoisonError};
+
+use crate::{types::Result, Error, UrlProvider};
+
+/// A single upstream endpoint in a [`LoadBalancingUrlProvider`]
+#[derive(Debug, Clone)]
+pub struct WeightedEndpoint {
+    /// Full URL of the endpoint
+    pub url: String,
+    /// Relative share of requests this endpoint receives
+    pub weight: u32,
+}
+
+impl WeightedEndpoint {
+    /// Create an endpoint with weight 1
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            weight: 1,
+        }
+    }
+
+    /// Set the relative share of requests this endpoint receives
+    #[must_use]
+    pub const fn with_weight(mut self, weight: u32) -> Self {
+        self.weight = weight;
+        self
+    }
+}
+
+/// URL provider that spreads requests across several upstream endpoints.
+///
+/// Endpoints are selected per request with smooth weighted round-robin, so
+/// an endpoint with weight 3 receives three requests for every request sent
+/// to an endpoint with weight 1, interleaved rather than in bursts. This is
+/// useful for self-hosted clusters (e.g. several vLLM or TGI replicas) that
+/// are not behind a load balancer of their own. Endpoints with weight 0
+/// receive no requests.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::providers::{LoadBalancingUrlProvider, WeightedEndpoint};
+///
+/// let provider = LoadBalancingUrlProvider::new(vec![
+///     WeightedEndpoint::new("http://vllm-0:8000/v1/chat/completions").with_weight(2),
+///     WeightedEndpoint::new("http://vllm-1:8000/v1/chat/completions"),
+/// ]);
+/// ```
+#[derive(Debug)]
+pub struct LoadBalancingUrlProvider {
+    endpoints: Vec<WeightedEndpoint>,
+    current_weights: Mutex<Vec<i64>>,
+}
+
+impl LoadBalancingUrlProvider {
+    /// Create a provider rotating across the given endpoints
+    #[must_use]
+    pub fn new(endpoints: Vec<WeightedEndpoint>) -> Self {
+        let current_weights = Mutex::new(vec![0; endpoints.len()]);
+        Self {
+            endpoints,
+            current_weights,
+        }
+    }
+
+    /// The endpoints requests are spread across
+    #[must_use]
+    pub fn endpoints(&self) -> &[WeightedEndpoint] {
+        &self.endpoints
+    }
+
+    /// Pick the next endpoint for which `available` returns true, updating the rotation state
+    pub(crate) fn next_endpoint(&self, available: impl Fn(usize) -> bool) -> Option<usize> {
+        let mut current = self
+            .current_weights
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let mut total_weight = 0;
+        let mut selected: Option<usize> = None;
+        for (index, endpoint) in self.endpoints.iter().enumerate() {
+            if endpoint.weight == 0 || !available(index) {
+                continue;
+            }
+            current[index] += i64::from(endpoint.weight);
+            total_weight += i64::from(endpoint.weight);
+            if selected.is_none_or(|best| current[index] > current[best]) {
+                selected = Some(index);
+            }
+        }
+
+        let index = selected?;
+        current[index] -= total_weight;
+        drop(current);
+        Some(index)
+    }
+}
+
+impl UrlProvider for LoadBalancingUrlProvider {
+    fn get_url(&self) -> Result<String> {
+        self.next_endpoint(|_| true)
+            .map(|index| self.endpoints[index].url.clone())
+            .ok_or_else(|| Error::ConfigError("No upstream endpoint is configured".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_rotation() {
+        let provider = LoadBalancingUrlProvider::new(vec![
+            WeightedEndpoint::new("a").with_weight(2),
+            WeightedEndpoint::new("b"),
+            WeightedEndpoint::new("c").with_weight(0),
+        ]);
+
+        let urls: Vec<_> = (0..6)
+            .map(|_| provider.get_url().expect("No URL"))
+            .collect();
+        assert_eq!(urls, ["a", "b", "a", "a", "b", "a"]);
+    }
+
+    #[test]
+    fn test_no_endpoints() {
+        let provider = LoadBalancingUrlProvider::new(vec![]);
+        assert!(provider.get_url().is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+
+use crate::{types::Result, ClientProvider, Error};
+
+/// Client certificate and trusted CAs used for TLS connections
+#[derive(Debug, Clone, Default)]
+pub struct TlsSettings {
+    /// PEM file with the client certificate (chain)
+    pub client_cert: Option<PathBuf>,
+    /// PEM file with the PKCS#8 private key of the client certificate
+    pub client_key: Option<PathBuf>,
+    /// PEM file with additional CA certificates to trust
+    pub ca_bundle: Option<PathBuf>,
+}
+
+impl TlsSettings {
+    /// Create settings that change nothing
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Authenticate with a client certificate and its private key
+    #[must_use]
+    pub fn with_client_identity(
+        mut self,
+        cert: impl Into<PathBuf>,
+        key: impl Into<PathBuf>,
+    ) -> Self {
+        self.client_cert = Some(cert.into());
+        self.client_key = Some(key.into());
+        self
+    }
+
+    /// Trust the CA certificates in `path` in addition to the system roots
+    #[must_use]
+    pub fn with_ca_bundle(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_bundle = Some(path.into());
+        self
+    }
+
+    /// Configure a client builder with these settings
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a file cannot be read or doesn't contain
+    /// valid PEM data, or if only one of the client certificate and key is set.
+    pub fn apply(&self, mut builder: reqwest::ClientBuilder) -> Result<reqwest::ClientBuilder> {
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => {
+                let identity = reqwest::Identity::from_pkcs8_pem(&read(cert)?, &read(key)?)
+                    .map_err(|e| {
+                        Error::ConfigError(format!(
+                            "Invalid client certificate {}: {e}",
+                            cert.display()
+                        ))
+                    })?;
+                builder = builder.identity(identity);
+            }
+            (None, None) => {}
+            _ => {
+                return Err(Error::ConfigError(
+                    "A client certificate requires both a certificate and a key".to_string(),
+                ))
+            }
+        }
+
+        if let Some(path) = &self.ca_bundle {
+            let certificates =
+                reqwest::Certificate::from_pem_bundle(&read(path)?).map_err(|e| {
+                    Error::ConfigError(format!("Invalid CA bundle {}: {e}", path.display()))
+                })?;
+            if certificates.is_empty() {
+                return Err(Error::ConfigError(format!(
+                    "CA bundle {} contains no certificates",
+                    path.display()
+                )));
+            }
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        Ok(builder)
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path)
+        .map_err(|e| Error::ConfigError(format!("Failed to read {}: {e}", path.display())))
+}
+
+/// Client provider for backends that require mutual TLS.
+///
+/// The client presents a certificate loaded from PEM files and can trust a
+/// custom CA bundle, e.g. for internal inference gateways with a private PKI.
+/// The private key must be in PKCS#8 format (`BEGIN PRIVATE KEY`).
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use llm_proxy_core::providers::{MtlsClientProvider, TlsSettings};
+///
+/// # fn example() -> llm_proxy_core::Result<()> {
+/// let provider = MtlsClientProvider::new(
+///     &TlsSettings::new()
+///         .with_client_identity("/etc/llm-proxy/client.pem", "/etc/llm-proxy/client-key.pem")
+///         .with_ca_bundle("/etc/llm-proxy/ca.pem"),
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct MtlsClientProvider {
+    client: reqwest::Client,
+}
+
+impl MtlsClientProvider {
+    /// Create a provider whose client uses the given TLS settings
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the certificates cannot be loaded
+    /// or the client cannot be built.
+    pub fn new(settings: &TlsSettings) -> Result<Self> {
+        let client = settings
+            .apply(reqwest::Client::builder())?
+            .build()
+            .map_err(|e| Error::ConfigError(format!("Failed to create HTTP client: {e}")))?;
+        Ok(Self { client })
+    }
+}
+
+#[async_trait]
+impl ClientProvider for MtlsClientProvider {
+    async fn get_client(&self) -> Result<reqwest::Client> {
+        Ok(self.client.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_file_is_reported() {
+        let settings = TlsSettings::new().with_ca_bundle("/nonexistent/ca.pem");
+        let Err(e) = MtlsClientProvider::new(&settings) else {
+            panic!("Expected a missing CA bundle to be rejected");
+        };
+        assert!(e.to_string().contains("/nonexistent/ca.pem"));
+    }
+
+    #[test]
+    fn test_certificate_requires_key() {
+        let settings = TlsSettings {
+            client_cert: Some("client.pem".into()),
+            ..TlsSettings::default()
+        };
+        assert!(MtlsClientProvider::new(&settings).is_err());
+    }
+}
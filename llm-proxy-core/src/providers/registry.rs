@@ -0,0 +1,189 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+
+use crate::{types::Result, Error, KeyStatus, RequestContext, TokenProvider};
+
+/// Token provider that resolves a different token provider per request context.
+///
+/// Providers are registered by tenant, model or route. For each request the
+/// most specific match wins, in that order, falling back to the default
+/// provider when nothing matches. This lets a single pipeline use separate
+/// upstream keys, e.g. a dedicated key for an expensive model or per-tenant
+/// keys for billing.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+/// # use async_trait::async_trait;
+/// # use llm_proxy_core::{Result, TokenProvider};
+/// use llm_proxy_core::{providers::TokenRegistry, RequestContext};
+///
+/// # struct Fixed(&'static str);
+/// # #[async_trait]
+/// # impl TokenProvider for Fixed {
+/// #     async fn get_token(&self) -> Result<String> { Ok(self.0.to_string()) }
+/// # }
+/// # async fn example() -> Result<()> {
+/// let registry = TokenRegistry::new()
+///     .with_default(Arc::new(Fixed("sk-default")))
+///     .with_model("gpt-4o", Arc::new(Fixed("sk-gpt-4o")));
+///
+/// let context = RequestContext::new().with_model("gpt-4o");
+/// assert_eq!(registry.get_token_for(&context).await?, "sk-gpt-4o");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct TokenRegistry {
+    default: Option<Arc<dyn TokenProvider>>,
+    by_tenant: HashMap<String, Arc<dyn TokenProvider>>,
+    by_model: HashMap<String, Arc<dyn TokenProvider>>,
+    by_route: HashMap<String, Arc<dyn TokenProvider>>,
+}
+
+impl TokenRegistry {
+    /// Create an empty registry
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the provider used when no registered key matches
+    #[must_use]
+    pub fn with_default(mut self, provider: Arc<dyn TokenProvider>) -> Self {
+        self.default = Some(provider);
+        self
+    }
+
+    /// Use `provider` for requests of `tenant`
+    #[must_use]
+    pub fn with_tenant(
+        mut self,
+        tenant: impl Into<String>,
+        provider: Arc<dyn TokenProvider>,
+    ) -> Self {
+        self.by_tenant.insert(tenant.into(), provider);
+        self
+    }
+
+    /// Use `provider` for requests for `model`
+    #[must_use]
+    pub fn with_model(
+        mut self,
+        model: impl Into<String>,
+        provider: Arc<dyn TokenProvider>,
+    ) -> Self {
+        self.by_model.insert(model.into(), provider);
+        self
+    }
+
+    /// Use `provider` for requests received on `route`
+    #[must_use]
+    pub fn with_route(
+        mut self,
+        route: impl Into<String>,
+        provider: Arc<dyn TokenProvider>,
+    ) -> Self {
+        self.by_route.insert(route.into(), provider);
+        self
+    }
+
+    /// Find the provider for a request context
+    fn resolve(&self, context: &RequestContext) -> Result<&Arc<dyn TokenProvider>> {
+        lookup(&self.by_tenant, context.tenant.as_ref())
+            .or_else(|| lookup(&self.by_model, context.model.as_ref()))
+            .or_else(|| lookup(&self.by_route, context.route.as_ref()))
+            .or(self.default.as_ref())
+            .ok_or_else(|| {
+                Error::AuthenticationError("No token provider matches the request".to_string())
+            })
+    }
+
+    /// All registered providers
+    fn providers(&self) -> impl Iterator<Item = &Arc<dyn TokenProvider>> {
+        self.default
+            .iter()
+            .chain(self.by_tenant.values())
+            .chain(self.by_model.values())
+            .chain(self.by_route.values())
+    }
+}
+
+/// Look up the provider registered for `key`, if any
+fn lookup<'a>(
+    map: &'a HashMap<String, Arc<dyn TokenProvider>>,
+    key: Option<&String>,
+) -> Option<&'a Arc<dyn TokenProvider>> {
+    key.and_then(|key| map.get(key))
+}
+
+#[async_trait]
+impl TokenProvider for TokenRegistry {
+    async fn get_token(&self) -> Result<String> {
+        self.get_token_for(&RequestContext::new()).await
+    }
+
+    async fn get_token_for(&self, context: &RequestContext) -> Result<String> {
+        self.resolve(context)?.get_token_for(context).await
+    }
+
+    async fn report_rejection(&self, token: &str, status: u16) {
+        for provider in self.providers() {
+            provider.report_rejection(token, status).await;
+        }
+    }
+
+    fn key_status(&self) -> Vec<KeyStatus> {
+        self.providers()
+            .flat_map(|provider| provider.key_status())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedProvider(&'static str);
+
+    #[async_trait]
+    impl TokenProvider for FixedProvider {
+        async fn get_token(&self) -> Result<String> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_most_specific_match_wins() {
+        let registry = TokenRegistry::new()
+            .with_default(Arc::new(FixedProvider("default")))
+            .with_model("gpt-4o", Arc::new(FixedProvider("model")))
+            .with_tenant("acme", Arc::new(FixedProvider("tenant")));
+
+        let model = RequestContext::new().with_model("gpt-4o");
+        let tenant = model.clone().with_tenant("acme");
+        let other = RequestContext::new().with_model("gpt-4o-mini");
+
+        assert_eq!(
+            registry.get_token_for(&tenant).await.expect("No token"),
+            "tenant"
+        );
+        assert_eq!(
+            registry.get_token_for(&model).await.expect("No token"),
+            "model"
+        );
+        assert_eq!(
+            registry.get_token_for(&other).await.expect("No token"),
+            "default"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_match_without_default() {
+        let registry =
+            TokenRegistry::new().with_route("/v1/embeddings", Arc::new(FixedProvider("route")));
+        assert!(registry.get_token().await.is_err());
+    }
+}
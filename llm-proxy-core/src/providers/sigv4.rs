@@ -0,0 +1,188 @@
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use aws_credential_types::{provider::ProvideCredentials, Credentials};
+use aws_sigv4::{
+    http_request::{sign, SignableBody, SignableRequest, SigningSettings},
+    sign::v4,
+};
+use aws_smithy_runtime_api::client::identity::Identity;
+use reqwest::header::{HeaderName, HeaderValue};
+use tokio::sync::Mutex;
+
+use crate::{types::Result, Error, RequestSigner};
+
+/// Credentials are loaded again this long before they expire
+const EXPIRY_MARGIN: Duration = Duration::from_mins(5);
+
+/// Request signer implementing AWS Signature Version 4.
+///
+/// Signs requests to AWS services such as Bedrock with credentials from the
+/// standard AWS credential chain, so instance, task and IRSA roles work
+/// without storing keys in the proxy configuration. Credentials are cached
+/// until shortly before they expire.
+///
+/// Buffered bodies are included in the signature; streamed bodies are signed
+/// as `UNSIGNED-PAYLOAD`, which not every service accepts.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use llm_proxy_core::providers::SigV4RequestSigner;
+///
+/// # async fn example() {
+/// let signer = SigV4RequestSigner::from_env("bedrock", Some("us-east-1".to_string())).await;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SigV4RequestSigner {
+    config: SdkConfig,
+    service: String,
+    credentials: Mutex<Option<Credentials>>,
+}
+
+impl SigV4RequestSigner {
+    /// Create a signer for `service` (e.g. `bedrock`) using the given AWS configuration
+    #[must_use]
+    pub fn new(config: SdkConfig, service: impl Into<String>) -> Self {
+        Self {
+            config,
+            service: service.into(),
+            credentials: Mutex::new(None),
+        }
+    }
+
+    /// Create a signer using the AWS configuration of the environment,
+    /// optionally overriding its region
+    pub async fn from_env(service: impl Into<String>, region: Option<String>) -> Self {
+        let mut loader = aws_config::defaults(BehaviorVersion::latest());
+        if let Some(region) = region {
+            loader = loader.region(Region::new(region));
+        }
+        Self::new(loader.load().await, service)
+    }
+
+    /// Return cached credentials, loading them again when they are about to expire
+    async fn credentials(&self) -> Result<Credentials> {
+        let mut cached = self.credentials.lock().await;
+        if let Some(credentials) = cached.as_ref() {
+            let fresh = credentials
+                .expiry()
+                .is_none_or(|expiry| expiry > SystemTime::now() + EXPIRY_MARGIN);
+            if fresh {
+                return Ok(credentials.clone());
+            }
+        }
+
+        let credentials = self
+            .config
+            .credentials_provider()
+            .ok_or_else(|| {
+                Error::AuthenticationError("No AWS credentials are configured".to_string())
+            })?
+            .provide_credentials()
+            .await
+            .map_err(|e| {
+                Error::AuthenticationError(format!("Failed to load AWS credentials: {e}"))
+            })?;
+        *cached = Some(credentials.clone());
+        drop(cached);
+        Ok(credentials)
+    }
+}
+
+#[async_trait]
+impl RequestSigner for SigV4RequestSigner {
+    async fn sign(&self, request: &mut reqwest::Request) -> Result<()> {
+        let region = self
+            .config
+            .region()
+            .ok_or_else(|| Error::ConfigError("No AWS region is configured".to_string()))?;
+        let identity: Identity = self.credentials().await?.into();
+
+        let signing_params = v4::SigningParams::builder()
+            .identity(&identity)
+            .region(region.as_ref())
+            .name(&self.service)
+            .time(SystemTime::now())
+            .settings(SigningSettings::default())
+            .build()
+            .map_err(|e| Error::AuthenticationError(format!("Invalid signing parameters: {e}")))?
+            .into();
+
+        let headers = request
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| value.to_str().ok().map(|value| (name.as_str(), value)));
+        let body = request
+            .body()
+            .map_or(Some(&[][..]), reqwest::Body::as_bytes)
+            .map_or(SignableBody::UnsignedPayload, SignableBody::Bytes);
+        let instructions = SignableRequest::new(
+            request.method().as_str(),
+            request.url().as_str(),
+            headers,
+            body,
+        )
+        .and_then(|signable| sign(signable, &signing_params))
+        .map_err(|e| Error::AuthenticationError(format!("Failed to sign AWS request: {e}")))?
+        .into_parts()
+        .0;
+
+        let (headers, params) = instructions.into_parts();
+        for header in headers {
+            let value = HeaderValue::from_str(header.value()).map_err(|e| {
+                Error::AuthenticationError(format!("Invalid signature header: {e}"))
+            })?;
+            request
+                .headers_mut()
+                .insert(HeaderName::from_static(header.name()), value);
+        }
+        if !params.is_empty() {
+            request
+                .url_mut()
+                .query_pairs_mut()
+                .extend_pairs(params.iter().map(|(name, value)| (*name, value.as_ref())));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sign_adds_authorization() {
+        let config = SdkConfig::builder()
+            .region(Region::new("us-east-1"))
+            .credentials_provider(
+                aws_credential_types::provider::SharedCredentialsProvider::new(Credentials::new(
+                    "ANOTREAL",
+                    "notrealsecret",
+                    Some("token".to_string()),
+                    None,
+                    "test",
+                )),
+            )
+            .behavior_version(BehaviorVersion::latest())
+            .build();
+        let signer = SigV4RequestSigner::new(config, "bedrock");
+
+        let mut request = reqwest::Client::new()
+            .post("https://bedrock-runtime.us-east-1.amazonaws.com/model/m/converse")
+            .body("{}")
+            .build()
+            .expect("Failed to build request");
+        signer.sign(&mut request).await.expect("Failed to sign");
+
+        let authorization = request.headers()["authorization"]
+            .to_str()
+            .expect("Invalid header");
+        assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=ANOTREAL/"));
+        assert!(authorization.contains("/us-east-1/bedrock/aws4_request"));
+        assert!(request.headers().contains_key("x-amz-date"));
+        assert!(request.headers().contains_key("x-amz-security-token"));
+    }
+}
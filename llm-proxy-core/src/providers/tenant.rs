@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::{types::Result, Tenant, TenantResolver};
+
+/// Tenant resolver backed by a fixed map of client keys, e.g. from configuration
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::{providers::StaticTenantResolver, Tenant};
+///
+/// let resolver = StaticTenantResolver::new().with_tenant(
+///     "proxy-key-acme",
+///     Tenant {
+///         id: "acme".to_string(),
+///         ..Tenant::default()
+///     },
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StaticTenantResolver {
+    tenants: HashMap<String, Tenant>,
+}
+
+impl StaticTenantResolver {
+    /// Create a resolver without tenants
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map `client_key` to `tenant`
+    #[must_use]
+    pub fn with_tenant(mut self, client_key: impl Into<String>, tenant: Tenant) -> Self {
+        self.tenants.insert(client_key.into(), tenant);
+        self
+    }
+
+    /// Whether no tenants are configured
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tenants.is_empty()
+    }
+}
+
+#[async_trait]
+impl TenantResolver for StaticTenantResolver {
+    async fn resolve(&self, client_key: &str) -> Result<Option<Tenant>> {
+        Ok(self.tenants.get(client_key).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resolves_known_keys_only() {
+        let resolver = StaticTenantResolver::new().with_tenant(
+            "proxy-key-acme",
+            Tenant {
+                id: "acme".to_string(),
+                ..Tenant::default()
+            },
+        );
+
+        let tenant = resolver
+            .resolve("proxy-key-acme")
+            .await
+            .expect("Failed to resolve");
+        assert_eq!(tenant.map(|tenant| tenant.id).as_deref(), Some("acme"));
+        assert!(resolver
+            .resolve("unknown")
+            .await
+            .expect("Failed to resolve")
+            .is_none());
+    }
+}
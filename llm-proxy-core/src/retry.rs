@@ -0,0 +1,98 @@
+//! Retries of requests the LLM service failed.
+//!
+//! A [`RetryPolicy`] on a [`Pipeline`](crate::Pipeline) sends a request again
+//! when the LLM client fails with an [`Error::LLMError`], i.e. when the
+//! service can't be reached or answers with an error before any response is
+//! streamed. Requests rejected by processors or for unsupported features are
+//! not retried. Retries wait for an exponentially growing backoff.
+
+use std::time::Duration;
+
+use crate::Error;
+
+/// How often and how fast failed requests are sent again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Send a failed request up to `max_retries` more times, waiting 200ms
+    /// before the first retry and twice as long before each further one, up to 5s
+    #[must_use]
+    pub const fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+
+    /// Wait `backoff` before the first retry
+    #[must_use]
+    pub const fn with_initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Wait at most `backoff` before any retry
+    #[must_use]
+    pub const fn with_max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Number of times a failed request is sent again
+    #[must_use]
+    pub const fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Time to wait before retry number `retry`, counted from 1
+    #[must_use]
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Whether a request that failed with `error` is sent again
+    #[must_use]
+    pub const fn retries(error: &Error) -> bool {
+        matches!(error, Error::LLMError(_))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_up_to_max() {
+        let policy = RetryPolicy::new(5)
+            .with_initial_backoff(Duration::from_millis(100))
+            .with_max_backoff(Duration::from_millis(500));
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_millis(500));
+        assert_eq!(policy.backoff(40), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_only_llm_errors_are_retried() {
+        assert!(RetryPolicy::retries(&Error::LLMError(
+            "timeout".to_string()
+        )));
+        assert!(!RetryPolicy::retries(&Error::Rejected(
+            "blocked".to_string()
+        )));
+        assert!(!RetryPolicy::retries(&Error::Unsupported(
+            "tools".to_string()
+        )));
+    }
+}
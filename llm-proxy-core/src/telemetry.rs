@@ -0,0 +1,37 @@
+//! Propagation of the proxy's traces to upstreams.
+//!
+//! With the `otel` feature, requests to LLMs carry the W3C `traceparent`
+//! header of the span sending them, so upstreams and gateways reporting to
+//! the same collector continue the proxy's trace.
+
+/// Headers carrying the trace context of the current span, to add to
+/// upstream requests
+#[cfg(feature = "otel")]
+#[must_use]
+pub fn trace_headers() -> Vec<(String, String)> {
+    use opentelemetry::propagation::Injector;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    struct HeaderInjector(Vec<(String, String)>);
+
+    impl Injector for HeaderInjector {
+        fn set(&mut self, key: &str, value: String) {
+            self.0.push((key.to_string(), value));
+        }
+    }
+
+    let context = tracing::Span::current().context();
+    let mut injector = HeaderInjector(Vec::new());
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut injector);
+    });
+    injector.0
+}
+
+/// Headers carrying the trace context of the current span, which are none
+/// without the `otel` feature
+#[cfg(not(feature = "otel"))]
+#[must_use]
+pub const fn trace_headers() -> Vec<(String, String)> {
+    Vec::new()
+}
@@ -1,6 +1,11 @@
+pub mod audit;
 pub mod client;
+pub mod client_key;
+pub mod conversation;
 pub mod processor;
 pub mod request;
+pub mod stream;
+pub mod tenant;
 
 // use client::*;
 // use processor::*;
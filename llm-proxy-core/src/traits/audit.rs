@@ -0,0 +1,126 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::types::Result;
+
+/// A request and the response it got, as kept for audits
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transcript {
+    /// Unique identifier of the transcript
+    pub id: String,
+    /// When the request was received, in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Route of the request
+    pub route: Option<String>,
+    /// Tenant whose key the request was sent with
+    pub tenant: Option<String>,
+    /// Model of the request
+    pub model: Option<String>,
+    /// The request as sent to the LLM service
+    pub request: Value,
+    /// The completion, or the chunks of a streamed response
+    pub response: Value,
+}
+
+/// Which transcripts an [`AuditStore`] returns, newest first
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::AuditQuery;
+///
+/// let query = AuditQuery::new()
+///     .with_since(1_700_000_000_000)
+///     .with_tenant("acme")
+///     .with_model("gpt-4o")
+///     .with_limit(50);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditQuery {
+    /// Earliest timestamp, inclusive
+    pub since: Option<u64>,
+    /// Latest timestamp, exclusive
+    pub until: Option<u64>,
+    /// Tenant of the requests
+    pub tenant: Option<String>,
+    /// Model of the requests
+    pub model: Option<String>,
+    /// Maximum number of transcripts returned
+    pub limit: Option<u32>,
+}
+
+impl AuditQuery {
+    /// Create a query for all transcripts
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only return transcripts from `since`, in milliseconds since the Unix epoch
+    #[must_use]
+    pub const fn with_since(mut self, since: u64) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Only return transcripts before `until`, in milliseconds since the Unix epoch
+    #[must_use]
+    pub const fn with_until(mut self, until: u64) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Only return the transcripts of `tenant`
+    #[must_use]
+    pub fn with_tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    /// Only return the transcripts of requests for `model`
+    #[must_use]
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Return at most `limit` transcripts
+    #[must_use]
+    pub const fn with_limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+/// Trait for persisting request/response transcripts, for compliance reviews
+/// and prompt datasets.
+///
+/// # Example
+///
+/// ```rust
+/// # use async_trait::async_trait;
+/// # use llm_proxy_core::{AuditQuery, AuditStore, Result, Transcript};
+/// struct WarehouseAuditStore;
+///
+/// #[async_trait]
+/// impl AuditStore for WarehouseAuditStore {
+///     async fn record(&self, transcript: &Transcript) -> Result<()> {
+///         // Insert the transcript into the warehouse
+///         # Ok(())
+///     }
+///
+///     async fn query(&self, query: &AuditQuery) -> Result<Vec<Transcript>> {
+///         // Select the matching transcripts
+///         # Ok(Vec::new())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait AuditStore: Send + Sync {
+    /// Persist `transcript`
+    async fn record(&self, transcript: &Transcript) -> Result<()>;
+
+    /// Find the transcripts matching `query`, newest first
+    async fn query(&self, query: &AuditQuery) -> Result<Vec<Transcript>>;
+}
@@ -1,8 +1,9 @@
 use async_trait::async_trait;
+use serde::Serialize;
 
 use crate::{
     types::{ResponseStream, Result},
-    LLMRequest,
+    LLMRequest, ProviderCapabilities, RequestContext,
 };
 
 /// Trait for interacting with an LLM service.
@@ -15,10 +16,20 @@ use crate::{
 ///
 /// ```rust
 /// # use async_trait::async_trait;
-/// # use anyhow::Result;
+/// # use llm_proxy_core::Result;
 /// # use llm_proxy_core::{LLMClient, ResponseStream};
 /// #
+/// # #[derive(serde::Deserialize)]
 /// # struct OpenAIRequest;
+/// # impl llm_proxy_core::LLMRequest for OpenAIRequest {
+/// #     fn messages(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
+/// #     fn model(&self) -> Result<String> { Ok("model".to_string()) }
+/// #     fn stream(&self) -> Result<bool> { Ok(false) }
+/// #     fn max_tokens(&self) -> Option<u32> { None }
+/// #     fn to_map(&self) -> Result<std::collections::HashMap<String, serde_json::Value>> { Ok(std::collections::HashMap::new()) }
+/// #     fn to_value(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
+/// #     fn to_bytes(&self) -> Result<bytes::Bytes> { Ok(bytes::Bytes::new()) }
+/// # }
 ///
 /// struct OpenAIClient {
 ///     api_key: String,
@@ -43,6 +54,29 @@ pub trait LLMClient<T: LLMRequest>: Send + Sync {
     /// # Returns
     /// A channel receiver that will receive the response chunks
     async fn execute(&self, request: T) -> Result<ResponseStream>;
+
+    /// Execute a request with information about the inbound request.
+    ///
+    /// Clients that make per-request decisions, such as picking the API token,
+    /// should override this. The default implementation ignores the context.
+    async fn execute_with_context(
+        &self,
+        request: T,
+        _context: &RequestContext,
+    ) -> Result<ResponseStream>
+    where
+        T: 'async_trait,
+    {
+        self.execute(request).await
+    }
+
+    /// Describe what the LLM service supports.
+    ///
+    /// The pipeline rejects requests using unsupported features before they
+    /// are sent. The default implementation declares no restrictions.
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities::default()
+    }
 }
 
 /// Trait for managing LLM API tokens.
@@ -54,7 +88,7 @@ pub trait LLMClient<T: LLMRequest>: Send + Sync {
 ///
 /// ```rust
 /// # use async_trait::async_trait;
-/// # use anyhow::Result;
+/// # use llm_proxy_core::Result;
 /// # use llm_proxy_core::TokenProvider;
 ///
 /// struct EnvTokenProvider {
@@ -64,7 +98,8 @@ pub trait LLMClient<T: LLMRequest>: Send + Sync {
 /// #[async_trait]
 /// impl TokenProvider for EnvTokenProvider {
 ///     async fn get_token(&self) -> Result<String> {
-///         std::env::var(&self.env_var).map_err(Into::into)
+///         std::env::var(&self.env_var)
+///             .map_err(|e| llm_proxy_core::Error::ConfigError(e.to_string()))
 ///     }
 /// }
 /// ```
@@ -74,6 +109,44 @@ pub trait TokenProvider: Send + Sync {
     /// This might involve reading from environment variables,
     /// secure storage, or a token management service.
     async fn get_token(&self) -> Result<String>;
+
+    /// Get an API token for a specific request.
+    ///
+    /// Providers holding several tokens can use the context (e.g. its model
+    /// or tenant) to pick one. The default implementation ignores the context.
+    async fn get_token_for(&self, _context: &RequestContext) -> Result<String> {
+        self.get_token().await
+    }
+
+    /// Report that the upstream service rejected a request made with `token`.
+    ///
+    /// `status` is the HTTP status returned by the service (e.g. 401 or 429).
+    /// Providers managing several tokens can use this to stop handing out a
+    /// failing token for a while. The default implementation does nothing.
+    async fn report_rejection(&self, _token: &str, _status: u16) {}
+
+    /// Current state of the tokens this provider rotates across, without
+    /// the tokens themselves.
+    ///
+    /// The default implementation reports none.
+    fn key_status(&self) -> Vec<KeyStatus> {
+        Vec::new()
+    }
+}
+
+/// State of a token managed by a [`TokenProvider`], e.g. for dashboards
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeyStatus {
+    /// Position of the token in its provider
+    pub index: usize,
+    /// Relative share of requests the token receives
+    pub weight: u32,
+    /// Maximum number of requests per minute sent with the token
+    pub max_requests_per_minute: Option<u32>,
+    /// Number of requests sent with the token in the current minute
+    pub requests_this_minute: u32,
+    /// Seconds until the token is handed out again, if it is quarantined
+    pub quarantined_secs: Option<u64>,
 }
 
 /// Trait for providing the LLM service URL.
@@ -85,23 +158,44 @@ pub trait TokenProvider: Send + Sync {
 ///
 /// ```rust
 /// # use async_trait::async_trait;
-/// # use anyhow::Result;
+/// # use llm_proxy_core::Result;
 /// # use llm_proxy_core::UrlProvider;
 ///
 /// struct ConfigUrlProvider {
 ///     base_url: String,
 /// }
 ///
-/// #[async_trait]
 /// impl UrlProvider for ConfigUrlProvider {
-///     async fn get_url(&self) -> Result<String> {
+///     fn get_url(&self) -> Result<String> {
 ///         Ok(self.base_url.clone())
 ///     }
 /// }
 /// ```
 pub trait UrlProvider: Send + Sync {
     /// Get the URL for the LLM service endpoint.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no URL can be determined.
     fn get_url(&self) -> Result<String>;
+
+    /// Health of the endpoints this provider picks from.
+    ///
+    /// The default implementation reports none.
+    fn endpoint_status(&self) -> Vec<EndpointStatus> {
+        Vec::new()
+    }
+}
+
+/// Health of an upstream endpoint of a [`UrlProvider`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EndpointStatus {
+    /// URL of the endpoint
+    pub url: String,
+    /// Whether the endpoint currently receives requests
+    pub healthy: bool,
+    /// Number of health checks failed in a row
+    pub consecutive_failures: u32,
 }
 
 /// Trait for providing an HTTP client.
@@ -113,7 +207,7 @@ pub trait UrlProvider: Send + Sync {
 ///
 /// ```rust
 /// # use async_trait::async_trait;
-/// # use anyhow::Result;
+/// # use llm_proxy_core::Result;
 /// # use std::time::Duration;
 /// # use llm_proxy_core::ClientProvider;
 ///
@@ -127,7 +221,7 @@ pub trait UrlProvider: Send + Sync {
 ///         reqwest::Client::builder()
 ///             .timeout(self.timeout)
 ///             .build()
-///             .map_err(Into::into)
+///             .map_err(|e| llm_proxy_core::Error::ConfigError(e.to_string()))
 ///     }
 /// }
 /// ```
@@ -136,3 +230,35 @@ pub trait ClientProvider: Send + Sync {
     /// Get an HTTP client for making requests.
     async fn get_client(&self) -> Result<reqwest::Client>;
 }
+
+/// Trait for signing upstream requests.
+///
+/// Some services, like AWS Bedrock, authenticate requests with a signature
+/// over the method, URL, headers and body instead of a static token. Clients
+/// call the signer on the fully built request, just before it is sent, so
+/// the signature covers everything that goes over the wire.
+///
+/// # Example
+///
+/// ```rust
+/// # use async_trait::async_trait;
+/// # use llm_proxy_core::Result;
+/// # use llm_proxy_core::RequestSigner;
+///
+/// struct TimestampSigner;
+///
+/// #[async_trait]
+/// impl RequestSigner for TimestampSigner {
+///     async fn sign(&self, request: &mut reqwest::Request) -> Result<()> {
+///         request
+///             .headers_mut()
+///             .insert("x-signed-at", reqwest::header::HeaderValue::from_static("now"));
+///         Ok(())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait RequestSigner: Send + Sync {
+    /// Sign a request by adding headers or query parameters to it.
+    async fn sign(&self, request: &mut reqwest::Request) -> Result<()>;
+}
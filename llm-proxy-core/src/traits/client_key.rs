@@ -0,0 +1,40 @@
+use async_trait::async_trait;
+
+use crate::types::Result;
+
+/// A key the proxy issued to a client
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientKey {
+    /// Identifier of the key, stored in [`RequestContext::client_key`](crate::RequestContext)
+    pub id: String,
+    /// Tenant the key belongs to, if any
+    pub tenant: Option<String>,
+}
+
+/// Trait for looking up the proxy-issued keys clients authenticate with.
+///
+/// The server checks the key of every request against the store before it
+/// is routed, and records the key's identity in the request context for
+/// quotas, logs and tenant mapping. Stores should keep hashes of the keys
+/// rather than the keys, see [`hash_key`](crate::providers::hash_key).
+///
+/// # Example
+///
+/// ```rust
+/// # use async_trait::async_trait;
+/// # use llm_proxy_core::{ClientKey, ClientKeyStore, Result};
+/// struct VaultClientKeyStore;
+///
+/// #[async_trait]
+/// impl ClientKeyStore for VaultClientKeyStore {
+///     async fn lookup(&self, key: &str) -> Result<Option<ClientKey>> {
+///         // Look the key up in a secret store
+///         # Ok(None)
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait ClientKeyStore: Send + Sync {
+    /// Find the identity of `key`, or `None` if the key is unknown
+    async fn lookup(&self, key: &str) -> Result<Option<ClientKey>>;
+}
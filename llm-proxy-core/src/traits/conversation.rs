@@ -0,0 +1,61 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::types::Result;
+
+/// One exchange of a conversation: the messages a client sent and the
+/// assistant message it got back
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Turn {
+    /// When the request was received, in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// The messages new in this turn, e.g. the user message, or the results
+    /// of the tool calls of the last reply
+    pub messages: Vec<Value>,
+    /// The assistant message answering them
+    pub reply: Value,
+}
+
+impl Turn {
+    /// The messages of the turn followed by its reply, in the order they are
+    /// sent to a model
+    pub fn into_messages(self) -> impl Iterator<Item = Value> {
+        self.messages.into_iter().chain(std::iter::once(self.reply))
+    }
+}
+
+/// Trait for keeping the history of conversations, so clients can send only
+/// their newest message and let the proxy maintain the rest.
+///
+/// Conversations are identified by a session ID chosen by the client.
+///
+/// # Example
+///
+/// ```rust
+/// # use async_trait::async_trait;
+/// # use llm_proxy_core::{ConversationStore, Result, Turn};
+/// struct CacheConversationStore;
+///
+/// #[async_trait]
+/// impl ConversationStore for CacheConversationStore {
+///     async fn append(&self, session: &str, turn: &Turn) -> Result<()> {
+///         // Add the turn to the session's list
+///         # Ok(())
+///     }
+///
+///     async fn history(&self, session: &str, limit: Option<u32>) -> Result<Vec<Turn>> {
+///         // Read the last turns of the session's list
+///         # Ok(Vec::new())
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait ConversationStore: Send + Sync {
+    /// Add `turn` at the end of the conversation of `session`
+    async fn append(&self, session: &str, turn: &Turn) -> Result<()>;
+
+    /// The last `limit` turns of the conversation of `session`, or all of
+    /// them, oldest first
+    async fn history(&self, session: &str, limit: Option<u32>) -> Result<Vec<Turn>>;
+}
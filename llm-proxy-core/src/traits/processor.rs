@@ -4,7 +4,7 @@ use async_trait::async_trait;
 
 use crate::types::Result;
 
-use crate::LLMRequest;
+use crate::{LLMRequest, RequestContext};
 
 /// Trait for processing requests before they are sent to the LLM service.
 ///
@@ -19,10 +19,20 @@ use crate::LLMRequest;
 ///
 /// ```rust
 /// # use async_trait::async_trait;
-/// # use anyhow::Result;
+/// # use llm_proxy_core::Result;
 /// # use llm_proxy_core::Processor;
 /// #
+/// # #[derive(serde::Deserialize)]
 /// # struct MyLLMRequest;
+/// # impl llm_proxy_core::LLMRequest for MyLLMRequest {
+/// #     fn messages(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
+/// #     fn model(&self) -> Result<String> { Ok("model".to_string()) }
+/// #     fn stream(&self) -> Result<bool> { Ok(false) }
+/// #     fn max_tokens(&self) -> Option<u32> { None }
+/// #     fn to_map(&self) -> Result<std::collections::HashMap<String, serde_json::Value>> { Ok(std::collections::HashMap::new()) }
+/// #     fn to_value(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
+/// #     fn to_bytes(&self) -> Result<bytes::Bytes> { Ok(bytes::Bytes::new()) }
+/// # }
 /// # impl MyLLMRequest {
 /// #     fn add_system_message(&mut self, _msg: &str) -> Result<()> { Ok(()) }
 /// # }
@@ -56,6 +66,18 @@ pub trait Processor<T: LLMRequest>: Send + Sync {
     /// # Returns
     /// The processed request, which may be modified from the input
     async fn process(&self, request: T) -> Result<T>;
+
+    /// Process a request with information about the inbound request.
+    ///
+    /// Processors that make per-request decisions, or record results for
+    /// later components such as audit logs, should override this. The default
+    /// implementation ignores the context.
+    async fn process_with_context(&self, request: T, _context: &mut RequestContext) -> Result<T>
+    where
+        T: 'async_trait,
+    {
+        self.process(request).await
+    }
 }
 
 /// A chain of processors that are executed in sequence.
@@ -68,25 +90,47 @@ pub trait Processor<T: LLMRequest>: Send + Sync {
 ///
 /// ```rust
 /// # use std::sync::Arc;
-/// # use anyhow::Result;
+/// # use llm_proxy_core::Result;
 /// # use llm_proxy_core::ProcessorChain;
 /// #
+/// # #[derive(serde::Deserialize)]
 /// # struct MyLLMRequest;
+/// # impl llm_proxy_core::LLMRequest for MyLLMRequest {
+/// #     fn messages(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
+/// #     fn model(&self) -> Result<String> { Ok("model".to_string()) }
+/// #     fn stream(&self) -> Result<bool> { Ok(false) }
+/// #     fn max_tokens(&self) -> Option<u32> { None }
+/// #     fn to_map(&self) -> Result<std::collections::HashMap<String, serde_json::Value>> { Ok(std::collections::HashMap::new()) }
+/// #     fn to_value(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
+/// #     fn to_bytes(&self) -> Result<bytes::Bytes> { Ok(bytes::Bytes::new()) }
+/// # }
 /// # struct SystemMessageProcessor;
+/// # #[async_trait::async_trait]
+/// # impl llm_proxy_core::Processor<MyLLMRequest> for SystemMessageProcessor {
+/// #     async fn process(&self, request: MyLLMRequest) -> Result<MyLLMRequest> { Ok(request) }
+/// # }
 /// # impl SystemMessageProcessor {
 /// #     fn new(_: &str) -> Self { Self }
 /// # }
 /// # struct TokenLimitProcessor;
+/// # #[async_trait::async_trait]
+/// # impl llm_proxy_core::Processor<MyLLMRequest> for TokenLimitProcessor {
+/// #     async fn process(&self, request: MyLLMRequest) -> Result<MyLLMRequest> { Ok(request) }
+/// # }
 /// # impl TokenLimitProcessor {
 /// #     fn new(_: u32) -> Self { Self }
 /// # }
 /// # struct LoggingProcessor;
+/// # #[async_trait::async_trait]
+/// # impl llm_proxy_core::Processor<MyLLMRequest> for LoggingProcessor {
+/// #     async fn process(&self, request: MyLLMRequest) -> Result<MyLLMRequest> { Ok(request) }
+/// # }
 /// # impl LoggingProcessor {
 /// #     fn new() -> Self { Self }
 /// # }
 /// #
 /// # async fn example() -> Result<()> {
-/// let chain = ProcessorChain::new(vec![
+/// let chain = ProcessorChain::<MyLLMRequest>::new(vec![
 ///     Arc::new(SystemMessageProcessor::new("Be helpful")),
 ///     Arc::new(TokenLimitProcessor::new(2000)),
 ///     Arc::new(LoggingProcessor::new()),
@@ -121,10 +165,38 @@ impl<T: LLMRequest> ProcessorChain<T> {
     ///
     /// This function will return an error if the request processing fails.
     pub async fn execute(&self, initial_request: T) -> Result<T> {
+        self.execute_with_context(initial_request, &mut RequestContext::new())
+            .await
+    }
+
+    /// Execute all processors in the chain in sequence, passing each the
+    /// context of the inbound request
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request processing fails.
+    pub async fn execute_with_context(
+        &self,
+        initial_request: T,
+        context: &mut RequestContext,
+    ) -> Result<T> {
         let mut request = initial_request;
         for processor in &self.processors {
-            request = processor.process(request).await?;
+            request = processor.process_with_context(request, context).await?;
         }
         Ok(request)
     }
 }
+
+/// A chain is itself a processor, so chains can be nested, e.g. to run
+/// route-level processors before the processors of a provider.
+#[async_trait]
+impl<T: LLMRequest + 'static> Processor<T> for ProcessorChain<T> {
+    async fn process(&self, request: T) -> Result<T> {
+        self.execute(request).await
+    }
+
+    async fn process_with_context(&self, request: T, context: &mut RequestContext) -> Result<T> {
+        self.execute_with_context(request, context).await
+    }
+}
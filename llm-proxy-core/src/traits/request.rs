@@ -17,12 +17,14 @@ use serde_json::Value;
 /// ```rust
 /// # use serde_json::Value;
 /// # use std::collections::HashMap;
-/// # use anyhow::Result;
+/// # use llm_proxy_core::Result;
 /// #
+/// #[derive(serde::Serialize, serde::Deserialize)]
 /// struct Message {
 ///     content: String,
 /// }
 ///
+/// #[derive(serde::Deserialize)]
 /// struct MyLLMRequest {
 ///     messages: Vec<Message>,
 ///     model: String,
@@ -54,6 +56,10 @@ use serde_json::Value;
 ///     fn to_value(&self) -> Result<Value> {
 ///         Ok(Value::Null)
 ///     }
+///
+///     fn to_bytes(&self) -> Result<bytes::Bytes> {
+///         Ok(bytes::Bytes::new())
+///     }
 /// }
 /// ```
 pub trait LLMRequest: Send + Sync + DeserializeOwned {
@@ -159,10 +165,20 @@ pub trait LLMResponse {
 /// ```rust
 /// # use bytes::Bytes;
 /// # use async_trait::async_trait;
-/// # use anyhow::Result;
+/// # use llm_proxy_core::Result;
 /// # use llm_proxy_core::RequestParser;
 /// #
+/// # #[derive(serde::Deserialize)]
 /// # struct MyLLMRequest;
+/// # impl llm_proxy_core::LLMRequest for MyLLMRequest {
+/// #     fn messages(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
+/// #     fn model(&self) -> Result<String> { Ok("model".to_string()) }
+/// #     fn stream(&self) -> Result<bool> { Ok(false) }
+/// #     fn max_tokens(&self) -> Option<u32> { None }
+/// #     fn to_map(&self) -> Result<std::collections::HashMap<String, serde_json::Value>> { Ok(std::collections::HashMap::new()) }
+/// #     fn to_value(&self) -> Result<serde_json::Value> { Ok(serde_json::Value::Null) }
+/// #     fn to_bytes(&self) -> Result<bytes::Bytes> { Ok(bytes::Bytes::new()) }
+/// # }
 /// # impl MyLLMRequest {
 /// #     fn new() -> Self { Self }
 /// # }
@@ -179,6 +195,6 @@ pub trait LLMResponse {
 /// ```
 #[async_trait]
 pub trait RequestParser<T: LLMRequest>: Send + Sync {
-    /// Parse raw request bytes into a specific LLMRequest implementation.
+    /// Parse raw request bytes into a specific `LLMRequest` implementation.
     async fn parse(&self, body: Bytes) -> Result<T>;
 }
@@ -0,0 +1,43 @@
+use serde_json::Value;
+
+use crate::{types::ResponseStream, RequestContext};
+
+/// Trait for processing the response stream of the LLM service before it
+/// reaches the client.
+///
+/// Stream processors can inspect, rewrite or cut short the response, such as:
+/// - Stopping generations that violate a policy
+/// - Enforcing stop sequences or output limits the backend ignores
+///
+/// A processor that stops a response drops the stream it was given, which
+/// lets the LLM client cancel the upstream request.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::{RequestContext, ResponseStream, StreamProcessor};
+/// use serde_json::Value;
+///
+/// struct PassThrough;
+///
+/// impl StreamProcessor for PassThrough {
+///     fn process_stream(
+///         &self,
+///         _request: &Value,
+///         _context: &RequestContext,
+///         stream: ResponseStream,
+///     ) -> ResponseStream {
+///         stream
+///     }
+/// }
+/// ```
+pub trait StreamProcessor: Send + Sync {
+    /// Wrap the response stream of `request`, as sent to the LLM service,
+    /// returning the stream forwarded to the client
+    fn process_stream(
+        &self,
+        request: &Value,
+        context: &RequestContext,
+        stream: ResponseStream,
+    ) -> ResponseStream;
+}
@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::types::Result;
+
+/// A client of the proxy, identified by a proxy-issued key
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tenant {
+    /// Identifier of the tenant, stored in [`RequestContext::tenant`](crate::RequestContext)
+    pub id: String,
+    /// Attributes copied into the request context, e.g. the upstream organization
+    pub attributes: HashMap<String, String>,
+}
+
+/// Trait for mapping the key a client authenticates with to a tenant.
+///
+/// The server consults the resolver before the pipeline runs, so that token
+/// providers and clients can pick the upstream key and settings for the tenant
+/// through the [`RequestContext`](crate::RequestContext).
+///
+/// # Example
+///
+/// ```rust
+/// # use async_trait::async_trait;
+/// # use llm_proxy_core::{Result, Tenant, TenantResolver};
+/// struct DatabaseTenantResolver;
+///
+/// #[async_trait]
+/// impl TenantResolver for DatabaseTenantResolver {
+///     async fn resolve(&self, client_key: &str) -> Result<Option<Tenant>> {
+///         // Look the key up in a database
+///         # Ok(None)
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait TenantResolver: Send + Sync {
+    /// Find the tenant owning `client_key`, or `None` if the key is unknown
+    async fn resolve(&self, client_key: &str) -> Result<Option<Tenant>>;
+}
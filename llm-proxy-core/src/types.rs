@@ -1,3 +1,5 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
@@ -10,6 +12,14 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// Represents a raw response stream from an LLM service
 pub type ResponseStream = mpsc::Receiver<Result<Bytes>>;
 
+/// Current Unix timestamp, used as the `created` time of completions
+#[must_use]
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
 /// Configuration for a specific LLM backend service
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LLMConfig {
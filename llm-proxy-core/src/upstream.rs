@@ -0,0 +1,275 @@
+//! Exchanges with the upstream services of the provider crates.
+//!
+//! Providers translating `OpenAI` chat requests into another API send them
+//! with [`send_request`], then hand the response to [`forward_stream`] or
+//! [`forward_response`], which translate it into `OpenAI` chunks or a chat
+//! completion for the pipeline. Failures are reported the same way whichever
+//! backend serves a request.
+
+use bytes::Bytes;
+use futures_util::StreamExt;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+use crate::{Error, RequestSigner, Result, TokenProvider};
+
+/// The error body of an upstream service
+pub trait UpstreamError: DeserializeOwned {
+    /// The message describing the error
+    fn message(self) -> String;
+}
+
+/// Translation of an upstream's streamed response into `OpenAI` chunks
+pub trait ChunkTranslator: Send {
+    /// The `OpenAI` chunks completed by the next `bytes` of the response
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the response can't be translated.
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>>;
+}
+
+/// Send the request of `builder` to the upstream service `name`
+///
+/// The request is signed by `signer` if given. Error statuses are reported
+/// to the provider of the token the request was sent with, if given.
+///
+/// # Errors
+///
+/// This function will return an error if the request can't be built, signed
+/// or sent, or if the service answers with an error status, with the message
+/// of its error body `E`.
+pub async fn send_request<E: UpstreamError>(
+    name: &str,
+    builder: reqwest::RequestBuilder,
+    signer: Option<&dyn RequestSigner>,
+    token: Option<(&dyn TokenProvider, &str)>,
+) -> Result<reqwest::Response> {
+    let (client, request) = builder.build_split();
+    let mut request =
+        request.map_err(|e| Error::LLMError(format!("Failed to build request to {name}: {e}")))?;
+    if let Some(signer) = signer {
+        signer.sign(&mut request).await?;
+    }
+    let response = client
+        .execute(request)
+        .await
+        .map_err(|e| Error::LLMError(format!("Failed to send request to {name}: {e}")))?;
+
+    if let Some((provider, token)) = token {
+        if !response.status().is_success() {
+            provider
+                .report_rejection(token, response.status().as_u16())
+                .await;
+        }
+    }
+    error_for_status::<E>(name, response).await
+}
+
+/// `response` of the upstream service `name` if it succeeded
+///
+/// # Errors
+///
+/// This function will return an error with the message of the error body `E`
+/// if the response has an error status.
+pub async fn error_for_status<E: UpstreamError>(
+    name: &str,
+    response: reqwest::Response,
+) -> Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let error_body = response.json::<E>().await.map_err(|e| {
+        Error::LLMError(format!(
+            "Failed to parse {name} error response: {e}, status: {status}"
+        ))
+    })?;
+    Err(Error::LLMError(format!(
+        "{name} request failed: {} ({status})",
+        error_body.message()
+    )))
+}
+
+/// Translate the streamed `response` of the upstream service `name` with
+/// `translator`, sending the chunks to `tx`
+///
+/// Stops at the first error, which is sent as the last item, or when the
+/// receiver goes away.
+pub async fn forward_stream(
+    name: &str,
+    response: reqwest::Response,
+    mut translator: impl ChunkTranslator,
+    tx: &mpsc::Sender<Result<Bytes>>,
+) {
+    let mut stream = response.bytes_stream();
+
+    while let Some(chunk_result) = stream.next().await {
+        let events = match chunk_result
+            .map_err(|e| Error::LLMError(format!("Error reading chunk from {name}: {e}")))
+            .and_then(|chunk| translator.push(&chunk))
+        {
+            Ok(events) => events,
+            Err(e) => {
+                send_response(name, tx, Err(e)).await;
+                return;
+            }
+        };
+        for event in events {
+            if tx.send(Ok(event)).await.is_err() {
+                warn!("Failed to send chunk - receiver dropped");
+                return;
+            }
+        }
+    }
+}
+
+/// Translate the response of the upstream service `name`, a `R` body, into
+/// an `OpenAI` chat completion with `translate` and send it to `tx`
+pub async fn forward_response<R: DeserializeOwned>(
+    name: &str,
+    response: reqwest::Response,
+    translate: impl FnOnce(R) -> Value + Send,
+    tx: &mpsc::Sender<Result<Bytes>>,
+) {
+    let result = response
+        .json::<R>()
+        .await
+        .map_err(|e| Error::LLMError(format!("Failed to read {name} response: {e}")))
+        .map(|response| Bytes::from(translate(response).to_string()));
+    send_response(name, tx, result).await;
+}
+
+/// Send `result`, the whole response of the upstream service `name` or the
+/// error ending it, to `tx`
+pub async fn send_response(name: &str, tx: &mpsc::Sender<Result<Bytes>>, result: Result<Bytes>) {
+    if let Err(e) = &result {
+        error!(error = %e, "Error handling {name} response");
+    }
+    let sent = if result.is_ok() { "response" } else { "error" };
+    if tx.send(result).await.is_err() {
+        warn!("Failed to send {sent} - receiver dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct TestError {
+        message: String,
+    }
+
+    impl UpstreamError for TestError {
+        fn message(self) -> String {
+            self.message
+        }
+    }
+
+    /// Translates every chunk into its uppercase
+    struct Uppercase;
+
+    impl ChunkTranslator for Uppercase {
+        fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+            Ok(vec![Bytes::from(bytes.to_ascii_uppercase())])
+        }
+    }
+
+    /// Records the statuses its tokens were rejected with
+    #[derive(Default)]
+    struct RecordingTokenProvider {
+        rejections: Mutex<Vec<u16>>,
+    }
+
+    #[async_trait]
+    impl TokenProvider for RecordingTokenProvider {
+        async fn get_token(&self) -> Result<String> {
+            Ok("test-token".to_string())
+        }
+
+        async fn report_rejection(&self, _token: &str, status: u16) {
+            self.rejections
+                .lock()
+                .expect("Poisoned rejections")
+                .push(status);
+        }
+    }
+
+    /// Serve one HTTP response with `status` and `body` on a local port,
+    /// returning its URL
+    async fn serve_once(status: &'static str, body: &'static str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let address = listener.local_addr().expect("No local address");
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.expect("Failed to accept");
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            let head = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(head.as_bytes()).await;
+            let _ = stream.write_all(body.as_bytes()).await;
+        });
+        format!("http://{address}/v1/chat")
+    }
+
+    #[tokio::test]
+    async fn test_send_request_reports_errors() {
+        let url = serve_once("429 Too Many Requests", r#"{"message":"Slow down"}"#).await;
+        let provider = RecordingTokenProvider::default();
+        let builder = reqwest::Client::new().post(url);
+        let error =
+            send_request::<TestError>("Test", builder, None, Some((&provider, "test-token")))
+                .await
+                .expect_err("Accepted an error status");
+        assert!(
+            matches!(&error, Error::LLMError(message)
+                if message == "Test request failed: Slow down (429 Too Many Requests)"),
+            "{error}"
+        );
+        assert_eq!(
+            *provider.rejections.lock().expect("Poisoned rejections"),
+            [429]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forward() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let url = serve_once("200 OK", "data: hi\n\n").await;
+        let builder = reqwest::Client::new().get(url);
+        let response = send_request::<TestError>("Test", builder, None, None)
+            .await
+            .expect("Request failed");
+        forward_stream("Test", response, Uppercase, &tx).await;
+        let mut streamed = Vec::new();
+        while let Ok(chunk) = rx.try_recv() {
+            streamed.extend_from_slice(&chunk.expect("Failed chunk"));
+        }
+        assert_eq!(streamed, b"DATA: HI\n\n");
+
+        let url = serve_once("200 OK", r#"{"text":"hi"}"#).await;
+        let response = reqwest::get(url).await.expect("Request failed");
+        let translate = |response: Value| serde_json::json!({ "content": response["text"] });
+        forward_response("Test", response, translate, &tx).await;
+        let completion = rx
+            .recv()
+            .await
+            .expect("No response")
+            .expect("Failed response");
+        assert_eq!(&completion[..], br#"{"content":"hi"}"#);
+    }
+}
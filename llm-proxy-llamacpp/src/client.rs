@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use llm_proxy_core::{
+    upstream, AuthScheme, ClientProvider, Error, LLMClient, ProviderCapabilities, RequestContext,
+    Result, TokenProvider, UrlProvider,
+};
+use llm_proxy_openai::ChatCompletionRequest;
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::{
+    translate::{normalize_response, to_llamacpp_request, StreamTranslator},
+    types::ErrorResponse,
+};
+
+/// Name of the upstream service in errors
+const UPSTREAM: &str = "llama.cpp";
+
+/// llama.cpp server implementation of `LLMClient` for `OpenAI` chat completion requests.
+///
+/// Requests are sent to the OpenAI-like `/v1/chat/completions` endpoint of
+/// `llama-server` with their parameters mapped to llama.cpp's samplers, and
+/// the non-standard fields of its responses are replaced with their `OpenAI`
+/// equivalents. Servers started without `--api-key` need no token.
+#[derive(Clone)]
+pub struct LlamaCppClient {
+    client: Arc<dyn ClientProvider>,
+    token: Option<Arc<dyn TokenProvider>>,
+    url: Arc<dyn UrlProvider>,
+    auth_scheme: AuthScheme,
+    headers: Vec<(String, String)>,
+    sampling: Map<String, Value>,
+    capabilities: ProviderCapabilities,
+}
+
+impl LlamaCppClient {
+    /// Create a new llama.cpp client with the given providers
+    pub fn new(
+        client_provider: Arc<dyn ClientProvider>,
+        url_provider: Arc<dyn UrlProvider>,
+    ) -> Self {
+        Self {
+            client: client_provider,
+            token: None,
+            url: url_provider,
+            auth_scheme: AuthScheme::default(),
+            headers: Vec::new(),
+            sampling: Map::new(),
+            capabilities: ProviderCapabilities::default(),
+        }
+    }
+
+    /// Replace the provider of the HTTP client used for upstream requests
+    #[must_use]
+    pub fn with_client_provider(mut self, client_provider: Arc<dyn ClientProvider>) -> Self {
+        self.client = client_provider;
+        self
+    }
+
+    /// Authenticate requests to servers started with `--api-key`
+    #[must_use]
+    pub fn with_token_provider(mut self, token_provider: Arc<dyn TokenProvider>) -> Self {
+        self.token = Some(token_provider);
+        self
+    }
+
+    /// Set how the API token is sent to the upstream service
+    #[must_use]
+    pub fn with_auth_scheme(mut self, auth_scheme: AuthScheme) -> Self {
+        self.auth_scheme = auth_scheme;
+        self
+    }
+
+    /// Send a static header with every request
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set default sampling parameters, e.g. `mirostat` or `repeat_penalty`,
+    /// used when a request doesn't set them
+    #[must_use]
+    pub fn with_sampling(mut self, sampling: Map<String, Value>) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Declare what the upstream backend supports, e.g. its models
+    #[must_use]
+    pub fn with_capabilities(mut self, capabilities: ProviderCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Send request to llama.cpp and get response
+    async fn send_request(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+        client: reqwest::Client,
+        url: String,
+    ) -> Result<reqwest::Response> {
+        let token = match &self.token {
+            Some(token) => Some(
+                token
+                    .get_token_for(context)
+                    .await
+                    .map_err(|e| Error::LLMError(format!("Failed to get API token: {e}")))?,
+            ),
+            None => None,
+        };
+
+        let mut builder = client.post(url);
+        if let Some(token) = &token {
+            builder = self.auth_scheme.apply(builder, token);
+        }
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let rejected = self.token.as_deref().zip(token.as_deref());
+        upstream::send_request::<ErrorResponse>(UPSTREAM, builder.json(request), None, rejected)
+            .await
+    }
+}
+
+#[async_trait]
+impl LLMClient<ChatCompletionRequest> for LlamaCppClient {
+    async fn execute(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        self.execute_with_context(request, &RequestContext::new())
+            .await
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.capabilities.clone()
+    }
+
+    async fn execute_with_context(
+        &self,
+        request: ChatCompletionRequest,
+        context: &RequestContext,
+    ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        // 1. Map the sampling parameters
+        let request = to_llamacpp_request(request, &self.sampling);
+
+        // 2. Get dependencies
+        let client = self
+            .client
+            .get_client()
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get HTTP client: {e}")))?;
+        let url = self.url.get_url()?;
+
+        // 3. Send request
+        let response = self.send_request(&request, context, client, url).await?;
+
+        // 4. Normalize the response based on streaming flag
+        let (tx, rx) = mpsc::channel(100);
+        let stream = request.stream;
+        info!("The request is streaming: {}", stream);
+        tokio::spawn(async move {
+            if stream {
+                upstream::forward_stream(UPSTREAM, response, StreamTranslator::new(), &tx).await;
+            } else {
+                let normalize = |mut response: Value| {
+                    normalize_response(&mut response);
+                    response
+                };
+                upstream::forward_response(UPSTREAM, response, normalize, &tx).await;
+            }
+        });
+
+        Ok(rx)
+    }
+}
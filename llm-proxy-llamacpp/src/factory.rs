@@ -0,0 +1,65 @@
+//! Provider factory for llama.cpp servers, registered as `llamacpp`.
+
+use async_trait::async_trait;
+use llm_proxy_core::{Pipeline, ProviderContext, ProviderFactory, ProviderRegistry, Result};
+use llm_proxy_openai::ChatCompletionRequest;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    create_chat_client, create_chat_client_with_url_provider, create_pipeline_with_client,
+};
+
+/// Register the factory of this crate in `registry`
+pub fn register(registry: &mut ProviderRegistry<ChatCompletionRequest>) {
+    registry.register("llamacpp", LlamaCppFactory);
+}
+
+/// Settings of a llama.cpp backend
+#[derive(Debug, Deserialize, Clone)]
+pub struct LlamaCppSettings {
+    /// Default sampling parameters, e.g. `{ mirostat = 2, repeat_penalty = 1.1 }`
+    #[serde(default)]
+    pub sampling: Map<String, Value>,
+}
+
+/// Factory for llama.cpp servers, with [`LlamaCppSettings`] as settings
+///
+/// Servers started with `--api-key` are authenticated with the token of the
+/// context, when set.
+pub struct LlamaCppFactory;
+
+#[async_trait]
+impl ProviderFactory<ChatCompletionRequest> for LlamaCppFactory {
+    fn requires_token(&self) -> bool {
+        false
+    }
+
+    async fn create_pipeline(
+        &self,
+        context: ProviderContext,
+    ) -> Result<Pipeline<ChatCompletionRequest>> {
+        let settings: LlamaCppSettings = context.settings()?;
+
+        let mut client = match &context.url_provider {
+            Some(url_provider) => create_chat_client_with_url_provider(url_provider.clone()),
+            None => create_chat_client(Some(&context.base_url)),
+        };
+        if let Some(token_provider) = &context.token_provider {
+            client = client.with_token_provider(token_provider.clone());
+        }
+        if let Some(client_provider) = &context.client_provider {
+            client = client.with_client_provider(client_provider.clone());
+        }
+        if let Some(auth_scheme) = &context.auth_scheme {
+            client = client.with_auth_scheme(auth_scheme.clone());
+        }
+        for (name, value) in &context.headers {
+            client = client.with_header(name, value);
+        }
+        client = client
+            .with_sampling(settings.sampling)
+            .with_capabilities(context.capabilities);
+        Ok(create_pipeline_with_client(vec![], client))
+    }
+}
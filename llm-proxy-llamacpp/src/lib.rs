@@ -0,0 +1,123 @@
+//! # LLM Proxy llama.cpp
+//!
+//! This crate serves `OpenAI`-format chat completion requests with models
+//! running on a [llama.cpp](https://github.com/ggml-org/llama.cpp) server
+//! (`llama-server`), mapping sampling parameters to llama.cpp's samplers and
+//! hiding the non-standard fields of its responses.
+//!
+//! ## Components
+//!
+//! ### Client
+//! The [`client`] module provides [`LlamaCppClient`], an `LLMClient` for
+//! [`ChatCompletionRequest`]s that talks to the OpenAI-like
+//! `/v1/chat/completions` endpoint of `llama-server`, both for streaming and
+//! non-streaming requests.
+//!
+//! ### Providers
+//! The [`providers`] module provides [`LlamaCppUrlProvider`] for the chat
+//! endpoint of a llama.cpp server.
+//!
+//! ### Factory
+//! The [`factory`] module registers the `llamacpp` provider in a
+//! [`ProviderRegistry`](llm_proxy_core::ProviderRegistry).
+//!
+//! ### Translate
+//! The [`translate`] module maps request parameters such as
+//! `repetition_penalty` to their llama.cpp names, and replaces the `timings`
+//! and other llama.cpp-specific fields of responses and streamed chunks with
+//! their `OpenAI` equivalents.
+//!
+//! ### Types
+//! The [`types`] module defines the llama.cpp timings and errors.
+//!
+//! ## Example Usage
+//!
+//! ```rust,no_run
+//! use llm_proxy_llamacpp::create_chat_pipeline;
+//!
+//! # async fn example() -> llm_proxy_core::Result<()> {
+//! // Talks to http://localhost:8080 by default
+//! let pipeline = create_chat_pipeline(vec![], None);
+//!
+//! let request = bytes::Bytes::from(
+//!     r#"{"model": "local", "messages": [{"role": "user", "content": "Hi"}], "mirostat": 2}"#,
+//! );
+//! let response = pipeline.execute(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Configuration
+//!
+//! ```toml
+//! [llm.llamacpp]
+//! provider = "llamacpp"
+//! type = "chat"
+//! base_url = "http://localhost:8080"
+//! token_env = ""
+//! supports_streaming = true
+//!
+//! [llm.llamacpp.additional_config.sampling]
+//! mirostat = 2
+//! repeat_penalty = 1.1
+//! ```
+
+pub mod client;
+pub mod factory;
+pub mod providers;
+pub mod translate;
+pub mod types;
+
+use std::sync::Arc;
+
+use llm_proxy_core::{Pipeline, Processor, ProcessorChain, UrlProvider};
+use llm_proxy_openai::{
+    providers::StaticClientProvider, ChatCompletionRequest, OpenAIRequestParser,
+};
+
+pub use client::LlamaCppClient;
+pub use providers::LlamaCppUrlProvider;
+pub use translate::{normalize_response, to_llamacpp_request, StreamTranslator};
+
+/// Create a new pipeline that serves `OpenAI` chat completion requests with a llama.cpp server.
+///
+/// # Arguments
+/// * `processors` - List of processors to apply to requests
+/// * `base_url` - Optional address of the llama.cpp server (default: "<http://localhost:8080>")
+#[must_use]
+pub fn create_chat_pipeline(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    base_url: Option<&str>,
+) -> Pipeline<ChatCompletionRequest> {
+    create_pipeline_with_client(processors, create_chat_client(base_url))
+}
+
+/// Create a llama.cpp client for the chat API.
+#[must_use]
+pub fn create_chat_client(base_url: Option<&str>) -> LlamaCppClient {
+    let url_provider =
+        Arc::new(base_url.map_or_else(LlamaCppUrlProvider::local, LlamaCppUrlProvider::new));
+    create_chat_client_with_url_provider(url_provider)
+}
+
+/// Create a llama.cpp client whose request URL is chosen by `url_provider`.
+#[must_use]
+pub fn create_chat_client_with_url_provider(url_provider: Arc<dyn UrlProvider>) -> LlamaCppClient {
+    let client_provider = Arc::new(StaticClientProvider::new());
+    LlamaCppClient::new(client_provider, url_provider)
+}
+
+/// Create a chat completion pipeline around an existing llama.cpp client.
+///
+/// Requests are parsed in the `OpenAI` format, so processors written for
+/// `OpenAI` requests apply unchanged.
+#[must_use]
+pub fn create_pipeline_with_client(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    llm_client: LlamaCppClient,
+) -> Pipeline<ChatCompletionRequest> {
+    let parser = Arc::new(OpenAIRequestParser::new());
+    let processor_chain = Arc::new(ProcessorChain::new(processors));
+
+    Pipeline::new(parser, processor_chain, Arc::new(llm_client))
+}
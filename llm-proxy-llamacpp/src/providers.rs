@@ -0,0 +1,60 @@
+use llm_proxy_core::{Result, UrlProvider};
+
+/// Provider that returns the URL of the chat endpoint of a llama.cpp server
+pub struct LlamaCppUrlProvider {
+    endpoint: String,
+}
+
+impl LlamaCppUrlProvider {
+    /// Create a provider for the chat endpoint of the `llama-server` at `base_url`
+    ///
+    /// The server address (`http://localhost:8080`), its OpenAI-like API
+    /// (`http://localhost:8080/v1`) and the full endpoint
+    /// (`http://localhost:8080/v1/chat/completions`) are accepted.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into();
+        let base_url = base_url.trim_end_matches('/');
+        let endpoint = if base_url.ends_with("/chat/completions") {
+            base_url.to_string()
+        } else if base_url.ends_with("/v1") {
+            format!("{base_url}/chat/completions")
+        } else {
+            format!("{base_url}/v1/chat/completions")
+        };
+        Self { endpoint }
+    }
+
+    /// Create a provider for a llama.cpp server on its default local port
+    #[must_use]
+    pub fn local() -> Self {
+        Self::new("http://localhost:8080/v1/chat/completions")
+    }
+}
+
+impl UrlProvider for LlamaCppUrlProvider {
+    fn get_url(&self) -> Result<String> {
+        Ok(self.endpoint.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_provider() {
+        for base_url in [
+            "http://localhost:8080",
+            "http://localhost:8080/",
+            "http://localhost:8080/v1",
+            "http://localhost:8080/v1/chat/completions",
+        ] {
+            assert_eq!(
+                LlamaCppUrlProvider::new(base_url)
+                    .get_url()
+                    .expect("Failed to get URL"),
+                "http://localhost:8080/v1/chat/completions"
+            );
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! Translation between the `OpenAI` chat completions format and the
+//! OpenAI-like chat endpoint of `llama-server`.
+//!
+//! - [`to_llamacpp_request`] maps parameters to the names of llama.cpp's
+//!   samplers and adds the backend's default sampling parameters.
+//! - [`normalize_response`] replaces the llama.cpp-specific fields of a chat
+//!   completion, such as `timings`, with their `OpenAI` equivalents.
+//! - [`StreamTranslator`] does the same for streamed chunks and turns the
+//!   `error:` events of `llama-server` into errors.
+
+use bytes::Bytes;
+use llm_proxy_core::{upstream::ChunkTranslator, Error, Result};
+use llm_proxy_openai::ChatCompletionRequest;
+use serde_json::{json, Map, Value};
+
+use crate::types::{ErrorDetail, ErrorResponse, Timings};
+
+/// Parameters of other APIs and the llama.cpp parameters they map to
+pub const PARAMETER_ALIASES: &[(&str, &str)] = &[
+    ("repetition_penalty", "repeat_penalty"),
+    ("mirostat_mode", "mirostat"),
+    ("typical", "typical_p"),
+];
+
+/// Fields of `llama-server` responses that are not part of the `OpenAI` API
+const NATIVE_FIELDS: &[&str] = &[
+    "timings",
+    "__verbose",
+    "generation_settings",
+    "tokens_predicted",
+    "tokens_evaluated",
+    "stop_type",
+    "id_slot",
+];
+
+/// Prepare an `OpenAI` chat completion request for `llama-server`.
+///
+/// Parameters known under another name, e.g. `repetition_penalty`, are
+/// renamed to their llama.cpp equivalent unless the request sets both, and
+/// `max_completion_tokens` is sent as `max_tokens`, which older servers
+/// require. Sampling parameters in `sampling`, e.g. `mirostat` or
+/// `repeat_penalty`, are added when the request doesn't set them.
+#[must_use]
+pub fn to_llamacpp_request(
+    mut request: ChatCompletionRequest,
+    sampling: &Map<String, Value>,
+) -> ChatCompletionRequest {
+    for (alias, name) in PARAMETER_ALIASES {
+        if let Some(value) = request.additional_params.remove(*alias) {
+            request
+                .additional_params
+                .entry((*name).to_string())
+                .or_insert(value);
+        }
+    }
+    if request.max_tokens.is_none() {
+        request.max_tokens = request.param("max_completion_tokens");
+    }
+    request.additional_params.remove("max_completion_tokens");
+
+    for (name, value) in sampling {
+        request
+            .additional_params
+            .entry(name.clone())
+            .or_insert_with(|| value.clone());
+    }
+    request
+}
+
+/// Move the `timings` of a response into `OpenAI` `usage`, unless it has usage already
+fn usage_from_timings(response: &mut Map<String, Value>, timings: Option<Value>) {
+    if response.get("usage").is_some_and(|usage| !usage.is_null()) {
+        return;
+    }
+    let Some(timings) = timings.and_then(|timings| serde_json::from_value::<Timings>(timings).ok())
+    else {
+        return;
+    };
+    response.insert(
+        "usage".to_string(),
+        json!({
+            "prompt_tokens": timings.prompt_n,
+            "completion_tokens": timings.predicted_n,
+            "total_tokens": timings.prompt_n + timings.predicted_n,
+        }),
+    );
+}
+
+/// Remove the llama.cpp-specific fields of a response, returning its `timings`
+fn strip_native_fields(response: &mut Map<String, Value>) -> Option<Value> {
+    let timings = response.remove("timings");
+    for field in NATIVE_FIELDS {
+        response.remove(*field);
+    }
+    timings
+}
+
+/// Replace the llama.cpp-specific fields of a chat completion with their
+/// `OpenAI` equivalents.
+///
+/// Servers that don't report `usage` have it derived from `timings`.
+pub fn normalize_response(response: &mut Value) {
+    if let Some(response) = response.as_object_mut() {
+        let timings = strip_native_fields(response);
+        usage_from_timings(response, timings);
+    }
+}
+
+/// Normalizes the server-sent events of a streamed `llama-server` chat completion.
+///
+/// Feed the raw bytes of the response to [`StreamTranslator::push`] as they
+/// arrive; events may be split across reads. Chunks are returned without
+/// llama.cpp-specific fields, and the last chunk carries `usage` derived from
+/// its `timings`. `error:` events, which `llama-server` sends instead of an
+/// HTTP error once streaming has started, are returned as errors.
+#[derive(Debug, Default)]
+pub struct StreamTranslator {
+    /// Bytes of an incomplete line
+    buffer: Vec<u8>,
+}
+
+impl StreamTranslator {
+    /// Create a translator for a new response
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { buffer: Vec::new() }
+    }
+
+    /// Translate the next bytes of the response
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an event can't be parsed or
+    /// reports an error.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = line.trim_ascii();
+            if let Some(data) = line.strip_prefix(b"error:") {
+                return Err(stream_error(data));
+            }
+            let Some(data) = line.strip_prefix(b"data:") else {
+                continue;
+            };
+            let data = data.trim_ascii();
+            if data == b"[DONE]" {
+                events.push(Bytes::from_static(b"data: [DONE]\n\n"));
+                continue;
+            }
+
+            let mut chunk: Value = serde_json::from_slice(data).map_err(|e| {
+                Error::LLMError(format!("Failed to parse llama.cpp stream event: {e}"))
+            })?;
+            if chunk.get("error").is_some() {
+                return Err(stream_error(data));
+            }
+            if let Some(object) = chunk.as_object_mut() {
+                let timings = strip_native_fields(object);
+                if is_finished(object) {
+                    usage_from_timings(object, timings);
+                }
+            }
+            events.push(Bytes::from(format!("data: {chunk}\n\n")));
+        }
+        Ok(events)
+    }
+}
+
+impl ChunkTranslator for StreamTranslator {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        Self::push(self, bytes)
+    }
+}
+
+/// Whether a chunk finishes its choice
+fn is_finished(chunk: &Map<String, Value>) -> bool {
+    chunk
+        .get("choices")
+        .and_then(Value::as_array)
+        .is_some_and(|choices| {
+            choices
+                .iter()
+                .any(|choice| !choice["finish_reason"].is_null())
+        })
+}
+
+/// The error reported by an `error:` event, or a `data:` event with an `error`
+fn stream_error(data: &[u8]) -> Error {
+    let message = serde_json::from_slice::<ErrorResponse>(data)
+        .map(|response| response.error.message)
+        .or_else(|_| serde_json::from_slice::<ErrorDetail>(data).map(|error| error.message))
+        .unwrap_or_else(|_| String::from_utf8_lossy(data).trim().to_string());
+    Error::LLMError(format!("llama.cpp stream error: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_translation() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "local",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "max_completion_tokens": 64,
+            "repetition_penalty": 1.2,
+            "mirostat_tau": 4.0,
+        }))
+        .expect("Invalid request");
+        let sampling = json!({"mirostat": 2, "mirostat_tau": 5.0});
+
+        let translated = to_llamacpp_request(
+            request,
+            sampling.as_object().expect("Sampling is an object"),
+        );
+        assert_eq!(translated.max_tokens, Some(64));
+        assert!(!translated
+            .additional_params
+            .contains_key("max_completion_tokens"));
+        assert_eq!(translated.additional_params["repeat_penalty"], 1.2);
+        assert_eq!(translated.additional_params["mirostat"], 2);
+        assert_eq!(translated.additional_params["mirostat_tau"], 4.0);
+    }
+
+    #[test]
+    fn test_stream_translation() {
+        let stream = concat!(
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":null}],\"object\":\"chat.completion.chunk\"}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}],\"object\":\"chat.completion.chunk\",\"timings\":{\"prompt_n\":5,\"predicted_n\":2}}\n\n",
+            "data: [DONE]\n\n",
+        );
+
+        // Split the stream in the middle of an event
+        let mut translator = StreamTranslator::new();
+        let (first, second) = stream.as_bytes().split_at(40);
+        let mut events = translator.push(first).expect("Failed to translate");
+        events.extend(translator.push(second).expect("Failed to translate"));
+
+        assert_eq!(events.len(), 3);
+        let last: Value = serde_json::from_slice(&events[1][6..]).expect("Invalid chunk");
+        assert!(last.get("timings").is_none());
+        assert_eq!(last["usage"]["total_tokens"], 7);
+        assert_eq!(events[2], "data: [DONE]\n\n");
+
+        let mut translator = StreamTranslator::new();
+        let error = translator
+            .push(
+                b"error: {\"code\":500,\"message\":\"context full\",\"type\":\"server_error\"}\n\n",
+            )
+            .expect_err("Errors are reported");
+        assert!(error.to_string().contains("context full"));
+    }
+}
@@ -0,0 +1,47 @@
+//! Types of the llama.cpp server (`llama-server`) that have no `OpenAI` equivalent.
+
+use llm_proxy_core::upstream::UpstreamError;
+use serde::{Deserialize, Serialize};
+
+/// Timings of a generation, reported by `llama-server` in place of `usage`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Timings {
+    /// Number of prompt tokens evaluated
+    #[serde(default)]
+    pub prompt_n: u32,
+    /// Milliseconds spent evaluating the prompt
+    #[serde(default)]
+    pub prompt_ms: Option<f64>,
+    /// Number of tokens generated
+    #[serde(default)]
+    pub predicted_n: u32,
+    /// Milliseconds spent generating
+    #[serde(default)]
+    pub predicted_ms: Option<f64>,
+}
+
+/// Error response from `llama-server`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    /// The error details
+    pub error: ErrorDetail,
+}
+
+impl UpstreamError for ErrorResponse {
+    fn message(self) -> String {
+        self.error.message
+    }
+}
+
+/// Details of an error reported by `llama-server`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorDetail {
+    /// The error message
+    pub message: String,
+    /// The HTTP status code of the error
+    #[serde(default)]
+    pub code: Option<u16>,
+    /// The kind of error, e.g. `invalid_request_error`
+    #[serde(default, rename = "type")]
+    pub error_type: Option<String>,
+}
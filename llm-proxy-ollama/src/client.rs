@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use llm_proxy_core::{
+    upstream, ClientProvider, Error, LLMClient, ProviderCapabilities, Result, UrlProvider,
+};
+use llm_proxy_openai::ChatCompletionRequest;
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::{
+    translate::{to_ollama_request, to_openai_response, StreamTranslator},
+    types::ErrorResponse,
+};
+
+/// Name of the upstream service in errors
+const UPSTREAM: &str = "Ollama";
+
+/// Ollama implementation of `LLMClient` for `OpenAI` chat completion requests.
+///
+/// Requests are translated into the format of Ollama's `/api/chat` endpoint
+/// and responses are translated back, so clients written against the `OpenAI`
+/// API can use local models without changes. Ollama needs no API key.
+#[derive(Clone)]
+pub struct OllamaClient {
+    client: Arc<dyn ClientProvider>,
+    url: Arc<dyn UrlProvider>,
+    headers: Vec<(String, String)>,
+    capabilities: ProviderCapabilities,
+}
+
+impl OllamaClient {
+    /// Create a new Ollama client with the given providers
+    pub fn new(
+        client_provider: Arc<dyn ClientProvider>,
+        url_provider: Arc<dyn UrlProvider>,
+    ) -> Self {
+        Self {
+            client: client_provider,
+            url: url_provider,
+            headers: Vec::new(),
+            capabilities: ProviderCapabilities::default(),
+        }
+    }
+
+    /// Replace the provider of the HTTP client used for upstream requests
+    #[must_use]
+    pub fn with_client_provider(mut self, client_provider: Arc<dyn ClientProvider>) -> Self {
+        self.client = client_provider;
+        self
+    }
+
+    /// Send a static header with every request, e.g. for a reverse proxy in front of Ollama
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Declare what the upstream backend supports, e.g. its models
+    #[must_use]
+    pub fn with_capabilities(mut self, capabilities: ProviderCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+}
+
+#[async_trait]
+impl LLMClient<ChatCompletionRequest> for OllamaClient {
+    async fn execute(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        // 1. Translate the request
+        let ollama_request = to_ollama_request(&request)?;
+
+        // 2. Get dependencies
+        let client = self
+            .client
+            .get_client()
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get HTTP client: {e}")))?;
+        let url = self.url.get_url()?;
+
+        // 3. Send request
+        let mut builder = client.post(url);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let builder = builder.json(&ollama_request);
+        let response =
+            upstream::send_request::<ErrorResponse>(UPSTREAM, builder, None, None).await?;
+
+        // 4. Translate the response based on streaming flag
+        let (tx, rx) = mpsc::channel(100);
+        let stream = request.stream;
+        info!("The request is streaming: {}", stream);
+        tokio::spawn(async move {
+            if stream {
+                upstream::forward_stream(UPSTREAM, response, StreamTranslator::new(), &tx).await;
+            } else {
+                upstream::forward_response(UPSTREAM, response, to_openai_response, &tx).await;
+            }
+        });
+
+        Ok(rx)
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.capabilities.clone()
+    }
+}
@@ -0,0 +1,42 @@
+//! Provider factory for Ollama servers, registered as `ollama`.
+
+use async_trait::async_trait;
+use llm_proxy_core::{Pipeline, ProviderContext, ProviderFactory, ProviderRegistry, Result};
+use llm_proxy_openai::ChatCompletionRequest;
+
+use crate::{
+    create_chat_client, create_chat_client_with_url_provider, create_pipeline_with_client,
+};
+
+/// Register the factory of this crate in `registry`
+pub fn register(registry: &mut ProviderRegistry<ChatCompletionRequest>) {
+    registry.register("ollama", OllamaFactory);
+}
+
+/// Factory for Ollama servers, which need no API key
+pub struct OllamaFactory;
+
+#[async_trait]
+impl ProviderFactory<ChatCompletionRequest> for OllamaFactory {
+    fn requires_token(&self) -> bool {
+        false
+    }
+
+    async fn create_pipeline(
+        &self,
+        context: ProviderContext,
+    ) -> Result<Pipeline<ChatCompletionRequest>> {
+        let mut client = match &context.url_provider {
+            Some(url_provider) => create_chat_client_with_url_provider(url_provider.clone()),
+            None => create_chat_client(Some(&context.base_url)),
+        };
+        if let Some(client_provider) = &context.client_provider {
+            client = client.with_client_provider(client_provider.clone());
+        }
+        for (name, value) in &context.headers {
+            client = client.with_header(name, value);
+        }
+        client = client.with_capabilities(context.capabilities);
+        Ok(create_pipeline_with_client(vec![], client))
+    }
+}
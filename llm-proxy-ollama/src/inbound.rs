@@ -0,0 +1,461 @@
+//! Serving Ollama clients from `OpenAI` chat completion backends.
+//!
+//! The reverse of [`translate`](crate::translate), for tools speaking the
+//! Ollama chat API to a proxy whose pipelines use the `OpenAI` format:
+//!
+//! - [`to_openai_request`] converts an inbound Ollama chat request into an
+//!   `OpenAI` chat completion request.
+//! - [`to_ollama_response`] converts an `OpenAI` chat completion into an
+//!   Ollama chat response.
+//! - [`ChunkTranslator`] converts the server-sent events of a streamed chat
+//!   completion into Ollama's newline-delimited JSON.
+
+use std::{collections::VecDeque, time::SystemTime};
+
+use bytes::Bytes;
+use serde_json::{json, Map, Value};
+
+use crate::types::{ChatRequest, OllamaMessage};
+
+/// Convert an Ollama chat request into an `OpenAI` chat completion request.
+///
+/// Model options become sampling parameters, `num_predict` becomes
+/// `max_tokens` and the output `format` becomes `response_format`. Ollama
+/// doesn't identify tool calls, so they are given IDs, which the `tool`
+/// messages following them refer to in order.
+#[must_use]
+pub fn to_openai_request(request: ChatRequest) -> Value {
+    let mut calls = 0;
+    let mut pending = VecDeque::new();
+    let messages: Vec<Value> = request
+        .messages
+        .into_iter()
+        .map(|message| to_openai_message(message, &mut calls, &mut pending))
+        .collect();
+
+    let mut body = Map::new();
+    body.insert("model".to_string(), json!(request.model));
+    body.insert("messages".to_string(), Value::Array(messages));
+    body.insert("stream".to_string(), json!(request.stream));
+    if !request.tools.is_empty() {
+        body.insert("tools".to_string(), Value::Array(request.tools));
+    }
+    match request.format {
+        Some(Value::String(format)) if format == "json" => {
+            body.insert(
+                "response_format".to_string(),
+                json!({ "type": "json_object" }),
+            );
+        }
+        Some(schema @ Value::Object(_)) => {
+            body.insert(
+                "response_format".to_string(),
+                json!({ "type": "json_schema", "json_schema": { "name": "response", "schema": schema } }),
+            );
+        }
+        _ => {}
+    }
+    let options = request.options;
+    let parameters = [
+        ("temperature", options.temperature.map(Value::from)),
+        ("top_p", options.top_p.map(Value::from)),
+        ("max_tokens", options.num_predict.map(Value::from)),
+        ("seed", options.seed.map(Value::from)),
+        (
+            "presence_penalty",
+            options.presence_penalty.map(Value::from),
+        ),
+        (
+            "frequency_penalty",
+            options.frequency_penalty.map(Value::from),
+        ),
+        (
+            "stop",
+            (!options.stop.is_empty()).then(|| json!(options.stop)),
+        ),
+    ];
+    for (name, value) in parameters {
+        if let Some(value) = value {
+            body.insert(name.to_string(), value);
+        }
+    }
+    Value::Object(body)
+}
+
+/// Convert an Ollama message, numbering its tool calls after the `calls` so far
+fn to_openai_message(
+    message: OllamaMessage,
+    calls: &mut usize,
+    pending: &mut VecDeque<String>,
+) -> Value {
+    let content = if message.images.is_empty() {
+        json!(message.content)
+    } else {
+        let mut parts = vec![json!({ "type": "text", "text": message.content })];
+        parts.extend(message.images.iter().map(|image| {
+            json!({
+                "type": "image_url",
+                "image_url": { "url": format!("data:{};base64,{image}", media_type(image)) },
+            })
+        }));
+        Value::Array(parts)
+    };
+    let mut openai = json!({ "role": message.role, "content": content });
+    if message.role == "tool" {
+        // Results of calls that weren't made get an ID of their own
+        let id = pending.pop_front().unwrap_or_else(|| {
+            *calls += 1;
+            format!("call_{calls}")
+        });
+        openai["tool_call_id"] = json!(id);
+    }
+    if !message.tool_calls.is_empty() {
+        pending.clear();
+        let tool_calls = message
+            .tool_calls
+            .into_iter()
+            .map(|call| {
+                *calls += 1;
+                let id = format!("call_{calls}");
+                pending.push_back(id.clone());
+                json!({
+                    "id": id,
+                    "type": "function",
+                    "function": {
+                        "name": call.function.name,
+                        "arguments": call.function.arguments.to_string(),
+                    },
+                })
+            })
+            .collect();
+        openai["tool_calls"] = Value::Array(tool_calls);
+    }
+    openai
+}
+
+/// MIME type of a base64 encoded image, by its first bytes
+fn media_type(image: &str) -> &'static str {
+    if image.starts_with("/9j/") {
+        "image/jpeg"
+    } else if image.starts_with("R0lGOD") {
+        "image/gif"
+    } else if image.starts_with("UklGR") {
+        "image/webp"
+    } else {
+        "image/png"
+    }
+}
+
+/// The Ollama done reason for an `OpenAI` finish reason
+fn done_reason(finish_reason: &str) -> &'static str {
+    match finish_reason {
+        "length" => "length",
+        _ => "stop",
+    }
+}
+
+/// The current time in the format of Ollama's `created_at`
+fn created_at() -> String {
+    humantime::format_rfc3339_nanos(SystemTime::now()).to_string()
+}
+
+/// The Ollama tool call of an `OpenAI` function call, with its JSON arguments parsed
+fn tool_call(name: &Value, arguments: &str) -> Value {
+    let arguments: Value = serde_json::from_str(arguments).unwrap_or_else(|_| json!({}));
+    json!({ "function": { "name": name, "arguments": arguments } })
+}
+
+/// Convert an `OpenAI` chat completion into an Ollama chat response
+#[must_use]
+pub fn to_ollama_response(completion: &Value) -> Value {
+    let choice = &completion["choices"][0];
+    let mut message = json!({
+        "role": "assistant",
+        "content": choice["message"]["content"].as_str().unwrap_or_default(),
+    });
+    let tool_calls: Vec<Value> = choice["message"]["tool_calls"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|call| {
+            let arguments = call["function"]["arguments"].as_str().unwrap_or_default();
+            tool_call(&call["function"]["name"], arguments)
+        })
+        .collect();
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+    json!({
+        "model": completion["model"],
+        "created_at": created_at(),
+        "message": message,
+        "done": true,
+        "done_reason": done_reason(choice["finish_reason"].as_str().unwrap_or_default()),
+        "prompt_eval_count": completion["usage"]["prompt_tokens"].as_u64().unwrap_or_default(),
+        "eval_count": completion["usage"]["completion_tokens"].as_u64().unwrap_or_default(),
+    })
+}
+
+/// A tool call being streamed
+#[derive(Debug)]
+struct PendingCall {
+    name: Value,
+    arguments: String,
+}
+
+/// Converts the chunks of a streamed `OpenAI` chat completion into Ollama's
+/// newline-delimited JSON.
+///
+/// Feed the raw bytes of the stream to [`ChunkTranslator::push`] as they
+/// arrive; chunks may be split across reads. Text is sent as it arrives,
+/// while tool calls, which Ollama sends whole, are sent once their arguments
+/// are complete. The response ends with the `data: [DONE]` line, or with
+/// [`ChunkTranslator::finish`] for streams that end without one.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_ollama::inbound::ChunkTranslator;
+///
+/// let mut translator = ChunkTranslator::new();
+/// let lines = translator.push(
+///     b"data: {\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"}}]}\n\n",
+/// );
+/// assert_eq!(lines.len(), 1);
+/// ```
+#[derive(Debug, Default)]
+pub struct ChunkTranslator {
+    model: Value,
+    finished: bool,
+    /// Tool calls by their index in the `OpenAI` chunks
+    calls: Vec<PendingCall>,
+    done_reason: Option<&'static str>,
+    prompt_eval_count: u64,
+    eval_count: u64,
+    /// Bytes of an incomplete line
+    buffer: Vec<u8>,
+}
+
+impl ChunkTranslator {
+    /// Create a translator for a new response
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Translate the next bytes of the stream
+    ///
+    /// Lines that aren't chunks, such as comments, are skipped.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<Bytes> {
+        self.buffer.extend_from_slice(bytes);
+        let mut lines = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:").map(str::trim) else {
+                continue;
+            };
+            if data == "[DONE]" {
+                lines.extend(self.finish());
+            } else if let Ok(chunk) = serde_json::from_str::<Value>(data) {
+                lines.extend(self.translate(&chunk));
+            }
+        }
+        lines
+    }
+
+    /// End the response, if it hasn't ended yet
+    pub fn finish(&mut self) -> Vec<Bytes> {
+        if self.finished {
+            return Vec::new();
+        }
+        self.finished = true;
+        let mut lines = Vec::new();
+        if !self.calls.is_empty() {
+            let tool_calls: Vec<Value> = self
+                .calls
+                .drain(..)
+                .map(|call| tool_call(&call.name, &call.arguments))
+                .collect();
+            lines.push(self.line(&json!({
+                "role": "assistant",
+                "content": "",
+                "tool_calls": tool_calls,
+            })));
+        }
+        lines.push(to_line(&json!({
+            "model": self.model,
+            "created_at": created_at(),
+            "message": { "role": "assistant", "content": "" },
+            "done": true,
+            "done_reason": self.done_reason.unwrap_or("stop"),
+            "prompt_eval_count": self.prompt_eval_count,
+            "eval_count": self.eval_count,
+        })));
+        lines
+    }
+
+    /// An error line for a stream that failed
+    #[must_use]
+    pub fn error(message: &str) -> Bytes {
+        to_line(&json!({ "error": message }))
+    }
+
+    fn translate(&mut self, chunk: &Value) -> Option<Bytes> {
+        if let Some(usage) = chunk.get("usage").filter(|usage| usage.is_object()) {
+            self.prompt_eval_count = usage["prompt_tokens"]
+                .as_u64()
+                .unwrap_or(self.prompt_eval_count);
+            self.eval_count = usage["completion_tokens"]
+                .as_u64()
+                .unwrap_or(self.eval_count);
+        }
+        if self.model.is_null() {
+            self.model = chunk["model"].clone();
+        }
+        let choice = chunk["choices"].get(0)?;
+        if let Some(finish_reason) = choice["finish_reason"].as_str() {
+            self.done_reason = Some(done_reason(finish_reason));
+        }
+        for call in choice["delta"]["tool_calls"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            let index =
+                usize::try_from(call["index"].as_u64().unwrap_or_default()).unwrap_or_default();
+            if self.calls.len() <= index {
+                self.calls.resize_with(index + 1, || PendingCall {
+                    name: Value::Null,
+                    arguments: String::new(),
+                });
+            }
+            let pending = &mut self.calls[index];
+            if let Some(name) = call["function"].get("name").filter(|name| name.is_string()) {
+                pending.name = name.clone();
+            }
+            if let Some(arguments) = call["function"]["arguments"].as_str() {
+                pending.arguments.push_str(arguments);
+            }
+        }
+        let content = choice["delta"]["content"]
+            .as_str()
+            .filter(|content| !content.is_empty())?;
+        Some(self.line(&json!({ "role": "assistant", "content": content })))
+    }
+
+    /// A line of the response with `message`
+    fn line(&self, message: &Value) -> Bytes {
+        to_line(&json!({
+            "model": self.model,
+            "created_at": created_at(),
+            "message": message,
+            "done": false,
+        }))
+    }
+}
+
+/// A line of newline-delimited JSON
+fn to_line(value: &Value) -> Bytes {
+    Bytes::from(format!("{value}\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_translation() {
+        let request: ChatRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "system", "content": "Be brief." },
+                { "role": "user", "content": "What is this?", "images": ["iVBORw0KGgo"] },
+                { "role": "assistant", "content": "", "tool_calls": [
+                    { "function": { "name": "lookup", "arguments": { "q": "cat" } } },
+                ] },
+                { "role": "tool", "content": "A cat" },
+            ],
+            "format": "json",
+            "options": { "temperature": 0.5, "num_predict": 64, "stop": ["END"] },
+        }))
+        .expect("Invalid request");
+
+        let body = to_openai_request(request);
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["max_tokens"], 64);
+        assert_eq!(body["temperature"], 0.5);
+        assert_eq!(body["stop"], json!(["END"]));
+        assert_eq!(body["response_format"]["type"], "json_object");
+        let messages = &body["messages"];
+        assert_eq!(messages[0]["content"], "Be brief.");
+        assert_eq!(
+            messages[1]["content"][1]["image_url"]["url"],
+            "data:image/png;base64,iVBORw0KGgo"
+        );
+        assert_eq!(messages[2]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(
+            messages[2]["tool_calls"][0]["function"]["arguments"],
+            "{\"q\":\"cat\"}"
+        );
+        assert_eq!(messages[3]["tool_call_id"], "call_1");
+    }
+
+    #[test]
+    fn test_response_translation() {
+        let response = to_ollama_response(&json!({
+            "id": "chatcmpl-1",
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": "Hello" },
+                "finish_reason": "length",
+            }],
+            "usage": { "prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15 },
+        }));
+        assert_eq!(response["model"], "gpt-4o");
+        assert_eq!(response["message"]["content"], "Hello");
+        assert_eq!(response["done"], true);
+        assert_eq!(response["done_reason"], "length");
+        assert_eq!(response["prompt_eval_count"], 10);
+        assert_eq!(response["eval_count"], 5);
+    }
+
+    #[test]
+    fn test_stream_translation() {
+        let stream = concat!(
+            "data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n\n",
+            "data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Let me check.\"}}]}\n\n",
+            "data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"get_weather\",\"arguments\":\"{\\\"city\\\":\"}}]}}]}\n\n",
+            "data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\"Paris\\\"}\"}}]}}]}\n\n",
+            "data: {\"id\":\"c1\",\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"tool_calls\"}],\"usage\":{\"prompt_tokens\":10,\"completion_tokens\":7}}\n\n",
+            "data: [DONE]\n\n",
+        );
+
+        // Split the stream in the middle of a chunk
+        let mut translator = ChunkTranslator::new();
+        let (first, second) = stream.as_bytes().split_at(150);
+        let mut lines = translator.push(first);
+        lines.extend(translator.push(second));
+        lines.extend(translator.finish());
+
+        let lines: Vec<Value> = lines
+            .iter()
+            .map(|line| {
+                assert!(line.ends_with(b"\n"));
+                serde_json::from_slice(line).expect("Invalid line")
+            })
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["model"], "gpt-4o");
+        assert_eq!(lines[0]["message"]["content"], "Let me check.");
+        assert_eq!(lines[0]["done"], false);
+        assert_eq!(
+            lines[1]["message"]["tool_calls"][0]["function"]["arguments"]["city"],
+            "Paris"
+        );
+        assert_eq!(lines[2]["done"], true);
+        assert_eq!(lines[2]["done_reason"], "stop");
+        assert_eq!(lines[2]["eval_count"], 7);
+    }
+}
@@ -0,0 +1,124 @@
+//! # LLM Proxy Ollama
+//!
+//! This crate serves `OpenAI`-format chat completion requests with models
+//! running on a local [Ollama](https://ollama.com) server, so the proxy can
+//! front local models during development.
+//!
+//! ## Components
+//!
+//! ### Client
+//! The [`client`] module provides [`OllamaClient`], an `LLMClient` for
+//! [`ChatCompletionRequest`]s that talks to Ollama's `/api/chat` endpoint
+//! and answers in the `OpenAI` format, both for streaming and non-streaming
+//! requests.
+//!
+//! ### Providers
+//! The [`providers`] module provides [`OllamaUrlProvider`] for the chat
+//! endpoint of an Ollama server.
+//!
+//! ### Inbound
+//! The [`inbound`] module serves tools speaking the Ollama chat API from
+//! `OpenAI` chat completion pipelines, converting their requests, responses
+//! and streams.
+//!
+//! ### Factory
+//! The [`factory`] module registers the `ollama` provider in a
+//! [`ProviderRegistry`](llm_proxy_core::ProviderRegistry).
+//!
+//! ### Translate
+//! The [`translate`] module converts requests and responses between the two
+//! formats, and turns Ollama's newline-delimited JSON stream into
+//! server-sent events.
+//!
+//! ### Types
+//! The [`types`] module defines the Ollama chat requests and responses.
+//!
+//! ## Example Usage
+//!
+//! ```rust,no_run
+//! use llm_proxy_ollama::create_chat_pipeline;
+//!
+//! # async fn example() -> llm_proxy_core::Result<()> {
+//! // Talks to http://localhost:11434 by default
+//! let pipeline = create_chat_pipeline(vec![], None);
+//!
+//! let request = bytes::Bytes::from(
+//!     r#"{"model": "llama3.2", "messages": [{"role": "user", "content": "Hi"}]}"#,
+//! );
+//! let response = pipeline.execute(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Configuration
+//!
+//! ```toml
+//! [llm.local]
+//! provider = "ollama"
+//! type = "chat"
+//! base_url = "http://localhost:11434"
+//! token_env = ""
+//! supports_streaming = true
+//! ```
+
+pub mod client;
+pub mod factory;
+pub mod inbound;
+pub mod providers;
+pub mod translate;
+pub mod types;
+
+use std::sync::Arc;
+
+use llm_proxy_core::{Pipeline, Processor, ProcessorChain, UrlProvider};
+use llm_proxy_openai::{
+    providers::StaticClientProvider, ChatCompletionRequest, OpenAIRequestParser,
+};
+
+pub use client::OllamaClient;
+pub use inbound::{to_ollama_response, to_openai_request, ChunkTranslator};
+pub use providers::OllamaUrlProvider;
+pub use translate::{to_ollama_request, to_openai_response, StreamTranslator};
+
+/// Create a new pipeline that serves `OpenAI` chat completion requests with Ollama models.
+///
+/// # Arguments
+/// * `processors` - List of processors to apply to requests
+/// * `base_url` - Optional address of the Ollama server (default: "<http://localhost:11434>")
+#[must_use]
+pub fn create_chat_pipeline(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    base_url: Option<&str>,
+) -> Pipeline<ChatCompletionRequest> {
+    create_pipeline_with_client(processors, create_chat_client(base_url))
+}
+
+/// Create an Ollama client for the chat API.
+#[must_use]
+pub fn create_chat_client(base_url: Option<&str>) -> OllamaClient {
+    let url_provider =
+        Arc::new(base_url.map_or_else(OllamaUrlProvider::local, OllamaUrlProvider::new));
+    create_chat_client_with_url_provider(url_provider)
+}
+
+/// Create an Ollama client whose request URL is chosen by `url_provider`.
+#[must_use]
+pub fn create_chat_client_with_url_provider(url_provider: Arc<dyn UrlProvider>) -> OllamaClient {
+    let client_provider = Arc::new(StaticClientProvider::new());
+    OllamaClient::new(client_provider, url_provider)
+}
+
+/// Create a chat completion pipeline around an existing Ollama client.
+///
+/// Requests are parsed in the `OpenAI` format, so processors written for
+/// `OpenAI` requests apply unchanged.
+#[must_use]
+pub fn create_pipeline_with_client(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    llm_client: OllamaClient,
+) -> Pipeline<ChatCompletionRequest> {
+    let parser = Arc::new(OpenAIRequestParser::new());
+    let processor_chain = Arc::new(ProcessorChain::new(processors));
+
+    Pipeline::new(parser, processor_chain, Arc::new(llm_client))
+}
@@ -0,0 +1,56 @@
+use llm_proxy_core::{Result, UrlProvider};
+
+/// Provider that returns the URL of an Ollama chat API endpoint
+pub struct OllamaUrlProvider {
+    endpoint: String,
+}
+
+impl OllamaUrlProvider {
+    /// Create a provider for the chat API of the Ollama server at `base_url`
+    ///
+    /// Both the server address (`http://localhost:11434`) and the full
+    /// endpoint (`http://localhost:11434/api/chat`) are accepted.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into();
+        let base_url = base_url.trim_end_matches('/');
+        let endpoint = if base_url.ends_with("/api/chat") {
+            base_url.to_string()
+        } else {
+            format!("{base_url}/api/chat")
+        };
+        Self { endpoint }
+    }
+
+    /// Create a provider for an Ollama server on its default local port
+    #[must_use]
+    pub fn local() -> Self {
+        Self::new("http://localhost:11434/api/chat")
+    }
+}
+
+impl UrlProvider for OllamaUrlProvider {
+    fn get_url(&self) -> Result<String> {
+        Ok(self.endpoint.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_provider() {
+        for base_url in [
+            "http://localhost:11434",
+            "http://localhost:11434/",
+            "http://localhost:11434/api/chat",
+        ] {
+            assert_eq!(
+                OllamaUrlProvider::new(base_url)
+                    .get_url()
+                    .expect("Failed to get URL"),
+                "http://localhost:11434/api/chat"
+            );
+        }
+    }
+}
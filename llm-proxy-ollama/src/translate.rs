@@ -0,0 +1,420 @@
+//! Translation between the `OpenAI` chat completions format and the Ollama
+//! chat API.
+//!
+//! - [`to_ollama_request`] converts an inbound `OpenAI` chat completion
+//!   request into an Ollama chat request.
+//! - [`to_openai_response`] converts an Ollama chat response into an `OpenAI`
+//!   chat completion.
+//! - [`StreamTranslator`] converts the newline-delimited JSON of a streaming
+//!   Ollama response into `OpenAI` chat completion chunks.
+
+use bytes::Bytes;
+use llm_proxy_core::{unix_now, upstream::ChunkTranslator, Error, Result};
+use llm_proxy_openai::{ChatCompletionRequest, ContentPart, Message, MessageContent};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::types::{
+    ChatRequest, ChatResponse, ErrorResponse, FunctionCall, OllamaMessage, Options, ToolCall,
+};
+
+/// The `stop` parameter, either a single sequence or a list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stop {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Convert an `OpenAI` chat completion request into an Ollama chat request.
+///
+/// Sampling parameters become model options, `max_tokens` becomes
+/// `num_predict` and `response_format` becomes the output `format`. Images
+/// are sent inline, without their `data:` URL prefix.
+///
+/// # Errors
+///
+/// This function will return an error if an image is referenced by URL,
+/// which Ollama can't fetch, or if tool call arguments are not valid JSON.
+pub fn to_ollama_request(request: &ChatCompletionRequest) -> Result<ChatRequest> {
+    let messages = request
+        .messages
+        .iter()
+        .map(to_ollama_message)
+        .collect::<Result<_>>()?;
+
+    let mut tools: Vec<Value> = request.param("tools").unwrap_or_default();
+    tools.extend(
+        request
+            .functions
+            .iter()
+            .flatten()
+            .map(|function| json!({"type": "function", "function": function})),
+    );
+
+    let format = request
+        .additional_params
+        .get("response_format")
+        .and_then(|format| match format["type"].as_str() {
+            Some("json_object") => Some(json!("json")),
+            Some("json_schema") => Some(format["json_schema"]["schema"].clone()),
+            _ => None,
+        });
+
+    Ok(ChatRequest {
+        model: request.model.clone(),
+        messages,
+        stream: request.stream,
+        tools,
+        format,
+        options: Options {
+            temperature: request.temperature,
+            top_p: request.param("top_p"),
+            num_predict: request
+                .max_tokens
+                .or_else(|| request.param("max_completion_tokens")),
+            stop: match request.param("stop") {
+                Some(Stop::One(stop)) => vec![stop],
+                Some(Stop::Many(stops)) => stops,
+                None => Vec::new(),
+            },
+            seed: request.param("seed"),
+            presence_penalty: request.param("presence_penalty"),
+            frequency_penalty: request.param("frequency_penalty"),
+        },
+    })
+}
+
+/// Convert an `OpenAI` message into an Ollama message
+fn to_ollama_message(message: &Message) -> Result<OllamaMessage> {
+    let mut images = Vec::new();
+    let content = match &message.content {
+        None => String::new(),
+        Some(MessageContent::Text(text)) => text.clone(),
+        Some(MessageContent::Parts(parts)) => {
+            let mut text = Vec::new();
+            for part in parts {
+                match part {
+                    ContentPart::Text { text: part } => text.push(part.as_str()),
+                    ContentPart::ImageUrl { image_url } => {
+                        let (_, data) = image_url
+                            .url
+                            .strip_prefix("data:")
+                            .and_then(|data_url| data_url.split_once(";base64,"))
+                            .ok_or_else(|| {
+                                Error::Unsupported(
+                                    "Ollama only accepts images sent as base64 data URLs"
+                                        .to_string(),
+                                )
+                            })?;
+                        images.push(data.to_string());
+                    }
+                    ContentPart::Other(_) => {}
+                }
+            }
+            text.join("\n")
+        }
+    };
+
+    let calls = message
+        .tool_calls
+        .iter()
+        .flatten()
+        .map(|call| &call.function)
+        .chain(&message.function_call);
+    let tool_calls = calls
+        .map(|call| {
+            let arguments = if call.arguments.trim().is_empty() {
+                json!({})
+            } else {
+                serde_json::from_str(&call.arguments)
+                    .map_err(|e| Error::ParseError(format!("Invalid tool call arguments: {e}")))?
+            };
+            Ok(ToolCall {
+                function: FunctionCall {
+                    name: call.name.clone(),
+                    arguments,
+                },
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(OllamaMessage {
+        // Legacy function results are tool results
+        role: match message.role.as_str() {
+            "function" => "tool".to_string(),
+            "developer" => "system".to_string(),
+            role => role.to_string(),
+        },
+        content,
+        images,
+        tool_calls,
+    })
+}
+
+/// `OpenAI` tool calls for the tool calls of an Ollama message.
+///
+/// Ollama doesn't assign IDs to tool calls, so they are generated.
+fn to_openai_tool_calls(tool_calls: Vec<ToolCall>, first_index: usize) -> Vec<Value> {
+    tool_calls
+        .into_iter()
+        .enumerate()
+        .map(|(index, call)| {
+            json!({
+                "index": first_index + index,
+                "id": format!("call_{}", uuid::Uuid::new_v4().simple()),
+                "type": "function",
+                "function": {
+                    "name": call.function.name,
+                    "arguments": call.function.arguments.to_string(),
+                },
+            })
+        })
+        .collect()
+}
+
+/// The `OpenAI` finish reason of a finished response
+fn finish_reason(done_reason: Option<&str>, called_tools: bool) -> &'static str {
+    match done_reason {
+        Some("length") => "length",
+        _ if called_tools => "tool_calls",
+        _ => "stop",
+    }
+}
+
+/// A new completion ID; Ollama responses have none
+fn completion_id() -> String {
+    format!("chatcmpl-{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Convert an Ollama chat response into an `OpenAI` chat completion
+#[must_use]
+pub fn to_openai_response(response: ChatResponse) -> Value {
+    let called_tools = !response.message.tool_calls.is_empty();
+    let mut message = json!({
+        "role": "assistant",
+        "content": response.message.content,
+    });
+    if called_tools {
+        message["tool_calls"] = Value::Array(to_openai_tool_calls(response.message.tool_calls, 0));
+    }
+
+    json!({
+        "id": completion_id(),
+        "object": "chat.completion",
+        "created": unix_now(),
+        "model": response.model,
+        "choices": [{
+            "index": 0,
+            "message": message,
+            "finish_reason": finish_reason(response.done_reason.as_deref(), called_tools),
+        }],
+        "usage": {
+            "prompt_tokens": response.prompt_eval_count,
+            "completion_tokens": response.eval_count,
+            "total_tokens": response.prompt_eval_count + response.eval_count,
+        },
+    })
+}
+
+/// Converts a streaming Ollama response into `OpenAI` chat completion chunks.
+///
+/// Feed the raw bytes of the response to [`StreamTranslator::push`] as they
+/// arrive; lines may be split across reads. The translated chunks are
+/// returned as server-sent events in the format of the `OpenAI` API, ending
+/// with `data: [DONE]`.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_ollama::translate::StreamTranslator;
+///
+/// let mut translator = StreamTranslator::new();
+/// let events = translator
+///     .push(b"{\"model\":\"llama3.2\",\"message\":{\"role\":\"assistant\",\"content\":\"Hi\"},\"done\":false}\n")
+///     .expect("valid line");
+/// assert_eq!(events.len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct StreamTranslator {
+    id: String,
+    created: u64,
+    /// Whether the role was sent in a chunk already
+    started: bool,
+    /// Number of tool calls sent so far
+    tool_calls: usize,
+    /// Bytes of an incomplete line
+    buffer: Vec<u8>,
+}
+
+impl Default for StreamTranslator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamTranslator {
+    /// Create a translator for a new response
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            id: completion_id(),
+            created: unix_now(),
+            started: false,
+            tool_calls: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Translate the next bytes of the response
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a line can't be parsed or
+    /// reports an error.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            if let Ok(error) = serde_json::from_slice::<ErrorResponse>(&line) {
+                return Err(Error::LLMError(format!(
+                    "Ollama stream error: {}",
+                    error.error
+                )));
+            }
+            let response: ChatResponse = serde_json::from_slice(&line)
+                .map_err(|e| Error::LLMError(format!("Failed to parse Ollama stream line: {e}")))?;
+            for data in self.translate(response) {
+                events.push(Bytes::from(format!("data: {data}\n\n")));
+            }
+        }
+        Ok(events)
+    }
+
+    /// Translate one line into the data of `OpenAI` server-sent events
+    fn translate(&mut self, response: ChatResponse) -> Vec<String> {
+        let mut delta = json!({});
+        if !self.started {
+            self.started = true;
+            delta["role"] = json!("assistant");
+        }
+        if !response.message.content.is_empty() {
+            delta["content"] = json!(response.message.content);
+        }
+        if !response.message.tool_calls.is_empty() {
+            let calls = response.message.tool_calls.len();
+            delta["tool_calls"] = Value::Array(to_openai_tool_calls(
+                response.message.tool_calls,
+                self.tool_calls,
+            ));
+            self.tool_calls += calls;
+        }
+
+        let mut chunks = Vec::new();
+        if delta.as_object().is_some_and(|delta| !delta.is_empty()) {
+            chunks.push(self.chunk(&response.model, &delta, None).to_string());
+        }
+        if response.done {
+            let reason = finish_reason(response.done_reason.as_deref(), self.tool_calls > 0);
+            chunks.push(
+                self.chunk(&response.model, &json!({}), Some(reason))
+                    .to_string(),
+            );
+            chunks.push("[DONE]".to_string());
+        }
+        chunks
+    }
+
+    /// An `OpenAI` chat completion chunk
+    fn chunk(&self, model: &str, delta: &Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        })
+    }
+}
+
+impl ChunkTranslator for StreamTranslator {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        Self::push(self, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_translation() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "llava",
+            "messages": [
+                {"role": "user", "content": [
+                    {"type": "text", "text": "What is this?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBOR"}},
+                ]},
+                {"role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "lookup", "arguments": "{\"q\":\"cat\"}"},
+                }]},
+            ],
+            "max_tokens": 128,
+            "stop": "END",
+            "response_format": {"type": "json_object"},
+        }))
+        .expect("Invalid request");
+
+        let translated = to_ollama_request(&request).expect("Failed to translate");
+        let body = serde_json::to_value(&translated).expect("Failed to serialize");
+        assert_eq!(body["messages"][0]["content"], "What is this?");
+        assert_eq!(body["messages"][0]["images"], json!(["iVBOR"]));
+        assert_eq!(
+            body["messages"][1]["tool_calls"][0]["function"]["arguments"],
+            json!({"q": "cat"})
+        );
+        assert_eq!(
+            body["options"],
+            json!({"num_predict": 128, "stop": ["END"]})
+        );
+        assert_eq!(body["format"], "json");
+        assert_eq!(body["stream"], false);
+    }
+
+    #[test]
+    fn test_stream_translation() {
+        let stream = concat!(
+            "{\"model\":\"llama3.2\",\"message\":{\"role\":\"assistant\",\"content\":\"Hel\"},\"done\":false}\n",
+            "{\"model\":\"llama3.2\",\"message\":{\"role\":\"assistant\",\"content\":\"lo\"},\"done\":false}\n",
+            "{\"model\":\"llama3.2\",\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"done_reason\":\"length\",\"eval_count\":2}\n",
+        );
+
+        // Split the stream in the middle of a line
+        let mut translator = StreamTranslator::new();
+        let (first, second) = stream.as_bytes().split_at(40);
+        let mut events = translator.push(first).expect("Failed to translate");
+        events.extend(translator.push(second).expect("Failed to translate"));
+
+        let chunks: Vec<Value> = events[..events.len() - 1]
+            .iter()
+            .map(|event| serde_json::from_slice(&event[6..]).expect("Invalid chunk"))
+            .collect();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[0]["choices"][0]["delta"]["content"], "Hel");
+        assert!(chunks[1]["choices"][0]["delta"].get("role").is_none());
+        assert_eq!(chunks[2]["choices"][0]["finish_reason"], "length");
+        assert_eq!(events[events.len() - 1], "data: [DONE]\n\n");
+
+        assert!(translator
+            .push(b"{\"error\":\"model not found\"}\n")
+            .is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use llm_proxy_core::upstream::UpstreamError;
+use serde::{Deserialize, Serialize};
+
+/// A request to the Ollama chat API (`/api/chat`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatRequest {
+    /// The model to use (e.g., "llama3.2")
+    pub model: String,
+    /// The conversation
+    pub messages: Vec<OllamaMessage>,
+    /// Whether to stream the response as newline-delimited JSON, by default
+    /// when a client leaves it out
+    #[serde(default = "default_stream")]
+    pub stream: bool,
+    /// Tools the model may call, in the `OpenAI` format
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<serde_json::Value>,
+    /// Output format: "json" or a JSON schema
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<serde_json::Value>,
+    /// Model parameters
+    #[serde(default, skip_serializing_if = "Options::is_empty")]
+    pub options: Options,
+}
+
+const fn default_stream() -> bool {
+    true
+}
+
+/// A message in an Ollama chat request or response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OllamaMessage {
+    /// The role of the message sender (system, user, assistant, or tool)
+    pub role: String,
+    /// The text of the message
+    #[serde(default)]
+    pub content: String,
+    /// Base64 encoded images
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<String>,
+    /// Tool calls made by the assistant
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+}
+
+/// A tool call made by the model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// The function to call
+    pub function: FunctionCall,
+}
+
+/// A function call made by the model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionCall {
+    /// Name of the function
+    pub name: String,
+    /// Arguments of the call
+    pub arguments: serde_json::Value,
+}
+
+/// Model parameters of an Ollama request
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Options {
+    /// Temperature for response randomness
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Nucleus sampling threshold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Maximum tokens to generate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<u32>,
+    /// Sequences that stop generation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    /// Random seed for reproducible outputs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    /// Penalty for tokens that already appeared
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// Penalty for tokens by how often they appeared
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+}
+
+impl Options {
+    /// Whether no parameter is set
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A response, or a line of a streamed response, from the Ollama chat API
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatResponse {
+    /// The model that generated the response
+    pub model: String,
+    /// The generated message, or the part generated since the previous line
+    #[serde(default)]
+    pub message: OllamaMessage,
+    /// Whether this is the last line of the response
+    pub done: bool,
+    /// Why generation stopped ("stop" or "length"), set on the last line
+    #[serde(default)]
+    pub done_reason: Option<String>,
+    /// Tokens in the prompt, set on the last line
+    #[serde(default)]
+    pub prompt_eval_count: u32,
+    /// Tokens generated, set on the last line
+    #[serde(default)]
+    pub eval_count: u32,
+}
+
+/// Error response from Ollama
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    /// The error message
+    pub error: String,
+}
+
+impl UpstreamError for ErrorResponse {
+    fn message(self) -> String {
+        self.error
+    }
+}
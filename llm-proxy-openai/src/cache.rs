@@ -0,0 +1,480 @@
+//! Cache of chat completion responses.
+//!
+//! A [`CachingLLMClient`] answers requests from a [`ResponseCache`] as the
+//! `cache_policy` processor allows it: requests with the
+//! [`CACHE_LOOKUP_ATTRIBUTE`] set to `"true"` are served a stored response
+//! when there is one, and the responses to requests with the
+//! [`CACHE_STORE_ATTRIBUTE`] set to `"true"` are stored. Requests without
+//! these attributes go to the backend as usual.
+//!
+//! Entries are keyed by the pipeline's namespace, the model and a hash of
+//! the request and tenant, as in `/v1/chat/completions:gpt-4o:3f2a...`, so
+//! they can be purged by prefix, e.g. all entries of a route or of one of
+//! its models. Requests with and without streaming share entries: streaming
+//! requests are answered with a [`StreamReplay`] of the stored response, and
+//! the [`completion`] of streamed responses is stored.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use llm_proxy_core::{LLMClient, ProviderCapabilities, RequestContext, ResponseStream, Result};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::{
+    processors::cache_policy::{CACHE_LOOKUP_ATTRIBUTE, CACHE_STORE_ATTRIBUTE},
+    replay::{completion, StreamReplay},
+    types::ChatCompletionRequest,
+};
+
+/// Entries a cache holds at most, unless configured otherwise
+pub const DEFAULT_MAX_ENTRIES: usize = 1000;
+
+/// Stored chat completion responses, by key
+#[derive(Debug)]
+pub struct ResponseCache {
+    entries: Mutex<HashMap<String, Entry>>,
+    insertions: AtomicU64,
+    max_entries: usize,
+    ttl: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    response: Value,
+    stored: Instant,
+    /// Order of the insertion, to evict the oldest entry
+    insertion: u64,
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResponseCache {
+    /// Create a cache of at most [`DEFAULT_MAX_ENTRIES`] entries that don't
+    /// expire
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            insertions: AtomicU64::new(0),
+            max_entries: DEFAULT_MAX_ENTRIES,
+            ttl: None,
+        }
+    }
+
+    /// Hold at most `max_entries`, evicting the oldest entry beyond
+    #[must_use]
+    pub const fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Expire entries `ttl` after they were stored
+    #[must_use]
+    pub const fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// The response stored under `key`, unless it expired
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<Value> {
+        let mut entries = self.entries.lock().ok()?;
+        let entry = entries.get(key)?;
+        if self.ttl.is_some_and(|ttl| entry.stored.elapsed() >= ttl) {
+            entries.remove(key);
+            return None;
+        }
+        let response = entry.response.clone();
+        drop(entries);
+        Some(response)
+    }
+
+    /// Store `response` under `key`
+    pub fn insert(&self, key: String, response: Value) {
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+        if !entries.contains_key(&key) && entries.len() >= self.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.insertion)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        if self.max_entries > 0 {
+            entries.insert(
+                key,
+                Entry {
+                    response,
+                    stored: Instant::now(),
+                    insertion: self.insertions.fetch_add(1, Ordering::Relaxed),
+                },
+            );
+        }
+    }
+
+    /// Remove the entries whose key starts with `prefix`, returning how many
+    /// there were
+    pub fn purge(&self, prefix: &str) -> usize {
+        let Ok(mut entries) = self.entries.lock() else {
+            return 0;
+        };
+        let before = entries.len();
+        entries.retain(|key, _| !key.starts_with(prefix));
+        before - entries.len()
+    }
+
+    /// Number of entries, including expired ones not removed yet
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.lock().map_or(0, |entries| entries.len())
+    }
+
+    /// Whether the cache has no entries
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Key of the response to `request` from the tenant of `context` in the
+/// `namespace` of a pipeline
+///
+/// # Errors
+///
+/// Returns an error if the request can't be serialized
+pub fn cache_key(
+    namespace: &str,
+    request: &ChatCompletionRequest,
+    context: &RequestContext,
+) -> Result<String> {
+    // Whether the response is streamed doesn't change it
+    let mut request_value = serde_json::to_value(request)?;
+    if let Some(fields) = request_value.as_object_mut() {
+        fields.remove("stream");
+        fields.remove("stream_options");
+    }
+    let mut hash = Sha256::new();
+    hash.update(request_value.to_string());
+    hash.update([0]);
+    hash.update(context.tenant.as_deref().unwrap_or_default());
+    Ok(format!(
+        "{namespace}:{}:{}",
+        request.model,
+        hex::encode(hash.finalize())
+    ))
+}
+
+/// LLM client answering requests from a [`ResponseCache`]; see the
+/// [module documentation](self)
+pub struct CachingLLMClient {
+    inner: Arc<dyn LLMClient<ChatCompletionRequest>>,
+    cache: Arc<ResponseCache>,
+    namespace: String,
+    replay: StreamReplay,
+}
+
+impl CachingLLMClient {
+    /// Serve the requests of `inner` from `cache`, under keys starting with
+    /// `namespace`, e.g. the ID of the route
+    #[must_use]
+    pub fn new(
+        inner: Arc<dyn LLMClient<ChatCompletionRequest>>,
+        cache: Arc<ResponseCache>,
+        namespace: impl Into<String>,
+    ) -> Self {
+        Self {
+            inner,
+            cache,
+            namespace: namespace.into(),
+            replay: StreamReplay::new(),
+        }
+    }
+
+    /// Answer streaming requests with `replay` of the stored responses, e.g.
+    /// to pace the chunks
+    #[must_use]
+    pub const fn with_replay(mut self, replay: StreamReplay) -> Self {
+        self.replay = replay;
+        self
+    }
+}
+
+/// Whether the context `attribute` allows a use of the cache
+fn allowed(context: &RequestContext, attribute: &str) -> bool {
+    context
+        .attributes
+        .get(attribute)
+        .is_some_and(|value| value == "true")
+}
+
+#[async_trait]
+impl LLMClient<ChatCompletionRequest> for CachingLLMClient {
+    async fn execute(&self, request: ChatCompletionRequest) -> Result<ResponseStream> {
+        self.inner.execute(request).await
+    }
+
+    async fn execute_with_context(
+        &self,
+        request: ChatCompletionRequest,
+        context: &RequestContext,
+    ) -> Result<ResponseStream> {
+        let lookup = allowed(context, CACHE_LOOKUP_ATTRIBUTE);
+        let store = allowed(context, CACHE_STORE_ATTRIBUTE);
+        if !(lookup || store) {
+            return self.inner.execute_with_context(request, context).await;
+        }
+        let key = cache_key(&self.namespace, &request, context)?;
+        let streaming = request.stream;
+        if lookup {
+            if let Some(response) = self.cache.get(&key) {
+                metrics::counter!("llm_proxy_cache_requests_total", "result" => "hit").increment(1);
+                debug!(key, streaming, "Answered request from the cache");
+                if streaming {
+                    let usage = request
+                        .additional_params
+                        .get("stream_options")
+                        .is_some_and(|options| options["include_usage"] == true);
+                    return Ok(self.replay.clone().with_usage(usage).replay(&response));
+                }
+                let (tx, rx) = mpsc::channel(1);
+                let _ = tx.try_send(Ok(Bytes::from(response.to_string())));
+                return Ok(rx);
+            }
+            metrics::counter!("llm_proxy_cache_requests_total", "result" => "miss").increment(1);
+        }
+        let mut stream = self.inner.execute_with_context(request, context).await?;
+        if !store {
+            return Ok(stream);
+        }
+
+        let cache = self.cache.clone();
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            let mut body = Vec::new();
+            let mut failed = false;
+            while let Some(item) = stream.recv().await {
+                match &item {
+                    Ok(chunk) => body.extend_from_slice(chunk),
+                    Err(_) => failed = true,
+                }
+                if tx.send(item).await.is_err() {
+                    return;
+                }
+            }
+            let response = if streaming {
+                completion(&body)
+            } else {
+                serde_json::from_slice::<Value>(&body).ok()
+            };
+            if let Some(response) = response.filter(|response| {
+                !failed
+                    && response["choices"]
+                        .as_array()
+                        .is_some_and(|c| !c.is_empty())
+            }) {
+                debug!(key, "Stored response in the cache");
+                cache.insert(key, response);
+            }
+        });
+        Ok(rx)
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.inner.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Answers every request with a completion and counts them
+    #[derive(Default)]
+    struct CountingClient(AtomicUsize);
+
+    #[async_trait]
+    impl LLMClient<ChatCompletionRequest> for CountingClient {
+        async fn execute(&self, request: ChatCompletionRequest) -> Result<ResponseStream> {
+            let calls = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+            let response = json!({
+                "id": format!("chatcmpl-{calls}"),
+                "object": "chat.completion",
+                "created": 1,
+                "model": "gpt-4o",
+                "choices": [{
+                    "index": 0,
+                    "message": {"role": "assistant", "content": "Hello there"},
+                    "finish_reason": "stop",
+                }],
+            });
+            if request.stream {
+                return Ok(StreamReplay::new().replay(&response));
+            }
+            let (tx, rx) = mpsc::channel(1);
+            let _ = tx.try_send(Ok(Bytes::from(response.to_string())));
+            Ok(rx)
+        }
+    }
+
+    fn request() -> ChatCompletionRequest {
+        serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "Hi"}],
+        }))
+        .expect("Failed to build request")
+    }
+
+    fn context(lookup: bool, store: bool) -> RequestContext {
+        let mut context = RequestContext::new();
+        context
+            .attributes
+            .insert(CACHE_LOOKUP_ATTRIBUTE.to_string(), lookup.to_string());
+        context
+            .attributes
+            .insert(CACHE_STORE_ATTRIBUTE.to_string(), store.to_string());
+        context
+    }
+
+    async fn read(mut stream: ResponseStream) -> Vec<u8> {
+        let mut body = Vec::new();
+        while let Some(chunk) = stream.recv().await {
+            body.extend_from_slice(&chunk.expect("Unexpected error"));
+        }
+        body
+    }
+
+    async fn body(stream: ResponseStream) -> Value {
+        serde_json::from_slice(&read(stream).await).expect("Invalid response")
+    }
+
+    #[tokio::test]
+    async fn test_serves_stored_responses() {
+        let inner = Arc::new(CountingClient::default());
+        let cache = Arc::new(ResponseCache::new());
+        let client = CachingLLMClient::new(inner.clone(), cache.clone(), "/v1/chat/completions");
+
+        let first = body(
+            client
+                .execute_with_context(request(), &context(true, true))
+                .await
+                .expect("Failed to execute"),
+        )
+        .await;
+        let second = body(
+            client
+                .execute_with_context(request(), &context(true, true))
+                .await
+                .expect("Failed to execute"),
+        )
+        .await;
+        assert_eq!(first, second);
+        assert_eq!(inner.0.load(Ordering::SeqCst), 1);
+
+        // no-cache skips the lookup, without attributes the cache isn't used
+        client
+            .execute_with_context(request(), &context(false, true))
+            .await
+            .expect("Failed to execute");
+        client
+            .execute_with_context(request(), &RequestContext::new())
+            .await
+            .expect("Failed to execute");
+        assert_eq!(inner.0.load(Ordering::SeqCst), 3);
+
+        // Other tenants have entries of their own
+        let mut tenant = context(true, true);
+        tenant.tenant = Some("acme".to_string());
+        client
+            .execute_with_context(request(), &tenant)
+            .await
+            .expect("Failed to execute");
+        assert_eq!(inner.0.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_replays_stored_responses_to_streams() {
+        let inner = Arc::new(CountingClient::default());
+        let client = CachingLLMClient::new(
+            inner.clone(),
+            Arc::new(ResponseCache::new()),
+            "/v1/chat/completions",
+        );
+        let streaming = || {
+            let mut request = request();
+            request.stream = true;
+            request
+        };
+
+        // A streamed response is stored complete, for requests without streaming
+        let streamed = read(
+            client
+                .execute_with_context(streaming(), &context(true, true))
+                .await
+                .expect("Failed to execute"),
+        )
+        .await;
+        let stored = body(
+            client
+                .execute_with_context(request(), &context(true, true))
+                .await
+                .expect("Failed to execute"),
+        )
+        .await;
+        assert_eq!(completion(&streamed), Some(stored));
+
+        // and replayed as a stream to streaming requests
+        let replayed = read(
+            client
+                .execute_with_context(streaming(), &context(true, true))
+                .await
+                .expect("Failed to execute"),
+        )
+        .await;
+        assert_eq!(replayed, streamed);
+        assert_eq!(inner.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_purges_by_prefix_and_evicts() {
+        let cache = ResponseCache::new().with_max_entries(2);
+        cache.insert("/v1/chat/completions:gpt-4o:1".to_string(), json!(1));
+        cache.insert("/v1/chat/completions:gpt-4o-mini:2".to_string(), json!(2));
+        cache.insert("/v1/chat/completions:gpt-4o:3".to_string(), json!(3));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("/v1/chat/completions:gpt-4o:1"), None);
+
+        assert_eq!(cache.purge("/v1/chat/completions:gpt-4o:"), 1);
+        assert_eq!(
+            cache.get("/v1/chat/completions:gpt-4o-mini:2"),
+            Some(json!(2))
+        );
+        assert_eq!(cache.purge(""), 1);
+        assert!(cache.is_empty());
+
+        let cache = ResponseCache::new().with_ttl(Duration::ZERO);
+        cache.insert("key".to_string(), json!(1));
+        assert_eq!(cache.get("key"), None);
+    }
+}
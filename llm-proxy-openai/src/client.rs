@@ -4,17 +4,36 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures_util::StreamExt;
-use llm_proxy_core::{ClientProvider, Error, LLMClient, Result, TokenProvider, UrlProvider};
+use llm_proxy_core::{
+    telemetry, AuthScheme, ClientProvider, Error, LLMClient, ProviderCapabilities, RequestContext,
+    RequestSigner, Result, StreamTimer, TokenProvider, UrlProvider, REQUEST_ID_HEADER,
+};
 use tokio::sync::mpsc;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error, field, info, info_span, instrument, warn, Instrument, Span};
 
-use crate::types::{ChatCompletionRequest, ErrorResponse, StreamChunk};
+use crate::{
+    quirks::Quirks,
+    types::{ChatCompletionRequest, ErrorResponse, StreamChunk},
+};
+
+/// Request context attribute overriding the `OpenAI-Organization` header
+pub const ORGANIZATION_ATTRIBUTE: &str = "openai.organization";
+
+/// Request context attribute overriding the `OpenAI-Project` header
+pub const PROJECT_ATTRIBUTE: &str = "openai.project";
 
 /// OpenAI-specific implementation of `LLMClient`
 pub struct OpenAIClient {
     client: Arc<dyn ClientProvider>,
     token: Arc<dyn TokenProvider>,
     url: Arc<dyn UrlProvider>,
+    auth_scheme: AuthScheme,
+    signer: Option<Arc<dyn RequestSigner>>,
+    organization: Option<String>,
+    project: Option<String>,
+    headers: Vec<(String, String)>,
+    capabilities: ProviderCapabilities,
+    quirks: Quirks,
 }
 
 impl Clone for OpenAIClient {
@@ -23,6 +42,13 @@ impl Clone for OpenAIClient {
             client: self.client.clone(),
             token: self.token.clone(),
             url: self.url.clone(),
+            auth_scheme: self.auth_scheme.clone(),
+            signer: self.signer.clone(),
+            organization: self.organization.clone(),
+            project: self.project.clone(),
+            headers: self.headers.clone(),
+            capabilities: self.capabilities.clone(),
+            quirks: self.quirks.clone(),
         }
     }
 }
@@ -38,27 +64,144 @@ impl OpenAIClient {
             client: client_provider,
             token: token_provider,
             url: url_provider,
+            auth_scheme: AuthScheme::default(),
+            signer: None,
+            organization: None,
+            project: None,
+            headers: Vec::new(),
+            capabilities: ProviderCapabilities::default(),
+            quirks: Quirks::default(),
+        }
+    }
+
+    /// Replace the provider of the HTTP client used for upstream requests
+    #[must_use]
+    pub fn with_client_provider(mut self, client_provider: Arc<dyn ClientProvider>) -> Self {
+        self.client = client_provider;
+        self
+    }
+
+    /// Set how the API token is sent to the upstream service
+    #[must_use]
+    pub fn with_auth_scheme(mut self, auth_scheme: AuthScheme) -> Self {
+        self.auth_scheme = auth_scheme;
+        self
+    }
+
+    /// Sign every request just before it is sent, e.g. with AWS `SigV4`
+    #[must_use]
+    pub fn with_request_signer(mut self, signer: Arc<dyn RequestSigner>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Send the `OpenAI-Organization` header with every request
+    #[must_use]
+    pub fn with_organization(mut self, organization: impl Into<String>) -> Self {
+        self.organization = Some(organization.into());
+        self
+    }
+
+    /// Send the `OpenAI-Project` header with every request
+    #[must_use]
+    pub fn with_project(mut self, project: impl Into<String>) -> Self {
+        self.project = Some(project.into());
+        self
+    }
+
+    /// Send a static header with every request, e.g. for an LLM gateway in front of the API
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Declare what the upstream backend supports, e.g. its models
+    #[must_use]
+    pub fn with_capabilities(mut self, capabilities: ProviderCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Work around deviations of an OpenAI-compatible backend from the `OpenAI` API
+    #[must_use]
+    pub fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Build the upstream HTTP request, including authentication and headers
+    fn build_request(
+        &self,
+        request: &ChatCompletionRequest,
+        context: &RequestContext,
+        client: &reqwest::Client,
+        token: &str,
+        url: String,
+    ) -> reqwest::RequestBuilder {
+        let mut builder = self.auth_scheme.apply(client.post(url), token);
+        for (name, value) in context.upstream_headers.iter().chain(&self.headers) {
+            builder = builder.header(name, value);
+        }
+
+        // Overrides set by processors take precedence over the tenant's settings,
+        // which take precedence over the backend defaults
+        let organization = request
+            .overrides
+            .organization
+            .as_ref()
+            .or_else(|| context.attributes.get(ORGANIZATION_ATTRIBUTE))
+            .or(self.organization.as_ref());
+        let project = request
+            .overrides
+            .project
+            .as_ref()
+            .or_else(|| context.attributes.get(PROJECT_ATTRIBUTE))
+            .or(self.project.as_ref());
+        let builder = match organization {
+            Some(organization) => builder.header("OpenAI-Organization", organization),
+            None => builder,
+        };
+        let builder = match project {
+            Some(project) => builder.header("OpenAI-Project", project),
+            None => builder,
+        };
+        let mut builder = match &context.request_id {
+            Some(request_id) => builder.header(REQUEST_ID_HEADER, request_id),
+            None => builder,
+        };
+        // Upstreams reporting to the same collector continue the trace
+        for (name, value) in telemetry::trace_headers() {
+            builder = builder.header(name, value);
         }
+
+        builder.json(request)
     }
 
     /// Send request to `OpenAI` and get response
     async fn send_request(
         &self,
         request: &ChatCompletionRequest,
+        context: &RequestContext,
         client: reqwest::Client,
         token: String,
         url: String,
     ) -> Result<reqwest::Response> {
+        let mut upstream_request = self
+            .build_request(request, context, &client, &token, url)
+            .build()
+            .map_err(|e| Error::LLMError(format!("Failed to build request to OpenAI: {e}")))?;
+        if let Some(signer) = &self.signer {
+            signer.sign(&mut upstream_request).await?;
+        }
         let response = client
-            .post(url)
-            .bearer_auth(token)
-            .json(&request)
-            .send()
+            .execute(upstream_request)
             .await
             .map_err(|e| Error::LLMError(format!("Failed to send request to OpenAI: {e}")))?;
 
         if !response.status().is_success() {
             let status = response.status();
+            self.token.report_rejection(&token, status.as_u16()).await;
             let error_body = response.json::<ErrorResponse>().await.map_err(|e| {
                 Error::LLMError(format!(
                     "Failed to parse OpenAI error response: {e}, status: {status}"
@@ -78,18 +221,29 @@ impl OpenAIClient {
         self,
         response: reqwest::Response,
         tx: mpsc::Sender<Result<Bytes>>,
+        mut timer: StreamTimer,
     ) -> Result<()> {
         let mut stream = response.bytes_stream();
 
         while let Some(chunk_result) = stream.next().await {
+            // Dropping the response cancels the upstream request once nobody
+            // reads the stream anymore, e.g. after a stream processor stopped it
+            if tx.is_closed() {
+                info!("Response stream closed, cancelling upstream request");
+                break;
+            }
             match chunk_result {
-                Ok(chunk) => self.process_chunk(chunk, &tx).await?,
+                Ok(chunk) => {
+                    time_chunk(&mut timer, &chunk);
+                    self.process_chunk(chunk, &tx).await?;
+                }
                 Err(e) => {
                     self.send_error(&tx, format!("Error reading chunk from OpenAI: {e}"))
                         .await?;
                 }
             }
         }
+        timer.finish();
 
         Ok(())
     }
@@ -122,6 +276,12 @@ impl OpenAIClient {
 
         if data == "[DONE]" {
             info!("Received [DONE] signal");
+            // Rewritten chunks are sent one by one, without the rest of the raw chunk
+            if self.quirks.rewrites_responses() {
+                return self
+                    .send_chunk(&Bytes::from_static(b"data: [DONE]\n\n"), tx)
+                    .await;
+            }
             return Ok(());
         }
 
@@ -138,7 +298,15 @@ impl OpenAIClient {
         match serde_json::from_str::<StreamChunk>(data) {
             Ok(chunk_data) => {
                 debug!(?chunk_data, "Successfully parsed chunk");
-                self.send_chunk(original_chunk, tx).await
+                if !self.quirks.rewrites_responses() {
+                    return self.send_chunk(original_chunk, tx).await;
+                }
+                let mut chunk: serde_json::Value = serde_json::from_str(data).map_err(|e| {
+                    Error::LLMError(format!("Failed to parse OpenAI stream chunk: {e}"))
+                })?;
+                self.quirks.sanitize_response(&mut chunk);
+                self.send_chunk(&Bytes::from(format!("data: {chunk}\n\n")), tx)
+                    .await
             }
             Err(e) => {
                 error!(
@@ -178,9 +346,15 @@ impl OpenAIClient {
         response: reqwest::Response,
         tx: mpsc::Sender<Result<Bytes>>,
     ) -> Result<()> {
-        let bytes = response.bytes().await.map_err(|e| {
+        let mut bytes = response.bytes().await.map_err(|e| {
             Error::LLMError(format!("Failed to read OpenAI non-streaming response: {e}"))
         })?;
+        if self.quirks.rewrites_responses() {
+            if let Ok(mut completion) = serde_json::from_slice::<serde_json::Value>(&bytes) {
+                self.quirks.sanitize_response(&mut completion);
+                bytes = Bytes::from(completion.to_string());
+            }
+        }
 
         if tx.send(Ok(bytes)).await.is_err() {
             warn!("Failed to send response - receiver dropped");
@@ -190,12 +364,55 @@ impl OpenAIClient {
     }
 }
 
+/// Record the events of a raw stream chunk with generated output in `timer`
+fn time_chunk(timer: &mut StreamTimer, chunk: &Bytes) {
+    let lines = String::from_utf8_lossy(chunk);
+    for data in lines.lines().filter_map(|line| line.strip_prefix("data:")) {
+        let Ok(event) = serde_json::from_str::<serde_json::Value>(data.trim()) else {
+            continue;
+        };
+        let has_output = event["choices"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .any(|choice| {
+                let delta = &choice["delta"];
+                delta["content"]
+                    .as_str()
+                    .is_some_and(|content| !content.is_empty())
+                    || delta["tool_calls"].is_array()
+            });
+        if has_output {
+            timer.output_chunk();
+        }
+        if let Some(tokens) = event["usage"]["completion_tokens"].as_u64() {
+            timer.set_tokens(tokens);
+        }
+    }
+}
+
 #[async_trait]
 impl LLMClient<ChatCompletionRequest> for OpenAIClient {
     async fn execute(
         &self,
         request: ChatCompletionRequest,
     ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        self.execute_with_context(request, &RequestContext::new())
+            .await
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.capabilities.clone()
+    }
+
+    #[instrument(name = "forward", skip_all, fields(model = %request.model, stream = request.stream))]
+    async fn execute_with_context(
+        &self,
+        mut request: ChatCompletionRequest,
+        context: &RequestContext,
+    ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        self.quirks.sanitize_request(&mut request);
+
         // 1. Get dependencies
         let client = self
             .client
@@ -204,35 +421,54 @@ impl LLMClient<ChatCompletionRequest> for OpenAIClient {
             .map_err(|e| Error::LLMError(format!("Failed to get HTTP client: {e}")))?;
         let token = self
             .token
-            .get_token()
+            .get_token_for(context)
             .await
             .map_err(|e| Error::LLMError(format!("Failed to get API token: {e}")))?;
         let url = self
             .url
             .get_url()
             .map_err(|e| Error::LLMError(format!("Failed to get API URL: {e}")))?;
+        let url = context.upstream_url(url)?;
 
         // 2. Create response channel
         let (tx, rx) = mpsc::channel(100);
 
         // 3. Send request and handle response
-        let response = self.send_request(&request, client, token, url).await?;
+        let timer = StreamTimer::start(request.model.clone());
+        let response = self
+            .send_request(&request, context, client, token, url)
+            .await?;
 
         // 4. Handle response based on streaming flag
         let client = self.clone();
         let stream = request.stream;
         info!("The request is streaming: {}", stream);
-        tokio::spawn(async move {
-            let result = if stream {
-                client.handle_stream(response, tx).await
-            } else {
-                client.handle_non_stream(response, tx).await
-            };
-
-            if let Err(e) = result {
-                error!(error = %e, "Error handling OpenAI response");
+        // The response is read after this call returns, so in a span linked to
+        // this one rather than a child outliving it
+        let span = info_span!(
+            parent: None,
+            "sse",
+            stream,
+            time_to_first_token_ms = field::Empty,
+            duration_ms = field::Empty,
+            tokens = field::Empty,
+            tokens_per_second = field::Empty,
+        );
+        span.follows_from(Span::current());
+        tokio::spawn(
+            async move {
+                let result = if stream {
+                    client.handle_stream(response, tx, timer).await
+                } else {
+                    client.handle_non_stream(response, tx).await
+                };
+
+                if let Err(e) = result {
+                    error!(error = %e, "Error handling OpenAI response");
+                }
             }
-        });
+            .instrument(span),
+        );
 
         Ok(rx)
     }
@@ -261,10 +497,107 @@ mod tests {
         }
     }
 
-    #[async_trait]
     impl UrlProvider for MockUrlProvider {
-        async fn get_url(&self) -> Result<String> {
+        fn get_url(&self) -> Result<String> {
             Ok("https://api.openai.com/v1/chat/completions".to_string())
         }
     }
+
+    #[test]
+    fn test_auth_scheme_is_preserved_on_clone() {
+        let client = OpenAIClient::new(
+            Arc::new(MockClientProvider),
+            Arc::new(MockTokenProvider),
+            Arc::new(MockUrlProvider),
+        )
+        .with_auth_scheme(AuthScheme::api_key_header());
+
+        let cloned = client.clone();
+        assert_eq!(cloned.auth_scheme, AuthScheme::api_key_header());
+        assert_eq!(client.auth_scheme, AuthScheme::api_key_header());
+    }
+
+    #[test]
+    fn test_upstream_headers() {
+        let client = OpenAIClient::new(
+            Arc::new(MockClientProvider),
+            Arc::new(MockTokenProvider),
+            Arc::new(MockUrlProvider),
+        )
+        .with_organization("org-default")
+        .with_project("proj-default")
+        .with_header("Helicone-Auth", "Bearer sk-helicone");
+
+        let mut request = ChatCompletionRequest::new_block("gpt-4o".to_string(), vec![]);
+        request.overrides.project = Some("proj-request".to_string());
+        let context = RequestContext::new()
+            .with_attribute(ORGANIZATION_ATTRIBUTE, "org-tenant")
+            .with_attribute(PROJECT_ATTRIBUTE, "proj-tenant")
+            .with_request_id("req-123")
+            .with_upstream_header("X-Conversation-Id", "conv-1");
+
+        let http_request = client
+            .build_request(
+                &request,
+                &context,
+                &reqwest::Client::new(),
+                "test-token",
+                "https://api.openai.com/v1/chat/completions".to_string(),
+            )
+            .build()
+            .expect("Failed to build request");
+
+        let headers = http_request.headers();
+        assert_eq!(headers["OpenAI-Organization"], "org-tenant");
+        assert_eq!(headers["OpenAI-Project"], "proj-request");
+        assert_eq!(headers["Authorization"], "Bearer test-token");
+        assert_eq!(headers["Helicone-Auth"], "Bearer sk-helicone");
+        assert_eq!(headers["X-Request-Id"], "req-123");
+        assert_eq!(headers["X-Conversation-Id"], "conv-1");
+    }
+
+    #[test]
+    fn test_trace_context_is_propagated() {
+        use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+        use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider};
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let client = OpenAIClient::new(
+            Arc::new(MockClientProvider),
+            Arc::new(MockTokenProvider),
+            Arc::new(MockUrlProvider),
+        );
+        let request = ChatCompletionRequest::new_block("gpt-4o".to_string(), vec![]);
+
+        let (http_request, span_context) = tracing::subscriber::with_default(subscriber, || {
+            let span = info_span!("forward");
+            let _entered = span.enter();
+            let http_request = client
+                .build_request(
+                    &request,
+                    &RequestContext::new(),
+                    &reqwest::Client::new(),
+                    "test-token",
+                    "https://api.openai.com/v1/chat/completions".to_string(),
+                )
+                .build()
+                .expect("Failed to build request");
+            (http_request, span.context().span().span_context().clone())
+        });
+
+        // The upstream request is a child of the span sending it
+        assert_eq!(
+            http_request.headers()["traceparent"],
+            format!(
+                "00-{}-{}-01",
+                span_context.trace_id(),
+                span_context.span_id()
+            )
+        );
+    }
 }
@@ -0,0 +1,274 @@
+//! Provider factories for `OpenAI` and OpenAI-compatible backends.
+//!
+//! [`register`] adds them to a [`ProviderRegistry`] under the names used in
+//! configuration: `openai`, `openai_compatible`, `azure_openai` and
+//! `openrouter`, and with the `mock` feature `mock` and `fixture`.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use llm_proxy_core::{
+    Pipeline, Processor, ProviderContext, ProviderFactory, ProviderRegistry, Result,
+};
+use serde::Deserialize;
+
+use crate::{
+    client::{OpenAIClient, ORGANIZATION_ATTRIBUTE, PROJECT_ATTRIBUTE},
+    create_azure_chat_client, create_chat_client, create_chat_client_with_url_provider,
+    create_pipeline_with_client,
+    processors::openrouter::{
+        OpenRouterProcessor, OPENROUTER_REFERER_HEADER, OPENROUTER_TITLE_HEADER,
+    },
+    quirks::Quirks,
+    types::ChatCompletionRequest,
+};
+
+/// Register the factories of this crate in `registry`
+pub fn register(registry: &mut ProviderRegistry<ChatCompletionRequest>) {
+    registry
+        .register("openai", OpenAIFactory)
+        .register("openai_compatible", OpenAICompatibleFactory)
+        .register("azure_openai", AzureOpenAIFactory)
+        .register("openrouter", OpenRouterFactory);
+    #[cfg(feature = "mock")]
+    registry
+        .register("mock", MockFactory)
+        .register("fixture", FixtureFactory);
+}
+
+/// Create an `OpenAI` client with the shared settings of `context`
+fn create_client(context: &ProviderContext) -> Result<OpenAIClient> {
+    let token_provider = context.require_token_provider()?;
+    let client = match &context.url_provider {
+        Some(url_provider) => {
+            create_chat_client_with_url_provider(token_provider, url_provider.clone())
+        }
+        None => create_chat_client(token_provider, Some(&context.base_url)),
+    };
+    Ok(configure_client(client, context))
+}
+
+/// Apply the client provider, authentication, headers and capabilities of `context`
+fn configure_client(mut client: OpenAIClient, context: &ProviderContext) -> OpenAIClient {
+    if let Some(client_provider) = &context.client_provider {
+        client = client.with_client_provider(client_provider.clone());
+    }
+    if let Some(auth_scheme) = &context.auth_scheme {
+        client = client.with_auth_scheme(auth_scheme.clone());
+    }
+    for (name, value) in &context.headers {
+        client = client.with_header(name, value);
+    }
+    if let Some(organization) = context.attributes.get(ORGANIZATION_ATTRIBUTE) {
+        client = client.with_organization(organization);
+    }
+    if let Some(project) = context.attributes.get(PROJECT_ATTRIBUTE) {
+        client = client.with_project(project);
+    }
+    client.with_capabilities(context.capabilities.clone())
+}
+
+/// Factory for the `OpenAI` chat completions API, or any server implementing it exactly
+pub struct OpenAIFactory;
+
+#[async_trait]
+impl ProviderFactory<ChatCompletionRequest> for OpenAIFactory {
+    async fn create_pipeline(
+        &self,
+        context: ProviderContext,
+    ) -> Result<Pipeline<ChatCompletionRequest>> {
+        let client = create_client(&context)?;
+        Ok(create_pipeline_with_client(vec![], client))
+    }
+}
+
+/// Factory for OpenAI-compatible backends, with [`Quirks`] as settings
+pub struct OpenAICompatibleFactory;
+
+#[async_trait]
+impl ProviderFactory<ChatCompletionRequest> for OpenAICompatibleFactory {
+    async fn create_pipeline(
+        &self,
+        context: ProviderContext,
+    ) -> Result<Pipeline<ChatCompletionRequest>> {
+        let quirks: Quirks = context.settings()?;
+        let client = create_client(&context)?.with_quirks(quirks);
+        Ok(create_pipeline_with_client(vec![], client))
+    }
+}
+
+/// Settings of an Azure `OpenAI` deployment
+#[derive(Debug, Deserialize, Clone)]
+pub struct AzureOpenAISettings {
+    /// Name of the model deployment
+    pub deployment: String,
+    /// Azure `OpenAI` API version
+    #[serde(default)]
+    pub api_version: Option<String>,
+}
+
+/// Factory for Azure `OpenAI` deployments, with [`AzureOpenAISettings`] as settings
+///
+/// The deployment URL is built from `base_url`, the resource endpoint.
+pub struct AzureOpenAIFactory;
+
+#[async_trait]
+impl ProviderFactory<ChatCompletionRequest> for AzureOpenAIFactory {
+    async fn create_pipeline(
+        &self,
+        context: ProviderContext,
+    ) -> Result<Pipeline<ChatCompletionRequest>> {
+        let settings: AzureOpenAISettings = context.settings()?;
+        let client = create_azure_chat_client(
+            context.require_token_provider()?,
+            &context.base_url,
+            &settings.deployment,
+            settings.api_version.as_deref(),
+        );
+        let client = configure_client(client, &context);
+        Ok(create_pipeline_with_client(vec![], client))
+    }
+}
+
+/// Settings of an `OpenRouter` backend
+#[derive(Debug, Deserialize, Clone)]
+pub struct OpenRouterSettings {
+    /// URL of the app, sent as the `HTTP-Referer` attribution header
+    #[serde(default)]
+    pub site_url: Option<String>,
+    /// Name of the app, sent as the `X-Title` attribution header
+    #[serde(default)]
+    pub app_name: Option<String>,
+    /// Default provider routing preferences, e.g. `{ order = ["anthropic"] }`
+    #[serde(default)]
+    pub provider: Option<serde_json::Value>,
+    /// Default prompt transforms, e.g. `["middle-out"]`
+    #[serde(default)]
+    pub transforms: Option<Vec<String>>,
+    /// Models tried in order when the requested model is unavailable
+    #[serde(default)]
+    pub fallback_models: Vec<String>,
+}
+
+/// Factory for `OpenRouter`, with [`OpenRouterSettings`] as settings
+pub struct OpenRouterFactory;
+
+#[async_trait]
+impl ProviderFactory<ChatCompletionRequest> for OpenRouterFactory {
+    async fn create_pipeline(
+        &self,
+        context: ProviderContext,
+    ) -> Result<Pipeline<ChatCompletionRequest>> {
+        let settings: OpenRouterSettings = context.settings()?;
+        let mut processor =
+            OpenRouterProcessor::new().with_fallback_models(settings.fallback_models);
+        if let Some(provider) = settings.provider {
+            processor = processor.with_provider_preferences(provider);
+        }
+        if let Some(transforms) = settings.transforms {
+            processor = processor.with_transforms(transforms);
+        }
+        let processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>> = vec![Arc::new(processor)];
+
+        let mut client = create_client(&context)?;
+        if let Some(site_url) = settings.site_url {
+            client = client.with_header(OPENROUTER_REFERER_HEADER, site_url);
+        }
+        if let Some(app_name) = settings.app_name {
+            client = client.with_header(OPENROUTER_TITLE_HEADER, app_name);
+        }
+        Ok(create_pipeline_with_client(processors, client))
+    }
+}
+
+/// Settings of a mock backend
+#[cfg(feature = "mock")]
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct MockSettings {
+    /// Responses to the first requests, in order
+    #[serde(default)]
+    pub script: Vec<llm_proxy_core::MockResponse>,
+    /// Template answering requests once the script is exhausted
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Milliseconds between two streamed words
+    #[serde(default)]
+    pub token_delay_ms: u64,
+}
+
+/// Factory for a mock backend that needs no upstream service or credentials,
+/// with [`MockSettings`] as settings
+#[cfg(feature = "mock")]
+pub struct MockFactory;
+
+#[cfg(feature = "mock")]
+#[async_trait]
+impl ProviderFactory<ChatCompletionRequest> for MockFactory {
+    fn requires_token(&self) -> bool {
+        false
+    }
+
+    async fn create_pipeline(
+        &self,
+        context: ProviderContext,
+    ) -> Result<Pipeline<ChatCompletionRequest>> {
+        let settings: MockSettings = context.settings()?;
+        let mut client = llm_proxy_core::MockLLMClient::new()
+            .with_responses(settings.script)
+            .with_token_delay(std::time::Duration::from_millis(settings.token_delay_ms))
+            .with_capabilities(context.capabilities);
+        if let Some(template) = settings.template {
+            client = client.with_template(template);
+        }
+        Ok(Pipeline::new(
+            Arc::new(crate::OpenAIRequestParser::new()),
+            Arc::new(llm_proxy_core::ProcessorChain::new(vec![])),
+            Arc::new(client),
+        ))
+    }
+}
+
+/// Settings of a backend replaying recorded fixtures
+#[cfg(feature = "mock")]
+#[derive(Debug, Deserialize, Clone)]
+pub struct FixtureSettings {
+    /// Directory of the fixtures, as written by a
+    /// [`RecordingLLMClient`](llm_proxy_core::fixture::RecordingLLMClient)
+    pub dir: String,
+    /// Send chunks with their recorded delays
+    #[serde(default = "default_timing")]
+    pub timing: bool,
+}
+
+#[cfg(feature = "mock")]
+const fn default_timing() -> bool {
+    true
+}
+
+/// Factory for a backend answering with recorded fixtures instead of an
+/// upstream service, with [`FixtureSettings`] as settings
+#[cfg(feature = "mock")]
+pub struct FixtureFactory;
+
+#[cfg(feature = "mock")]
+#[async_trait]
+impl ProviderFactory<ChatCompletionRequest> for FixtureFactory {
+    fn requires_token(&self) -> bool {
+        false
+    }
+
+    async fn create_pipeline(
+        &self,
+        context: ProviderContext,
+    ) -> Result<Pipeline<ChatCompletionRequest>> {
+        let settings: FixtureSettings = context.settings()?;
+        let client = llm_proxy_core::fixture::ReplayingLLMClient::new(settings.dir)
+            .with_timing(settings.timing)
+            .with_capabilities(context.capabilities);
+        Ok(Pipeline::new(
+            Arc::new(crate::OpenAIRequestParser::new()),
+            Arc::new(llm_proxy_core::ProcessorChain::new(vec![])),
+            Arc::new(client),
+        ))
+    }
+}
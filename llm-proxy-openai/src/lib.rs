@@ -13,6 +13,10 @@
 //!
 //! ## Components
 //!
+//! ### Cache
+//! The [`cache`] module answers chat requests from stored responses, as the
+//! `cache_policy` processor allows it.
+//!
 //! ### Client
 //! The [`client`] module provides a high-level client for interacting with `OpenAI`'s API.
 //! It handles authentication, request formatting, and response parsing.
@@ -22,6 +26,34 @@
 //! for `OpenAI`'s services. This includes handling both streaming and non-streaming
 //! chat completions.
 //!
+//! ### Factory
+//! The [`factory`] module registers the `OpenAI` provider factories, such as
+//! `openai` and `azure_openai`, in a [`ProviderRegistry`](llm_proxy_core::ProviderRegistry).
+//!
+//! ### Passthrough
+//! The [`passthrough`] module forwards raw requests for APIs that don't fit the chat
+//! completion pipeline, such as the Assistants and Threads APIs.
+//!
+//! ### Processors
+//! The [`processors`] module contains ready-made request processors for chat
+//! completion requests, such as validation of multimodal image inputs and
+//! `OpenRouter` routing preferences.
+//!
+//! ### Quirks
+//! The [`quirks`] module describes how OpenAI-compatible backends (vLLM,
+//! LM Studio, ...) deviate from the API, so requests and responses can be
+//! sanitized for them.
+//!
+//! ### Replay
+//! The [`replay`] module turns complete chat completion responses into the
+//! streams a streaming request would have received, e.g. to answer streaming
+//! clients from stored responses.
+//!
+//! ### Stream Processors
+//! The [`stream_processors`] module contains ready-made processors for streamed
+//! chat completion responses, such as an output guardrail that stops
+//! generations violating a policy.
+//!
 //! ### Types
 //! The [`types`] module defines `OpenAI`-specific types for requests and responses,
 //! including chat messages, model parameters, and API responses.
@@ -29,18 +61,21 @@
 //! ## Example Usage
 //!
 //! ```rust,no_run
-//! use llm_proxy_openai::{create_chat_pipeline, ChatCompletionRequest};
+//! use llm_proxy_openai::create_chat_pipeline;
 //!
+//! # async fn example() -> llm_proxy_core::Result<()> {
 //! // Create a pipeline for OpenAI chat completions
 //! let pipeline = create_chat_pipeline(
-//!     processors,
+//!     vec![],
 //!     Some("OPENAI_API_KEY"),
-//!     Some("https://api.openai.com/v1"),
-//!     Some(route_config)
+//!     Some("https://api.openai.com/v1/chat/completions"),
 //! );
 //!
 //! // Process a chat completion request
+//! let request = bytes::Bytes::from(r#"{"model": "gpt-4o", "messages": []}"#);
 //! let response = pipeline.execute(request).await?;
+//! # Ok(())
+//! # }
 //! ```
 //!
 //! ## Configuration
@@ -56,51 +91,82 @@
 //! token_env = "OPENAI_API_KEY"
 //! supports_streaming = true
 //! ```
+//!
+//! Azure `OpenAI` deployments use the `azure_openai` provider, with the resource
+//! endpoint as `base_url` and the deployment details in `additional_config`:
+//!
+//! ```toml
+//! [llm.azure_chat]
+//! provider = "azure_openai"
+//! type = "chat"
+//! base_url = "https://my-resource.openai.azure.com"
+//! token_env = "AZURE_OPENAI_API_KEY"
+//! supports_streaming = true
+//! additional_config = { deployment = "gpt-4o", api_version = "2024-10-21" }
+//! ```
+//!
+//! To authenticate with Microsoft Entra ID instead of an API key, set a
+//! `token_source` of type `azure_entra`; tokens are then sent as bearer tokens:
+//!
+//! ```toml
+//! [llm.azure_chat.token_source]
+//! type = "azure_entra"
+//! # Omit these three to use the managed identity of the host
+//! tenant_id = "00000000-0000-0000-0000-000000000000"
+//! client_id = "00000000-0000-0000-0000-000000000000"
+//! client_secret_env = "AZURE_CLIENT_SECRET"
+//! ```
 
+pub mod cache;
 pub mod client;
+pub mod factory;
+pub mod passthrough;
+pub mod processors;
 pub mod providers;
+pub mod quirks;
+pub mod replay;
+pub mod stream_processors;
 pub mod types;
 
 use std::sync::Arc;
 
-use llm_proxy_core::{Pipeline, ProcessorChain};
+use llm_proxy_core::{AuthScheme, Pipeline, ProcessorChain};
 
-pub use client::OpenAIClient;
-pub use providers::{EnvTokenProvider, OpenAIRequestParser, OpenAIUrlProvider};
-use providers::{StaticClientProvider, StaticTokenProvider};
+pub use client::{OpenAIClient, ORGANIZATION_ATTRIBUTE, PROJECT_ATTRIBUTE};
+pub use passthrough::{OpenAIPassthroughClient, PassthroughRequest, PassthroughResponse};
+use providers::StaticClientProvider;
+pub use providers::{
+    AzureCredential, AzureEntraTokenProvider, AzureOpenAIUrlProvider, EnvTokenProvider,
+    OpenAIRequestParser, OpenAIUrlProvider,
+};
+pub use quirks::Quirks;
 pub use types::*;
 
-use llm_proxy_core::Processor;
+use llm_proxy_core::{Processor, TokenProvider, UrlProvider};
 
 /// Create a new pipeline configured for `OpenAI`'s chat completion API.
 ///
 /// # Arguments
 /// * `processors` - Optional list of processors to apply to requests
-/// * `token_env_var` - Environment variable containing the `OpenAI` API key (default:`OPENAI_API_KEY`EY")
+/// * `token_env_var` - Environment variable containing the `OpenAI` API key (default: `OPENAI_API_KEY`)
 /// * `base_url` - Optional base URL for the API (default: "<https://api.openai.com/v1/chat/completions>")
-/// * `route_config` - Optional route configuration for the `RequestParser`
 ///
 /// # Returns
 /// A pipeline configured with OpenAI-specific components
 ///
 /// # Example
 /// ```rust
-/// use llm_proxy_openai::create_chat_pipeline;
-/// use llm_proxy_core::traits::Processor;
+/// use llm_proxy_openai::{create_chat_pipeline, processors::VisionProcessor};
 /// use std::sync::Arc;
 ///
 /// // Create a pipeline with no processors
-/// let simple_pipeline = create_chat_pipeline(vec![], None, None, None);
+/// let simple_pipeline = create_chat_pipeline(vec![], None, None);
 ///
 /// // Create a pipeline with custom processors and API key env var
-/// let processors = vec![
-///     Arc::new(my_custom_processor::new())
-/// ];
 /// let pipeline = create_chat_pipeline(
-///     processors,
+///     vec![Arc::new(VisionProcessor::new().with_max_images(4))],
 ///     Some("MY_OPENAI_KEY"),
 ///     None,
-///     None
 /// );
 /// ```
 #[must_use]
@@ -109,18 +175,114 @@ pub fn create_chat_pipeline(
     token_env_var: Option<&str>,
     base_url: Option<&str>,
 ) -> Pipeline<ChatCompletionRequest> {
-    let client_provider = Arc::new(StaticClientProvider::new());
-    let token_provider = Arc::new(StaticTokenProvider::new(token_env_var.unwrap_or("")));
+    let token_provider = Arc::new(EnvTokenProvider::new(
+        token_env_var.unwrap_or("OPENAI_API_KEY"),
+    ));
+    create_pipeline_with_client(processors, create_chat_client(token_provider, base_url))
+}
+
+/// Create an `OpenAI` client for the chat completion API.
+///
+/// The client can be customized further (e.g. with organization and project
+/// headers) before being passed to [`create_pipeline_with_client`].
+#[must_use]
+pub fn create_chat_client(
+    token_provider: Arc<dyn TokenProvider>,
+    base_url: Option<&str>,
+) -> OpenAIClient {
     let url_provider = Arc::new(OpenAIUrlProvider::new(
         base_url.unwrap_or("https://api.openai.com/v1/chat/completions"),
     ));
+    create_chat_client_with_url_provider(token_provider, url_provider)
+}
+
+/// Create an `OpenAI` client whose request URL is chosen by `url_provider`.
+///
+/// Use this with a [`LoadBalancingUrlProvider`](llm_proxy_core::providers::LoadBalancingUrlProvider)
+/// to spread requests across several OpenAI-compatible endpoints.
+#[must_use]
+pub fn create_chat_client_with_url_provider(
+    token_provider: Arc<dyn TokenProvider>,
+    url_provider: Arc<dyn UrlProvider>,
+) -> OpenAIClient {
+    let client_provider = Arc::new(StaticClientProvider::new());
+    OpenAIClient::new(client_provider, token_provider, url_provider)
+}
+
+/// Create a new pipeline configured for an Azure `OpenAI` chat deployment.
+///
+/// Requests are sent to the deployment's chat completions URL and authenticated
+/// with the `api-key` header instead of a bearer token.
+///
+/// # Arguments
+/// * `processors` - List of processors to apply to requests
+/// * `token_env_var` - Environment variable containing the Azure `OpenAI` API key
+/// * `endpoint` - The Azure resource endpoint, e.g. `https://my-resource.openai.azure.com`
+/// * `deployment` - Name of the model deployment
+/// * `api_version` - Azure `OpenAI` API version (default: [`AzureOpenAIUrlProvider::DEFAULT_API_VERSION`])
+#[must_use]
+pub fn create_azure_chat_pipeline(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    token_env_var: &str,
+    endpoint: &str,
+    deployment: &str,
+    api_version: Option<&str>,
+) -> Pipeline<ChatCompletionRequest> {
+    create_pipeline_with_client(
+        processors,
+        create_azure_chat_client(
+            Arc::new(EnvTokenProvider::new(token_env_var)),
+            endpoint,
+            deployment,
+            api_version,
+        ),
+    )
+}
+
+/// Create an `OpenAI` client for an Azure `OpenAI` chat deployment.
+///
+/// See [`create_azure_chat_pipeline`] for a description of the remaining arguments.
+#[must_use]
+pub fn create_azure_chat_client(
+    token_provider: Arc<dyn TokenProvider>,
+    endpoint: &str,
+    deployment: &str,
+    api_version: Option<&str>,
+) -> OpenAIClient {
+    let client_provider = Arc::new(StaticClientProvider::new());
+    let url_provider = Arc::new(AzureOpenAIUrlProvider::new(
+        endpoint,
+        deployment,
+        api_version.unwrap_or(AzureOpenAIUrlProvider::DEFAULT_API_VERSION),
+    ));
+    OpenAIClient::new(client_provider, token_provider, url_provider)
+        .with_auth_scheme(AuthScheme::api_key_header())
+}
+
+/// Create a chat completion pipeline around an existing `OpenAI` client.
+#[must_use]
+pub fn create_pipeline_with_client(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    llm_client: OpenAIClient,
+) -> Pipeline<ChatCompletionRequest> {
     let parser = Arc::new(OpenAIRequestParser::new());
     let processor_chain = Arc::new(ProcessorChain::new(processors));
-    let llm_client = Arc::new(OpenAIClient::new(
+
+    Pipeline::new(parser, processor_chain, Arc::new(llm_client))
+}
+
+/// Create a client that forwards raw requests to an `OpenAI` API family endpoint.
+///
+/// Used for passthrough routes such as `/v1/assistants` and `/v1/threads`.
+#[must_use]
+pub fn create_passthrough_client(
+    token_provider: Arc<dyn TokenProvider>,
+    base_url: Option<&str>,
+) -> OpenAIPassthroughClient {
+    let client_provider = Arc::new(StaticClientProvider::new());
+    OpenAIPassthroughClient::new(
         client_provider,
         token_provider,
-        url_provider,
-    ));
-
-    Pipeline::new(parser, processor_chain, llm_client)
+        base_url.unwrap_or("https://api.openai.com/v1"),
+    )
 }
@@ -0,0 +1,310 @@
+use std::sync::Arc;
+
+use futures_util::StreamExt;
+use llm_proxy_core::{
+    telemetry, AuthScheme, ClientProvider, Error, RequestContext, RequestSigner, ResponseStream,
+    Result, TokenProvider, REQUEST_ID_HEADER,
+};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::client::{ORGANIZATION_ATTRIBUTE, PROJECT_ATTRIBUTE};
+
+/// Inbound request headers that are forwarded to the upstream API
+const FORWARDED_HEADERS: [&str; 4] = ["content-type", "content-length", "accept", "openai-beta"];
+
+/// Upstream response headers that are returned to the client
+const RETURNED_HEADERS: [&str; 2] = ["content-type", "content-disposition"];
+
+/// A raw API request forwarded without being parsed
+#[derive(Debug)]
+pub struct PassthroughRequest {
+    /// HTTP method of the request
+    pub method: reqwest::Method,
+    /// Path of the request, forwarded unchanged (e.g. `/v1/threads/thread_abc/runs`)
+    pub path: String,
+    /// Query string of the request, without the leading `?`
+    pub query: Option<String>,
+    /// Inbound headers; only a small allowlist is forwarded upstream
+    pub headers: Vec<(String, String)>,
+    /// Request body; use [`reqwest::Body::wrap_stream`] to forward large uploads
+    /// (e.g. multipart file uploads) without buffering them
+    pub body: reqwest::Body,
+}
+
+/// The upstream response to a [`PassthroughRequest`]
+pub struct PassthroughResponse {
+    /// HTTP status returned by the upstream API
+    pub status: u16,
+    /// Allowlisted headers returned by the upstream API, such as `content-type`
+    pub headers: Vec<(String, String)>,
+    /// Response body chunks, streamed as they arrive
+    pub body: ResponseStream,
+}
+
+/// Client that forwards raw requests to an `OpenAI` API family endpoint.
+///
+/// Used for APIs that don't go through the chat completion pipeline, like the
+/// Assistants, Threads, Files and fine-tuning APIs, so that API keys stay
+/// centralized in the proxy. Request and response bodies are streamed in both
+/// directions, so large file uploads and downloads are never fully buffered.
+/// The request path is forwarded unchanged to the host of the configured base URL,
+/// and responses (including run event streams) are streamed back untouched.
+pub struct OpenAIPassthroughClient {
+    client: Arc<dyn ClientProvider>,
+    token: Arc<dyn TokenProvider>,
+    base_url: String,
+    auth_scheme: AuthScheme,
+    signer: Option<Arc<dyn RequestSigner>>,
+    headers: Vec<(String, String)>,
+}
+
+impl OpenAIPassthroughClient {
+    /// Create a new passthrough client for the API hosted at `base_url`
+    pub fn new(
+        client_provider: Arc<dyn ClientProvider>,
+        token_provider: Arc<dyn TokenProvider>,
+        base_url: impl Into<String>,
+    ) -> Self {
+        Self {
+            client: client_provider,
+            token: token_provider,
+            base_url: base_url.into(),
+            auth_scheme: AuthScheme::default(),
+            signer: None,
+            headers: Vec::new(),
+        }
+    }
+
+    /// Replace the provider of the HTTP client used for upstream requests
+    #[must_use]
+    pub fn with_client_provider(mut self, client_provider: Arc<dyn ClientProvider>) -> Self {
+        self.client = client_provider;
+        self
+    }
+
+    /// Set how the API token is sent to the upstream service
+    #[must_use]
+    pub fn with_auth_scheme(mut self, auth_scheme: AuthScheme) -> Self {
+        self.auth_scheme = auth_scheme;
+        self
+    }
+
+    /// Sign every request just before it is sent, e.g. with AWS `SigV4`
+    #[must_use]
+    pub fn with_request_signer(mut self, signer: Arc<dyn RequestSigner>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Send a static header with every request, e.g. for an LLM gateway in front of the API
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Build the upstream URL for the given path and query
+    fn upstream_url(&self, path: &str, query: Option<&str>) -> Result<reqwest::Url> {
+        let mut url = reqwest::Url::parse(&self.base_url)
+            .map_err(|e| Error::ConfigError(format!("Invalid base URL {}: {e}", self.base_url)))?;
+        url.set_path(path);
+        url.set_query(query);
+        Ok(url)
+    }
+
+    /// Forward a request upstream and stream back the response
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the upstream request cannot be sent.
+    /// Upstream error statuses are returned as a normal [`PassthroughResponse`].
+    pub async fn forward(&self, request: PassthroughRequest) -> Result<PassthroughResponse> {
+        self.forward_with_context(request, &RequestContext::new())
+            .await
+    }
+
+    /// Forward a request upstream on behalf of the request context's tenant
+    ///
+    /// The token is resolved for the context, and the tenant's organization and
+    /// project, if any, are sent along.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the upstream request cannot be sent.
+    /// Upstream error statuses are returned as a normal [`PassthroughResponse`].
+    pub async fn forward_with_context(
+        &self,
+        request: PassthroughRequest,
+        context: &RequestContext,
+    ) -> Result<PassthroughResponse> {
+        let client = self
+            .client
+            .get_client()
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get HTTP client: {e}")))?;
+        let token = self
+            .token
+            .get_token_for(context)
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get API token: {e}")))?;
+        let url = self.upstream_url(&request.path, request.query.as_deref())?;
+
+        info!(method = %request.method, path = %request.path, "Forwarding passthrough request");
+
+        let mut builder = self
+            .auth_scheme
+            .apply(client.request(request.method, url), &token);
+        // Headers the route forwards itself are sent once, under its names
+        for (name, value) in &request.headers {
+            let routed = context
+                .upstream_headers
+                .iter()
+                .any(|(upstream_name, _)| upstream_name.eq_ignore_ascii_case(name));
+            if is_listed(&FORWARDED_HEADERS, name) && !routed {
+                builder = builder.header(name, value);
+            }
+        }
+        for (name, value) in context.upstream_headers.iter().chain(&self.headers) {
+            builder = builder.header(name, value);
+        }
+        if let Some(organization) = context.attributes.get(ORGANIZATION_ATTRIBUTE) {
+            builder = builder.header("OpenAI-Organization", organization);
+        }
+        if let Some(project) = context.attributes.get(PROJECT_ATTRIBUTE) {
+            builder = builder.header("OpenAI-Project", project);
+        }
+        if let Some(request_id) = &context.request_id {
+            builder = builder.header(REQUEST_ID_HEADER, request_id);
+        }
+        for (name, value) in telemetry::trace_headers() {
+            builder = builder.header(name, value);
+        }
+
+        let mut upstream_request = builder
+            .body(request.body)
+            .build()
+            .map_err(|e| Error::LLMError(format!("Failed to build upstream request: {e}")))?;
+        if let Some(signer) = &self.signer {
+            signer.sign(&mut upstream_request).await?;
+        }
+        let response = client
+            .execute(upstream_request)
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to forward request: {e}")))?;
+
+        let status = response.status().as_u16();
+        if !response.status().is_success() {
+            self.token.report_rejection(&token, status).await;
+        }
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| is_listed(&RETURNED_HEADERS, name.as_str()))
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|value| (name.to_string(), value.to_string()))
+            })
+            .collect();
+
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            let mut stream = response.bytes_stream();
+            while let Some(chunk) = stream.next().await {
+                let chunk =
+                    chunk.map_err(|e| Error::LLMError(format!("Error reading upstream body: {e}")));
+                if tx.send(chunk).await.is_err() {
+                    warn!("Failed to send passthrough chunk - receiver dropped");
+                    break;
+                }
+            }
+        });
+
+        Ok(PassthroughResponse {
+            status,
+            headers,
+            body: rx,
+        })
+    }
+}
+
+/// Check whether a header name is in an allowlist, ignoring case
+fn is_listed(allowlist: &[&str], name: &str) -> bool {
+    allowlist
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{StaticClientProvider, StaticTokenProvider};
+
+    #[test]
+    fn test_upstream_url_keeps_inbound_path() {
+        let client = OpenAIPassthroughClient::new(
+            Arc::new(StaticClientProvider::new()),
+            Arc::new(StaticTokenProvider::new("test-token")),
+            "https://api.openai.com/v1",
+        );
+
+        let url = client
+            .upstream_url("/v1/threads/thread_abc/runs", Some("limit=10"))
+            .expect("Failed to build URL");
+        assert_eq!(
+            url.as_str(),
+            "https://api.openai.com/v1/threads/thread_abc/runs?limit=10"
+        );
+    }
+
+    #[test]
+    fn test_header_allowlist() {
+        assert!(is_listed(&FORWARDED_HEADERS, "Content-Type"));
+        assert!(is_listed(&FORWARDED_HEADERS, "OpenAI-Beta"));
+        assert!(!is_listed(&FORWARDED_HEADERS, "Authorization"));
+        assert!(is_listed(&RETURNED_HEADERS, "Content-Disposition"));
+    }
+
+    #[tokio::test]
+    async fn test_static_headers() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // An upstream answering one request, returning its head
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let address = listener.local_addr().expect("No local address");
+        let upstream = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.expect("Failed to accept");
+            let mut request = [0; 4096];
+            let read = stream.read(&mut request).await.expect("Failed to read");
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}")
+                .await;
+            String::from_utf8_lossy(&request[..read]).to_lowercase()
+        });
+
+        let client = OpenAIPassthroughClient::new(
+            Arc::new(StaticClientProvider::new()),
+            Arc::new(StaticTokenProvider::new("test-token")),
+            format!("http://{address}/v1"),
+        )
+        .with_header("Helicone-Auth", "Bearer sk-helicone");
+        let request = PassthroughRequest {
+            method: reqwest::Method::GET,
+            path: "/v1/files".to_string(),
+            query: None,
+            headers: vec![("X-Other".to_string(), "other".to_string())],
+            body: reqwest::Body::from(""),
+        };
+        let response = client.forward(request).await.expect("Failed to forward");
+        assert_eq!(response.status, 200);
+
+        let head = upstream.await.expect("Upstream failed");
+        assert!(head.contains("helicone-auth: bearer sk-helicone"), "{head}");
+        assert!(head.contains("authorization: bearer test-token"), "{head}");
+        assert!(!head.contains("x-other"), "{head}");
+    }
+}
@@ -0,0 +1,46 @@
+//! Request processors for `OpenAI` chat completion requests.
+//!
+//! These processors implement the [`Processor`](llm_proxy_core::Processor) trait for
+//! [`ChatCompletionRequest`](crate::ChatCompletionRequest) and can be combined into a
+//! [`ProcessorChain`](llm_proxy_core::ProcessorChain).
+
+pub mod attribution;
+pub mod cache_policy;
+pub mod content_filter;
+pub mod context_window;
+pub mod experiment;
+pub mod history;
+pub mod injection;
+pub mod logging;
+pub mod model_rewrite;
+pub mod moderation;
+pub mod normalize;
+pub mod openrouter;
+pub mod request_log;
+pub mod system_prompt;
+pub mod tool_allowlist;
+pub mod tool_schema;
+pub mod vision;
+
+pub use attribution::{UserAttributionProcessor, UserSource};
+pub use cache_policy::CachePolicyProcessor;
+pub use content_filter::{ContentFilterProcessor, FilterAction, FilterRule};
+pub use context_window::{
+    ApproximateTokenCounter, ContextWindowProcessor, TokenCounter, TruncationStrategy,
+};
+pub use experiment::{ExperimentArm, ExperimentProcessor};
+pub use history::ConversationHistoryProcessor;
+pub use injection::{
+    InjectionAction, InjectionClassifier, LLMInjectionClassifier, PromptInjectionProcessor,
+    Sensitivity,
+};
+pub use logging::LoggingProcessor;
+pub use model_rewrite::ModelRewriteProcessor;
+pub use moderation::{ModerationAction, ModerationProcessor};
+pub use normalize::NormalizeProcessor;
+pub use openrouter::OpenRouterProcessor;
+pub use request_log::{HttpSink, RequestLogProcessor, RequestLogSink, RollingFileSink, StdoutSink};
+pub use system_prompt::{SystemPromptMode, SystemPromptProcessor};
+pub use tool_allowlist::ToolAllowlistProcessor;
+pub use tool_schema::ToolSchemaProcessor;
+pub use vision::VisionProcessor;
@@ -0,0 +1,220 @@
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use llm_proxy_core::{Processor, RequestContext, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::types::ChatCompletionRequest;
+
+/// Context attribute with the end user a request was attributed to
+pub const USER_ATTRIBUTE: &str = "user.id";
+
+/// Where a [`UserAttributionProcessor`] finds the end user of a request
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UserSource {
+    /// The tenant of the client API key
+    Tenant,
+    /// A stable ID derived from the client API key, without revealing it
+    ApiKey,
+    /// A header of the inbound request
+    Header {
+        /// Name of the header
+        name: String,
+    },
+    /// The `sub` claim of the JWT in the `Authorization` header.
+    ///
+    /// The token's signature isn't checked; authenticate clients before
+    /// relying on the subject for anything but attribution.
+    JwtSubject,
+    /// An attribute of the request context, e.g. set by another processor
+    Attribute {
+        /// Name of the attribute
+        name: String,
+    },
+}
+
+impl UserSource {
+    /// The end user of the request with `context`, if this source knows it
+    fn resolve(&self, context: &RequestContext) -> Option<String> {
+        match self {
+            Self::Tenant => context.tenant.clone(),
+            Self::ApiKey => bearer_token(context).map(|key| {
+                let digest = hex::encode(Sha256::digest(key));
+                format!("key-{}", &digest[..16])
+            }),
+            Self::Header { name } => context.header(name).map(str::to_string),
+            Self::JwtSubject => {
+                let payload = bearer_token(context)?.split('.').nth(1)?;
+                let claims: Value =
+                    serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+                claims["sub"].as_str().map(str::to_string)
+            }
+            Self::Attribute { name } => context.attributes.get(name).cloned(),
+        }
+        .filter(|user| !user.is_empty())
+    }
+}
+
+/// The bearer token of the inbound request
+fn bearer_token(context: &RequestContext) -> Option<&str> {
+    context.header("authorization")?.strip_prefix("Bearer ")
+}
+
+/// Processor that attributes requests to their end user, so upstream abuse
+/// monitoring and internal analytics can tell users apart.
+///
+/// The first source that knows the user sets the `OpenAI` `user` field,
+/// replacing the client's value unless configured otherwise, and optionally a
+/// `metadata` entry. The user is also recorded in the request context as the
+/// [`USER_ATTRIBUTE`] attribute.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::processors::{UserAttributionProcessor, UserSource};
+///
+/// let processor = UserAttributionProcessor::new()
+///     .with_source(UserSource::JwtSubject)
+///     .with_source(UserSource::Tenant)
+///     .with_metadata_key("end_user")
+///     .with_hashed(true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct UserAttributionProcessor {
+    sources: Vec<UserSource>,
+    metadata_key: Option<String>,
+    overwrite: bool,
+    hashed: bool,
+}
+
+impl Default for UserAttributionProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UserAttributionProcessor {
+    /// Create a processor without sources, which replaces client values
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+            metadata_key: None,
+            overwrite: true,
+            hashed: false,
+        }
+    }
+
+    /// Add a source, tried after the sources added before
+    #[must_use]
+    pub fn with_source(mut self, source: UserSource) -> Self {
+        self.sources.push(source);
+        self
+    }
+
+    /// Also set the user as the `metadata` entry `key`
+    #[must_use]
+    pub fn with_metadata_key(mut self, key: impl Into<String>) -> Self {
+        self.metadata_key = Some(key.into());
+        self
+    }
+
+    /// Set whether the user replaces a `user` the client sent
+    #[must_use]
+    pub const fn with_overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Set whether the user is sent as a SHA-256 hash instead of as is
+    #[must_use]
+    pub const fn with_hashed(mut self, hashed: bool) -> Self {
+        self.hashed = hashed;
+        self
+    }
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for UserAttributionProcessor {
+    async fn process(&self, request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        self.process_with_context(request, &mut RequestContext::new())
+            .await
+    }
+
+    async fn process_with_context(
+        &self,
+        mut request: ChatCompletionRequest,
+        context: &mut RequestContext,
+    ) -> Result<ChatCompletionRequest> {
+        let Some(user) = self
+            .sources
+            .iter()
+            .find_map(|source| source.resolve(context))
+        else {
+            return Ok(request);
+        };
+        let user = if self.hashed {
+            hex::encode(Sha256::digest(&user))
+        } else {
+            user
+        };
+
+        if self.overwrite || !request.additional_params.contains_key("user") {
+            request
+                .additional_params
+                .insert("user".to_string(), Value::String(user.clone()));
+        }
+        if let Some(key) = &self.metadata_key {
+            let metadata = request
+                .additional_params
+                .entry("metadata".to_string())
+                .or_insert_with(|| Value::Object(serde_json::Map::new()));
+            if let Value::Object(metadata) = metadata {
+                metadata.insert(key.clone(), Value::String(user.clone()));
+            }
+        }
+        context.attributes.insert(USER_ATTRIBUTE.to_string(), user);
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_attribution() {
+        let claims = URL_SAFE_NO_PAD.encode(r#"{"sub":"user-42","iss":"idp"}"#);
+        let mut context = RequestContext::new().with_tenant("acme").with_header(
+            "Authorization",
+            format!("Bearer eyJhbGciOiJIUzI1NiJ9.{claims}.sig"),
+        );
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "user": "spoofed",
+        }))
+        .expect("Failed to build request");
+
+        let processed = UserAttributionProcessor::new()
+            .with_source(UserSource::Header {
+                name: "X-End-User".to_string(),
+            })
+            .with_source(UserSource::JwtSubject)
+            .with_source(UserSource::Tenant)
+            .with_metadata_key("end_user")
+            .process_with_context(request, &mut context)
+            .await
+            .expect("Failed to process request");
+        assert_eq!(processed.additional_params["user"], "user-42");
+        assert_eq!(
+            processed.additional_params["metadata"],
+            json!({"end_user": "user-42"})
+        );
+        assert_eq!(context.attributes[USER_ATTRIBUTE], "user-42");
+    }
+}
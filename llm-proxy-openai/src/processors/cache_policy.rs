@@ -0,0 +1,247 @@
+use async_trait::async_trait;
+use llm_proxy_core::{Processor, RequestContext, Result};
+use tracing::debug;
+
+use crate::types::ChatCompletionRequest;
+
+/// Context attribute telling whether the response may be served from a
+/// cache, `"true"` or `"false"`
+pub const CACHE_LOOKUP_ATTRIBUTE: &str = "cache.lookup";
+
+/// Context attribute telling whether the response may be stored in a cache,
+/// `"true"` or `"false"`
+pub const CACHE_STORE_ATTRIBUTE: &str = "cache.store";
+
+/// Temperature `OpenAI` samples with when a request doesn't set one
+const DEFAULT_TEMPERATURE: f32 = 1.0;
+
+/// Processor that decides whether a request's response may be cached, from
+/// the client's `Cache-Control` header and the route's rules.
+///
+/// `no-store` keeps the response out of the cache entirely, while
+/// `no-cache` (or `max-age=0`) skips the lookup but lets the fresh response
+/// be stored. Requests excluded by the rules, such as those with tools or
+/// sampling above a temperature, are neither looked up nor stored. The
+/// decision is recorded in the request context as the
+/// [`CACHE_LOOKUP_ATTRIBUTE`] and [`CACHE_STORE_ATTRIBUTE`] attributes, which
+/// a [`CachingLLMClient`](crate::cache::CachingLLMClient) follows.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::processors::CachePolicyProcessor;
+///
+/// // Only cache deterministic requests without tools
+/// let processor = CachePolicyProcessor::new()
+///     .with_exclude_tools(true)
+///     .with_max_temperature(0.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CachePolicyProcessor {
+    models: Vec<String>,
+    exclude_tools: bool,
+    max_temperature: Option<f32>,
+    client_headers: bool,
+}
+
+impl Default for CachePolicyProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CachePolicyProcessor {
+    /// Create a processor allowing every request to be cached, unless the
+    /// client's headers say otherwise
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            models: Vec::new(),
+            exclude_tools: false,
+            max_temperature: None,
+            client_headers: true,
+        }
+    }
+
+    /// Only cache requests for models starting with `prefix`; once a prefix
+    /// is added, requests for other models aren't cached
+    #[must_use]
+    pub fn with_model(mut self, prefix: impl Into<String>) -> Self {
+        self.models.push(prefix.into());
+        self
+    }
+
+    /// Set whether requests with tools or functions are never cached
+    #[must_use]
+    pub const fn with_exclude_tools(mut self, exclude_tools: bool) -> Self {
+        self.exclude_tools = exclude_tools;
+        self
+    }
+
+    /// Never cache requests sampling above `temperature`; requests without a
+    /// temperature sample at 1
+    #[must_use]
+    pub const fn with_max_temperature(mut self, temperature: f32) -> Self {
+        self.max_temperature = Some(temperature);
+        self
+    }
+
+    /// Set whether the client's `Cache-Control` header is honored
+    #[must_use]
+    pub const fn with_client_headers(mut self, client_headers: bool) -> Self {
+        self.client_headers = client_headers;
+        self
+    }
+
+    /// Why the rules exclude `request` from the cache, if they do
+    fn excluded(&self, request: &ChatCompletionRequest) -> Option<&'static str> {
+        if !self.models.is_empty()
+            && !self
+                .models
+                .iter()
+                .any(|prefix| request.model.starts_with(prefix.as_str()))
+        {
+            return Some("model");
+        }
+        let has_tools = request.functions.as_ref().is_some_and(|f| !f.is_empty())
+            || request
+                .additional_params
+                .get("tools")
+                .and_then(|tools| tools.as_array())
+                .is_some_and(|tools| !tools.is_empty());
+        if self.exclude_tools && has_tools {
+            return Some("tools");
+        }
+        let temperature = request.temperature.unwrap_or(DEFAULT_TEMPERATURE);
+        if self.max_temperature.is_some_and(|max| temperature > max) {
+            return Some("temperature");
+        }
+        None
+    }
+
+    /// Whether the response to `request` may be looked up and stored
+    fn decide(&self, request: &ChatCompletionRequest, context: &RequestContext) -> (bool, bool) {
+        if let Some(reason) = self.excluded(request) {
+            debug!(reason, "Request excluded from the cache");
+            return (false, false);
+        }
+        let Some(header) = context
+            .header("cache-control")
+            .filter(|_| self.client_headers)
+        else {
+            return (true, true);
+        };
+        let (no_lookup, no_store) = cache_control(header);
+        (!no_lookup, !no_store)
+    }
+}
+
+/// The directives of a `Cache-Control` header, as (no lookup, no store)
+fn cache_control(header: &str) -> (bool, bool) {
+    header
+        .split(',')
+        .map(|directive| directive.trim().to_ascii_lowercase())
+        .fold(
+            (false, false),
+            |(no_lookup, no_store), directive| match directive.as_str() {
+                "no-store" => (true, true),
+                "no-cache" | "max-age=0" => (true, no_store),
+                _ => (no_lookup, no_store),
+            },
+        )
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for CachePolicyProcessor {
+    async fn process(&self, request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        self.process_with_context(request, &mut RequestContext::new())
+            .await
+    }
+
+    async fn process_with_context(
+        &self,
+        request: ChatCompletionRequest,
+        context: &mut RequestContext,
+    ) -> Result<ChatCompletionRequest> {
+        let (lookup, store) = self.decide(&request, context);
+        context
+            .attributes
+            .insert(CACHE_LOOKUP_ATTRIBUTE.to_string(), lookup.to_string());
+        context
+            .attributes
+            .insert(CACHE_STORE_ATTRIBUTE.to_string(), store.to_string());
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cache_policy() {
+        let processor = CachePolicyProcessor::new()
+            .with_exclude_tools(true)
+            .with_max_temperature(0.0);
+        let request = |body| -> ChatCompletionRequest {
+            serde_json::from_value(body).expect("Failed to build request")
+        };
+        let decide = |body, context: RequestContext| {
+            let processor = processor.clone();
+            async move {
+                let mut context = context;
+                processor
+                    .process_with_context(request(body), &mut context)
+                    .await
+                    .expect("Failed to process request");
+                (
+                    context.attributes[CACHE_LOOKUP_ATTRIBUTE].clone(),
+                    context.attributes[CACHE_STORE_ATTRIBUTE].clone(),
+                )
+            }
+        };
+
+        let deterministic = json!({"model": "gpt-4o", "messages": [], "temperature": 0.0});
+        assert_eq!(
+            decide(deterministic.clone(), RequestContext::new()).await,
+            ("true".to_string(), "true".to_string())
+        );
+        assert_eq!(
+            decide(
+                deterministic.clone(),
+                RequestContext::new().with_header("Cache-Control", "no-cache")
+            )
+            .await,
+            ("false".to_string(), "true".to_string())
+        );
+        assert_eq!(
+            decide(
+                deterministic,
+                RequestContext::new().with_header("Cache-Control", "private, no-store")
+            )
+            .await,
+            ("false".to_string(), "false".to_string())
+        );
+        // Sampled at the default temperature
+        assert_eq!(
+            decide(
+                json!({"model": "gpt-4o", "messages": []}),
+                RequestContext::new()
+            )
+            .await,
+            ("false".to_string(), "false".to_string())
+        );
+        let with_tools = json!({
+            "model": "gpt-4o",
+            "messages": [],
+            "temperature": 0.0,
+            "tools": [{"type": "function", "function": {"name": "lookup"}}],
+        });
+        assert_eq!(
+            decide(with_tools, RequestContext::new()).await,
+            ("false".to_string(), "false".to_string())
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use llm_proxy_core::{Error, Processor, Result};
+use regex::Regex;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::types::{ChatCompletionRequest, ContentPart, MessageContent};
+
+/// Text that replaces redacted matches, unless configured otherwise
+pub const DEFAULT_REPLACEMENT: &str = "[REDACTED]";
+
+/// What a [`ContentFilterProcessor`] does with a request matching a rule
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    /// Refuse the request, which the server answers with 400
+    #[default]
+    Reject,
+    /// Replace the matches in the messages before the request is sent
+    Redact,
+    /// Send the request unchanged and log the rule it matched
+    Annotate,
+}
+
+/// A rule of a [`ContentFilterProcessor`]: a blocklist or a regular expression
+#[derive(Debug, Clone)]
+pub struct FilterRule {
+    name: String,
+    pattern: Regex,
+    action: FilterAction,
+}
+
+impl FilterRule {
+    /// Create a rule matching any of `words`, case-insensitively and as whole
+    /// words; entries may be phrases
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `words` is empty or too large
+    /// to compile.
+    pub fn words<S: AsRef<str>>(
+        name: impl Into<String>,
+        words: impl IntoIterator<Item = S>,
+        action: FilterAction,
+    ) -> Result<Self> {
+        let name = name.into();
+        let Some(pattern) = words_pattern(words) else {
+            return Err(Error::ConfigError(format!(
+                "Content filter rule {name} has no words"
+            )));
+        };
+        Self::pattern(name, &pattern, action)
+    }
+
+    /// Create a rule matching the regular expression `pattern`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `pattern` is not a valid regular
+    /// expression.
+    pub fn pattern(name: impl Into<String>, pattern: &str, action: FilterAction) -> Result<Self> {
+        let name = name.into();
+        let pattern = Regex::new(pattern).map_err(|e| {
+            Error::ConfigError(format!(
+                "Invalid pattern of content filter rule {name}: {e}"
+            ))
+        })?;
+        Ok(Self {
+            name,
+            pattern,
+            action,
+        })
+    }
+}
+
+/// Regular expression matching any of `words`, case-insensitively and as
+/// whole words, or `None` if there are no words
+pub(crate) fn words_pattern<S: AsRef<str>>(words: impl IntoIterator<Item = S>) -> Option<String> {
+    let alternatives: Vec<String> = words
+        .into_iter()
+        .map(|word| word.as_ref().trim().to_string())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+            let start = if word.starts_with(is_word_char) {
+                r"\b"
+            } else {
+                ""
+            };
+            let end = if word.ends_with(is_word_char) {
+                r"\b"
+            } else {
+                ""
+            };
+            format!("{start}{}{end}", regex::escape(&word))
+        })
+        .collect();
+    (!alternatives.is_empty()).then(|| format!("(?i)(?:{})", alternatives.join("|")))
+}
+
+/// Read a blocklist file: one word or phrase per line, ignoring blank lines
+/// and lines starting with `#`
+///
+/// # Errors
+///
+/// This function will return an error if the file can't be read.
+pub fn read_word_list(path: impl AsRef<Path>) -> Result<Vec<String>> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        Error::ConfigError(format!("Failed to read blocklist {}: {e}", path.display()))
+    })?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Processor that enforces acceptable use on the text of chat requests.
+///
+/// Rules are checked in order against the text of every message. A request
+/// matching a `Reject` rule is refused with [`Error::Rejected`], naming the
+/// rule but not the matched text; `Redact` rules replace their matches;
+/// `Annotate` rules only log the match.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::processors::{ContentFilterProcessor, FilterAction, FilterRule};
+///
+/// let processor = ContentFilterProcessor::new()
+///     .with_rule(FilterRule::words("banned", ["forbidden phrase"], FilterAction::Reject)?)
+///     .with_rule(FilterRule::pattern("emails", r"[\w.+-]+@[\w-]+\.[\w.]+", FilterAction::Redact)?);
+/// # Ok::<(), llm_proxy_core::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ContentFilterProcessor {
+    rules: Vec<FilterRule>,
+    replacement: String,
+}
+
+impl Default for ContentFilterProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContentFilterProcessor {
+    /// Create a processor without rules
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+            replacement: DEFAULT_REPLACEMENT.to_string(),
+        }
+    }
+
+    /// Add a rule, checked after the rules added before
+    #[must_use]
+    pub fn with_rule(mut self, rule: FilterRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Set the text that replaces redacted matches
+    #[must_use]
+    pub fn with_replacement(mut self, replacement: impl Into<String>) -> Self {
+        self.replacement = replacement.into();
+        self
+    }
+
+    /// Apply `rule` to one text of a request
+    fn apply(&self, rule: &FilterRule, text: &mut String) -> Result<()> {
+        if !rule.pattern.is_match(text) {
+            return Ok(());
+        }
+        match rule.action {
+            FilterAction::Reject => {
+                return Err(Error::Rejected(format!(
+                    "Content matches the {} filter",
+                    rule.name
+                )))
+            }
+            FilterAction::Redact => {
+                *text = rule
+                    .pattern
+                    .replace_all(text, regex::NoExpand(&self.replacement))
+                    .into_owned();
+            }
+            FilterAction::Annotate => warn!(rule = %rule.name, "Request matches content filter"),
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for ContentFilterProcessor {
+    async fn process(&self, mut request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        for rule in &self.rules {
+            for message in &mut request.messages {
+                match &mut message.content {
+                    Some(MessageContent::Text(text)) => self.apply(rule, text)?,
+                    Some(MessageContent::Parts(parts)) => {
+                        for part in parts {
+                            if let ContentPart::Text { text } = part {
+                                self.apply(rule, text)?;
+                            }
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn request(content: &str) -> ChatCompletionRequest {
+        serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": content}],
+        }))
+        .expect("Failed to build request")
+    }
+
+    #[tokio::test]
+    async fn test_actions() {
+        let processor = ContentFilterProcessor::new()
+            .with_rule(
+                FilterRule::words("banned", ["Secret Plan", "nuke"], FilterAction::Reject)
+                    .expect("Invalid rule"),
+            )
+            .with_rule(
+                FilterRule::pattern("emails", r"[\w.+-]+@[\w-]+\.\w+", FilterAction::Redact)
+                    .expect("Invalid rule"),
+            );
+
+        let error = processor
+            .process(request("Tell me the secret plan."))
+            .await
+            .expect_err("Blocked phrases are rejected");
+        assert!(matches!(error, Error::Rejected(_)));
+        assert!(!error.to_string().contains("secret plan"));
+
+        // Only whole words match
+        let processed = processor
+            .process(request("Nukes aside, mail me at a.b@example.com"))
+            .await
+            .expect("Failed to process request");
+        assert_eq!(
+            processed.messages[0]
+                .content
+                .as_ref()
+                .map(MessageContent::text),
+            Some("Nukes aside, mail me at [REDACTED]".to_string())
+        );
+    }
+}
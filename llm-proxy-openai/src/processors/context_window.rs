@@ -0,0 +1,362 @@
+use async_trait::async_trait;
+use llm_proxy_core::{Error, Processor, Result};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::types::{ChatCompletionRequest, ContentPart, Message, MessageContent};
+
+/// Tokens every message costs on top of its content, as counted by `OpenAI`
+const TOKENS_PER_MESSAGE: usize = 3;
+/// Tokens the reply is primed with
+const TOKENS_PER_REPLY: usize = 3;
+/// Tokens of an image at low detail, the least an image costs
+const TOKENS_PER_IMAGE: usize = 85;
+
+/// Text put in place of the middle of truncated messages
+pub const TRUNCATION_MARKER: &str = "\n[...]\n";
+
+/// Counts the tokens of a text
+pub trait TokenCounter: Send + Sync {
+    /// Number of tokens `text` is encoded to
+    fn count(&self, text: &str) -> usize;
+}
+
+/// [`TokenCounter`] approximating the `tiktoken` encodings of `OpenAI` models
+/// without their vocabularies.
+///
+/// Words cost a token per four characters and every other non-blank
+/// character a token of its own, which slightly overestimates English text.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApproximateTokenCounter;
+
+impl TokenCounter for ApproximateTokenCounter {
+    fn count(&self, text: &str) -> usize {
+        let mut tokens = 0;
+        let mut word: usize = 0;
+        for c in text.chars() {
+            if c.is_alphanumeric() {
+                word += 1;
+                continue;
+            }
+            tokens += word.div_ceil(4);
+            word = 0;
+            if !c.is_whitespace() {
+                tokens += 1;
+            }
+        }
+        tokens + word.div_ceil(4)
+    }
+}
+
+/// How a [`ContextWindowProcessor`] shortens requests that don't fit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TruncationStrategy {
+    /// Drop the oldest messages other than system messages and the last
+    /// message, then truncate the middle of the remaining ones if needed
+    #[default]
+    DropOldest,
+    /// Keep every message and truncate the middle of the longest ones
+    TruncateMiddle,
+}
+
+/// Processor that shortens chat requests to fit the context window of their
+/// model, leaving room for the completion.
+///
+/// The room needed for the completion is the request's `max_tokens` or
+/// `max_completion_tokens`, or the configured reserve when neither is set.
+/// Requests that still don't fit after truncation are refused with
+/// [`Error::Rejected`].
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::processors::{ContextWindowProcessor, TruncationStrategy};
+///
+/// let processor = ContextWindowProcessor::new(8_192)
+///     .with_model_limit("gpt-4o", 128_000)
+///     .with_completion_reserve(1_024)
+///     .with_strategy(TruncationStrategy::TruncateMiddle);
+/// ```
+pub struct ContextWindowProcessor {
+    default_limit: u32,
+    model_limits: Vec<(String, u32)>,
+    completion_reserve: u32,
+    strategy: TruncationStrategy,
+    counter: Box<dyn TokenCounter>,
+}
+
+impl ContextWindowProcessor {
+    /// Create a processor for models with a context window of `default_limit`
+    /// tokens
+    #[must_use]
+    pub fn new(default_limit: u32) -> Self {
+        Self {
+            default_limit,
+            model_limits: Vec::new(),
+            completion_reserve: 0,
+            strategy: TruncationStrategy::default(),
+            counter: Box::new(ApproximateTokenCounter),
+        }
+    }
+
+    /// Set the context window of the models whose name starts with `prefix`;
+    /// the longest matching prefix applies
+    #[must_use]
+    pub fn with_model_limit(mut self, prefix: impl Into<String>, limit: u32) -> Self {
+        self.model_limits.push((prefix.into(), limit));
+        self
+    }
+
+    /// Set the tokens kept for the completion of requests without `max_tokens`
+    #[must_use]
+    pub const fn with_completion_reserve(mut self, tokens: u32) -> Self {
+        self.completion_reserve = tokens;
+        self
+    }
+
+    /// Set how requests that don't fit are shortened
+    #[must_use]
+    pub const fn with_strategy(mut self, strategy: TruncationStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Set the counter used to measure messages
+    #[must_use]
+    pub fn with_counter(mut self, counter: impl TokenCounter + 'static) -> Self {
+        self.counter = Box::new(counter);
+        self
+    }
+
+    /// The context window of `model`
+    fn limit(&self, model: &str) -> u32 {
+        self.model_limits
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default_limit, |(_, limit)| *limit)
+    }
+
+    /// Tokens `message` takes in the prompt
+    fn message_tokens(&self, message: &Message) -> usize {
+        message_tokens(self.counter.as_ref(), message)
+    }
+
+    /// Tokens `messages` take in the prompt
+    fn prompt_tokens(&self, messages: &[Message]) -> usize {
+        TOKENS_PER_REPLY
+            + messages
+                .iter()
+                .map(|message| self.message_tokens(message))
+                .sum::<usize>()
+    }
+
+    /// Drop the oldest droppable messages, with the tool results answering
+    /// them, until `messages` fit in `budget` tokens
+    fn drop_oldest(&self, messages: &mut Vec<Message>, budget: usize) {
+        while self.prompt_tokens(messages) > budget {
+            let last = messages.len().saturating_sub(1);
+            let Some(index) = messages[..last]
+                .iter()
+                .position(|message| !is_system(message))
+            else {
+                return;
+            };
+            messages.remove(index);
+            while index < messages.len() - 1 && messages[index].role == "tool" {
+                messages.remove(index);
+            }
+        }
+    }
+
+    /// Truncate the middle of the longest text messages until `messages` fit
+    /// in `budget` tokens
+    fn truncate_middle(&self, messages: &mut [Message], budget: usize) {
+        let marker_tokens = self.counter.count(TRUNCATION_MARKER);
+        loop {
+            let total = self.prompt_tokens(messages);
+            if total <= budget {
+                return;
+            }
+            let Some((tokens, text)) = messages
+                .iter_mut()
+                .filter_map(|message| match &mut message.content {
+                    Some(MessageContent::Text(text)) => Some((self.counter.count(text), text)),
+                    _ => None,
+                })
+                .max_by_key(|(tokens, _)| *tokens)
+            else {
+                return;
+            };
+            let keep_tokens = tokens.saturating_sub(total - budget + marker_tokens);
+            let chars = text.chars().count();
+            let keep_chars = chars * keep_tokens / tokens.max(1);
+            let truncated = truncate_middle(text, keep_chars);
+            if truncated.len() >= text.len() {
+                return;
+            }
+            *text = truncated;
+        }
+    }
+}
+
+/// Tokens of the text of `message`
+fn content_tokens(counter: &dyn TokenCounter, message: &Message) -> usize {
+    match &message.content {
+        Some(MessageContent::Text(text)) => counter.count(text),
+        Some(MessageContent::Parts(parts)) => parts
+            .iter()
+            .map(|part| match part {
+                ContentPart::Text { text } => counter.count(text),
+                ContentPart::ImageUrl { .. } => TOKENS_PER_IMAGE,
+                ContentPart::Other(value) => counter.count(&value.to_string()),
+            })
+            .sum(),
+        None => 0,
+    }
+}
+
+/// Tokens `message` takes in the prompt, as counted by `counter`
+pub(crate) fn message_tokens(counter: &dyn TokenCounter, message: &Message) -> usize {
+    let calls = message
+        .tool_calls
+        .iter()
+        .flatten()
+        .map(|call| &call.function);
+    TOKENS_PER_MESSAGE
+        + counter.count(&message.role)
+        + content_tokens(counter, message)
+        + message
+            .name
+            .as_deref()
+            .map_or(0, |name| counter.count(name) + 1)
+        + message
+            .function_call
+            .iter()
+            .chain(calls)
+            .map(|call| counter.count(&call.name) + counter.count(&call.arguments))
+            .sum::<usize>()
+}
+
+/// Whether a message gives instructions, as `system` or `developer`
+fn is_system(message: &Message) -> bool {
+    matches!(message.role.as_str(), "system" | "developer")
+}
+
+/// Keep the first and last characters of `text`, `keep` in total, around
+/// [`TRUNCATION_MARKER`]
+fn truncate_middle(text: &str, keep: usize) -> String {
+    let chars = text.chars().count();
+    if keep >= chars {
+        return text.to_string();
+    }
+    let head: String = text.chars().take(keep.div_ceil(2)).collect();
+    let tail: String = text.chars().skip(chars - keep / 2).collect();
+    [head.as_str(), TRUNCATION_MARKER, tail.as_str()].concat()
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for ContextWindowProcessor {
+    async fn process(&self, mut request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        let limit = self.limit(&request.model);
+        let completion = request
+            .max_tokens
+            .or_else(|| request.param("max_completion_tokens"))
+            .unwrap_or(self.completion_reserve);
+        let budget = limit.saturating_sub(completion) as usize;
+
+        let tokens = self.prompt_tokens(&request.messages);
+        if tokens <= budget {
+            return Ok(request);
+        }
+        let count = request.messages.len();
+        if self.strategy == TruncationStrategy::DropOldest {
+            self.drop_oldest(&mut request.messages, budget);
+        }
+        self.truncate_middle(&mut request.messages, budget);
+
+        let truncated = self.prompt_tokens(&request.messages);
+        if truncated > budget {
+            return Err(Error::Rejected(format!(
+                "Messages take about {truncated} tokens, more than the {budget} left by the \
+                 {limit} token context window of {}",
+                request.model
+            )));
+        }
+        debug!(
+            model = %request.model,
+            tokens,
+            truncated,
+            dropped = count - request.messages.len(),
+            "Truncated request to the context window"
+        );
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn request(max_tokens: u32) -> ChatCompletionRequest {
+        serde_json::from_value(json!({
+            "model": "gpt-4o-mini",
+            "max_tokens": max_tokens,
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "word ".repeat(100)},
+                {"role": "assistant", "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "lookup", "arguments": "{}"},
+                }]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "found"},
+                {"role": "user", "content": "And now?"},
+            ],
+        }))
+        .expect("Failed to build request")
+    }
+
+    #[tokio::test]
+    async fn test_truncation() {
+        let processor = ContextWindowProcessor::new(100_000).with_model_limit("gpt-4o", 200);
+
+        // Requests that fit are unchanged
+        let processed = processor
+            .process(request(10))
+            .await
+            .expect("Failed to process request");
+        assert_eq!(processed.messages.len(), 5);
+
+        // The oldest turns are dropped, tool results included, keeping the system prompt
+        let processed = processor
+            .process(request(180))
+            .await
+            .expect("Failed to process request");
+        let roles: Vec<&str> = processed.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user"]);
+
+        // Long messages lose their middle instead with `TruncateMiddle`
+        let processed = processor
+            .with_strategy(TruncationStrategy::TruncateMiddle)
+            .process(request(120))
+            .await
+            .expect("Failed to process request");
+        assert_eq!(processed.messages.len(), 5);
+        let text = processed.messages[1]
+            .content
+            .as_ref()
+            .map(MessageContent::text)
+            .unwrap_or_default();
+        assert!(text.starts_with("word") && text.contains(TRUNCATION_MARKER));
+
+        let error = ContextWindowProcessor::new(100)
+            .process(request(100))
+            .await
+            .expect_err("Requests without room are rejected");
+        assert!(matches!(error, Error::Rejected(_)));
+    }
+}
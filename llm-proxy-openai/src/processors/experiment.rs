@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use llm_proxy_core::{Error, Processor, RequestContext, Result};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use super::attribution::USER_ATTRIBUTE;
+use crate::types::ChatCompletionRequest;
+
+/// Context attribute with the experiment a request took part in
+pub const EXPERIMENT_ATTRIBUTE: &str = "experiment.name";
+
+/// Context attribute with the arm a request was assigned to
+pub const ARM_ATTRIBUTE: &str = "experiment.arm";
+
+/// Response header naming the experiment a request took part in
+pub const EXPERIMENT_HEADER: &str = "X-Experiment";
+
+/// Response header naming the arm a request was assigned to
+pub const ARM_HEADER: &str = "X-Experiment-Arm";
+
+/// One variant of an [`ExperimentProcessor`]
+#[derive(Debug, Clone)]
+pub struct ExperimentArm {
+    id: String,
+    weight: u32,
+    model: Option<String>,
+    params: HashMap<String, Value>,
+}
+
+impl ExperimentArm {
+    /// Create an arm that receives `weight` shares of the users and leaves
+    /// their requests unchanged
+    #[must_use]
+    pub fn new(id: impl Into<String>, weight: u32) -> Self {
+        Self {
+            id: id.into(),
+            weight,
+            model: None,
+            params: HashMap::new(),
+        }
+    }
+
+    /// Send the arm's requests to `model`
+    #[must_use]
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Set the request parameter `name`, e.g. `temperature`, for the arm's requests
+    #[must_use]
+    pub fn with_param(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.params.insert(name.into(), value);
+        self
+    }
+
+    /// Apply the arm's model and parameters to `request`
+    fn apply(&self, request: &mut ChatCompletionRequest) -> Result<()> {
+        if let Some(model) = &self.model {
+            request.model.clone_from(model);
+        }
+        for (name, value) in &self.params {
+            let invalid = |e: serde_json::Error| {
+                Error::ConfigError(format!("Invalid {name} of experiment arm {}: {e}", self.id))
+            };
+            match name.as_str() {
+                "max_tokens" => {
+                    request.max_tokens = serde_json::from_value(value.clone()).map_err(invalid)?;
+                }
+                "temperature" => {
+                    request.temperature = serde_json::from_value(value.clone()).map_err(invalid)?;
+                }
+                _ => {
+                    request
+                        .additional_params
+                        .insert(name.clone(), value.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Processor that splits users between the arms of an experiment, so models
+/// or parameters can be compared on live traffic.
+///
+/// Requests are assigned by a hash of the experiment name and their user:
+/// the [`USER_ATTRIBUTE`] context attribute, the `user` field, or else the
+/// tenant. A user therefore always lands in the same arm, and different
+/// experiments split users independently. Requests without a user don't take
+/// part. The arm's model and parameters replace the request's, and the
+/// experiment and arm are recorded in the [`EXPERIMENT_ATTRIBUTE`] and
+/// [`ARM_ATTRIBUTE`] attributes and returned in the [`EXPERIMENT_HEADER`] and
+/// [`ARM_HEADER`] response headers, streams included, for analytics to
+/// compare the arms.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::processors::{ExperimentArm, ExperimentProcessor};
+///
+/// let processor = ExperimentProcessor::new("mini-vs-4o")
+///     .with_model("gpt-4o")
+///     .with_arm(ExperimentArm::new("control", 9))
+///     .with_arm(ExperimentArm::new("mini", 1).with_model("gpt-4o-mini"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ExperimentProcessor {
+    name: String,
+    models: Vec<String>,
+    arms: Vec<ExperimentArm>,
+}
+
+impl ExperimentProcessor {
+    /// Create an experiment without arms, which applies to every model
+    #[must_use]
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            models: Vec::new(),
+            arms: Vec::new(),
+        }
+    }
+
+    /// Limit the experiment to requests for `model`; may be called repeatedly
+    #[must_use]
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.models.push(model.into());
+        self
+    }
+
+    /// Add an arm
+    #[must_use]
+    pub fn with_arm(mut self, arm: ExperimentArm) -> Self {
+        self.arms.push(arm);
+        self
+    }
+
+    /// The arm of `user`, if any arm has a weight
+    fn assign(&self, user: &str) -> Option<&ExperimentArm> {
+        let total: u64 = self.arms.iter().map(|arm| u64::from(arm.weight)).sum();
+        if total == 0 {
+            return None;
+        }
+        let digest = Sha256::digest(format!("{}:{user}", self.name));
+        let mut bucket = digest
+            .iter()
+            .take(8)
+            .fold(0_u64, |hash, byte| (hash << 8) | u64::from(*byte))
+            % total;
+        self.arms.iter().find(|arm| {
+            let weight = u64::from(arm.weight);
+            if bucket < weight {
+                return true;
+            }
+            bucket -= weight;
+            false
+        })
+    }
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for ExperimentProcessor {
+    async fn process(&self, request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        self.process_with_context(request, &mut RequestContext::new())
+            .await
+    }
+
+    async fn process_with_context(
+        &self,
+        mut request: ChatCompletionRequest,
+        context: &mut RequestContext,
+    ) -> Result<ChatCompletionRequest> {
+        if !self.models.is_empty() && !self.models.contains(&request.model) {
+            return Ok(request);
+        }
+        let user = context
+            .attributes
+            .get(USER_ATTRIBUTE)
+            .cloned()
+            .or_else(|| {
+                request
+                    .additional_params
+                    .get("user")
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+            })
+            .or_else(|| context.tenant.clone());
+        let Some(arm) = user.as_deref().and_then(|user| self.assign(user)) else {
+            return Ok(request);
+        };
+
+        arm.apply(&mut request)?;
+        debug!(experiment = %self.name, arm = %arm.id, model = %request.model, "Assigned experiment arm");
+        metrics::counter!(
+            "llm_proxy_experiment_assignments_total",
+            "experiment" => self.name.clone(),
+            "arm" => arm.id.clone()
+        )
+        .increment(1);
+        context.model = Some(request.model.clone());
+        context
+            .attributes
+            .insert(EXPERIMENT_ATTRIBUTE.to_string(), self.name.clone());
+        context
+            .attributes
+            .insert(ARM_ATTRIBUTE.to_string(), arm.id.clone());
+        context.add_response_header(EXPERIMENT_HEADER, self.name.clone());
+        context.add_response_header(ARM_HEADER, arm.id.clone());
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_experiment_assignment() {
+        let processor = ExperimentProcessor::new("mini-vs-4o")
+            .with_model("gpt-4o")
+            .with_arm(ExperimentArm::new("control", 1))
+            .with_arm(
+                ExperimentArm::new("mini", 1)
+                    .with_model("gpt-4o-mini")
+                    .with_param("temperature", json!(0.2)),
+            );
+
+        let mut arms = HashMap::new();
+        for user in 0..100 {
+            let mut context =
+                RequestContext::new().with_attribute(USER_ATTRIBUTE, format!("user-{user}"));
+            let request = ChatCompletionRequest::new_block("gpt-4o".to_string(), Vec::new());
+            let processed = processor
+                .process_with_context(request.clone(), &mut context)
+                .await
+                .expect("Failed to process request");
+            let arm = context.attributes[ARM_ATTRIBUTE].clone();
+            if arm == "mini" {
+                assert_eq!(processed.model, "gpt-4o-mini");
+                assert_eq!(processed.temperature, Some(0.2));
+            } else {
+                assert_eq!(processed.model, "gpt-4o");
+            }
+            assert_eq!(
+                context.response_headers[1],
+                (ARM_HEADER.to_string(), arm.clone())
+            );
+
+            // The same user always lands in the same arm
+            let mut context =
+                RequestContext::new().with_attribute(USER_ATTRIBUTE, format!("user-{user}"));
+            processor
+                .process_with_context(request, &mut context)
+                .await
+                .expect("Failed to process request");
+            assert_eq!(context.attributes[ARM_ATTRIBUTE], arm);
+            *arms.entry(arm).or_insert(0) += 1;
+        }
+        assert!(arms["control"] > 25 && arms["mini"] > 25);
+
+        // Requests without a user don't take part
+        let mut context = RequestContext::new();
+        let request = ChatCompletionRequest::new_block("gpt-4o".to_string(), Vec::new());
+        processor
+            .process_with_context(request, &mut context)
+            .await
+            .expect("Failed to process request");
+        assert!(context.response_headers.is_empty());
+    }
+}
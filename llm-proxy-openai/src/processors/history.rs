@@ -0,0 +1,320 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use llm_proxy_core::{ConversationStore, Processor, RequestContext, Result};
+use tracing::{debug, warn};
+
+use super::context_window::{message_tokens, ApproximateTokenCounter, TokenCounter};
+use crate::{
+    stream_processors::conversation::{session, SESSION_HEADER},
+    types::{ChatCompletionRequest, Message},
+};
+
+/// Tokens of history injected at most, unless configured otherwise
+pub const DEFAULT_HISTORY_TOKENS: u32 = 4_000;
+
+/// Turns loaded from the store at most, unless configured otherwise
+pub const DEFAULT_MAX_TURNS: u32 = 50;
+
+/// Context attribute with the number of turns injected into a request
+pub const HISTORY_TURNS_ATTRIBUTE: &str = "conversation.turns";
+
+/// Processor that adds the earlier turns of a conversation to requests
+/// carrying only its newest messages, so clients can be stateless.
+///
+/// Requests with a session ID in the header (by default `X-Session-Id`) and
+/// no assistant message get the latest turns recorded for the session, by a
+/// [`ConversationProcessor`](crate::stream_processors::ConversationProcessor)
+/// on the same store, inserted after their system messages. Whole turns are
+/// added, newest first, as long as they fit in the token budget, so a tool
+/// call is never separated from its result. Requests that already carry
+/// history are left as they are.
+///
+/// A store that can't be read is logged and the request is forwarded without
+/// history. The number of injected turns is recorded in the request context
+/// as the [`HISTORY_TURNS_ATTRIBUTE`] attribute.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use llm_proxy_core::ConversationStore;
+/// use llm_proxy_openai::processors::ConversationHistoryProcessor;
+///
+/// fn history(store: Arc<dyn ConversationStore>) -> ConversationHistoryProcessor {
+///     ConversationHistoryProcessor::new(store)
+///         .with_token_budget(8_000)
+///         .with_max_turns(20)
+/// }
+/// ```
+pub struct ConversationHistoryProcessor {
+    store: Arc<dyn ConversationStore>,
+    header: String,
+    token_budget: u32,
+    max_turns: u32,
+    counter: Box<dyn TokenCounter>,
+}
+
+impl ConversationHistoryProcessor {
+    /// Create a processor injecting up to [`DEFAULT_HISTORY_TOKENS`] of the
+    /// history in `store`, by the session ID of the [`SESSION_HEADER`]
+    #[must_use]
+    pub fn new(store: Arc<dyn ConversationStore>) -> Self {
+        Self {
+            store,
+            header: SESSION_HEADER.to_string(),
+            token_budget: DEFAULT_HISTORY_TOKENS,
+            max_turns: DEFAULT_MAX_TURNS,
+            counter: Box::new(ApproximateTokenCounter),
+        }
+    }
+
+    /// Take the session ID from the header `name`
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>) -> Self {
+        self.header = name.into();
+        self
+    }
+
+    /// Inject at most `tokens` tokens of history
+    #[must_use]
+    pub const fn with_token_budget(mut self, tokens: u32) -> Self {
+        self.token_budget = tokens;
+        self
+    }
+
+    /// Consider at most the last `turns` turns of a conversation
+    #[must_use]
+    pub const fn with_max_turns(mut self, turns: u32) -> Self {
+        self.max_turns = turns;
+        self
+    }
+
+    /// Count tokens with `counter` instead of an approximation
+    #[must_use]
+    pub fn with_counter(mut self, counter: impl TokenCounter + 'static) -> Self {
+        self.counter = Box::new(counter);
+        self
+    }
+
+    /// The messages of the latest turns of `session` fitting in the budget,
+    /// oldest first, and the number of turns they belong to
+    async fn history(&self, session: &str) -> Result<(Vec<Message>, usize)> {
+        let turns = self.store.history(session, Some(self.max_turns)).await?;
+        let budget = usize::try_from(self.token_budget).unwrap_or(usize::MAX);
+        let mut used = 0;
+        let mut kept = Vec::new();
+        for turn in turns.into_iter().rev() {
+            let messages = turn
+                .into_messages()
+                .map(serde_json::from_value)
+                .collect::<std::result::Result<Vec<Message>, _>>()?;
+            let tokens: usize = messages
+                .iter()
+                .map(|message| message_tokens(self.counter.as_ref(), message))
+                .sum();
+            if used + tokens > budget {
+                break;
+            }
+            used += tokens;
+            kept.push(messages);
+        }
+        let turns = kept.len();
+        Ok((kept.into_iter().rev().flatten().collect(), turns))
+    }
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for ConversationHistoryProcessor {
+    async fn process(&self, request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        Ok(request)
+    }
+
+    async fn process_with_context(
+        &self,
+        mut request: ChatCompletionRequest,
+        context: &mut RequestContext,
+    ) -> Result<ChatCompletionRequest> {
+        let Some(session) = session(context, &self.header) else {
+            return Ok(request);
+        };
+        if request
+            .messages
+            .iter()
+            .any(|message| message.role == "assistant")
+        {
+            return Ok(request);
+        }
+        let (history, turns) = match self.history(&session).await {
+            Ok(history) => history,
+            Err(e) => {
+                warn!(error = %e, session, "Failed to load conversation history");
+                return Ok(request);
+            }
+        };
+        let start = request
+            .messages
+            .iter()
+            .position(|message| !matches!(message.role.as_str(), "system" | "developer"))
+            .unwrap_or(request.messages.len());
+        request.messages.splice(start..start, history);
+        context
+            .attributes
+            .insert(HISTORY_TURNS_ATTRIBUTE.to_string(), turns.to_string());
+        debug!(session, turns, "Injected conversation history");
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use llm_proxy_core::Turn;
+    use serde_json::{json, Value};
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::types::MessageContent;
+
+    #[derive(Default)]
+    struct MemoryConversationStore(Mutex<Vec<Turn>>);
+
+    #[async_trait]
+    impl ConversationStore for MemoryConversationStore {
+        async fn append(&self, _session: &str, turn: &Turn) -> Result<()> {
+            self.0.lock().await.push(turn.clone());
+            Ok(())
+        }
+
+        async fn history(&self, session: &str, limit: Option<u32>) -> Result<Vec<Turn>> {
+            if session != "acme/s1" {
+                return Ok(Vec::new());
+            }
+            let turns = self.0.lock().await;
+            let limit = limit.map_or(turns.len(), |limit| limit as usize);
+            Ok(turns[turns.len().saturating_sub(limit)..].to_vec())
+        }
+    }
+
+    async fn store() -> Arc<MemoryConversationStore> {
+        let store = Arc::new(MemoryConversationStore::default());
+        for (index, question) in ["Hi", "Weather in Paris?"].into_iter().enumerate() {
+            store
+                .append(
+                    "acme/s1",
+                    &Turn {
+                        timestamp: index as u64,
+                        messages: vec![json!({"role": "user", "content": question})],
+                        reply: json!({"role": "assistant", "content": format!("Re: {question}")}),
+                    },
+                )
+                .await
+                .expect("Failed to append turn");
+        }
+        store
+    }
+
+    fn request(messages: &Value) -> ChatCompletionRequest {
+        serde_json::from_value(json!({"model": "gpt-4o", "messages": messages}))
+            .expect("Invalid request")
+    }
+
+    fn contents(request: &ChatCompletionRequest) -> Vec<String> {
+        request
+            .messages
+            .iter()
+            .map(|message| {
+                let content = message
+                    .content
+                    .as_ref()
+                    .map(MessageContent::text)
+                    .unwrap_or_default();
+                format!("{}: {content}", message.role)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_injects_history() {
+        let processor = ConversationHistoryProcessor::new(store().await);
+        let mut context = RequestContext::new()
+            .with_tenant("acme")
+            .with_header("X-Session-Id", "s1");
+        let request = request(&json!([
+            {"role": "system", "content": "Be brief"},
+            {"role": "user", "content": "And tomorrow?"},
+        ]));
+
+        let request = processor
+            .process_with_context(request, &mut context)
+            .await
+            .expect("Failed to process request");
+        assert_eq!(
+            contents(&request),
+            [
+                "system: Be brief",
+                "user: Hi",
+                "assistant: Re: Hi",
+                "user: Weather in Paris?",
+                "assistant: Re: Weather in Paris?",
+                "user: And tomorrow?",
+            ]
+        );
+        assert_eq!(context.attributes[HISTORY_TURNS_ATTRIBUTE], "2");
+    }
+
+    #[tokio::test]
+    async fn test_keeps_newest_turns_within_budget() {
+        // A turn of two short messages takes about 20 tokens
+        let processor = ConversationHistoryProcessor::new(store().await).with_token_budget(25);
+        let mut context = RequestContext::new()
+            .with_tenant("acme")
+            .with_header("X-Session-Id", "s1");
+        let request = request(&json!([{"role": "user", "content": "And tomorrow?"}]));
+
+        let request = processor
+            .process_with_context(request, &mut context)
+            .await
+            .expect("Failed to process request");
+        assert_eq!(
+            contents(&request),
+            [
+                "user: Weather in Paris?",
+                "assistant: Re: Weather in Paris?",
+                "user: And tomorrow?",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_leaves_requests_with_history() {
+        let processor = ConversationHistoryProcessor::new(store().await);
+        let mut context = RequestContext::new()
+            .with_tenant("acme")
+            .with_header("X-Session-Id", "s1");
+        let messages = json!([
+            {"role": "user", "content": "Hi"},
+            {"role": "assistant", "content": "Hello"},
+            {"role": "user", "content": "And tomorrow?"},
+        ]);
+
+        let processed = processor
+            .process_with_context(request(&messages), &mut context)
+            .await
+            .expect("Failed to process request");
+        assert_eq!(contents(&processed), contents(&request(&messages)));
+
+        // Other tenants don't see the conversation
+        let mut context = RequestContext::new()
+            .with_tenant("globex")
+            .with_header("X-Session-Id", "s1");
+        let processed = processor
+            .process_with_context(
+                request(&json!([{"role": "user", "content": "And tomorrow?"}])),
+                &mut context,
+            )
+            .await
+            .expect("Failed to process request");
+        assert_eq!(processed.messages.len(), 1);
+    }
+}
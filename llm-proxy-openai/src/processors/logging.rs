@@ -0,0 +1,54 @@
+use async_trait::async_trait;
+use llm_proxy_core::{Processor, Result};
+use tracing::{debug, error, info, trace, warn, Level};
+
+use crate::types::ChatCompletionRequest;
+
+/// Processor that logs a summary of each request, leaving it unchanged.
+///
+/// The model, number of messages, streaming flag and `max_tokens` are logged;
+/// message contents are not, so prompts stay out of the logs.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::processors::LoggingProcessor;
+///
+/// let processor = LoggingProcessor::new(tracing::Level::DEBUG);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LoggingProcessor {
+    level: Level,
+}
+
+impl LoggingProcessor {
+    /// Create a processor logging at `level`
+    #[must_use]
+    pub const fn new(level: Level) -> Self {
+        Self { level }
+    }
+}
+
+impl Default for LoggingProcessor {
+    fn default() -> Self {
+        Self::new(Level::INFO)
+    }
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for LoggingProcessor {
+    async fn process(&self, request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        let model = request.model.as_str();
+        let messages = request.messages.len();
+        let stream = request.stream;
+        let max_tokens = request.max_tokens;
+        match self.level {
+            Level::ERROR => error!(model, messages, stream, ?max_tokens, "Chat request"),
+            Level::WARN => warn!(model, messages, stream, ?max_tokens, "Chat request"),
+            Level::INFO => info!(model, messages, stream, ?max_tokens, "Chat request"),
+            Level::DEBUG => debug!(model, messages, stream, ?max_tokens, "Chat request"),
+            Level::TRACE => trace!(model, messages, stream, ?max_tokens, "Chat request"),
+        }
+        Ok(request)
+    }
+}
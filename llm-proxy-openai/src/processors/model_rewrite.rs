@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use llm_proxy_core::{Processor, RequestContext, Result};
+use tracing::warn;
+
+use crate::types::ChatCompletionRequest;
+
+/// Context attribute with the model a client requested before it was rewritten
+pub const REQUESTED_MODEL_ATTRIBUTE: &str = "model.requested";
+
+/// Processor that replaces deprecated or banned models with their successors,
+/// so old clients keep working during model sunsets.
+///
+/// Rewritten requests are logged, counted in the
+/// `llm_proxy_model_rewrites_total` metric and answered with a `Warning`
+/// header naming the replacement. The request context's model is updated, so
+/// token rules apply to the model that is actually called.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::processors::ModelRewriteProcessor;
+///
+/// let processor = ModelRewriteProcessor::new()
+///     .with_rewrite("gpt-4-0314", "gpt-4o")
+///     .with_rewrite("gpt-3.5-turbo-0301", "gpt-4o-mini");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ModelRewriteProcessor {
+    rewrites: HashMap<String, String>,
+}
+
+impl ModelRewriteProcessor {
+    /// Create a processor without rewrites
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send requests for `model` to `replacement` instead
+    #[must_use]
+    pub fn with_rewrite(
+        mut self,
+        model: impl Into<String>,
+        replacement: impl Into<String>,
+    ) -> Self {
+        self.rewrites.insert(model.into(), replacement.into());
+        self
+    }
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for ModelRewriteProcessor {
+    async fn process(&self, request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        self.process_with_context(request, &mut RequestContext::new())
+            .await
+    }
+
+    async fn process_with_context(
+        &self,
+        mut request: ChatCompletionRequest,
+        context: &mut RequestContext,
+    ) -> Result<ChatCompletionRequest> {
+        let Some(replacement) = self.rewrites.get(&request.model) else {
+            return Ok(request);
+        };
+        let requested = std::mem::replace(&mut request.model, replacement.clone());
+        warn!(
+            model = %requested,
+            replacement = %replacement,
+            "Rewriting request for deprecated model"
+        );
+        metrics::counter!(
+            "llm_proxy_model_rewrites_total",
+            "model" => requested.clone(),
+            "replacement" => replacement.clone()
+        )
+        .increment(1);
+        context.add_response_header(
+            "Warning",
+            format!("299 - \"Model {requested} is deprecated, {replacement} was used instead\""),
+        );
+        context.model = Some(replacement.clone());
+        context
+            .attributes
+            .insert(REQUESTED_MODEL_ATTRIBUTE.to_string(), requested);
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rewrites_deprecated_models() {
+        let processor = ModelRewriteProcessor::new().with_rewrite("gpt-4-0314", "gpt-4o");
+        let mut context = RequestContext::new().with_model("gpt-4-0314");
+
+        let request = ChatCompletionRequest::new_block("gpt-4-0314".to_string(), Vec::new());
+        let processed = processor
+            .process_with_context(request, &mut context)
+            .await
+            .expect("Failed to process request");
+        assert_eq!(processed.model, "gpt-4o");
+        assert_eq!(context.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(context.attributes[REQUESTED_MODEL_ATTRIBUTE], "gpt-4-0314");
+        assert_eq!(context.response_headers[0].0, "Warning");
+
+        // Other models are left alone
+        let request = ChatCompletionRequest::new_block("gpt-4o-mini".to_string(), Vec::new());
+        let mut context = RequestContext::new();
+        let processed = processor
+            .process_with_context(request, &mut context)
+            .await
+            .expect("Failed to process request");
+        assert_eq!(processed.model, "gpt-4o-mini");
+        assert!(context.response_headers.is_empty());
+    }
+}
@@ -0,0 +1,305 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use llm_proxy_core::{
+    AuthScheme, ClientProvider, Error, Processor, RequestContext, Result, TokenProvider,
+    UrlProvider,
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::types::{ChatCompletionRequest, MessageContent};
+
+/// Context attribute set to `true` or `false` by a [`ModerationProcessor`]
+pub const FLAGGED_ATTRIBUTE: &str = "moderation.flagged";
+/// Context attribute listing the categories a [`ModerationProcessor`] flagged
+pub const CATEGORIES_ATTRIBUTE: &str = "moderation.categories";
+
+/// A request to the `OpenAI` moderations API
+#[derive(Debug, Clone, Serialize)]
+pub struct ModerationRequest {
+    /// The texts to classify
+    pub input: Vec<String>,
+    /// The moderation model, e.g. `omni-moderation-latest`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+/// A response of the `OpenAI` moderations API
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModerationResponse {
+    /// One result per input text
+    pub results: Vec<ModerationResult>,
+}
+
+/// The classification of one text
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ModerationResult {
+    /// Whether the text violates any category, by the API's own thresholds
+    #[serde(default)]
+    pub flagged: bool,
+    /// Whether the text violates each category, by the API's own thresholds
+    #[serde(default)]
+    pub categories: HashMap<String, bool>,
+    /// Scores of each category, between 0 and 1
+    #[serde(default)]
+    pub category_scores: HashMap<String, f64>,
+}
+
+/// What a [`ModerationProcessor`] does with flagged requests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    /// Refuse the request, which the server answers with 400
+    #[default]
+    Reject,
+    /// Send the request and log the flagged categories
+    Flag,
+}
+
+/// Processor that checks the user content of chat requests with the `OpenAI`
+/// moderations endpoint before they are sent upstream.
+///
+/// A category is flagged when its score reaches the threshold configured for
+/// it, or, without a threshold, when the API flags it. The verdict is
+/// recorded in the request context as the [`FLAGGED_ATTRIBUTE`] and
+/// [`CATEGORIES_ATTRIBUTE`] attributes. Requests failing moderation are
+/// refused with [`Error::Rejected`], or only logged with
+/// [`ModerationAction::Flag`]; requests are refused too when the moderations
+/// endpoint can't be reached.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use llm_proxy_openai::{
+///     processors::{ModerationAction, ModerationProcessor},
+///     providers::StaticClientProvider,
+///     EnvTokenProvider, OpenAIUrlProvider,
+/// };
+///
+/// let processor = ModerationProcessor::new(
+///     Arc::new(StaticClientProvider::new()),
+///     Arc::new(EnvTokenProvider::standard()),
+///     Arc::new(OpenAIUrlProvider::moderations()),
+/// )
+/// .with_model("omni-moderation-latest")
+/// .with_threshold("violence", 0.5)
+/// .with_action(ModerationAction::Flag);
+/// ```
+pub struct ModerationProcessor {
+    client: Arc<dyn ClientProvider>,
+    token: Arc<dyn TokenProvider>,
+    url: Arc<dyn UrlProvider>,
+    auth_scheme: AuthScheme,
+    model: Option<String>,
+    thresholds: HashMap<String, f64>,
+    action: ModerationAction,
+}
+
+impl ModerationProcessor {
+    /// Create a processor calling the moderations endpoint at `url_provider`
+    pub fn new(
+        client_provider: Arc<dyn ClientProvider>,
+        token_provider: Arc<dyn TokenProvider>,
+        url_provider: Arc<dyn UrlProvider>,
+    ) -> Self {
+        Self {
+            client: client_provider,
+            token: token_provider,
+            url: url_provider,
+            auth_scheme: AuthScheme::default(),
+            model: None,
+            thresholds: HashMap::new(),
+            action: ModerationAction::default(),
+        }
+    }
+
+    /// Set how the API token is sent to the moderations endpoint
+    #[must_use]
+    pub fn with_auth_scheme(mut self, auth_scheme: AuthScheme) -> Self {
+        self.auth_scheme = auth_scheme;
+        self
+    }
+
+    /// Set the moderation model instead of the endpoint's default
+    #[must_use]
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// Flag `category` when its score reaches `threshold`, instead of by the
+    /// API's own threshold
+    #[must_use]
+    pub fn with_threshold(mut self, category: impl Into<String>, threshold: f64) -> Self {
+        self.thresholds.insert(category.into(), threshold);
+        self
+    }
+
+    /// Set what happens to flagged requests
+    #[must_use]
+    pub const fn with_action(mut self, action: ModerationAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Classify `input` with the moderations endpoint
+    async fn moderate(
+        &self,
+        input: Vec<String>,
+        context: &RequestContext,
+    ) -> Result<ModerationResponse> {
+        let client = self
+            .client
+            .get_client()
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get HTTP client: {e}")))?;
+        let token = self
+            .token
+            .get_token_for(context)
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get API token: {e}")))?;
+        let url = self
+            .url
+            .get_url()
+            .map_err(|e| Error::LLMError(format!("Failed to get API URL: {e}")))?;
+
+        let request = ModerationRequest {
+            input,
+            model: self.model.clone(),
+        };
+        let response = self
+            .auth_scheme
+            .apply(client.post(url), &token)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| Error::LLMError(format!("Moderation request failed: {e}")))?;
+        let status = response.status();
+        if !status.is_success() {
+            self.token.report_rejection(&token, status.as_u16()).await;
+            let body = response.text().await.unwrap_or_default();
+            return Err(Error::LLMError(format!(
+                "Moderation request failed with {status}: {body}"
+            )));
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| Error::LLMError(format!("Invalid moderation response: {e}")))
+    }
+
+    /// The categories flagged in any of `results`, sorted
+    fn flagged_categories(&self, results: &[ModerationResult]) -> Vec<String> {
+        let mut flagged: Vec<String> = results
+            .iter()
+            .flat_map(|result| {
+                let scored = result
+                    .category_scores
+                    .iter()
+                    .filter(|(category, score)| {
+                        self.thresholds
+                            .get(*category)
+                            .is_some_and(|threshold| *score >= threshold)
+                    })
+                    .map(|(category, _)| category);
+                let by_api = result
+                    .categories
+                    .iter()
+                    .filter(|(category, flagged)| {
+                        **flagged && !self.thresholds.contains_key(*category)
+                    })
+                    .map(|(category, _)| category);
+                scored.chain(by_api).cloned()
+            })
+            .collect();
+        flagged.sort();
+        flagged.dedup();
+        flagged
+    }
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for ModerationProcessor {
+    async fn process(&self, request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        self.process_with_context(request, &mut RequestContext::new())
+            .await
+    }
+
+    async fn process_with_context(
+        &self,
+        request: ChatCompletionRequest,
+        context: &mut RequestContext,
+    ) -> Result<ChatCompletionRequest> {
+        let input: Vec<String> = request
+            .messages
+            .iter()
+            .filter(|message| message.role == "user")
+            .filter_map(|message| message.content.as_ref().map(MessageContent::text))
+            .filter(|text| !text.trim().is_empty())
+            .collect();
+        if input.is_empty() {
+            return Ok(request);
+        }
+
+        let response = self.moderate(input, context).await?;
+        let flagged = self.flagged_categories(&response.results);
+        context.attributes.insert(
+            FLAGGED_ATTRIBUTE.to_string(),
+            (!flagged.is_empty()).to_string(),
+        );
+        if flagged.is_empty() {
+            return Ok(request);
+        }
+        let categories = flagged.join(", ");
+        context
+            .attributes
+            .insert(CATEGORIES_ATTRIBUTE.to_string(), categories.clone());
+        match self.action {
+            ModerationAction::Reject => Err(Error::Rejected(format!(
+                "Content flagged by moderation: {categories}"
+            ))),
+            ModerationAction::Flag => {
+                warn!(model = %request.model, categories, "Request flagged by moderation");
+                Ok(request)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::providers::{EnvTokenProvider, OpenAIUrlProvider, StaticClientProvider};
+
+    #[test]
+    fn test_flagged_categories() {
+        let processor = ModerationProcessor::new(
+            Arc::new(StaticClientProvider::new()),
+            Arc::new(EnvTokenProvider::standard()),
+            Arc::new(OpenAIUrlProvider::moderations()),
+        )
+        .with_threshold("violence", 0.3)
+        .with_threshold("harassment", 0.9);
+        let response: ModerationResponse = serde_json::from_value(json!({
+            "id": "modr-1",
+            "model": "omni-moderation-latest",
+            "results": [{
+                "flagged": true,
+                "categories": {"violence": false, "harassment": true, "self-harm": true, "hate": false},
+                "category_scores": {"violence": 0.4, "harassment": 0.8, "self-harm": 0.7, "hate": 0.1},
+            }],
+        }))
+        .expect("Failed to parse response");
+
+        // Thresholds replace the API's verdict for their categories only
+        assert_eq!(
+            processor.flagged_categories(&response.results),
+            ["self-harm", "violence"]
+        );
+    }
+}
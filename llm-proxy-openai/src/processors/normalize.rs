@@ -0,0 +1,268 @@
+use async_trait::async_trait;
+use llm_proxy_core::{Processor, Result};
+
+use crate::types::{ChatCompletionRequest, ContentPart, Message, MessageContent};
+
+/// Text of the user message inserted before conversations that start with
+/// the assistant when alternation is enforced
+pub const PLACEHOLDER_USER_MESSAGE: &str = "Continue.";
+
+/// Processor that cleans up the message list of chat requests.
+///
+/// By default it strips empty messages, removes messages repeating the one
+/// before them (and repeated system messages) and merges consecutive
+/// messages of the same role. With alternation enforced, system messages are
+/// also moved to the front and conversations start with a user message, as
+/// backends such as Anthropic require.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::processors::NormalizeProcessor;
+///
+/// let processor = NormalizeProcessor::new().with_enforce_alternation(true);
+/// ```
+#[derive(Debug, Clone, Copy)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct NormalizeProcessor {
+    strip_empty: bool,
+    deduplicate: bool,
+    merge_consecutive: bool,
+    enforce_alternation: bool,
+}
+
+impl Default for NormalizeProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NormalizeProcessor {
+    /// Create a processor that strips empty messages, removes duplicates and
+    /// merges consecutive messages of the same role
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            strip_empty: true,
+            deduplicate: true,
+            merge_consecutive: true,
+            enforce_alternation: false,
+        }
+    }
+
+    /// Set whether messages without content or calls are removed
+    #[must_use]
+    pub const fn with_strip_empty(mut self, strip_empty: bool) -> Self {
+        self.strip_empty = strip_empty;
+        self
+    }
+
+    /// Set whether messages identical to the previous one, and repeated
+    /// system messages, are removed
+    #[must_use]
+    pub const fn with_deduplicate(mut self, deduplicate: bool) -> Self {
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    /// Set whether consecutive messages of the same role are merged
+    #[must_use]
+    pub const fn with_merge_consecutive(mut self, merge_consecutive: bool) -> Self {
+        self.merge_consecutive = merge_consecutive;
+        self
+    }
+
+    /// Set whether system messages are moved to the front and the
+    /// conversation is made to start with a user message
+    #[must_use]
+    pub const fn with_enforce_alternation(mut self, enforce_alternation: bool) -> Self {
+        self.enforce_alternation = enforce_alternation;
+        self
+    }
+}
+
+/// Whether a message gives instructions, as `system` or `developer`
+fn is_system(message: &Message) -> bool {
+    matches!(message.role.as_str(), "system" | "developer")
+}
+
+/// Whether `message` carries neither content nor calls
+fn is_empty(message: &Message) -> bool {
+    let no_content = match &message.content {
+        Some(MessageContent::Text(text)) => text.trim().is_empty(),
+        Some(MessageContent::Parts(parts)) => parts.is_empty(),
+        None => true,
+    };
+    no_content
+        && message.role != "tool"
+        && message.function_call.is_none()
+        && message.tool_calls.as_ref().is_none_or(Vec::is_empty)
+}
+
+/// Whether two messages are the same, field by field
+fn same_message(a: &Message, b: &Message) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+/// Whether `next` can be merged into `previous`
+fn can_merge(previous: &Message, next: &Message) -> bool {
+    previous.role == next.role
+        && matches!(
+            next.role.as_str(),
+            "system" | "developer" | "user" | "assistant"
+        )
+        && previous.name == next.name
+        && previous.function_call.is_none()
+        && next.function_call.is_none()
+        // Text can't follow the calls of an assistant message
+        && (previous.tool_calls.is_none() || next.content.is_none())
+}
+
+/// Append the content of `next` to the content of `previous`
+fn merge_content(
+    previous: Option<MessageContent>,
+    next: Option<MessageContent>,
+) -> Option<MessageContent> {
+    match (previous, next) {
+        (None, content) | (content, None) => content,
+        (Some(MessageContent::Text(previous)), Some(MessageContent::Text(next))) => {
+            Some(MessageContent::Text([previous, next].join("\n\n")))
+        }
+        (Some(previous), Some(next)) => {
+            let into_parts = |content| match content {
+                MessageContent::Text(text) => vec![ContentPart::Text { text }],
+                MessageContent::Parts(parts) => parts,
+            };
+            let mut parts = into_parts(previous);
+            parts.extend(into_parts(next));
+            Some(MessageContent::Parts(parts))
+        }
+    }
+}
+
+/// Merge each message into the previous one when they have the same role
+fn merge_consecutive(messages: Vec<Message>) -> Vec<Message> {
+    let mut merged: Vec<Message> = Vec::with_capacity(messages.len());
+    for message in messages {
+        match merged.last_mut() {
+            Some(previous) if can_merge(previous, &message) => {
+                previous.content = merge_content(previous.content.take(), message.content);
+                if let Some(calls) = message.tool_calls {
+                    previous
+                        .tool_calls
+                        .get_or_insert_with(Vec::new)
+                        .extend(calls);
+                }
+            }
+            _ => merged.push(message),
+        }
+    }
+    merged
+}
+
+/// Remove messages identical to the previous one and repeated system messages
+fn deduplicate(messages: Vec<Message>) -> Vec<Message> {
+    let mut kept: Vec<Message> = Vec::with_capacity(messages.len());
+    for message in messages {
+        let duplicate = kept.last().is_some_and(|last| same_message(last, &message))
+            || (is_system(&message)
+                && kept
+                    .iter()
+                    .any(|kept| is_system(kept) && same_message(kept, &message)));
+        if !duplicate {
+            kept.push(message);
+        }
+    }
+    kept
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for NormalizeProcessor {
+    async fn process(&self, mut request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        let mut messages = std::mem::take(&mut request.messages);
+        if self.strip_empty {
+            messages.retain(|message| !is_empty(message));
+        }
+        if self.deduplicate {
+            messages = deduplicate(messages);
+        }
+        if self.enforce_alternation {
+            // A stable sort keeps the order within system and other messages
+            messages.sort_by_key(|message| !is_system(message));
+        }
+        if self.merge_consecutive {
+            messages = merge_consecutive(messages);
+        }
+        if self.enforce_alternation {
+            let first = messages.iter().position(|message| !is_system(message));
+            if let Some(index) = first.filter(|&index| messages[index].role == "assistant") {
+                messages.insert(
+                    index,
+                    Message {
+                        role: "user".to_string(),
+                        content: Some(MessageContent::Text(PLACEHOLDER_USER_MESSAGE.to_string())),
+                        name: None,
+                        function_call: None,
+                        tool_calls: None,
+                        tool_call_id: None,
+                    },
+                );
+            }
+        }
+        request.messages = messages;
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_normalization() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "claude-3-5-sonnet",
+            "messages": [
+                {"role": "assistant", "content": "Hi, how can I help?"},
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Hello"},
+                {"role": "user", "content": "Hello"},
+                {"role": "user", "content": ""},
+                {"role": "user", "content": "What's new?"},
+                {"role": "system", "content": "Be brief."},
+            ],
+        }))
+        .expect("Failed to build request");
+
+        let processed = NormalizeProcessor::new()
+            .with_enforce_alternation(true)
+            .process(request)
+            .await
+            .expect("Failed to process request");
+        let messages: Vec<(&str, String)> = processed
+            .messages
+            .iter()
+            .map(|message| {
+                (
+                    message.role.as_str(),
+                    message
+                        .content
+                        .as_ref()
+                        .map(MessageContent::text)
+                        .unwrap_or_default(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                ("system", "Be brief.".to_string()),
+                ("user", PLACEHOLDER_USER_MESSAGE.to_string()),
+                ("assistant", "Hi, how can I help?".to_string()),
+                ("user", "Hello\n\nWhat's new?".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use async_trait::async_trait;
+use llm_proxy_core::{Processor, Result};
+use serde_json::{json, Value};
+
+use crate::types::ChatCompletionRequest;
+
+/// Header `OpenRouter` uses to attribute requests to an app's site
+pub const OPENROUTER_REFERER_HEADER: &str = "HTTP-Referer";
+
+/// Header `OpenRouter` uses to attribute requests to an app's name
+pub const OPENROUTER_TITLE_HEADER: &str = "X-Title";
+
+/// Processor that applies `OpenRouter` routing preferences to chat requests.
+///
+/// `OpenRouter` accepts extra fields on top of the `OpenAI` format: `provider`
+/// preferences (order, fallbacks, data collection), prompt `transforms`, and
+/// a list of fallback `models`. This processor adds backend-wide defaults for
+/// them; fields set by the client take precedence.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::processors::OpenRouterProcessor;
+///
+/// let processor = OpenRouterProcessor::new()
+///     .with_provider_preferences(serde_json::json!({"order": ["anthropic"], "allow_fallbacks": true}))
+///     .with_transforms(vec!["middle-out".to_string()])
+///     .with_fallback_models(vec!["openai/gpt-4o-mini".to_string()]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OpenRouterProcessor {
+    provider: Option<Value>,
+    transforms: Option<Vec<String>>,
+    fallback_models: Vec<String>,
+}
+
+impl OpenRouterProcessor {
+    /// Create a processor that leaves requests unchanged
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send `preferences` as the `provider` field of requests
+    #[must_use]
+    pub fn with_provider_preferences(mut self, preferences: Value) -> Self {
+        self.provider = Some(preferences);
+        self
+    }
+
+    /// Apply `transforms` (e.g. `middle-out`) to the prompts of requests
+    #[must_use]
+    pub fn with_transforms(mut self, transforms: Vec<String>) -> Self {
+        self.transforms = Some(transforms);
+        self
+    }
+
+    /// Try `models`, in order, when the requested model is unavailable
+    #[must_use]
+    pub fn with_fallback_models(mut self, models: Vec<String>) -> Self {
+        self.fallback_models = models;
+        self
+    }
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for OpenRouterProcessor {
+    async fn process(&self, mut request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        let params = &mut request.additional_params;
+        if let Some(provider) = &self.provider {
+            params
+                .entry("provider".to_string())
+                .or_insert_with(|| provider.clone());
+        }
+        if let Some(transforms) = &self.transforms {
+            params
+                .entry("transforms".to_string())
+                .or_insert_with(|| json!(transforms));
+        }
+        if !self.fallback_models.is_empty() && !params.contains_key("models") {
+            // OpenRouter tries the models in order, starting with the requested one
+            let models: Vec<&str> = std::iter::once(request.model.as_str())
+                .chain(self.fallback_models.iter().map(String::as_str))
+                .collect();
+            params.insert("models".to_string(), json!(models));
+        }
+
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_applies_defaults() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "anthropic/claude-3.5-sonnet",
+            "messages": [],
+            "transforms": [],
+        }))
+        .expect("Failed to build request");
+
+        let processor = OpenRouterProcessor::new()
+            .with_provider_preferences(json!({"order": ["anthropic"]}))
+            .with_transforms(vec!["middle-out".to_string()])
+            .with_fallback_models(vec!["openai/gpt-4o".to_string()]);
+        let request = processor
+            .process(request)
+            .await
+            .expect("Failed to process request");
+
+        let body = serde_json::to_value(&request).expect("Failed to serialize");
+        assert_eq!(body["provider"], json!({"order": ["anthropic"]}));
+        // Set by the client
+        assert_eq!(body["transforms"], json!([]));
+        assert_eq!(
+            body["models"],
+            json!(["anthropic/claude-3.5-sonnet", "openai/gpt-4o"])
+        );
+    }
+}
@@ -0,0 +1,381 @@
+use std::{
+    io::ErrorKind,
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use llm_proxy_core::{redact::redact_json, Error, Processor, RequestContext, Result};
+use serde_json::{json, Value};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+use tracing::warn;
+
+use crate::types::ChatCompletionRequest;
+
+/// Destination of the records written by a [`RequestLogProcessor`]
+#[async_trait]
+pub trait RequestLogSink: Send + Sync {
+    /// Write one record
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the record can't be written.
+    async fn write(&self, record: &Value) -> Result<()>;
+}
+
+/// Sink printing each record to stdout as one line of JSON
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdoutSink;
+
+#[async_trait]
+impl RequestLogSink for StdoutSink {
+    async fn write(&self, record: &Value) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        tokio::io::stdout().write_all(&line).await?;
+        Ok(())
+    }
+}
+
+/// Sink appending records as JSON lines to a file, rolled over by size.
+///
+/// When a record would grow the file past its maximum size, the file is
+/// renamed to `<path>.1`, older files move up one number, the oldest beyond
+/// the number kept is removed, and a new file is started.
+#[derive(Debug)]
+pub struct RollingFileSink {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: Mutex<Option<(File, u64)>>,
+}
+
+impl RollingFileSink {
+    /// Create a sink writing to `path`, rolled over at 100 MiB with 5 old files kept
+    #[must_use]
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes: 100 * 1024 * 1024,
+            max_files: 5,
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Set the size from which the file is rolled over
+    #[must_use]
+    pub const fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Set how many rolled over files are kept
+    #[must_use]
+    pub const fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files;
+        self
+    }
+
+    /// Path of the `index`th rolled over file
+    fn rolled_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{index}"));
+        path.into()
+    }
+
+    /// Shift the rolled over files and move the current file to `<path>.1`
+    async fn roll_over(&self) -> Result<()> {
+        let renames = (1..self.max_files)
+            .rev()
+            .map(|index| (self.rolled_path(index), self.rolled_path(index + 1)))
+            .chain((self.max_files > 0).then(|| (self.path.clone(), self.rolled_path(1))));
+        for (from, to) in renames {
+            match fs::rename(&from, &to).await {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        if self.max_files == 0 {
+            fs::remove_file(&self.path).await?;
+        }
+        Ok(())
+    }
+
+    async fn open(&self) -> Result<(File, u64)> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        let size = file.metadata().await?.len();
+        Ok((file, size))
+    }
+}
+
+#[async_trait]
+impl RequestLogSink for RollingFileSink {
+    async fn write(&self, record: &Value) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let line_len = line.len() as u64;
+
+        let mut state = self.file.lock().await;
+        if state.is_none() {
+            *state = Some(self.open().await?);
+        }
+        if state
+            .as_ref()
+            .is_some_and(|(_, size)| *size > 0 && size + line_len > self.max_bytes)
+        {
+            *state = None;
+            self.roll_over().await?;
+            *state = Some(self.open().await?);
+        }
+        let Some((file, size)) = state.as_mut() else {
+            return Ok(());
+        };
+        file.write_all(&line).await?;
+        file.flush().await?;
+        *size += line_len;
+        drop(state);
+        Ok(())
+    }
+}
+
+/// Sink posting each record as JSON to an HTTP endpoint
+#[derive(Debug, Clone)]
+pub struct HttpSink {
+    client: reqwest::Client,
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+impl HttpSink {
+    /// Create a sink posting to `url`
+    #[must_use]
+    pub fn new(client: reqwest::Client, url: impl Into<String>) -> Self {
+        Self {
+            client,
+            url: url.into(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Add a header sent with every record, e.g. for authentication
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+#[async_trait]
+impl RequestLogSink for HttpSink {
+    async fn write(&self, record: &Value) -> Result<()> {
+        let request = self
+            .headers
+            .iter()
+            .fold(self.client.post(&self.url), |request, (name, value)| {
+                request.header(name, value)
+            });
+        request
+            .json(record)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| Error::ProcessError(format!("Failed to send request log: {e}")))?;
+        Ok(())
+    }
+}
+
+/// Processor writing the full request to a [`RequestLogSink`], leaving it
+/// unchanged, to build prompt datasets or debug clients without an audit
+/// database.
+///
+/// Each record is a JSON object with the `timestamp` in milliseconds since
+/// the Unix epoch, the `route`, `tenant` and `model` of the request context
+/// and the `request` itself. Keys and tokens in the request are masked unless
+/// redaction is turned off. A sample rate below 1 logs that share of
+/// requests, spread evenly over them. Records are written in the background;
+/// failing to write one is logged and doesn't fail or delay the request.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use llm_proxy_openai::processors::{RequestLogProcessor, RollingFileSink};
+///
+/// let sink = RollingFileSink::new("requests.jsonl").with_max_bytes(10 * 1024 * 1024);
+/// let processor = RequestLogProcessor::new(Arc::new(sink)).with_sample_rate(0.1);
+/// ```
+pub struct RequestLogProcessor {
+    sink: Arc<dyn RequestLogSink>,
+    sample_rate: f64,
+    redact: bool,
+    seen: AtomicU64,
+}
+
+impl RequestLogProcessor {
+    /// Create a processor logging every request, redacted, to `sink`
+    #[must_use]
+    pub const fn new(sink: Arc<dyn RequestLogSink>) -> Self {
+        Self {
+            sink,
+            sample_rate: 1.0,
+            redact: true,
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    /// Set the share of requests logged, between 0 and 1
+    #[must_use]
+    pub const fn with_sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = sample_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set whether keys and tokens in requests are masked
+    #[must_use]
+    pub const fn with_redaction(mut self, redact: bool) -> Self {
+        self.redact = redact;
+        self
+    }
+
+    /// Whether the next request is logged
+    #[allow(clippy::cast_precision_loss)]
+    fn sampled(&self) -> bool {
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed);
+        // Log request n when the expected number of logged requests passes
+        // an integer, which spreads the logged ones evenly
+        let before = (seen as f64 * self.sample_rate).floor();
+        let after = ((seen + 1) as f64 * self.sample_rate).floor();
+        after > before
+    }
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for RequestLogProcessor {
+    async fn process(&self, request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        self.process_with_context(request, &mut RequestContext::new())
+            .await
+    }
+
+    async fn process_with_context(
+        &self,
+        request: ChatCompletionRequest,
+        context: &mut RequestContext,
+    ) -> Result<ChatCompletionRequest> {
+        if !self.sampled() {
+            return Ok(request);
+        }
+        let mut body = serde_json::to_value(&request)?;
+        if self.redact {
+            redact_json(&mut body);
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis());
+        let record = json!({
+            "timestamp": timestamp,
+            "route": context.route,
+            "tenant": context.tenant,
+            "model": request.model,
+            "request": body,
+        });
+        // Written in the background, so a slow sink doesn't delay requests
+        let sink = self.sink.clone();
+        tokio::spawn(async move {
+            if let Err(e) = sink.write(&record).await {
+                warn!(error = %e, "Failed to write request log");
+            }
+        });
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_request_log() {
+        let path =
+            std::env::temp_dir().join(format!("llm-proxy-request-log-{}", std::process::id()));
+        let sink = RollingFileSink::new(&path)
+            .with_max_bytes(1)
+            .with_max_files(1);
+        let processor = RequestLogProcessor::new(Arc::new(sink)).with_sample_rate(0.5);
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "My key is sk-abcdefghijklmnopqrstuvwxyz"}],
+        }))
+        .expect("Failed to build request");
+        let mut context = RequestContext::new().with_tenant("acme");
+
+        for _ in 0..4 {
+            processor
+                .process_with_context(request.clone(), &mut context)
+                .await
+                .expect("Failed to process request");
+        }
+
+        // Records are written in the background
+        let rolled_path = format!("{}.1", path.display());
+        for _ in 0..100 {
+            if std::fs::metadata(&path).is_ok_and(|metadata| metadata.len() > 0)
+                && std::fs::metadata(&rolled_path).is_ok()
+            {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let current = std::fs::read_to_string(&path).expect("Failed to read log");
+        let rolled = std::fs::read_to_string(&rolled_path).expect("Failed to read rolled log");
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(&rolled_path).ok();
+        for log in [&current, &rolled] {
+            let record: Value = serde_json::from_str(log.trim()).expect("Invalid record");
+            assert_eq!(record["tenant"], "acme");
+            assert_eq!(
+                record["request"]["messages"][0]["content"],
+                "My key is [REDACTED]"
+            );
+        }
+    }
+
+    /// Sink whose writes never finish, like an endpoint that hangs
+    struct HangingSink;
+
+    #[async_trait]
+    impl RequestLogSink for HangingSink {
+        async fn write(&self, _record: &Value) -> Result<()> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hanging_sink_doesnt_delay_requests() {
+        let processor = RequestLogProcessor::new(Arc::new(HangingSink));
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "Hi"}],
+        }))
+        .expect("Failed to build request");
+
+        let processed = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            processor.process_with_context(request, &mut RequestContext::new()),
+        )
+        .await
+        .expect("The request waited for the sink")
+        .expect("Failed to process request");
+        assert_eq!(processed.model, "gpt-4o");
+    }
+}
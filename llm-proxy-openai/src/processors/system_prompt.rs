@@ -0,0 +1,137 @@
+use async_trait::async_trait;
+use llm_proxy_core::{Processor, Result};
+use serde::Deserialize;
+
+use crate::types::{ChatCompletionRequest, Message, MessageContent};
+
+/// How a [`SystemPromptProcessor`] combines its prompt with the system
+/// messages of a request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemPromptMode {
+    /// Add the prompt before any system messages of the request
+    #[default]
+    Prepend,
+    /// Replace the system messages of the request with the prompt
+    Replace,
+    /// Add the prompt only to requests without system messages
+    IfMissing,
+}
+
+/// Processor that adds a route-wide system prompt to chat requests.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::processors::{SystemPromptMode, SystemPromptProcessor};
+///
+/// let processor = SystemPromptProcessor::new("Answer in English.")
+///     .with_mode(SystemPromptMode::IfMissing);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SystemPromptProcessor {
+    prompt: String,
+    mode: SystemPromptMode,
+}
+
+impl SystemPromptProcessor {
+    /// Create a processor that prepends `prompt` to every request
+    pub fn new(prompt: impl Into<String>) -> Self {
+        Self {
+            prompt: prompt.into(),
+            mode: SystemPromptMode::default(),
+        }
+    }
+
+    /// Set how the prompt is combined with the system messages of requests
+    #[must_use]
+    pub const fn with_mode(mut self, mode: SystemPromptMode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+/// Whether a message gives instructions, as `system` or `developer`
+fn is_system(message: &Message) -> bool {
+    matches!(message.role.as_str(), "system" | "developer")
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for SystemPromptProcessor {
+    async fn process(&self, mut request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        match self.mode {
+            SystemPromptMode::Prepend => {}
+            SystemPromptMode::Replace => request.messages.retain(|message| !is_system(message)),
+            SystemPromptMode::IfMissing => {
+                if request.messages.iter().any(is_system) {
+                    return Ok(request);
+                }
+            }
+        }
+        request.messages.insert(
+            0,
+            Message {
+                role: "system".to_string(),
+                content: Some(MessageContent::Text(self.prompt.clone())),
+                name: None,
+                function_call: None,
+                tool_calls: None,
+                tool_call_id: None,
+            },
+        );
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_modes() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Hi"},
+            ],
+        }))
+        .expect("Failed to build request");
+
+        let processed = SystemPromptProcessor::new("Answer in English.")
+            .process(request.clone())
+            .await
+            .expect("Failed to process request");
+        assert_eq!(processed.messages.len(), 3);
+        assert_eq!(
+            processed.messages[0]
+                .content
+                .as_ref()
+                .map(MessageContent::text),
+            Some("Answer in English.".to_string())
+        );
+
+        let processed = SystemPromptProcessor::new("Answer in English.")
+            .with_mode(SystemPromptMode::Replace)
+            .process(request.clone())
+            .await
+            .expect("Failed to process request");
+        assert_eq!(processed.messages.len(), 2);
+        assert_eq!(processed.messages[1].role, "user");
+
+        let processed = SystemPromptProcessor::new("Answer in English.")
+            .with_mode(SystemPromptMode::IfMissing)
+            .process(request)
+            .await
+            .expect("Failed to process request");
+        assert_eq!(
+            processed.messages[0]
+                .content
+                .as_ref()
+                .map(MessageContent::text),
+            Some("Be brief.".to_string())
+        );
+    }
+}
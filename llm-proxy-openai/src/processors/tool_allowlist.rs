@@ -0,0 +1,261 @@
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use llm_proxy_core::{Error, Processor, Result};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::types::ChatCompletionRequest;
+
+/// Hash identifying a tool by its parameters: the hex SHA-256 of the JSON
+/// Schema, serialized with sorted keys
+#[must_use]
+pub fn schema_hash(parameters: &Value) -> String {
+    hex::encode(Sha256::digest(sorted(parameters).to_string()))
+}
+
+/// `value` with the keys of all its objects in order
+fn sorted(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key.clone(), sorted(value)))
+                    .collect(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.iter().map(sorted).collect()),
+        _ => value.clone(),
+    }
+}
+
+/// Processor that only lets a request use approved tools, for deployments
+/// where models must not be handed arbitrary capabilities.
+///
+/// A tool is approved when its function name, or the [`schema_hash`] of its
+/// parameters, is allowed; tools that aren't functions are approved by their
+/// `type`. Unapproved tools are removed from `tools` and `functions`, and a
+/// `tool_choice` forcing one falls back to the default. With rejection
+/// enabled, requests forcing an unapproved tool or carrying calls to one are
+/// refused with [`Error::Rejected`] instead.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::processors::{tool_allowlist::schema_hash, ToolAllowlistProcessor};
+/// use serde_json::json;
+///
+/// let search_schema = json!({"type": "object", "properties": {"query": {"type": "string"}}});
+/// let processor = ToolAllowlistProcessor::new()
+///     .with_name("get_weather")
+///     .with_schema_hash(schema_hash(&search_schema))
+///     .with_reject(true);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ToolAllowlistProcessor {
+    names: HashSet<String>,
+    schema_hashes: HashSet<String>,
+    reject: bool,
+}
+
+impl ToolAllowlistProcessor {
+    /// Create a processor approving no tools
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Approve the function `name`, or the tools of type `name`
+    #[must_use]
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.names.insert(name.into());
+        self
+    }
+
+    /// Approve the tools whose parameters have the [`schema_hash`] `hash`
+    #[must_use]
+    pub fn with_schema_hash(mut self, hash: impl Into<String>) -> Self {
+        self.schema_hashes.insert(hash.into().to_lowercase());
+        self
+    }
+
+    /// Set whether requests calling unapproved tools are refused
+    #[must_use]
+    pub const fn with_reject(mut self, reject: bool) -> Self {
+        self.reject = reject;
+        self
+    }
+
+    /// Whether the function `name` with `parameters` is approved
+    fn allows_function(&self, name: &str, parameters: Option<&Value>) -> bool {
+        self.names.contains(name)
+            || parameters
+                .is_some_and(|parameters| self.schema_hashes.contains(&schema_hash(parameters)))
+    }
+
+    /// Whether a `tools` entry is approved
+    fn allows_tool(&self, tool: &Value) -> bool {
+        match tool["type"].as_str() {
+            Some("function") | None => tool["function"]["name"]
+                .as_str()
+                .is_some_and(|name| self.allows_function(name, tool["function"].get("parameters"))),
+            Some(tool_type) => self.names.contains(tool_type),
+        }
+    }
+
+    /// Whether the request may call the function `name`, judged by its
+    /// definition in the request
+    fn allows_call(&self, request: &ChatCompletionRequest, name: &str) -> bool {
+        let tool_parameters = request
+            .additional_params
+            .get("tools")
+            .and_then(Value::as_array)
+            .and_then(|tools| {
+                tools
+                    .iter()
+                    .find(|tool| tool["function"]["name"].as_str() == Some(name))
+            })
+            .and_then(|tool| tool["function"].get("parameters"));
+        let function_parameters = || {
+            request
+                .functions
+                .iter()
+                .flatten()
+                .find(|function| function.name == name)
+                .map(|function| &function.parameters)
+        };
+        self.allows_function(name, tool_parameters.or_else(function_parameters))
+    }
+
+    /// The unapproved tool the request forces with `tool_choice` or
+    /// `function_call`, if any
+    fn forced_tool(&self, request: &ChatCompletionRequest) -> Option<String> {
+        let forced = request
+            .additional_params
+            .get("tool_choice")
+            .and_then(|choice| choice["function"]["name"].as_str())
+            .or_else(|| {
+                request
+                    .additional_params
+                    .get("function_call")
+                    .and_then(|call| call["name"].as_str())
+            })?;
+        (!self.allows_call(request, forced)).then(|| forced.to_string())
+    }
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for ToolAllowlistProcessor {
+    async fn process(&self, mut request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        if self.reject {
+            let called = request
+                .messages
+                .iter()
+                .flat_map(|message| {
+                    let calls = message
+                        .tool_calls
+                        .iter()
+                        .flatten()
+                        .map(|call| &call.function);
+                    message.function_call.iter().chain(calls)
+                })
+                .find(|call| !self.allows_call(&request, &call.name))
+                .map(|call| call.name.clone());
+            if let Some(name) = self.forced_tool(&request).or(called) {
+                return Err(Error::Rejected(format!("Tool {name} is not allowed")));
+            }
+        }
+
+        if self.forced_tool(&request).is_some() {
+            request.additional_params.remove("tool_choice");
+            request.additional_params.remove("function_call");
+        }
+        if let Some(Value::Array(tools)) = request.additional_params.get_mut("tools") {
+            let count = tools.len();
+            tools.retain(|tool| self.allows_tool(tool));
+            if tools.len() < count {
+                debug!(removed = count - tools.len(), "Removed unapproved tools");
+            }
+            if tools.is_empty() {
+                request.additional_params.remove("tools");
+                request.additional_params.remove("tool_choice");
+                request.additional_params.remove("parallel_tool_calls");
+            }
+        }
+        if let Some(functions) = &mut request.functions {
+            functions.retain(|function| {
+                self.allows_function(&function.name, Some(&function.parameters))
+            });
+            if functions.is_empty() {
+                request.functions = None;
+                request.additional_params.remove("function_call");
+            }
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn tool(name: &str) -> Value {
+        json!({
+            "type": "function",
+            "function": {
+                "name": name,
+                "parameters": {"type": "object", "properties": {name: {"type": "string"}}},
+            },
+        })
+    }
+
+    fn request(tool_choice: &str) -> ChatCompletionRequest {
+        serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "Weather in Paris?"}],
+            "tools": [tool("get_weather"), tool("run_shell"), {"type": "code_interpreter"}],
+            "tool_choice": {"type": "function", "function": {"name": tool_choice}},
+        }))
+        .expect("Failed to build request")
+    }
+
+    #[tokio::test]
+    async fn test_allowlist() {
+        let processor = ToolAllowlistProcessor::new().with_name("get_weather");
+
+        let processed = processor
+            .process(request("run_shell"))
+            .await
+            .expect("Failed to process request");
+        assert_eq!(
+            processed.additional_params["tools"],
+            json!([tool("get_weather")])
+        );
+        assert!(!processed.additional_params.contains_key("tool_choice"));
+
+        // Tools are also approved by the hash of their schema
+        let hash = schema_hash(&tool("run_shell")["function"]["parameters"]);
+        let processed = ToolAllowlistProcessor::new()
+            .with_schema_hash(hash)
+            .process(request("run_shell"))
+            .await
+            .expect("Failed to process request");
+        assert_eq!(
+            processed.additional_params["tools"],
+            json!([tool("run_shell")])
+        );
+
+        let error = processor
+            .with_reject(true)
+            .process(request("run_shell"))
+            .await
+            .expect_err("Forcing unapproved tools is rejected");
+        assert!(matches!(error, Error::Rejected(_)));
+    }
+}
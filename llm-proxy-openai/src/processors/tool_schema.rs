@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use jsonschema::{ValidationError, Validator};
+use llm_proxy_core::{Error, Processor, Result};
+use serde_json::Value;
+
+use crate::types::ChatCompletionRequest;
+
+/// Processor that checks the tools of chat requests before they are sent, so
+/// clients get a clear 400 instead of an opaque upstream error.
+///
+/// The `parameters` of every tool and function must be a valid JSON Schema,
+/// and the arguments of the assistant's tool calls in the conversation must
+/// be JSON matching the schema of the called tool. Failures are reported with
+/// [`Error::InvalidRequest`].
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::processors::ToolSchemaProcessor;
+///
+/// let processor = ToolSchemaProcessor::new();
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ToolSchemaProcessor;
+
+impl ToolSchemaProcessor {
+    /// Create a processor
+    #[must_use]
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+/// Describe a validation error with the location it happened at
+fn describe(error: &ValidationError) -> String {
+    let path = error.instance_path.to_string();
+    if path.is_empty() {
+        error.to_string()
+    } else {
+        format!("{error} at {path}")
+    }
+}
+
+/// Check `schema` against its meta-schema and compile it
+fn compile(name: &str, schema: &Value) -> Result<Validator> {
+    let invalid = |reason: String| {
+        Error::InvalidRequest(format!(
+            "Parameters of tool {name} are not a valid JSON Schema: {reason}"
+        ))
+    };
+    jsonschema::meta::try_validate(schema)
+        .map_err(|e| invalid(e.to_string()))?
+        .map_err(|e| invalid(describe(&e)))?;
+    jsonschema::validator_for(schema).map_err(|e| invalid(describe(&e)))
+}
+
+/// The tools and functions of `request` by name, with their parameters
+fn tool_schemas(request: &ChatCompletionRequest) -> Vec<(&str, &Value)> {
+    let tools = request
+        .additional_params
+        .get("tools")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|tool| {
+            let function = tool.get("function")?;
+            Some((function["name"].as_str()?, function.get("parameters")?))
+        });
+    let functions = request
+        .functions
+        .iter()
+        .flatten()
+        .map(|function| (function.name.as_str(), &function.parameters));
+    tools.chain(functions).collect()
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for ToolSchemaProcessor {
+    async fn process(&self, request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        let validators = tool_schemas(&request)
+            .into_iter()
+            .map(|(name, schema)| Ok((name, compile(name, schema)?)))
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        for message in &request.messages {
+            let calls = message
+                .tool_calls
+                .iter()
+                .flatten()
+                .map(|call| (call.id.as_str(), &call.function));
+            let function_call = message.function_call.iter().map(|call| ("", call));
+            for (id, call) in calls.chain(function_call) {
+                let target = if id.is_empty() {
+                    format!("call to {}", call.name)
+                } else {
+                    format!("call {id} to {}", call.name)
+                };
+                let arguments: Value = serde_json::from_str(&call.arguments).map_err(|e| {
+                    Error::InvalidRequest(format!("Arguments of {target} are not valid JSON: {e}"))
+                })?;
+                if let Some(validator) = validators.get(call.name.as_str()) {
+                    validator.validate(&arguments).map_err(|e| {
+                        Error::InvalidRequest(format!(
+                            "Arguments of {target} don't match its schema: {}",
+                            describe(&e)
+                        ))
+                    })?;
+                }
+            }
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn request(schema: &Value, arguments: &str) -> ChatCompletionRequest {
+        serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [
+                {"role": "user", "content": "Weather in Paris?"},
+                {"role": "assistant", "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": arguments},
+                }]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "Sunny"},
+            ],
+            "tools": [{"type": "function", "function": {"name": "get_weather", "parameters": schema}}],
+        }))
+        .expect("Failed to build request")
+    }
+
+    #[tokio::test]
+    async fn test_validation() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"city": {"type": "string"}},
+            "required": ["city"],
+        });
+        let processor = ToolSchemaProcessor::new();
+
+        processor
+            .process(request(&schema, r#"{"city": "Paris"}"#))
+            .await
+            .expect("Valid calls pass");
+
+        let error = processor
+            .process(request(&schema, r#"{"city": 75}"#))
+            .await
+            .expect_err("Arguments must match the schema");
+        assert!(matches!(error, Error::InvalidRequest(_)));
+        assert!(error.to_string().contains("/city"));
+
+        let error = processor
+            .process(request(&schema, "{city: Paris"))
+            .await
+            .expect_err("Arguments must be JSON");
+        assert!(error.to_string().contains("not valid JSON"));
+
+        let error = processor
+            .process(request(&json!({"type": "dictionary"}), "{}"))
+            .await
+            .expect_err("Schemas must be valid");
+        assert!(error.to_string().contains("not a valid JSON Schema"));
+    }
+}
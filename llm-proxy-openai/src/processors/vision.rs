@@ -0,0 +1,414 @@
+use std::net::IpAddr;
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::StreamExt;
+use llm_proxy_core::{Error, Processor, Result};
+use tracing::debug;
+
+use crate::types::{ChatCompletionRequest, ImageDetail, ImageUrl};
+
+/// Default upper bound for images downloaded and inlined by the processor (20 MiB)
+const DEFAULT_MAX_INLINE_BYTES: usize = 20 * 1024 * 1024;
+
+/// Processor that validates and normalizes image parts in multimodal requests.
+///
+/// Depending on its configuration, it:
+/// - rejects requests carrying more than a maximum number of images
+/// - rewrites the `detail` level of every image to a route-wide policy
+/// - downloads remote image URLs and inlines them as base64 `data:` URLs,
+///   for backends that don't accept remote URLs
+///
+/// Images are only downloaded from hosts resolving to public addresses, or
+/// else from the hosts allowed with [`with_allowed_hosts`](Self::with_allowed_hosts),
+/// so clients can't make the proxy fetch from its own network. Downloads stop
+/// as soon as they exceed the maximum size.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::processors::VisionProcessor;
+/// use llm_proxy_openai::ImageDetail;
+///
+/// let processor = VisionProcessor::new()
+///     .with_max_images(4)
+///     .with_detail(ImageDetail::Low);
+/// ```
+#[derive(Debug, Clone)]
+pub struct VisionProcessor {
+    max_images: Option<usize>,
+    detail: Option<ImageDetail>,
+    inline_client: Option<reqwest::Client>,
+    allowed_hosts: Option<Vec<String>>,
+    max_inline_bytes: usize,
+}
+
+impl VisionProcessor {
+    /// Create a processor that only validates image URLs
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            max_images: None,
+            detail: None,
+            inline_client: None,
+            allowed_hosts: None,
+            max_inline_bytes: DEFAULT_MAX_INLINE_BYTES,
+        }
+    }
+
+    /// Reject requests containing more than `max_images` images
+    #[must_use]
+    pub const fn with_max_images(mut self, max_images: usize) -> Self {
+        self.max_images = Some(max_images);
+        self
+    }
+
+    /// Rewrite the `detail` level of every image to `detail`
+    #[must_use]
+    pub const fn with_detail(mut self, detail: ImageDetail) -> Self {
+        self.detail = Some(detail);
+        self
+    }
+
+    /// Download remote images with `client` and inline them as base64 data URLs
+    ///
+    /// The client shouldn't follow redirects, which could lead to hosts that
+    /// aren't allowed.
+    #[must_use]
+    pub fn with_inline_remote_images(mut self, client: reqwest::Client) -> Self {
+        self.inline_client = Some(client);
+        self
+    }
+
+    /// Only download images from `hosts`, trusted even when they resolve to
+    /// private addresses
+    #[must_use]
+    pub fn with_allowed_hosts(mut self, hosts: Vec<String>) -> Self {
+        self.allowed_hosts = Some(hosts);
+        self
+    }
+
+    /// Set the maximum size of a single downloaded image
+    #[must_use]
+    pub const fn with_max_inline_bytes(mut self, max_inline_bytes: usize) -> Self {
+        self.max_inline_bytes = max_inline_bytes;
+        self
+    }
+
+    /// Refuse to download from the host of `url` unless it is allowed, or
+    /// resolves to public addresses only when no hosts are allowed
+    async fn check_host(&self, url: &str) -> Result<()> {
+        let parsed = reqwest::Url::parse(url)
+            .map_err(|e| Error::InvalidRequest(format!("Invalid image URL {url}: {e}")))?;
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| Error::InvalidRequest(format!("Image URL {url} has no host")))?;
+        if let Some(allowed_hosts) = &self.allowed_hosts {
+            if allowed_hosts
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(host))
+            {
+                return Ok(());
+            }
+            return Err(Error::Rejected(format!(
+                "Images can't be downloaded from {host}"
+            )));
+        }
+
+        let port = parsed.port_or_known_default().unwrap_or(443);
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let addresses: Vec<IpAddr> = match host.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|e| Error::InvalidRequest(format!("Failed to resolve {host}: {e}")))?
+                .map(|address| address.ip())
+                .collect(),
+        };
+        if addresses.is_empty() || !addresses.into_iter().all(is_public) {
+            return Err(Error::Rejected(format!(
+                "Images can't be downloaded from {host}, which isn't a public address"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Download a remote image and encode it as a `data:` URL
+    async fn inline_image(&self, client: &reqwest::Client, url: &str) -> Result<String> {
+        self.check_host(url).await?;
+        let response = client
+            .get(url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| Error::InvalidRequest(format!("Failed to download image {url}: {e}")))?;
+
+        let mime = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.split(';').next().unwrap_or(value).trim().to_string())
+            .filter(|value| value.starts_with("image/"))
+            .ok_or_else(|| {
+                Error::InvalidRequest(format!("Remote resource {url} is not an image"))
+            })?;
+
+        let too_large = || {
+            Error::Rejected(format!(
+                "Image {url} exceeds the maximum size of {} bytes",
+                self.max_inline_bytes
+            ))
+        };
+        let announced = response.content_length().unwrap_or_default();
+        if usize::try_from(announced).map_or(true, |length| length > self.max_inline_bytes) {
+            return Err(too_large());
+        }
+        let mut bytes = Vec::new();
+        let mut chunks = response.bytes_stream();
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk
+                .map_err(|e| Error::InvalidRequest(format!("Failed to read image {url}: {e}")))?;
+            if bytes.len() + chunk.len() > self.max_inline_bytes {
+                return Err(too_large());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        debug!(url = %url, size = bytes.len(), "Inlined remote image");
+        Ok(format!("data:{mime};base64,{}", STANDARD.encode(&bytes)))
+    }
+
+    /// Validate and rewrite a single image part
+    async fn normalize_image(&self, image: &mut ImageUrl) -> Result<()> {
+        let is_remote = image.url.starts_with("http://") || image.url.starts_with("https://");
+        if !is_remote && !image.url.starts_with("data:image/") {
+            return Err(Error::InvalidRequest(format!(
+                "Unsupported image URL: {}",
+                image.url.chars().take(64).collect::<String>()
+            )));
+        }
+
+        if let Some(detail) = self.detail {
+            image.detail = Some(detail);
+        }
+
+        if let (true, Some(client)) = (is_remote, &self.inline_client) {
+            image.url = self.inline_image(client, &image.url).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether `ip` is a public address, rather than one of the host, a private
+/// network or a link (such as cloud metadata endpoints)
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            // Shared address space of carrier-grade NAT, 100.64.0.0/10
+            let shared = first == 100 && (64..128).contains(&second);
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || shared)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            // Unique local fc00::/7 and link-local fe80::/10 addresses
+            let unique_local = first & 0xfe00 == 0xfc00;
+            let link_local = first & 0xffc0 == 0xfe80;
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || unique_local
+                || link_local)
+        }
+    }
+}
+
+impl Default for VisionProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Processor<ChatCompletionRequest> for VisionProcessor {
+    async fn process(&self, mut request: ChatCompletionRequest) -> Result<ChatCompletionRequest> {
+        let image_count: usize = request
+            .messages
+            .iter_mut()
+            .filter_map(|message| message.content.as_mut())
+            .map(|content| content.images_mut().count())
+            .sum();
+
+        if let Some(max_images) = self.max_images {
+            if image_count > max_images {
+                return Err(Error::Rejected(format!(
+                    "Request contains {image_count} images, at most {max_images} are allowed"
+                )));
+            }
+        }
+
+        for content in request
+            .messages
+            .iter_mut()
+            .filter_map(|message| message.content.as_mut())
+        {
+            for image in content.images_mut() {
+                self.normalize_image(image).await?;
+            }
+        }
+
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vision_request(images: usize) -> ChatCompletionRequest {
+        let mut parts = vec![serde_json::json!({"type": "text", "text": "What is this?"})];
+        for _ in 0..images {
+            parts.push(serde_json::json!({
+                "type": "image_url",
+                "image_url": {"url": "https://example.com/cat.png", "detail": "high"}
+            }));
+        }
+        serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": parts}]
+        }))
+        .expect("Failed to build request")
+    }
+
+    #[tokio::test]
+    async fn test_rejects_too_many_images() {
+        let processor = VisionProcessor::new().with_max_images(1);
+        let result = processor.process(vision_request(2)).await;
+        assert!(matches!(result, Err(Error::Rejected(_))));
+    }
+
+    #[tokio::test]
+    async fn test_rewrites_detail() {
+        let processor = VisionProcessor::new().with_detail(ImageDetail::Low);
+        let mut request = processor
+            .process(vision_request(1))
+            .await
+            .expect("Failed to process request");
+
+        let content = request.messages[0]
+            .content
+            .as_mut()
+            .expect("Message has no content");
+        let image = content.images_mut().next().expect("Image part missing");
+        assert_eq!(image.detail, Some(ImageDetail::Low));
+        assert_eq!(content.text(), "What is this?");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unsupported_scheme() {
+        let mut request = vision_request(1);
+        if let Some(content) = request.messages[0].content.as_mut() {
+            for image in content.images_mut() {
+                image.url = "file:///etc/passwd".to_string();
+            }
+        }
+        let result = VisionProcessor::new().process(request).await;
+        assert!(matches!(result, Err(Error::InvalidRequest(_))));
+    }
+
+    /// Serve one HTTP response with `body` and `headers` on a local port,
+    /// returning the URL of an image on it
+    async fn serve_once(headers: &'static str, body: Vec<u8>) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind");
+        let address = listener.local_addr().expect("No local address");
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.expect("Failed to accept");
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            let head = format!("HTTP/1.1 200 OK\r\nContent-Type: image/png\r\n{headers}\r\n");
+            let _ = stream.write_all(head.as_bytes()).await;
+            let _ = stream.write_all(&body).await;
+        });
+        format!("http://{address}/cat.png")
+    }
+
+    fn inline_processor() -> VisionProcessor {
+        VisionProcessor::new()
+            .with_inline_remote_images(reqwest::Client::new())
+            .with_allowed_hosts(vec!["127.0.0.1".to_string()])
+            .with_max_inline_bytes(16)
+    }
+
+    #[tokio::test]
+    async fn test_inlines_allowed_image() {
+        let url = serve_once("Content-Length: 4\r\n", b"\x89PNG".to_vec()).await;
+        let inlined = inline_processor()
+            .inline_image(&reqwest::Client::new(), &url)
+            .await
+            .expect("Failed to inline image");
+        assert_eq!(inlined, "data:image/png;base64,iVBORw==");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_oversized_images() {
+        // Announced by the Content-Length
+        let url = serve_once("Content-Length: 17\r\n", vec![0; 17]).await;
+        let result = inline_processor()
+            .inline_image(&reqwest::Client::new(), &url)
+            .await;
+        assert!(matches!(result, Err(Error::Rejected(_))));
+
+        // Streamed without a Content-Length
+        let url = serve_once("Connection: close\r\n", vec![0; 64]).await;
+        let result = inline_processor()
+            .inline_image(&reqwest::Client::new(), &url)
+            .await;
+        assert!(matches!(result, Err(Error::Rejected(_))));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_private_hosts() {
+        let processor = VisionProcessor::new();
+        for url in [
+            "http://127.0.0.1/cat.png",
+            "http://localhost:8080/cat.png",
+            "http://10.0.0.8/cat.png",
+            "http://169.254.169.254/latest/meta-data/",
+            "http://[::1]/cat.png",
+            "http://[fd00::1]/cat.png",
+            "http://[::ffff:192.168.0.1]/cat.png",
+        ] {
+            let result = processor.check_host(url).await;
+            assert!(matches!(result, Err(Error::Rejected(_))), "{url}");
+        }
+        assert!(processor
+            .check_host("http://93.184.215.14/cat.png")
+            .await
+            .is_ok());
+
+        // Only the allowed hosts once some are
+        let processor = processor.with_allowed_hosts(vec!["images.internal".to_string()]);
+        assert!(processor
+            .check_host("https://images.internal/cat.png")
+            .await
+            .is_ok());
+        assert!(matches!(
+            processor.check_host("https://example.com/cat.png").await,
+            Err(Error::Rejected(_))
+        ));
+    }
+}
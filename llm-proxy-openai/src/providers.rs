@@ -1,21 +1,34 @@
+pub mod azure_entra;
+
 use std::env;
 
 use async_trait::async_trait;
 use bytes::Bytes;
-use llm_proxy_core::{ClientProvider, Error, RequestParser, Result, TokenProvider, UrlProvider};
+use llm_proxy_core::{
+    redact::SecretString, ClientProvider, Error, RequestParser, Result, TokenProvider, UrlProvider,
+};
 
 use crate::ChatCompletionRequest;
 
+pub use azure_entra::{AzureCredential, AzureEntraTokenProvider};
+
 /// Parser for `OpenAI` chat completion requests
 pub struct OpenAIRequestParser;
 
 impl OpenAIRequestParser {
-    /// Create a new `OpenAI` request parser with the given route configuration
+    /// Create a new `OpenAI` request parser
+    #[must_use]
     pub const fn new() -> Self {
         Self {}
     }
 }
 
+impl Default for OpenAIRequestParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[async_trait]
 impl RequestParser<ChatCompletionRequest> for OpenAIRequestParser {
     async fn parse(&self, body: Bytes) -> Result<ChatCompletionRequest> {
@@ -43,6 +56,12 @@ impl StaticClientProvider {
             .expect("Failed to create reqwest client");
         Self { client }
     }
+
+    /// Create a `StaticClientProvider` that hands out an already configured client
+    #[must_use]
+    pub const fn from_client(client: reqwest::Client) -> Self {
+        Self { client }
+    }
 }
 
 impl Default for StaticClientProvider {
@@ -60,13 +79,13 @@ impl ClientProvider for StaticClientProvider {
 
 /// Provider that gets an `OpenAI` API token from an environment variable
 pub struct StaticTokenProvider {
-    token: String,
+    token: SecretString,
 }
 
 impl StaticTokenProvider {
     pub fn new(token: impl Into<String>) -> Self {
         Self {
-            token: token.into(),
+            token: SecretString::new(token),
         }
     }
 }
@@ -74,7 +93,7 @@ impl StaticTokenProvider {
 #[async_trait]
 impl TokenProvider for StaticTokenProvider {
     async fn get_token(&self) -> Result<String> {
-        Ok(self.token.clone())
+        Ok(self.token.expose().to_string())
     }
 }
 
@@ -127,12 +146,24 @@ impl OpenAIUrlProvider {
         Self::new("https://api.openai.com/v1/chat/completions")
     }
 
+    /// Create a provider for the `OpenRouter` chat completions endpoint
+    #[must_use]
+    pub fn openrouter() -> Self {
+        Self::new("https://openrouter.ai/api/v1/chat/completions")
+    }
+
     /// Create a provider for the `OpenAI` completions endpoint
     #[must_use]
     pub fn completions() -> Self {
         Self::new("https://api.openai.com/v1/completions")
     }
 
+    /// Create a provider for the `OpenAI` moderations endpoint
+    #[must_use]
+    pub fn moderations() -> Self {
+        Self::new("https://api.openai.com/v1/moderations")
+    }
+
     /// Create a provider for the `OpenAI` embeddings endpoint
     #[must_use]
     pub fn embeddings() -> Self {
@@ -146,6 +177,35 @@ impl UrlProvider for OpenAIUrlProvider {
     }
 }
 
+/// Provider that builds chat completion URLs for an Azure `OpenAI` deployment
+///
+/// URLs have the form
+/// `{endpoint}/openai/deployments/{deployment}/chat/completions?api-version={api_version}`.
+pub struct AzureOpenAIUrlProvider {
+    url: String,
+}
+
+impl AzureOpenAIUrlProvider {
+    /// Default Azure `OpenAI` API version used when none is configured
+    pub const DEFAULT_API_VERSION: &'static str = "2024-10-21";
+
+    #[must_use]
+    pub fn new(endpoint: &str, deployment: &str, api_version: &str) -> Self {
+        Self {
+            url: format!(
+                "{}/openai/deployments/{deployment}/chat/completions?api-version={api_version}",
+                endpoint.trim_end_matches('/')
+            ),
+        }
+    }
+}
+
+impl UrlProvider for AzureOpenAIUrlProvider {
+    fn get_url(&self) -> Result<String> {
+        Ok(self.url.clone())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -190,11 +250,24 @@ mod tests {
     #[tokio::test]
     async fn test_url_provider() {
         let provider = OpenAIUrlProvider::chat_completions();
-        let result = provider.get_url().await;
+        let result = provider.get_url();
         assert!(result.is_ok());
         assert_eq!(
             result.expect("Failed to get URL"),
             "https://api.openai.com/v1/chat/completions"
         );
     }
+
+    #[test]
+    fn test_azure_url_provider() {
+        let provider = AzureOpenAIUrlProvider::new(
+            "https://my-resource.openai.azure.com/",
+            "gpt-4o",
+            "2024-10-21",
+        );
+        assert_eq!(
+            provider.get_url().expect("Failed to get URL"),
+            "https://my-resource.openai.azure.com/openai/deployments/gpt-4o/chat/completions?api-version=2024-10-21"
+        );
+    }
 }
@@ -0,0 +1,239 @@
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use llm_proxy_core::{redact::SecretString, Error, Result, TokenProvider};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+use tracing::debug;
+
+/// Scope of the tokens accepted by Azure `OpenAI`
+pub const COGNITIVE_SERVICES_SCOPE: &str = "https://cognitiveservices.azure.com/.default";
+
+/// Azure Instance Metadata Service token endpoint used for managed identities
+const IMDS_TOKEN_ENDPOINT: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+/// Tokens are renewed once they are this close to expiring
+const EXPIRY_MARGIN: Duration = Duration::from_mins(5);
+
+/// How an [`AzureEntraTokenProvider`] authenticates with Microsoft Entra ID
+#[derive(Debug, Clone)]
+pub enum AzureCredential {
+    /// An app registration authenticating with a client secret
+    ClientSecret {
+        /// Directory (tenant) ID
+        tenant_id: String,
+        /// Application (client) ID
+        client_id: String,
+        /// Client secret of the application
+        client_secret: SecretString,
+    },
+    /// The managed identity of the Azure resource the proxy runs on
+    ManagedIdentity {
+        /// Client ID of a user-assigned identity; the system-assigned
+        /// identity is used when unset
+        client_id: Option<String>,
+    },
+}
+
+/// Token endpoint response; managed identity endpoints encode `expires_in` as a string
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(deserialize_with = "deserialize_seconds")]
+    expires_in: u64,
+}
+
+fn deserialize_seconds<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Seconds {
+        Number(u64),
+        Text(String),
+    }
+
+    match Seconds::deserialize(deserializer)? {
+        Seconds::Number(seconds) => Ok(seconds),
+        Seconds::Text(seconds) => seconds.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+/// An access token and when it stops being usable
+#[derive(Debug)]
+struct CachedToken {
+    token: SecretString,
+    refresh_at: Instant,
+}
+
+/// Token provider that acquires Microsoft Entra ID (AAD) access tokens for Azure `OpenAI`.
+///
+/// Tokens are requested for the Cognitive Services scope with either a client
+/// secret or the managed identity of the host, and cached until shortly
+/// before they expire. Use it with [`AuthScheme::Bearer`](llm_proxy_core::AuthScheme::Bearer)
+/// so that Azure `OpenAI` can be used without API keys.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::providers::{AzureCredential, AzureEntraTokenProvider};
+///
+/// let provider = AzureEntraTokenProvider::new(AzureCredential::ManagedIdentity {
+///     client_id: None,
+/// });
+/// ```
+#[derive(Debug)]
+pub struct AzureEntraTokenProvider {
+    client: reqwest::Client,
+    credential: AzureCredential,
+    scope: String,
+    cached: Mutex<Option<CachedToken>>,
+}
+
+impl AzureEntraTokenProvider {
+    /// Create a provider acquiring Cognitive Services tokens with `credential`
+    #[must_use]
+    pub fn new(credential: AzureCredential) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            credential,
+            scope: COGNITIVE_SERVICES_SCOPE.to_string(),
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Request tokens for a different scope
+    #[must_use]
+    pub fn with_scope(mut self, scope: impl Into<String>) -> Self {
+        self.scope = scope.into();
+        self
+    }
+
+    /// Set the HTTP client used to call the token endpoint
+    #[must_use]
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Request a new access token
+    async fn acquire(&self) -> Result<TokenResponse> {
+        let request = match &self.credential {
+            AzureCredential::ClientSecret {
+                tenant_id,
+                client_id,
+                client_secret,
+            } => self
+                .client
+                .post(format!(
+                    "https://login.microsoftonline.com/{tenant_id}/oauth2/v2.0/token"
+                ))
+                .form(&[
+                    ("grant_type", "client_credentials"),
+                    ("client_id", client_id),
+                    ("client_secret", client_secret.expose()),
+                    ("scope", &self.scope),
+                ]),
+            AzureCredential::ManagedIdentity { client_id } => {
+                // Managed identity endpoints take a resource rather than a scope
+                let resource = self.scope.trim_end_matches("/.default");
+                let mut query = vec![("resource", resource)];
+                if let Some(client_id) = client_id {
+                    query.push(("client_id", client_id.as_str()));
+                }
+
+                // App Service and Functions expose their own identity endpoint
+                match (
+                    std::env::var("IDENTITY_ENDPOINT"),
+                    std::env::var("IDENTITY_HEADER"),
+                ) {
+                    (Ok(endpoint), Ok(header)) => self
+                        .client
+                        .get(endpoint)
+                        .header("X-IDENTITY-HEADER", header)
+                        .query(&[("api-version", "2019-08-01")])
+                        .query(&query),
+                    _ => self
+                        .client
+                        .get(IMDS_TOKEN_ENDPOINT)
+                        .header("Metadata", "true")
+                        .query(&[("api-version", "2018-02-01")])
+                        .query(&query),
+                }
+            }
+        };
+
+        let response = request.send().await.map_err(|e| {
+            Error::AuthenticationError(format!("Failed to request Entra token: {e}"))
+        })?;
+        let status = response.status();
+        if !status.is_success() {
+            let message = response.text().await.unwrap_or_default();
+            return Err(Error::AuthenticationError(format!(
+                "Entra token request returned {status}: {message}"
+            )));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| Error::AuthenticationError(format!("Invalid Entra token response: {e}")))
+    }
+}
+
+#[async_trait]
+impl TokenProvider for AzureEntraTokenProvider {
+    async fn get_token(&self) -> Result<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref() {
+            if Instant::now() < token.refresh_at {
+                return Ok(token.token.expose().to_string());
+            }
+        }
+
+        let response = self.acquire().await?;
+        let lifetime = Duration::from_secs(response.expires_in);
+        debug!(
+            expires_in = response.expires_in,
+            "Acquired Entra access token"
+        );
+        *cached = Some(CachedToken {
+            token: SecretString::new(response.access_token.clone()),
+            refresh_at: Instant::now() + lifetime.saturating_sub(EXPIRY_MARGIN),
+        });
+        drop(cached);
+        Ok(response.access_token)
+    }
+
+    async fn report_rejection(&self, token: &str, status: u16) {
+        if status != 401 {
+            return;
+        }
+        // The token may have been revoked; acquire a new one on the next request
+        let mut cached = self.cached.lock().await;
+        if cached
+            .as_ref()
+            .is_some_and(|cached| cached.token.expose() == token)
+        {
+            *cached = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_response_expiry_formats() {
+        let entra: TokenResponse =
+            serde_json::from_str(r#"{"access_token": "a", "expires_in": 3599}"#)
+                .expect("Failed to parse");
+        assert_eq!(entra.expires_in, 3599);
+
+        let imds: TokenResponse =
+            serde_json::from_str(r#"{"access_token": "b", "expires_in": "86399"}"#)
+                .expect("Failed to parse");
+        assert_eq!(imds.expires_in, 86399);
+    }
+}
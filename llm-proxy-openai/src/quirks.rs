@@ -0,0 +1,177 @@
+//! Workarounds for OpenAI-compatible backends that deviate from the `OpenAI` API.
+//!
+//! Servers such as vLLM, LM Studio, `SiliconFlow` or Fireworks accept the
+//! chat completions format but differ in details: some reject fields they
+//! don't know, some report other finish reasons, some never report usage.
+//! [`Quirks`] describes the deviations of one backend, and
+//! [`OpenAIClient::with_quirks`](crate::OpenAIClient::with_quirks) sanitizes
+//! requests and responses accordingly.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::types::ChatCompletionRequest;
+
+/// Parameters of the `OpenAI` chat completions API that OpenAI-compatible
+/// backends commonly accept, besides the fields of [`ChatCompletionRequest`]
+const STANDARD_PARAMS: &[&str] = &[
+    "frequency_penalty",
+    "function_call",
+    "logit_bias",
+    "logprobs",
+    "max_completion_tokens",
+    "n",
+    "parallel_tool_calls",
+    "presence_penalty",
+    "response_format",
+    "seed",
+    "stop",
+    "stream_options",
+    "tool_choice",
+    "tools",
+    "top_logprobs",
+    "top_p",
+    "user",
+];
+
+/// Deviations of an OpenAI-compatible backend from the `OpenAI` API
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::Quirks;
+///
+/// let quirks = Quirks {
+///     no_stream_options: true,
+///     finish_reasons: [("eos".to_string(), "stop".to_string())].into(),
+///     ..Quirks::default()
+/// };
+/// assert!(quirks.rewrites_responses());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    /// The backend rejects `stream_options`, so it is removed from requests
+    pub no_stream_options: bool,
+    /// Finish reasons of the backend mapped to the `OpenAI` ones, e.g. `eos` to `stop`
+    pub finish_reasons: HashMap<String, String>,
+    /// The backend omits `usage`, so non-streaming responses get zero counts
+    /// for clients that require the field
+    pub missing_usage: bool,
+    /// The backend rejects unknown fields, so only standard parameters are sent
+    pub strict_fields: bool,
+}
+
+impl Quirks {
+    /// Whether responses of the backend need to be rewritten
+    #[must_use]
+    pub fn rewrites_responses(&self) -> bool {
+        !self.finish_reasons.is_empty() || self.missing_usage
+    }
+
+    /// Remove the parameters of a request that the backend rejects
+    pub fn sanitize_request(&self, request: &mut ChatCompletionRequest) {
+        if self.no_stream_options {
+            request.additional_params.remove("stream_options");
+        }
+        if self.strict_fields {
+            request.additional_params.retain(|name, _| {
+                let standard = STANDARD_PARAMS.contains(&name.as_str());
+                if !standard {
+                    debug!(param = %name, "Dropping parameter unsupported by the backend");
+                }
+                standard
+            });
+        }
+    }
+
+    /// Rewrite a chat completion, or a chunk of a streamed one, into the `OpenAI` format
+    pub fn sanitize_response(&self, response: &mut Value) {
+        if !response.is_object() {
+            return;
+        }
+        if let Some(choices) = response["choices"].as_array_mut() {
+            for choice in choices {
+                let mapped = choice["finish_reason"]
+                    .as_str()
+                    .and_then(|reason| self.finish_reasons.get(reason))
+                    .cloned();
+                if let Some(reason) = mapped {
+                    choice["finish_reason"] = json!(reason);
+                }
+            }
+        }
+
+        let completion = response["object"] == "chat.completion";
+        if self.missing_usage && completion && response["usage"].is_null() {
+            response["usage"] = json!({
+                "prompt_tokens": 0,
+                "completion_tokens": 0,
+                "total_tokens": 0,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_request() {
+        let mut request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "qwen2.5",
+            "messages": [],
+            "stream": true,
+            "stream_options": {"include_usage": true},
+            "top_p": 0.9,
+            "store": true,
+            "reasoning_effort": "low",
+        }))
+        .expect("Invalid request");
+
+        let quirks = Quirks {
+            no_stream_options: true,
+            strict_fields: true,
+            ..Quirks::default()
+        };
+        quirks.sanitize_request(&mut request);
+
+        let mut params: Vec<&str> = request
+            .additional_params
+            .keys()
+            .map(String::as_str)
+            .collect();
+        params.sort_unstable();
+        assert_eq!(params, ["top_p"]);
+    }
+
+    #[test]
+    fn test_sanitize_response() {
+        let quirks: Quirks = serde_json::from_value(json!({
+            "finish_reasons": {"eos": "stop"},
+            "missing_usage": true,
+        }))
+        .expect("Invalid quirks");
+
+        let mut response = json!({
+            "object": "chat.completion",
+            "choices": [{"index": 0, "finish_reason": "eos"}],
+        });
+        quirks.sanitize_response(&mut response);
+        assert_eq!(response["choices"][0]["finish_reason"], "stop");
+        assert_eq!(response["usage"]["total_tokens"], 0);
+
+        // Chunks of streamed responses don't carry usage
+        let mut chunk = json!({
+            "object": "chat.completion.chunk",
+            "choices": [{"index": 0, "finish_reason": null}],
+        });
+        quirks.sanitize_response(&mut chunk);
+        assert!(chunk["choices"][0]["finish_reason"].is_null());
+        assert!(chunk.get("usage").is_none());
+    }
+}
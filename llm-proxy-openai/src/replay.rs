@@ -0,0 +1,318 @@
+//! Replay of complete chat completion responses as streams.
+//!
+//! A [`StreamReplay`] turns a stored (non-streaming) chat completion response
+//! into the server-sent events a streaming request would have received, so
+//! clients asking for `stream: true` can be answered from a complete response
+//! the same way as from the backend. The [response cache](crate::cache)
+//! answers streaming requests with it, and stores the [`completion`] of
+//! streamed responses.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use llm_proxy_core::ResponseStream;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::stream_processors::{audit, conversation};
+
+/// Characters of content per replayed chunk, unless configured otherwise;
+/// about a token, like the deltas of the API
+pub const DEFAULT_CHUNK_CHARS: usize = 4;
+
+/// Replays a complete chat completion response as a stream of chunks.
+///
+/// The content of every choice is cut into deltas of about the configured
+/// size, breaking after whitespace where possible, and preceded by a delta
+/// with the role. Tool calls are sent whole, one delta each, and the last
+/// chunk of a choice carries its `finish_reason`. Chunks can be paced with a
+/// delay so clients see a realistic stream.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use llm_proxy_openai::replay::StreamReplay;
+/// use serde_json::json;
+///
+/// let response = json!({
+///     "id": "chatcmpl-1",
+///     "object": "chat.completion",
+///     "created": 1,
+///     "model": "gpt-4o",
+///     "choices": [{
+///         "index": 0,
+///         "message": {"role": "assistant", "content": "Hello there"},
+///         "finish_reason": "stop",
+///     }],
+/// });
+/// let replay = StreamReplay::new().with_delay(Duration::from_millis(10));
+/// assert_eq!(replay.chunks(&response).len(), 4);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StreamReplay {
+    chunk_chars: usize,
+    delay: Option<Duration>,
+    usage: bool,
+}
+
+impl Default for StreamReplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamReplay {
+    /// Create a replay sending chunks of [`DEFAULT_CHUNK_CHARS`] without delay
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            chunk_chars: DEFAULT_CHUNK_CHARS,
+            delay: None,
+            usage: false,
+        }
+    }
+
+    /// Set about how many characters of content each chunk carries
+    #[must_use]
+    pub const fn with_chunk_chars(mut self, chunk_chars: usize) -> Self {
+        self.chunk_chars = chunk_chars;
+        self
+    }
+
+    /// Wait `delay` between chunks
+    #[must_use]
+    pub const fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Set whether the response's `usage` is sent in a last chunk, as for
+    /// requests with `stream_options.include_usage`
+    #[must_use]
+    pub const fn with_usage(mut self, usage: bool) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    /// The chunks of the stream replaying `response`
+    #[must_use]
+    pub fn chunks(&self, response: &Value) -> Vec<Value> {
+        let chunk = |choices: Value| {
+            let mut chunk = json!({
+                "id": response["id"],
+                "object": "chat.completion.chunk",
+                "created": response["created"],
+                "model": response["model"],
+                "choices": choices,
+            });
+            if let Some(fingerprint) = response.get("system_fingerprint") {
+                chunk["system_fingerprint"] = fingerprint.clone();
+            }
+            chunk
+        };
+        let delta = |index: &Value, delta: Value| {
+            chunk(json!([{"index": index, "delta": delta, "finish_reason": null}]))
+        };
+
+        let mut chunks = Vec::new();
+        for choice in response["choices"].as_array().into_iter().flatten() {
+            let index = &choice["index"];
+            let message = &choice["message"];
+            let role = message["role"].as_str().unwrap_or("assistant");
+            chunks.push(delta(index, json!({"role": role, "content": ""})));
+            if let Some(content) = message["content"].as_str() {
+                chunks.extend(
+                    self.split(content)
+                        .into_iter()
+                        .map(|piece| delta(index, json!({ "content": piece }))),
+                );
+            }
+            for (position, call) in message["tool_calls"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .enumerate()
+            {
+                let mut call = call.clone();
+                call["index"] = json!(position);
+                chunks.push(delta(index, json!({ "tool_calls": [call] })));
+            }
+            chunks.push(chunk(json!([{
+                "index": index,
+                "delta": {},
+                "finish_reason": choice["finish_reason"],
+            }])));
+        }
+        if self.usage && response.get("usage").is_some() {
+            let mut usage = chunk(json!([]));
+            usage["usage"] = response["usage"].clone();
+            chunks.push(usage);
+        }
+        chunks
+    }
+
+    /// Stream the chunks replaying `response`, ending with `[DONE]`
+    #[must_use]
+    pub fn replay(&self, response: &Value) -> ResponseStream {
+        let chunks = self.chunks(response);
+        let delay = self.delay;
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            for (position, chunk) in chunks.iter().enumerate() {
+                if let Some(delay) = delay.filter(|_| position > 0) {
+                    tokio::time::sleep(delay).await;
+                }
+                if tx
+                    .send(Ok(Bytes::from(format!("data: {chunk}\n\n"))))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            let _ = tx.send(Ok(Bytes::from_static(b"data: [DONE]\n\n"))).await;
+        });
+        rx
+    }
+
+    /// `content` cut into deltas, after whitespace where possible
+    fn split<'a>(&self, content: &'a str) -> Vec<&'a str> {
+        let mut pieces = Vec::new();
+        let (mut start, mut end, mut chars) = (0, 0, 0);
+        for word in content.split_inclusive(char::is_whitespace) {
+            let word_chars = word.chars().count();
+            if chars > 0 && chars + word_chars > self.chunk_chars {
+                pieces.push(&content[start..end]);
+                start = end;
+                chars = 0;
+            }
+            end += word.len();
+            chars += word_chars;
+        }
+        if start < content.len() {
+            pieces.push(&content[start..]);
+        }
+        pieces
+    }
+}
+
+/// The complete response a stream `body` sent, the opposite of a replay
+///
+/// Streams that didn't finish, or that have several choices, have none.
+#[must_use]
+pub fn completion(body: &[u8]) -> Option<Value> {
+    let chunks = audit::response(body);
+    let first = chunks.as_array()?.first()?;
+    let choices = chunks
+        .as_array()?
+        .iter()
+        .filter_map(|chunk| chunk["choices"].as_array())
+        .flatten();
+    if choices.clone().any(|choice| choice["index"] != 0) {
+        return None;
+    }
+    let finish_reason = choices
+        .filter_map(|choice| choice["finish_reason"].as_str())
+        .next_back()?;
+    let message = conversation::reply(&chunks)?;
+    let mut response = json!({
+        "id": first["id"],
+        "object": "chat.completion",
+        "created": first["created"],
+        "model": first["model"],
+        "choices": [{"index": 0, "message": message, "finish_reason": finish_reason}],
+    });
+    if let Some(usage) = chunks
+        .as_array()?
+        .iter()
+        .rev()
+        .find_map(|chunk| chunk.get("usage").filter(|usage| usage.is_object()))
+    {
+        response["usage"] = usage.clone();
+    }
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_replays_response_as_stream() {
+        let response = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "The answer is forty-two."},
+                "finish_reason": "stop",
+            }],
+            "usage": {"prompt_tokens": 5, "completion_tokens": 6, "total_tokens": 11},
+        });
+        let mut stream = StreamReplay::new()
+            .with_chunk_chars(8)
+            .with_delay(Duration::from_millis(1))
+            .with_usage(true)
+            .replay(&response);
+
+        let mut events = Vec::new();
+        while let Some(event) = stream.recv().await {
+            let event = event.expect("Unexpected error");
+            events.push(String::from_utf8(event.to_vec()).expect("Invalid UTF-8"));
+        }
+        assert_eq!(events.last().map(String::as_str), Some("data: [DONE]\n\n"));
+        let chunks: Vec<Value> = events[..events.len() - 1]
+            .iter()
+            .map(|event| {
+                serde_json::from_str(event.trim_start_matches("data: ")).expect("Invalid chunk")
+            })
+            .collect();
+
+        let deltas: Vec<&str> = chunks
+            .iter()
+            .filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str())
+            .collect();
+        assert_eq!(deltas, ["", "The ", "answer ", "is ", "forty-two."]);
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[0]["object"], "chat.completion.chunk");
+        assert_eq!(chunks[5]["choices"][0]["finish_reason"], "stop");
+        assert_eq!(chunks[6]["usage"]["total_tokens"], 11);
+    }
+
+    #[tokio::test]
+    async fn test_completion_of_replay() {
+        let response = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1,
+            "model": "gpt-4o",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "The answer is forty-two."},
+                "finish_reason": "stop",
+            }],
+            "usage": {"prompt_tokens": 5, "completion_tokens": 6, "total_tokens": 11},
+        });
+        let mut stream = StreamReplay::new().with_usage(true).replay(&response);
+        let mut body = Vec::new();
+        while let Some(event) = stream.recv().await {
+            body.extend_from_slice(&event.expect("Unexpected error"));
+        }
+        assert_eq!(completion(&body), Some(response));
+
+        // Without its last chunk, the stream didn't finish
+        let unfinished = StreamReplay::new().chunks(&json!({
+            "choices": [{"index": 0, "message": {"content": "Hi"}, "finish_reason": null}],
+        }));
+        let body = unfinished
+            .iter()
+            .map(|chunk| format!("data: {chunk}\n\n"))
+            .collect::<Vec<_>>()
+            .concat();
+        assert_eq!(completion(body.as_bytes()), None);
+    }
+}
@@ -0,0 +1,167 @@
+//! Stream processors for `OpenAI` chat completion responses.
+//!
+//! These processors implement the [`StreamProcessor`](llm_proxy_core::StreamProcessor)
+//! trait on top of a [`ChunkFilter`], which sees every chunk of a streamed
+//! response and can rewrite it or end the response. Non-streaming responses
+//! are passed through unchanged.
+
+use bytes::Bytes;
+use llm_proxy_core::ResponseStream;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+pub mod audit;
+pub mod code_fence;
+pub mod conversation;
+pub mod guardrail;
+pub mod output_limit;
+pub mod rewrite;
+pub mod stop_sequence;
+pub mod usage;
+
+pub use audit::AuditProcessor;
+pub use code_fence::{CodeFenceProcessor, CodeFenceTracker, FencePolicy, FenceSegment};
+pub use conversation::ConversationProcessor;
+pub use guardrail::OutputGuardrailProcessor;
+pub use output_limit::OutputLimitProcessor;
+pub use rewrite::ContentRewriteProcessor;
+pub use stop_sequence::StopSequenceProcessor;
+pub use usage::UsageMetricsProcessor;
+
+/// What a [`ChunkFilter`] decided about a chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkVerdict {
+    /// Forward the chunk, with any changes the filter made
+    Forward,
+    /// Send `events` instead of the chunk and end the response, which
+    /// cancels the upstream request
+    Stop(Vec<Value>),
+}
+
+/// Per-response state of a stream processor working on the chunks of a
+/// chat completion stream
+pub trait ChunkFilter: Send {
+    /// Inspect the next chunk of the response, possibly changing it
+    fn filter(&mut self, chunk: &mut Value) -> ChunkVerdict;
+}
+
+/// Run `filter` over the server-sent events of `stream`.
+///
+/// Events other than chunks, like `[DONE]` and comments, are forwarded as
+/// they are. After a [`ChunkVerdict::Stop`], `[DONE]` is sent and `stream`
+/// is dropped.
+#[must_use]
+pub fn filter_stream(
+    mut stream: ResponseStream,
+    mut filter: impl ChunkFilter + 'static,
+) -> ResponseStream {
+    let (tx, rx) = mpsc::channel(100);
+    tokio::spawn(async move {
+        let mut buffer = Vec::new();
+        let mut streaming = None;
+        while let Some(item) = stream.recv().await {
+            let chunk = match item {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    if tx.send(Err(e)).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            // Non-streaming responses are a JSON body instead of events
+            let is_streaming = *streaming.get_or_insert_with(|| {
+                !String::from_utf8_lossy(&chunk)
+                    .trim_start()
+                    .starts_with('{')
+            });
+            if !is_streaming {
+                if tx.send(Ok(chunk)).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+
+            // Events are decoded once complete, so characters split between
+            // chunks stay intact
+            buffer.extend_from_slice(&chunk);
+            while let Some(end) = event_end(&buffer) {
+                let event: Vec<u8> = buffer.drain(..end).collect();
+                let (events, stop) = filter_event(&String::from_utf8_lossy(&event), &mut filter);
+                for event in events {
+                    if tx.send(Ok(event)).await.is_err() {
+                        return;
+                    }
+                }
+                if stop {
+                    return;
+                }
+            }
+        }
+        if !buffer.is_empty() {
+            let _ = tx.send(Ok(Bytes::from(buffer))).await;
+        }
+    });
+    rx
+}
+
+/// The length of the first complete event in `buffer`, with its separator
+fn event_end(buffer: &[u8]) -> Option<usize> {
+    [&b"\n\n"[..], &b"\r\n\r\n"[..]]
+        .iter()
+        .filter_map(|separator| {
+            buffer
+                .windows(separator.len())
+                .position(|window| window == *separator)
+                .map(|start| start + separator.len())
+        })
+        .min()
+}
+
+/// The events to send for `event`, and whether the response ends with them
+fn filter_event(event: &str, filter: &mut impl ChunkFilter) -> (Vec<Bytes>, bool) {
+    let data = event
+        .lines()
+        .find_map(|line| line.strip_prefix("data:"))
+        .map(str::trim);
+    let Some(mut chunk) = data.and_then(|data| serde_json::from_str::<Value>(data).ok()) else {
+        return (vec![Bytes::from(event.to_string())], false);
+    };
+    match filter.filter(&mut chunk) {
+        ChunkVerdict::Forward => (vec![Bytes::from(format!("data: {chunk}\n\n"))], false),
+        ChunkVerdict::Stop(events) => {
+            let mut events: Vec<Bytes> = events
+                .iter()
+                .map(|event| Bytes::from(format!("data: {event}\n\n")))
+                .collect();
+            events.push(Bytes::from_static(b"data: [DONE]\n\n"));
+            (events, true)
+        }
+    }
+}
+
+/// The content deltas of `chunk`, by choice index
+pub fn content_deltas(chunk: &Value) -> impl Iterator<Item = (u64, &str)> {
+    chunk["choices"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|choice| {
+            let index = choice["index"].as_u64().unwrap_or_default();
+            choice["delta"]["content"]
+                .as_str()
+                .map(|content| (index, content))
+        })
+}
+
+/// A chunk like `chunk`, ending choice `index` with `finish_reason`
+#[must_use]
+pub fn finish_chunk(chunk: &Value, index: u64, finish_reason: &str) -> Value {
+    json!({
+        "id": chunk["id"],
+        "object": "chat.completion.chunk",
+        "created": chunk["created"],
+        "model": chunk["model"],
+        "choices": [{"index": index, "delta": {}, "finish_reason": finish_reason}],
+    })
+}
@@ -0,0 +1,221 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use llm_proxy_core::{
+    redact::redact_json, AuditStore, RequestContext, ResponseStream, StreamProcessor, Transcript,
+};
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tracing::warn;
+use uuid::Uuid;
+
+use super::event_end;
+
+/// Stream processor persisting the transcripts of requests, the request
+/// and the response it got, in an [`AuditStore`].
+///
+/// The response is forwarded unchanged; once it ends, the request and the
+/// completion, or the chunks of a streamed response, are recorded with the
+/// `route`, `tenant` and `model` of the request context. Keys and tokens are
+/// masked unless redaction is turned off. A sample rate below 1 records that
+/// share of requests, spread evenly over them. Failing to record a
+/// transcript is logged and doesn't affect the response.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use llm_proxy_core::AuditStore;
+/// use llm_proxy_openai::stream_processors::AuditProcessor;
+///
+/// fn audit(store: Arc<dyn AuditStore>) -> AuditProcessor {
+///     AuditProcessor::new(store).with_sample_rate(0.1)
+/// }
+/// ```
+pub struct AuditProcessor {
+    store: Arc<dyn AuditStore>,
+    sample_rate: f64,
+    redact: bool,
+    seen: AtomicU64,
+}
+
+impl AuditProcessor {
+    /// Create a processor recording every transcript, redacted, in `store`
+    #[must_use]
+    pub const fn new(store: Arc<dyn AuditStore>) -> Self {
+        Self {
+            store,
+            sample_rate: 1.0,
+            redact: true,
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    /// Set the share of requests recorded, between 0 and 1
+    #[must_use]
+    pub const fn with_sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = sample_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set whether keys and tokens in transcripts are masked
+    #[must_use]
+    pub const fn with_redaction(mut self, redact: bool) -> Self {
+        self.redact = redact;
+        self
+    }
+
+    /// Whether the next request is recorded
+    #[allow(clippy::cast_precision_loss)]
+    fn sampled(&self) -> bool {
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed);
+        let before = (seen as f64 * self.sample_rate).floor();
+        let after = ((seen + 1) as f64 * self.sample_rate).floor();
+        after > before
+    }
+}
+
+impl StreamProcessor for AuditProcessor {
+    fn process_stream(
+        &self,
+        request: &Value,
+        context: &RequestContext,
+        mut stream: ResponseStream,
+    ) -> ResponseStream {
+        if !self.sampled() {
+            return stream;
+        }
+        let store = self.store.clone();
+        let redact = self.redact;
+        let mut transcript = Transcript {
+            id: Uuid::new_v4().to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| {
+                    u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX)
+                }),
+            route: context.route.clone(),
+            tenant: context.tenant.clone(),
+            model: context.model.clone(),
+            request: request.clone(),
+            response: Value::Null,
+        };
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            let mut body = Vec::new();
+            while let Some(item) = stream.recv().await {
+                if let Ok(chunk) = &item {
+                    body.extend_from_slice(chunk);
+                }
+                if tx.send(item).await.is_err() {
+                    break;
+                }
+            }
+            // The client doesn't wait for the transcript to be recorded
+            drop(tx);
+            transcript.response = response(&body);
+            if redact {
+                redact_json(&mut transcript.request);
+                redact_json(&mut transcript.response);
+            }
+            if let Err(e) = store.record(&transcript).await {
+                warn!(error = %e, "Failed to record transcript");
+            }
+        });
+        rx
+    }
+}
+
+/// The completion in `body`, or the chunks of the events in it
+pub(crate) fn response(body: &[u8]) -> Value {
+    if let Ok(completion) = serde_json::from_slice::<Value>(body) {
+        return completion;
+    }
+    let mut chunks = Vec::new();
+    let mut rest = body;
+    while !rest.is_empty() {
+        let end = event_end(rest).unwrap_or(rest.len());
+        let event = String::from_utf8_lossy(&rest[..end]);
+        chunks.extend(
+            event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok()),
+        );
+        rest = &rest[end..];
+    }
+    Value::Array(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use llm_proxy_core::{AuditQuery, Result};
+    use serde_json::json;
+    use tokio::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryAuditStore(Mutex<Vec<Transcript>>);
+
+    #[async_trait]
+    impl AuditStore for MemoryAuditStore {
+        async fn record(&self, transcript: &Transcript) -> Result<()> {
+            self.0.lock().await.push(transcript.clone());
+            Ok(())
+        }
+
+        async fn query(&self, _query: &AuditQuery) -> Result<Vec<Transcript>> {
+            Ok(self.0.lock().await.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_records_streamed_transcript() {
+        let store = Arc::new(MemoryAuditStore::default());
+        let processor = AuditProcessor::new(store.clone());
+        let (tx, rx) = mpsc::channel(10);
+        for event in [
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"}}]}\n\n",
+            "data: [DONE]\n\n",
+        ] {
+            tx.send(Ok(Bytes::from(event)))
+                .await
+                .expect("Failed to send chunk");
+        }
+        drop(tx);
+
+        let request = json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "Key sk-abcdefghijklmnopqrstuvwxyz"}]});
+        let context = RequestContext::new()
+            .with_tenant("acme")
+            .with_model("gpt-4o");
+        let mut stream = processor.process_stream(&request, &context, rx);
+        let mut forwarded = 0;
+        while stream.recv().await.is_some() {
+            forwarded += 1;
+        }
+        assert_eq!(forwarded, 2);
+
+        // The transcript is recorded after the response ended
+        tokio::task::yield_now().await;
+        let transcripts = store
+            .query(&AuditQuery::new())
+            .await
+            .expect("Failed to query transcripts");
+        assert_eq!(transcripts.len(), 1);
+        assert_eq!(transcripts[0].tenant.as_deref(), Some("acme"));
+        assert_eq!(
+            transcripts[0].response,
+            json!([{"choices": [{"index": 0, "delta": {"content": "Hi"}}]}])
+        );
+        assert!(!transcripts[0].request.to_string().contains("sk-abcdef"));
+    }
+}
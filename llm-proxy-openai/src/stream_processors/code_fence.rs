@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+
+use llm_proxy_core::{redact::redact_secrets, RequestContext, ResponseStream, StreamProcessor};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{filter_stream, ChunkFilter, ChunkVerdict};
+
+/// A piece of markdown text, either in a code block or outside
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FenceSegment {
+    /// The text
+    pub text: String,
+    /// Whether the text is part of a fenced code block, fences included
+    pub in_code: bool,
+}
+
+/// Tracks whether markdown text fed in pieces is inside a fenced code block.
+///
+/// Fences are lines starting, after up to three spaces, with at least three
+/// backticks or tildes; a block is closed by a fence of the same character
+/// that is at least as long and has no info string. Text at the start of a
+/// line that might still become a fence is held back until it's known,
+/// so fences split between pieces are recognized.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::stream_processors::CodeFenceTracker;
+///
+/// let mut tracker = CodeFenceTracker::new();
+/// let mut segments = tracker.push("Run:\n`");
+/// segments.extend(tracker.push("``sh\nls\n```\n"));
+/// segments.extend(tracker.finish());
+/// let code: String = segments
+///     .iter()
+///     .filter(|segment| segment.in_code)
+///     .map(|segment| segment.text.as_str())
+///     .collect();
+/// assert_eq!(code, "```sh\nls\n```\n");
+/// ```
+#[derive(Debug, Clone)]
+pub struct CodeFenceTracker {
+    /// Character and length of the fence that opened the current block
+    fence: Option<(char, usize)>,
+    /// Start of the current line, while it might still be a fence
+    pending: Option<String>,
+    /// Whether the current line is a fence, and closes the block
+    fence_line: Option<bool>,
+    segments: Vec<FenceSegment>,
+}
+
+impl Default for CodeFenceTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CodeFenceTracker {
+    /// Create a tracker at the start of a text, outside of code
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            fence: None,
+            pending: Some(String::new()),
+            fence_line: None,
+            segments: Vec::new(),
+        }
+    }
+
+    /// Whether the text fed so far ends inside a code block
+    #[must_use]
+    pub const fn in_code(&self) -> bool {
+        self.fence.is_some()
+    }
+
+    /// Feed the next piece of text, returning the segments known so far
+    pub fn push(&mut self, text: &str) -> Vec<FenceSegment> {
+        for c in text.chars() {
+            self.push_char(c);
+        }
+        std::mem::take(&mut self.segments)
+    }
+
+    /// End the text, returning the segments held back
+    pub fn finish(&mut self) -> Vec<FenceSegment> {
+        if let Some(pending) = self.pending.take() {
+            let in_code = self.in_code();
+            self.emit(&pending, in_code);
+        }
+        self.pending = Some(String::new());
+        self.fence_line = None;
+        std::mem::take(&mut self.segments)
+    }
+
+    fn push_char(&mut self, c: char) {
+        if let Some(closes) = self.fence_line {
+            self.emit(&c.to_string(), true);
+            if c == '\n' {
+                if closes {
+                    self.fence = None;
+                }
+                self.fence_line = None;
+                self.pending = Some(String::new());
+            }
+            return;
+        }
+        let Some(mut pending) = self.pending.take() else {
+            let in_code = self.in_code();
+            self.emit(&c.to_string(), in_code);
+            if c == '\n' {
+                self.pending = Some(String::new());
+            }
+            return;
+        };
+
+        pending.push(c);
+        match self.classify(&pending, c == '\n') {
+            LineStart::Undecided => self.pending = Some(pending),
+            LineStart::Text => {
+                let in_code = self.in_code();
+                self.emit(&pending, in_code);
+                if c == '\n' {
+                    self.pending = Some(String::new());
+                }
+            }
+            LineStart::Fence(marker, length) => {
+                self.emit(&pending, true);
+                let closes = self.fence.is_some();
+                if !closes {
+                    self.fence = Some((marker, length));
+                }
+                if c == '\n' {
+                    if closes {
+                        self.fence = None;
+                    }
+                    self.pending = Some(String::new());
+                } else {
+                    self.fence_line = Some(closes);
+                }
+            }
+        }
+    }
+
+    /// What the start of a line, `line`, is known to be
+    fn classify(&self, line: &str, complete: bool) -> LineStart {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        if indent > 3 {
+            return LineStart::Text;
+        }
+        let rest = &line[indent..];
+        let Some(marker) = rest.chars().next().filter(|c| matches!(c, '`' | '~')) else {
+            return if rest.is_empty() && !complete {
+                LineStart::Undecided
+            } else {
+                LineStart::Text
+            };
+        };
+        let length = rest.chars().take_while(|&c| c == marker).count();
+        let after = &rest[length..];
+        if after.is_empty() && !complete {
+            return LineStart::Undecided;
+        }
+        if length < 3 {
+            return LineStart::Text;
+        }
+        match self.fence {
+            None if marker == '`' && after.contains('`') => LineStart::Text,
+            None => LineStart::Fence(marker, length),
+            // Closing fences have nothing but whitespace after them
+            Some((open, open_length)) if marker == open && length >= open_length => {
+                if !after.trim().is_empty() {
+                    LineStart::Text
+                } else if complete {
+                    LineStart::Fence(marker, length)
+                } else {
+                    LineStart::Undecided
+                }
+            }
+            Some(_) => LineStart::Text,
+        }
+    }
+
+    fn emit(&mut self, text: &str, in_code: bool) {
+        match self.segments.last_mut() {
+            Some(last) if last.in_code == in_code => last.text.push_str(text),
+            _ => self.segments.push(FenceSegment {
+                text: text.to_string(),
+                in_code,
+            }),
+        }
+    }
+}
+
+/// What the start of a line is known to be
+enum LineStart {
+    Undecided,
+    Text,
+    Fence(char, usize),
+}
+
+/// What a [`CodeFenceProcessor`] does with text inside or outside code blocks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FencePolicy {
+    /// Send the text unchanged
+    #[default]
+    Keep,
+    /// Mask keys and tokens in the text
+    Redact,
+    /// Remove the text
+    Strip,
+}
+
+/// Stream processor that treats the output inside and outside markdown code
+/// blocks differently, e.g. masking secrets only in code or removing code
+/// from the answers of some routes.
+///
+/// Code block state is tracked per choice with a [`CodeFenceTracker`];
+/// fences belong to the blocks they open and close. Text redacted with
+/// [`FencePolicy::Redact`] is held back up to the last whitespace, so keys
+/// split between chunks are masked as a whole.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::stream_processors::{CodeFenceProcessor, FencePolicy};
+///
+/// let processor = CodeFenceProcessor::new().with_inside(FencePolicy::Redact);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CodeFenceProcessor {
+    inside: FencePolicy,
+    outside: FencePolicy,
+}
+
+impl CodeFenceProcessor {
+    /// Create a processor keeping all text
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set what is done with text inside code blocks
+    #[must_use]
+    pub const fn with_inside(mut self, policy: FencePolicy) -> Self {
+        self.inside = policy;
+        self
+    }
+
+    /// Set what is done with text outside code blocks
+    #[must_use]
+    pub const fn with_outside(mut self, policy: FencePolicy) -> Self {
+        self.outside = policy;
+        self
+    }
+}
+
+impl StreamProcessor for CodeFenceProcessor {
+    fn process_stream(
+        &self,
+        _request: &Value,
+        _context: &RequestContext,
+        stream: ResponseStream,
+    ) -> ResponseStream {
+        if self.inside == FencePolicy::Keep && self.outside == FencePolicy::Keep {
+            return stream;
+        }
+        filter_stream(
+            stream,
+            CodeFenceFilter {
+                processor: *self,
+                choices: HashMap::new(),
+            },
+        )
+    }
+}
+
+/// State of a choice of a [`CodeFenceProcessor`] response
+#[derive(Default)]
+struct ChoiceState {
+    tracker: CodeFenceTracker,
+    /// Text to redact once it ends with whitespace
+    redacting: String,
+}
+
+/// State of a [`CodeFenceProcessor`] for one response
+struct CodeFenceFilter {
+    processor: CodeFenceProcessor,
+    choices: HashMap<u64, ChoiceState>,
+}
+
+impl CodeFenceFilter {
+    /// The text to send for `content`, with everything held back if `finish` is set
+    fn apply(&mut self, index: u64, content: &str, finish: bool) -> String {
+        let processor = self.processor;
+        let state = self.choices.entry(index).or_default();
+        let mut segments = state.tracker.push(content);
+        if finish {
+            segments.extend(state.tracker.finish());
+        }
+
+        let mut sent = String::new();
+        for segment in segments {
+            let policy = if segment.in_code {
+                processor.inside
+            } else {
+                processor.outside
+            };
+            if policy != FencePolicy::Redact {
+                sent.push_str(&redact_secrets(&std::mem::take(&mut state.redacting)));
+            }
+            match policy {
+                FencePolicy::Keep => sent.push_str(&segment.text),
+                FencePolicy::Strip => {}
+                FencePolicy::Redact => state.redacting.push_str(&segment.text),
+            }
+        }
+        let complete = if finish {
+            state.redacting.len()
+        } else {
+            state
+                .redacting
+                .rfind(char::is_whitespace)
+                .map_or(0, |end| end + 1)
+        };
+        let redacted: String = state.redacting.drain(..complete).collect();
+        sent.push_str(&redact_secrets(&redacted));
+        sent
+    }
+}
+
+impl ChunkFilter for CodeFenceFilter {
+    fn filter(&mut self, chunk: &mut Value) -> ChunkVerdict {
+        let Some(choices) = chunk["choices"].as_array_mut() else {
+            return ChunkVerdict::Forward;
+        };
+        for choice in choices {
+            let index = choice["index"].as_u64().unwrap_or_default();
+            let finish = choice["finish_reason"].is_string();
+            let content = choice["delta"]["content"].as_str();
+            if content.is_none() && !finish {
+                continue;
+            }
+            let sent = self.apply(index, content.unwrap_or_default(), finish);
+            choice["delta"]["content"] = Value::String(sent);
+        }
+        ChunkVerdict::Forward
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use serde_json::json;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_policies_inside_and_outside_code() {
+        let (tx, rx) = mpsc::channel(10);
+        let deltas = [
+            ("Set the key sk-abcdefgh", None),
+            ("ijklmnopqrstuvwxyz:\n`", None),
+            ("``sh\nexport KEY=sk-abcdefghijkl", None),
+            ("mnopqrstuvwxyz\n``", None),
+            ("`\nDone.", Some("stop")),
+        ];
+        for (content, finish_reason) in deltas {
+            let chunk = json!({
+                "id": "chatcmpl-1",
+                "choices": [{"index": 0, "delta": {"content": content}, "finish_reason": finish_reason}],
+            });
+            tx.send(Ok(Bytes::from(format!("data: {chunk}\n\n"))))
+                .await
+                .expect("Failed to send chunk");
+        }
+        drop(tx);
+
+        let processor = CodeFenceProcessor::new().with_inside(FencePolicy::Redact);
+        let mut stream = processor.process_stream(&Value::Null, &RequestContext::new(), rx);
+        let mut output = String::new();
+        while let Some(event) = stream.recv().await {
+            let event = event.expect("Unexpected error");
+            let data = std::str::from_utf8(&event).expect("Invalid UTF-8");
+            if let Ok(chunk) = serde_json::from_str::<Value>(data.trim_start_matches("data: ")) {
+                output.push_str(
+                    chunk["choices"][0]["delta"]["content"]
+                        .as_str()
+                        .unwrap_or_default(),
+                );
+            }
+        }
+        assert_eq!(
+            output,
+            "Set the key sk-abcdefghijklmnopqrstuvwxyz:\n```sh\nexport KEY=[REDACTED]\n```\nDone."
+        );
+
+        // Code can be removed entirely
+        let mut tracker = CodeFenceTracker::new();
+        let mut segments = tracker.push("Intro\n~~~~\ncode\n~~~\nstill code\n~~~~\nOutro");
+        segments.extend(tracker.finish());
+        let text: String = segments
+            .iter()
+            .filter(|segment| !segment.in_code)
+            .map(|segment| segment.text.as_str())
+            .collect();
+        assert_eq!(text, "Intro\nOutro");
+    }
+}
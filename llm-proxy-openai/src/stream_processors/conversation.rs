@@ -0,0 +1,369 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use llm_proxy_core::{ConversationStore, RequestContext, ResponseStream, StreamProcessor, Turn};
+use serde_json::{json, Map, Value};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use super::audit::response;
+
+/// Header carrying the ID of a client's conversation, unless configured otherwise
+pub const SESSION_HEADER: &str = "x-session-id";
+
+/// Stream processor persisting the turns of conversations in a
+/// [`ConversationStore`], keyed by the session ID of a request header.
+///
+/// Each turn holds the messages new in the request, those after its last
+/// assistant message except system messages, and the assistant message of
+/// the response, assembled from the chunks of a streamed one. So whether a
+/// client sends its whole history or only its newest message, every message
+/// is stored once. Sessions are scoped to the tenant of the request, when it
+/// has one.
+///
+/// The response is forwarded unchanged; the turn is recorded once it ended.
+/// Requests without a session ID, responses that fail or that the client
+/// stops reading aren't recorded. Failing to record a turn is logged and
+/// doesn't affect the response.
+///
+/// # Example
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use llm_proxy_core::ConversationStore;
+/// use llm_proxy_openai::stream_processors::ConversationProcessor;
+///
+/// fn conversations(store: Arc<dyn ConversationStore>) -> ConversationProcessor {
+///     ConversationProcessor::new(store).with_header("X-Conversation-Id")
+/// }
+/// ```
+pub struct ConversationProcessor {
+    store: Arc<dyn ConversationStore>,
+    header: String,
+}
+
+impl ConversationProcessor {
+    /// Create a processor recording turns in `store`, by the session ID of
+    /// the [`SESSION_HEADER`]
+    #[must_use]
+    pub fn new(store: Arc<dyn ConversationStore>) -> Self {
+        Self {
+            store,
+            header: SESSION_HEADER.to_string(),
+        }
+    }
+
+    /// Take the session ID from the header `name`
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>) -> Self {
+        self.header = name.into();
+        self
+    }
+}
+
+impl StreamProcessor for ConversationProcessor {
+    fn process_stream(
+        &self,
+        request: &Value,
+        context: &RequestContext,
+        mut stream: ResponseStream,
+    ) -> ResponseStream {
+        let Some(session) = session(context, &self.header) else {
+            return stream;
+        };
+        let messages = new_messages(request);
+        if messages.is_empty() {
+            return stream;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| {
+                u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX)
+            });
+        let store = self.store.clone();
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            let mut body = Vec::new();
+            while let Some(item) = stream.recv().await {
+                match &item {
+                    Ok(chunk) => body.extend_from_slice(chunk),
+                    Err(_) => body.clear(),
+                }
+                let failed = item.is_err();
+                if tx.send(item).await.is_err() || failed {
+                    return;
+                }
+            }
+            // The client doesn't wait for the turn to be recorded
+            drop(tx);
+            let Some(reply) = reply(&response(&body)) else {
+                debug!(session, "No assistant message to record");
+                return;
+            };
+            let turn = Turn {
+                timestamp,
+                messages,
+                reply,
+            };
+            if let Err(e) = store.append(&session, &turn).await {
+                warn!(error = %e, session, "Failed to record conversation turn");
+            }
+        });
+        rx
+    }
+}
+
+/// The session ID of the request in the header `name`, prefixed with the
+/// request's tenant, if any, so tenants can't reach each other's conversations
+#[must_use]
+pub fn session(context: &RequestContext, name: &str) -> Option<String> {
+    let id = context
+        .header(name)
+        .map(str::trim)
+        .filter(|id| !id.is_empty())?;
+    Some(
+        context
+            .tenant
+            .as_ref()
+            .map_or_else(|| id.to_string(), |tenant| format!("{tenant}/{id}")),
+    )
+}
+
+/// The messages of `request` after its last assistant message, without
+/// system messages
+#[must_use]
+pub fn new_messages(request: &Value) -> Vec<Value> {
+    let messages = request["messages"]
+        .as_array()
+        .map_or(&[][..], Vec::as_slice);
+    let start = messages
+        .iter()
+        .rposition(|message| message["role"] == "assistant")
+        .map_or(0, |last| last + 1);
+    messages[start..]
+        .iter()
+        .filter(|message| !matches!(message["role"].as_str(), Some("system" | "developer")))
+        .cloned()
+        .collect()
+}
+
+/// The assistant message of the first choice of `response`, a completion or
+/// the chunks of a streamed one
+pub(crate) fn reply(response: &Value) -> Option<Value> {
+    if let Some(message) = response["choices"][0].get("message") {
+        return Some(message.clone());
+    }
+    let mut content = String::new();
+    let mut calls: Vec<Value> = Vec::new();
+    for delta in response
+        .as_array()?
+        .iter()
+        .filter_map(|chunk| {
+            chunk["choices"]
+                .as_array()?
+                .iter()
+                .find(|c| c["index"] == 0)
+        })
+        .map(|choice| &choice["delta"])
+    {
+        if let Some(text) = delta["content"].as_str() {
+            content.push_str(text);
+        }
+        for call in delta["tool_calls"].as_array().into_iter().flatten() {
+            let index =
+                usize::try_from(call["index"].as_u64().unwrap_or_default()).unwrap_or_default();
+            if calls.len() <= index {
+                calls.resize_with(index + 1, || {
+                    json!({"id": "", "type": "function", "function": {"name": "", "arguments": ""}})
+                });
+            }
+            append_call_delta(&mut calls[index], call);
+        }
+    }
+    if content.is_empty() && calls.is_empty() {
+        return None;
+    }
+    let mut message = Map::new();
+    message.insert("role".to_string(), json!("assistant"));
+    message.insert(
+        "content".to_string(),
+        if content.is_empty() {
+            Value::Null
+        } else {
+            Value::String(content)
+        },
+    );
+    if !calls.is_empty() {
+        message.insert("tool_calls".to_string(), Value::Array(calls));
+    }
+    Some(Value::Object(message))
+}
+
+/// Add the pieces of a streamed tool call `delta` to `call`
+fn append_call_delta(call: &mut Value, delta: &Value) {
+    if let Some(id) = delta["id"].as_str() {
+        call["id"] = json!(id);
+    }
+    for field in ["name", "arguments"] {
+        if let Some(piece) = delta["function"][field].as_str() {
+            let mut value = call["function"][field]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            value.push_str(piece);
+            call["function"][field] = Value::String(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use llm_proxy_core::Result;
+    use tokio::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryConversationStore(Mutex<Vec<(String, Turn)>>);
+
+    #[async_trait]
+    impl ConversationStore for MemoryConversationStore {
+        async fn append(&self, session: &str, turn: &Turn) -> Result<()> {
+            self.0
+                .lock()
+                .await
+                .push((session.to_string(), turn.clone()));
+            Ok(())
+        }
+
+        async fn history(&self, session: &str, _limit: Option<u32>) -> Result<Vec<Turn>> {
+            Ok(self
+                .0
+                .lock()
+                .await
+                .iter()
+                .filter(|(id, _)| id == session)
+                .map(|(_, turn)| turn.clone())
+                .collect())
+        }
+    }
+
+    async fn run(
+        processor: &ConversationProcessor,
+        request: &Value,
+        context: &RequestContext,
+        events: &[&str],
+    ) {
+        let (tx, rx) = mpsc::channel(10);
+        for event in events {
+            tx.send(Ok(Bytes::from(event.to_string())))
+                .await
+                .expect("Failed to send chunk");
+        }
+        drop(tx);
+        let mut stream = processor.process_stream(request, context, rx);
+        while stream.recv().await.is_some() {}
+        // The turn is recorded after the response ended
+        tokio::task::yield_now().await;
+    }
+
+    #[test]
+    fn test_new_messages() {
+        let request = json!({"messages": [
+            {"role": "system", "content": "Be brief"},
+            {"role": "user", "content": "Weather in Paris?"},
+            {"role": "assistant", "tool_calls": [{"id": "call_1"}]},
+            {"role": "tool", "tool_call_id": "call_1", "content": "Sunny"},
+        ]});
+        assert_eq!(
+            new_messages(&request),
+            [json!({"role": "tool", "tool_call_id": "call_1", "content": "Sunny"})]
+        );
+
+        let request = json!({"messages": [
+            {"role": "system", "content": "Be brief"},
+            {"role": "user", "content": "Hi"},
+        ]});
+        assert_eq!(
+            new_messages(&request),
+            [json!({"role": "user", "content": "Hi"})]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_records_streamed_turn() {
+        let store = Arc::new(MemoryConversationStore::default());
+        let processor = ConversationProcessor::new(store.clone());
+        let request = json!({"model": "gpt-4o", "messages": [
+            {"role": "system", "content": "Be brief"},
+            {"role": "user", "content": "Weather in Paris?"},
+        ]});
+        let context = RequestContext::new()
+            .with_tenant("acme")
+            .with_header("X-Session-Id", "s1");
+
+        run(
+            &processor,
+            &request,
+            &context,
+            &[
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"Let me \"}}]}\n\n",
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"check.\",\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"weather\",\"arguments\":\"{\\\"city\\\":\"}}]}}]}\n\n",
+                "data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\"Paris\\\"}\"}}]},\"finish_reason\":\"tool_calls\"}]}\n\n",
+                "data: [DONE]\n\n",
+            ],
+        )
+        .await;
+
+        let history = store
+            .history("acme/s1", None)
+            .await
+            .expect("Failed to read history");
+        assert_eq!(history.len(), 1);
+        assert_eq!(
+            history[0].messages,
+            [json!({"role": "user", "content": "Weather in Paris?"})]
+        );
+        assert_eq!(
+            history[0].reply,
+            json!({
+                "role": "assistant",
+                "content": "Let me check.",
+                "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "weather", "arguments": "{\"city\":\"Paris\"}"},
+                }],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skips_requests_without_session() {
+        let store = Arc::new(MemoryConversationStore::default());
+        let processor = ConversationProcessor::new(store.clone()).with_header("X-Conversation");
+        let request = json!({"messages": [{"role": "user", "content": "Hi"}]});
+        let completion =
+            r#"{"choices":[{"index":0,"message":{"role":"assistant","content":"Hello"}}]}"#;
+
+        let context = RequestContext::new().with_header("X-Session-Id", "s1");
+        run(&processor, &request, &context, &[completion]).await;
+        assert!(store.0.lock().await.is_empty());
+
+        let context = RequestContext::new().with_header("X-Conversation", "s1");
+        run(&processor, &request, &context, &[completion]).await;
+        let history = store
+            .history("s1", None)
+            .await
+            .expect("Failed to read history");
+        assert_eq!(
+            history[0].reply,
+            json!({"role": "assistant", "content": "Hello"})
+        );
+    }
+}
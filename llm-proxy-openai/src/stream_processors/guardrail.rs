@@ -0,0 +1,180 @@
+use std::{collections::HashMap, sync::Arc};
+
+use llm_proxy_core::{Error, RequestContext, ResponseStream, Result, StreamProcessor};
+use regex::Regex;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use super::{content_deltas, filter_stream, finish_chunk, ChunkFilter, ChunkVerdict};
+use crate::processors::content_filter::words_pattern;
+
+/// Stream processor that stops generations whose output violates a policy.
+///
+/// Each category is a set of regular expressions and blocklists, matched
+/// against the text generated so far for every choice, so matches spanning
+/// chunks are found too. On a hit, the chunk completing the match and
+/// everything after it are withheld: the client instead receives a chunk with
+/// `finish_reason: "content_filter"` and an `error` event of type
+/// `policy_violation` naming the category, and the upstream request is
+/// cancelled. Stops are logged and counted in the
+/// `llm_proxy_output_guardrail_stops_total` metric.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::stream_processors::OutputGuardrailProcessor;
+///
+/// let processor = OutputGuardrailProcessor::new()
+///     .with_words("profanity", ["darn", "heck"])?
+///     .with_pattern("credentials", r"(?i)password:\s*\S+")?;
+/// # Ok::<(), llm_proxy_core::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OutputGuardrailProcessor {
+    rules: Arc<Vec<(String, Regex)>>,
+}
+
+impl OutputGuardrailProcessor {
+    /// Create a processor without categories
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop output matching the regular expression `pattern`, reported as `category`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `pattern` is not a valid regular
+    /// expression.
+    pub fn with_pattern(mut self, category: impl Into<String>, pattern: &str) -> Result<Self> {
+        let category = category.into();
+        let pattern = Regex::new(pattern).map_err(|e| {
+            Error::ConfigError(format!(
+                "Invalid pattern of output guardrail category {category}: {e}"
+            ))
+        })?;
+        Arc::make_mut(&mut self.rules).push((category, pattern));
+        Ok(self)
+    }
+
+    /// Stop output containing any of `words`, case-insensitively and as whole
+    /// words, reported as `category`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `words` is empty or too large
+    /// to compile.
+    pub fn with_words<S: AsRef<str>>(
+        self,
+        category: impl Into<String>,
+        words: impl IntoIterator<Item = S>,
+    ) -> Result<Self> {
+        let category = category.into();
+        let Some(pattern) = words_pattern(words) else {
+            return Err(Error::ConfigError(format!(
+                "Output guardrail category {category} has no words"
+            )));
+        };
+        self.with_pattern(category, &pattern)
+    }
+}
+
+impl StreamProcessor for OutputGuardrailProcessor {
+    fn process_stream(
+        &self,
+        _request: &Value,
+        context: &RequestContext,
+        stream: ResponseStream,
+    ) -> ResponseStream {
+        filter_stream(
+            stream,
+            GuardrailFilter {
+                rules: self.rules.clone(),
+                route: context.route.clone(),
+                output: HashMap::new(),
+            },
+        )
+    }
+}
+
+/// State of an [`OutputGuardrailProcessor`] for one response
+struct GuardrailFilter {
+    rules: Arc<Vec<(String, Regex)>>,
+    route: Option<String>,
+    output: HashMap<u64, String>,
+}
+
+impl ChunkFilter for GuardrailFilter {
+    fn filter(&mut self, chunk: &mut Value) -> ChunkVerdict {
+        for (index, content) in content_deltas(chunk) {
+            let output = self.output.entry(index).or_default();
+            output.push_str(content);
+            let Some((category, _)) = self.rules.iter().find(|(_, rule)| rule.is_match(output))
+            else {
+                continue;
+            };
+            warn!(category = %category, route = ?self.route, "Stopping output violating policy");
+            metrics::counter!(
+                "llm_proxy_output_guardrail_stops_total",
+                "category" => category.clone()
+            )
+            .increment(1);
+            return ChunkVerdict::Stop(vec![
+                finish_chunk(chunk, index, "content_filter"),
+                json!({
+                    "error": {
+                        "message": format!("Output stopped for violating the {category} policy"),
+                        "type": "policy_violation",
+                        "code": "content_filter",
+                    }
+                }),
+            ]);
+        }
+        ChunkVerdict::Forward
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stops_violating_output() {
+        let processor = OutputGuardrailProcessor::new()
+            .with_words("secrets", ["launch code"])
+            .expect("Failed to build processor");
+        let (tx, rx) = mpsc::channel(10);
+        let chunk = |content: &str| {
+            let chunk = json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1,
+                "model": "gpt-4o",
+                "choices": [{"index": 0, "delta": {"content": content}}],
+            });
+            Ok(Bytes::from(format!("data: {chunk}\n\n")))
+        };
+        for content in ["The launch", " code is", " 1234"] {
+            tx.send(chunk(content)).await.expect("Failed to send chunk");
+        }
+        drop(tx);
+
+        let mut stream = processor.process_stream(&Value::Null, &RequestContext::new(), rx);
+        let mut events = Vec::new();
+        while let Some(event) = stream.recv().await {
+            events.push(
+                String::from_utf8(event.expect("Unexpected error").to_vec())
+                    .expect("Invalid UTF-8"),
+            );
+        }
+        assert_eq!(events.len(), 4);
+        assert!(events[0].contains("The launch"));
+        assert!(events[1].contains(r#""finish_reason":"content_filter""#));
+        assert!(events[2].contains("policy_violation"));
+        assert_eq!(events[3], "data: [DONE]\n\n");
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use llm_proxy_core::{RequestContext, ResponseStream, StreamProcessor};
+use serde_json::Value;
+use tracing::warn;
+
+use super::{filter_stream, ChunkFilter, ChunkVerdict};
+use crate::processors::{ApproximateTokenCounter, TokenCounter};
+
+/// Stream processor that caps the output of every response, protecting
+/// against runaway generations from backends that ignore `max_tokens`.
+///
+/// The limit is the configured cap, or the request's `max_tokens` or
+/// `max_completion_tokens` when lower. Output is counted per choice with a
+/// [`TokenCounter`]; the chunk reaching the limit is cut to fit and ends its
+/// choice with `finish_reason: "length"`. Once every choice has ended, the
+/// upstream request is cancelled. Cut responses are logged and counted in the
+/// `llm_proxy_output_limit_stops_total` metric.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::stream_processors::OutputLimitProcessor;
+///
+/// let processor = OutputLimitProcessor::new(4096);
+/// ```
+#[derive(Clone)]
+pub struct OutputLimitProcessor {
+    max_tokens: usize,
+    counter: Arc<dyn TokenCounter>,
+}
+
+impl OutputLimitProcessor {
+    /// Create a processor capping output at `max_tokens`, counted with an
+    /// [`ApproximateTokenCounter`]
+    #[must_use]
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens,
+            counter: Arc::new(ApproximateTokenCounter),
+        }
+    }
+
+    /// Count tokens with `counter`, e.g. a tokenizer of the backend's models
+    #[must_use]
+    pub fn with_counter(mut self, counter: Arc<dyn TokenCounter>) -> Self {
+        self.counter = counter;
+        self
+    }
+}
+
+impl StreamProcessor for OutputLimitProcessor {
+    fn process_stream(
+        &self,
+        request: &Value,
+        context: &RequestContext,
+        stream: ResponseStream,
+    ) -> ResponseStream {
+        let max_tokens = ["max_tokens", "max_completion_tokens"]
+            .iter()
+            .filter_map(|name| request[name].as_u64())
+            .filter_map(|limit| usize::try_from(limit).ok())
+            .fold(self.max_tokens, usize::min);
+        filter_stream(
+            stream,
+            OutputLimitFilter {
+                max_tokens,
+                counter: self.counter.clone(),
+                route: context.route.clone(),
+                choices: request["n"].as_u64().unwrap_or(1),
+                output: HashMap::new(),
+                stopped: HashSet::new(),
+            },
+        )
+    }
+}
+
+/// State of an [`OutputLimitProcessor`] for one response
+struct OutputLimitFilter {
+    max_tokens: usize,
+    counter: Arc<dyn TokenCounter>,
+    route: Option<String>,
+    choices: u64,
+    output: HashMap<u64, String>,
+    stopped: HashSet<u64>,
+}
+
+impl ChunkFilter for OutputLimitFilter {
+    fn filter(&mut self, chunk: &mut Value) -> ChunkVerdict {
+        let Some(choices) = chunk["choices"].as_array_mut() else {
+            return ChunkVerdict::Forward;
+        };
+        // Choices already cut ignore the rest of their output
+        choices.retain(|choice| {
+            !self
+                .stopped
+                .contains(&choice["index"].as_u64().unwrap_or_default())
+        });
+        for choice in choices {
+            let index = choice["index"].as_u64().unwrap_or_default();
+            let Some(content) = choice["delta"]["content"].as_str() else {
+                continue;
+            };
+            let output = self.output.entry(index).or_default();
+            let before = output.len();
+            output.push_str(content);
+            if self.counter.count(output) <= self.max_tokens {
+                continue;
+            }
+
+            // Keep the longest part of the delta that fits
+            let fits = content
+                .char_indices()
+                .map(|(end, _)| end)
+                .rev()
+                .find(|&end| self.counter.count(&output[..before + end]) <= self.max_tokens)
+                .unwrap_or_default();
+            choice["delta"]["content"] = Value::String(content[..fits].to_string());
+            choice["finish_reason"] = Value::String("length".to_string());
+            self.stopped.insert(index);
+            warn!(
+                index,
+                max_tokens = self.max_tokens,
+                route = ?self.route,
+                "Cutting output exceeding the limit"
+            );
+            metrics::counter!("llm_proxy_output_limit_stops_total").increment(1);
+        }
+        if self.stopped.len() as u64 >= self.choices {
+            return ChunkVerdict::Stop(vec![chunk.clone()]);
+        }
+        ChunkVerdict::Forward
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use serde_json::json;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cuts_output_at_limit() {
+        let (tx, rx) = mpsc::channel(10);
+        for content in ["one two", " three four", " five six"] {
+            let chunk = json!({
+                "id": "chatcmpl-1",
+                "created": 1,
+                "choices": [{"index": 0, "delta": {"content": content}}],
+            });
+            tx.send(Ok(Bytes::from(format!("data: {chunk}\n\n"))))
+                .await
+                .expect("Failed to send chunk");
+        }
+        drop(tx);
+
+        // The request's max_tokens is lower than the cap
+        let request = json!({"model": "gpt-4o", "max_tokens": 4});
+        let mut stream =
+            OutputLimitProcessor::new(100).process_stream(&request, &RequestContext::new(), rx);
+        let mut chunks = Vec::new();
+        while let Some(event) = stream.recv().await {
+            let event = event.expect("Unexpected error");
+            let data = std::str::from_utf8(&event).expect("Invalid UTF-8");
+            if let Ok(chunk) = serde_json::from_str::<Value>(data.trim_start_matches("data: ")) {
+                chunks.push(chunk);
+            }
+        }
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], " three ");
+        assert_eq!(chunks[1]["choices"][0]["finish_reason"], "length");
+    }
+}
@@ -0,0 +1,256 @@
+use std::{collections::HashMap, sync::Arc};
+
+use llm_proxy_core::{Error, RequestContext, ResponseStream, Result, StreamProcessor};
+use regex::{NoExpand, Regex};
+use serde_json::Value;
+
+use super::{filter_stream, ChunkFilter, ChunkVerdict};
+
+/// Characters held back for regular expression matches spanning chunks,
+/// unless configured otherwise
+pub const DEFAULT_WINDOW: usize = 32;
+
+/// A replacement of a [`ContentRewriteProcessor`]
+#[derive(Debug, Clone)]
+struct Rewrite {
+    pattern: Regex,
+    replacement: String,
+    literal: bool,
+}
+
+impl Rewrite {
+    fn apply(&self, text: &str) -> String {
+        if self.literal {
+            self.pattern
+                .replace_all(text, NoExpand(&self.replacement))
+                .into_owned()
+        } else {
+            self.pattern
+                .replace_all(text, self.replacement.as_str())
+                .into_owned()
+        }
+    }
+}
+
+/// Stream processor that rewrites the generated text, e.g. to mask internal
+/// hostnames or replace vendor names.
+///
+/// Replacements are literal strings or regular expressions, applied in
+/// order to the content of every choice. The last characters of the output
+/// are held back until the next chunk, so matches spanning chunks are
+/// rewritten as a whole: as many as the longest literal, and at least the
+/// window for regular expressions ([`DEFAULT_WINDOW`] unless configured).
+/// Held back text is sent with the chunk finishing the choice.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::stream_processors::ContentRewriteProcessor;
+///
+/// let processor = ContentRewriteProcessor::new()
+///     .with_literal("AcmeCorp", "the vendor")
+///     .with_pattern(r"[\w-]+\.internal\.example\.com", "[internal host]")?;
+/// # Ok::<(), llm_proxy_core::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct ContentRewriteProcessor {
+    rewrites: Arc<Vec<Rewrite>>,
+    window: usize,
+    longest_literal: usize,
+}
+
+impl Default for ContentRewriteProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ContentRewriteProcessor {
+    /// Create a processor without replacements
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            rewrites: Arc::new(Vec::new()),
+            window: DEFAULT_WINDOW,
+            longest_literal: 0,
+        }
+    }
+
+    /// Replace every occurrence of `text` with `replacement`
+    #[must_use]
+    pub fn with_literal(mut self, text: &str, replacement: impl Into<String>) -> Self {
+        // An escaped literal is always a valid pattern
+        if let Ok(pattern) = Regex::new(&regex::escape(text)) {
+            self.longest_literal = self.longest_literal.max(text.chars().count());
+            Arc::make_mut(&mut self.rewrites).push(Rewrite {
+                pattern,
+                replacement: replacement.into(),
+                literal: true,
+            });
+        }
+        self
+    }
+
+    /// Replace every match of the regular expression `pattern` with
+    /// `replacement`, which may refer to groups like `$1`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `pattern` is not a valid regular
+    /// expression.
+    pub fn with_pattern(mut self, pattern: &str, replacement: impl Into<String>) -> Result<Self> {
+        let pattern = Regex::new(pattern).map_err(|e| {
+            Error::ConfigError(format!("Invalid content rewrite pattern {pattern}: {e}"))
+        })?;
+        Arc::make_mut(&mut self.rewrites).push(Rewrite {
+            pattern,
+            replacement: replacement.into(),
+            literal: false,
+        });
+        Ok(self)
+    }
+
+    /// Set how many characters are held back for regular expression matches
+    #[must_use]
+    pub const fn with_window(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+}
+
+impl StreamProcessor for ContentRewriteProcessor {
+    fn process_stream(
+        &self,
+        _request: &Value,
+        _context: &RequestContext,
+        stream: ResponseStream,
+    ) -> ResponseStream {
+        if self.rewrites.is_empty() {
+            return stream;
+        }
+        filter_stream(
+            stream,
+            RewriteFilter {
+                rewrites: self.rewrites.clone(),
+                window: self.window.max(self.longest_literal),
+                held_back: HashMap::new(),
+            },
+        )
+    }
+}
+
+/// State of a [`ContentRewriteProcessor`] for one response
+struct RewriteFilter {
+    rewrites: Arc<Vec<Rewrite>>,
+    window: usize,
+    held_back: HashMap<u64, String>,
+}
+
+impl RewriteFilter {
+    /// Split `text` into its rewritten part that can be sent and the part
+    /// held back, which is all of it but the window unless `flush` is set
+    fn rewrite(&self, text: &str, flush: bool) -> (String, String) {
+        let mut cut = if flush || self.window == 0 {
+            text.len()
+        } else {
+            text.char_indices()
+                .rev()
+                .nth(self.window - 1)
+                .map_or(0, |(start, _)| start)
+        };
+        // Matches crossing the cut are held back as a whole
+        loop {
+            let crossing = self
+                .rewrites
+                .iter()
+                .flat_map(|rewrite| rewrite.pattern.find_iter(text))
+                .filter(|found| found.start() < cut && found.end() > cut)
+                .map(|found| found.start())
+                .min();
+            match crossing {
+                Some(start) => cut = start,
+                None => break,
+            }
+        }
+        let sent = self
+            .rewrites
+            .iter()
+            .fold(text[..cut].to_string(), |sent, rewrite| {
+                rewrite.apply(&sent)
+            });
+        (sent, text[cut..].to_string())
+    }
+}
+
+impl ChunkFilter for RewriteFilter {
+    fn filter(&mut self, chunk: &mut Value) -> ChunkVerdict {
+        let Some(choices) = chunk["choices"].as_array_mut() else {
+            return ChunkVerdict::Forward;
+        };
+        for choice in choices {
+            let index = choice["index"].as_u64().unwrap_or_default();
+            let held_back = self.held_back.remove(&index).unwrap_or_default();
+            let content = choice["delta"]["content"].as_str();
+            if content.is_none() && held_back.is_empty() {
+                continue;
+            }
+            let text = held_back + content.unwrap_or_default();
+            let (sent, held_back) = self.rewrite(&text, choice["finish_reason"].is_string());
+            choice["delta"]["content"] = Value::String(sent);
+            if !held_back.is_empty() {
+                self.held_back.insert(index, held_back);
+            }
+        }
+        ChunkVerdict::Forward
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use serde_json::json;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rewrites_across_chunks() {
+        let processor = ContentRewriteProcessor::new()
+            .with_literal("AcmeCorp", "the vendor")
+            .with_pattern(r"db\d+\.corp", "[host]")
+            .expect("Failed to build processor")
+            .with_window(8);
+        let (tx, rx) = mpsc::channel(10);
+        let deltas = [
+            ("Ask Ac", None),
+            ("meCorp to fix db", None),
+            ("12.corp today", None),
+            ("", Some("stop")),
+        ];
+        for (content, finish_reason) in deltas {
+            let chunk = json!({
+                "id": "chatcmpl-1",
+                "choices": [{"index": 0, "delta": {"content": content}, "finish_reason": finish_reason}],
+            });
+            tx.send(Ok(Bytes::from(format!("data: {chunk}\n\n"))))
+                .await
+                .expect("Failed to send chunk");
+        }
+        drop(tx);
+
+        let mut stream = processor.process_stream(&Value::Null, &RequestContext::new(), rx);
+        let mut output = String::new();
+        while let Some(event) = stream.recv().await {
+            let event = event.expect("Unexpected error");
+            let data = std::str::from_utf8(&event).expect("Invalid UTF-8");
+            if let Ok(chunk) = serde_json::from_str::<Value>(data.trim_start_matches("data: ")) {
+                output.push_str(
+                    chunk["choices"][0]["delta"]["content"]
+                        .as_str()
+                        .unwrap_or_default(),
+                );
+            }
+        }
+        assert_eq!(output, "Ask the vendor to fix [host] today");
+    }
+}
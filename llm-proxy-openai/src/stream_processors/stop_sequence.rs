@@ -0,0 +1,222 @@
+use std::collections::{HashMap, HashSet};
+
+use llm_proxy_core::{RequestContext, ResponseStream, StreamProcessor};
+use serde_json::Value;
+use tracing::debug;
+
+use super::{filter_stream, ChunkFilter, ChunkVerdict};
+
+/// Stream processor that enforces stop sequences for backends ignoring
+/// `stop` when streaming.
+///
+/// The configured sequences and, unless turned off, those in the request's
+/// `stop` parameter are searched in the output of every choice. Text that
+/// could be the start of a sequence is held back until the next chunk shows
+/// whether it is, so sequences split between chunks are found and never
+/// reach the client. The output is cut before the first sequence found and
+/// the choice is ended with `finish_reason: "stop"`; once every choice has
+/// ended, the upstream request is cancelled.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_openai::stream_processors::StopSequenceProcessor;
+///
+/// let processor = StopSequenceProcessor::new().with_stop("\nUser:");
+/// ```
+#[derive(Debug, Clone)]
+pub struct StopSequenceProcessor {
+    stops: Vec<String>,
+    request_stops: bool,
+}
+
+impl Default for StopSequenceProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StopSequenceProcessor {
+    /// Create a processor enforcing only the stop sequences of requests
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            stops: Vec::new(),
+            request_stops: true,
+        }
+    }
+
+    /// Also stop at `stop` in every response
+    #[must_use]
+    pub fn with_stop(mut self, stop: impl Into<String>) -> Self {
+        self.stops.push(stop.into());
+        self
+    }
+
+    /// Set whether the stop sequences of requests are enforced
+    #[must_use]
+    pub const fn with_request_stops(mut self, request_stops: bool) -> Self {
+        self.request_stops = request_stops;
+        self
+    }
+}
+
+impl StreamProcessor for StopSequenceProcessor {
+    fn process_stream(
+        &self,
+        request: &Value,
+        _context: &RequestContext,
+        stream: ResponseStream,
+    ) -> ResponseStream {
+        let mut stops = self.stops.clone();
+        if self.request_stops {
+            match &request["stop"] {
+                Value::String(stop) => stops.push(stop.clone()),
+                Value::Array(request_stops) => stops.extend(
+                    request_stops
+                        .iter()
+                        .filter_map(Value::as_str)
+                        .map(str::to_string),
+                ),
+                _ => {}
+            }
+        }
+        stops.retain(|stop| !stop.is_empty());
+        if stops.is_empty() {
+            return stream;
+        }
+        filter_stream(
+            stream,
+            StopSequenceFilter {
+                stops,
+                choices: request["n"].as_u64().unwrap_or(1),
+                held_back: HashMap::new(),
+                stopped: HashSet::new(),
+            },
+        )
+    }
+}
+
+/// State of a [`StopSequenceProcessor`] for one response
+struct StopSequenceFilter {
+    stops: Vec<String>,
+    choices: u64,
+    held_back: HashMap<u64, String>,
+    stopped: HashSet<u64>,
+}
+
+impl StopSequenceFilter {
+    /// Where the first stop sequence in `text` starts
+    fn find_stop(&self, text: &str) -> Option<usize> {
+        self.stops.iter().filter_map(|stop| text.find(stop)).min()
+    }
+
+    /// Where the text that could be the start of a stop sequence starts
+    fn partial_stop(&self, text: &str) -> usize {
+        text.char_indices()
+            .map(|(start, _)| start)
+            .find(|&start| {
+                self.stops
+                    .iter()
+                    .any(|stop| stop.starts_with(&text[start..]))
+            })
+            .unwrap_or(text.len())
+    }
+}
+
+impl ChunkFilter for StopSequenceFilter {
+    fn filter(&mut self, chunk: &mut Value) -> ChunkVerdict {
+        let Some(choices) = chunk["choices"].as_array_mut() else {
+            return ChunkVerdict::Forward;
+        };
+        // Choices already stopped ignore the rest of their output
+        choices.retain(|choice| {
+            !self
+                .stopped
+                .contains(&choice["index"].as_u64().unwrap_or_default())
+        });
+        for choice in choices {
+            let index = choice["index"].as_u64().unwrap_or_default();
+            let held_back = self.held_back.remove(&index).unwrap_or_default();
+            let text = held_back + choice["delta"]["content"].as_str().unwrap_or_default();
+            if let Some(start) = self.find_stop(&text) {
+                debug!(index, "Stopping output at stop sequence");
+                choice["delta"]["content"] = Value::String(text[..start].to_string());
+                choice["finish_reason"] = Value::String("stop".to_string());
+                self.stopped.insert(index);
+                continue;
+            }
+            // The output is complete when the backend finishes the choice
+            let end = if choice["finish_reason"].is_string() {
+                text.len()
+            } else {
+                self.partial_stop(&text)
+            };
+            if !text.is_empty() {
+                choice["delta"]["content"] = Value::String(text[..end].to_string());
+            }
+            if end < text.len() {
+                self.held_back.insert(index, text[end..].to_string());
+            }
+        }
+        if self.stopped.len() as u64 >= self.choices {
+            return ChunkVerdict::Stop(vec![chunk.clone()]);
+        }
+        ChunkVerdict::Forward
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use serde_json::json;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stops_at_split_sequence() {
+        let (tx, rx) = mpsc::channel(10);
+        for content in ["Hello", " wor", "ld\nUs", "er: hi", " there"] {
+            let chunk = json!({
+                "id": "chatcmpl-1",
+                "created": 1,
+                "choices": [{"index": 0, "delta": {"content": content}}],
+            });
+            tx.send(Ok(Bytes::from(format!("data: {chunk}\n\n"))))
+                .await
+                .expect("Failed to send chunk");
+        }
+        drop(tx);
+
+        let request = json!({"model": "gpt-4o", "stop": ["\nUser:"]});
+        let mut stream =
+            StopSequenceProcessor::new().process_stream(&request, &RequestContext::new(), rx);
+        let mut output = String::new();
+        let mut finish_reason = None;
+        while let Some(event) = stream.recv().await {
+            let event = event.expect("Unexpected error");
+            let Some(data) = std::str::from_utf8(&event)
+                .expect("Invalid UTF-8")
+                .strip_prefix("data: ")
+                .map(str::trim)
+            else {
+                continue;
+            };
+            if data == "[DONE]" {
+                break;
+            }
+            let chunk: Value = serde_json::from_str(data).expect("Invalid chunk");
+            output.push_str(
+                chunk["choices"][0]["delta"]["content"]
+                    .as_str()
+                    .unwrap_or_default(),
+            );
+            if let Some(reason) = chunk["choices"][0]["finish_reason"].as_str() {
+                finish_reason = Some(reason.to_string());
+            }
+        }
+        assert_eq!(output, "Hello world");
+        assert_eq!(finish_reason.as_deref(), Some("stop"));
+    }
+}
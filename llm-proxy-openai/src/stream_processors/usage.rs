@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use llm_proxy_core::{ModelPrice, Pricing, RequestContext, ResponseStream, StreamProcessor};
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use super::event_end;
+
+/// Stream processor that records the token usage and cost of responses as
+/// metrics, for spend dashboards.
+///
+/// The `usage` of non-streaming responses and of the last chunk of streamed
+/// ones is counted in `llm_proxy_prompt_tokens_total` and
+/// `llm_proxy_completion_tokens_total`, labeled by `route`, `model` and
+/// `client` (the tenant, or `anonymous`). When the model has a price in the
+/// processor's [`Pricing`], by default the built-in one, the cost is counted
+/// in millionths of a dollar in `llm_proxy_cost_microdollars_total`. Streamed responses only
+/// carry usage when requested with `stream_options.include_usage`. The
+/// response is forwarded unchanged.
+///
+/// # Example
+///
+/// ```rust
+/// use llm_proxy_core::ModelPrice;
+/// use llm_proxy_openai::stream_processors::UsageMetricsProcessor;
+///
+/// let processor = UsageMetricsProcessor::new()
+///     .with_price("ft:gpt-4o-mini", ModelPrice::new(0.3, 1.2));
+/// ```
+#[derive(Debug, Clone)]
+pub struct UsageMetricsProcessor {
+    pricing: Arc<Pricing>,
+}
+
+impl Default for UsageMetricsProcessor {
+    fn default() -> Self {
+        Self {
+            pricing: Arc::new(Pricing::builtin()),
+        }
+    }
+}
+
+impl UsageMetricsProcessor {
+    /// Create a processor with the built-in prices
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the prices of models, e.g. with [`Pricing::new`] to only count
+    /// tokens
+    #[must_use]
+    pub fn with_pricing(mut self, pricing: Pricing) -> Self {
+        self.pricing = Arc::new(pricing);
+        self
+    }
+
+    /// Price the models whose name starts with `prefix` at `price`
+    #[must_use]
+    pub fn with_price(mut self, prefix: impl Into<String>, price: ModelPrice) -> Self {
+        let pricing = Arc::make_mut(&mut self.pricing);
+        *pricing = std::mem::take(pricing).with_price(prefix, price);
+        self
+    }
+
+    /// The price of `model`, by the longest matching prefix
+    #[must_use]
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        self.pricing.price(model)
+    }
+
+    /// Record the `usage` of `response`, a completion or chunk
+    fn record(&self, response: &Value, labels: &UsageLabels) {
+        let usage = &response["usage"];
+        let (Some(prompt_tokens), Some(completion_tokens)) = (
+            usage["prompt_tokens"].as_u64(),
+            usage["completion_tokens"].as_u64(),
+        ) else {
+            return;
+        };
+        let model = response["model"]
+            .as_str()
+            .or(labels.model.as_deref())
+            .unwrap_or("unknown")
+            .to_string();
+        let labels = [
+            ("route", labels.route.clone()),
+            ("model", model.clone()),
+            ("client", labels.client.clone()),
+        ];
+        metrics::counter!("llm_proxy_prompt_tokens_total", &labels).increment(prompt_tokens);
+        metrics::counter!("llm_proxy_completion_tokens_total", &labels)
+            .increment(completion_tokens);
+        let cost = self.pricing.cost(&model, prompt_tokens, completion_tokens);
+        if let Some(cost) = cost {
+            // Whole microdollars, since counters can't count fractions
+            let microdollars = (cost * 1_000_000.0).round();
+            if microdollars >= 1.0 {
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                metrics::counter!("llm_proxy_cost_microdollars_total", &labels)
+                    .increment(microdollars as u64);
+            }
+        }
+        debug!(
+            model = %model,
+            prompt_tokens,
+            completion_tokens,
+            cost = ?cost,
+            "Recorded token usage"
+        );
+    }
+}
+
+/// Labels of the usage metrics of a response
+struct UsageLabels {
+    route: String,
+    model: Option<String>,
+    client: String,
+}
+
+impl StreamProcessor for UsageMetricsProcessor {
+    fn process_stream(
+        &self,
+        _request: &Value,
+        context: &RequestContext,
+        mut stream: ResponseStream,
+    ) -> ResponseStream {
+        let processor = self.clone();
+        let labels = UsageLabels {
+            route: context.route.clone().unwrap_or_default(),
+            model: context.model.clone(),
+            client: context
+                .tenant
+                .clone()
+                .unwrap_or_else(|| "anonymous".to_string()),
+        };
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            let mut buffer = Vec::new();
+            let mut streaming = None;
+            while let Some(item) = stream.recv().await {
+                if let Ok(chunk) = &item {
+                    let is_streaming = *streaming.get_or_insert_with(|| {
+                        !String::from_utf8_lossy(chunk).trim_start().starts_with('{')
+                    });
+                    buffer.extend_from_slice(chunk);
+                    // Usage comes with the last chunk, so only the events
+                    // mentioning it are parsed
+                    while let Some(end) = event_end(&buffer).filter(|_| is_streaming) {
+                        let event: Vec<u8> = buffer.drain(..end).collect();
+                        let event = String::from_utf8_lossy(&event);
+                        let usage = event
+                            .lines()
+                            .filter_map(|line| line.strip_prefix("data:"))
+                            .filter(|data| data.contains("\"usage\""))
+                            .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok());
+                        for chunk in usage {
+                            processor.record(&chunk, &labels);
+                        }
+                    }
+                }
+                if tx.send(item).await.is_err() {
+                    return;
+                }
+            }
+            if streaming == Some(false) {
+                if let Ok(completion) = serde_json::from_slice::<Value>(&buffer) {
+                    processor.record(&completion, &labels);
+                }
+            }
+        });
+        rx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_prices_and_forwards_usage() {
+        let processor = UsageMetricsProcessor::new()
+            .with_pricing(Pricing::new())
+            .with_price("gpt-4o", ModelPrice::new(2.5, 10.0))
+            .with_price("gpt-4o-mini", ModelPrice::new(0.15, 0.6));
+        assert_eq!(
+            processor.price("gpt-4o-mini-2024-07-18"),
+            Some(ModelPrice::new(0.15, 0.6))
+        );
+        assert_eq!(processor.price("o1"), None);
+        let cost = processor
+            .price("gpt-4o-2024-08-06")
+            .map(|price| price.cost(1_000, 500));
+        assert_eq!(cost, Some(0.0075));
+
+        let (tx, rx) = mpsc::channel(10);
+        let chunks = [
+            json!({"id": "chatcmpl-1", "model": "gpt-4o", "choices": [{"index": 0, "delta": {"content": "Hi"}}]}),
+            json!({"id": "chatcmpl-1", "model": "gpt-4o", "choices": [], "usage": {"prompt_tokens": 10, "completion_tokens": 1}}),
+        ];
+        for chunk in &chunks {
+            tx.send(Ok(Bytes::from(format!("data: {chunk}\n\n"))))
+                .await
+                .expect("Failed to send chunk");
+        }
+        drop(tx);
+
+        let context = RequestContext::new().with_route("/v1/chat/completions");
+        let mut stream = processor.process_stream(&Value::Null, &context, rx);
+        let mut forwarded = Vec::new();
+        while let Some(event) = stream.recv().await {
+            forwarded.push(event.expect("Unexpected error"));
+        }
+        assert_eq!(forwarded.len(), 2);
+        assert_eq!(forwarded[1], format!("data: {}\n\n", chunks[1]));
+    }
+}
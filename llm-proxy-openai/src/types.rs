@@ -1,5 +1,5 @@
 use llm_proxy_core::{LLMRequest, Result};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::HashMap;
 
 /// A request to the `OpenAI` chat completions API
@@ -25,18 +25,36 @@ pub struct ChatCompletionRequest {
     /// Additional model parameters
     #[serde(flatten)]
     pub additional_params: HashMap<String, serde_json::Value>,
+    /// Per-request overrides of upstream settings, set by processors
+    #[serde(skip)]
+    pub overrides: UpstreamOverrides,
+}
+
+/// Upstream settings that processors can override for a single request
+///
+/// Values set here take precedence over the defaults configured on the
+/// backend's [`OpenAIClient`](crate::OpenAIClient).
+#[derive(Debug, Clone, Default)]
+pub struct UpstreamOverrides {
+    /// Value for the `OpenAI-Organization` header
+    pub organization: Option<String>,
+    /// Value for the `OpenAI-Project` header
+    pub project: Option<String>,
 }
 
 impl ChatCompletionRequest {
     /// Create a new `ChatCompletionRequest` with the given model and messages
+    #[must_use]
     pub fn new_stream(model: String, messages: Vec<Message>) -> Self {
         Self::new(model, messages, true)
     }
 
+    #[must_use]
     pub fn new_block(model: String, messages: Vec<Message>) -> Self {
         Self::new(model, messages, false)
     }
 
+    #[must_use]
     pub fn new(model: String, messages: Vec<Message>, stream: bool) -> Self {
         Self {
             model,
@@ -46,8 +64,17 @@ impl ChatCompletionRequest {
             temperature: None,
             functions: None,
             additional_params: HashMap::new(),
+            overrides: UpstreamOverrides::default(),
         }
     }
+
+    /// Read an additional parameter (e.g. `top_p` or `tools`), if present and valid
+    #[must_use]
+    pub fn param<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        self.additional_params
+            .get(name)
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+    }
 }
 
 /// A message in a chat completion request/response
@@ -56,15 +83,111 @@ impl ChatCompletionRequest {
 pub struct Message {
     /// The role of the message sender (system, user, assistant, or function)
     pub role: String,
-    /// The content of the message
+    /// The content of the message, either plain text or a list of content parts
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub content: Option<String>,
+    pub content: Option<MessageContent>,
     /// Name of the function that was called
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
     /// Function call in the message
     #[serde(skip_serializing_if = "Option::is_none")]
     pub function_call: Option<FunctionCall>,
+    /// Tool calls made by the assistant
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// ID of the tool call a `tool` message responds to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+/// The content of a chat message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    /// Plain text content
+    Text(String),
+    /// Multimodal content made of text and image parts
+    Parts(Vec<ContentPart>),
+}
+
+impl MessageContent {
+    /// Concatenate all text in the content, ignoring non-text parts
+    #[must_use]
+    pub fn text(&self) -> String {
+        match self {
+            Self::Text(text) => text.clone(),
+            Self::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    /// Iterate over the image parts of the content
+    pub fn images_mut(&mut self) -> impl Iterator<Item = &mut ImageUrl> {
+        let parts: &mut [ContentPart] = match self {
+            Self::Text(_) => &mut [],
+            Self::Parts(parts) => parts,
+        };
+        parts.iter_mut().filter_map(|part| match part {
+            ContentPart::ImageUrl { image_url } => Some(image_url),
+            _ => None,
+        })
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+/// A single part of a multimodal message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    /// A text part
+    Text {
+        /// The text content
+        text: String,
+    },
+    /// An image part, referenced by URL or inline data URL
+    ImageUrl {
+        /// The image reference
+        image_url: ImageUrl,
+    },
+    /// Any other part type (e.g. audio or files), passed through untouched
+    #[serde(untagged)]
+    Other(serde_json::Value),
+}
+
+/// An image referenced from a message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageUrl {
+    /// Either a remote URL or a `data:` URL with base64 encoded image data
+    pub url: String,
+    /// The level of detail the model should use when processing the image
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<ImageDetail>,
+}
+
+/// Detail level for image inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageDetail {
+    Auto,
+    Low,
+    High,
 }
 
 /// A function that can be called by the model
@@ -87,6 +210,18 @@ pub struct FunctionCall {
     pub arguments: String,
 }
 
+/// A tool call made by the assistant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// ID of the call, referenced by the `tool` message with its result
+    pub id: String,
+    /// The type of tool (always "function")
+    #[serde(rename = "type")]
+    pub call_type: String,
+    /// The function to call
+    pub function: FunctionCall,
+}
+
 /// A chunk in a streaming response
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StreamChunk {
@@ -0,0 +1,133 @@
+//! The plugin side of the ABI, called by the functions [`export_processors!`]
+//! exports.
+//!
+//! [`export_processors!`]: crate::export_processors
+#![allow(unsafe_code)]
+
+use std::{
+    ffi::{c_char, c_void, CStr, CString},
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+};
+
+use serde_json::Value;
+
+use crate::{
+    PluginConfig, ProcessError, ProcessorFactory, RequestProcessor, PROCESS_FAILED, PROCESS_OK,
+    PROCESS_REJECTED,
+};
+
+/// The JSON array of the types of `factories`
+#[must_use]
+pub fn processor_types(factories: &[(&str, ProcessorFactory)]) -> CString {
+    let types: Vec<_> = factories
+        .iter()
+        .map(|(processor_type, _)| *processor_type)
+        .collect();
+    into_c_string(Value::from(types).to_string())
+}
+
+/// Create a processor with the factory of `processor_type`
+///
+/// # Safety
+///
+/// `processor_type` and `config` must be NUL-terminated strings, and `error`
+/// null or valid for writes.
+pub unsafe fn create(
+    factories: &[(&str, ProcessorFactory)],
+    processor_type: *const c_char,
+    config: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_void {
+    let created = catch_unwind(AssertUnwindSafe(|| {
+        let processor_type = read(processor_type)?;
+        let config: PluginConfig = serde_json::from_str(&read(config)?)
+            .map_err(|e| format!("Invalid configuration: {e}"))?;
+        let (_, factory) = factories
+            .iter()
+            .find(|(name, _)| *name == processor_type)
+            .ok_or_else(|| format!("Unknown processor type {processor_type}"))?;
+        factory(&config)
+    }))
+    .unwrap_or_else(|_| Err("The processor factory panicked".to_string()));
+    match created {
+        Ok(processor) => Box::into_raw(Box::new(processor)).cast(),
+        Err(message) => {
+            if !error.is_null() {
+                *error = into_c_string(message).into_raw();
+            }
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Process `request` with `processor`
+///
+/// # Safety
+///
+/// `processor` must have been returned by [`create`] and not destroyed,
+/// `request` must be a NUL-terminated string, and `output` valid for writes.
+pub unsafe fn process(
+    processor: *mut c_void,
+    request: *const c_char,
+    output: *mut *mut c_char,
+) -> i32 {
+    let processor = &*processor.cast::<Box<dyn RequestProcessor>>();
+    let processed = catch_unwind(AssertUnwindSafe(|| {
+        let request = read(request)
+            .and_then(|request| serde_json::from_str(&request).map_err(|e| e.to_string()))
+            .map_err(ProcessError::Failed)?;
+        processor.process(request)
+    }))
+    .unwrap_or_else(|_| Err(ProcessError::Failed("The processor panicked".to_string())));
+    let (status, message) = match processed {
+        Ok(request) => (PROCESS_OK, request.to_string()),
+        Err(ProcessError::Rejected(reason)) => (PROCESS_REJECTED, reason),
+        Err(ProcessError::Failed(reason)) => (PROCESS_FAILED, reason),
+    };
+    *output = into_c_string(message).into_raw();
+    status
+}
+
+/// Destroy `processor`
+///
+/// # Safety
+///
+/// `processor` must have been returned by [`create`] and not destroyed.
+pub unsafe fn destroy(processor: *mut c_void) {
+    if !processor.is_null() {
+        drop(Box::from_raw(processor.cast::<Box<dyn RequestProcessor>>()));
+    }
+}
+
+/// Free `string`
+///
+/// # Safety
+///
+/// `string` must be null or have been returned through `error` or `output`,
+/// and not freed.
+pub unsafe fn free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// The UTF-8 string at `string`
+unsafe fn read(string: *const c_char) -> Result<String, String> {
+    if string.is_null() {
+        return Err("Missing argument".to_string());
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map(str::to_string)
+        .map_err(|e| format!("Invalid argument: {e}"))
+}
+
+/// `string` as a C string, without the NUL characters it can't hold
+fn into_c_string(string: String) -> CString {
+    CString::new(string).unwrap_or_else(|e| {
+        let mut bytes = e.into_vec();
+        bytes.retain(|&byte| byte != 0);
+        CString::new(bytes).unwrap_or_default()
+    })
+}
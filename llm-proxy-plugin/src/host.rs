@@ -0,0 +1,342 @@
+//! The proxy side of the ABI: loading plugins and calling their processors.
+#![allow(unsafe_code)]
+
+use std::{
+    ffi::{c_char, c_void, CStr, CString},
+    path::{Path, PathBuf},
+    ptr::{self, NonNull},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use libloading::Library;
+use llm_proxy_core::{Error, LLMRequest, Processor, Result};
+
+use crate::{PluginConfig, ABI_VERSION, PROCESS_OK, PROCESS_REJECTED};
+
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+type ProcessorTypesFn = unsafe extern "C" fn() -> *const c_char;
+type CreateFn = unsafe extern "C" fn(*const c_char, *const c_char, *mut *mut c_char) -> *mut c_void;
+type ProcessFn = unsafe extern "C" fn(*mut c_void, *const c_char, *mut *mut c_char) -> i32;
+type DestroyFn = unsafe extern "C" fn(*mut c_void);
+type FreeStringFn = unsafe extern "C" fn(*mut c_char);
+
+/// The functions of a plugin
+#[derive(Clone, Copy)]
+struct Api {
+    processor_types: ProcessorTypesFn,
+    create: CreateFn,
+    process: ProcessFn,
+    destroy: DestroyFn,
+    free_string: FreeStringFn,
+}
+
+/// A loaded plugin
+pub struct Plugin {
+    path: PathBuf,
+    api: Api,
+    processor_types: Vec<String>,
+    /// Keeps the functions of `api` loaded, unless they are linked in
+    _library: Option<Library>,
+}
+
+/// A processor created by a plugin
+pub struct PluginProcessor {
+    plugin: Arc<Plugin>,
+    handle: NonNull<c_void>,
+}
+
+// Plugins must allow calls of their processors from several threads at once
+unsafe impl Send for PluginProcessor {}
+unsafe impl Sync for PluginProcessor {}
+
+/// The shared libraries in `dir`, by path
+///
+/// # Errors
+///
+/// This function will return an error if the directory cannot be read.
+pub fn discover(dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    let entries = std::fs::read_dir(dir).map_err(|e| {
+        Error::ConfigError(format!(
+            "Failed to read plugin directory {}: {e}",
+            dir.display()
+        ))
+    })?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file()
+            && path.extension().and_then(|extension| extension.to_str())
+                == Some(std::env::consts::DLL_EXTENSION)
+        {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+impl Plugin {
+    /// Load the plugin at `path`
+    ///
+    /// Loading runs the library's initialization code, so only plugins from
+    /// trusted sources should be loaded.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the library cannot be loaded,
+    /// lacks a function of the ABI or implements another version of it.
+    pub fn load(path: impl AsRef<Path>) -> Result<Arc<Self>> {
+        let path = path.as_ref();
+        // SAFETY: plugins are trusted to initialize soundly
+        let library = unsafe { Library::new(path) }.map_err(|e| {
+            Error::ConfigError(format!("Failed to load plugin {}: {e}", path.display()))
+        })?;
+        let abi_version: AbiVersionFn = symbol(&library, path, "llm_proxy_plugin_abi_version")?;
+        // SAFETY: the function has the type of the ABI
+        let version = unsafe { abi_version() };
+        if version != ABI_VERSION {
+            return Err(Error::ConfigError(format!(
+                "Plugin {} implements version {version} of the plugin ABI, not {ABI_VERSION}",
+                path.display()
+            )));
+        }
+        let api = Api {
+            processor_types: symbol(&library, path, "llm_proxy_plugin_processor_types")?,
+            create: symbol(&library, path, "llm_proxy_processor_create")?,
+            process: symbol(&library, path, "llm_proxy_processor_process")?,
+            destroy: symbol(&library, path, "llm_proxy_processor_destroy")?,
+            free_string: symbol(&library, path, "llm_proxy_plugin_free_string")?,
+        };
+        Self::new(path, api, Some(library)).map(Arc::new)
+    }
+
+    fn new(path: &Path, api: Api, library: Option<Library>) -> Result<Self> {
+        // SAFETY: the function has the type of the ABI, and returns a string
+        // valid while the plugin is loaded
+        let types = unsafe { (api.processor_types)() };
+        if types.is_null() {
+            return Err(Error::ConfigError(format!(
+                "Plugin {} has no processor types",
+                path.display()
+            )));
+        }
+        // SAFETY: see above
+        let types = unsafe { CStr::from_ptr(types) }.to_string_lossy();
+        let processor_types = serde_json::from_str(&types).map_err(|e| {
+            Error::ConfigError(format!(
+                "Invalid processor types of plugin {}: {e}",
+                path.display()
+            ))
+        })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            api,
+            processor_types,
+            _library: library,
+        })
+    }
+
+    /// Path of the library
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Types of the processors the plugin provides
+    #[must_use]
+    pub fn processor_types(&self) -> &[String] {
+        &self.processor_types
+    }
+
+    /// Create a processor of `processor_type` configured by `config`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the plugin refuses the configuration.
+    pub fn create(
+        self: &Arc<Self>,
+        processor_type: &str,
+        config: &PluginConfig,
+    ) -> Result<PluginProcessor> {
+        let processor_type = c_string(processor_type)?;
+        let config = c_string(&serde_json::to_string(config)?)?;
+        let mut error = ptr::null_mut();
+        // SAFETY: the function has the type of the ABI, and the arguments are
+        // valid for the call
+        let handle =
+            unsafe { (self.api.create)(processor_type.as_ptr(), config.as_ptr(), &raw mut error) };
+        let error = self.take_string(error);
+        NonNull::new(handle)
+            .map(|handle| PluginProcessor {
+                plugin: self.clone(),
+                handle,
+            })
+            .ok_or_else(|| {
+                Error::ConfigError(format!(
+                    "Plugin {} failed to create a processor: {}",
+                    self.path.display(),
+                    error.as_deref().unwrap_or("no reason given")
+                ))
+            })
+    }
+
+    /// Copy `string` returned by the plugin and free it
+    fn take_string(&self, string: *mut c_char) -> Option<String> {
+        if string.is_null() {
+            return None;
+        }
+        // SAFETY: the plugin returned a NUL-terminated string, freed once copied
+        unsafe {
+            let copy = CStr::from_ptr(string).to_string_lossy().into_owned();
+            (self.api.free_string)(string);
+            Some(copy)
+        }
+    }
+}
+
+impl PluginProcessor {
+    /// Process the JSON `request`, returning the processed request
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`Error::Rejected`] if the processor refuses
+    /// the request, or another error if it fails.
+    pub fn process_json(&self, request: &str) -> Result<String> {
+        let request = c_string(request)?;
+        let mut output = ptr::null_mut();
+        // SAFETY: the function has the type of the ABI, and the handle is
+        // alive until dropped
+        let status = unsafe {
+            (self.plugin.api.process)(self.handle.as_ptr(), request.as_ptr(), &raw mut output)
+        };
+        let output = self.plugin.take_string(output).unwrap_or_default();
+        match status {
+            PROCESS_OK => Ok(output),
+            PROCESS_REJECTED => Err(Error::Rejected(output)),
+            _ => Err(Error::ProcessError(format!(
+                "Plugin {} failed: {output}",
+                self.plugin.path.display()
+            ))),
+        }
+    }
+}
+
+impl Drop for PluginProcessor {
+    fn drop(&mut self) {
+        // SAFETY: the handle was created by the plugin, which is still loaded
+        unsafe { (self.plugin.api.destroy)(self.handle.as_ptr()) };
+    }
+}
+
+#[async_trait]
+impl<T: LLMRequest + 'static> Processor<T> for PluginProcessor {
+    async fn process(&self, request: T) -> Result<T> {
+        let processed = self.process_json(&request.to_value()?.to_string())?;
+        Ok(serde_json::from_str(&processed)?)
+    }
+}
+
+/// The function `name` of `library`
+fn symbol<T: Copy>(library: &Library, path: &Path, name: &str) -> Result<T> {
+    // SAFETY: `T` is the type of the function in the ABI, and the library is
+    // kept loaded while the function is used
+    unsafe { library.get::<T>(name.as_bytes()) }
+        .map(|symbol| *symbol)
+        .map_err(|e| {
+            Error::ConfigError(format!(
+                "Plugin {} lacks the function {name}: {e}",
+                path.display()
+            ))
+        })
+}
+
+fn c_string(string: &str) -> Result<CString> {
+    CString::new(string).map_err(|e| Error::InvalidRequest(format!("Invalid string: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{ProcessError, RequestProcessor};
+
+    /// Sets the `user` of requests, refusing those without messages
+    struct Tag(String);
+
+    impl RequestProcessor for Tag {
+        fn process(&self, mut request: Value) -> std::result::Result<Value, ProcessError> {
+            if request["messages"].as_array().is_none_or(Vec::is_empty) {
+                return Err(ProcessError::Rejected("No messages".to_string()));
+            }
+            request["user"] = json!(self.0);
+            Ok(request)
+        }
+    }
+
+    fn create_tag(config: &PluginConfig) -> std::result::Result<Box<dyn RequestProcessor>, String> {
+        if config.config_value.is_empty() {
+            return Err("A tag is required".to_string());
+        }
+        Ok(Box::new(Tag(config.config_value.clone())))
+    }
+
+    crate::export_processors!("tag" => create_tag);
+
+    /// The plugin exported by this test binary
+    fn linked() -> Arc<Plugin> {
+        let api = Api {
+            processor_types: llm_proxy_plugin_processor_types,
+            create: llm_proxy_processor_create,
+            process: llm_proxy_processor_process,
+            destroy: llm_proxy_processor_destroy,
+            free_string: llm_proxy_plugin_free_string,
+        };
+        Arc::new(Plugin::new(Path::new("linked"), api, None).expect("Invalid plugin"))
+    }
+
+    #[test]
+    fn test_process() {
+        let plugin = linked();
+        assert_eq!(plugin.processor_types(), ["tag"]);
+
+        let processor = plugin
+            .create(
+                "tag",
+                &PluginConfig {
+                    config_value: "team-a".to_string(),
+                    additional_config: Value::Null,
+                },
+            )
+            .expect("Failed to create processor");
+        let processed = processor
+            .process_json(r#"{"model":"m","messages":[{"role":"user","content":"Hi"}]}"#)
+            .expect("Failed to process");
+        let processed: Value = serde_json::from_str(&processed).expect("Invalid output");
+        assert_eq!(processed["user"], "team-a");
+
+        let rejected = processor.process_json(r#"{"model":"m","messages":[]}"#);
+        assert!(matches!(rejected, Err(Error::Rejected(reason)) if reason == "No messages"));
+    }
+
+    #[test]
+    fn test_create_errors() {
+        let plugin = linked();
+        let error = plugin
+            .create("tag", &PluginConfig::default())
+            .err()
+            .expect("Created without a tag");
+        assert!(error.to_string().ends_with("A tag is required"));
+        let error = plugin
+            .create("unknown", &PluginConfig::default())
+            .err()
+            .expect("Created an unknown type");
+        assert!(error
+            .to_string()
+            .ends_with("Unknown processor type unknown"));
+
+        assert!(Plugin::load("/nonexistent/plugin.so").is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! Request processors loaded from shared libraries at runtime.
+//!
+//! A plugin is a shared library exporting the functions of a small C ABI,
+//! through which the proxy creates processors of the types the plugin
+//! provides and passes them requests as JSON. Proprietary processors can so
+//! ship separately from the proxy binary, written in any language that can
+//! export C functions.
+//!
+//! # ABI
+//!
+//! Version [`ABI_VERSION`] of the ABI consists of these functions:
+//!
+//! ```c
+//! // The version of the ABI the plugin implements
+//! uint32_t llm_proxy_plugin_abi_version(void);
+//! // JSON array of the processor types the plugin provides
+//! const char *llm_proxy_plugin_processor_types(void);
+//! // A processor of `type`, configured by a JSON object with the
+//! // `config_value` and `additional_config` of its section, or NULL with a
+//! // message in `*error`
+//! void *llm_proxy_processor_create(const char *type, const char *config, char **error);
+//! // Process the JSON `request`, returning a status and setting `*output` to
+//! // the processed request, or to the reason it was rejected or failed
+//! int32_t llm_proxy_processor_process(void *processor, const char *request, char **output);
+//! void llm_proxy_processor_destroy(void *processor);
+//! // Free a string the plugin returned through `error` or `output`
+//! void llm_proxy_plugin_free_string(char *string);
+//! ```
+//!
+//! Strings are NUL-terminated UTF-8. The processor types stay valid while the
+//! plugin is loaded, and processors must allow calls from several threads at
+//! once. A status of [`PROCESS_OK`] continues the pipeline with the output,
+//! [`PROCESS_REJECTED`] answers the client with a 400 error, and any other
+//! status fails the request.
+//!
+//! Rust plugins implement [`RequestProcessor`] and export factories of their
+//! processors with [`export_processors!`] from a `cdylib` crate.
+
+#[doc(hidden)]
+pub mod export;
+mod host;
+
+pub use host::{discover, Plugin, PluginProcessor};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Version of the ABI implemented by the loader
+pub const ABI_VERSION: u32 = 1;
+
+/// Status of a processed request
+pub const PROCESS_OK: i32 = 0;
+
+/// Status of a request the processor refused
+pub const PROCESS_REJECTED: i32 = 1;
+
+/// Status of a request the processor failed to process
+pub const PROCESS_FAILED: i32 = 2;
+
+/// Configuration of a plugin processor, from its `[processor.*]` section
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginConfig {
+    /// Primary configuration value
+    #[serde(default)]
+    pub config_value: String,
+    /// Additional processor-specific configuration
+    #[serde(default)]
+    pub additional_config: Value,
+}
+
+/// Why a [`RequestProcessor`] didn't process a request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessError {
+    /// The processor refused the request, e.g. because of its content
+    Rejected(String),
+    /// The processor failed
+    Failed(String),
+}
+
+/// A request processor of a Rust plugin
+pub trait RequestProcessor: Send + Sync {
+    /// Process the JSON `request`, returning the request to continue with
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the request is refused or
+    /// cannot be processed.
+    fn process(&self, request: Value) -> Result<Value, ProcessError>;
+}
+
+/// Builds the processors of a type from their configuration
+pub type ProcessorFactory = fn(&PluginConfig) -> Result<Box<dyn RequestProcessor>, String>;
+
+/// Export the functions of the ABI from a `cdylib` crate, creating the
+/// processors of each type with its [`ProcessorFactory`].
+///
+/// ```ignore
+/// fn create_tag(config: &PluginConfig) -> Result<Box<dyn RequestProcessor>, String> {
+///     Ok(Box::new(Tag(config.config_value.clone())))
+/// }
+///
+/// llm_proxy_plugin::export_processors!("tag" => create_tag);
+/// ```
+#[macro_export]
+macro_rules! export_processors {
+    ($($processor_type:literal => $factory:expr),+ $(,)?) => {
+        const LLM_PROXY_PLUGIN_FACTORIES: &[(&str, $crate::ProcessorFactory)] =
+            &[$(($processor_type, $factory)),+];
+
+        #[no_mangle]
+        pub extern "C" fn llm_proxy_plugin_abi_version() -> u32 {
+            $crate::ABI_VERSION
+        }
+
+        #[no_mangle]
+        pub extern "C" fn llm_proxy_plugin_processor_types() -> *const ::std::ffi::c_char {
+            static TYPES: ::std::sync::OnceLock<::std::ffi::CString> =
+                ::std::sync::OnceLock::new();
+            TYPES
+                .get_or_init(|| $crate::export::processor_types(LLM_PROXY_PLUGIN_FACTORIES))
+                .as_ptr()
+        }
+
+        /// # Safety
+        ///
+        /// The arguments must follow the ABI.
+        #[no_mangle]
+        pub unsafe extern "C" fn llm_proxy_processor_create(
+            processor_type: *const ::std::ffi::c_char,
+            config: *const ::std::ffi::c_char,
+            error: *mut *mut ::std::ffi::c_char,
+        ) -> *mut ::std::ffi::c_void {
+            $crate::export::create(LLM_PROXY_PLUGIN_FACTORIES, processor_type, config, error)
+        }
+
+        /// # Safety
+        ///
+        /// The arguments must follow the ABI.
+        #[no_mangle]
+        pub unsafe extern "C" fn llm_proxy_processor_process(
+            processor: *mut ::std::ffi::c_void,
+            request: *const ::std::ffi::c_char,
+            output: *mut *mut ::std::ffi::c_char,
+        ) -> i32 {
+            $crate::export::process(processor, request, output)
+        }
+
+        /// # Safety
+        ///
+        /// `processor` must have been created by the plugin.
+        #[no_mangle]
+        pub unsafe extern "C" fn llm_proxy_processor_destroy(processor: *mut ::std::ffi::c_void) {
+            $crate::export::destroy(processor);
+        }
+
+        /// # Safety
+        ///
+        /// `string` must have been returned by the plugin.
+        #[no_mangle]
+        pub unsafe extern "C" fn llm_proxy_plugin_free_string(string: *mut ::std::ffi::c_char) {
+            $crate::export::free_string(string);
+        }
+    };
+}
@@ -0,0 +1,400 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use llm_proxy_core::{
+    upstream, AuthScheme, ClientProvider, Error, LLMClient, ProviderCapabilities, RequestContext,
+    Result, TokenProvider, UrlProvider,
+};
+use llm_proxy_openai::ChatCompletionRequest;
+use serde_json::{Map, Value};
+use tokio::{sync::mpsc, time::Instant};
+use tracing::{info, warn};
+
+use crate::{
+    translate::{split_model, to_openai_response, to_prediction_request, StreamTranslator},
+    types::{ErrorResponse, Prediction, PredictionRequest},
+};
+
+/// Name of the upstream service in errors
+const UPSTREAM: &str = "Replicate";
+
+/// Default time between two polls of a prediction
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Default time after which a prediction that hasn't finished is canceled
+const DEFAULT_TIMEOUT: Duration = Duration::from_mins(10);
+
+/// Replicate implementation of `LLMClient` for `OpenAI` chat completion requests.
+///
+/// Each request creates a prediction of the model named by the request's
+/// `model`, e.g. `meta/meta-llama-3-70b-instruct`. Streaming requests follow
+/// the prediction's stream URL, or poll the prediction when the model doesn't
+/// stream, and translate its output into `OpenAI` chunks; other requests wait
+/// for the prediction to finish. Predictions whose receiver goes away are
+/// canceled.
+#[derive(Clone)]
+pub struct ReplicateClient {
+    client: Arc<dyn ClientProvider>,
+    token: Arc<dyn TokenProvider>,
+    url: Arc<dyn UrlProvider>,
+    auth_scheme: AuthScheme,
+    headers: Vec<(String, String)>,
+    input: Map<String, Value>,
+    poll_interval: Duration,
+    timeout: Duration,
+    capabilities: ProviderCapabilities,
+}
+
+/// Everything needed to follow a prediction after it was created
+struct Follower {
+    client: reqwest::Client,
+    token: String,
+    auth_scheme: AuthScheme,
+    headers: Vec<(String, String)>,
+    poll_interval: Duration,
+    deadline: Instant,
+}
+
+impl Follower {
+    fn request(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let mut builder = self.auth_scheme.apply(builder, &self.token);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        builder
+    }
+
+    /// Get the current state of a prediction
+    async fn poll(&self, prediction: &Prediction) -> Result<Prediction> {
+        let url = prediction
+            .urls
+            .as_ref()
+            .map(|urls| urls.get.clone())
+            .ok_or_else(|| {
+                Error::LLMError(format!("Replicate prediction {} has no URL", prediction.id))
+            })?;
+        if Instant::now() >= self.deadline {
+            self.cancel(prediction).await;
+            return Err(Error::LLMError(format!(
+                "Replicate prediction {} timed out",
+                prediction.id
+            )));
+        }
+        tokio::time::sleep(self.poll_interval).await;
+
+        let response = self
+            .request(self.client.get(url))
+            .send()
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to poll Replicate prediction: {e}")))?;
+        read_prediction(response).await
+    }
+
+    /// Cancel a prediction whose output is no longer needed
+    async fn cancel(&self, prediction: &Prediction) {
+        let Some(url) = prediction
+            .urls
+            .as_ref()
+            .and_then(|urls| urls.cancel.clone())
+        else {
+            return;
+        };
+        if let Err(e) = self.request(self.client.post(url)).send().await {
+            warn!(error = %e, "Failed to cancel Replicate prediction {}", prediction.id);
+        }
+    }
+}
+
+/// Read a prediction from a response, or the error it reports
+async fn read_prediction(response: reqwest::Response) -> Result<Prediction> {
+    upstream::error_for_status::<ErrorResponse>(UPSTREAM, response)
+        .await?
+        .json::<Prediction>()
+        .await
+        .map_err(|e| Error::LLMError(format!("Failed to read Replicate prediction: {e}")))
+}
+
+impl ReplicateClient {
+    /// Create a new Replicate client with the given providers
+    pub fn new(
+        client_provider: Arc<dyn ClientProvider>,
+        token_provider: Arc<dyn TokenProvider>,
+        url_provider: Arc<dyn UrlProvider>,
+    ) -> Self {
+        Self {
+            client: client_provider,
+            token: token_provider,
+            url: url_provider,
+            auth_scheme: AuthScheme::default(),
+            headers: Vec::new(),
+            input: Map::new(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+            capabilities: ProviderCapabilities::default(),
+        }
+    }
+
+    /// Replace the provider of the HTTP client used for upstream requests
+    #[must_use]
+    pub fn with_client_provider(mut self, client_provider: Arc<dyn ClientProvider>) -> Self {
+        self.client = client_provider;
+        self
+    }
+
+    /// Set how the API token is sent to the upstream service
+    #[must_use]
+    pub fn with_auth_scheme(mut self, auth_scheme: AuthScheme) -> Self {
+        self.auth_scheme = auth_scheme;
+        self
+    }
+
+    /// Send a static header with every request
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set model inputs added to requests that don't set them, e.g.
+    /// `prompt_template`
+    #[must_use]
+    pub fn with_input(mut self, input: Map<String, Value>) -> Self {
+        self.input = input;
+        self
+    }
+
+    /// Set the time between two polls of a prediction that doesn't stream
+    #[must_use]
+    pub const fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Set the time after which a prediction that hasn't finished is canceled
+    #[must_use]
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Declare what the upstream backend supports, e.g. its models
+    #[must_use]
+    pub fn with_capabilities(mut self, capabilities: ProviderCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Create a prediction
+    ///
+    /// Non-streaming requests ask Replicate to answer once the prediction has
+    /// finished, which saves polling short predictions.
+    async fn create_prediction(
+        &self,
+        request: &PredictionRequest,
+        follower: &Follower,
+        url: String,
+    ) -> Result<Prediction> {
+        let mut builder = follower.request(follower.client.post(url));
+        if !request.stream {
+            builder = builder.header("Prefer", "wait");
+        }
+        let response =
+            builder.json(request).send().await.map_err(|e| {
+                Error::LLMError(format!("Failed to send request to Replicate: {e}"))
+            })?;
+        if !response.status().is_success() {
+            self.token
+                .report_rejection(&follower.token, response.status().as_u16())
+                .await;
+        }
+        read_prediction(response).await
+    }
+
+    /// Translate the server-sent events of a prediction's stream URL into
+    /// `OpenAI` chunks
+    async fn handle_stream(
+        follower: Follower,
+        prediction: Prediction,
+        stream_url: String,
+        translator: StreamTranslator,
+        tx: mpsc::Sender<Result<Bytes>>,
+    ) {
+        let response = follower
+            .request(follower.client.get(stream_url))
+            .header("Accept", "text/event-stream")
+            .header("Cache-Control", "no-store")
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+        match response {
+            Ok(response) => upstream::forward_stream(UPSTREAM, response, translator, &tx).await,
+            Err(e) => {
+                let e = Error::LLMError(format!("Failed to open Replicate stream: {e}"));
+                upstream::send_response(UPSTREAM, &tx, Err(e)).await;
+            }
+        }
+        if tx.is_closed() {
+            follower.cancel(&prediction).await;
+        }
+    }
+
+    /// Poll a prediction that doesn't stream, sending its output as `OpenAI`
+    /// chunks as it grows
+    async fn handle_poll_stream(
+        follower: Follower,
+        mut prediction: Prediction,
+        mut translator: StreamTranslator,
+        tx: mpsc::Sender<Result<Bytes>>,
+    ) {
+        loop {
+            let events = match translator.push_prediction(&prediction) {
+                Ok(events) => events,
+                Err(e) => {
+                    upstream::send_response(UPSTREAM, &tx, Err(e)).await;
+                    return;
+                }
+            };
+            for event in events {
+                if tx.send(Ok(event)).await.is_err() {
+                    warn!("Failed to send chunk - receiver dropped");
+                    follower.cancel(&prediction).await;
+                    return;
+                }
+            }
+            if prediction.status.is_terminal() {
+                return;
+            }
+            if tx.is_closed() {
+                follower.cancel(&prediction).await;
+                return;
+            }
+
+            prediction = match follower.poll(&prediction).await {
+                Ok(prediction) => prediction,
+                Err(e) => {
+                    upstream::send_response(UPSTREAM, &tx, Err(e)).await;
+                    return;
+                }
+            };
+        }
+    }
+
+    /// Wait for a prediction to finish and send it as an `OpenAI` chat completion
+    async fn handle_non_stream(
+        follower: Follower,
+        mut prediction: Prediction,
+        model: String,
+        tx: mpsc::Sender<Result<Bytes>>,
+    ) {
+        let mut result = Ok(());
+        while !prediction.status.is_terminal() {
+            if tx.is_closed() {
+                follower.cancel(&prediction).await;
+                return;
+            }
+            match follower.poll(&prediction).await {
+                Ok(next) => prediction = next,
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        let result = result
+            .and_then(|()| to_openai_response(&prediction, &model))
+            .map(|response| Bytes::from(response.to_string()));
+        upstream::send_response(UPSTREAM, &tx, result).await;
+    }
+}
+
+#[async_trait]
+impl LLMClient<ChatCompletionRequest> for ReplicateClient {
+    async fn execute(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        self.execute_with_context(request, &RequestContext::new())
+            .await
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.capabilities.clone()
+    }
+
+    async fn execute_with_context(
+        &self,
+        request: ChatCompletionRequest,
+        context: &RequestContext,
+    ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        // 1. Translate the request
+        let prediction_request = to_prediction_request(&request, &self.input)?;
+
+        // 2. Get dependencies
+        let client = self
+            .client
+            .get_client()
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get HTTP client: {e}")))?;
+        let token = self
+            .token
+            .get_token_for(context)
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get API token: {e}")))?;
+        let base_url = self.url.get_url()?;
+        let base_url = base_url.trim_end_matches('/');
+        let url = match split_model(&request.model) {
+            (_, Some(_)) => format!("{base_url}/predictions"),
+            (model, None) => format!("{base_url}/models/{model}/predictions"),
+        };
+        let follower = Follower {
+            client,
+            token,
+            auth_scheme: self.auth_scheme.clone(),
+            headers: self.headers.clone(),
+            poll_interval: self.poll_interval,
+            deadline: Instant::now() + self.timeout,
+        };
+
+        // 3. Create the prediction
+        let prediction = self
+            .create_prediction(&prediction_request, &follower, url)
+            .await?;
+        info!(
+            "Created Replicate prediction {} ({:?})",
+            prediction.id, prediction.status
+        );
+
+        // 4. Follow the prediction based on streaming flag
+        let (tx, rx) = mpsc::channel(100);
+        info!("The request is streaming: {}", request.stream);
+        if request.stream {
+            let translator = StreamTranslator::new(&prediction.id, request.model);
+            let stream_url = prediction
+                .urls
+                .as_ref()
+                .and_then(|urls| urls.stream.clone());
+            match stream_url {
+                Some(stream_url) if !prediction.status.is_terminal() => {
+                    tokio::spawn(Self::handle_stream(
+                        follower, prediction, stream_url, translator, tx,
+                    ));
+                }
+                _ => {
+                    tokio::spawn(Self::handle_poll_stream(
+                        follower, prediction, translator, tx,
+                    ));
+                }
+            }
+        } else {
+            tokio::spawn(Self::handle_non_stream(
+                follower,
+                prediction,
+                request.model,
+                tx,
+            ));
+        }
+
+        Ok(rx)
+    }
+}
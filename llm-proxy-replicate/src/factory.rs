@@ -0,0 +1,72 @@
+//! Provider factory for models on Replicate, registered as `replicate`.
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use llm_proxy_core::{Pipeline, ProviderContext, ProviderFactory, ProviderRegistry, Result};
+use llm_proxy_openai::ChatCompletionRequest;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    create_chat_client_with_url_provider, create_pipeline_with_client, ReplicateUrlProvider,
+};
+
+/// Register the factory of this crate in `registry`
+pub fn register(registry: &mut ProviderRegistry<ChatCompletionRequest>) {
+    registry.register("replicate", ReplicateFactory);
+}
+
+/// Settings of a Replicate backend
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ReplicateSettings {
+    /// Model inputs added to requests that don't set them, e.g. `prompt_template`
+    #[serde(default)]
+    pub input: Map<String, Value>,
+    /// Milliseconds between two polls of a prediction that doesn't stream
+    #[serde(default)]
+    pub poll_interval_ms: Option<u64>,
+    /// Seconds after which a prediction that hasn't finished is canceled
+    #[serde(default)]
+    pub prediction_timeout_secs: Option<u64>,
+}
+
+/// Factory for models on Replicate, with [`ReplicateSettings`] as settings
+///
+/// Requests are authenticated with the Replicate API token of the context.
+pub struct ReplicateFactory;
+
+#[async_trait]
+impl ProviderFactory<ChatCompletionRequest> for ReplicateFactory {
+    async fn create_pipeline(
+        &self,
+        context: ProviderContext,
+    ) -> Result<Pipeline<ChatCompletionRequest>> {
+        let settings: ReplicateSettings = context.settings()?;
+        let token_provider = context.require_token_provider()?;
+
+        let url_provider = context
+            .url_provider
+            .clone()
+            .unwrap_or_else(|| Arc::new(ReplicateUrlProvider::new(&context.base_url)));
+        let mut client = create_chat_client_with_url_provider(token_provider, url_provider)
+            .with_input(settings.input);
+        if let Some(poll_interval_ms) = settings.poll_interval_ms {
+            client = client.with_poll_interval(Duration::from_millis(poll_interval_ms));
+        }
+        if let Some(timeout_secs) = settings.prediction_timeout_secs {
+            client = client.with_timeout(Duration::from_secs(timeout_secs));
+        }
+        if let Some(client_provider) = &context.client_provider {
+            client = client.with_client_provider(client_provider.clone());
+        }
+        if let Some(auth_scheme) = &context.auth_scheme {
+            client = client.with_auth_scheme(auth_scheme.clone());
+        }
+        for (name, value) in &context.headers {
+            client = client.with_header(name, value);
+        }
+        client = client.with_capabilities(context.capabilities);
+        Ok(create_pipeline_with_client(vec![], client))
+    }
+}
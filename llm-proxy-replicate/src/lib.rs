@@ -0,0 +1,126 @@
+//! # LLM Proxy Replicate
+//!
+//! This crate serves `OpenAI`-format chat completion requests with language
+//! models hosted on Replicate, hiding Replicate's asynchronous predictions
+//! behind the standard `LLMClient` interface.
+//!
+//! ## Components
+//!
+//! ### Client
+//! The [`client`] module provides [`ReplicateClient`], an `LLMClient` for
+//! [`ChatCompletionRequest`]s that creates a prediction of the requested
+//! model, then follows its stream URL or polls it until it finishes, and
+//! answers in the `OpenAI` format, both for streaming and non-streaming
+//! requests.
+//!
+//! ### Providers
+//! The [`providers`] module provides [`ReplicateUrlProvider`], which returns
+//! the base URL of the Replicate API.
+//!
+//! ### Factory
+//! The [`factory`] module registers the `replicate` provider in a
+//! [`ProviderRegistry`](llm_proxy_core::ProviderRegistry).
+//!
+//! ### Translate
+//! The [`translate`] module converts chat requests into model inputs, and
+//! prediction output, whether streamed or polled, into `OpenAI` completions
+//! and chunks.
+//!
+//! ### Types
+//! The [`types`] module defines the predictions API.
+//!
+//! ## Example Usage
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use llm_proxy_core::TokenProvider;
+//! use llm_proxy_replicate::create_chat_pipeline;
+//!
+//! // `token_provider` supplies the Replicate API token
+//! async fn example(token_provider: Arc<dyn TokenProvider>) -> llm_proxy_core::Result<()> {
+//!     let pipeline = create_chat_pipeline(vec![], token_provider);
+//!
+//!     // Requests and responses use the OpenAI chat completions format
+//!     let request = bytes::Bytes::from(
+//!         r#"{"model": "meta/meta-llama-3-8b-instruct", "messages": [{"role": "user", "content": "Hi"}]}"#,
+//!     );
+//!     let response = pipeline.execute(request).await?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ## Configuration
+//!
+//! ```toml
+//! [llm.replicate]
+//! provider = "replicate"
+//! type = "chat"
+//! base_url = "https://api.replicate.com/v1"
+//! token_env = "REPLICATE_API_TOKEN"
+//! supports_streaming = true
+//! additional_config = { poll_interval_ms = 500 }
+//! ```
+
+pub mod client;
+pub mod factory;
+pub mod providers;
+pub mod translate;
+pub mod types;
+
+use std::sync::Arc;
+
+use llm_proxy_core::{Pipeline, Processor, ProcessorChain, TokenProvider, UrlProvider};
+use llm_proxy_openai::{
+    providers::StaticClientProvider, ChatCompletionRequest, OpenAIRequestParser,
+};
+
+pub use client::ReplicateClient;
+pub use providers::ReplicateUrlProvider;
+pub use translate::{to_openai_response, to_prediction_request, StreamTranslator};
+
+/// Create a new pipeline that serves `OpenAI` chat completion requests with
+/// the models of the public Replicate API.
+///
+/// # Arguments
+/// * `processors` - List of processors to apply to requests
+/// * `token_provider` - Provider of the Replicate API token
+#[must_use]
+pub fn create_chat_pipeline(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    token_provider: Arc<dyn TokenProvider>,
+) -> Pipeline<ChatCompletionRequest> {
+    create_pipeline_with_client(processors, create_chat_client(token_provider))
+}
+
+/// Create a Replicate client for the public Replicate API.
+#[must_use]
+pub fn create_chat_client(token_provider: Arc<dyn TokenProvider>) -> ReplicateClient {
+    let url_provider = Arc::new(ReplicateUrlProvider::api());
+    create_chat_client_with_url_provider(token_provider, url_provider)
+}
+
+/// Create a Replicate client whose API URL is chosen by `url_provider`.
+#[must_use]
+pub fn create_chat_client_with_url_provider(
+    token_provider: Arc<dyn TokenProvider>,
+    url_provider: Arc<dyn UrlProvider>,
+) -> ReplicateClient {
+    let client_provider = Arc::new(StaticClientProvider::new());
+    ReplicateClient::new(client_provider, token_provider, url_provider)
+}
+
+/// Create a chat completion pipeline around an existing Replicate client.
+///
+/// Requests are parsed in the `OpenAI` format, so processors written for
+/// `OpenAI` requests apply unchanged.
+#[must_use]
+pub fn create_pipeline_with_client(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    llm_client: ReplicateClient,
+) -> Pipeline<ChatCompletionRequest> {
+    let parser = Arc::new(OpenAIRequestParser::new());
+    let processor_chain = Arc::new(ProcessorChain::new(processors));
+
+    Pipeline::new(parser, processor_chain, Arc::new(llm_client))
+}
@@ -0,0 +1,39 @@
+use llm_proxy_core::{Result, UrlProvider};
+
+/// Base URL of the Replicate API
+pub const REPLICATE_API_URL: &str = "https://api.replicate.com/v1";
+
+/// Provider that returns the base URL of the Replicate API
+///
+/// The client appends `/models/{owner}/{name}/predictions`, or `/predictions`
+/// for models referenced by version, to the URL.
+pub struct ReplicateUrlProvider {
+    base_url: String,
+}
+
+impl ReplicateUrlProvider {
+    /// Create a provider for the API at `base_url`, e.g. of a proxy in front of Replicate
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Create a provider for the public Replicate API
+    #[must_use]
+    pub fn api() -> Self {
+        Self::new(REPLICATE_API_URL)
+    }
+}
+
+impl Default for ReplicateUrlProvider {
+    fn default() -> Self {
+        Self::api()
+    }
+}
+
+impl UrlProvider for ReplicateUrlProvider {
+    fn get_url(&self) -> Result<String> {
+        Ok(self.base_url.clone())
+    }
+}
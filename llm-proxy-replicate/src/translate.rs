@@ -0,0 +1,498 @@
+//! Translation between the `OpenAI` chat completions format and Replicate
+//! predictions.
+//!
+//! - [`to_prediction_request`] converts an inbound `OpenAI` chat completion
+//!   request into the inputs of a language model prediction.
+//! - [`to_openai_response`] converts a finished prediction into an `OpenAI`
+//!   chat completion.
+//! - [`StreamTranslator`] converts the server-sent events of a prediction's
+//!   stream URL, or the snapshots of a polled prediction, into `OpenAI` chat
+//!   completion chunks.
+
+use bytes::Bytes;
+use llm_proxy_core::{unix_now, upstream::ChunkTranslator, Error, Result};
+use llm_proxy_openai::{ChatCompletionRequest, ContentPart, Message, MessageContent};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::types::{ErrorResponse, Metrics, Prediction, PredictionRequest, PredictionStatus};
+
+/// The `stop` parameter, either a single sequence or a list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stop {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// `OpenAI` parameters that language models on Replicate accept under the same name
+const PASSTHROUGH_PARAMS: &[&str] = &[
+    "top_p",
+    "top_k",
+    "presence_penalty",
+    "frequency_penalty",
+    "seed",
+];
+
+/// Split a model reference into the model and its version, if any
+///
+/// Models are referenced as `owner/name`, which runs the latest version of an
+/// official model, or as `owner/name:version`.
+#[must_use]
+pub fn split_model(model: &str) -> (&str, Option<&str>) {
+    model
+        .split_once(':')
+        .map_or((model, None), |(model, version)| (model, Some(version)))
+}
+
+/// Convert an `OpenAI` chat completion request into a prediction request.
+///
+/// System messages become the `system_prompt` input. A single user message is
+/// sent as the `prompt` as is; longer conversations are rendered as a
+/// `User:`/`Assistant:` transcript ending with an open assistant turn. The
+/// first image is sent as the `image` input of vision models. Inputs in
+/// `defaults`, e.g. a `prompt_template`, are added when the request doesn't
+/// set them.
+///
+/// # Errors
+///
+/// This function will return an error if the request uses tools, which
+/// Replicate models don't support.
+pub fn to_prediction_request(
+    request: &ChatCompletionRequest,
+    defaults: &Map<String, Value>,
+) -> Result<PredictionRequest> {
+    if request.functions.is_some()
+        || request.additional_params.contains_key("tools")
+        || request
+            .messages
+            .iter()
+            .any(|message| message.tool_calls.is_some() || message.role == "tool")
+    {
+        return Err(Error::Unsupported(
+            "Replicate models don't support tool calls".to_string(),
+        ));
+    }
+
+    let mut input = Map::new();
+    let (system, turns): (Vec<&Message>, Vec<&Message>) = request
+        .messages
+        .iter()
+        .partition(|message| matches!(message.role.as_str(), "system" | "developer"));
+    let system_prompt = system
+        .iter()
+        .map(|message| message_text(message))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if !system_prompt.is_empty() {
+        input.insert("system_prompt".to_string(), Value::String(system_prompt));
+    }
+    input.insert("prompt".to_string(), Value::String(render_prompt(&turns)));
+    if let Some(image) = turns.iter().find_map(|message| first_image(message)) {
+        input.insert("image".to_string(), Value::String(image.to_string()));
+    }
+
+    if let Some(max_tokens) = request
+        .max_tokens
+        .or_else(|| request.param("max_completion_tokens"))
+    {
+        input.insert("max_tokens".to_string(), json!(max_tokens));
+    }
+    if let Some(temperature) = request.temperature {
+        input.insert("temperature".to_string(), json!(temperature));
+    }
+    for name in PASSTHROUGH_PARAMS {
+        if let Some(value) = request.additional_params.get(*name) {
+            input.insert((*name).to_string(), value.clone());
+        }
+    }
+    let stop_sequences = match request.param::<Stop>("stop") {
+        Some(Stop::One(stop)) => vec![stop],
+        Some(Stop::Many(stop)) => stop,
+        None => Vec::new(),
+    };
+    if !stop_sequences.is_empty() {
+        input.insert(
+            "stop_sequences".to_string(),
+            Value::String(stop_sequences.join(",")),
+        );
+    }
+
+    for (name, value) in defaults {
+        input.entry(name.clone()).or_insert_with(|| value.clone());
+    }
+
+    Ok(PredictionRequest {
+        version: split_model(&request.model).1.map(str::to_string),
+        input,
+        stream: request.stream,
+    })
+}
+
+/// The text of a message, without its images
+fn message_text(message: &Message) -> String {
+    message
+        .content
+        .as_ref()
+        .map(MessageContent::text)
+        .unwrap_or_default()
+}
+
+/// The URL of the first image of a message
+fn first_image(message: &Message) -> Option<&str> {
+    match &message.content {
+        Some(MessageContent::Parts(parts)) => parts.iter().find_map(|part| match part {
+            ContentPart::ImageUrl { image_url } => Some(image_url.url.as_str()),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
+/// Render the turns of a conversation into a single prompt
+fn render_prompt(turns: &[&Message]) -> String {
+    if let [message] = turns {
+        if message.role == "user" {
+            return message_text(message);
+        }
+    }
+    let mut lines: Vec<String> = turns
+        .iter()
+        .map(|message| {
+            let speaker = if message.role == "assistant" {
+                "Assistant"
+            } else {
+                "User"
+            };
+            format!("{speaker}: {}", message_text(message))
+        })
+        .collect();
+    lines.push("Assistant:".to_string());
+    lines.join("\n\n")
+}
+
+/// The text generated so far by a prediction
+///
+/// Language models output a list of tokens; other models may output a string.
+#[must_use]
+pub fn output_text(prediction: &Prediction) -> String {
+    match &prediction.output {
+        Some(Value::Array(tokens)) => tokens
+            .iter()
+            .filter_map(Value::as_str)
+            .collect::<Vec<_>>()
+            .concat(),
+        Some(Value::String(text)) => text.clone(),
+        _ => String::new(),
+    }
+}
+
+/// The error of a failed or canceled prediction
+fn prediction_error(prediction: &Prediction) -> Error {
+    if prediction.status == PredictionStatus::Canceled {
+        return Error::LLMError(format!(
+            "Replicate prediction {} was canceled",
+            prediction.id
+        ));
+    }
+    let message = match &prediction.error {
+        Some(Value::String(message)) => message.clone(),
+        Some(error) => error.to_string(),
+        None => "unknown error".to_string(),
+    };
+    Error::LLMError(format!("Replicate prediction failed: {message}"))
+}
+
+/// `OpenAI` usage from the metrics of a prediction
+fn to_openai_usage(metrics: Option<&Metrics>) -> Value {
+    let metrics = metrics.cloned().unwrap_or_default();
+    let prompt_tokens = metrics.input_token_count.unwrap_or_default();
+    let completion_tokens = metrics.output_token_count.unwrap_or_default();
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    })
+}
+
+/// Convert a finished prediction into an `OpenAI` chat completion
+///
+/// # Errors
+///
+/// This function will return an error if the prediction failed or was
+/// canceled.
+pub fn to_openai_response(prediction: &Prediction, model: &str) -> Result<Value> {
+    if prediction.status != PredictionStatus::Succeeded {
+        return Err(prediction_error(prediction));
+    }
+    Ok(json!({
+        "id": format!("chatcmpl-{}", prediction.id),
+        "object": "chat.completion",
+        "created": unix_now(),
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": output_text(prediction)},
+            "finish_reason": "stop",
+        }],
+        "usage": to_openai_usage(prediction.metrics.as_ref()),
+    }))
+}
+
+/// Converts the output of a prediction into `OpenAI` chat completion chunks.
+///
+/// The output arrives in one of two ways, depending on how the client follows
+/// the prediction:
+///
+/// - [`StreamTranslator::push`] takes the raw bytes of the server-sent events
+///   of the prediction's stream URL; events may be split across reads.
+/// - [`StreamTranslator::push_prediction`] takes the prediction as returned by
+///   each poll and emits the output added since the previous poll, for models
+///   that don't stream.
+///
+/// Either way, the stream ends with a `stop` chunk and `data: [DONE]` once the
+/// prediction succeeds, and failures are returned as errors.
+#[derive(Debug)]
+pub struct StreamTranslator {
+    id: String,
+    model: String,
+    created: u64,
+    /// Whether the first chunk, carrying the role, was sent
+    started: bool,
+    /// Bytes of the output already sent, when polling
+    sent: usize,
+    /// Bytes of an incomplete line
+    buffer: Vec<u8>,
+    /// Name of the event being received
+    event: Option<String>,
+    /// Data lines of the event being received
+    data: Vec<String>,
+}
+
+impl StreamTranslator {
+    /// Create a translator for the prediction `id`, answering as `model`
+    pub fn new(id: &str, model: impl Into<String>) -> Self {
+        Self {
+            id: format!("chatcmpl-{id}"),
+            model: model.into(),
+            created: unix_now(),
+            started: false,
+            sent: 0,
+            buffer: Vec::new(),
+            event: None,
+            data: Vec::new(),
+        }
+    }
+
+    /// Translate the next bytes of the stream URL's events
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the stream reports an error or
+    /// the prediction was canceled.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                if let Some(event) = self.event.take() {
+                    let data = std::mem::take(&mut self.data).join("\n");
+                    events.extend(self.dispatch(&event, &data)?);
+                }
+                self.data.clear();
+            } else if let Some(event) = line.strip_prefix("event:") {
+                self.event = Some(event.trim().to_string());
+            } else if let Some(data) = line.strip_prefix("data:") {
+                // Only the space after the colon is stripped, since tokens
+                // often start with a space
+                self.data
+                    .push(data.strip_prefix(' ').unwrap_or(data).to_string());
+            }
+        }
+        Ok(events)
+    }
+
+    /// Translate a snapshot of a polled prediction
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the prediction failed or was
+    /// canceled.
+    pub fn push_prediction(&mut self, prediction: &Prediction) -> Result<Vec<Bytes>> {
+        if matches!(
+            prediction.status,
+            PredictionStatus::Failed | PredictionStatus::Canceled
+        ) {
+            return Err(prediction_error(prediction));
+        }
+
+        let mut events = Vec::new();
+        let text = output_text(prediction);
+        if let Some(added) = text.get(self.sent..).filter(|added| !added.is_empty()) {
+            events.push(self.content(added));
+            self.sent = text.len();
+        }
+        if prediction.status == PredictionStatus::Succeeded {
+            events.extend(self.finish(Some(to_openai_usage(prediction.metrics.as_ref()))));
+        }
+        Ok(events)
+    }
+
+    /// Translate a complete server-sent event
+    fn dispatch(&mut self, event: &str, data: &str) -> Result<Vec<Bytes>> {
+        match event {
+            "output" => Ok(vec![self.content(data)]),
+            "done" => {
+                let reason = serde_json::from_str::<Value>(data)
+                    .ok()
+                    .and_then(|data| data["reason"].as_str().map(str::to_string));
+                match reason.as_deref() {
+                    Some("canceled") => Err(Error::LLMError(
+                        "Replicate prediction was canceled".to_string(),
+                    )),
+                    Some("error") => {
+                        Err(Error::LLMError("Replicate prediction failed".to_string()))
+                    }
+                    _ => Ok(self.finish(None)),
+                }
+            }
+            "error" => {
+                let message = serde_json::from_str::<ErrorResponse>(data)
+                    .map_or_else(|_| data.to_string(), |error| error.detail);
+                Err(Error::LLMError(format!(
+                    "Replicate stream error: {message}"
+                )))
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// A chunk adding `text` to the answer
+    fn content(&mut self, text: &str) -> Bytes {
+        let mut delta = json!({"content": text});
+        if !self.started {
+            self.started = true;
+            delta["role"] = json!("assistant");
+        }
+        Self::event(&self.chunk(&delta, None))
+    }
+
+    /// The last chunk and the end of the stream
+    fn finish(&self, usage: Option<Value>) -> Vec<Bytes> {
+        let mut chunk = self.chunk(&json!({}), Some("stop"));
+        if let Some(usage) = usage {
+            chunk["usage"] = usage;
+        }
+        vec![Self::event(&chunk), Bytes::from_static(b"data: [DONE]\n\n")]
+    }
+
+    fn chunk(&self, delta: &Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{
+                "index": 0,
+                "delta": delta,
+                "finish_reason": finish_reason,
+            }],
+        })
+    }
+
+    fn event(chunk: &Value) -> Bytes {
+        Bytes::from(format!("data: {chunk}\n\n"))
+    }
+}
+
+impl ChunkTranslator for StreamTranslator {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        Self::push(self, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_translation() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "meta/meta-llama-3-8b-instruct",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Hi"},
+                {"role": "assistant", "content": "Hello!"},
+                {"role": "user", "content": "Bye"},
+            ],
+            "max_tokens": 64,
+            "stop": ["\n\n", "User:"],
+            "top_k": 40,
+        }))
+        .expect("Invalid request");
+        let defaults = json!({"top_k": 50, "min_tokens": 1});
+
+        let prediction = to_prediction_request(
+            &request,
+            defaults.as_object().expect("Defaults are an object"),
+        )
+        .expect("Failed to translate");
+        assert_eq!(prediction.version, None);
+        assert_eq!(prediction.input["system_prompt"], "Be brief.");
+        assert_eq!(
+            prediction.input["prompt"],
+            "User: Hi\n\nAssistant: Hello!\n\nUser: Bye\n\nAssistant:"
+        );
+        assert_eq!(prediction.input["max_tokens"], 64);
+        assert_eq!(prediction.input["stop_sequences"], "\n\n,User:");
+        assert_eq!(prediction.input["top_k"], 40);
+        assert_eq!(prediction.input["min_tokens"], 1);
+
+        assert_eq!(
+            split_model("owner/model:5c7d5dc6"),
+            ("owner/model", Some("5c7d5dc6"))
+        );
+    }
+
+    #[test]
+    fn test_stream_translation() {
+        let stream = "event: output\nid: 1\ndata: Hello\n\nevent: output\ndata:  world\n\nevent: done\ndata: {}\n\n";
+
+        // Split the stream in the middle of an event
+        let mut translator = StreamTranslator::new("abc", "owner/model");
+        let (first, second) = stream.as_bytes().split_at(30);
+        let mut events = translator.push(first).expect("Failed to translate");
+        events.extend(translator.push(second).expect("Failed to translate"));
+
+        assert_eq!(events.len(), 4);
+        let second: Value = serde_json::from_slice(&events[1][6..]).expect("Invalid chunk");
+        assert_eq!(second["choices"][0]["delta"]["content"], " world");
+        assert_eq!(events[3], "data: [DONE]\n\n");
+
+        // Polled snapshots only emit the output added since the last poll
+        let snapshot = |status: &str, output: Value| -> Prediction {
+            serde_json::from_value(json!({"id": "abc", "status": status, "output": output}))
+                .expect("Invalid prediction")
+        };
+        let mut translator = StreamTranslator::new("abc", "owner/model");
+        let events = translator
+            .push_prediction(&snapshot("processing", json!(["Hel"])))
+            .expect("Failed to translate");
+        assert_eq!(events.len(), 1);
+        let events = translator
+            .push_prediction(&snapshot("succeeded", json!(["Hel", "lo"])))
+            .expect("Failed to translate");
+        assert_eq!(events.len(), 3);
+        let chunk: Value = serde_json::from_slice(&events[0][6..]).expect("Invalid chunk");
+        assert_eq!(chunk["choices"][0]["delta"]["content"], "lo");
+
+        let error = translator
+            .push_prediction(&snapshot("failed", Value::Null))
+            .expect_err("Failures are reported");
+        assert!(error.to_string().contains("failed"));
+    }
+}
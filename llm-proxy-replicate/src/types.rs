@@ -0,0 +1,105 @@
+//! Types of the Replicate predictions API.
+
+use llm_proxy_core::upstream::UpstreamError;
+use serde::{Deserialize, Serialize};
+
+/// A request to create a prediction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PredictionRequest {
+    /// Version of the model, for models referenced as `owner/name:version`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Inputs of the model, e.g. `prompt` and `max_tokens`
+    pub input: serde_json::Map<String, serde_json::Value>,
+    /// Whether to provide a URL streaming the output as it is generated
+    #[serde(default)]
+    pub stream: bool,
+}
+
+/// State of a prediction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PredictionStatus {
+    /// Waiting for the model to boot
+    Starting,
+    /// Running
+    Processing,
+    /// Finished with output
+    Succeeded,
+    /// Finished with an error
+    Failed,
+    /// Canceled before finishing
+    Canceled,
+    /// Any status added to the API later
+    #[serde(other)]
+    Unknown,
+}
+
+impl PredictionStatus {
+    /// Whether the prediction won't change anymore
+    #[must_use]
+    pub const fn is_terminal(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed | Self::Canceled)
+    }
+}
+
+/// A prediction, as returned when it is created and polled
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Prediction {
+    /// ID of the prediction
+    pub id: String,
+    /// State of the prediction
+    pub status: PredictionStatus,
+    /// The output so far; language models return a list of tokens
+    #[serde(default)]
+    pub output: Option<serde_json::Value>,
+    /// The error of a failed prediction
+    #[serde(default)]
+    pub error: Option<serde_json::Value>,
+    /// URLs to poll, stream or cancel the prediction
+    #[serde(default)]
+    pub urls: Option<PredictionUrls>,
+    /// Token counts of a finished prediction
+    #[serde(default)]
+    pub metrics: Option<Metrics>,
+}
+
+/// URLs of a prediction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PredictionUrls {
+    /// URL returning the current state of the prediction
+    pub get: String,
+    /// URL of the server-sent events of the output, for models that stream
+    #[serde(default)]
+    pub stream: Option<String>,
+    /// URL canceling the prediction
+    #[serde(default)]
+    pub cancel: Option<String>,
+}
+
+/// Metrics of a prediction
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Metrics {
+    /// Tokens in the prompt
+    #[serde(default)]
+    pub input_token_count: Option<u32>,
+    /// Tokens generated
+    #[serde(default)]
+    pub output_token_count: Option<u32>,
+}
+
+/// Error response from Replicate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    /// The error message
+    pub detail: String,
+    /// Short description of the kind of error
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+impl UpstreamError for ErrorResponse {
+    fn message(self) -> String {
+        self.detail
+    }
+}
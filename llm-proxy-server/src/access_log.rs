@@ -0,0 +1,396 @@
+//! Structured access log of the requests the server handles.
+//!
+//! Independent of the tracing output meant for people, an [`AccessLog`]
+//! writes one JSON line per request to stdout or a file, for log pipelines,
+//! and to any other sinks, such as the [`Events`](crate::events::Events) of
+//! ended requests.
+//! Each line holds the `timestamp` in milliseconds since the Unix epoch, the
+//! request's `request_id`, `method` and `path`, the `route`, `model` and `client` (the
+//! client key, or else the tenant) from its context, the response `status`, the `latency_ms` until
+//! the response ended, and, for responses from a pipeline, the
+//! `time_to_first_token_ms` and the `prompt_tokens` and `completion_tokens`
+//! the backend reported. Failed requests carry an `error` class. Anything
+//! looking like a key, e.g. in a path, is masked.
+
+use std::{
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{http::StatusCode, HttpRequest};
+use llm_proxy_core::{redact::redact_json, RequestContext, ResponseStream};
+use llm_proxy_openai::processors::{
+    ApproximateTokenCounter, RequestLogSink, RollingFileSink, StdoutSink, TokenCounter,
+};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::config::AccessLogConfig;
+
+/// Writes an [`AccessLogEntry`] per request to its sinks
+#[derive(Clone)]
+pub struct AccessLog {
+    sinks: Vec<Arc<dyn RequestLogSink>>,
+}
+
+impl AccessLog {
+    /// Create an access log writing to `sink`
+    #[must_use]
+    pub fn new(sink: Arc<dyn RequestLogSink>) -> Self {
+        Self { sinks: vec![sink] }
+    }
+
+    /// Also write the entries to `sink`
+    #[must_use]
+    pub fn with_sink(mut self, sink: Arc<dyn RequestLogSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Create the access log configured in `[access_log]`
+    #[must_use]
+    pub fn from_config(config: &AccessLogConfig) -> Self {
+        let Some(path) = &config.path else {
+            return Self::new(Arc::new(StdoutSink));
+        };
+        let mut sink = RollingFileSink::new(path);
+        if let Some(max_bytes) = config.max_bytes {
+            sink = sink.with_max_bytes(max_bytes);
+        }
+        if let Some(max_files) = config.max_files {
+            sink = sink.with_max_files(max_files);
+        }
+        Self::new(Arc::new(sink))
+    }
+
+    /// Write `entry` for a response with `status` that has ended
+    pub fn write(&self, entry: AccessLogEntry, status: StatusCode) {
+        let record = Arc::new(entry.finish(status));
+        for sink in &self.sinks {
+            let sink = sink.clone();
+            let record = record.clone();
+            tokio::spawn(async move {
+                if let Err(e) = sink.write(&record).await {
+                    warn!(error = %e, "Failed to write access log entry");
+                }
+            });
+        }
+    }
+
+    /// Forward a successful response `stream`, writing `entry` when it ends
+    /// with the time to its first chunk and the usage it reports
+    #[must_use]
+    pub fn observe(&self, mut entry: AccessLogEntry, mut stream: ResponseStream) -> ResponseStream {
+        let log = self.clone();
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            let mut usage = UsageScanner::default();
+            while let Some(item) = stream.recv().await {
+                match &item {
+                    Ok(chunk) => {
+                        entry.first_chunk.get_or_insert_with(Instant::now);
+                        usage.push(chunk);
+                    }
+                    Err(_) => entry.error = Some("upstream"),
+                }
+                // Dropping the stream once the client left cancels the
+                // upstream request
+                if tx.send(item).await.is_err() {
+                    entry.error = Some("cancelled");
+                    break;
+                }
+            }
+            if let Some((prompt_tokens, completion_tokens)) = usage.finish() {
+                entry.prompt_tokens = Some(prompt_tokens);
+                entry.completion_tokens = Some(completion_tokens);
+            }
+            log.write(entry, StatusCode::OK);
+        });
+        rx
+    }
+}
+
+/// What is known about a request for its access log line
+#[derive(Debug)]
+pub struct AccessLogEntry {
+    started: Instant,
+    first_chunk: Option<Instant>,
+    timestamp: u128,
+    request_id: String,
+    method: String,
+    path: String,
+    route: Option<String>,
+    model: Option<String>,
+    client: Option<String>,
+    prompt_tokens: Option<u64>,
+    completion_tokens: Option<u64>,
+    error: Option<&'static str>,
+}
+
+impl AccessLogEntry {
+    /// Start the entry of `req` with ID `request_id`, received now
+    #[must_use]
+    pub fn start(req: &HttpRequest, request_id: &str) -> Self {
+        Self::new(req.method().as_str(), req.uri().path(), request_id)
+    }
+
+    /// Start the entry of a `method` call of `path` with ID `request_id`,
+    /// for calls other than HTTP requests
+    #[must_use]
+    pub fn new(method: &str, path: &str, request_id: &str) -> Self {
+        Self {
+            started: Instant::now(),
+            first_chunk: None,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis()),
+            request_id: request_id.to_string(),
+            method: method.to_string(),
+            path: path.to_string(),
+            route: None,
+            model: None,
+            client: None,
+            prompt_tokens: None,
+            completion_tokens: None,
+            error: None,
+        }
+    }
+
+    /// Take the route, model and client of the request from its context
+    pub fn set_context(&mut self, context: &RequestContext) {
+        self.route.clone_from(&context.route);
+        self.model.clone_from(&context.model);
+        self.client = context
+            .client_key
+            .clone()
+            .or_else(|| context.tenant.clone());
+    }
+
+    fn finish(self, status: StatusCode) -> Value {
+        let record = AccessLogRecord {
+            timestamp: self.timestamp,
+            request_id: self.request_id,
+            method: self.method,
+            path: self.path,
+            route: self.route,
+            model: self.model,
+            client: self.client,
+            status: status.as_u16(),
+            latency_ms: self.started.elapsed().as_millis(),
+            time_to_first_token_ms: self
+                .first_chunk
+                .map(|first| first.saturating_duration_since(self.started).as_millis()),
+            prompt_tokens: self.prompt_tokens,
+            completion_tokens: self.completion_tokens,
+            error: self.error.or_else(|| error_class(status)),
+        };
+        let mut record = serde_json::to_value(record).unwrap_or_default();
+        redact_json(&mut record);
+        record
+    }
+}
+
+/// One line of the access log
+#[derive(Serialize)]
+struct AccessLogRecord {
+    timestamp: u128,
+    request_id: String,
+    method: String,
+    path: String,
+    route: Option<String>,
+    model: Option<String>,
+    client: Option<String>,
+    status: u16,
+    latency_ms: u128,
+    time_to_first_token_ms: Option<u128>,
+    prompt_tokens: Option<u64>,
+    completion_tokens: Option<u64>,
+    error: Option<&'static str>,
+}
+
+/// Class of the error a response with `status` reports, if any
+fn error_class(status: StatusCode) -> Option<&'static str> {
+    let class = match status.as_u16() {
+        0..=399 => return None,
+        400 => "invalid_request",
+        401 | 403 => "unauthorized",
+        404 => "not_found",
+        429 => "rate_limited",
+        502..=504 => "upstream",
+        405..=499 => "client_error",
+        _ => "internal",
+    };
+    Some(class)
+}
+
+/// Finds the `usage` of a response, streamed or not
+#[derive(Default)]
+pub(crate) struct UsageScanner {
+    buffer: Vec<u8>,
+    streaming: Option<bool>,
+    usage: Option<(u64, u64)>,
+    /// Approximate tokens of the streamed output so far
+    output_tokens: u64,
+}
+
+impl UsageScanner {
+    pub(crate) fn push(&mut self, chunk: &[u8]) {
+        let streaming = *self
+            .streaming
+            .get_or_insert_with(|| !String::from_utf8_lossy(chunk).trim_start().starts_with('{'));
+        self.buffer.extend_from_slice(chunk);
+        if !streaming {
+            return;
+        }
+        // Usage comes with the last event, so only complete lines mentioning
+        // it or carrying output are parsed
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line
+                .strip_prefix("data:")
+                .filter(|data| data.contains("\"usage\"") || data.contains("\"delta\""))
+            else {
+                continue;
+            };
+            if let Ok(event) = serde_json::from_str::<Value>(data.trim()) {
+                self.usage = usage(&event).or(self.usage);
+                self.output_tokens += output_tokens(&event, "delta");
+            }
+        }
+    }
+
+    /// The prompt and completion tokens of the response, once it ended
+    pub(crate) fn finish(self) -> Option<(u64, u64)> {
+        if self.streaming == Some(false) {
+            return serde_json::from_slice::<Value>(&self.buffer)
+                .ok()
+                .as_ref()
+                .and_then(usage);
+        }
+        self.usage
+    }
+
+    /// The completion tokens of the response, once it ended, or an estimate
+    /// of them when the backend reported no usage
+    pub(crate) fn completion_estimate(&self) -> u64 {
+        if self.streaming == Some(false) {
+            return serde_json::from_slice::<Value>(&self.buffer)
+                .map(|response| output_tokens(&response, "message"))
+                .unwrap_or_default();
+        }
+        self.output_tokens
+    }
+}
+
+/// Approximate tokens of the text and tool call arguments in the `field`
+/// (`message` or `delta`) of the choices of `response`
+fn output_tokens(response: &Value, field: &str) -> u64 {
+    let counter = ApproximateTokenCounter;
+    let tokens: usize = response["choices"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|choice| &choice[field])
+        .map(|output| {
+            let arguments = output["tool_calls"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|call| call["function"]["arguments"].as_str());
+            output["content"]
+                .as_str()
+                .into_iter()
+                .chain(arguments)
+                .map(|text| counter.count(text))
+                .sum::<usize>()
+        })
+        .sum();
+    u64::try_from(tokens).unwrap_or(u64::MAX)
+}
+
+fn usage(response: &Value) -> Option<(u64, u64)> {
+    let usage = &response["usage"];
+    Some((
+        usage["prompt_tokens"].as_u64()?,
+        usage["completion_tokens"].as_u64()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use serde_json::json;
+
+    use super::*;
+
+    /// Sink sending the records it is given to a channel
+    struct ChannelSink(mpsc::UnboundedSender<Value>);
+
+    #[async_trait]
+    impl RequestLogSink for ChannelSink {
+        async fn write(&self, record: &Value) -> llm_proxy_core::Result<()> {
+            self.0.send(record.clone()).ok();
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writes_record() {
+        let (tx, mut records) = mpsc::unbounded_channel();
+        let log = AccessLog::new(Arc::new(ChannelSink(tx)));
+
+        let mut entry = AccessLogEntry::new(
+            "POST",
+            "/v1/keys/sk-proj-0123456789abcdefghijklmnop",
+            "req-1",
+        );
+        let mut context = RequestContext::new()
+            .with_route("/v1/chat/completions")
+            .with_tenant("acme");
+        context.model = Some("gpt-4o".to_string());
+        context.client_key = Some("ci-bot".to_string());
+        entry.set_context(&context);
+
+        let (upstream, stream) = mpsc::channel(2);
+        let response = json!({
+            "choices": [{"delta": {"content": "Hi"}}],
+            "usage": {"prompt_tokens": 12, "completion_tokens": 3},
+        });
+        upstream
+            .send(Ok(Bytes::from(format!("data: {response}\n\n"))))
+            .await
+            .expect("Failed to send chunk");
+        drop(upstream);
+        let mut stream = log.observe(entry, stream);
+        while stream.recv().await.is_some() {}
+
+        let record = records.recv().await.expect("No record");
+        assert!(record["timestamp"].as_u64().is_some_and(|ms| ms > 0));
+        assert!(record["latency_ms"].is_u64());
+        assert!(record["time_to_first_token_ms"].is_u64());
+        assert_eq!(record["request_id"], "req-1");
+        assert_eq!(record["method"], "POST");
+        assert_eq!(record["route"], "/v1/chat/completions");
+        assert_eq!(record["model"], "gpt-4o");
+        assert_eq!(record["client"], "ci-bot");
+        assert_eq!(record["status"], 200);
+        assert_eq!(record["prompt_tokens"], 12);
+        assert_eq!(record["completion_tokens"], 3);
+        assert!(record["error"].is_null());
+        let path = record["path"].as_str().expect("No path");
+        assert!(path.starts_with("/v1/keys/"), "{path}");
+        assert!(!path.contains("0123456789abcdefghijklmnop"), "{path}");
+
+        let entry = AccessLogEntry::new("GET", "/v1/models", "req-2");
+        log.write(entry, StatusCode::TOO_MANY_REQUESTS);
+        let record = records.recv().await.expect("No record");
+        assert_eq!(record["status"], 429);
+        assert_eq!(record["error"], "rate_limited");
+        assert!(record["client"].is_null());
+        assert!(record["time_to_first_token_ms"].is_null());
+    }
+}
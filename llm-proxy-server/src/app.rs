@@ -1,24 +1,258 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
 
 use actix_cors::Cors;
 use actix_web::{
-    middleware,
+    error::PayloadError,
+    http::{
+        header::{HeaderName, HeaderValue},
+        StatusCode,
+    },
+    middleware::{self, from_fn, Compress, Condition},
     web::{self},
     App, HttpRequest, HttpResponse, HttpServer,
 };
-use anyhow::Result;
-use bytes::BytesMut;
-use futures_util::StreamExt;
-use llm_proxy_core::Pipeline;
-use llm_proxy_openai::ChatCompletionRequest;
-use tracing::{error, info};
+use anyhow::{Context, Result};
+use bytes::{Bytes, BytesMut};
+use futures_util::{Stream, StreamExt};
+use llm_proxy_core::{
+    providers::HealthListener, redact::SecretString, ClientKeyStore, Pipeline, ProviderRegistry,
+    RequestContext, ResponseStream, Tenant, TenantResolver, TokenProvider, UrlProvider,
+    REQUEST_ID_HEADER,
+};
+use llm_proxy_openai::{
+    cache::{CachingLLMClient, ResponseCache},
+    processors::RequestLogSink,
+    replay::StreamReplay,
+    ChatCompletionRequest, OpenAIPassthroughClient, PassthroughRequest,
+};
+use tracing::{error, field, info, instrument, Span};
+use uuid::Uuid;
+
+use crate::{
+    access_log::{AccessLog, AccessLogEntry},
+    admin,
+    byok::{ClientKeyCheck, ClientTokenProvider},
+    client_auth, compression, config,
+    events::Events,
+    inbound, mcp,
+    models::{self, VIRTUAL_MODEL_ATTRIBUTE},
+    payload::{self, BodyTooLarge},
+    processors, prometheus, providers,
+    quota::{self, Quotas},
+    routing,
+    split::{TrafficSplit, TARGET_ATTRIBUTE},
+    telemetry,
+    tenancy::{self, TenantOverlays, TenantTokenProvider},
+    tools::{self, RouteTools},
+    webhooks::{Alert, Webhooks},
+    websocket,
+};
+
+/// Longest inbound request ID that is kept rather than replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
 
-use crate::config;
+/// Largest buffer allocated up front for a request body, whatever its
+/// announced `Content-Length`, so clients can't make the server reserve
+/// memory they never send
+const MAX_PREALLOCATED_BODY: usize = 64 * 1024;
 
 /// Application state shared across request handlers
 pub struct AppState {
-    config: Arc<config::Config>,
-    pipelines: Arc<tokio::sync::RwLock<PipelineRegistry>>,
+    pub(crate) config: Arc<config::Config>,
+    /// Finds the configured route of a request
+    router: routing::Router,
+    /// Traffic splits of the routes, in configured order
+    splits: Vec<Option<TrafficSplit>>,
+    /// Checks of the keys clients bring to routes with `byok`, in configured order
+    pub(crate) client_key_checks: Vec<Option<ClientKeyCheck>>,
+    /// Tools the proxy adds to the requests of routes, in configured order
+    route_tools: Vec<Option<RouteTools>>,
+    pub(crate) pipelines: Arc<tokio::sync::RwLock<PipelineRegistry>>,
+    pub(crate) passthroughs:
+        Arc<tokio::sync::RwLock<HashMap<String, Arc<OpenAIPassthroughClient>>>>,
+    /// Token providers per LLM, shared by all routes targeting that LLM
+    pub(crate) token_providers: Arc<tokio::sync::RwLock<HashMap<String, Arc<dyn TokenProvider>>>>,
+    /// URL providers of the routes' pipelines, for routes with several endpoints
+    pub(crate) url_providers: Arc<tokio::sync::RwLock<HashMap<String, Arc<dyn UrlProvider>>>>,
+    /// Token admin requests must carry, set when the admin API is configured
+    pub(crate) admin_token: Option<SecretString>,
+    /// Resolver for tenant keys, set when tenants but no client keys are configured
+    pub(crate) tenants: Option<Arc<dyn TenantResolver>>,
+    /// Store of the keys clients authenticate with, set when configured
+    pub(crate) client_keys: Option<Arc<dyn ClientKeyStore>>,
+    /// Limits and usage of the client keys, set when configured
+    pub(crate) quotas: Option<Arc<Quotas>>,
+    /// Tenants by ID, which client keys belong to
+    pub(crate) tenants_by_id: HashMap<String, Tenant>,
+    /// Overlays of the tenants, set when an overlay directory is configured
+    pub(crate) overlays: Option<Arc<TenantOverlays>>,
+    /// Pipeline factories per provider name
+    provider_factories: Arc<ProviderRegistry<ChatCompletionRequest>>,
+    /// Processor factories per processor type
+    processor_factories: Arc<processors::ProcessorRegistry>,
+    /// Access log, set when configured, or when events are published or
+    /// webhooks notified
+    pub(crate) access_log: Option<AccessLog>,
+    /// Cache of responses, set when configured
+    pub(crate) response_cache: Option<Arc<ResponseCache>>,
+    /// Publisher of request events, set when configured
+    events: Option<Arc<Events>>,
+    /// Webhooks notified of failures and budget events, set when configured
+    pub(crate) webhooks: Option<Arc<Webhooks>>,
+}
+
+impl AppState {
+    /// State of a server with `config`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the routes are invalid, or the
+    /// client keys, tenants, admin token or tenant overlays cannot be loaded.
+    pub fn new(config: Arc<config::Config>) -> Result<Self> {
+        let splits = config
+            .route
+            .iter()
+            .map(|route| {
+                TrafficSplit::new(route).with_context(|| format!("Invalid route {}", route.id()))
+            })
+            .collect::<Result<_>>()?;
+        for route in &config.route {
+            route
+                .check_forward_headers()
+                .with_context(|| format!("Invalid route {}", route.id()))?;
+        }
+        let client_key_checks = config
+            .route
+            .iter()
+            .map(|route| {
+                ClientKeyCheck::new(route).with_context(|| format!("Invalid route {}", route.id()))
+            })
+            .collect::<Result<_>>()?;
+        let mcp_servers = config
+            .mcp
+            .iter()
+            .map(|(name, server)| Ok((name.clone(), Arc::new(mcp::McpServer::new(name, server)?))))
+            .collect::<Result<HashMap<_, _>>>()?;
+        let tool_executors = config
+            .tool
+            .iter()
+            .map(|(name, tool)| {
+                let executor = tools::create_tool_executor(name, tool)
+                    .with_context(|| format!("Invalid tool {name}"))?;
+                Ok((name.clone(), executor))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+        let route_tools = config
+            .route
+            .iter()
+            .map(|route| {
+                RouteTools::new(route, &mcp_servers, &tool_executors)
+                    .with_context(|| format!("Invalid route {}", route.id()))
+            })
+            .collect::<Result<_>>()?;
+        let client_keys = config
+            .client_keys
+            .as_ref()
+            .map(providers::create_client_key_store)
+            .transpose()?;
+        // With client keys, tenants are those of the keys rather than their own keys
+        let tenants: Option<Arc<dyn TenantResolver>> =
+            if config.tenant.is_empty() || client_keys.is_some() {
+                None
+            } else {
+                Some(Arc::new(providers::create_tenant_resolver(&config.tenant)?))
+            };
+        let admin_token = config
+            .admin
+            .as_ref()
+            .map(|admin| {
+                std::env::var(&admin.token_env)
+                    .map(SecretString::from)
+                    .with_context(|| format!("Failed to read admin token from {}", admin.token_env))
+            })
+            .transpose()?;
+        let events = config
+            .events
+            .as_ref()
+            .map(Events::from_config)
+            .transpose()?
+            .map(Arc::new);
+        let webhooks = Webhooks::from_config(&config.webhook)?.map(Arc::new);
+        let access_log = access_log(&config, events.as_ref(), webhooks.as_ref());
+        let mut processor_factories = processors::create_processor_registry(&config);
+        if let Some(plugins) = &config.plugins {
+            processors::register_plugins(&mut processor_factories, plugins)?;
+        }
+        Ok(Self {
+            router: routing::Router::new(&config.route)?,
+            splits,
+            client_key_checks,
+            route_tools,
+            pipelines: Arc::new(tokio::sync::RwLock::new(PipelineRegistry::new())),
+            passthroughs: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            token_providers: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            url_providers: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            admin_token,
+            tenants,
+            client_keys,
+            quotas: Quotas::from_config(&config).map(Arc::new),
+            tenants_by_id: providers::tenants_by_id(&config.tenant),
+            overlays: TenantOverlays::from_config(&config, webhooks.as_ref())?.map(Arc::new),
+            provider_factories: Arc::new(providers::create_provider_registry()),
+            processor_factories: Arc::new(processor_factories),
+            access_log,
+            response_cache: config.cache.as_ref().map(response_cache),
+            events,
+            webhooks,
+            config,
+        })
+    }
+}
+
+/// The response cache of `[cache]`
+fn response_cache(config: &config::CacheConfig) -> Arc<ResponseCache> {
+    let mut cache = ResponseCache::new();
+    if let Some(max_entries) = config.max_entries {
+        cache = cache.with_max_entries(max_entries);
+    }
+    if let Some(ttl_secs) = config.ttl_secs {
+        cache = cache.with_ttl(Duration::from_secs(ttl_secs));
+    }
+    Arc::new(cache)
+}
+
+/// The replay of cached responses to streaming requests of `[cache]`
+const fn stream_replay(config: &config::CacheConfig) -> StreamReplay {
+    let mut replay = StreamReplay::new();
+    if let Some(chunk_chars) = config.replay_chunk_chars {
+        replay = replay.with_chunk_chars(chunk_chars);
+    }
+    if let Some(delay_ms) = config.replay_delay_ms {
+        replay = replay.with_delay(Duration::from_millis(delay_ms));
+    }
+    replay
+}
+
+/// The access log of `[access_log]`, also written to `events` and `webhooks`,
+/// which publish the events of ended requests and count the failures of
+/// routes from its entries
+fn access_log(
+    config: &config::Config,
+    events: Option<&Arc<Events>>,
+    webhooks: Option<&Arc<Webhooks>>,
+) -> Option<AccessLog> {
+    let mut access_log = config.access_log.as_ref().map(AccessLog::from_config);
+    let sinks = [
+        events.map(|events| events.clone() as Arc<dyn RequestLogSink>),
+        webhooks.map(|webhooks| webhooks.clone() as Arc<dyn RequestLogSink>),
+    ];
+    for sink in sinks.into_iter().flatten() {
+        access_log = Some(match access_log {
+            Some(access_log) => access_log.with_sink(sink),
+            None => AccessLog::new(sink),
+        });
+    }
+    access_log
 }
 
 /// Registry of pre-configured pipelines
@@ -39,6 +273,11 @@ impl PipelineRegistry {
         self.pipelines.get(route_id).cloned()
     }
 
+    /// IDs of the routes with a pipeline
+    pub fn route_ids(&self) -> impl Iterator<Item = &str> {
+        self.pipelines.keys().map(String::as_str)
+    }
+
     pub fn insert(&mut self, route_id: String, pipeline: Arc<Pipeline<ChatCompletionRequest>>) {
         self.pipelines.insert(route_id, pipeline);
     }
@@ -55,41 +294,69 @@ impl Default for PipelineRegistry {
 /// # Errors
 ///
 /// This function will return an error if the server cannot be started.
-#[must_use]
 pub async fn run_server(config: config::Config) -> Result<()> {
     let config = Arc::new(config);
-    let pipelines = Arc::new(tokio::sync::RwLock::new(PipelineRegistry::new()));
     let server_config = config.server.clone();
+    let app_state = web::Data::new(AppState::new(config.clone())?);
+    let client_auth_enabled = app_state.client_keys.is_some();
+    let admin_enabled = app_state.admin_token.is_some();
+    let compress = server_config.compress_responses;
+    if let Some(metrics) = &config.metrics {
+        prometheus::install(metrics)?;
+    }
+    if let Some(grpc) = &app_state.config.grpc {
+        let addr = SocketAddr::new(grpc.host.unwrap_or(server_config.host), grpc.port);
+        spawn_grpc(addr, app_state.clone().into_inner())?;
+    }
 
-    let app_state = web::Data::new(AppState {
-        config: config.clone(),
-        pipelines,
-    });
-
-    let server = HttpServer::new(move || {
-        let config = config.clone();
-        // Configure CORS
-        let cors = Cors::default()
-            .allowed_origin_fn(move |origin, _req_head| {
-                let origin_str = origin.to_str().unwrap_or_default();
-                config
-                    .server
-                    .cors_allowed_origins
-                    .iter()
-                    .any(|allowed| allowed == "*" || allowed == origin_str)
-            })
-            .allowed_methods(vec!["GET", "POST"])
-            .allowed_headers(vec!["Authorization", "Content-Type"])
-            .max_age(3600);
-
-        App::new()
-            .wrap(cors)
-            .wrap(middleware::Logger::default())
-            .app_data(app_state.clone())
-            .default_service(web::route().to(handle_request))
-    })
-    .bind((server_config.host, server_config.port))?
-    .run();
+    let server = {
+        let mut http_server = HttpServer::new(move || {
+            let cors = cors(config.clone());
+            App::new()
+                .wrap(Condition::new(
+                    client_auth_enabled,
+                    from_fn(client_auth::authenticate),
+                ))
+                .wrap(Condition::new(
+                    compress,
+                    from_fn(compression::mark_event_streams),
+                ))
+                .wrap(Condition::new(compress, Compress::default()))
+                .wrap(Condition::new(
+                    compress,
+                    from_fn(compression::unmark_event_streams),
+                ))
+                .wrap(cors)
+                .wrap(middleware::Logger::default())
+                .app_data(app_state.clone())
+                .configure(|service_config| {
+                    if admin_enabled {
+                        admin::configure(service_config);
+                    }
+                    models::configure(service_config, &config);
+                    prometheus::configure(service_config, &config);
+                })
+                .default_service(web::route().to(handle_request))
+        });
+        if let Some(workers) = server_config.workers {
+            http_server = http_server.workers(workers);
+        }
+        if let Some(secs) = server_config.keep_alive_secs {
+            http_server = http_server.keep_alive(Duration::from_secs(secs));
+        }
+        if let Some(secs) = server_config.client_request_timeout_secs {
+            http_server = http_server.client_request_timeout(Duration::from_secs(secs));
+        }
+        if let Some(max) = server_config.max_connections {
+            http_server = http_server.max_connections(max);
+        }
+        if let Some(backlog) = server_config.backlog {
+            http_server = http_server.backlog(backlog);
+        }
+        http_server
+            .bind((server_config.host, server_config.port))?
+            .run()
+    };
 
     info!(
         "Server running at http://{}:{}",
@@ -100,110 +367,1188 @@ pub async fn run_server(config: config::Config) -> Result<()> {
     Ok(())
 }
 
+/// Serve the gRPC interface on `addr`
+#[cfg(feature = "grpc")]
+fn spawn_grpc(addr: SocketAddr, state: Arc<AppState>) -> Result<()> {
+    crate::grpc::spawn(addr, state)
+}
+
+/// Serve the gRPC interface on `addr`, which needs the `grpc` feature
+#[cfg(not(feature = "grpc"))]
+fn spawn_grpc(_addr: SocketAddr, _state: Arc<AppState>) -> Result<()> {
+    anyhow::bail!("The [grpc] section needs the server built with the grpc feature")
+}
+
+/// CORS policy allowing the configured origins
+fn cors(config: Arc<config::Config>) -> Cors {
+    Cors::default()
+        .allowed_origin_fn(move |origin, _req_head| {
+            let origin_str = origin.to_str().unwrap_or_default();
+            config
+                .server
+                .cors_allowed_origins
+                .iter()
+                .any(|allowed| allowed == "*" || allowed == origin_str)
+        })
+        .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
+        .allowed_headers(vec!["Authorization", "Content-Type", "X-Request-Id"])
+        .expose_headers(vec!["X-Request-Id"])
+        .max_age(3600)
+}
+
 /// Generic request handler that routes requests based on configuration
-#[allow(clippy::future_not_send, clippy::cognitive_complexity)]
+#[allow(clippy::future_not_send)]
+#[instrument(
+    name = "request",
+    skip_all,
+    fields(
+        method = %req.method(),
+        path = %req.uri().path(),
+        request_id = field::Empty,
+        tenant = field::Empty,
+    )
+)]
 async fn handle_request(
     req: HttpRequest,
     payload: web::Payload,
     state: web::Data<AppState>,
 ) -> HttpResponse {
-    let path = req.uri().path();
+    telemetry::set_remote_parent(&Span::current(), req.headers());
+    let request_id = request_id(&req);
+    Span::current().record("request_id", request_id.as_str());
+    let mut entry = state
+        .access_log
+        .as_ref()
+        .map(|_| AccessLogEntry::start(&req, &request_id));
+    let mut response = route_request(&req, payload, &state, &request_id, &mut entry).await;
+    // Entries of streamed responses are taken and written when they end
+    if let (Some(access_log), Some(entry)) = (&state.access_log, entry) {
+        access_log.write(entry, response.status());
+    }
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+    response
+}
+
+/// The ID of `req` from its `X-Request-Id` header, see [`request_id_or_new`]
+fn request_id(req: &HttpRequest) -> String {
+    request_id_or_new(
+        req.headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    )
+}
+
+/// The inbound request ID `id`, or a new one if there is none or it isn't a
+/// short string of visible ASCII characters
+pub(crate) fn request_id_or_new(id: Option<&str>) -> String {
+    id.filter(|id| {
+        !id.is_empty()
+            && id.len() <= MAX_REQUEST_ID_LEN
+            && id.bytes().all(|byte| byte.is_ascii_graphic())
+    })
+    .map_or_else(|| Uuid::new_v4().to_string(), ToString::to_string)
+}
+
+/// Handle a request with the route it matches
+///
+/// `entry` is filled in as the request proceeds; for a response from a
+/// pipeline it is taken and written when the response ends.
+#[allow(clippy::future_not_send, clippy::cognitive_complexity)]
+async fn route_request(
+    req: &HttpRequest,
+    payload: web::Payload,
+    state: &AppState,
+    request_id: &str,
+    entry: &mut Option<AccessLogEntry>,
+) -> HttpResponse {
+    let Some(matched) = state.router.find(req) else {
+        return HttpResponse::NotFound().body(format!(
+            "No route found for {} {}",
+            req.method(),
+            req.uri().path()
+        ));
+    };
+
+    let route = &state.config.route[matched.index];
 
-    // Find matching route
-    let Some(route) = state.config.find_route(path) else {
-        return HttpResponse::NotFound().body(format!("No route found for path: {path}"));
+    let mut context = match resolve_context(req, state, route, request_id).await {
+        Ok(context) => context,
+        Err(response) => return response,
     };
+    context.upstream_path = matched.upstream_path;
+    context.upstream_headers = route.forwarded_headers(&context.headers);
+    if let Some(tenant) = &context.tenant {
+        Span::current().record("tenant", tenant.as_str());
+    }
+    if let Some(entry) = entry {
+        entry.set_context(&context);
+    }
+
+    let client_key_check = state.client_key_checks[matched.index].as_ref();
+    if let Some(response) = client_key_check.and_then(|check| check.check(&context)) {
+        return response;
+    }
+    if route.websocket.is_some() && websocket::is_upgrade(req) {
+        return websocket::serve(req, payload, matched.index, context);
+    }
+
+    let body_limit = route.max_body_bytes(&state.config.server);
+    if let Some(too_large) = payload::check_content_length(req, body_limit) {
+        return too_large.response();
+    }
+
+    let overlay = context
+        .tenant
+        .as_deref()
+        .zip(state.overlays.as_ref())
+        .and_then(|(tenant, overlays)| overlays.get(tenant));
+    if let Some(response) = admit(state, &mut context, overlay.as_deref()) {
+        return response;
+    }
+
+    if route.passthrough {
+        return handle_passthrough(req, payload, state, route, &context).await;
+    }
+
+    let length = payload::content_length(req);
+    let body = match read_chat_body(payload, route, length, body_limit).await {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    let streaming = compression::requests_stream(&body);
+    let result = execute_chat(state, matched.index, overlay.as_deref(), body, &mut context).await;
+    if let Some(entry) = entry {
+        entry.set_context(&context);
+    }
+    match result {
+        Ok(rx) => respond(state, context, entry.take(), rx, route.api, streaming),
+        Err(response) => response,
+    }
+}
+
+/// Run a chat request `body` through the pipeline of the route at `index`
+pub(crate) async fn execute_chat(
+    state: &AppState,
+    index: usize,
+    overlay: Option<&config::TenantOverlay>,
+    body: Bytes,
+    context: &mut RequestContext,
+) -> std::result::Result<ResponseStream, HttpResponse> {
+    let route = &state.config.route[index];
+
+    // Virtual models are checked by the name clients ask for here, and by
+    // the model they resolve to in `resolve_target`
+    let tenant_overlay = context.tenant.as_deref().zip(overlay);
+    if let Some(response) = check_model_policy(route, tenant_overlay, &body) {
+        return Err(response);
+    }
+
+    // Quotas are counted from the usage of responses
+    let body = if state.quotas.is_some() && context.client_key.is_some() {
+        quota::request_usage(body, context)
+    } else {
+        body
+    };
+
+    let split = state.splits[index].as_ref();
+    let (body, llm_id) = resolve_target(state, route, split, overlay, body, context)?;
 
     // Get or create pipeline for this route
-    let pipeline = match get_pipeline_for_route(&state, route).await {
-        Ok(pipeline) => pipeline,
-        Err(e) => {
+    let pipeline = get_pipeline_for_route(state, route, llm_id)
+        .await
+        .map_err(|e| {
             error!(error = %e, "Failed to get pipeline for route");
-            return HttpResponse::InternalServerError().body(format!("Pipeline error: {e}"));
+            HttpResponse::InternalServerError().body(format!("Pipeline error: {e}"))
+        })?;
+
+    if let Some(events) = &state.events {
+        events.request_started(context, &body);
+    }
+
+    // Execute pipeline, with the tools of the route if it has any
+    let result = match &state.route_tools[index] {
+        Some(tools) => tools.execute(&pipeline, body, context).await,
+        None => pipeline.execute_with_context(body, context).await,
+    };
+    result.map_err(|e| match e {
+        llm_proxy_core::Error::Unsupported(_)
+        | llm_proxy_core::Error::Rejected(_)
+        | llm_proxy_core::Error::InvalidRequest(_) => {
+            HttpResponse::BadRequest().body(e.to_string())
+        }
+        e => {
+            error!(error = %e, "Pipeline execution failed");
+            HttpResponse::InternalServerError().body(format!("Pipeline error: {e}"))
         }
+    })
+}
+
+/// The response streaming `rx` back to the client in the route's API format,
+/// observed by the access log and quotas
+fn respond(
+    state: &AppState,
+    context: RequestContext,
+    entry: Option<AccessLogEntry>,
+    rx: ResponseStream,
+    api: config::ApiFormat,
+    streaming: bool,
+) -> HttpResponse {
+    let rx = match (&state.access_log, entry) {
+        (Some(access_log), Some(entry)) => access_log.observe(entry, rx),
+        _ => rx,
     };
+    let rx = observe_quota(state, &context, rx);
+    let rx = inbound::translate_response(api, rx, streaming);
 
-    // Read request body
-    let body = match read_request_body(payload).await {
-        Ok(body) => body,
+    // Stream response back to client
+    let receiver_stream = tokio_stream::wrappers::ReceiverStream::new(rx);
+    let mut response = HttpResponse::Ok();
+    for header in context.response_headers {
+        response.append_header(header);
+    }
+    response
+        .content_type(inbound::content_type(api, streaming))
+        .streaming(receiver_stream)
+}
+
+/// `rx`, counted against the quota of the request's client key
+pub(crate) fn observe_quota(
+    state: &AppState,
+    context: &RequestContext,
+    rx: ResponseStream,
+) -> ResponseStream {
+    match (&state.quotas, context.client_key.clone()) {
+        (Some(quotas), Some(client_key)) => {
+            let prompt_tokens = context
+                .attributes
+                .get(quota::PROMPT_TOKENS_ATTRIBUTE)
+                .and_then(|tokens| tokens.parse().ok())
+                .unwrap_or_default();
+            let strip_usage = context
+                .attributes
+                .contains_key(quota::STRIP_USAGE_ATTRIBUTE);
+            quotas.observe(
+                client_key,
+                context.model.clone(),
+                prompt_tokens,
+                strip_usage,
+                rx,
+            )
+        }
+        _ => rx,
+    }
+}
+
+/// A 429 response for requests whose client key used up a quota, adding the
+/// remaining quota to the response headers of the others
+pub(crate) fn admit(
+    state: &AppState,
+    context: &mut RequestContext,
+    overlay: Option<&config::TenantOverlay>,
+) -> Option<HttpResponse> {
+    let (quotas, client_key) = state.quotas.as_ref().zip(context.client_key.as_ref())?;
+    match quotas.admit(client_key, overlay.and_then(|overlay| overlay.quota)) {
+        Ok(headers) => {
+            context.response_headers.extend(headers);
+            None
+        }
+        Err(exceeded) => {
+            if let Some(webhooks) = &state.webhooks {
+                webhooks.notify(&Alert::QuotaExhausted {
+                    client: client_key.clone(),
+                    message: exceeded.message().to_string(),
+                });
+            }
+            Some(exceeded.response())
+        }
+    }
+}
+
+/// The body and LLM of a request on `route`
+///
+/// Requests for a virtual model go to the model's LLM if the model it
+/// resolves to is allowed on the route too, others to the LLM the
+/// tenant's overlay sets for the route, or else the route's split or target
+/// LLM. The overlay's system prompt is added to the body.
+fn resolve_target<'a>(
+    state: &'a AppState,
+    route: &'a config::RouteConfig,
+    split: Option<&'a TrafficSplit>,
+    overlay: Option<&'a config::TenantOverlay>,
+    body: Bytes,
+    context: &mut RequestContext,
+) -> std::result::Result<(Bytes, &'a str), HttpResponse> {
+    let route_id = route.id();
+    let (body, llm_id) = if let Some(virtual_model) = models::resolve(&state.config, &body) {
+        let tenant = context.tenant.as_deref().zip(overlay);
+        if let Some(response) = check_model_policy(route, tenant, &virtual_model.body) {
+            return Err(response);
+        }
+        context.attributes.insert(
+            VIRTUAL_MODEL_ATTRIBUTE.to_string(),
+            virtual_model.name.to_string(),
+        );
+        (virtual_model.body, virtual_model.preset.target_llm.as_str())
+    } else if let Some(llm_id) = overlay.and_then(|overlay| overlay.target_llm(&route_id)) {
+        (body, llm_id)
+    } else if let Some(split) = split {
+        let llm_id = split
+            .pick(context, &body)
+            .map_err(|e| HttpResponse::BadRequest().body(e.to_string()))?;
+        context
+            .attributes
+            .insert(TARGET_ATTRIBUTE.to_string(), llm_id.to_string());
+        (body, llm_id)
+    } else {
+        (body, route.target_llm.as_str())
+    };
+    let body = match overlay.and_then(|overlay| overlay.system_prompt(&route_id)) {
+        Some(prompt) => tenancy::add_system_prompt(&body, prompt).unwrap_or(body),
+        None => body,
+    };
+    Ok((body, llm_id))
+}
+
+/// A 403 response for requests asking for a model their route or, given
+/// with its ID, their tenant's overlay doesn't allow
+fn check_model_policy(
+    route: &config::RouteConfig,
+    tenant: Option<(&str, &config::TenantOverlay)>,
+    body: &[u8],
+) -> Option<HttpResponse> {
+    #[derive(serde::Deserialize)]
+    struct RequestedModel {
+        model: String,
+    }
+
+    let tenant = tenant.filter(|(_, overlay)| {
+        !(overlay.allowed_models.is_empty() && overlay.denied_models.is_empty())
+    });
+    if route.allowed_models.is_empty() && route.denied_models.is_empty() && tenant.is_none() {
+        return None;
+    }
+    // Requests without a model are rejected by the pipeline
+    let model = serde_json::from_slice::<RequestedModel>(body).ok()?.model;
+    let (subject, allowed, denied) = if route.allows_model(&model) {
+        let (tenant, overlay) = tenant.filter(|(_, overlay)| !overlay.allows_model(&model))?;
+        (
+            format!("for tenant {tenant}"),
+            &overlay.allowed_models,
+            &overlay.denied_models,
+        )
+    } else {
+        (
+            format!("on route {}", route.id()),
+            &route.allowed_models,
+            &route.denied_models,
+        )
+    };
+    let policy = if config::matches_model(denied, &model) {
+        format!("it denies {}", denied.join(", "))
+    } else {
+        format!("it only allows {}", allowed.join(", "))
+    };
+    let message = format!("The model {model} is not allowed {subject}: {policy}");
+    // In the format of the OpenAI API's errors, which clients know to display
+    Some(HttpResponse::Forbidden().json(serde_json::json!({
+        "error": {
+            "message": message,
+            "type": "permission_error",
+            "param": "model",
+            "code": "model_not_allowed",
+        }
+    })))
+}
+
+/// Build the request context, with the tenant of the client key or else
+/// authenticating the tenant if tenants are configured, or else taking the
+/// tenant from the `[tenancy]` header
+#[allow(clippy::future_not_send)]
+async fn resolve_context(
+    req: &HttpRequest,
+    state: &AppState,
+    route: &config::RouteConfig,
+    request_id: &str,
+) -> std::result::Result<RequestContext, HttpResponse> {
+    let context = req.headers().iter().fold(
+        RequestContext::new()
+            .with_request_id(request_id)
+            .with_route(route.id()),
+        |context, (name, value)| match value.to_str() {
+            Ok(value) => context.with_header(name.as_str(), value),
+            Err(_) => context,
+        },
+    );
+    if let Some(identity) = client_auth::client_key(req) {
+        let mut context = context.with_client_key(identity.id);
+        if let Some(tenant_id) = identity.tenant {
+            if let Some(tenant) = state.tenants_by_id.get(&tenant_id) {
+                context.attributes.extend(tenant.attributes.clone());
+            }
+            context = context.with_tenant(tenant_id);
+        }
+        return Ok(context);
+    }
+    let Some(tenants) = &state.tenants else {
+        return header_tenant(req, state, context);
+    };
+
+    let client_key = client_auth::request_key(req.headers())
+        .ok_or_else(|| HttpResponse::Unauthorized().body("Missing tenant key"))?;
+
+    match tenants.resolve(client_key).await {
+        Ok(Some(tenant)) => {
+            let mut context = context.with_tenant(tenant.id);
+            context.attributes.extend(tenant.attributes);
+            Ok(context)
+        }
+        Ok(None) => Err(HttpResponse::Unauthorized().body("Invalid tenant key")),
         Err(e) => {
-            error!(error = %e, "Failed to read request body");
-            return HttpResponse::BadRequest().body(format!("Invalid request body: {e}"));
+            error!(error = %e, "Failed to resolve tenant");
+            Err(HttpResponse::InternalServerError().body(format!("Tenant error: {e}")))
         }
+    }
+}
+
+/// Set the tenant of an unauthenticated request from the `[tenancy]` header
+///
+/// The tenant must be configured or have an overlay; requests without the
+/// header have no tenant.
+fn header_tenant(
+    req: &HttpRequest,
+    state: &AppState,
+    context: RequestContext,
+) -> std::result::Result<RequestContext, HttpResponse> {
+    let Some(header) = state
+        .config
+        .tenancy
+        .as_ref()
+        .and_then(|tenancy| tenancy.header.as_deref())
+    else {
+        return Ok(context);
+    };
+    let Some(tenant_id) = req.headers().get(header) else {
+        return Ok(context);
     };
+    let tenant_id = tenant_id.to_str().unwrap_or_default();
+    let overlay = state
+        .overlays
+        .as_ref()
+        .and_then(|overlays| overlays.get(tenant_id));
+    match (state.tenants_by_id.get(tenant_id), overlay) {
+        (Some(tenant), _) => {
+            let mut context = context.with_tenant(tenant_id);
+            context.attributes.extend(tenant.attributes.clone());
+            Ok(context)
+        }
+        (None, Some(_)) => Ok(context.with_tenant(tenant_id)),
+        (None, None) => {
+            Err(HttpResponse::Unauthorized().body(format!("Unknown tenant {tenant_id}")))
+        }
+    }
+}
 
-    // Execute pipeline
-    let rx = match pipeline.execute(body.freeze()).await {
-        Ok(rx) => rx,
+/// Forward a request on a passthrough route to the upstream API unchanged
+#[allow(clippy::future_not_send)]
+async fn handle_passthrough(
+    req: &HttpRequest,
+    payload: web::Payload,
+    state: &AppState,
+    route: &config::RouteConfig,
+    context: &RequestContext,
+) -> HttpResponse {
+    let client = match get_passthrough_client_for_route(state, route).await {
+        Ok(client) => client,
         Err(e) => {
-            error!(error = %e, "Pipeline execution failed");
-            return HttpResponse::InternalServerError().body(format!("Pipeline error: {e}"));
+            error!(error = %e, "Failed to get passthrough client for route");
+            return HttpResponse::InternalServerError().body(format!("Passthrough error: {e}"));
         }
     };
 
-    // Stream response back to client
-    let receiver_stream = tokio_stream::wrappers::ReceiverStream::new(rx);
-    HttpResponse::Ok()
-        .content_type("application/json")
-        .streaming(receiver_stream)
+    let Ok(method) = reqwest::Method::from_bytes(req.method().as_str().as_bytes()) else {
+        return HttpResponse::MethodNotAllowed().finish();
+    };
+    let request = PassthroughRequest {
+        method,
+        path: context
+            .upstream_path
+            .clone()
+            .unwrap_or_else(|| req.uri().path().to_string()),
+        query: req.uri().query().map(ToString::to_string),
+        headers: req
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|value| (name.to_string(), value.to_string()))
+            })
+            .collect(),
+        body: stream_request_body(payload, route.max_body_bytes(&state.config.server)),
+    };
+
+    let response = match client.forward_with_context(request, context).await {
+        Ok(response) => response,
+        Err(e) => {
+            error!(error = %e, "Passthrough request failed");
+            return HttpResponse::BadGateway().body(format!("Passthrough error: {e}"));
+        }
+    };
+
+    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::BAD_GATEWAY);
+    let mut builder = HttpResponse::build(status);
+    for (name, value) in response.headers {
+        builder.insert_header((name, value));
+    }
+    for header in &context.response_headers {
+        builder.append_header(header.clone());
+    }
+    builder.streaming(tokio_stream::wrappers::ReceiverStream::new(response.body))
+}
+
+/// Stream the request body to an upstream request without buffering it
+///
+/// The actix payload is not `Send`, so it is drained on the local task and
+/// handed to the upstream request through a channel. Bodies exceeding
+/// `limit` bytes are cut off with an error.
+fn stream_request_body(mut payload: web::Payload, limit: Option<usize>) -> reqwest::Body {
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<bytes::Bytes>>(16);
+    actix_web::rt::spawn(async move {
+        let mut length = 0;
+        while let Some(chunk) = payload.next().await {
+            let chunk = chunk.map_err(|e| std::io::Error::other(e.to_string()));
+            length += chunk.as_ref().map_or(0, Bytes::len);
+            if let Some(limit) = limit.filter(|limit| length > *limit) {
+                tx.send(Err(std::io::Error::other(BodyTooLarge { limit })))
+                    .await
+                    .ok();
+                break;
+            }
+            if tx.send(chunk).await.is_err() {
+                break;
+            }
+        }
+    });
+    reqwest::Body::wrap_stream(tokio_stream::wrappers::ReceiverStream::new(rx))
+}
+
+/// Read the body of a request on `route`, checking it against the route's limits
+#[allow(clippy::future_not_send)]
+async fn read_chat_body(
+    payload: web::Payload,
+    route: &config::RouteConfig,
+    length: Option<usize>,
+    limit: Option<usize>,
+) -> std::result::Result<Bytes, HttpResponse> {
+    let body = match read_request_body(payload, length, limit).await {
+        Ok(body) => body.freeze(),
+        Err(e) => {
+            if let Some(too_large) = e.downcast_ref::<BodyTooLarge>() {
+                return Err(too_large.response());
+            }
+            error!(error = %e, "Failed to read request body");
+            return Err(HttpResponse::BadRequest().body(format!("Invalid request body: {e}")));
+        }
+    };
+    let body = inbound::translate_request(route.api, body)?;
+    payload::check_messages(route, &body).map_or(Ok(body), Err)
 }
 
 /// Read the entire request body into a buffer
+///
+/// The buffer is sized for the `Content-Length` the request announced, if
+/// any, up to [`MAX_PREALLOCATED_BODY`], and grows as the body arrives.
+/// Reading stops with a [`BodyTooLarge`] error once the body exceeds `limit`
+/// bytes.
 #[allow(clippy::future_not_send)]
-async fn read_request_body(mut payload: web::Payload) -> Result<BytesMut> {
-    let mut body = BytesMut::new();
+async fn read_request_body(
+    mut payload: impl Stream<Item = Result<Bytes, PayloadError>> + Unpin,
+    length: Option<usize>,
+    limit: Option<usize>,
+) -> Result<BytesMut> {
+    let capacity = length.unwrap_or_default().min(MAX_PREALLOCATED_BODY);
+    let mut body = BytesMut::with_capacity(capacity);
     while let Some(chunk) = payload.next().await {
-        body.extend_from_slice(&chunk?);
+        let chunk = chunk?;
+        if let Some(limit) = limit.filter(|limit| body.len() + chunk.len() > *limit) {
+            return Err(BodyTooLarge { limit }.into());
+        }
+        body.extend_from_slice(&chunk);
     }
     Ok(body)
 }
 
-/// Get or create a pipeline for the given route
+/// Get or create a pipeline sending the requests of the given route to `llm_id`
+///
+/// This is the route's target LLM, or the LLM of a virtual model.
 async fn get_pipeline_for_route(
     state: &AppState,
     route: &config::RouteConfig,
+    llm_id: &str,
 ) -> Result<Arc<Pipeline<ChatCompletionRequest>>> {
     // Check if we already have a pipeline for this route
-    let value = state.pipelines.read().await.get(&route.path_prefix);
+    let route_id = if llm_id == route.target_llm {
+        route.id()
+    } else {
+        format!("{} -> {llm_id}", route.id())
+    };
+    let value = state.pipelines.read().await.get(&route_id);
     if let Some(pipeline) = value {
         return Ok(pipeline);
     }
 
-    // No existing pipeline - create one
-    #[cfg(feature = "openai")]
-    if let Some(llm_config) = state.config.llm.get(&route.target_llm) {
-        if llm_config.provider == "openai" {
-            let pipeline = create_openai_pipeline(llm_config, route);
+    // No existing pipeline - create one with the factory of the provider
+    if let Some(llm_config) = state.config.llm.get(llm_id) {
+        if let Some(factory) = state.provider_factories.get(&llm_config.provider) {
+            // Backends that work without a token, e.g. local servers, only
+            // get one when it is configured
+            let token_provider = if route.byok.is_some() {
+                Some(Arc::new(ClientTokenProvider) as Arc<dyn TokenProvider>)
+            } else if factory.requires_token() || llm_config.has_token() {
+                Some(get_token_provider(state, llm_id).await?)
+            } else {
+                None
+            };
+            let health_listener = state
+                .webhooks
+                .clone()
+                .map(|webhooks| webhooks as Arc<dyn HealthListener>);
+            let context = providers::create_provider_context(
+                llm_config,
+                route,
+                token_provider,
+                health_listener.as_ref(),
+            )?;
+            let url_provider = context.url_provider.clone();
+            // The route's processors run before those of the provider
+            let processors = state
+                .processor_factories
+                .create_processors(&state.config, &route.processors)?;
+            let stream_processors = state
+                .processor_factories
+                .create_stream_processors(&state.config, &route.stream_processors)?;
+            let mut pipeline = factory
+                .create_pipeline(context)
+                .await?
+                .with_processors(processors)
+                .with_stream_processors(stream_processors);
+            if let Some(retry) = route.retry(llm_config) {
+                pipeline = pipeline.with_retry_policy(providers::retry_policy(retry));
+            }
+            if let Some(scenario) = &llm_config.fault_scenario {
+                pipeline = providers::inject_faults(pipeline, scenario)?;
+            }
+            // Responses paid for with one client's key are not served to
+            // others, and every key is checked upstream
+            let cache = state
+                .response_cache
+                .as_ref()
+                .zip(state.config.cache.as_ref())
+                .filter(|_| route.byok.is_none());
+            if let Some((cache, config)) = cache {
+                let replay = stream_replay(config);
+                pipeline = pipeline.map_client(|client| {
+                    Arc::new(
+                        CachingLLMClient::new(client, cache.clone(), route_id.clone())
+                            .with_replay(replay),
+                    )
+                });
+            }
+            let pipeline = Arc::new(pipeline);
 
             // Store it in the registry
             state
                 .pipelines
                 .write()
                 .await
-                .insert(route.path_prefix.clone(), pipeline.clone());
+                .insert(route_id.clone(), pipeline.clone());
+            if let Some(url_provider) = url_provider {
+                state
+                    .url_providers
+                    .write()
+                    .await
+                    .insert(route_id, url_provider);
+            }
 
             return Ok(pipeline);
         }
     }
 
     Err(anyhow::anyhow!(
-        "No pipeline implementation available for provider: {}",
-        route.target_llm
+        "No pipeline implementation available for provider: {llm_id}"
     ))
 }
 
-#[cfg(feature = "openai")]
-fn create_openai_pipeline(
-    llm_config: &config::LLMConfig,
+/// Get or create a passthrough client for the given route
+async fn get_passthrough_client_for_route(
+    state: &AppState,
     route: &config::RouteConfig,
-) -> Arc<Pipeline<ChatCompletionRequest>> {
-    let processors = vec![];
+) -> Result<Arc<OpenAIPassthroughClient>> {
+    let route_id = route.id();
+    let value = state.passthroughs.read().await.get(&route_id).cloned();
+    if let Some(client) = value {
+        return Ok(client);
+    }
 
-    let pipeline = llm_proxy_openai::create_chat_pipeline(
-        processors,
-        Some(&llm_config.token_env),
-        Some(&llm_config.base_url),
-    );
+    #[cfg(feature = "openai")]
+    if let Some(llm_config) = state.config.llm.get(&route.target_llm) {
+        if llm_config.provider == "openai" {
+            let token_provider = if route.byok.is_some() {
+                Arc::new(ClientTokenProvider)
+            } else {
+                get_token_provider(state, &route.target_llm).await?
+            };
+            let mut client = llm_proxy_openai::create_passthrough_client(
+                token_provider,
+                Some(&llm_config.base_url),
+            );
+            if let Some(client_provider) =
+                providers::create_client_provider(llm_config, Some(route))?
+            {
+                client = client.with_client_provider(client_provider);
+            }
+            if let Some(auth) = &llm_config.auth {
+                client = client.with_auth_scheme(auth.scheme());
+            }
+            for (name, value) in route.upstream_headers(llm_config) {
+                client = client.with_header(name, value);
+            }
+            let client = Arc::new(client);
 
-    Arc::new(pipeline)
+            state
+                .passthroughs
+                .write()
+                .await
+                .insert(route_id, client.clone());
+
+            return Ok(client);
+        }
+    }
+
+    Err(anyhow::anyhow!(
+        "No passthrough implementation available for provider: {}",
+        route.target_llm
+    ))
+}
+
+/// Get or create the token provider for the given LLM
+async fn get_token_provider(state: &AppState, llm_id: &str) -> Result<Arc<dyn TokenProvider>> {
+    let value = state.token_providers.read().await.get(llm_id).cloned();
+    if let Some(provider) = value {
+        return Ok(provider);
+    }
+
+    let llm_config = state
+        .config
+        .llm
+        .get(llm_id)
+        .ok_or_else(|| anyhow::anyhow!("Unknown LLM: {llm_id}"))?;
+
+    // Keep the provider of a concurrent request that got here first, so that
+    // all routes share the same key pool state
+    let mut provider = providers::create_token_provider(llm_config).await?;
+    if let Some(overlays) = &state.overlays {
+        provider = Arc::new(TenantTokenProvider::new(llm_id, provider, overlays.clone()));
+    }
+    Ok(state
+        .token_providers
+        .write()
+        .await
+        .entry(llm_id.to_string())
+        .or_insert(provider)
+        .clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+
+    use super::*;
+
+    fn payload(
+        chunks: &'static [&'static [u8]],
+    ) -> impl Stream<Item = Result<Bytes, PayloadError>> + Unpin {
+        stream::iter(chunks.iter().map(|chunk| Ok(Bytes::from_static(chunk))))
+    }
+
+    #[actix_web::test]
+    async fn test_read_request_body() {
+        // An announced length doesn't reserve more than a small buffer
+        let body = read_request_body(
+            payload(&[b"{\"model\"", b": \"gpt-4o\"}"]),
+            Some(1 << 30),
+            None,
+        )
+        .await
+        .expect("Failed to read body");
+        assert_eq!(&body[..], b"{\"model\": \"gpt-4o\"}");
+        assert!(body.capacity() <= MAX_PREALLOCATED_BODY);
+
+        let error = read_request_body(payload(&[b"0123456789", b"0123456789"]), Some(20), Some(16))
+            .await
+            .expect_err("Read body above the limit");
+        assert!(error.downcast_ref::<BodyTooLarge>().is_some());
+    }
+
+    fn route(allowed_models: &[&str], denied_models: &[&str]) -> config::RouteConfig {
+        serde_json::from_value(serde_json::json!({
+            "path_prefix": "/v1/chat/completions",
+            "target_llm": "openai_chat",
+            "allowed_models": allowed_models,
+            "denied_models": denied_models,
+        }))
+        .expect("Invalid route")
+    }
+
+    /// The error of the 403 response for a request for `model`, if any
+    #[allow(clippy::future_not_send)]
+    async fn model_policy_error(
+        route: &config::RouteConfig,
+        tenant: Option<(&str, &config::TenantOverlay)>,
+        model: &str,
+    ) -> Option<serde_json::Value> {
+        let body = serde_json::json!({"model": model, "messages": []}).to_string();
+        let response = check_model_policy(route, tenant, body.as_bytes())?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .expect("Failed to read body");
+        let body: serde_json::Value = serde_json::from_slice(&body).expect("Invalid body");
+        Some(body["error"].clone())
+    }
+
+    #[actix_web::test]
+    async fn test_model_policy() {
+        let unrestricted = route(&[], &[]);
+        assert!(model_policy_error(&unrestricted, None, "o1")
+            .await
+            .is_none());
+
+        // Wildcards match by prefix, and the deny-list wins over the allow-list
+        let route = route(&["gpt-4o*", "o1"], &["gpt-4o-realtime*"]);
+        assert!(model_policy_error(&route, None, "gpt-4o").await.is_none());
+        assert!(model_policy_error(&route, None, "gpt-4o-mini")
+            .await
+            .is_none());
+        assert!(model_policy_error(&route, None, "o1").await.is_none());
+
+        let error = model_policy_error(&route, None, "o1-mini")
+            .await
+            .expect("Allowed a model outside the allow-list");
+        assert_eq!(error["type"], "permission_error");
+        assert_eq!(error["param"], "model");
+        assert_eq!(error["code"], "model_not_allowed");
+        assert_eq!(
+            error["message"],
+            "The model o1-mini is not allowed on route /v1/chat/completions: \
+             it only allows gpt-4o*, o1"
+        );
+
+        let error = model_policy_error(&route, None, "gpt-4o-realtime-preview")
+            .await
+            .expect("Allowed a denied model");
+        assert_eq!(error["code"], "model_not_allowed");
+        assert_eq!(
+            error["message"],
+            "The model gpt-4o-realtime-preview is not allowed on route \
+             /v1/chat/completions: it denies gpt-4o-realtime*"
+        );
+
+        // Tenants are restricted further by their overlay
+        let overlay: config::TenantOverlay =
+            serde_json::from_value(serde_json::json!({"denied_models": ["gpt-4o"]}))
+                .expect("Invalid overlay");
+        let tenant = Some(("acme", &overlay));
+        assert!(model_policy_error(&route, tenant, "gpt-4o-mini")
+            .await
+            .is_none());
+        let error = model_policy_error(&route, tenant, "gpt-4o")
+            .await
+            .expect("Allowed a model denied to the tenant");
+        assert_eq!(
+            error["message"],
+            "The model gpt-4o is not allowed for tenant acme: it denies gpt-4o"
+        );
+        let error = model_policy_error(&unrestricted, tenant, "gpt-4o").await;
+        assert!(error.is_some());
+
+        // Requests without a model are left to the pipeline
+        assert!(check_model_policy(&route, None, b"{}").is_none());
+    }
+
+    #[test]
+    fn test_request_id_or_new() {
+        assert_eq!(request_id_or_new(Some("req-1")), "req-1");
+        let long = "a".repeat(MAX_REQUEST_ID_LEN + 1);
+        for id in [
+            None,
+            Some(""),
+            Some("req 1"),
+            Some("req\u{e9}"),
+            Some(&long),
+        ] {
+            let new_id = request_id_or_new(id);
+            assert!(Uuid::parse_str(&new_id).is_ok(), "{id:?}");
+        }
+    }
+
+    /// Start an upstream answering every request with a completion, returning
+    /// its URL and the `Authorization` headers of the requests it received
+    fn upstream() -> (String, Arc<std::sync::Mutex<Vec<String>>>) {
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = received.clone();
+        let server = HttpServer::new(move || {
+            let recorded = recorded.clone();
+            App::new().default_service(web::to(move |req: HttpRequest| {
+                let authorization = req
+                    .headers()
+                    .get("authorization")
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string();
+                recorded
+                    .lock()
+                    .expect("Poisoned upstream")
+                    .push(authorization);
+                async {
+                    HttpResponse::Ok().json(serde_json::json!({
+                        "id": "chatcmpl-1",
+                        "object": "chat.completion",
+                        "created": 1,
+                        "model": "gpt-4o",
+                        "choices": [{
+                            "index": 0,
+                            "message": {"role": "assistant", "content": "Hello there"},
+                            "finish_reason": "stop",
+                        }],
+                    }))
+                }
+            }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .expect("Failed to bind upstream");
+        let address = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        (format!("http://{address}/v1/chat/completions"), received)
+    }
+
+    #[actix_web::test]
+    async fn test_byok_responses_are_not_cached() {
+        let (url, received) = upstream();
+        let config: config::Config = serde_json::from_value(serde_json::json!({
+            "llm": {
+                "openai_chat": {
+                    "provider": "openai",
+                    "type": "chat",
+                    "base_url": url,
+                    "token_env": "OPENAI_API_KEY",
+                    "supports_streaming": true,
+                },
+            },
+            "processor": {"cache": {"type": "cache_policy"}},
+            "route": [{
+                "path_prefix": "/v1/chat/completions",
+                "target_llm": "openai_chat",
+                "processors": ["cache"],
+                "byok": {},
+            }],
+            "server": {
+                "host": "127.0.0.1",
+                "port": 3000,
+                "log_level": "info",
+                "request_timeout_secs": 30,
+                "cors_allowed_origins": [],
+            },
+            "cache": {},
+        }))
+        .expect("Invalid config");
+        let state =
+            web::Data::new(AppState::new(Arc::new(config)).expect("Failed to create state"));
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(state)
+                .default_service(web::route().to(handle_request)),
+        )
+        .await;
+
+        for key in ["sk-client-a", "sk-client-b"] {
+            let request = actix_web::test::TestRequest::post()
+                .uri("/v1/chat/completions")
+                .insert_header(("authorization", format!("Bearer {key}")))
+                .set_json(serde_json::json!({
+                    "model": "gpt-4o",
+                    "messages": [{"role": "user", "content": "Hi"}],
+                }))
+                .to_request();
+            let response = actix_web::test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::OK, "{key}");
+            let body = actix_web::test::read_body(response).await;
+            assert!(String::from_utf8_lossy(&body).contains("Hello there"));
+        }
+        assert_eq!(
+            *received.lock().expect("Poisoned upstream"),
+            ["Bearer sk-client-a", "Bearer sk-client-b"]
+        );
+    }
+
+    #[test]
+    fn test_reserved_forward_headers_fail_startup() {
+        let config: config::Config = serde_json::from_value(serde_json::json!({
+            "llm": {},
+            "processor": {},
+            "route": [{
+                "path_prefix": "/v1/chat/completions",
+                "target_llm": "openai_chat",
+                "forward_headers": ["traceparent", "authorization"],
+            }],
+            "server": {
+                "host": "127.0.0.1",
+                "port": 3000,
+                "log_level": "info",
+                "request_timeout_secs": 30,
+                "cors_allowed_origins": [],
+            },
+        }))
+        .expect("Invalid config");
+        let Err(error) = AppState::new(Arc::new(config)) else {
+            panic!("Started with a reserved forwarded header");
+        };
+        assert_eq!(
+            format!("{error:#}"),
+            "Invalid route /v1/chat/completions: The authorization header is set by the \
+             proxy and can't be forwarded"
+        );
+    }
+
+    #[actix_web::test]
+    #[allow(clippy::future_not_send)]
+    async fn test_virtual_models_resolve_to_allowed_models() {
+        let (url, received) = upstream();
+        std::env::set_var("LLM_PROXY_TEST_VIRTUAL_MODEL_KEY", "sk-proxy");
+        let config: config::Config = serde_json::from_value(serde_json::json!({
+            "llm": {
+                "openai_chat": {
+                    "provider": "openai",
+                    "type": "chat",
+                    "base_url": url,
+                    "token_env": "LLM_PROXY_TEST_VIRTUAL_MODEL_KEY",
+                    "supports_streaming": true,
+                },
+            },
+            "model": {
+                "acme-fast": {"target_llm": "openai_chat", "model": "gpt-4o-mini"},
+                "acme-smart": {"target_llm": "openai_chat", "model": "gpt-4o"},
+            },
+            "processor": {},
+            "route": [{
+                "path_prefix": "/v1/chat/completions",
+                "target_llm": "openai_chat",
+                "allowed_models": ["gpt-4o-mini", "acme-*"],
+            }],
+            "server": {
+                "host": "127.0.0.1",
+                "port": 3000,
+                "log_level": "info",
+                "request_timeout_secs": 30,
+                "cors_allowed_origins": [],
+            },
+        }))
+        .expect("Invalid config");
+        let state =
+            web::Data::new(AppState::new(Arc::new(config)).expect("Failed to create state"));
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(state)
+                .default_service(web::route().to(handle_request)),
+        )
+        .await;
+
+        for (model, status) in [
+            ("acme-fast", StatusCode::OK),
+            ("acme-smart", StatusCode::FORBIDDEN),
+        ] {
+            let request = actix_web::test::TestRequest::post()
+                .uri("/v1/chat/completions")
+                .set_json(serde_json::json!({
+                    "model": model,
+                    "messages": [{"role": "user", "content": "Hi"}],
+                }))
+                .to_request();
+            let response = actix_web::test::call_service(&app, request).await;
+            assert_eq!(response.status(), status, "{model}");
+        }
+        // The virtual model's name doesn't unlock a denied model upstream
+        assert_eq!(received.lock().expect("Poisoned upstream").len(), 1);
+    }
+
+    /// Send `request` on a new connection to the server at `address` and
+    /// read what it answers until it closes the connection
+    async fn exchange(address: std::net::SocketAddr, request: &[u8]) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = tokio::net::TcpStream::connect(address)
+            .await
+            .expect("Failed to connect");
+        stream.write_all(request).await.expect("Failed to send");
+        let mut response = Vec::new();
+        tokio::time::timeout(Duration::from_secs(10), stream.read_to_end(&mut response))
+            .await
+            .expect("The connection was kept open")
+            .expect("Failed to read");
+        String::from_utf8_lossy(&response).to_lowercase()
+    }
+
+    #[actix_web::test]
+    async fn test_server_tuning() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("No free port")
+            .port();
+        let config: config::Config = serde_json::from_value(serde_json::json!({
+            "llm": {},
+            "processor": {},
+            "route": [],
+            "server": {
+                "host": "127.0.0.1",
+                "port": port,
+                "log_level": "info",
+                "request_timeout_secs": 30,
+                "cors_allowed_origins": [],
+                "workers": 1,
+                "keep_alive_secs": 0,
+                "client_request_timeout_secs": 1,
+            },
+        }))
+        .expect("Invalid config");
+        actix_web::rt::spawn(run_server(config));
+        let address = std::net::SocketAddr::from(([127, 0, 0, 1], port));
+        for _ in 0..50 {
+            if tokio::net::TcpStream::connect(address).await.is_ok() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        // Without keep-alive, connections are closed after each response
+        let response = exchange(
+            address,
+            b"GET /v1/models HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("http/1.1 "), "{response}");
+        assert!(response.contains("connection: close"), "{response}");
+
+        // Clients that don't send the head of their request in time are dropped
+        let started = std::time::Instant::now();
+        let response = exchange(address, b"GET /v1/models HTTP/1.1\r\n").await;
+        assert!(response.starts_with("http/1.1 408"), "{response}");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
 }
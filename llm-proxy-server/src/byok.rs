@@ -0,0 +1,141 @@
+//! Routes sending clients' own API keys upstream.
+//!
+//! A route with `byok` sends requests upstream with the key the client put in
+//! its `Authorization` header instead of the proxy's key for the LLM, so
+//! usage is billed to the client's account while the route's processors,
+//! logging and limits still apply. With a `key_pattern`, requests whose key
+//! doesn't match it are refused with a 401 before anything is sent upstream.
+
+use actix_web::{http::header::AUTHORIZATION, HttpResponse};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use llm_proxy_core::{Error, RequestContext, TokenProvider};
+use regex::Regex;
+use serde_json::json;
+
+use crate::config::RouteConfig;
+
+/// Check of the keys clients send on a route with `byok`
+#[derive(Debug, Clone)]
+pub struct ClientKeyCheck {
+    pattern: Option<Regex>,
+}
+
+impl ClientKeyCheck {
+    /// The check of `route`'s client keys, if it has `byok`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the route's `key_pattern` is
+    /// not a valid regular expression.
+    pub fn new(route: &RouteConfig) -> Result<Option<Self>> {
+        let Some(byok) = &route.byok else {
+            return Ok(None);
+        };
+        let pattern = byok
+            .key_pattern
+            .as_deref()
+            .map(|pattern| Regex::new(&format!("^(?:{pattern})$")))
+            .transpose()
+            .context("Invalid key_pattern")?;
+        Ok(Some(Self { pattern }))
+    }
+
+    /// A 401 response for requests without a key, or with one not matching the pattern
+    #[must_use]
+    pub fn check(&self, context: &RequestContext) -> Option<HttpResponse> {
+        let Some(key) = client_key(context) else {
+            return Some(reject(
+                "Missing API key, send your own key as a bearer token",
+            ));
+        };
+        let pattern = self.pattern.as_ref()?;
+        (!pattern.is_match(key)).then(|| reject("The API key doesn't look like a valid key"))
+    }
+}
+
+/// Token provider handing out the key the client sent with its request
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClientTokenProvider;
+
+#[async_trait]
+impl TokenProvider for ClientTokenProvider {
+    async fn get_token(&self) -> llm_proxy_core::Result<String> {
+        Err(Error::ConfigError(
+            "Routes with byok only have the keys of requests".to_string(),
+        ))
+    }
+
+    async fn get_token_for(&self, context: &RequestContext) -> llm_proxy_core::Result<String> {
+        client_key(context)
+            .map(ToString::to_string)
+            .ok_or_else(|| Error::InvalidRequest("Missing API key".to_string()))
+    }
+}
+
+/// The bearer token of the request's `Authorization` header, or its `x-api-key`
+fn client_key(context: &RequestContext) -> Option<&str> {
+    context
+        .header(AUTHORIZATION.as_str())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| context.header("x-api-key"))
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+}
+
+fn reject(message: &str) -> HttpResponse {
+    HttpResponse::Unauthorized().json(json!({
+        "error": {
+            "message": message,
+            "type": "invalid_request_error",
+            "param": null,
+            "code": "invalid_api_key",
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(key_pattern: Option<&str>) -> RouteConfig {
+        serde_json::from_value(json!({
+            "path_prefix": "/v1/chat/completions",
+            "target_llm": "openai_chat",
+            "byok": { "key_pattern": key_pattern },
+        }))
+        .expect("Invalid route")
+    }
+
+    #[tokio::test]
+    async fn test_client_keys() {
+        let check = ClientKeyCheck::new(&route(Some("sk-[A-Za-z0-9]{8,}")))
+            .expect("Invalid pattern")
+            .expect("No byok");
+        let context = RequestContext::new().with_header("Authorization", "Bearer sk-abcd1234");
+        assert!(check.check(&context).is_none());
+        assert_eq!(
+            ClientTokenProvider
+                .get_token_for(&context)
+                .await
+                .expect("No key"),
+            "sk-abcd1234"
+        );
+
+        let context = RequestContext::new().with_header("Authorization", "Bearer pk-abcd1234");
+        let response = check.check(&context).expect("Not refused");
+        assert_eq!(response.status(), 401);
+        let context = RequestContext::new();
+        assert!(check.check(&context).is_some());
+        assert!(ClientTokenProvider.get_token_for(&context).await.is_err());
+
+        // Without a pattern any key is sent upstream
+        let check = ClientKeyCheck::new(&route(None))
+            .expect("Invalid pattern")
+            .expect("No byok");
+        let context = RequestContext::new().with_header("Authorization", "Bearer anything");
+        assert!(check.check(&context).is_none());
+
+        assert!(ClientKeyCheck::new(&route(Some("("))).is_err());
+    }
+}
@@ -0,0 +1,633 @@
+//! Validation of a configuration before it is deployed.
+//!
+//! The server builds pipelines, token providers and processors lazily, on the
+//! first request of each route, so a broken configuration may only surface
+//! in production. [`check_config`] builds or resolves all of them up front
+//! and reports every problem it finds, for `--check-config` runs in CI.
+
+use std::{fmt, path::Path};
+
+use anyhow::Context;
+use reqwest::Url;
+
+use crate::{
+    byok,
+    config::{ApiFormat, Config, DiscoverySourceConfig, LLMConfig, RouteConfig},
+    events, mcp, processors, providers, routing, split, tenancy, tools,
+};
+
+/// Providers sending requests to the upstream path of routes with
+/// `rewrite_path`, with the headers of their `forward_headers`
+const OPENAI_CLIENT_PROVIDERS: &[&str] =
+    &["openai", "openai_compatible", "azure_openai", "openrouter"];
+
+/// Problems found in one section of a configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionReport {
+    /// Name of the section, e.g. `llm.openai_chat`
+    pub section: String,
+    /// What is wrong with the section; empty if nothing is
+    pub problems: Vec<String>,
+}
+
+/// Outcome of [`check_config`], with a line per section when displayed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    /// The checked sections, in the order they were checked
+    pub sections: Vec<SectionReport>,
+}
+
+impl CheckReport {
+    /// Whether no section has a problem
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.sections
+            .iter()
+            .all(|section| section.problems.is_empty())
+    }
+
+    fn add(&mut self, section: String, problems: Vec<String>) {
+        self.sections.push(SectionReport { section, problems });
+    }
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for section in &self.sections {
+            if section.problems.is_empty() {
+                writeln!(f, "ok     {}", section.section)?;
+                continue;
+            }
+            writeln!(f, "error  {}", section.section)?;
+            for problem in &section.problems {
+                writeln!(f, "         - {problem}")?;
+            }
+        }
+        let failed = self
+            .sections
+            .iter()
+            .filter(|section| !section.problems.is_empty())
+            .count();
+        writeln!(
+            f,
+            "{} sections checked, {failed} with problems",
+            self.sections.len()
+        )
+    }
+}
+
+/// Check that every part of `config` can be set up.
+///
+/// Routes must have valid match conditions and distinct IDs, target
+/// configured LLMs and reference processors of the right kind with valid
+/// settings; LLMs must use a provider built into the server, have URLs that
+/// parse and tokens that resolve; processors must have a known type; virtual
+/// models must target a configured LLM serving their model; client, tenant
+/// and admin keys must be set. Tokens are fetched from their sources, so this must run
+/// where the server would.
+pub async fn check_config(config: &Config) -> CheckReport {
+    let mut report = CheckReport::default();
+
+    let provider_factories = providers::create_provider_registry();
+    let mut llms: Vec<_> = config.llm.iter().collect();
+    llms.sort_by_key(|(id, _)| *id);
+    for (id, llm_config) in llms {
+        let mut problems = Vec::new();
+        match provider_factories.get(&llm_config.provider) {
+            Some(factory) => {
+                if factory.requires_token() || llm_config.has_token() {
+                    if let Err(e) = check_token(llm_config).await {
+                        problems.push(format!("{e:#}"));
+                    }
+                }
+            }
+            None => problems.push(format!(
+                "Unknown provider {}, known providers are {}",
+                llm_config.provider,
+                provider_factories.names().join(", ")
+            )),
+        }
+        problems.extend(
+            llm_urls(llm_config)
+                .into_iter()
+                .filter_map(|(name, url)| check_url(name, url)),
+        );
+        if let Err(e) = providers::create_client_provider(llm_config, None) {
+            problems.push(format!("{e:#}"));
+        }
+        if let Some(scenario) = &llm_config.fault_scenario {
+            if let Err(e) = providers::check_fault_scenario(scenario) {
+                problems.push(format!("{e:#}"));
+            }
+        }
+        report.add(format!("llm.{id}"), problems);
+    }
+
+    let processor_factories = check_processors(config, &mut report);
+
+    let mut mcp_servers: Vec<_> = config.mcp.iter().collect();
+    mcp_servers.sort_by_key(|(name, _)| *name);
+    for (name, server) in mcp_servers {
+        let problems = mcp::McpServer::new(name, server)
+            .err()
+            .map(|e| format!("{e:#}"))
+            .into_iter()
+            .collect();
+        report.add(format!("mcp.{name}"), problems);
+    }
+    let mut tools: Vec<_> = config.tool.iter().collect();
+    tools.sort_by_key(|(name, _)| *name);
+    for (name, tool) in tools {
+        let problems = tools::create_tool_executor(name, tool)
+            .err()
+            .map(|e| format!("{e:#}"))
+            .into_iter()
+            .collect();
+        report.add(format!("tool.{name}"), problems);
+    }
+
+    let mut conditions_ok = true;
+    for route in &config.route {
+        let mut problems = Vec::new();
+        if let Err(e) = routing::Router::new(std::slice::from_ref(route)) {
+            conditions_ok = false;
+            problems.push(format!("{e:#}"));
+        }
+        problems.extend(check_route(config, route, &processor_factories));
+        report.add(format!("route {}", route.id()), problems);
+    }
+    // Invalid match conditions are reported above, this finds duplicate IDs
+    if conditions_ok {
+        if let Err(e) = routing::Router::new(&config.route) {
+            report.add("routes".to_string(), vec![format!("{e:#}")]);
+        }
+    }
+
+    let mut virtual_models: Vec<_> = config.model.iter().collect();
+    virtual_models.sort_by_key(|(name, _)| *name);
+    for (name, preset) in virtual_models {
+        let problems = match config.llm.get(&preset.target_llm) {
+            None => vec![format!("Unknown target_llm {}", preset.target_llm)],
+            Some(llm) if !llm.models.is_empty() && !llm.models.contains(&preset.model) => {
+                vec![format!(
+                    "Model {} is not among the models of {}",
+                    preset.model, preset.target_llm
+                )]
+            }
+            Some(_) => Vec::new(),
+        };
+        report.add(format!("model.{name}"), problems);
+    }
+
+    check_clients(config, &mut report);
+    check_tenancy(config, &mut report);
+
+    check_services(config, &mut report);
+
+    report
+}
+
+/// Check the plugins and processors, returning the processor factories with
+/// those of the plugins that loaded
+fn check_processors(config: &Config, report: &mut CheckReport) -> processors::ProcessorRegistry {
+    let mut processor_factories = processors::create_processor_registry(config);
+    if let Some(plugins) = &config.plugins {
+        let problems = processors::register_plugins(&mut processor_factories, plugins)
+            .err()
+            .map(|e| format!("{e:#}"))
+            .into_iter()
+            .collect();
+        report.add("plugins".to_string(), problems);
+    }
+    let mut processor_configs: Vec<_> = config.processor.iter().collect();
+    processor_configs.sort_by_key(|(id, _)| *id);
+    for (id, processor_config) in processor_configs {
+        let processor_type = &processor_config.processor_type;
+        let known = processor_factories.get(processor_type).is_some()
+            || processor_factories.get_stream(processor_type).is_some();
+        let problems = if known {
+            Vec::new()
+        } else {
+            vec![format!("Unknown processor type {processor_type}")]
+        };
+        report.add(format!("processor.{id}"), problems);
+    }
+    processor_factories
+}
+
+/// Check the admin API, gRPC interface, trace export, event publishing and webhooks
+fn check_services(config: &Config, report: &mut CheckReport) {
+    if let Some(admin) = &config.admin {
+        let problems = std::env::var(&admin.token_env)
+            .err()
+            .map(|_| format!("Token variable {} is not set", admin.token_env))
+            .into_iter()
+            .collect();
+        report.add("admin".to_string(), problems);
+    }
+
+    if let Some(grpc) = &config.grpc {
+        let mut problems = Vec::new();
+        if !cfg!(feature = "grpc") {
+            problems.push(
+                "The [grpc] section needs the server built with the grpc feature".to_string(),
+            );
+        }
+        if grpc.port == config.server.port {
+            problems.push(format!("Port {} is the HTTP server's", grpc.port));
+        }
+        report.add("grpc".to_string(), problems);
+    }
+
+    if let Some(telemetry) = &config.telemetry {
+        let problems = check_url("otlp_endpoint", &telemetry.otlp_endpoint)
+            .into_iter()
+            .collect();
+        report.add("telemetry".to_string(), problems);
+    }
+
+    if let Some(events) = &config.events {
+        let problems = events::Events::from_config(events)
+            .err()
+            .map(|e| format!("{e:#}"))
+            .into_iter()
+            .collect();
+        report.add("events".to_string(), problems);
+    }
+
+    for (name, webhook) in &config.webhook {
+        let problems = match (&webhook.url, &webhook.url_env) {
+            (Some(url), None) => check_url("url", url).into_iter().collect(),
+            (None, Some(url_env)) => std::env::var(url_env)
+                .err()
+                .map(|_| format!("URL variable {url_env} is not set"))
+                .into_iter()
+                .collect(),
+            _ => vec!["Needs either a url or a url_env".to_string()],
+        };
+        report.add(format!("webhook.{name}"), problems);
+    }
+}
+
+/// Check the overlay files of the tenants
+fn check_tenancy(config: &Config, report: &mut CheckReport) {
+    let Some(dir) = config
+        .tenancy
+        .as_ref()
+        .and_then(|tenancy| tenancy.overlay_dir.as_deref())
+    else {
+        return;
+    };
+    let files = match tenancy::overlay_files(Path::new(dir)) {
+        Ok(files) => files,
+        Err(e) => {
+            report.add("tenancy".to_string(), vec![format!("{e:#}")]);
+            return;
+        }
+    };
+    let mut files: Vec<_> = files.into_iter().collect();
+    files.sort();
+    for (tenant, path) in files {
+        let problems = tenancy::read_overlay(&path, config)
+            .err()
+            .map(|e| format!("{e:#}"))
+            .into_iter()
+            .collect();
+        report.add(format!("tenant overlay {tenant}"), problems);
+    }
+}
+
+/// Check that the client key store opens and tenants have the keys they need
+fn check_clients(config: &Config, report: &mut CheckReport) {
+    if let Some(client_keys) = &config.client_keys {
+        let problems = providers::create_client_key_store(client_keys)
+            .err()
+            .map(|e| format!("{e:#}"))
+            .into_iter()
+            .collect();
+        report.add("client_keys".to_string(), problems);
+    }
+    if config.quota.is_some() {
+        // Quotas are kept per client key
+        let problems = config
+            .client_keys
+            .is_none()
+            .then(|| "quota needs client_keys to count requests by".to_string())
+            .into_iter()
+            .collect();
+        report.add("quota".to_string(), problems);
+    }
+
+    for tenant in &config.tenant {
+        // Tenants only need keys of their own without client keys
+        let problems = match (&tenant.key_env, &config.client_keys) {
+            (_, Some(_)) => Vec::new(),
+            (Some(key_env), None) => std::env::var(key_env)
+                .err()
+                .map(|_| format!("Key variable {key_env} is not set"))
+                .into_iter()
+                .collect(),
+            (None, None) => vec!["key_env is needed unless client_keys are configured".to_string()],
+        };
+        report.add(format!("tenant {}", tenant.id), problems);
+    }
+}
+
+/// Resolve the token of an LLM, as its first request would
+async fn check_token(llm_config: &LLMConfig) -> anyhow::Result<()> {
+    let provider = providers::create_token_provider(llm_config).await?;
+    provider
+        .get_token()
+        .await
+        .context("Failed to resolve the API token")?;
+    Ok(())
+}
+
+/// The URLs configured for an LLM, with the name of their field
+fn llm_urls(llm_config: &LLMConfig) -> Vec<(&'static str, &str)> {
+    let mut urls = vec![("base_url", llm_config.base_url.as_str())];
+    urls.extend(
+        llm_config
+            .endpoints
+            .iter()
+            .map(|endpoint| ("endpoints.url", endpoint.url.as_str())),
+    );
+    if let Some(proxy) = &llm_config.proxy {
+        urls.push(("proxy.url", &proxy.url));
+    }
+    if let Some(discovery) = &llm_config.discovery {
+        match &discovery.source {
+            DiscoverySourceConfig::Consul { address, .. } => {
+                urls.push(("discovery.address", address));
+            }
+            #[cfg(feature = "dns")]
+            DiscoverySourceConfig::DnsSrv { .. } => {}
+        }
+    }
+    urls
+}
+
+/// A problem with the URL in field `name`, if it doesn't parse
+fn check_url(name: &str, url: &str) -> Option<String> {
+    Url::parse(url)
+        .err()
+        .map(|e| format!("Invalid {name} {url}: {e}"))
+}
+
+/// Problems with the `byok` settings of a route
+fn check_byok(config: &Config, route: &RouteConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+    if route.byok.is_none() {
+        return problems;
+    }
+    if let Err(e) = byok::ClientKeyCheck::new(route) {
+        problems.push(format!("{e:#}"));
+    }
+    if config.client_keys.is_some() || !config.tenant.is_empty() {
+        problems.push(
+            "byok doesn't work with client keys or tenants, which use the Authorization header"
+                .to_string(),
+        );
+    }
+    let cache_policy = route.processors.iter().any(|id| {
+        config
+            .processor
+            .get(id)
+            .is_some_and(|processor| processor.processor_type == "cache_policy")
+    });
+    if cache_policy {
+        problems.push(
+            "Responses of byok routes are never cached, drop the cache_policy processor"
+                .to_string(),
+        );
+    }
+    problems
+}
+
+/// Problems with the target and processors of a route
+fn check_route(
+    config: &Config,
+    route: &RouteConfig,
+    processor_factories: &processors::ProcessorRegistry,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Err(e) = split::TrafficSplit::new(route) {
+        problems.push(format!("{e:#}"));
+    }
+    problems.extend(check_byok(config, route));
+    // Routes without a target are reported above
+    for llm_id in route.target_llms().into_iter().filter(|id| !id.is_empty()) {
+        match config.llm.get(llm_id) {
+            Some(llm_config) if route.passthrough && llm_config.provider != "openai" => {
+                problems.push(format!(
+                    "Passthrough routes need an openai LLM, {llm_id} uses {}",
+                    llm_config.provider
+                ));
+            }
+            Some(llm_config) => {
+                let openai_client = OPENAI_CLIENT_PROVIDERS.contains(&llm_config.provider.as_str());
+                if route.rewrite_path.is_some() && !openai_client {
+                    problems.push(format!(
+                        "rewrite_path needs an OpenAI-compatible LLM, {llm_id} uses {}",
+                        llm_config.provider
+                    ));
+                }
+                if !route.forward_headers.is_empty() && !openai_client {
+                    problems.push(format!(
+                        "forward_headers needs an OpenAI-compatible LLM, {llm_id} uses {}",
+                        llm_config.provider
+                    ));
+                }
+                if route.client.is_some() || route.proxy.is_some() {
+                    if let Err(e) = providers::create_client_provider(llm_config, Some(route)) {
+                        problems.push(format!("{e:#}"));
+                    }
+                }
+            }
+            None => problems.push(format!("Unknown target LLM {llm_id}")),
+        }
+    }
+    for header in route
+        .forward_headers
+        .iter()
+        .filter(|header| header.is_reserved())
+    {
+        problems.push(format!(
+            "The {} header is set by the proxy and can't be forwarded",
+            header.upstream_name()
+        ));
+    }
+    if route.passthrough && route.api != ApiFormat::OpenAI {
+        problems.push("Passthrough routes forward requests untranslated, drop api".to_string());
+    }
+    if route.websocket.is_some() && (route.passthrough || route.api != ApiFormat::OpenAI) {
+        problems.push("websocket only works on OpenAI chat routes".to_string());
+    }
+    if route.passthrough && (route.mcp.is_some() || !route.tools.is_empty()) {
+        problems.push("mcp and tools don't apply to passthrough routes".to_string());
+    }
+    for name in route.mcp.iter().flat_map(|mcp| &mcp.servers) {
+        if !config.mcp.contains_key(name) {
+            problems.push(format!("Unknown MCP server {name}"));
+        }
+    }
+    for name in &route.tools {
+        if !config.tool.contains_key(name) {
+            problems.push(format!("Unknown tool {name}"));
+        }
+    }
+    let api_enabled = match route.api {
+        ApiFormat::OpenAI => true,
+        ApiFormat::Anthropic => cfg!(feature = "anthropic"),
+        ApiFormat::Ollama => cfg!(feature = "ollama"),
+    };
+    if !api_enabled {
+        let api = route.api.name();
+        problems.push(format!("api = \"{api}\" needs the {api} feature"));
+    }
+    if route.passthrough && !(route.allowed_models.is_empty() && route.denied_models.is_empty()) {
+        problems
+            .push("allowed_models and denied_models don't apply to passthrough routes".to_string());
+    }
+    if let Some(proxy) = &route.proxy {
+        problems.extend(check_url("proxy.url", &proxy.url));
+    }
+    if let Err(e) = processor_factories.create_processors(config, &route.processors) {
+        problems.push(format!("{e:#}"));
+    }
+    if let Err(e) = processor_factories.create_stream_processors(config, &route.stream_processors) {
+        problems.push(format!("{e:#}"));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn config(llm: &Value, route: &Value) -> Config {
+        serde_json::from_value(json!({
+            "llm": {"openai_chat": llm},
+            "processor": {
+                "logger": {"type": "logger", "config_value": "info"},
+            },
+            "route": [route],
+            "server": {
+                "host": "127.0.0.1",
+                "port": 3000,
+                "log_level": "info",
+                "request_timeout_secs": 30,
+                "cors_allowed_origins": [],
+            },
+        }))
+        .expect("Invalid config")
+    }
+
+    fn llm(token_env: &str, base_url: &str) -> Value {
+        json!({
+            "provider": "openai",
+            "type": "chat",
+            "base_url": base_url,
+            "token_env": token_env,
+            "supports_streaming": true,
+        })
+    }
+
+    fn route(target_llm: &str, processors: &[&str]) -> Value {
+        json!({
+            "path_prefix": "/v1/chat/completions",
+            "target_llm": target_llm,
+            "processors": processors,
+        })
+    }
+
+    fn problems<'a>(report: &'a CheckReport, section: &str) -> &'a [String] {
+        &report
+            .sections
+            .iter()
+            .find(|report| report.section == section)
+            .unwrap_or_else(|| panic!("No section {section} in {report}"))
+            .problems
+    }
+
+    #[tokio::test]
+    async fn test_clean_config() {
+        std::env::set_var("LLM_PROXY_TEST_CHECK_KEY", "sk-check");
+        let config = config(
+            &llm("LLM_PROXY_TEST_CHECK_KEY", "https://api.openai.com/v1"),
+            &route("openai_chat", &["logger"]),
+        );
+        let report = check_config(&config).await;
+        assert!(report.is_ok(), "{report}");
+        assert!(report.to_string().ends_with("0 with problems\n"));
+    }
+
+    #[tokio::test]
+    async fn test_reports_broken_references() {
+        std::env::set_var("LLM_PROXY_TEST_CHECK_KEY", "sk-check");
+        let llm = llm("LLM_PROXY_TEST_CHECK_KEY", "https://api.openai.com/v1");
+
+        let report = check_config(&config(&llm, &route("anthropic_chat", &[]))).await;
+        assert!(!report.is_ok());
+        assert!(problems(&report, "route /v1/chat/completions")
+            .contains(&"Unknown target LLM anthropic_chat".to_string()));
+
+        let report = check_config(&config(&llm, &route("openai_chat", &["unknown"]))).await;
+        assert!(!report.is_ok());
+        let route_problems = problems(&report, "route /v1/chat/completions");
+        assert!(
+            route_problems
+                .iter()
+                .any(|problem| problem.contains("unknown")),
+            "{route_problems:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reports_broken_llms() {
+        let config = config(
+            &llm("LLM_PROXY_TEST_CHECK_UNSET_KEY", "api.openai.com/v1"),
+            &route("openai_chat", &[]),
+        );
+        let report = check_config(&config).await;
+        assert!(!report.is_ok());
+        let llm_problems = problems(&report, "llm.openai_chat");
+        assert!(
+            llm_problems
+                .iter()
+                .any(|problem| problem.contains("LLM_PROXY_TEST_CHECK_UNSET_KEY")),
+            "{llm_problems:?}"
+        );
+        assert!(
+            llm_problems
+                .iter()
+                .any(|problem| problem.starts_with("Invalid base_url api.openai.com/v1")),
+            "{llm_problems:?}"
+        );
+        assert!(report.to_string().contains("error  llm.openai_chat"));
+    }
+
+    #[tokio::test]
+    async fn test_reports_cached_byok_routes() {
+        let mut config = config(
+            &llm("LLM_PROXY_TEST_CHECK_KEY", "https://api.openai.com/v1"),
+            &json!({
+                "path_prefix": "/v1/chat/completions",
+                "target_llm": "openai_chat",
+                "processors": ["cache"],
+                "byok": {},
+            }),
+        );
+        config.processor.insert(
+            "cache".to_string(),
+            serde_json::from_value(json!({"type": "cache_policy"})).expect("Invalid processor"),
+        );
+        let report = check_config(&config).await;
+        assert!(problems(&report, "route /v1/chat/completions").contains(
+            &"Responses of byok routes are never cached, drop the cache_policy processor"
+                .to_string()
+        ));
+    }
+}
@@ -0,0 +1,281 @@
+//! Authentication of clients with proxy-issued keys.
+//!
+//! With a `[client_keys]` section, every request outside the admin API must
+//! carry a key issued by the proxy as a bearer token, e.g.
+//! `Authorization: Bearer pk-...`, or in an `x-api-key` header as Anthropic
+//! SDKs send it. Keys are looked up in the configured
+//! [`ClientKeyStore`](llm_proxy_core::ClientKeyStore), and the identity of
+//! the key is attached to the request as a [`ClientKey`] extension. The
+//! request context then records the key's ID and, for keys belonging to a
+//! tenant, the tenant with its configured attributes. Requests without a
+//! known key are refused with a 401 and an OpenAI-style error.
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderMap, HeaderName, AUTHORIZATION},
+    middleware::Next,
+    web, HttpMessage, HttpResponse,
+};
+use llm_proxy_core::ClientKey;
+use serde_json::json;
+use tracing::error;
+
+use crate::app::AppState;
+
+/// Header of the API key in Anthropic's API
+const X_API_KEY: HeaderName = HeaderName::from_static("x-api-key");
+
+/// Reject requests without a known client key, and attach the key's identity to the others
+///
+/// # Errors
+///
+/// This function will return an error if the wrapped service fails.
+#[allow(clippy::future_not_send)]
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    // The admin API has a token of its own
+    let admin_path = req.path() == "/admin" || req.path().starts_with("/admin/");
+    let store = req
+        .app_data::<web::Data<AppState>>()
+        .filter(|state| !(admin_path && state.admin_token.is_some()))
+        .and_then(|state| state.client_keys.clone());
+    let Some(store) = store else {
+        return next
+            .call(req)
+            .await
+            .map(ServiceResponse::map_into_left_body);
+    };
+
+    let Some(key) = request_key(req.headers()) else {
+        return Ok(reject(
+            req,
+            "Missing API key, send a proxy-issued key as a bearer token",
+        ));
+    };
+    match store.lookup(key).await {
+        Ok(Some(identity)) => {
+            req.extensions_mut().insert(identity);
+            next.call(req)
+                .await
+                .map(ServiceResponse::map_into_left_body)
+        }
+        Ok(None) => Ok(reject(req, "Invalid API key")),
+        Err(e) => {
+            error!(error = %e, "Failed to look up client key");
+            let response = HttpResponse::InternalServerError().json(json!({
+                "error": {
+                    "message": "Failed to check the API key",
+                    "type": "server_error",
+                    "param": null,
+                    "code": null,
+                }
+            }));
+            Ok(req.into_response(response).map_into_right_body())
+        }
+    }
+}
+
+/// The key of a request's bearer token, or of its `x-api-key` header
+#[must_use]
+pub fn request_key(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| headers.get(X_API_KEY)?.to_str().ok())
+}
+
+/// The identity of the client key `req` was authenticated with, if any
+#[must_use]
+pub fn client_key(req: &actix_web::HttpRequest) -> Option<ClientKey> {
+    req.extensions().get::<ClientKey>().cloned()
+}
+
+/// Refuse `req` with a 401 in the format of the `OpenAI` API's errors
+fn reject<B>(
+    req: ServiceRequest,
+    message: &str,
+) -> ServiceResponse<actix_web::body::EitherBody<B>> {
+    let response = HttpResponse::Unauthorized().json(json!({
+        "error": {
+            "message": message,
+            "type": "invalid_request_error",
+            "param": null,
+            "code": "invalid_api_key",
+        }
+    }));
+    req.into_response(response).map_into_right_body()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex, PoisonError},
+    };
+
+    use actix_web::{http::StatusCode, middleware::from_fn, test, App, HttpRequest};
+    use async_trait::async_trait;
+    use llm_proxy_core::ClientKeyStore;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::config::Config;
+
+    /// Store whose keys can be revoked while the server runs
+    #[derive(Default)]
+    struct RevocableKeys(Mutex<HashMap<String, ClientKey>>);
+
+    impl RevocableKeys {
+        fn revoke(&self, key: &str) {
+            self.0
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(key);
+        }
+    }
+
+    #[async_trait]
+    impl ClientKeyStore for RevocableKeys {
+        async fn lookup(&self, key: &str) -> llm_proxy_core::Result<Option<ClientKey>> {
+            Ok(self
+                .0
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(key)
+                .cloned())
+        }
+    }
+
+    /// State with the keys of `store`, and an admin token if `admin`
+    fn state(store: Arc<RevocableKeys>, admin: bool) -> web::Data<AppState> {
+        let mut config = json!({
+            "llm": {},
+            "processor": {},
+            "route": [],
+            "server": {
+                "host": "127.0.0.1",
+                "port": 3000,
+                "log_level": "info",
+                "request_timeout_secs": 30,
+                "cors_allowed_origins": [],
+            },
+        });
+        if admin {
+            std::env::set_var("LLM_PROXY_TEST_AUTH_ADMIN_TOKEN", "admin-secret");
+            config["admin"] = json!({"token_env": "LLM_PROXY_TEST_AUTH_ADMIN_TOKEN"});
+        }
+        let config: Config = serde_json::from_value(config).expect("Invalid config");
+        let mut state = AppState::new(Arc::new(config)).expect("Failed to create state");
+        state.client_keys = Some(store);
+        web::Data::new(state)
+    }
+
+    /// The ID of the key the request was authenticated with
+    #[allow(clippy::future_not_send)]
+    async fn key_id(req: HttpRequest) -> HttpResponse {
+        HttpResponse::Ok().body(client_key(&req).map(|key| key.id).unwrap_or_default())
+    }
+
+    fn store() -> Arc<RevocableKeys> {
+        let store = RevocableKeys::default();
+        store.0.lock().expect("Poisoned store").insert(
+            "pk-ci".to_string(),
+            ClientKey {
+                id: "ci-bot".to_string(),
+                tenant: None,
+            },
+        );
+        Arc::new(store)
+    }
+
+    #[actix_web::test]
+    async fn test_authenticate() {
+        let store = store();
+        let app = test::init_service(
+            App::new()
+                .app_data(state(store.clone(), false))
+                .wrap(from_fn(authenticate))
+                .route("/v1/models", web::get().to(key_id)),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/v1/models")
+            .insert_header((AUTHORIZATION, "Bearer pk-ci"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(test::read_body(response).await, "ci-bot");
+        let request = test::TestRequest::get()
+            .uri("/v1/models")
+            .insert_header(("x-api-key", "pk-ci"))
+            .to_request();
+        assert_eq!(
+            test::read_body(test::call_service(&app, request).await).await,
+            "ci-bot"
+        );
+
+        let request = test::TestRequest::get().uri("/v1/models").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["error"]["code"], "invalid_api_key");
+        assert!(body["error"]["message"]
+            .as_str()
+            .is_some_and(|message| message.starts_with("Missing API key")));
+
+        let request = test::TestRequest::get()
+            .uri("/v1/models")
+            .insert_header((AUTHORIZATION, "Bearer pk-wrong"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["error"]["message"], "Invalid API key");
+
+        // Revoked keys are refused from the next request on
+        store.revoke("pk-ci");
+        let request = test::TestRequest::get()
+            .uri("/v1/models")
+            .insert_header((AUTHORIZATION, "Bearer pk-ci"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_admin_bypass() {
+        for admin in [true, false] {
+            let app = test::init_service(
+                App::new()
+                    .app_data(state(store(), admin))
+                    .wrap(from_fn(authenticate))
+                    .route("/admin/routes", web::get().to(key_id))
+                    .route("/administrator", web::get().to(key_id)),
+            )
+            .await;
+
+            // The admin API checks its own token, if it has one
+            let request = test::TestRequest::get().uri("/admin/routes").to_request();
+            let status = test::call_service(&app, request).await.status();
+            let expected = if admin {
+                StatusCode::OK
+            } else {
+                StatusCode::UNAUTHORIZED
+            };
+            assert_eq!(status, expected, "admin: {admin}");
+
+            let request = test::TestRequest::get().uri("/administrator").to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::UNAUTHORIZED,
+                "admin: {admin}"
+            );
+        }
+    }
+}
@@ -0,0 +1,375 @@
+//! Code the model writes, run in a WebAssembly sandbox.
+//!
+//! A `[tool.<name>]` section with `type = "code_interpreter"` offers the
+//! model a tool of that name taking `code`, which the proxy runs with a WASI
+//! module interpreting it, such as a WebAssembly build of `CPython`. Each run
+//! gets a fresh instance with no network access, read-only access to the
+//! directories of `mounts` only, and limits on its memory, fuel and time.
+//! The model gets back what the run wrote to standard output and error, and
+//! how it ended if it didn't succeed.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use wasmtime::{Engine, InstancePre, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap};
+use wasmtime_wasi::{
+    pipe::{MemoryInputPipe, MemoryOutputPipe},
+    preview1::{self, WasiP1Ctx},
+    DirPerms, FilePerms, I32Exit, WasiCtxBuilder,
+};
+
+use crate::{config::CodeInterpreterConfig, tools::ToolExecutor};
+
+/// Argument of the module replaced by the code
+const CODE_PLACEHOLDER: &str = "{code}";
+
+/// Memory of a run in MiB, unless the tool sets `max_memory_mb`
+const DEFAULT_MAX_MEMORY_MB: usize = 256;
+
+/// Seconds a run may take, unless the tool sets `timeout_secs`
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+/// Bytes of each output returned, unless the tool sets `max_output_bytes`
+const DEFAULT_MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// Interval at which the engine's epoch advances, checked against the
+/// deadlines of runs
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// A code interpreter tool
+pub struct CodeInterpreter {
+    name: String,
+    language: String,
+    runner: Arc<Runner>,
+}
+
+/// Runs of the module
+struct Runner {
+    engine: Engine,
+    instance: InstancePre<Sandbox>,
+    /// The module's arguments, its name first
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    /// Host directories by their path in the sandbox
+    mounts: Vec<(String, String)>,
+    max_memory: usize,
+    max_fuel: Option<u64>,
+    timeout: Duration,
+    max_output: usize,
+}
+
+/// State of a run
+struct Sandbox {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+}
+
+impl CodeInterpreter {
+    /// The tool `name` configured by `config`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the module cannot be compiled or
+    /// a mounted directory doesn't exist.
+    pub fn new(name: &str, config: &CodeInterpreterConfig) -> Result<Self> {
+        let mut engine_config = wasmtime::Config::new();
+        engine_config
+            .epoch_interruption(true)
+            .consume_fuel(config.max_fuel.is_some());
+        let engine = Engine::new(&engine_config)?;
+        let module = Module::from_file(&engine, &config.module)
+            .with_context(|| format!("Failed to load module {}", config.module))?;
+        let mut linker = Linker::new(&engine);
+        preview1::add_to_linker_sync(&mut linker, |sandbox: &mut Sandbox| &mut sandbox.wasi)?;
+        let instance = linker.instantiate_pre(&module)?;
+
+        let mut mounts: Vec<_> = config
+            .mounts
+            .iter()
+            .map(|(guest, host)| (guest.clone(), host.clone()))
+            .collect();
+        mounts.sort();
+        for (_, host) in &mounts {
+            if !std::path::Path::new(host).is_dir() {
+                anyhow::bail!("Mounted directory {host} doesn't exist");
+            }
+        }
+
+        // Advance the epoch while the engine is in use
+        let weak = engine.weak();
+        std::thread::Builder::new()
+            .name("wasm-epoch".to_string())
+            .spawn(move || {
+                while let Some(engine) = weak.upgrade() {
+                    engine.increment_epoch();
+                    drop(engine);
+                    std::thread::sleep(EPOCH_TICK);
+                }
+            })?;
+
+        let mut args = vec![name.to_string()];
+        args.extend(config.args.iter().cloned());
+        let runner = Runner {
+            engine,
+            instance,
+            args,
+            env: sorted(&config.env),
+            mounts,
+            max_memory: config.max_memory_mb.unwrap_or(DEFAULT_MAX_MEMORY_MB) * 1024 * 1024,
+            max_fuel: config.max_fuel,
+            timeout: Duration::from_secs(config.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS)),
+            max_output: config.max_output_bytes.unwrap_or(DEFAULT_MAX_OUTPUT_BYTES),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            language: config.language.clone(),
+            runner: Arc::new(runner),
+        })
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for CodeInterpreter {
+    async fn tools(&self) -> Result<Vec<Value>> {
+        let language = &self.language;
+        let access = if self.runner.mounts.is_empty() {
+            "without network or filesystem access".to_string()
+        } else {
+            let guests: Vec<_> = self
+                .runner
+                .mounts
+                .iter()
+                .map(|(guest, _)| guest.as_str())
+                .collect();
+            format!(
+                "without network access, which may only read the files under {}",
+                guests.join(", ")
+            )
+        };
+        Ok(vec![json!({
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "description": format!(
+                    "Run {language} code in a sandbox {access}. \
+                     Returns what the code prints, so print the results you need."
+                ),
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "code": { "type": "string", "description": format!("The {language} code") },
+                    },
+                    "required": ["code"],
+                },
+            },
+        })])
+    }
+
+    async fn call(&self, _name: &str, arguments: Value) -> Result<String> {
+        let code = arguments["code"]
+            .as_str()
+            .context("The code is required")?
+            .to_string();
+        let runner = self.runner.clone();
+        // Runs are CPU bound, and their time is limited by the epoch
+        tokio::task::spawn_blocking(move || runner.run(&code)).await?
+    }
+}
+
+impl Runner {
+    /// Run `code`, returning its output
+    fn run(&self, code: &str) -> Result<String> {
+        let stdout = MemoryOutputPipe::new(self.max_output);
+        let stderr = MemoryOutputPipe::new(self.max_output);
+        let mut wasi = WasiCtxBuilder::new();
+        let args: Vec<_> = self
+            .args
+            .iter()
+            .map(|arg| arg.replace(CODE_PLACEHOLDER, code))
+            .collect();
+        wasi.args(&args)
+            .envs(&self.env)
+            .stdout(stdout.clone())
+            .stderr(stderr.clone());
+        if !self.args.iter().any(|arg| arg.contains(CODE_PLACEHOLDER)) {
+            wasi.stdin(MemoryInputPipe::new(code.to_string()));
+        }
+        for (guest, host) in &self.mounts {
+            wasi.preopened_dir(host, guest, DirPerms::READ, FilePerms::READ)?;
+        }
+
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.max_memory)
+            .instances(1)
+            .build();
+        let mut store = Store::new(
+            &self.engine,
+            Sandbox {
+                wasi: wasi.build_p1(),
+                limits,
+            },
+        );
+        store.limiter(|sandbox| &mut sandbox.limits);
+        let ticks = self.timeout.as_millis() / EPOCH_TICK.as_millis();
+        store.set_epoch_deadline(u64::try_from(ticks).unwrap_or(u64::MAX).max(1));
+        if let Some(fuel) = self.max_fuel {
+            store.set_fuel(fuel)?;
+        }
+
+        let outcome = self.instance.instantiate(&mut store).and_then(|instance| {
+            instance
+                .get_typed_func::<(), ()>(&mut store, "_start")?
+                .call(&mut store, ())
+        });
+        let status = match outcome {
+            Ok(()) => None,
+            Err(e) => match (e.downcast_ref::<I32Exit>(), e.downcast_ref::<Trap>()) {
+                (Some(I32Exit(0)), _) => None,
+                (Some(I32Exit(code)), _) => Some(format!("Exited with code {code}")),
+                (_, Some(Trap::Interrupt)) => Some(format!(
+                    "Stopped after the time limit of {} s",
+                    self.timeout.as_secs_f32()
+                )),
+                (_, Some(Trap::OutOfFuel)) => Some("Stopped after using up its fuel".to_string()),
+                _ => Some(format!("Failed: {e:#}")),
+            },
+        };
+        Ok(output(&stdout.contents(), &stderr.contents(), status))
+    }
+}
+
+/// `map` as pairs in key order
+fn sorted(map: &HashMap<String, String>) -> Vec<(String, String)> {
+    let mut pairs: Vec<_> = map
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    pairs.sort();
+    pairs
+}
+
+/// The tool output of a run
+fn output(stdout: &[u8], stderr: &[u8], status: Option<String>) -> String {
+    let mut parts = Vec::new();
+    if !stdout.is_empty() {
+        parts.push(String::from_utf8_lossy(stdout).trim_end().to_string());
+    }
+    if !stderr.is_empty() {
+        parts.push(format!(
+            "stderr:\n{}",
+            String::from_utf8_lossy(stderr).trim_end()
+        ));
+    }
+    parts.extend(status);
+    if parts.is_empty() {
+        "No output".to_string()
+    } else {
+        parts.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module echoing its standard input, then exiting with code 3
+    const ECHO: &str = r#"(module
+        (import "wasi_snapshot_preview1" "fd_read"
+            (func $fd_read (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "fd_write"
+            (func $fd_write (param i32 i32 i32 i32) (result i32)))
+        (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+        (memory (export "memory") 1)
+        (func (export "_start")
+            (i32.store (i32.const 0) (i32.const 16))
+            (i32.store (i32.const 4) (i32.const 1024))
+            (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+            (i32.store (i32.const 4) (i32.load (i32.const 8)))
+            (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))
+            (call $proc_exit (i32.const 3))))"#;
+
+    /// A module that never ends
+    const LOOP: &str = r#"(module (func (export "_start") (loop $forever (br $forever))))"#;
+
+    fn interpreter(
+        module: &str,
+        config: impl FnOnce(&mut CodeInterpreterConfig),
+    ) -> CodeInterpreter {
+        let path = std::env::temp_dir().join(format!(
+            "llm-proxy-{}-{}.wat",
+            std::process::id(),
+            module.len()
+        ));
+        std::fs::write(&path, module).expect("Failed to write module");
+        let mut interpreter_config = CodeInterpreterConfig {
+            module: path.to_string_lossy().into_owned(),
+            language: "test".to_string(),
+            args: Vec::new(),
+            env: HashMap::new(),
+            mounts: HashMap::new(),
+            max_memory_mb: None,
+            max_fuel: None,
+            timeout_secs: None,
+            max_output_bytes: None,
+        };
+        config(&mut interpreter_config);
+        let interpreter = CodeInterpreter::new("run_code", &interpreter_config);
+        let _ = std::fs::remove_file(path);
+        interpreter.expect("Invalid interpreter")
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let echo = interpreter(ECHO, |_| {});
+        let output = echo
+            .call("run_code", json!({ "code": "print(1)" }))
+            .await
+            .expect("Run failed");
+        assert_eq!(output, "print(1)\nExited with code 3");
+
+        let tools = echo.tools().await.expect("No tools");
+        assert_eq!(tools[0]["function"]["name"], "run_code");
+        assert_eq!(
+            tools[0]["function"]["description"],
+            "Run test code in a sandbox without network or filesystem access. \
+             Returns what the code prints, so print the results you need."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mounts_are_described() {
+        let lib = std::env::temp_dir();
+        let interpreter = interpreter(ECHO, |config| {
+            config.mounts = HashMap::from([
+                ("/lib".to_string(), lib.to_string_lossy().into_owned()),
+                ("/data".to_string(), lib.to_string_lossy().into_owned()),
+            ]);
+        });
+        let tools = interpreter.tools().await.expect("No tools");
+        assert_eq!(
+            tools[0]["function"]["description"],
+            "Run test code in a sandbox without network access, which may only read the \
+             files under /data, /lib. Returns what the code prints, so print the results \
+             you need."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        let endless = interpreter(LOOP, |config| config.timeout_secs = Some(1));
+        let output = endless
+            .call("run_code", json!({ "code": "" }))
+            .await
+            .expect("Run failed");
+        assert_eq!(output, "Stopped after the time limit of 1 s");
+
+        let endless = interpreter(LOOP, |config| config.max_fuel = Some(10_000));
+        let output = endless
+            .call("run_code", json!({ "code": "" }))
+            .await
+            .expect("Run failed");
+        assert_eq!(output, "Stopped after using up its fuel");
+    }
+}
@@ -0,0 +1,145 @@
+//! Compression of responses.
+//!
+//! With `compress_responses`, responses are compressed with gzip, brotli or
+//! zstd as negotiated by the client's `Accept-Encoding`. Event streams, and
+//! the newline-delimited JSON streams of Ollama routes, are sent
+//! uncompressed: compressors hold data back until they have enough of it,
+//! which would delay the events of streamed completions.
+//!
+//! actix-web's `Compress` middleware leaves responses that already have a
+//! `Content-Encoding` alone, so [`mark_event_streams`], wrapped inside it,
+//! gives event streams an `identity` encoding that [`unmark_event_streams`],
+//! wrapped outside it, removes again.
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderMap, HeaderValue, CONTENT_ENCODING, CONTENT_TYPE},
+    middleware::Next,
+};
+
+const IDENTITY: HeaderValue = HeaderValue::from_static("identity");
+
+/// Mark event stream responses as encoded, so they aren't compressed
+///
+/// # Errors
+///
+/// This function will return an error if the wrapped service fails.
+#[allow(clippy::future_not_send)]
+pub async fn mark_event_streams(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let mut response = next.call(req).await?;
+    let headers = response.headers_mut();
+    if is_event_stream(headers) && !headers.contains_key(CONTENT_ENCODING) {
+        headers.insert(CONTENT_ENCODING, IDENTITY);
+    }
+    Ok(response)
+}
+
+/// Remove the encoding [`mark_event_streams`] gave event streams
+///
+/// # Errors
+///
+/// This function will return an error if the wrapped service fails.
+#[allow(clippy::future_not_send)]
+pub async fn unmark_event_streams(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let mut response = next.call(req).await?;
+    let headers = response.headers_mut();
+    if is_event_stream(headers) && headers.get(CONTENT_ENCODING) == Some(&IDENTITY) {
+        headers.remove(CONTENT_ENCODING);
+    }
+    Ok(response)
+}
+
+/// Whether a request body asks for a streamed response
+#[must_use]
+pub fn requests_stream(body: &[u8]) -> bool {
+    #[derive(serde::Deserialize)]
+    struct Stream {
+        #[serde(default)]
+        stream: bool,
+    }
+
+    serde_json::from_slice::<Stream>(body).is_ok_and(|request| request.stream)
+}
+
+fn is_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| {
+            content_type.starts_with("text/event-stream")
+                || content_type.starts_with("application/x-ndjson")
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::header::ACCEPT_ENCODING,
+        middleware::{from_fn, Compress},
+        test::{call_service, init_service, read_body, TestRequest},
+        web, App, HttpResponse,
+    };
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_event_streams_are_not_compressed() {
+        let app = init_service(
+            App::new()
+                .wrap(from_fn(mark_event_streams))
+                .wrap(Compress::default())
+                .wrap(from_fn(unmark_event_streams))
+                .route(
+                    "/json",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .content_type("application/json")
+                            .body("{\"data\":\"".to_string() + &"a".repeat(1024) + "\"}")
+                    }),
+                )
+                .route(
+                    "/stream",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .content_type("text/event-stream")
+                            .body("data: [DONE]\n\n")
+                    }),
+                ),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/json")
+            .insert_header((ACCEPT_ENCODING, "gzip"))
+            .to_request();
+        let response = call_service(&app, req).await;
+        assert_eq!(
+            response.headers().get(CONTENT_ENCODING),
+            Some(&HeaderValue::from_static("gzip"))
+        );
+
+        let req = TestRequest::get()
+            .uri("/stream")
+            .insert_header((ACCEPT_ENCODING, "gzip"))
+            .to_request();
+        let response = call_service(&app, req).await;
+        assert!(response.headers().get(CONTENT_ENCODING).is_none());
+        let body = read_body(response).await;
+        assert_eq!(body, "data: [DONE]\n\n");
+    }
+
+    #[test]
+    fn test_requests_stream() {
+        assert!(requests_stream(br#"{"model":"a","stream":true}"#));
+        assert!(!requests_stream(br#"{"model":"a","stream":false}"#));
+        assert!(!requests_stream(br#"{"model":"a"}"#));
+        assert!(!requests_stream(b"not json"));
+    }
+}
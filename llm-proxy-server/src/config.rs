@@ -1,9 +1,15 @@
-use serde::Deserialize;
+use llm_proxy_core::{AuthScheme, ModelPrice, ProviderCapabilities};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::net::IpAddr;
+use std::path::Path;
+
+/// Prefix of the environment variables overriding configuration values, with
+/// `__` separating the keys, e.g. `LLM_PROXY__SERVER__PORT=8080` for `server.port`
+pub const ENV_PREFIX: &str = "LLM_PROXY";
 
 /// Server configuration loaded from config.toml
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     /// LLM backend configurations
     pub llm: HashMap<String, LLMConfig>,
@@ -13,10 +19,239 @@ pub struct Config {
     pub route: Vec<RouteConfig>,
     /// Server-specific settings
     pub server: ServerConfig,
+    /// Tenants authenticating with proxy-issued keys; when any are configured,
+    /// every request must carry a known tenant key, unless `client_keys` are
+    /// configured, which then map to the tenants
+    #[serde(default)]
+    pub tenant: Vec<TenantConfig>,
+    /// Store of the proxy-issued keys clients authenticate with; when
+    /// configured, every request outside the admin API must carry a known key
+    #[serde(default)]
+    pub client_keys: Option<ClientKeysConfig>,
+    /// Resolution of tenants from a header and per-tenant configuration overlays
+    #[serde(default)]
+    pub tenancy: Option<TenancyConfig>,
+    /// Rate limits and spend quotas of client keys
+    #[serde(default)]
+    pub quota: Option<QuotaConfig>,
+    /// Export of traces to an OpenTelemetry collector (requires the `otel` feature)
+    #[serde(default)]
+    pub telemetry: Option<TelemetryConfig>,
+    /// Access log with a JSON line per request, written when configured
+    #[serde(default)]
+    pub access_log: Option<AccessLogConfig>,
+    /// Prometheus metrics of the proxy, served when configured
+    #[serde(default)]
+    pub metrics: Option<MetricsConfig>,
+    /// Cache of responses, used by routes with a `cache_policy` processor
+    #[serde(default)]
+    pub cache: Option<CacheConfig>,
+    /// Prices in US dollars per million tokens, keyed by model name prefix,
+    /// overriding or extending the built-in price table
+    #[serde(default)]
+    pub pricing: HashMap<String, ModelPrice>,
+    /// Admin API under `/admin`, served when configured
+    #[serde(default)]
+    pub admin: Option<AdminConfig>,
+    /// Virtual models clients can request, keyed by their name
+    #[serde(default)]
+    pub model: HashMap<String, VirtualModelConfig>,
+    /// gRPC interface running chat requests through the routes' pipelines
+    /// (requires the `grpc` feature)
+    #[serde(default)]
+    pub grpc: Option<GrpcConfig>,
+    /// MCP servers whose tools routes with `mcp` offer to the model, keyed by
+    /// the name routes refer to them by
+    #[serde(default)]
+    pub mcp: HashMap<String, McpServerConfig>,
+    /// Tools the proxy runs itself, offered on routes listing them in
+    /// `tools`, keyed by the name of the tool
+    #[serde(default)]
+    pub tool: HashMap<String, ToolConfig>,
+    /// Shared libraries providing processor types, loaded at startup
+    /// (requires the `plugins` feature)
+    #[serde(default)]
+    pub plugins: Option<PluginsConfig>,
+    /// Message broker the events of requests are published to
+    #[serde(default)]
+    pub events: Option<EventsConfig>,
+    /// Webhooks notified of upstream failures, unhealthy endpoints, used up
+    /// quotas and failed reloads, keyed by name
+    #[serde(default)]
+    pub webhook: HashMap<String, WebhookConfig>,
+}
+
+/// Where the proxy-issued keys clients authenticate with are looked up
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientKeysConfig {
+    /// Keys listed in the configuration
+    Static {
+        /// The keys
+        keys: Vec<StaticClientKeyConfig>,
+    },
+    /// A JSON file of key hashes, reloaded when it changes
+    File {
+        /// Path of the file
+        path: String,
+    },
+    /// A `client_keys` table of key hashes in a `SQLite` database
+    #[cfg(feature = "sqlite")]
+    Sqlite {
+        /// URL of the database, e.g. `sqlite:///var/lib/llm-proxy/keys.db`
+        url: String,
+    },
+}
+
+/// Rate limits and spend quotas of the client keys
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct QuotaConfig {
+    /// Limits of keys without limits of their own
+    #[serde(default)]
+    pub default: QuotaLimits,
+    /// Limits by client key ID
+    #[serde(default)]
+    pub keys: HashMap<String, QuotaLimits>,
+}
+
+/// Limits of a client key; unset limits don't apply
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct QuotaLimits {
+    /// Requests per UTC minute
+    #[serde(default)]
+    pub requests_per_minute: Option<u64>,
+    /// Prompt and completion tokens per UTC day
+    #[serde(default)]
+    pub tokens_per_day: Option<u64>,
+    /// Cost in US dollars per UTC calendar month, at the `[pricing]` prices
+    #[serde(default)]
+    pub dollars_per_month: Option<f64>,
+}
+
+/// A proxy-issued key listed in the configuration
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StaticClientKeyConfig {
+    /// Identifier of the key in the request context, logs and quotas
+    pub id: String,
+    /// Environment variable containing the key
+    pub key_env: String,
+    /// ID of the `[[tenant]]` the key belongs to
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+/// A model name resolving to a real model of an LLM, with default parameters
+///
+/// Requests for the virtual model are sent to `target_llm` for `model`,
+/// whatever the target of their route, with the parameters they don't set
+/// filled in.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VirtualModelConfig {
+    /// ID of the `[llm.*]` section requests are forwarded to
+    pub target_llm: String,
+    /// Model requested from the LLM
+    pub model: String,
+    /// Temperature of requests that don't set one
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    /// Maximum number of tokens to generate for requests that don't set one
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// System prompt of requests without a system message
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+}
+
+/// A client of the proxy, identified by a proxy-issued key
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TenantConfig {
+    /// Tenant identifier, matched by `token_rules` entries with a `tenant`
+    pub id: String,
+    /// Environment variable containing the key the tenant authenticates with,
+    /// unless clients authenticate with `client_keys`
+    #[serde(default)]
+    pub key_env: Option<String>,
+    /// `OpenAI` organization ID used for the tenant's requests
+    #[serde(default)]
+    pub organization: Option<String>,
+    /// `OpenAI` project ID used for the tenant's requests
+    #[serde(default)]
+    pub project: Option<String>,
+}
+
+/// Resolution of tenants from a header and per-tenant configuration overlays
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TenancyConfig {
+    /// Header naming the tenant of requests that aren't authenticated, e.g.
+    /// set by a gateway in front of the proxy
+    #[serde(default)]
+    pub header: Option<String>,
+    /// Directory of `<tenant>.toml` (or `.yaml`, `.json`) overlay files,
+    /// reloaded when they change
+    #[serde(default)]
+    pub overlay_dir: Option<String>,
+}
+
+/// Settings of a tenant overriding those of the configuration for its requests
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct TenantOverlay {
+    /// Overrides of routes by route ID
+    #[serde(default)]
+    pub routes: HashMap<String, RouteOverlay>,
+    /// System prompt added before the messages of the tenant's requests
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Limits of the tenant's client keys without limits of their own
+    #[serde(default)]
+    pub quota: Option<QuotaLimits>,
+    /// Models the tenant may request, names or prefixes ending with `*`
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    /// Models the tenant may not request, names or prefixes ending with `*`
+    #[serde(default)]
+    pub denied_models: Vec<String>,
+    /// Environment variables holding the tenant's upstream keys, by LLM ID
+    #[serde(default)]
+    pub tokens: HashMap<String, String>,
+}
+
+impl TenantOverlay {
+    /// Whether the tenant may request `model`
+    #[must_use]
+    pub fn allows_model(&self, model: &str) -> bool {
+        (self.allowed_models.is_empty() || matches_model(&self.allowed_models, model))
+            && !matches_model(&self.denied_models, model)
+    }
+
+    /// The LLM the tenant's requests on the route `route_id` are sent to, if overridden
+    #[must_use]
+    pub fn target_llm(&self, route_id: &str) -> Option<&str> {
+        self.routes.get(route_id)?.target_llm.as_deref()
+    }
+
+    /// The system prompt of the tenant's requests on the route `route_id`
+    #[must_use]
+    pub fn system_prompt(&self, route_id: &str) -> Option<&str> {
+        self.routes
+            .get(route_id)
+            .and_then(|route| route.system_prompt.as_deref())
+            .or(self.system_prompt.as_deref())
+    }
+}
+
+/// Overrides of a route for a tenant
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct RouteOverlay {
+    /// LLM the tenant's requests are sent to instead of the route's
+    #[serde(default)]
+    pub target_llm: Option<String>,
+    /// System prompt replacing the tenant-wide one on this route
+    #[serde(default)]
+    pub system_prompt: Option<String>,
 }
 
 /// Configuration for an LLM backend service
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LLMConfig {
     /// The type of LLM provider (e.g., "openai", "anthropic")
     pub provider: String,
@@ -25,30 +260,1004 @@ pub struct LLMConfig {
     pub endpoint_type: String,
     /// Base URL for the LLM API
     pub base_url: String,
+    /// Upstream endpoints requests are spread across, used instead of `base_url`
+    #[serde(default)]
+    pub endpoints: Vec<EndpointConfig>,
+    /// Active health checks; unhealthy endpoints receive no requests
+    #[serde(default)]
+    pub health_check: Option<HealthCheckConfig>,
+    /// Discover endpoints from DNS SRV records or Consul, used instead of `endpoints`
+    #[serde(default)]
+    pub discovery: Option<DiscoveryConfig>,
     /// Environment variable containing the API token
     pub token_env: String,
     /// Whether this endpoint supports streaming responses
     pub supports_streaming: bool,
+    /// Whether requests may define tools
+    #[serde(default = "default_true")]
+    pub supports_tools: bool,
+    /// Whether messages may contain images
+    #[serde(default = "default_true")]
+    pub supports_vision: bool,
+    /// Context window of the served models; requests asking for more `max_tokens` are rejected
+    #[serde(default)]
+    pub max_context_tokens: Option<u32>,
+    /// Models served by this LLM; requests for other models are rejected when set
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Pool of API keys rotated across requests, used instead of `token_env`
+    #[serde(default)]
+    pub token_pool: Option<TokenPoolConfig>,
+    /// External source the API key is read from, used instead of `token_env`
+    #[serde(default)]
+    pub token_source: Option<TokenSourceConfig>,
+    /// Tokens used instead of the default token for specific models, tenants or routes
+    #[serde(default)]
+    pub token_rules: Vec<TokenRuleConfig>,
+    /// `OpenAI` organization ID sent as the `OpenAI-Organization` header
+    #[serde(default)]
+    pub organization: Option<String>,
+    /// `OpenAI` project ID sent as the `OpenAI-Project` header
+    #[serde(default)]
+    pub project: Option<String>,
+    /// How the API token is sent, overriding the provider's default
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    /// Static headers sent with every request to this LLM
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Forward proxy used for requests to this LLM
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>,
+    /// Client certificate and CA bundle for backends requiring mutual TLS
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Retries of requests this LLM failed
+    #[serde(default)]
+    pub retry: Option<RetryConfig>,
+    /// JSON file of faults injected into the responses of this LLM, for
+    /// chaos testing (requires the `mock` feature)
+    #[serde(default)]
+    pub fault_scenario: Option<String>,
     /// Additional provider-specific configuration
     #[serde(default)]
     pub additional_config: serde_json::Value,
 }
 
+impl LLMConfig {
+    /// Whether an API token is configured, for providers where it is optional
+    #[must_use]
+    pub const fn has_token(&self) -> bool {
+        !self.token_env.is_empty()
+            || self.token_pool.is_some()
+            || self.token_source.is_some()
+            || !self.token_rules.is_empty()
+    }
+
+    /// What this LLM supports, checked before requests are sent
+    #[must_use]
+    pub fn capabilities(&self) -> ProviderCapabilities {
+        let mut capabilities = ProviderCapabilities::new().with_models(self.models.clone());
+        capabilities.supports_streaming = self.supports_streaming;
+        capabilities.supports_tools = self.supports_tools;
+        capabilities.supports_vision = self.supports_vision;
+        capabilities.max_context_tokens = self.max_context_tokens;
+        capabilities
+    }
+
+    /// Deserialize the provider-specific `additional_config` section
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `additional_config` doesn't match `T`.
+    pub fn provider_config<T: serde::de::DeserializeOwned>(&self) -> anyhow::Result<T> {
+        let value = if self.additional_config.is_null() {
+            serde_json::Value::Object(serde_json::Map::new())
+        } else {
+            self.additional_config.clone()
+        };
+        serde_json::from_value(value).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid additional_config for {} provider: {e}",
+                self.provider
+            )
+        })
+    }
+}
+
+/// External source an LLM's API key is read from
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TokenSourceConfig {
+    /// An environment variable containing the key
+    Env {
+        /// Name of the environment variable
+        env: String,
+    },
+    /// Several sources tried in order until one returns a key
+    Chain {
+        /// The sources, in order of preference
+        sources: Vec<Self>,
+    },
+    /// A file containing the key, reloaded when it changes
+    File {
+        /// Path of the file
+        path: String,
+    },
+    /// Microsoft Entra ID access tokens, for Azure `OpenAI`
+    AzureEntra {
+        /// Directory (tenant) ID, required for client secret authentication
+        #[serde(default)]
+        tenant_id: Option<String>,
+        /// Application (client) ID, or the client ID of a user-assigned managed identity
+        #[serde(default)]
+        client_id: Option<String>,
+        /// Environment variable containing the client secret; the managed
+        /// identity of the host is used when unset
+        #[serde(default)]
+        client_secret_env: Option<String>,
+    },
+    /// An AWS Secrets Manager secret
+    #[cfg(feature = "aws")]
+    AwsSecretsManager {
+        /// Name or ARN of the secret
+        secret_id: String,
+        /// Field holding the key, for secrets stored as JSON objects
+        #[serde(default)]
+        json_key: Option<String>,
+        /// AWS region, defaults to the region of the environment
+        #[serde(default)]
+        region: Option<String>,
+        /// How often the key is fetched again
+        #[serde(default)]
+        refresh_secs: Option<u64>,
+    },
+    /// An AWS SSM Parameter Store parameter
+    #[cfg(feature = "aws")]
+    AwsSsmParameter {
+        /// Name or ARN of the parameter
+        name: String,
+        /// AWS region, defaults to the region of the environment
+        #[serde(default)]
+        region: Option<String>,
+        /// How often the key is fetched again
+        #[serde(default)]
+        refresh_secs: Option<u64>,
+    },
+    /// Google OAuth tokens from Application Default Credentials, for Vertex AI
+    #[cfg(feature = "gcp")]
+    Gcp {
+        /// Service account key or authorized user file; ADC lookup is used when unset
+        #[serde(default)]
+        credentials_file: Option<String>,
+        /// Scopes of the access tokens, defaults to `cloud-platform`
+        #[serde(default)]
+        scopes: Vec<String>,
+        /// Request ID tokens for this audience instead of access tokens
+        #[serde(default)]
+        audience: Option<String>,
+    },
+    /// An entry in the OS keychain
+    #[cfg(feature = "keyring")]
+    Keyring {
+        /// Service the entry is stored under
+        service: String,
+        /// Account (user) name of the entry
+        user: String,
+    },
+}
+
+impl TokenSourceConfig {
+    /// Short description of the source, used in logs and errors
+    #[must_use]
+    pub fn name(&self) -> String {
+        match self {
+            Self::Env { env } => format!("env:{env}"),
+            Self::Chain { .. } => "chain".to_string(),
+            Self::File { path } => format!("file:{path}"),
+            Self::AzureEntra { .. } => "azure_entra".to_string(),
+            #[cfg(feature = "aws")]
+            Self::AwsSecretsManager { secret_id, .. } => format!("aws_secrets_manager:{secret_id}"),
+            #[cfg(feature = "aws")]
+            Self::AwsSsmParameter { name, .. } => format!("aws_ssm_parameter:{name}"),
+            #[cfg(feature = "gcp")]
+            Self::Gcp { .. } => "gcp".to_string(),
+            #[cfg(feature = "keyring")]
+            Self::Keyring { service, user } => format!("keyring:{service}/{user}"),
+        }
+    }
+}
+
+/// A token used for requests matching a model, tenant or route
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TokenRuleConfig {
+    /// Model the token is used for
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Tenant the token is used for
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// ID of the route the token is used for, its path prefix unless set otherwise
+    #[serde(default)]
+    pub route: Option<String>,
+    /// Where the token is read from
+    pub source: TokenSourceConfig,
+}
+
+/// Configuration for a rotating pool of API keys
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TokenPoolConfig {
+    /// Keys read from environment variables
+    #[serde(default)]
+    pub keys: Vec<PooledKeyConfig>,
+    /// File with one `<key> [weight]` entry per line
+    #[serde(default)]
+    pub file: Option<String>,
+    /// How long a key is quarantined after a 401/403 response
+    #[serde(default)]
+    pub unauthorized_quarantine_secs: Option<u64>,
+    /// How long a key is quarantined after a 429 response
+    #[serde(default)]
+    pub rate_limited_quarantine_secs: Option<u64>,
+}
+
+/// A key in a token pool, read from an environment variable
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PooledKeyConfig {
+    /// Environment variable containing the key
+    pub env: String,
+    /// Relative share of requests sent with this key
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Maximum number of requests per minute sent with this key
+    #[serde(default)]
+    pub max_requests_per_minute: Option<u32>,
+}
+
+const fn default_weight() -> u32 {
+    1
+}
+
+/// An upstream endpoint of a load-balanced LLM
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EndpointConfig {
+    /// Full URL of the endpoint
+    pub url: String,
+    /// Relative share of requests sent to this endpoint
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+/// Active health checking of an LLM's upstream endpoints
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HealthCheckConfig {
+    /// Path probed with a `GET` request, e.g. `/health` or `/v1/models`
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Seconds between probes
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+    /// Seconds a probe may take before it counts as failed
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Consecutive failed probes before an endpoint is marked unavailable
+    #[serde(default)]
+    pub failure_threshold: Option<u32>,
+}
+
+/// Dynamic discovery of an LLM's upstream endpoints
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DiscoveryConfig {
+    /// Where instances are looked up
+    #[serde(flatten)]
+    pub source: DiscoverySourceConfig,
+    /// URL scheme of discovered endpoints (default: `http`)
+    #[serde(default)]
+    pub scheme: Option<String>,
+    /// Path appended to discovered endpoints, e.g. `/v1/chat/completions`
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Seconds between lookups
+    #[serde(default)]
+    pub refresh_secs: Option<u64>,
+}
+
+/// Where an LLM's upstream instances are looked up
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DiscoverySourceConfig {
+    /// DNS SRV records
+    #[cfg(feature = "dns")]
+    DnsSrv {
+        /// Name of the SRV record
+        name: String,
+    },
+    /// Passing instances of a service registered in Consul
+    Consul {
+        /// Address of the Consul HTTP API
+        address: String,
+        /// Name of the service
+        service: String,
+        /// Only use instances with this tag
+        #[serde(default)]
+        tag: Option<String>,
+        /// Environment variable containing a Consul ACL token
+        #[serde(default)]
+        token_env: Option<String>,
+    },
+}
+
+/// How an LLM's API token is attached to upstream requests
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthConfig {
+    /// `Authorization: Bearer <token>`
+    Bearer,
+    /// The token as the value of a header
+    Header {
+        /// Name of the header, e.g. `x-api-key`
+        name: String,
+    },
+    /// The token as a query parameter
+    Query {
+        /// Name of the query parameter, e.g. `key`
+        name: String,
+    },
+}
+
+impl AuthConfig {
+    /// The auth scheme described by this configuration
+    #[must_use]
+    pub fn scheme(&self) -> AuthScheme {
+        match self {
+            Self::Bearer => AuthScheme::Bearer,
+            Self::Header { name } => AuthScheme::Header(name.clone()),
+            Self::Query { name } => AuthScheme::QueryParam(name.clone()),
+        }
+    }
+}
+
+/// Forward proxy for an LLM's upstream requests
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProxyConfig {
+    /// Proxy URL, e.g. `http://proxy:3128` or `socks5h://proxy:1080`
+    pub url: String,
+    /// Hosts, domains or IP ranges reached directly instead of through the proxy
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+/// TLS settings for an LLM's upstream connections
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TlsConfig {
+    /// PEM file with the client certificate
+    #[serde(default)]
+    pub client_cert: Option<String>,
+    /// PEM file with the PKCS#8 private key of the client certificate
+    #[serde(default)]
+    pub client_key: Option<String>,
+    /// PEM file with additional CA certificates to trust
+    #[serde(default)]
+    pub ca_bundle: Option<String>,
+}
+
+/// HTTP client tuning read from an LLM's `additional_config` or a route's `client`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct HttpClientConfig {
+    /// Seconds to establish a connection
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+    /// Seconds between two reads of a response
+    #[serde(default)]
+    pub read_timeout_secs: Option<u64>,
+    /// Seconds for a whole request, including a streamed response
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Maximum number of idle connections kept per host
+    #[serde(default)]
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Seconds after which idle connections are closed
+    #[serde(default)]
+    pub pool_idle_timeout_secs: Option<u64>,
+    /// Use HTTP/2 without negotiating it first
+    #[serde(default)]
+    pub http2_prior_knowledge: bool,
+    /// Seconds between TCP keepalive probes
+    #[serde(default)]
+    pub tcp_keepalive_secs: Option<u64>,
+    /// Value of the `User-Agent` header
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// Record DNS, connect and connection reuse metrics for this upstream
+    #[serde(default)]
+    pub connection_metrics: bool,
+}
+
+impl HttpClientConfig {
+    /// These settings with those set in `overrides` replaced
+    #[must_use]
+    pub fn merged(&self, overrides: &Self) -> Self {
+        Self {
+            connect_timeout_secs: overrides.connect_timeout_secs.or(self.connect_timeout_secs),
+            read_timeout_secs: overrides.read_timeout_secs.or(self.read_timeout_secs),
+            timeout_secs: overrides.timeout_secs.or(self.timeout_secs),
+            pool_max_idle_per_host: overrides
+                .pool_max_idle_per_host
+                .or(self.pool_max_idle_per_host),
+            pool_idle_timeout_secs: overrides
+                .pool_idle_timeout_secs
+                .or(self.pool_idle_timeout_secs),
+            http2_prior_knowledge: overrides.http2_prior_knowledge || self.http2_prior_knowledge,
+            tcp_keepalive_secs: overrides.tcp_keepalive_secs.or(self.tcp_keepalive_secs),
+            user_agent: overrides
+                .user_agent
+                .clone()
+                .or_else(|| self.user_agent.clone()),
+            connection_metrics: overrides.connection_metrics || self.connection_metrics,
+        }
+    }
+}
+
+/// Retries of requests an LLM failed to answer
+///
+/// Requests are sent again when the LLM can't be reached or answers with an
+/// error before streaming any response.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RetryConfig {
+    /// Number of times a failed request is sent again
+    pub max_retries: u32,
+    /// Milliseconds to wait before the first retry, doubled for each further one
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// Milliseconds to wait at most before a retry
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+}
+
+const fn default_initial_backoff_ms() -> u64 {
+    200
+}
+
+const fn default_max_backoff_ms() -> u64 {
+    5000
+}
+
 /// Configuration for a processor in the processing chain
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProcessorConfig {
     /// The type of processor
     #[serde(rename = "type")]
     pub processor_type: String,
-    /// Primary configuration value
+    /// Primary configuration value, e.g. the log level of a `logger`
+    #[serde(default)]
     pub config_value: String,
     /// Additional processor-specific configuration
     #[serde(default)]
     pub additional_config: serde_json::Value,
 }
 
+impl ProcessorConfig {
+    /// Deserialize the processor-specific `additional_config` section
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `additional_config` doesn't match `T`.
+    pub fn settings<T: serde::de::DeserializeOwned>(&self) -> anyhow::Result<T> {
+        let value = if self.additional_config.is_null() {
+            serde_json::Value::Object(serde_json::Map::new())
+        } else {
+            self.additional_config.clone()
+        };
+        serde_json::from_value(value).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid additional_config for {} processor: {e}",
+                self.processor_type
+            )
+        })
+    }
+}
+
+/// Configuration for a route mapping inbound paths to an LLM backend
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RouteConfig {
+    /// ID of the route in token rules, logs and the admin API, by default
+    /// derived from its match conditions, see [`RouteConfig::id`]
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Path prefix the route matches
+    #[serde(default)]
+    pub path_prefix: Option<String>,
+    /// Exact path the route matches, instead of a prefix
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Glob of the paths the route matches, instead of a prefix; `*` matches
+    /// within a path segment and `**` across segments
+    #[serde(default)]
+    pub path_glob: Option<String>,
+    /// Regular expression the whole path must match, instead of a prefix
+    #[serde(default)]
+    pub path_regex: Option<String>,
+    /// HTTP methods the route matches; any method if empty
+    #[serde(default)]
+    pub methods: Vec<String>,
+    /// Header values requests must carry to match the route, e.g. `X-Env = "staging"`
+    #[serde(default)]
+    pub match_headers: HashMap<String, String>,
+    /// Upstream path requests are sent to instead of the backend's, see
+    /// [`Router`](crate::routing::Router) for how it is derived from the inbound path
+    #[serde(default)]
+    pub rewrite_path: Option<String>,
+    /// ID of the `[llm.*]` section requests are forwarded to, unless `targets` is set
+    #[serde(default)]
+    pub target_llm: String,
+    /// LLMs requests are split across by weight, instead of `target_llm`
+    #[serde(default)]
+    pub targets: Vec<TargetConfig>,
+    /// Send the requests of a session to the same one of the `targets`
+    #[serde(default)]
+    pub sticky: Option<StickyConfig>,
+    /// Which of the `targets` the `X-LLM-Proxy-Target` header may pick
+    #[serde(default)]
+    pub target_header: TargetHeaderPolicy,
+    /// IDs of the `[processor.*]` sections applied to requests, in order
+    #[serde(default)]
+    pub processors: Vec<String>,
+    /// IDs of the `[processor.*]` sections applied to response streams, in order
+    #[serde(default)]
+    pub stream_processors: Vec<String>,
+    /// Whether streaming requests are allowed on this route
+    #[serde(default = "default_true")]
+    pub allow_streaming: bool,
+    /// Whether non-streaming requests are allowed on this route
+    #[serde(default = "default_true")]
+    pub allow_non_streaming: bool,
+    /// Forward requests to the target LLM's host unchanged instead of running
+    /// them through a chat pipeline (e.g. for the Assistants and Threads APIs)
+    #[serde(default)]
+    pub passthrough: bool,
+    /// API clients of this route speak, translated to and from the `OpenAI`
+    /// format of the chat pipelines
+    #[serde(default)]
+    pub api: ApiFormat,
+    /// Static headers sent with every upstream request of this route, in
+    /// addition to (and overriding) the target LLM's headers
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Inbound headers forwarded to the upstream request, e.g. `traceparent`;
+    /// no others are, apart from those passthrough routes need
+    #[serde(default)]
+    pub forward_headers: Vec<ForwardHeader>,
+    /// Send requests upstream with the key of the client's `Authorization`
+    /// header instead of the target LLM's
+    #[serde(default)]
+    pub byok: Option<ByokConfig>,
+    /// Also accept WebSocket connections on this route, taking chat requests
+    /// as JSON messages and sending their chunks back as messages
+    #[serde(default)]
+    pub websocket: Option<WebSocketConfig>,
+    /// Tools of MCP servers added to this route's requests, and optionally
+    /// called by the proxy when the model asks for them
+    #[serde(default)]
+    pub mcp: Option<RouteMcpConfig>,
+    /// Names of the `[tool.<name>]` tools added to this route's requests and
+    /// run by the proxy when the model calls them
+    #[serde(default)]
+    pub tools: Vec<String>,
+    /// Most model responses with tool calls the proxy runs for one request,
+    /// 5 by default; the response after the last round is returned as is
+    #[serde(default)]
+    pub max_tool_rounds: Option<usize>,
+    /// HTTP client settings of this route, overriding those of the target LLM
+    #[serde(default)]
+    pub client: Option<HttpClientConfig>,
+    /// Forward proxy for this route, used instead of the target LLM's
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>,
+    /// Retries of this route's requests, used instead of the target LLM's
+    #[serde(default)]
+    pub retry: Option<RetryConfig>,
+    /// Models requests on this route may ask for; any model if empty. A
+    /// trailing `*` matches any model starting with the rest, e.g. `gpt-4o*`
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    /// Models requests on this route may not ask for, with the same patterns
+    /// as `allowed_models`
+    #[serde(default)]
+    pub denied_models: Vec<String>,
+    /// Largest request body accepted, in bytes, overriding the server's
+    #[serde(default)]
+    pub max_body_bytes: Option<usize>,
+    /// Most messages a chat request may have
+    #[serde(default)]
+    pub max_messages: Option<usize>,
+    /// Longest total text of a chat request's messages, in characters
+    #[serde(default)]
+    pub max_content_chars: Option<usize>,
+}
+
+/// An LLM receiving a share of a route's requests
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TargetConfig {
+    /// ID of the `[llm.*]` section
+    pub llm: String,
+    /// Relative share of requests sent to this LLM
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+/// Targets a request may pick with the `X-LLM-Proxy-Target` header
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetHeaderPolicy {
+    /// The header is ignored, requests are always split by weight
+    #[default]
+    Ignored,
+    /// Targets with a weight above 0
+    Weighted,
+    /// All targets, including drained ones with weight 0
+    All,
+}
+
+/// What identifies the session of a request on a route with `targets`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StickyConfig {
+    /// The `user` field of the request
+    User,
+    /// The value of a request header, e.g. `X-Session-Id`
+    Header {
+        /// Name of the header
+        name: String,
+    },
+}
+
+/// API spoken by the clients of a route
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiFormat {
+    /// `OpenAI` chat completions
+    #[default]
+    #[serde(rename = "openai")]
+    OpenAI,
+    /// Anthropic's Messages API, e.g. on `/v1/messages`
+    Anthropic,
+    /// Ollama's chat API, on `/api/chat`; the proxy also lists models on
+    /// `/api/tags` when a route has it
+    Ollama,
+}
+
+impl ApiFormat {
+    /// Name of the API in the configuration, and of the feature translating it
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::OpenAI => "openai",
+            Self::Anthropic => "anthropic",
+            Self::Ollama => "ollama",
+        }
+    }
+}
+
+/// Sending clients' own keys upstream
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ByokConfig {
+    /// Regular expression the whole key must match, e.g. `sk-[A-Za-z0-9_-]{20,}`
+    #[serde(default)]
+    pub key_pattern: Option<String>,
+}
+
+/// WebSocket connections of a route
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct WebSocketConfig {
+    /// Seconds between the pings the proxy sends, 30 by default
+    #[serde(default)]
+    pub ping_interval_secs: Option<u64>,
+    /// Seconds without any message from the client before the connection is
+    /// closed, 90 by default
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+}
+
+/// An MCP server, reached over Streamable HTTP at `url` or started as a
+/// `command` speaking over its standard input and output
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct McpServerConfig {
+    /// URL of the server's MCP endpoint, e.g. `https://mcp.example.com/mcp`
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Environment variable holding a bearer token sent to `url`
+    #[serde(default)]
+    pub token_env: Option<String>,
+    /// Command starting the server, e.g. `npx`
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Arguments of `command`
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables set for `command`
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Seconds to wait for each response of the server, 60 by default
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// MCP tools of a route
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RouteMcpConfig {
+    /// Names of the `[mcp.<name>]` servers whose tools are added to requests
+    pub servers: Vec<String>,
+    /// Call the tools the model asks for and send it their results, returning
+    /// only the final response to the client, instead of returning the tool
+    /// calls to the client
+    #[serde(default)]
+    pub execute: bool,
+}
+
+/// A tool the proxy runs itself
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolConfig {
+    /// Web search through a search API, taking a `query`
+    WebSearch(WebSearchConfig),
+    /// Code run in a WebAssembly sandbox, taking the `code` (requires the
+    /// `wasm` feature)
+    CodeInterpreter(CodeInterpreterConfig),
+}
+
+/// A web search tool
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebSearchConfig {
+    /// The search API
+    pub provider: SearchProvider,
+    /// Environment variable holding the API key
+    pub token_env: String,
+    /// Base URL of the API, the provider's by default
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Results returned to the model, 5 by default
+    #[serde(default)]
+    pub max_results: Option<usize>,
+    /// Seconds to wait for the API, 30 by default
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// A code interpreter tool, running a WASI module that interprets the code
+/// the model writes, e.g. a WebAssembly build of `CPython`
+///
+/// Runs have no network access, and no filesystem access beyond `mounts`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CodeInterpreterConfig {
+    /// Path of the module, a `.wasm` or `.wat` file
+    pub module: String,
+    /// Language of the code, e.g. `python`, named in the tool's description
+    pub language: String,
+    /// Arguments of the module, in which `{code}` is replaced by the code;
+    /// the code is written to standard input if no argument has `{code}`
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables of the module
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Host directories the module may read, keyed by their path in the
+    /// sandbox, e.g. `{ "/lib" = "/opt/python/lib" }` for a standard library
+    #[serde(default)]
+    pub mounts: HashMap<String, String>,
+    /// Largest memory of a run in MiB, 256 by default
+    #[serde(default)]
+    pub max_memory_mb: Option<usize>,
+    /// Fuel a run may use, about one unit per WebAssembly instruction;
+    /// unlimited by default
+    #[serde(default)]
+    pub max_fuel: Option<u64>,
+    /// Seconds a run may take, 10 by default
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Bytes of standard output, and of standard error, returned to the
+    /// model, 64 KiB by default
+    #[serde(default)]
+    pub max_output_bytes: Option<usize>,
+}
+
+/// A search API
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchProvider {
+    /// The Brave Search API
+    Brave,
+    /// The Tavily search API
+    Tavily,
+}
+
+/// An inbound header forwarded to the upstream request
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ForwardHeader {
+    /// A header forwarded under its own name
+    Name(String),
+    /// A header forwarded under another name, e.g.
+    /// `{ name = "X-Session-Id", rename = "X-Conversation-Id" }`
+    Renamed {
+        /// Name of the inbound header
+        name: String,
+        /// Name of the upstream header
+        rename: String,
+    },
+}
+
+/// Upstream headers the proxy sets itself, which routes can't forward
+pub const RESERVED_HEADERS: &[&str] = &[
+    "authorization",
+    "api-key",
+    "x-api-key",
+    "proxy-authorization",
+    "connection",
+    "content-length",
+    "content-type",
+    "host",
+    "transfer-encoding",
+];
+
+impl ForwardHeader {
+    /// Name of the inbound header
+    #[must_use]
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Name(name) | Self::Renamed { name, .. } => name,
+        }
+    }
+
+    /// Name the header is forwarded under
+    #[must_use]
+    pub fn upstream_name(&self) -> &str {
+        match self {
+            Self::Name(name) | Self::Renamed { rename: name, .. } => name,
+        }
+    }
+
+    /// Whether the header is forwarded under one of the [`RESERVED_HEADERS`]
+    #[must_use]
+    pub fn is_reserved(&self) -> bool {
+        RESERVED_HEADERS
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(self.upstream_name()))
+    }
+}
+
+/// Whether `model` matches one of `patterns`, names or prefixes ending with `*`
+#[must_use]
+pub fn matches_model(patterns: &[String], model: &str) -> bool {
+    patterns.iter().any(|pattern| {
+        pattern
+            .strip_suffix('*')
+            .map_or(pattern == model, |prefix| model.starts_with(prefix))
+    })
+}
+
+pub(crate) const fn default_true() -> bool {
+    true
+}
+
+impl RouteConfig {
+    /// ID of the route, its `id` or else derived from its match conditions
+    ///
+    /// The derived ID of a route matching only a path prefix is the prefix.
+    /// Otherwise it lists the methods, the path condition and the required
+    /// headers, e.g. `GET,POST glob:/v1/*/models x-env=staging`.
+    #[must_use]
+    pub fn id(&self) -> String {
+        if let Some(id) = &self.id {
+            return id.clone();
+        }
+        let path = match (
+            &self.path,
+            &self.path_prefix,
+            &self.path_glob,
+            &self.path_regex,
+        ) {
+            (Some(path), ..) => format!("exact:{path}"),
+            (None, Some(prefix), ..) => prefix.clone(),
+            (None, None, Some(glob), _) => format!("glob:{glob}"),
+            (None, None, None, Some(regex)) => format!("regex:{regex}"),
+            (None, None, None, None) => String::new(),
+        };
+        let mut parts = Vec::new();
+        if !self.methods.is_empty() {
+            parts.push(self.methods.join(",").to_ascii_uppercase());
+        }
+        parts.push(path);
+        let mut headers: Vec<_> = self
+            .match_headers
+            .iter()
+            .map(|(name, value)| format!("{}={value}", name.to_ascii_lowercase()))
+            .collect();
+        headers.sort();
+        parts.extend(headers);
+        parts.join(" ")
+    }
+
+    /// Static upstream headers of this route merged with those of its target LLM
+    #[must_use]
+    pub fn upstream_headers(&self, llm_config: &LLMConfig) -> HashMap<String, String> {
+        // Header names are case-insensitive, so route headers replace LLM headers in any case
+        llm_config
+            .headers
+            .iter()
+            .chain(&self.headers)
+            .map(|(name, value)| (name.to_ascii_lowercase(), value.clone()))
+            .collect()
+    }
+
+    /// Check that `forward_headers` doesn't forward any of the [`RESERVED_HEADERS`]
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error naming the first reserved header.
+    pub fn check_forward_headers(&self) -> anyhow::Result<()> {
+        match self
+            .forward_headers
+            .iter()
+            .find(|header| header.is_reserved())
+        {
+            Some(header) => anyhow::bail!(
+                "The {} header is set by the proxy and can't be forwarded",
+                header.upstream_name()
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// The headers of an inbound request with `headers` forwarded to the
+    /// upstream request, by their upstream names; the [`RESERVED_HEADERS`]
+    /// never are
+    #[must_use]
+    pub fn forwarded_headers(&self, headers: &HashMap<String, String>) -> Vec<(String, String)> {
+        self.forward_headers
+            .iter()
+            .filter(|header| !header.is_reserved())
+            .filter_map(|header| {
+                let value = headers.get(&header.name().to_ascii_lowercase())?;
+                Some((header.upstream_name().to_string(), value.clone()))
+            })
+            .collect()
+    }
+
+    /// IDs of the LLMs requests on this route may be sent to, `target_llm` or those of `targets`
+    #[must_use]
+    pub fn target_llms(&self) -> Vec<&str> {
+        if self.targets.is_empty() {
+            vec![self.target_llm.as_str()]
+        } else {
+            self.targets
+                .iter()
+                .map(|target| target.llm.as_str())
+                .collect()
+        }
+    }
+
+    /// Whether requests on this route may ask for `model`
+    #[must_use]
+    pub fn allows_model(&self, model: &str) -> bool {
+        (self.allowed_models.is_empty() || matches_model(&self.allowed_models, model))
+            && !matches_model(&self.denied_models, model)
+    }
+
+    /// Largest request body accepted on this route, its own limit or else the server's
+    #[must_use]
+    pub fn max_body_bytes(&self, server: &ServerConfig) -> Option<usize> {
+        self.max_body_bytes.or(server.max_body_bytes)
+    }
+
+    /// Retries of this route's requests, its own or else its target LLM's
+    #[must_use]
+    pub fn retry<'a>(&'a self, llm_config: &'a LLMConfig) -> Option<&'a RetryConfig> {
+        self.retry.as_ref().or(llm_config.retry.as_ref())
+    }
+}
+
 /// Server-specific configuration settings
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ServerConfig {
     /// Host address to bind to
     pub host: IpAddr,
@@ -60,38 +1269,287 @@ pub struct ServerConfig {
     pub request_timeout_secs: u64,
     /// CORS allowed origins
     pub cors_allowed_origins: Vec<String>,
+    /// Largest request body accepted on routes without a limit of their own, in bytes
+    #[serde(default)]
+    pub max_body_bytes: Option<usize>,
+    /// Number of worker threads; one per CPU if unset
+    #[serde(default)]
+    pub workers: Option<usize>,
+    /// Seconds idle connections are kept alive; 0 disables keep-alive
+    #[serde(default)]
+    pub keep_alive_secs: Option<u64>,
+    /// Seconds clients have to send the head of a request; 0 disables the timeout
+    #[serde(default)]
+    pub client_request_timeout_secs: Option<u64>,
+    /// Concurrent connections accepted per worker
+    #[serde(default)]
+    pub max_connections: Option<usize>,
+    /// Pending connections queued before new ones are refused
+    #[serde(default)]
+    pub backlog: Option<u32>,
+    /// Compress responses other than event streams as clients accept
+    #[serde(default)]
+    pub compress_responses: bool,
+}
+
+/// Destination of the access log
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AccessLogConfig {
+    /// File the log is appended to, rolled over by size; stdout if unset
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Size from which the file is rolled over
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    /// Number of rolled over files kept
+    #[serde(default)]
+    pub max_files: Option<usize>,
+}
+
+/// Cache of chat completion responses
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CacheConfig {
+    /// Entries held at most, evicting the oldest beyond
+    #[serde(default)]
+    pub max_entries: Option<usize>,
+    /// Seconds after which entries expire; they don't if unset
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+    /// About how many characters of content each chunk replaying a cached
+    /// response to a streaming request carries
+    #[serde(default)]
+    pub replay_chunk_chars: Option<usize>,
+    /// Milliseconds between the chunks replaying a cached response
+    #[serde(default)]
+    pub replay_delay_ms: Option<u64>,
+}
+
+/// Prometheus metrics of the proxy
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MetricsConfig {
+    /// Path the metrics are served on
+    #[serde(default = "default_metrics_path")]
+    pub path: String,
+    /// Upper bounds of the buckets histograms are rendered with; histograms
+    /// are rendered as summaries if unset
+    #[serde(default)]
+    pub buckets: Option<Vec<f64>>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            path: default_metrics_path(),
+            buckets: None,
+        }
+    }
+}
+
+fn default_metrics_path() -> String {
+    "/metrics".to_string()
+}
+
+/// Publishing of request events to a message broker
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EventsConfig {
+    /// Broker the events are published to
+    #[serde(flatten)]
+    pub broker: EventBrokerConfig,
+    /// Topics, or subjects for NATS, the events are published to
+    #[serde(default)]
+    pub topics: EventTopicsConfig,
+}
+
+/// Message broker of request events
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventBrokerConfig {
+    /// Kafka cluster (requires the `kafka` feature)
+    Kafka {
+        /// Comma-separated bootstrap servers, e.g. `kafka-1:9092,kafka-2:9092`
+        brokers: String,
+        /// Additional producer properties of librdkafka, e.g. `security.protocol`
+        #[serde(default)]
+        properties: HashMap<String, String>,
+    },
+    /// NATS server (requires the `nats` feature)
+    Nats {
+        /// URL of the server, e.g. `nats://localhost:4222`
+        url: String,
+        /// Environment variable holding the token to authenticate with
+        #[serde(default)]
+        token_env: Option<String>,
+    },
+}
+
+/// Topics of request events, defaulting to `llm_proxy.request.<event>`
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct EventTopicsConfig {
+    /// Topic of requests that started running through a pipeline
+    #[serde(default)]
+    pub started: Option<String>,
+    /// Topic of requests that completed, with their usage
+    #[serde(default)]
+    pub completed: Option<String>,
+    /// Topic of requests that failed
+    #[serde(default)]
+    pub failed: Option<String>,
+}
+
+/// A webhook the proxy posts Slack-compatible JSON notifications to
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WebhookConfig {
+    /// URL notifications are posted to
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Environment variable holding the URL, for URLs embedding a secret
+    #[serde(default)]
+    pub url_env: Option<String>,
+    /// Events notified, all of them by default
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+    /// Consecutive failed requests on a route from which they are notified (default: 5)
+    #[serde(default)]
+    pub failure_threshold: Option<u32>,
+    /// Seconds before an event is notified again for the same route,
+    /// endpoint, client key or file (default: 300)
+    #[serde(default)]
+    pub min_interval_secs: Option<u64>,
+    /// Retries of notifications the webhook fails to accept (default: 3)
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+    /// Timeout of a delivery attempt in seconds (default: 10)
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// Events a webhook can be notified of
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// Requests on a route keep failing
+    UpstreamFailures,
+    /// A health check took an upstream endpoint out of rotation
+    CircuitOpen,
+    /// A client key used up a limit of its quota
+    QuotaExhausted,
+    /// A reloaded configuration file is invalid
+    ReloadFailed,
+}
+
+/// Admin API reporting the proxy's routes, pipelines, configuration and limits
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AdminConfig {
+    /// Environment variable containing the bearer token admin requests must carry
+    pub token_env: String,
+}
+
+/// gRPC interface of the proxy
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GrpcConfig {
+    /// Host address to bind to, the server's by default
+    #[serde(default)]
+    pub host: Option<IpAddr>,
+    /// Port to listen on
+    pub port: u16,
+}
+
+/// Processor plugins
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PluginsConfig {
+    /// Directory whose shared libraries are loaded as plugins
+    pub dir: String,
+}
+
+/// Export of the proxy's tracing spans as OpenTelemetry traces
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TelemetryConfig {
+    /// OTLP endpoint of the collector, e.g. `http://localhost:4317` for gRPC
+    /// or `http://localhost:4318/v1/traces` for HTTP
+    pub otlp_endpoint: String,
+    /// Protocol spoken with the collector
+    #[serde(default)]
+    pub protocol: OtlpProtocol,
+    /// Name the proxy reports itself as
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// Share of traces started by the proxy that are sampled, from 0 to 1;
+    /// traces continued from a client follow the client's sampling decision
+    #[serde(default = "default_sample_ratio")]
+    pub sample_ratio: f64,
+}
+
+/// Protocol of an OTLP exporter
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OtlpProtocol {
+    /// gRPC, usually on port 4317
+    #[default]
+    Grpc,
+    /// Protobuf over HTTP, usually on port 4318
+    Http,
+}
+
+fn default_service_name() -> String {
+    "llm-proxy".to_string()
+}
+
+const fn default_sample_ratio() -> f64 {
+    1.0
 }
 
 impl Config {
-    /// Load configuration from a TOML file
+    /// Load configuration from a TOML, YAML or JSON file
+    ///
+    /// See [`Config::from_files`].
     ///
     /// # Errors
     ///
     /// This function will return an error if the configuration file is not found or
     /// if the configuration is invalid.
-    #[must_use]
     pub fn from_file(path: &str) -> anyhow::Result<Self> {
-        let config = config::Config::builder()
-            .add_source(config::File::with_name(path))
+        Self::from_files(&[path])
+    }
+
+    /// Load configuration from TOML, YAML or JSON files layered in order
+    ///
+    /// The format of each file is detected from its extension (`.toml`,
+    /// `.yaml`, `.yml` or `.json`); paths without one are looked up with each
+    /// of these extensions. Values of later files override those of earlier
+    /// ones, and environment variables prefixed with [`ENV_PREFIX`] override
+    /// them all.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a configuration file is not
+    /// found or has an unsupported extension, or if the configuration is invalid.
+    pub fn from_files(paths: &[impl AsRef<str>]) -> anyhow::Result<Self> {
+        let mut builder = config::Config::builder();
+        for path in paths {
+            let path = path.as_ref();
+            let file = file_format(path)?.map_or_else(
+                || config::File::with_name(path),
+                |format| config::File::new(path, format),
+            );
+            builder = builder.add_source(file);
+        }
+        let config = builder
+            .add_source(
+                config::Environment::with_prefix(ENV_PREFIX)
+                    .prefix_separator("__")
+                    .separator("__")
+                    .try_parsing(true),
+            )
             .build()?;
 
         config.try_deserialize().map_err(|e| anyhow::anyhow!(e))
     }
 
-    /// Get a route configuration that matches the given path
-    #[must_use]
-    pub fn find_route(&self, path: &str) -> Option<&RouteConfig> {
-        self.route
-            .iter()
-            .find(|route| path.starts_with(&route.path_prefix))
-    }
-
     /// Get an LLM configuration by ID
     ///
     /// # Errors
     ///
     /// This function will return an error if the LLM configuration is not found.
-    #[must_use]
     pub fn get_llm(&self, id: &str) -> anyhow::Result<&LLMConfig> {
         self.llm
             .get(id)
@@ -103,10 +1561,291 @@ impl Config {
     /// # Errors
     ///
     /// This function will return an error if the processor configuration is not found.
-    #[must_use]
     pub fn get_processor(&self, id: &str) -> anyhow::Result<&ProcessorConfig> {
         self.processor
             .get(id)
             .ok_or_else(|| anyhow::anyhow!("Processor configuration not found for ID: {id}"))
     }
 }
+
+/// The format of the configuration file at `path`, by extension
+pub(crate) fn file_format(path: &str) -> anyhow::Result<Option<config::FileFormat>> {
+    let Some(extension) = Path::new(path).extension() else {
+        return Ok(None);
+    };
+    match extension.to_string_lossy().to_ascii_lowercase().as_str() {
+        "toml" => Ok(Some(config::FileFormat::Toml)),
+        "yaml" | "yml" => Ok(Some(config::FileFormat::Yaml)),
+        "json" => Ok(Some(config::FileFormat::Json)),
+        _ => anyhow::bail!(
+            "Unsupported configuration file {path}, expected a .toml, .yaml, .yml or .json file"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    const TOML: &str = r#"
+[server]
+host = "127.0.0.1"
+port = 3000
+log_level = "info"
+request_timeout_secs = 30
+cors_allowed_origins = []
+
+[llm.openai_chat]
+provider = "openai"
+type = "chat"
+base_url = "https://api.openai.com/v1"
+token_env = "OPENAI_API_KEY"
+supports_streaming = true
+
+[processor.logger]
+type = "logger"
+
+[[route]]
+path_prefix = "/v1/chat/completions"
+target_llm = "openai_chat"
+"#;
+
+    const YAML: &str = "
+server:
+  host: 127.0.0.1
+  port: 3000
+  log_level: info
+  request_timeout_secs: 30
+  cors_allowed_origins: []
+llm:
+  openai_chat:
+    provider: openai
+    type: chat
+    base_url: https://api.openai.com/v1
+    token_env: OPENAI_API_KEY
+    supports_streaming: true
+processor:
+  logger:
+    type: logger
+route:
+  - path_prefix: /v1/chat/completions
+    target_llm: openai_chat
+";
+
+    const JSON: &str = r#"{
+  "server": {
+    "host": "127.0.0.1",
+    "port": 3000,
+    "log_level": "info",
+    "request_timeout_secs": 30,
+    "cors_allowed_origins": []
+  },
+  "llm": {
+    "openai_chat": {
+      "provider": "openai",
+      "type": "chat",
+      "base_url": "https://api.openai.com/v1",
+      "token_env": "OPENAI_API_KEY",
+      "supports_streaming": true
+    }
+  },
+  "processor": {"logger": {"type": "logger"}},
+  "route": [{"path_prefix": "/v1/chat/completions", "target_llm": "openai_chat"}]
+}"#;
+
+    /// Write `contents` to a temporary file named `name`
+    fn write(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("llm-proxy-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Failed to create directory");
+        let path = dir.join(name);
+        std::fs::write(&path, contents).expect("Failed to write config");
+        path
+    }
+
+    fn path(path: &Path) -> &str {
+        path.to_str().expect("Invalid path")
+    }
+
+    #[test]
+    fn test_file_format() {
+        for (file, format) in [
+            ("config.toml", Some(config::FileFormat::Toml)),
+            ("config.TOML", Some(config::FileFormat::Toml)),
+            ("config.yaml", Some(config::FileFormat::Yaml)),
+            ("config.yml", Some(config::FileFormat::Yaml)),
+            ("config.json", Some(config::FileFormat::Json)),
+            ("config", None),
+        ] {
+            assert_eq!(
+                file_format(file).expect("Unsupported format"),
+                format,
+                "{file}"
+            );
+        }
+        assert!(file_format("config.ini").is_err());
+    }
+
+    #[test]
+    fn test_from_files_detects_format() {
+        for (name, contents) in [
+            ("format.toml", TOML),
+            ("format.yaml", YAML),
+            ("format.yml", YAML),
+            ("format.json", JSON),
+        ] {
+            let file = write(name, contents);
+            let config = Config::from_file(path(&file)).expect(name);
+            assert_eq!(config.server.port, 3000, "{name}");
+            assert_eq!(
+                config.get_llm("openai_chat").expect(name).base_url,
+                "https://api.openai.com/v1",
+                "{name}"
+            );
+            assert_eq!(config.route[0].target_llm, "openai_chat", "{name}");
+            std::fs::remove_file(file).ok();
+        }
+
+        // Without an extension, each format is tried
+        let file = write("extensionless.yaml", YAML);
+        let config = Config::from_file(path(&file.with_extension(""))).expect("No config");
+        assert_eq!(config.server.port, 3000);
+        std::fs::remove_file(file).ok();
+
+        let file = write("format.ini", TOML);
+        assert!(Config::from_file(path(&file)).is_err());
+        std::fs::remove_file(file).ok();
+    }
+
+    #[test]
+    fn test_from_files_layers_in_order() {
+        let base = write("base.toml", TOML);
+        let staging = write(
+            "staging.yaml",
+            "server:\n  port: 4000\n  host: 0.0.0.0\nllm:\n  openai_chat:\n    base_url: https://staging.example.com/v1\n",
+        );
+        let local = write("local.json", r#"{"server": {"port": 5000}}"#);
+        // Environment variables override every file
+        std::env::set_var("LLM_PROXY__SERVER__LOG_LEVEL", "debug");
+
+        let config =
+            Config::from_files(&[path(&base), path(&staging), path(&local)]).expect("No config");
+        assert_eq!(config.server.port, 5000);
+        assert_eq!(config.server.host.to_string(), "0.0.0.0");
+        assert_eq!(config.server.log_level, "debug");
+        let llm = config.get_llm("openai_chat").expect("No LLM");
+        assert_eq!(llm.base_url, "https://staging.example.com/v1");
+        assert_eq!(llm.token_env, "OPENAI_API_KEY");
+
+        let config =
+            Config::from_files(&[path(&local), path(&staging), path(&base)]).expect("No config");
+        assert_eq!(config.server.port, 3000);
+        assert_eq!(config.server.host.to_string(), "127.0.0.1");
+        assert_eq!(config.server.log_level, "debug");
+
+        std::env::remove_var("LLM_PROXY__SERVER__LOG_LEVEL");
+        for file in [base, staging, local] {
+            std::fs::remove_file(file).ok();
+        }
+    }
+
+    #[test]
+    fn test_upstream_headers() {
+        let llm: LLMConfig = serde_json::from_value(serde_json::json!({
+            "provider": "openai",
+            "type": "chat",
+            "base_url": "https://api.openai.com/v1/chat/completions",
+            "token_env": "OPENAI_API_KEY",
+            "supports_streaming": true,
+            "headers": {"Helicone-Auth": "Bearer sk-helicone", "X-Gateway": "llm"},
+        }))
+        .expect("Invalid LLM");
+        let route = |headers: serde_json::Value| -> RouteConfig {
+            serde_json::from_value(serde_json::json!({
+                "path_prefix": "/v1/chat/completions",
+                "target_llm": "openai_chat",
+                "headers": headers,
+            }))
+            .expect("Invalid route")
+        };
+
+        let expected = |gateway: &str| {
+            HashMap::from([
+                (
+                    "helicone-auth".to_string(),
+                    "Bearer sk-helicone".to_string(),
+                ),
+                ("x-gateway".to_string(), gateway.to_string()),
+            ])
+        };
+        assert_eq!(
+            route(serde_json::json!({})).upstream_headers(&llm),
+            expected("llm")
+        );
+        // The route's headers win, whatever the case of their names
+        assert_eq!(
+            route(serde_json::json!({"x-GATEWAY": "route"})).upstream_headers(&llm),
+            expected("route")
+        );
+    }
+
+    fn forwarding_route(forward_headers: &serde_json::Value) -> RouteConfig {
+        serde_json::from_value(serde_json::json!({
+            "path_prefix": "/v1/chat/completions",
+            "target_llm": "openai_chat",
+            "forward_headers": forward_headers,
+        }))
+        .expect("Invalid route")
+    }
+
+    #[test]
+    fn test_forwarded_headers() {
+        let headers: HashMap<String, String> = [
+            (
+                "traceparent",
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            ),
+            ("x-session-id", "session-1"),
+            ("authorization", "Bearer pk-client"),
+            ("x-other", "other"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+
+        // Names match inbound headers in any case, renamed headers go under their new name
+        let route = forwarding_route(&serde_json::json!([
+            "Traceparent",
+            {"name": "X-Session-Id", "rename": "X-Conversation-Id"},
+            "X-Missing",
+        ]));
+        route.check_forward_headers().expect("Rejected headers");
+        assert_eq!(
+            route.forwarded_headers(&headers),
+            [
+                (
+                    "Traceparent".to_string(),
+                    "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".to_string()
+                ),
+                ("X-Conversation-Id".to_string(), "session-1".to_string()),
+            ]
+        );
+
+        // Headers the proxy sets are refused and never forwarded
+        for forward_headers in [
+            serde_json::json!(["traceparent", "Authorization"]),
+            serde_json::json!([{"name": "X-Session-Id", "rename": "x-api-key"}]),
+        ] {
+            let route = forwarding_route(&forward_headers);
+            let error = route
+                .check_forward_headers()
+                .expect_err("Accepted a reserved header");
+            assert!(error.to_string().contains("set by the proxy"), "{error}");
+            assert!(route
+                .forwarded_headers(&headers)
+                .iter()
+                .all(|(name, _)| !RESERVED_HEADERS.contains(&name.to_ascii_lowercase().as_str())));
+        }
+    }
+}
@@ -0,0 +1,354 @@
+//! Events of the requests the server handles, published to a message broker.
+//!
+//! With an `[events]` section, the proxy publishes a JSON event to Kafka or
+//! NATS when a chat request starts running through a pipeline, and when a
+//! request ends, so analytics and billing systems can follow its activity as
+//! it happens. Each event has a `type`:
+//!
+//! - `request.started`, with the `timestamp` in milliseconds since the Unix
+//!   epoch and the `request_id`, `route`, `model` and `client` of the request
+//! - `request.completed`, with the fields of the request's access log line,
+//!   including its `status`, `latency_ms`, `prompt_tokens` and
+//!   `completion_tokens`
+//! - `request.failed`, like `request.completed` for requests that end with an
+//!   `error` class
+//!
+//! Events are keyed by their request ID where the broker supports keys, and
+//! published in the background, so a slow or unreachable broker doesn't delay
+//! requests; events that fail to publish are logged and dropped.
+
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use llm_proxy_core::RequestContext;
+use llm_proxy_openai::processors::RequestLogSink;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::config::{EventBrokerConfig, EventsConfig};
+
+/// Topic of `request.started` events, unless `[events.topics]` sets `started`
+pub const DEFAULT_STARTED_TOPIC: &str = "llm_proxy.request.started";
+
+/// Topic of `request.completed` events, unless `[events.topics]` sets `completed`
+pub const DEFAULT_COMPLETED_TOPIC: &str = "llm_proxy.request.completed";
+
+/// Topic of `request.failed` events, unless `[events.topics]` sets `failed`
+pub const DEFAULT_FAILED_TOPIC: &str = "llm_proxy.request.failed";
+
+/// Publishes messages to the topics of a message broker
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    /// Publish `payload` to `topic`, with `key` if the broker supports keys
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the broker doesn't accept the message.
+    async fn publish(&self, topic: &str, key: &str, payload: Vec<u8>) -> Result<()>;
+}
+
+/// Publishes the events of requests
+pub struct Events {
+    publisher: Arc<dyn EventPublisher>,
+    started_topic: String,
+    completed_topic: String,
+    failed_topic: String,
+}
+
+impl Events {
+    /// Create events published by `publisher` to the default topics
+    #[must_use]
+    pub fn new(publisher: Arc<dyn EventPublisher>) -> Self {
+        Self {
+            publisher,
+            started_topic: DEFAULT_STARTED_TOPIC.to_string(),
+            completed_topic: DEFAULT_COMPLETED_TOPIC.to_string(),
+            failed_topic: DEFAULT_FAILED_TOPIC.to_string(),
+        }
+    }
+
+    /// Create the events configured in `[events]`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the broker's client cannot be
+    /// created, or the server was built without the broker's feature.
+    pub fn from_config(config: &EventsConfig) -> Result<Self> {
+        let publisher = create_publisher(&config.broker)?;
+        let topics = &config.topics;
+        let mut events = Self::new(publisher);
+        if let Some(topic) = &topics.started {
+            events.started_topic.clone_from(topic);
+        }
+        if let Some(topic) = &topics.completed {
+            events.completed_topic.clone_from(topic);
+        }
+        if let Some(topic) = &topics.failed {
+            events.failed_topic.clone_from(topic);
+        }
+        Ok(events)
+    }
+
+    /// Publish the `request.started` event of the request with `context` and
+    /// chat request `body`, whose model the pipeline hasn't read yet
+    pub fn request_started(&self, context: &RequestContext, body: &[u8]) {
+        #[derive(serde::Deserialize)]
+        struct RequestedModel {
+            model: String,
+        }
+
+        let model = context.model.clone().or_else(|| {
+            serde_json::from_slice::<RequestedModel>(body)
+                .ok()
+                .map(|requested| requested.model)
+        });
+        let event = json!({
+            "type": "request.started",
+            "timestamp": SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis()),
+            "request_id": context.request_id,
+            "route": context.route,
+            "model": model,
+            "client": context.client_key.as_ref().or(context.tenant.as_ref()),
+        });
+        let publisher = self.publisher.clone();
+        let topic = self.started_topic.clone();
+        tokio::spawn(async move {
+            if let Err(e) = publish(&*publisher, &topic, &event).await {
+                warn!(error = %e, topic, "Failed to publish request event");
+            }
+        });
+    }
+}
+
+/// Access log lines of ended requests are published as their
+/// `request.completed` or `request.failed` events
+#[async_trait]
+impl RequestLogSink for Events {
+    async fn write(&self, record: &Value) -> llm_proxy_core::Result<()> {
+        let mut event = json!({});
+        let (event_type, topic) = if record["error"].is_null() {
+            ("request.completed", &self.completed_topic)
+        } else {
+            ("request.failed", &self.failed_topic)
+        };
+        event["type"] = event_type.into();
+        if let (Value::Object(event), Value::Object(record)) = (&mut event, record) {
+            event.extend(record.clone());
+        }
+        publish(&*self.publisher, topic, &event).await?;
+        Ok(())
+    }
+}
+
+/// Publish `event` to `topic`, keyed by its request ID
+async fn publish(publisher: &dyn EventPublisher, topic: &str, event: &Value) -> Result<()> {
+    let key = event["request_id"].as_str().unwrap_or_default();
+    publisher
+        .publish(topic, key, serde_json::to_vec(event)?)
+        .await
+}
+
+/// The publisher of the broker configured by `config`
+fn create_publisher(config: &EventBrokerConfig) -> Result<Arc<dyn EventPublisher>> {
+    match config {
+        #[cfg(feature = "kafka")]
+        EventBrokerConfig::Kafka {
+            brokers,
+            properties,
+        } => Ok(Arc::new(kafka::KafkaPublisher::new(brokers, properties)?)),
+        #[cfg(not(feature = "kafka"))]
+        EventBrokerConfig::Kafka { .. } => {
+            anyhow::bail!("Kafka events need the server built with the kafka feature")
+        }
+        #[cfg(feature = "nats")]
+        EventBrokerConfig::Nats { url, token_env } => Ok(Arc::new(nats::NatsPublisher::new(
+            url,
+            token_env.as_deref(),
+        )?)),
+        #[cfg(not(feature = "nats"))]
+        EventBrokerConfig::Nats { .. } => {
+            anyhow::bail!("NATS events need the server built with the nats feature")
+        }
+    }
+}
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    use std::{collections::HashMap, time::Duration};
+
+    use anyhow::{anyhow, Context, Result};
+    use async_trait::async_trait;
+    use rdkafka::{
+        producer::{FutureProducer, FutureRecord},
+        ClientConfig,
+    };
+
+    use super::EventPublisher;
+
+    /// Publishes events to the topics of a Kafka cluster
+    pub struct KafkaPublisher {
+        producer: FutureProducer,
+    }
+
+    impl KafkaPublisher {
+        /// Create a producer for the cluster of `brokers`, with additional
+        /// librdkafka `properties`
+        pub fn new(brokers: &str, properties: &HashMap<String, String>) -> Result<Self> {
+            let mut config = ClientConfig::new();
+            config.set("bootstrap.servers", brokers);
+            for (key, value) in properties {
+                config.set(key, value);
+            }
+            let producer = config
+                .create()
+                .context("Failed to create the Kafka producer")?;
+            Ok(Self { producer })
+        }
+    }
+
+    #[async_trait]
+    impl EventPublisher for KafkaPublisher {
+        async fn publish(&self, topic: &str, key: &str, payload: Vec<u8>) -> Result<()> {
+            let record = FutureRecord::to(topic).key(key).payload(&payload);
+            // Fail rather than wait when the producer's queue is full
+            self.producer
+                .send(record, Duration::ZERO)
+                .await
+                .map_err(|(e, _)| anyhow!("Failed to publish to Kafka topic {topic}: {e}"))?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "nats")]
+mod nats {
+    use anyhow::{Context, Result};
+    use async_nats::{Client, ConnectOptions};
+    use async_trait::async_trait;
+    use llm_proxy_core::redact::SecretString;
+    use tokio::sync::OnceCell;
+
+    use super::EventPublisher;
+
+    /// Publishes events to the subjects of a NATS server
+    pub struct NatsPublisher {
+        url: String,
+        token: Option<SecretString>,
+        client: OnceCell<Client>,
+    }
+
+    impl NatsPublisher {
+        /// Create a publisher to the server at `url`, authenticating with
+        /// the token in `token_env` if set
+        pub fn new(url: &str, token_env: Option<&str>) -> Result<Self> {
+            let token = token_env
+                .map(|token_env| {
+                    std::env::var(token_env)
+                        .map(SecretString::from)
+                        .with_context(|| format!("Failed to read NATS token from {token_env}"))
+                })
+                .transpose()?;
+            Ok(Self {
+                url: url.to_string(),
+                token,
+                client: OnceCell::new(),
+            })
+        }
+
+        /// The client, connecting on first use and after failed attempts
+        async fn client(&self) -> Result<&Client> {
+            self.client
+                .get_or_try_init(|| async {
+                    let mut options = ConnectOptions::new();
+                    if let Some(token) = &self.token {
+                        options = options.token(token.expose().to_string());
+                    }
+                    options
+                        .connect(self.url.as_str())
+                        .await
+                        .with_context(|| format!("Failed to connect to NATS at {}", self.url))
+                })
+                .await
+        }
+    }
+
+    #[async_trait]
+    impl EventPublisher for NatsPublisher {
+        async fn publish(&self, topic: &str, _key: &str, payload: Vec<u8>) -> Result<()> {
+            self.client()
+                .await?
+                .publish(topic.to_string(), payload.into())
+                .await
+                .with_context(|| format!("Failed to publish to NATS subject {topic}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Publisher recording the messages it is given
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<(String, String, Value)>>);
+
+    #[async_trait]
+    impl EventPublisher for Recorder {
+        async fn publish(&self, topic: &str, key: &str, payload: Vec<u8>) -> Result<()> {
+            let event = serde_json::from_slice(&payload)?;
+            self.0
+                .lock()
+                .expect("Poisoned")
+                .push((topic.to_string(), key.to_string(), event));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_events() {
+        let recorder = Arc::new(Recorder::default());
+        let mut events = Events::new(recorder.clone());
+        events.failed_topic = "errors".to_string();
+
+        let context = RequestContext::new()
+            .with_request_id("req-1")
+            .with_route("chat")
+            .with_client_key("ci-bot");
+        events.request_started(&context, br#"{"model":"gpt-4o","messages":[]}"#);
+        tokio::task::yield_now().await;
+
+        let record = json!({ "request_id": "req-1", "status": 200, "prompt_tokens": 12 });
+        events.write(&record).await.expect("Failed to publish");
+        let record = json!({ "request_id": "req-2", "status": 502, "error": "upstream" });
+        events.write(&record).await.expect("Failed to publish");
+
+        let published = recorder.0.lock().expect("Poisoned").clone();
+        let (topic, key, started) = &published[0];
+        assert_eq!(
+            (topic.as_str(), key.as_str()),
+            (DEFAULT_STARTED_TOPIC, "req-1")
+        );
+        assert_eq!(started["type"], "request.started");
+        assert_eq!(started["route"], "chat");
+        assert_eq!(started["model"], "gpt-4o");
+        assert_eq!(started["client"], "ci-bot");
+
+        let (topic, _, completed) = &published[1];
+        assert_eq!(topic, DEFAULT_COMPLETED_TOPIC);
+        assert_eq!(completed["type"], "request.completed");
+        assert_eq!(completed["prompt_tokens"], 12);
+
+        let (topic, key, failed) = &published[2];
+        assert_eq!((topic.as_str(), key.as_str()), ("errors", "req-2"));
+        assert_eq!(failed["type"], "request.failed");
+        assert_eq!(failed["error"], "upstream");
+    }
+}
@@ -0,0 +1,621 @@
+//! gRPC interface for running chat requests through the routes' pipelines.
+//!
+//! With the `grpc` feature, a `[grpc]` section serves the `llm_proxy.v1.Pipeline`
+//! service of `proto/llm_proxy.proto` on a port of its own, for internal
+//! services that prefer gRPC over HTTP and server-sent events. `Execute`
+//! runs a [`proto::ChatRequest`] on the route it names by ID and streams the
+//! completion back as [`proto::Chunk`]s. Calls go through the route's client
+//! key checks, quotas, limits and access log like HTTP requests; keys are
+//! sent in `authorization` metadata, and errors map to gRPC status codes.
+//!
+//! The message types are written by hand to match the protobuf definitions,
+//! so the build needs no `protoc`; change both together.
+
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use actix_web::{body::MessageBody, HttpResponse};
+use bytes::Bytes;
+use llm_proxy_core::{RequestContext, ResponseStream};
+use serde_json::{json, Map, Value};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{
+    codegen::{http, Body, BoxFuture, Service, StdError},
+    metadata::{MetadataKey, MetadataValue},
+    server::{NamedService, ServerStreamingService},
+    Code, Request, Response, Status,
+};
+use tracing::{error, info};
+
+use crate::{
+    access_log::AccessLogEntry,
+    app::{self, AppState},
+    config::{ApiFormat, RouteConfig},
+    inbound::EventData,
+    payload,
+};
+
+/// Path of the `Execute` method
+const EXECUTE_PATH: &str = "/llm_proxy.v1.Pipeline/Execute";
+
+/// Messages of the `llm_proxy.v1` package
+#[allow(missing_docs)]
+pub mod proto {
+    /// A chat request and the route running it
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ChatRequest {
+        #[prost(string, tag = "1")]
+        pub route: String,
+        #[prost(string, tag = "2")]
+        pub model: String,
+        #[prost(message, repeated, tag = "3")]
+        pub messages: Vec<ChatMessage>,
+        #[prost(double, optional, tag = "4")]
+        pub temperature: Option<f64>,
+        #[prost(double, optional, tag = "5")]
+        pub top_p: Option<f64>,
+        #[prost(uint32, optional, tag = "6")]
+        pub max_tokens: Option<u32>,
+        #[prost(string, repeated, tag = "7")]
+        pub stop: Vec<String>,
+        #[prost(string, optional, tag = "8")]
+        pub user: Option<String>,
+        #[prost(string, tag = "9")]
+        pub extra_json: String,
+    }
+
+    /// A message of a chat request
+    #[derive(Clone, PartialEq, Eq, prost::Message)]
+    pub struct ChatMessage {
+        #[prost(string, tag = "1")]
+        pub role: String,
+        #[prost(string, tag = "2")]
+        pub content: String,
+        #[prost(string, optional, tag = "3")]
+        pub name: Option<String>,
+        #[prost(string, optional, tag = "4")]
+        pub tool_call_id: Option<String>,
+        #[prost(message, repeated, tag = "5")]
+        pub tool_calls: Vec<ToolCall>,
+    }
+
+    /// A tool call, or the next part of a streamed one
+    #[derive(Clone, PartialEq, Eq, prost::Message)]
+    pub struct ToolCall {
+        #[prost(uint32, tag = "1")]
+        pub index: u32,
+        #[prost(string, tag = "2")]
+        pub id: String,
+        #[prost(string, tag = "3")]
+        pub name: String,
+        #[prost(string, tag = "4")]
+        pub arguments: String,
+    }
+
+    /// A chunk of a completion
+    #[derive(Clone, PartialEq, Eq, prost::Message)]
+    pub struct Chunk {
+        #[prost(string, tag = "1")]
+        pub id: String,
+        #[prost(string, tag = "2")]
+        pub model: String,
+        #[prost(int64, tag = "3")]
+        pub created: i64,
+        #[prost(message, repeated, tag = "4")]
+        pub choices: Vec<Choice>,
+        #[prost(message, optional, tag = "5")]
+        pub usage: Option<Usage>,
+    }
+
+    /// The part of a choice in a chunk
+    #[derive(Clone, PartialEq, Eq, prost::Message)]
+    pub struct Choice {
+        #[prost(uint32, tag = "1")]
+        pub index: u32,
+        #[prost(string, optional, tag = "2")]
+        pub role: Option<String>,
+        #[prost(string, tag = "3")]
+        pub content: String,
+        #[prost(message, repeated, tag = "4")]
+        pub tool_calls: Vec<ToolCall>,
+        #[prost(string, optional, tag = "5")]
+        pub finish_reason: Option<String>,
+    }
+
+    /// Token usage of a completion
+    #[derive(Clone, PartialEq, Eq, prost::Message)]
+    pub struct Usage {
+        #[prost(uint64, tag = "1")]
+        pub prompt_tokens: u64,
+        #[prost(uint64, tag = "2")]
+        pub completion_tokens: u64,
+        #[prost(uint64, tag = "3")]
+        pub total_tokens: u64,
+    }
+}
+
+/// Serve the gRPC interface on `addr` with the state of the HTTP server
+///
+/// # Errors
+///
+/// This function will return an error if `addr` can't be bound.
+pub fn spawn(addr: SocketAddr, state: Arc<AppState>) -> anyhow::Result<()> {
+    let incoming = tonic::transport::server::TcpIncoming::bind(addr)?;
+    tokio::spawn(async move {
+        let server = tonic::transport::Server::builder()
+            .serve_with_incoming(PipelineService { state }, incoming);
+        if let Err(e) = server.await {
+            error!(error = %e, "gRPC server failed");
+        }
+    });
+    info!("gRPC server running at {addr}");
+    Ok(())
+}
+
+/// The `llm_proxy.v1.Pipeline` service
+#[derive(Clone)]
+pub struct PipelineService {
+    state: Arc<AppState>,
+}
+
+impl NamedService for PipelineService {
+    const NAME: &'static str = "llm_proxy.v1.Pipeline";
+}
+
+impl<B> Service<http::Request<B>> for PipelineService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::Body>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let service = self.clone();
+        Box::pin(async move {
+            if req.uri().path() != EXECUTE_PATH {
+                return Ok(
+                    Status::unimplemented(format!("Unknown method {}", req.uri().path()))
+                        .into_http(),
+                );
+            }
+            let mut grpc = tonic::server::Grpc::new(tonic_prost::ProstCodec::default());
+            Ok(grpc.server_streaming(Execute(service), req).await)
+        })
+    }
+}
+
+/// The `Execute` method of a [`PipelineService`]
+struct Execute(PipelineService);
+
+impl ServerStreamingService<proto::ChatRequest> for Execute {
+    type Response = proto::Chunk;
+    type ResponseStream = ReceiverStream<Result<proto::Chunk, Status>>;
+    type Future = BoxFuture<Response<Self::ResponseStream>, Status>;
+
+    fn call(&mut self, request: Request<proto::ChatRequest>) -> Self::Future {
+        let service = self.0.clone();
+        Box::pin(async move { service.execute(request).await })
+    }
+}
+
+impl PipelineService {
+    /// Run `request` on the route it names, streaming the chunks of its completion
+    async fn execute(
+        &self,
+        request: Request<proto::ChatRequest>,
+    ) -> Result<Response<ReceiverStream<Result<proto::Chunk, Status>>>, Status> {
+        let state = &*self.state;
+        let (metadata, _, request) = request.into_parts();
+        let index = state
+            .config
+            .route
+            .iter()
+            .position(|route| route.id() == request.route)
+            .ok_or_else(|| Status::not_found(format!("No route with ID {}", request.route)))?;
+        let route = &state.config.route[index];
+        if route.passthrough || route.api != ApiFormat::OpenAI {
+            return Err(Status::failed_precondition(format!(
+                "Route {} is not an OpenAI chat route",
+                request.route
+            )));
+        }
+
+        // Validated as the HTTP server's, since it ends up in logs and events
+        let request_id = app::request_id_or_new(
+            metadata
+                .get("x-request-id")
+                .and_then(|value| value.to_str().ok()),
+        );
+        let mut context = self.resolve_context(&metadata, route, &request_id).await?;
+        let client_key_check = state.client_key_checks[index].as_ref();
+        if let Some(response) = client_key_check.and_then(|check| check.check(&context)) {
+            return Err(status(response));
+        }
+        let overlay = context
+            .tenant
+            .as_deref()
+            .zip(state.overlays.as_ref())
+            .and_then(|(tenant, overlays)| overlays.get(tenant));
+        if let Some(response) = app::admit(state, &mut context, overlay.as_deref()) {
+            return Err(status(response));
+        }
+
+        let streaming = route.allow_streaming;
+        let body = Bytes::from(openai_request(request, streaming)?.to_string());
+        if let Some(response) = payload::check_messages(route, &body) {
+            return Err(status(response));
+        }
+        let mut entry = state
+            .access_log
+            .as_ref()
+            .map(|_| AccessLogEntry::new("POST", EXECUTE_PATH, &request_id));
+        let result = app::execute_chat(state, index, overlay.as_deref(), body, &mut context).await;
+        if let Some(entry) = &mut entry {
+            entry.set_context(&context);
+        }
+        let rx = match (result, &state.access_log, entry) {
+            (Ok(rx), Some(access_log), Some(entry)) => access_log.observe(entry, rx),
+            (Ok(rx), _, _) => rx,
+            (Err(response), access_log, entry) => {
+                if let (Some(access_log), Some(entry)) = (access_log, entry) {
+                    access_log.write(entry, response.status());
+                }
+                return Err(status(response));
+            }
+        };
+        let rx = app::observe_quota(state, &context, rx);
+
+        let mut response = Response::new(ReceiverStream::new(chunks(rx, streaming)));
+        for (name, value) in &context.response_headers {
+            let key = MetadataKey::from_bytes(name.to_ascii_lowercase().as_bytes());
+            if let (Ok(key), Ok(value)) = (key, MetadataValue::try_from(value.as_str())) {
+                response.metadata_mut().insert(key, value);
+            }
+        }
+        Ok(response)
+    }
+
+    /// The context of a call, with the client key or tenant key of its
+    /// `authorization` metadata when those are configured
+    async fn resolve_context(
+        &self,
+        metadata: &tonic::metadata::MetadataMap,
+        route: &RouteConfig,
+        request_id: &str,
+    ) -> Result<RequestContext, Status> {
+        let state = &*self.state;
+        let context = metadata.clone().into_headers().iter().fold(
+            RequestContext::new()
+                .with_request_id(request_id)
+                .with_route(route.id()),
+            |context, (name, value)| match value.to_str() {
+                Ok(value) => context.with_header(name.as_str(), value),
+                Err(_) => context,
+            },
+        );
+        let key = context
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| context.header("x-api-key"))
+            .map(ToString::to_string);
+
+        if let Some(store) = &state.client_keys {
+            let key = key.ok_or_else(|| {
+                Status::unauthenticated(
+                    "Missing API key, send a proxy-issued key as a bearer token",
+                )
+            })?;
+            let identity = store
+                .lookup(&key)
+                .await
+                .map_err(|e| {
+                    error!(error = %e, "Failed to look up client key");
+                    Status::internal("Failed to check the API key")
+                })?
+                .ok_or_else(|| Status::unauthenticated("Invalid API key"))?;
+            let mut context = context.with_client_key(identity.id);
+            if let Some(tenant_id) = identity.tenant {
+                if let Some(tenant) = state.tenants_by_id.get(&tenant_id) {
+                    context.attributes.extend(tenant.attributes.clone());
+                }
+                context = context.with_tenant(tenant_id);
+            }
+            return Ok(context);
+        }
+        let Some(tenants) = &state.tenants else {
+            return Ok(context);
+        };
+        let key = key.ok_or_else(|| Status::unauthenticated("Missing tenant key"))?;
+        match tenants.resolve(&key).await {
+            Ok(Some(tenant)) => {
+                let mut context = context.with_tenant(tenant.id);
+                context.attributes.extend(tenant.attributes);
+                Ok(context)
+            }
+            Ok(None) => Err(Status::unauthenticated("Invalid tenant key")),
+            Err(e) => {
+                error!(error = %e, "Failed to resolve tenant");
+                Err(Status::internal(format!("Tenant error: {e}")))
+            }
+        }
+    }
+}
+
+/// The `OpenAI` request body of a gRPC `request`
+fn openai_request(request: proto::ChatRequest, stream: bool) -> Result<Value, Status> {
+    let mut body = if request.extra_json.is_empty() {
+        Map::new()
+    } else {
+        serde_json::from_str(&request.extra_json)
+            .map_err(|e| Status::invalid_argument(format!("Invalid extra_json: {e}")))?
+    };
+    body.insert("model".to_string(), request.model.into());
+    let messages = request.messages.into_iter().map(openai_message).collect();
+    body.insert("messages".to_string(), Value::Array(messages));
+    let optional = [
+        ("temperature", request.temperature.map(Value::from)),
+        ("top_p", request.top_p.map(Value::from)),
+        ("max_tokens", request.max_tokens.map(Value::from)),
+        ("user", request.user.map(Value::from)),
+        (
+            "stop",
+            Some(request.stop)
+                .filter(|stop| !stop.is_empty())
+                .map(Value::from),
+        ),
+    ];
+    for (name, value) in optional {
+        if let Some(value) = value {
+            body.insert(name.to_string(), value);
+        }
+    }
+    body.insert("stream".to_string(), stream.into());
+    Ok(Value::Object(body))
+}
+
+fn openai_message(message: proto::ChatMessage) -> Value {
+    let content = if message.content.is_empty() && !message.tool_calls.is_empty() {
+        Value::Null
+    } else {
+        message.content.into()
+    };
+    let mut value = json!({ "role": message.role, "content": content });
+    if let Some(name) = message.name {
+        value["name"] = name.into();
+    }
+    if let Some(tool_call_id) = message.tool_call_id {
+        value["tool_call_id"] = tool_call_id.into();
+    }
+    if !message.tool_calls.is_empty() {
+        value["tool_calls"] = message
+            .tool_calls
+            .into_iter()
+            .map(|call| {
+                json!({
+                    "id": call.id,
+                    "type": "function",
+                    "function": { "name": call.name, "arguments": call.arguments },
+                })
+            })
+            .collect();
+    }
+    value
+}
+
+/// The chunks of a pipeline's response stream `rx`, of a streamed completion
+/// or of one completion
+fn chunks(
+    mut rx: ResponseStream,
+    streaming: bool,
+) -> tokio::sync::mpsc::Receiver<Result<proto::Chunk, Status>> {
+    let (tx, chunks) = tokio::sync::mpsc::channel(100);
+    tokio::spawn(async move {
+        let mut data = EventData::default();
+        while let Some(item) = rx.recv().await {
+            let bytes = match item {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    let _ = tx.send(Err(Status::internal(e.to_string()))).await;
+                    return;
+                }
+            };
+            if !streaming {
+                data.buffer.extend_from_slice(&bytes);
+                continue;
+            }
+            for chunk in data.push(&bytes) {
+                // Events that aren't chunks, e.g. comments, are skipped
+                let Ok(chunk) = serde_json::from_str(&chunk) else {
+                    continue;
+                };
+                if tx.send(Ok(proto_chunk(&chunk))).await.is_err() {
+                    return;
+                }
+            }
+        }
+        if !streaming {
+            let chunk = serde_json::from_slice(&data.buffer)
+                .map(|completion| proto_chunk(&completion))
+                .map_err(|e| Status::internal(format!("Invalid completion: {e}")));
+            let _ = tx.send(chunk).await;
+        }
+    });
+    chunks
+}
+
+/// The gRPC chunk of an `OpenAI` chunk, or of a completion
+fn proto_chunk(chunk: &Value) -> proto::Chunk {
+    let text = |value: &Value| value.as_str().unwrap_or_default().to_string();
+    let index =
+        |value: &Value| u32::try_from(value.as_u64().unwrap_or_default()).unwrap_or_default();
+    let tool_call = |call: &Value| proto::ToolCall {
+        index: index(&call["index"]),
+        id: text(&call["id"]),
+        name: text(&call["function"]["name"]),
+        arguments: text(&call["function"]["arguments"]),
+    };
+    let choice = |choice: &Value| {
+        let delta = choice.get("delta").unwrap_or_else(|| &choice["message"]);
+        proto::Choice {
+            index: index(&choice["index"]),
+            role: delta["role"].as_str().map(ToString::to_string),
+            content: text(&delta["content"]),
+            tool_calls: delta["tool_calls"]
+                .as_array()
+                .map(|calls| calls.iter().map(tool_call).collect())
+                .unwrap_or_default(),
+            finish_reason: choice["finish_reason"].as_str().map(ToString::to_string),
+        }
+    };
+    proto::Chunk {
+        id: text(&chunk["id"]),
+        model: text(&chunk["model"]),
+        created: chunk["created"].as_i64().unwrap_or_default(),
+        choices: chunk["choices"]
+            .as_array()
+            .map(|choices| choices.iter().map(choice).collect())
+            .unwrap_or_default(),
+        usage: chunk
+            .get("usage")
+            .filter(|usage| usage.is_object())
+            .map(|usage| proto::Usage {
+                prompt_tokens: usage["prompt_tokens"].as_u64().unwrap_or_default(),
+                completion_tokens: usage["completion_tokens"].as_u64().unwrap_or_default(),
+                total_tokens: usage["total_tokens"].as_u64().unwrap_or_default(),
+            }),
+    }
+}
+
+/// The gRPC status of an HTTP error response
+fn status(response: HttpResponse) -> Status {
+    let code = match response.status().as_u16() {
+        400 => Code::InvalidArgument,
+        401 => Code::Unauthenticated,
+        403 => Code::PermissionDenied,
+        404 => Code::NotFound,
+        413 | 429 => Code::ResourceExhausted,
+        501 => Code::Unimplemented,
+        _ => Code::Internal,
+    };
+    let body = response.into_body().try_into_bytes().unwrap_or_default();
+    // Errors in the format of the OpenAI API carry their message in `error`
+    let message = serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|error| error["error"]["message"].as_str().map(ToString::to_string))
+        .unwrap_or_else(|| String::from_utf8_lossy(&body).into_owned());
+    Status::new(code, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests() {
+        let request = proto::ChatRequest {
+            route: "chat".to_string(),
+            model: "gpt-4o".to_string(),
+            messages: vec![
+                proto::ChatMessage {
+                    role: "assistant".to_string(),
+                    tool_calls: vec![proto::ToolCall {
+                        id: "call_1".to_string(),
+                        name: "lookup".to_string(),
+                        arguments: "{}".to_string(),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                },
+                proto::ChatMessage {
+                    role: "tool".to_string(),
+                    content: "42".to_string(),
+                    tool_call_id: Some("call_1".to_string()),
+                    ..Default::default()
+                },
+            ],
+            temperature: Some(0.5),
+            extra_json: r#"{"seed":7}"#.to_string(),
+            ..Default::default()
+        };
+        let body = openai_request(request, true).expect("Invalid request");
+        assert_eq!(body["model"], "gpt-4o");
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["temperature"], 0.5);
+        assert_eq!(body["seed"], 7);
+        assert!(body.get("max_tokens").is_none());
+        assert_eq!(body["messages"][0]["content"], Value::Null);
+        assert_eq!(
+            body["messages"][0]["tool_calls"][0]["function"]["name"],
+            "lookup"
+        );
+        assert_eq!(body["messages"][1]["tool_call_id"], "call_1");
+
+        let request = proto::ChatRequest {
+            extra_json: "[]".to_string(),
+            ..Default::default()
+        };
+        assert!(openai_request(request, false).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_chunks() {
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        tx.send(Ok(Bytes::from(
+            "data: {\"id\":\"c1\",\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"Hel",
+        )))
+        .await
+        .expect("Channel closed");
+        tx.send(Ok(Bytes::from(
+            "lo\"}}]}\n\ndata: {\"id\":\"c1\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":1,\"total_tokens\":4}}\n\ndata: [DONE]\n\n",
+        )))
+        .await
+        .expect("Channel closed");
+        drop(tx);
+        let mut chunks = chunks(rx, true);
+        let first = chunks.recv().await.expect("No chunk").expect("Failed");
+        assert_eq!(first.choices[0].content, "Hello");
+        assert_eq!(first.choices[0].role.as_deref(), Some("assistant"));
+        let last = chunks.recv().await.expect("No chunk").expect("Failed");
+        assert_eq!(last.choices[0].finish_reason.as_deref(), Some("stop"));
+        assert_eq!(last.usage.map(|usage| usage.total_tokens), Some(4));
+        assert!(chunks.recv().await.is_none());
+
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        tx.send(Ok(Bytes::from(
+            r#"{"id":"c2","choices":[{"index":0,"message":{"role":"assistant","content":"Hi"},"finish_reason":"stop"}]}"#,
+        )))
+        .await
+        .expect("Channel closed");
+        drop(tx);
+        let chunk = super::chunks(rx, false)
+            .recv()
+            .await
+            .expect("No chunk")
+            .expect("Failed");
+        assert_eq!(chunk.id, "c2");
+        assert_eq!(chunk.choices[0].content, "Hi");
+    }
+
+    #[test]
+    fn test_statuses() {
+        let response = HttpResponse::TooManyRequests().json(json!({
+            "error": { "message": "Quota exceeded", "type": "rate_limit_error" },
+        }));
+        let status = status(response);
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(status.message(), "Quota exceeded");
+        let status = super::status(HttpResponse::BadRequest().body("Invalid model"));
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "Invalid model");
+    }
+}
@@ -0,0 +1,307 @@
+//! Routes serving clients of APIs other than `OpenAI`'s.
+//!
+//! A route with `api = "anthropic"` accepts Anthropic Messages API requests,
+//! e.g. on `/v1/messages`, and one with `api = "ollama"` accepts Ollama chat
+//! requests on `/api/chat`. Requests are translated to the `OpenAI` format
+//! before the route's processors and policies apply, and responses, streamed
+//! or not, are translated back, so any configured LLM can serve Claude SDK
+//! clients or tools that only speak Ollama.
+
+use actix_web::{http::StatusCode, HttpResponse};
+use bytes::Bytes;
+use llm_proxy_core::ResponseStream;
+use serde_json::json;
+
+use crate::config::ApiFormat;
+
+/// The `OpenAI` request for a request `body` in the route's `api` format
+///
+/// # Errors
+///
+/// This function will return a 400 response, in the format of `api`, if the
+/// body isn't a valid request, and a 501 if the proxy was built without the
+/// feature of `api`.
+pub fn translate_request(api: ApiFormat, body: Bytes) -> Result<Bytes, HttpResponse> {
+    match api {
+        ApiFormat::OpenAI => Ok(body),
+        #[cfg(feature = "anthropic")]
+        ApiFormat::Anthropic => parse(api, &body, llm_proxy_anthropic::inbound::to_openai_request),
+        #[cfg(feature = "ollama")]
+        ApiFormat::Ollama => parse(api, &body, llm_proxy_ollama::inbound::to_openai_request),
+        #[allow(unreachable_patterns)]
+        _ => Err(error(
+            api,
+            StatusCode::NOT_IMPLEMENTED,
+            &format!("The proxy was built without the {} feature", api.name()),
+        )),
+    }
+}
+
+/// Parse a request `body` in the `api` format and `translate` it
+#[cfg(any(feature = "anthropic", feature = "ollama"))]
+fn parse<T: serde::de::DeserializeOwned>(
+    api: ApiFormat,
+    body: &[u8],
+    translate: fn(T) -> serde_json::Value,
+) -> Result<Bytes, HttpResponse> {
+    let request = serde_json::from_slice(body).map_err(|e| {
+        error(
+            api,
+            StatusCode::BAD_REQUEST,
+            &format!("Invalid request: {e}"),
+        )
+    })?;
+    Ok(Bytes::from(translate(request).to_string()))
+}
+
+/// The response stream `rx` of a pipeline, in the route's `api` format
+#[must_use]
+#[cfg_attr(
+    not(any(feature = "anthropic", feature = "ollama")),
+    allow(unused_variables, clippy::missing_const_for_fn)
+)]
+pub fn translate_response(api: ApiFormat, rx: ResponseStream, streaming: bool) -> ResponseStream {
+    match api {
+        #[cfg(feature = "anthropic")]
+        ApiFormat::Anthropic => {
+            translate::<llm_proxy_anthropic::inbound::ChunkTranslator>(rx, streaming)
+        }
+        #[cfg(feature = "ollama")]
+        ApiFormat::Ollama => translate::<llm_proxy_ollama::inbound::ChunkTranslator>(rx, streaming),
+        _ => rx,
+    }
+}
+
+/// Content type of responses in the `api` format
+#[must_use]
+pub const fn content_type(api: ApiFormat, streaming: bool) -> &'static str {
+    match (api, streaming) {
+        (ApiFormat::Ollama, true) => "application/x-ndjson",
+        (_, true) => "text/event-stream",
+        (_, false) => "application/json",
+    }
+}
+
+/// An error response in the `api` format
+#[must_use]
+pub fn error(api: ApiFormat, status: StatusCode, message: &str) -> HttpResponse {
+    let kind = if status.is_client_error() {
+        "invalid_request_error"
+    } else {
+        "api_error"
+    };
+    let body = match api {
+        ApiFormat::OpenAI => json!({
+            "error": { "message": message, "type": kind, "param": null, "code": null },
+        }),
+        ApiFormat::Anthropic => json!({
+            "type": "error",
+            "error": { "type": kind, "message": message },
+        }),
+        ApiFormat::Ollama => json!({ "error": message }),
+    };
+    HttpResponse::build(status).json(body)
+}
+
+/// The data of the `data:` lines of a pipeline's event stream, whose lines
+/// may be split across byte chunks
+#[derive(Debug, Default)]
+pub(crate) struct EventData {
+    pub(crate) buffer: bytes::BytesMut,
+}
+
+impl EventData {
+    /// The data of the lines completed by `bytes`, without the final `[DONE]`
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut chunks = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line = self.buffer.split_to(end + 1);
+            let line = String::from_utf8_lossy(&line);
+            if let Some(data) = line.trim_end().strip_prefix("data:") {
+                let data = data.trim_start();
+                if data != "[DONE]" {
+                    chunks.push(data.to_string());
+                }
+            }
+        }
+        chunks
+    }
+}
+
+/// Translation of a pipeline's responses into another API's format
+#[cfg(any(feature = "anthropic", feature = "ollama"))]
+trait Translator: Default + Send + 'static {
+    /// Translate the next bytes of a stream
+    fn push(&mut self, bytes: &[u8]) -> Vec<Bytes>;
+    /// End a stream
+    fn finish(&mut self) -> Vec<Bytes>;
+    /// The event reporting that a stream failed
+    fn error(message: &str) -> Bytes;
+    /// Translate a whole completion
+    fn response(completion: &serde_json::Value) -> serde_json::Value;
+}
+
+#[cfg(feature = "anthropic")]
+impl Translator for llm_proxy_anthropic::inbound::ChunkTranslator {
+    fn push(&mut self, bytes: &[u8]) -> Vec<Bytes> {
+        self.push(bytes)
+    }
+
+    fn finish(&mut self) -> Vec<Bytes> {
+        self.finish()
+    }
+
+    fn error(message: &str) -> Bytes {
+        Self::error(message)
+    }
+
+    fn response(completion: &serde_json::Value) -> serde_json::Value {
+        llm_proxy_anthropic::inbound::to_messages_response(completion)
+    }
+}
+
+#[cfg(feature = "ollama")]
+impl Translator for llm_proxy_ollama::inbound::ChunkTranslator {
+    fn push(&mut self, bytes: &[u8]) -> Vec<Bytes> {
+        self.push(bytes)
+    }
+
+    fn finish(&mut self) -> Vec<Bytes> {
+        self.finish()
+    }
+
+    fn error(message: &str) -> Bytes {
+        Self::error(message)
+    }
+
+    fn response(completion: &serde_json::Value) -> serde_json::Value {
+        llm_proxy_ollama::inbound::to_ollama_response(completion)
+    }
+}
+
+/// Translate `rx` with a `T`, as a stream or as one completion
+#[cfg(any(feature = "anthropic", feature = "ollama"))]
+fn translate<T: Translator>(mut rx: ResponseStream, streaming: bool) -> ResponseStream {
+    let (tx, translated) = tokio::sync::mpsc::channel(100);
+    tokio::spawn(async move {
+        if streaming {
+            let mut translator = T::default();
+            while let Some(item) = rx.recv().await {
+                let events = match item {
+                    Ok(chunk) => translator.push(&chunk),
+                    // The stream has started, so the error can only be an event
+                    Err(e) => vec![T::error(&e.to_string())],
+                };
+                for event in events {
+                    if tx.send(Ok(event)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            for event in translator.finish() {
+                if tx.send(Ok(event)).await.is_err() {
+                    return;
+                }
+            }
+        } else {
+            let mut body = bytes::BytesMut::new();
+            while let Some(item) = rx.recv().await {
+                match item {
+                    Ok(chunk) => body.extend_from_slice(&chunk),
+                    Err(e) => {
+                        let _ = tx.send(Err(e)).await;
+                        return;
+                    }
+                }
+            }
+            // Bodies that aren't completions are sent unchanged
+            let body = serde_json::from_slice(&body).map_or_else(
+                |_| body.freeze(),
+                |completion| Bytes::from(T::response(&completion).to_string()),
+            );
+            let _ = tx.send(Ok(body)).await;
+        }
+    });
+    translated
+}
+
+#[cfg(all(test, feature = "anthropic", feature = "ollama"))]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    const COMPLETION: &str = r#"{"id":"c1","model":"m","choices":[{"index":0,"message":{"role":"assistant","content":"Hello"},"finish_reason":"stop"}]}"#;
+
+    const CHUNKS: [&str; 2] = [
+        "data: {\"id\":\"c1\",\"model\":\"m\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hel",
+        "lo\"}}]}\n\n",
+    ];
+
+    async fn translate(api: ApiFormat, chunks: &[&str], streaming: bool) -> String {
+        let (tx, rx) = mpsc::channel(10);
+        for chunk in chunks {
+            tx.send(Ok(Bytes::from(chunk.to_string())))
+                .await
+                .expect("Channel closed");
+        }
+        drop(tx);
+        let mut rx = translate_response(api, rx, streaming);
+        let mut body = String::new();
+        while let Some(chunk) = rx.recv().await {
+            body.push_str(std::str::from_utf8(&chunk.expect("Stream failed")).expect("Not UTF-8"));
+        }
+        body
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_route() {
+        let body = Bytes::from(
+            r#"{"model":"claude-sonnet-4-5","max_tokens":64,"system":"Be brief.","messages":[{"role":"user","content":"Hi"}]}"#,
+        );
+        let body = translate_request(ApiFormat::Anthropic, body).expect("Not translated");
+        let request: serde_json::Value = serde_json::from_slice(&body).expect("Invalid JSON");
+        assert_eq!(request["messages"][0]["role"], "system");
+        assert_eq!(request["messages"][1]["content"], "Hi");
+
+        let response = translate_request(ApiFormat::Anthropic, Bytes::from("{}"))
+            .expect_err("Invalid request translated");
+        assert_eq!(response.status(), 400);
+
+        let response: serde_json::Value =
+            serde_json::from_str(&translate(ApiFormat::Anthropic, &[COMPLETION], false).await)
+                .expect("Invalid JSON");
+        assert_eq!(response["content"][0]["text"], "Hello");
+        assert_eq!(response["stop_reason"], "end_turn");
+
+        let events = translate(ApiFormat::Anthropic, &CHUNKS, true).await;
+        assert!(events.starts_with("event: message_start\n"));
+        assert!(events.contains("\"text\":\"Hello\""));
+        assert!(events.ends_with("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n"));
+    }
+
+    #[tokio::test]
+    async fn test_ollama_route() {
+        let body = Bytes::from(r#"{"model":"gpt-4o","messages":[{"role":"user","content":"Hi"}]}"#);
+        let body = translate_request(ApiFormat::Ollama, body).expect("Not translated");
+        let request: serde_json::Value = serde_json::from_slice(&body).expect("Invalid JSON");
+        assert_eq!(request["stream"], true);
+        assert_eq!(request["messages"][0]["content"], "Hi");
+
+        let response: serde_json::Value =
+            serde_json::from_str(&translate(ApiFormat::Ollama, &[COMPLETION], false).await)
+                .expect("Invalid JSON");
+        assert_eq!(response["message"]["content"], "Hello");
+        assert_eq!(response["done"], true);
+
+        let lines = translate(ApiFormat::Ollama, &CHUNKS, true).await;
+        let lines: Vec<serde_json::Value> = lines
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("Invalid line"))
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["message"]["content"], "Hello");
+        assert_eq!(lines[1]["done"], true);
+    }
+}
@@ -8,7 +8,7 @@
 //! - HTTP server with configurable routing
 //! - CORS support and security features
 //! - Dynamic pipeline management
-//! - Configuration through TOML files
+//! - Configuration through TOML, YAML or JSON files
 //! - Comprehensive logging and error handling
 //!
 //! ## Components
@@ -21,11 +21,110 @@
 //!
 //! ### Config
 //! The [`config`] module handles server configuration, including:
-//! - TOML file parsing
+//! - TOML, YAML and JSON file parsing, with layered files and environment
+//!   variable overrides
 //! - Route configuration
 //! - LLM provider settings
 //! - Server settings (host, port, CORS)
 //!
+//! ### Check
+//! The [`check`] module validates a configuration up front, resolving the
+//! targets, processors, URLs and tokens of every route and LLM, for the
+//! server's `--check-config` mode.
+//!
+//! ### Client Auth
+//! The [`client_auth`] module authenticates clients with proxy-issued keys
+//! from a configured store and attaches the key's identity to the request.
+//!
+//! ### Quota
+//! The [`quota`] module enforces per-key request rates, daily tokens and
+//! monthly spend of client keys, answering 429 once a limit is used up.
+//!
+//! ### Tenancy
+//! The [`tenancy`] module loads per-tenant overlays of routes' backends,
+//! system prompts, quotas, allowed models and upstream keys, reloading each
+//! tenant's file on its own.
+//!
+//! ### Routing
+//! The [`routing`] module matches requests to routes by path prefix, exact
+//! path, glob or regex, HTTP method and header values, most specific first.
+//!
+//! ### Split
+//! The [`split`] module spreads the requests of routes with several weighted
+//! `targets` across their LLMs.
+//!
+//! ### Models
+//! The [`models`] module resolves virtual model names to a real model of an
+//! LLM with default parameters, and lists them on `GET /v1/models`.
+//!
+//! ### Payload
+//! The [`payload`] module enforces the body size, message count and content
+//! length limits of routes.
+//!
+//! ### BYOK
+//! The [`byok`] module sends the keys clients bring upstream on routes with
+//! `byok`, refusing keys that don't match the route's pattern.
+//!
+//! ### Inbound
+//! The [`inbound`] module translates the requests and responses of routes
+//! whose clients speak another API, Anthropic's Messages API or Ollama's.
+//!
+//! ### WebSocket
+//! The [`websocket`] module serves chat requests sent as messages over the
+//! WebSocket connections of routes with `websocket`, streaming chunks back.
+//!
+//! ### gRPC
+//! The [`grpc`] module serves the `llm_proxy.v1.Pipeline` gRPC service of
+//! `proto/llm_proxy.proto`, streaming the chunks of chat requests run
+//! through the routes' pipelines (with the `grpc` feature).
+//!
+//! ### Tools
+//! The [`tools`] module adds the tools of routes to their requests and runs
+//! the calls the model makes of tools the proxy runs itself, before answering
+//! the client. The [`mcp`] module offers the tools of the configured MCP
+//! servers, the [`web_search`] module a web search tool, and the
+//! [`code_interpreter`] module a tool running code in a WebAssembly sandbox
+//! (with the `wasm` feature).
+//!
+//! ### Compression
+//! The [`compression`] module keeps event streams out of the compression of
+//! responses negotiated by `Accept-Encoding`.
+//!
+//! ### Processors
+//! The [`processors`] module builds the request and stream processors that
+//! routes reference from their `[processor.*]` sections, by processor type,
+//! including the types of the plugins loaded from the `[plugins]` directory
+//! (with the `plugins` feature).
+//!
+//! ### Providers
+//! The [`providers`] module builds the supporting providers for configured
+//! LLM backends, such as token providers backed by environment variables or
+//! rotating key pools.
+//!
+//! ### Admin
+//! The [`admin`] module serves the authenticated `/admin` API listing routes,
+//! pipelines with their backend health, the configuration and key limits.
+//!
+//! ### Access Log
+//! The [`access_log`] module writes a JSON line per request, with its route,
+//! model, client, status, latency and token usage, to stdout or a file.
+//!
+//! ### Prometheus
+//! The [`prometheus`] module installs a Prometheus recorder for the proxy's
+//! metrics and serves them on `/metrics`.
+//!
+//! ### Events
+//! The [`events`] module publishes events of started, completed and failed
+//! requests to Kafka or NATS (with the `kafka` or `nats` feature).
+//!
+//! ### Webhooks
+//! The [`webhooks`] module posts Slack-compatible notifications of sustained
+//! upstream failures, unhealthy endpoints, used up quotas and failed reloads.
+//!
+//! ### Telemetry
+//! The [`telemetry`] module exports the proxy's tracing spans to an
+//! OpenTelemetry collector over OTLP (with the `otel` feature).
+//!
 //! ## Server Configuration
 //!
 //! The server is configured through a TOML file with the following sections:
@@ -58,7 +157,7 @@
 //! #[tokio::main]
 //! async fn main() -> anyhow::Result<()> {
 //!     // Load configuration
-//!     let config = config::load_config("config.toml")?;
+//!     let config = config::Config::from_file("config.toml")?;
 //!     
 //!     // Start the server
 //!     app::run_server(config).await
@@ -75,8 +174,36 @@
 //!
 //! All errors are properly logged and appropriate HTTP status codes are returned.
 
+pub mod access_log;
+pub mod admin;
 pub mod app;
+pub mod byok;
+pub mod check;
+pub mod client_auth;
+#[cfg(feature = "wasm")]
+pub mod code_interpreter;
+pub mod compression;
 pub mod config;
+pub mod events;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod inbound;
+pub mod mcp;
+pub mod models;
+pub mod payload;
+pub mod processors;
+pub mod prometheus;
+pub mod providers;
+pub mod quota;
+pub mod replay;
+pub mod routing;
+pub mod split;
+pub mod telemetry;
+pub mod tenancy;
+pub mod tools;
+pub mod web_search;
+pub mod webhooks;
+pub mod websocket;
 
 pub use app::run_server;
 pub use config::Config;
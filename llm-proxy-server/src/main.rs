@@ -1,6 +1,5 @@
-mod app;
-mod config;
-
+use llm_proxy_core::redact::RedactingMakeWriter;
+use llm_proxy_server::{app, check, config, telemetry};
 use tracing::info;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
@@ -8,7 +7,19 @@ use tracing_subscriber::{fmt, fmt::format::FmtSpan, EnvFilter};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // Initialize logging
+    // Load configuration, layering comma-separated files
+    let config_path = std::env::var("CONFIG_PATH").unwrap_or_else(|_| "config.toml".to_string());
+    let config_paths: Vec<&str> = config_path.split(',').map(str::trim).collect();
+    let config = config::Config::from_files(&config_paths)?;
+
+    // Only validate the configuration with `--check-config`, e.g. in CI
+    if std::env::args().skip(1).any(|arg| arg == "--check-config") {
+        let report = check::check_config(&config).await;
+        print!("{report}");
+        std::process::exit(i32::from(!report.is_ok()));
+    }
+
+    // Initialize logging and trace export
     let env_filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new("info,server=debug,core=debug"));
 
@@ -19,17 +30,22 @@ async fn main() -> anyhow::Result<()> {
         .with_file(true)
         .with_line_number(true)
         .with_span_events(FmtSpan::NEW | FmtSpan::CLOSE)
+        .with_writer(RedactingMakeWriter::new(std::io::stdout))
         .pretty();
 
+    let (telemetry_layer, _telemetry_guard) = config
+        .telemetry
+        .as_ref()
+        .map(telemetry::layer)
+        .transpose()?
+        .unzip();
+
     tracing_subscriber::registry()
         .with(env_filter)
         .with(formatting_layer)
+        .with(telemetry_layer)
         .init();
-
-    // Load configuration
-    let config_path = std::env::var("CONFIG_PATH").unwrap_or_else(|_| "config.toml".to_string());
-    info!("Loading configuration from {}", config_path);
-    let config = config::Config::from_file(&config_path)?;
+    info!("Loaded configuration from {}", config_path);
 
     // Start server
     app::run_server(config).await?;
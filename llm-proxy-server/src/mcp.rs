@@ -0,0 +1,575 @@
+//! Tools of MCP servers offered to the models of chat routes.
+//!
+//! Servers configured as `[mcp.<name>]` are reached over Streamable HTTP, with
+//! JSON-RPC requests posted to their `url`, or started as a `command` that
+//! speaks JSON-RPC, a message per line, over its standard input and output.
+//! The proxy connects to a server on the first request that needs it, lists
+//! its tools once, and reconnects after a failed request.
+//!
+//! Servers are [`ToolExecutor`]s: a route with `mcp` adds the tools of its
+//! servers to every request, and with `execute = true` the proxy calls those
+//! the model asks for itself, see [`crate::tools`].
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use llm_proxy_core::redact::SecretString;
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines},
+    process::{Child, Command},
+    sync::Mutex,
+};
+use tracing::debug;
+
+use crate::{config::McpServerConfig, tools::ToolExecutor};
+
+/// MCP protocol version the proxy asks servers for
+const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// Seconds to wait for a response, unless the server sets `timeout_secs`
+const DEFAULT_TIMEOUT_SECS: u64 = 60;
+
+/// Header carrying the session ID of Streamable HTTP servers
+const SESSION_HEADER: &str = "mcp-session-id";
+
+/// A configured MCP server
+pub struct McpServer {
+    name: String,
+    config: McpServerConfig,
+    token: Option<SecretString>,
+    timeout: Duration,
+    client: reqwest::Client,
+    next_id: AtomicU64,
+    /// The open session, if connected
+    session: Mutex<Option<Session>>,
+}
+
+/// A session with an MCP server
+struct Session {
+    /// The server's tools, in the MCP format
+    tools: Arc<Vec<Value>>,
+    channel: Channel,
+}
+
+/// How messages reach a server
+enum Channel {
+    /// Streamable HTTP, with the session ID the server assigned, if any
+    Http { session_id: Option<String> },
+    /// Standard input and output of a process
+    Stdio(Box<Stdio>),
+}
+
+/// JSON-RPC messages exchanged a line at a time
+struct Stdio {
+    /// The server process, killed when the session is dropped
+    _child: Option<Child>,
+    input: Box<dyn AsyncWrite + Send + Unpin>,
+    output: Lines<BufReader<Box<dyn AsyncRead + Send + Unpin>>>,
+}
+
+impl McpServer {
+    /// The server `name` configured by `config`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the server has neither or both
+    /// of `url` and `command`, or its token variable is not set.
+    pub fn new(name: &str, config: &McpServerConfig) -> Result<Self> {
+        match (&config.url, &config.command) {
+            (Some(url), None) => {
+                reqwest::Url::parse(url).with_context(|| format!("Invalid url {url}"))?;
+            }
+            (None, Some(_)) => {
+                if config.token_env.is_some() {
+                    bail!("token_env only applies to servers with a url");
+                }
+            }
+            _ => bail!("MCP server {name} needs either a url or a command"),
+        }
+        let token = config
+            .token_env
+            .as_ref()
+            .map(|var| {
+                std::env::var(var)
+                    .map(SecretString::from)
+                    .with_context(|| format!("Failed to read MCP token from {var}"))
+            })
+            .transpose()?;
+        Ok(Self {
+            name: name.to_string(),
+            config: config.clone(),
+            token,
+            timeout: Duration::from_secs(config.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS)),
+            client: reqwest::Client::new(),
+            next_id: AtomicU64::new(1),
+            session: Mutex::new(None),
+        })
+    }
+
+    /// The server's tools, in the MCP format
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the server cannot be reached.
+    pub async fn list_tools(&self) -> Result<Arc<Vec<Value>>> {
+        let mut session = self.session.lock().await;
+        if let Some(session) = session.as_ref() {
+            return Ok(session.tools.clone());
+        }
+        let connected = self.connect().await?;
+        let tools = connected.tools.clone();
+        *session = Some(connected);
+        drop(session);
+        Ok(tools)
+    }
+
+    /// Call the tool `name` with `arguments`, returning its output as text
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the server cannot be reached or
+    /// rejects the call. Tools that fail report it in their output.
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<String> {
+        let result = self
+            .request(
+                "tools/call",
+                json!({ "name": name, "arguments": arguments }),
+            )
+            .await?;
+        Ok(tool_output(&result))
+    }
+
+    /// Send a request in the open session, connecting first if needed
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let mut guard = self.session.lock().await;
+        let session = match guard.take() {
+            Some(session) => guard.insert(session),
+            None => guard.insert(self.connect().await?),
+        };
+        let message = self.message(method, &params);
+        let response = match &mut session.channel {
+            Channel::Http { session_id } => {
+                // HTTP requests don't need to wait for each other
+                let session_id = session_id.clone();
+                drop(guard);
+                let response = self.post(session_id.as_deref(), &message).await;
+                if response.is_err() {
+                    // The session may have expired
+                    self.session.lock().await.take();
+                }
+                return response.and_then(|(response, _)| {
+                    response.ok_or_else(|| anyhow!("No response to {method}"))
+                });
+            }
+            Channel::Stdio(stdio) => tokio::time::timeout(self.timeout, stdio.request(&message))
+                .await
+                .map_err(|_| anyhow!("No response to {method} in time"))
+                .and_then(|response| response),
+        };
+        if response.is_err() {
+            // The process may have exited or be out of step
+            guard.take();
+        }
+        response
+    }
+
+    /// Open a session: initialize it and list the server's tools
+    async fn connect(&self) -> Result<Session> {
+        debug!(server = %self.name, "Connecting to MCP server");
+        let channel = match &self.config.command {
+            Some(command) => Channel::Stdio(Box::new(Stdio::spawn(command, &self.config)?)),
+            None => Channel::Http { session_id: None },
+        };
+        let mut session = Session {
+            tools: Arc::default(),
+            channel,
+        };
+        let initialize = self.message(
+            "initialize",
+            &json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": {},
+                "clientInfo": { "name": "llm-proxy", "version": env!("CARGO_PKG_VERSION") },
+            }),
+        );
+        let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        match &mut session.channel {
+            Channel::Http { session_id } => {
+                let (_, id) = self.post(None, &initialize).await?;
+                *session_id = id;
+                self.post(session_id.as_deref(), &initialized).await?;
+            }
+            Channel::Stdio(stdio) => {
+                tokio::time::timeout(self.timeout, async {
+                    stdio.request(&initialize).await?;
+                    stdio.send(&initialized).await
+                })
+                .await
+                .map_err(|_| anyhow!("No response to initialize in time"))??;
+            }
+        }
+
+        let mut tools = Vec::new();
+        let mut cursor = None;
+        loop {
+            let params = cursor.map_or_else(|| json!({}), |cursor| json!({ "cursor": cursor }));
+            let message = self.message("tools/list", &params);
+            let result = match &mut session.channel {
+                Channel::Http { session_id } => self
+                    .post(session_id.as_deref(), &message)
+                    .await?
+                    .0
+                    .ok_or_else(|| anyhow!("No response to tools/list"))?,
+                Channel::Stdio(stdio) => {
+                    tokio::time::timeout(self.timeout, stdio.request(&message))
+                        .await
+                        .map_err(|_| anyhow!("No response to tools/list in time"))??
+                }
+            };
+            tools.extend(result["tools"].as_array().into_iter().flatten().cloned());
+            cursor = result["nextCursor"].as_str().map(str::to_string);
+            if cursor.is_none() {
+                break;
+            }
+        }
+        debug!(server = %self.name, tools = tools.len(), "Connected to MCP server");
+        session.tools = Arc::new(tools);
+        Ok(session)
+    }
+
+    /// A JSON-RPC request with a new ID
+    fn message(&self, method: &str, params: &Value) -> Value {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    /// Post `message` to a Streamable HTTP server, returning the result of a
+    /// request, or `None` for a notification, and the session ID the server
+    /// assigned
+    async fn post(
+        &self,
+        session_id: Option<&str>,
+        message: &Value,
+    ) -> Result<(Option<Value>, Option<String>)> {
+        let url = self.config.url.as_deref().unwrap_or_default();
+        let mut request = self
+            .client
+            .post(url)
+            .timeout(self.timeout)
+            .header(
+                reqwest::header::ACCEPT,
+                "application/json, text/event-stream",
+            )
+            .json(message);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token.expose());
+        }
+        if let Some(session_id) = session_id {
+            request = request
+                .header(SESSION_HEADER, session_id)
+                .header("mcp-protocol-version", PROTOCOL_VERSION);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach MCP server {}", self.name))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            bail!("MCP server {} answered {status}: {body}", self.name);
+        }
+        let session_id = response
+            .headers()
+            .get(SESSION_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let Some(id) = message.get("id") else {
+            return Ok((None, session_id));
+        };
+        let event_stream = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream"));
+        let body = response.bytes().await?;
+        let response = if event_stream {
+            event_response(&body, id)
+                .ok_or_else(|| anyhow!("MCP server {} sent no response", self.name))?
+        } else {
+            serde_json::from_slice(&body)
+                .with_context(|| format!("Invalid response of MCP server {}", self.name))?
+        };
+        rpc_result(response).map(|result| (Some(result), session_id))
+    }
+}
+
+impl Stdio {
+    /// Start `command` with the arguments and environment of `config`
+    fn spawn(command: &str, config: &McpServerConfig) -> Result<Self> {
+        let mut child = Command::new(command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("Failed to start {command}"))?;
+        let input = child.stdin.take().context("No standard input")?;
+        let output = child.stdout.take().context("No standard output")?;
+        Ok(Self::new(Some(child), Box::new(input), Box::new(output)))
+    }
+
+    fn new(
+        child: Option<Child>,
+        input: Box<dyn AsyncWrite + Send + Unpin>,
+        output: Box<dyn AsyncRead + Send + Unpin>,
+    ) -> Self {
+        Self {
+            _child: child,
+            input,
+            output: BufReader::new(output).lines(),
+        }
+    }
+
+    /// Write `message` as a line
+    async fn send(&mut self, message: &Value) -> Result<()> {
+        let mut line = message.to_string();
+        line.push('\n');
+        self.input.write_all(line.as_bytes()).await?;
+        self.input.flush().await?;
+        Ok(())
+    }
+
+    /// Send the request `message` and read lines until its response
+    async fn request(&mut self, message: &Value) -> Result<Value> {
+        self.send(message).await?;
+        loop {
+            let line = self
+                .output
+                .next_line()
+                .await?
+                .context("MCP server closed its output")?;
+            let Ok(incoming) = serde_json::from_str::<Value>(&line) else {
+                continue;
+            };
+            if incoming.get("method").is_some() {
+                // Requests of the server, of which the proxy only answers pings
+                if let Some(id) = incoming.get("id") {
+                    let reply = if incoming["method"] == "ping" {
+                        json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+                    } else {
+                        json!({
+                            "jsonrpc": "2.0",
+                            "id": id,
+                            "error": { "code": -32601, "message": "Method not found" },
+                        })
+                    };
+                    self.send(&reply).await?;
+                }
+            } else if incoming.get("id") == message.get("id") {
+                return rpc_result(incoming);
+            }
+        }
+    }
+}
+
+/// The response with `id` among the events of an event stream
+fn event_response(body: &[u8], id: &Value) -> Option<Value> {
+    String::from_utf8_lossy(body)
+        .replace("\r\n", "\n")
+        .split("\n\n")
+        .filter_map(|event| {
+            let data: Vec<_> = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(str::trim_start)
+                .collect();
+            serde_json::from_str::<Value>(&data.join("\n")).ok()
+        })
+        .find(|message| message.get("id") == Some(id) && message.get("method").is_none())
+}
+
+/// The result of a JSON-RPC `response`
+fn rpc_result(mut response: Value) -> Result<Value> {
+    if let Some(error) = response.get("error") {
+        bail!(
+            "{} (code {})",
+            error["message"].as_str().unwrap_or("MCP error"),
+            error["code"]
+        );
+    }
+    Ok(response["result"].take())
+}
+
+/// The output of a `tools/call` result, as text for the model
+fn tool_output(result: &Value) -> String {
+    let parts: Vec<String> = result["content"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|part| match part["text"].as_str() {
+            Some(text) if part["type"] == "text" => text.to_string(),
+            _ => part.to_string(),
+        })
+        .collect();
+    let output = if parts.is_empty() {
+        result
+            .get("structuredContent")
+            .map(Value::to_string)
+            .unwrap_or_default()
+    } else {
+        parts.join("\n")
+    };
+    if result["isError"] == true {
+        format!("Error: {output}")
+    } else {
+        output
+    }
+}
+
+/// An MCP tool as an `OpenAI` function tool
+fn openai_tool(tool: &Value) -> Value {
+    json!({
+        "type": "function",
+        "function": {
+            "name": tool["name"],
+            "description": tool["description"].as_str().unwrap_or_default(),
+            "parameters": tool
+                .get("inputSchema")
+                .cloned()
+                .unwrap_or_else(|| json!({ "type": "object", "properties": {} })),
+        },
+    })
+}
+
+#[async_trait]
+impl ToolExecutor for McpServer {
+    async fn tools(&self) -> Result<Vec<Value>> {
+        let tools = self
+            .list_tools()
+            .await
+            .with_context(|| format!("MCP server {} is unavailable", self.name))?;
+        Ok(tools.iter().map(openai_tool).collect())
+    }
+
+    async fn call(&self, name: &str, arguments: Value) -> Result<String> {
+        self.call_tool(name, arguments).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::duplex;
+
+    use super::*;
+
+    /// A server on the other end of a stdio session, with one `echo` tool
+    fn stdio_server() -> McpServer {
+        let (client_input, server_input) = duplex(4096);
+        let (server_output, client_output) = duplex(4096);
+        tokio::spawn(async move {
+            let mut requests = BufReader::new(server_input).lines();
+            let mut output = server_output;
+            while let Ok(Some(line)) = requests.next_line().await {
+                let request: Value = serde_json::from_str(&line).expect("Invalid JSON");
+                let result = match request["method"].as_str() {
+                    Some("initialize") => json!({ "protocolVersion": PROTOCOL_VERSION }),
+                    Some("tools/list") => json!({ "tools": [{
+                        "name": "echo",
+                        "description": "Echo the text",
+                        "inputSchema": { "type": "object" },
+                    }] }),
+                    Some("tools/call") => json!({ "content": [{
+                        "type": "text",
+                        "text": request["params"]["arguments"]["text"],
+                    }] }),
+                    _ => continue,
+                };
+                // A ping of the server comes before every response
+                let ping = json!({ "jsonrpc": "2.0", "id": "p", "method": "ping" });
+                let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": result });
+                output
+                    .write_all(format!("{ping}\n{response}\n").as_bytes())
+                    .await
+                    .expect("Closed");
+            }
+        });
+        let server = McpServer::new(
+            "test",
+            &McpServerConfig {
+                command: Some("test".to_string()),
+                ..McpServerConfig::default()
+            },
+        )
+        .expect("Invalid server");
+        let channel = Channel::Stdio(Box::new(Stdio::new(
+            None,
+            Box::new(client_input),
+            Box::new(client_output),
+        )));
+        *server.session.try_lock().expect("Locked") = Some(Session {
+            tools: Arc::new(vec![
+                json!({ "name": "echo", "description": "Echo the text" }),
+            ]),
+            channel,
+        });
+        server
+    }
+
+    #[test]
+    fn test_server_config() {
+        let config = McpServerConfig {
+            url: Some("https://mcp.example.com/mcp".to_string()),
+            command: Some("mcp-server".to_string()),
+            ..McpServerConfig::default()
+        };
+        assert!(McpServer::new("both", &config).is_err());
+        assert!(McpServer::new("neither", &McpServerConfig::default()).is_err());
+        let config = McpServerConfig {
+            url: Some("not a url".to_string()),
+            ..McpServerConfig::default()
+        };
+        assert!(McpServer::new("invalid", &config).is_err());
+    }
+
+    #[test]
+    fn test_tool_output() {
+        let result = json!({ "content": [
+            { "type": "text", "text": "one" },
+            { "type": "text", "text": "two" },
+        ] });
+        assert_eq!(tool_output(&result), "one\ntwo");
+        let result = json!({ "content": [{ "type": "text", "text": "boom" }], "isError": true });
+        assert_eq!(tool_output(&result), "Error: boom");
+
+        let events = b"event: message\ndata: {\"jsonrpc\":\"2.0\",\"method\":\"notifications/progress\"}\n\ndata: {\"jsonrpc\":\"2.0\",\"id\":3,\"result\":{}}\n\n";
+        assert_eq!(
+            event_response(events, &json!(3)),
+            Some(json!({"jsonrpc": "2.0", "id": 3, "result": {}}))
+        );
+        assert!(
+            rpc_result(json!({ "id": 1, "error": { "code": -32601, "message": "No" } })).is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stdio_session() {
+        let server = stdio_server();
+        let tools = server.tools().await.expect("No tools");
+        assert_eq!(tools[0]["function"]["name"], "echo");
+        assert_eq!(tools[0]["function"]["description"], "Echo the text");
+        let output = server
+            .call("echo", json!({ "text": "hello" }))
+            .await
+            .expect("Call failed");
+        assert_eq!(output, "hello");
+    }
+}
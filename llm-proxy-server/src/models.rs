@@ -0,0 +1,249 @@
+//! Virtual models.
+//!
+//! `[model.<name>]` sections define model names clients can request, such as
+//! `acme-fast`, that resolve to a real model of a configured LLM. Requests
+//! for a virtual model are rewritten before they enter the pipeline: the
+//! model is replaced, and the preset's temperature, `max_tokens` and system
+//! prompt are filled in where the request doesn't set them. They are sent
+//! to the preset's LLM, with the processors and settings of their route.
+//!
+//! When virtual models are configured, the proxy answers `GET /v1/models`
+//! itself, listing them along with the models the LLMs declare in `models`.
+//! When a route serves the Ollama API, it answers `GET /api/tags` with the
+//! same models, for tools that pick models from there.
+
+use std::collections::BTreeMap;
+
+use actix_web::{web, HttpResponse};
+use bytes::Bytes;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    app::AppState,
+    config::{ApiFormat, Config, VirtualModelConfig},
+};
+
+/// Attribute of the request context holding the virtual model a request asked for
+pub const VIRTUAL_MODEL_ATTRIBUTE: &str = "virtual_model";
+
+/// A request for a virtual model, rewritten for its real model
+#[derive(Debug)]
+pub struct VirtualModelRequest<'a> {
+    /// Name of the virtual model
+    pub name: &'a str,
+    /// The virtual model's preset
+    pub preset: &'a VirtualModelConfig,
+    /// The rewritten request body
+    pub body: Bytes,
+}
+
+/// Rewrite a chat request `body` for the virtual model it asks for
+///
+/// Returns `None` for requests for other models and bodies that aren't JSON
+/// objects, which are left for the pipeline to handle.
+#[must_use]
+pub fn resolve<'a>(config: &'a Config, body: &[u8]) -> Option<VirtualModelRequest<'a>> {
+    if config.model.is_empty() {
+        return None;
+    }
+    let Ok(Value::Object(mut request)) = serde_json::from_slice(body) else {
+        return None;
+    };
+    let (name, preset) = request
+        .get("model")
+        .and_then(Value::as_str)
+        .and_then(|model| config.model.get_key_value(model))?;
+    apply_preset(&mut request, preset);
+    let body = serde_json::to_vec(&request).ok()?;
+    Some(VirtualModelRequest {
+        name,
+        preset,
+        body: Bytes::from(body),
+    })
+}
+
+/// Replace the model of `request` and fill in the defaults of `preset`
+fn apply_preset(request: &mut Map<String, Value>, preset: &VirtualModelConfig) {
+    request.insert("model".to_string(), Value::from(preset.model.as_str()));
+    if let Some(temperature) = preset.temperature {
+        request
+            .entry("temperature")
+            .or_insert_with(|| Value::from(temperature));
+    }
+    if let Some(max_tokens) = preset.max_tokens {
+        if !request.contains_key("max_completion_tokens") {
+            request
+                .entry("max_tokens")
+                .or_insert_with(|| Value::from(max_tokens));
+        }
+    }
+    if let Some(system_prompt) = &preset.system_prompt {
+        if let Some(Value::Array(messages)) = request.get_mut("messages") {
+            let has_system_message = messages.iter().any(|message| {
+                matches!(
+                    message.get("role").and_then(Value::as_str),
+                    Some("system" | "developer")
+                )
+            });
+            if !has_system_message {
+                messages.insert(0, json!({ "role": "system", "content": system_prompt }));
+            }
+        }
+    }
+}
+
+/// A model in the `OpenAI` list models format
+#[derive(Serialize)]
+struct ModelInfo<'a> {
+    id: &'a str,
+    object: &'static str,
+    created: u64,
+    owned_by: &'a str,
+}
+
+/// Register `GET /v1/models` if there are virtual models, and `GET /api/tags`
+/// if a route serves the Ollama API
+pub fn configure(service_config: &mut web::ServiceConfig, config: &Config) {
+    if !config.model.is_empty() {
+        service_config.route("/v1/models", web::get().to(list_models));
+    }
+    if config
+        .route
+        .iter()
+        .any(|route| route.api == ApiFormat::Ollama)
+    {
+        service_config.route("/api/tags", web::get().to(list_tags));
+    }
+}
+
+#[allow(clippy::future_not_send)]
+async fn list_models(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "object": "list", "data": models(&state.config) }))
+}
+
+/// List the models in the format of Ollama's local models
+#[allow(clippy::future_not_send)]
+async fn list_tags(state: web::Data<AppState>) -> HttpResponse {
+    let models: Vec<_> = models(&state.config)
+        .into_iter()
+        .map(|model| {
+            json!({
+                "name": model.id,
+                "model": model.id,
+                "modified_at": "1970-01-01T00:00:00Z",
+                "size": 0,
+                "digest": "",
+                "details": {
+                    "parent_model": "",
+                    "format": "",
+                    "family": model.owned_by,
+                    "families": [model.owned_by],
+                    "parameter_size": "",
+                    "quantization_level": "",
+                },
+            })
+        })
+        .collect();
+    HttpResponse::Ok().json(json!({ "models": models }))
+}
+
+/// The virtual models, then the models the LLMs declare
+fn models(config: &Config) -> Vec<ModelInfo<'_>> {
+    // Virtual models come first and hide real models of the same name; a
+    // model declared by several LLMs is owned by the first of them by ID
+    let mut llms: Vec<_> = config.llm.iter().collect();
+    llms.sort_by_key(|(id, _)| *id);
+    let mut models = BTreeMap::new();
+    for (id, llm) in llms {
+        for model in &llm.models {
+            models.entry(model.as_str()).or_insert(id.as_str());
+        }
+    }
+    let real: Vec<_> = models
+        .into_iter()
+        .filter(|(model, _)| !config.model.contains_key(*model))
+        .map(|(model, owned_by)| ModelInfo {
+            id: model,
+            object: "model",
+            created: 0,
+            owned_by,
+        })
+        .collect();
+    let mut virtual_models: Vec<_> = config
+        .model
+        .keys()
+        .map(|name| ModelInfo {
+            id: name,
+            object: "model",
+            created: 0,
+            owned_by: "llm-proxy",
+        })
+        .collect();
+    virtual_models.sort_by_key(|model| model.id);
+    virtual_models.extend(real);
+    virtual_models
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset() -> VirtualModelConfig {
+        serde_json::from_value(json!({
+            "target_llm": "openai_chat",
+            "model": "gpt-4o-mini",
+            "temperature": 0.2,
+            "max_tokens": 512,
+            "system_prompt": "You are Acme's assistant.",
+        }))
+        .expect("Invalid preset")
+    }
+
+    #[test]
+    fn test_preset_fills_in_defaults() {
+        let mut request = json!({
+            "model": "acme-fast",
+            "messages": [{ "role": "user", "content": "Hi" }],
+        });
+        let Value::Object(request) = &mut request else {
+            unreachable!()
+        };
+        apply_preset(request, &preset());
+
+        assert_eq!(
+            Value::Object(request.clone()),
+            json!({
+                "model": "gpt-4o-mini",
+                "temperature": 0.2,
+                "max_tokens": 512,
+                "messages": [
+                    { "role": "system", "content": "You are Acme's assistant." },
+                    { "role": "user", "content": "Hi" },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_request_parameters_win() {
+        let mut request = json!({
+            "model": "acme-fast",
+            "temperature": 1.0,
+            "max_completion_tokens": 100,
+            "messages": [
+                { "role": "developer", "content": "Be brief." },
+                { "role": "user", "content": "Hi" },
+            ],
+        });
+        let Value::Object(request) = &mut request else {
+            unreachable!()
+        };
+        apply_preset(request, &preset());
+
+        assert_eq!(request["model"], "gpt-4o-mini");
+        assert_eq!(request["temperature"], 1.0);
+        assert!(!request.contains_key("max_tokens"));
+        assert_eq!(request["messages"].as_array().map(Vec::len), Some(2));
+    }
+}
@@ -0,0 +1,176 @@
+//! Limits of the size and content of requests.
+//!
+//! A route's `max_body_bytes`, or else the server's, bounds the request bodies
+//! the proxy holds in memory: requests announcing a larger `Content-Length`
+//! are refused with a 413 before any of the body is read, and bodies sent
+//! without one are refused as soon as they grow past the limit. Passthrough
+//! requests, whose bodies are streamed upstream, are cut off instead.
+//!
+//! Chat requests on routes with `max_messages` or `max_content_chars` are
+//! refused with a 400 if they have more messages, or more text across their
+//! messages, than allowed.
+
+use std::fmt;
+
+use actix_web::{http::header::CONTENT_LENGTH, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::config::RouteConfig;
+
+/// Error of a request body growing past the limit of its route
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BodyTooLarge {
+    /// The limit, in bytes
+    pub limit: usize,
+}
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Request body exceeds {} bytes", self.limit)
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
+
+impl BodyTooLarge {
+    /// A 413 response in the format of the `OpenAI` API's errors
+    #[must_use]
+    pub fn response(&self) -> HttpResponse {
+        HttpResponse::PayloadTooLarge().json(error(&self.to_string(), None, "request_too_large"))
+    }
+}
+
+/// The error of a request announcing a body larger than `limit`, if it does
+#[must_use]
+pub fn check_content_length(req: &HttpRequest, limit: Option<usize>) -> Option<BodyTooLarge> {
+    let limit = limit?;
+    let length = content_length(req)?;
+    (length > limit).then_some(BodyTooLarge { limit })
+}
+
+/// The size of the body a request announces with its `Content-Length`
+#[must_use]
+pub fn content_length(req: &HttpRequest) -> Option<usize> {
+    req.headers()
+        .get(CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// A 400 response for chat requests with more messages or text than `route` allows
+///
+/// Bodies that aren't chat requests are left for the pipeline to reject.
+#[must_use]
+pub fn check_messages(route: &RouteConfig, body: &[u8]) -> Option<HttpResponse> {
+    #[derive(Deserialize)]
+    struct ChatMessages {
+        messages: Vec<Value>,
+    }
+
+    if route.max_messages.is_none() && route.max_content_chars.is_none() {
+        return None;
+    }
+    let messages = serde_json::from_slice::<ChatMessages>(body).ok()?.messages;
+    if let Some(limit) = route.max_messages.filter(|limit| messages.len() > *limit) {
+        let message = format!(
+            "The request has {} messages, more than the {limit} allowed",
+            messages.len()
+        );
+        return Some(HttpResponse::BadRequest().json(error(
+            &message,
+            Some("messages"),
+            "too_many_messages",
+        )));
+    }
+    let chars: usize = messages.iter().map(content_chars).sum();
+    let limit = route.max_content_chars.filter(|limit| chars > *limit)?;
+    let message =
+        format!("The messages have {chars} characters of content, more than the {limit} allowed");
+    Some(HttpResponse::BadRequest().json(error(&message, Some("messages"), "content_too_long")))
+}
+
+/// Characters of the text of a message, a string or the `text` of its parts
+fn content_chars(message: &Value) -> usize {
+    match &message["content"] {
+        Value::String(text) => text.chars().count(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .map(|text| text.chars().count())
+            .sum(),
+        _ => 0,
+    }
+}
+
+/// An error body in the format of the `OpenAI` API's errors
+fn error(message: &str, param: Option<&str>, code: &str) -> Value {
+    json!({
+        "error": {
+            "message": message,
+            "type": "invalid_request_error",
+            "param": param,
+            "code": code,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn route(limits: Value) -> RouteConfig {
+        let mut route = json!({ "path_prefix": "/v1/chat/completions", "target_llm": "a" });
+        if let (Some(route), Value::Object(limits)) = (route.as_object_mut(), limits) {
+            route.extend(limits);
+        }
+        serde_json::from_value(route).expect("Invalid route")
+    }
+
+    #[test]
+    fn test_content_length() {
+        let req = TestRequest::default()
+            .insert_header((CONTENT_LENGTH, "2048"))
+            .to_http_request();
+        assert_eq!(
+            check_content_length(&req, Some(1024)),
+            Some(BodyTooLarge { limit: 1024 })
+        );
+        assert!(check_content_length(&req, Some(4096)).is_none());
+        assert!(check_content_length(&req, None).is_none());
+        let req = TestRequest::default().to_http_request();
+        assert!(check_content_length(&req, Some(1024)).is_none());
+    }
+
+    #[test]
+    fn test_message_limits() {
+        let body = json!({
+            "model": "gpt-4o",
+            "messages": [
+                { "role": "system", "content": "Be brief." },
+                { "role": "user", "content": [
+                    { "type": "text", "text": "Héllo" },
+                    { "type": "image_url", "image_url": { "url": "https://example.com/a.png" } },
+                ] },
+            ],
+        })
+        .to_string();
+
+        let unlimited = route(json!({}));
+        assert!(check_messages(&unlimited, body.as_bytes()).is_none());
+
+        let messages = route(json!({ "max_messages": 1 }));
+        let response = check_messages(&messages, body.as_bytes()).expect("Not refused");
+        assert_eq!(response.status(), 400);
+
+        // "Be brief." and "Héllo" have 14 characters
+        let content = route(json!({ "max_messages": 2, "max_content_chars": 14 }));
+        assert!(check_messages(&content, body.as_bytes()).is_none());
+        let content = route(json!({ "max_content_chars": 13 }));
+        assert!(check_messages(&content, body.as_bytes()).is_some());
+    }
+}
@@ -0,0 +1,1437 @@
+//! Construction of the request and stream processors referenced by routes.
+//!
+//! Each `[processor.*]` section names a processor `type`; a
+//! [`ProcessorRegistry`] maps the type to the [`ProcessorFactory`] or
+//! [`StreamProcessorFactory`] that builds the processor from the section.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use llm_proxy_core::{
+    AuditStore, ConversationStore, ModelPrice, Pricing, Processor, StreamProcessor,
+};
+use llm_proxy_openai::{
+    processors::{
+        content_filter::read_word_list, CachePolicyProcessor, ContentFilterProcessor,
+        ContextWindowProcessor, ConversationHistoryProcessor, ExperimentArm, ExperimentProcessor,
+        FilterAction, FilterRule, HttpSink, InjectionAction, LLMInjectionClassifier,
+        LoggingProcessor, ModelRewriteProcessor, ModerationAction, ModerationProcessor,
+        NormalizeProcessor, PromptInjectionProcessor, RequestLogProcessor, RequestLogSink,
+        RollingFileSink, Sensitivity, StdoutSink, SystemPromptMode, SystemPromptProcessor,
+        ToolAllowlistProcessor, ToolSchemaProcessor, TruncationStrategy, UserAttributionProcessor,
+        UserSource, VisionProcessor,
+    },
+    providers::StaticClientProvider,
+    stream_processors::{
+        AuditProcessor, CodeFenceProcessor, ContentRewriteProcessor, ConversationProcessor,
+        FencePolicy, OutputGuardrailProcessor, OutputLimitProcessor, StopSequenceProcessor,
+        UsageMetricsProcessor,
+    },
+    ChatCompletionRequest, EnvTokenProvider, ImageDetail, OpenAIClient, OpenAIUrlProvider,
+};
+use serde::Deserialize;
+
+use crate::config::{default_true, Config, PluginsConfig, ProcessorConfig};
+
+/// Builds processors of one type from their configuration.
+///
+/// Closures taking a [`ProcessorConfig`] implement this trait, so most
+/// processors are registered as a constructor.
+pub trait ProcessorFactory: Send + Sync {
+    /// Create a processor from its `[processor.*]` section
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the section is invalid for the type.
+    fn create_processor(
+        &self,
+        config: &ProcessorConfig,
+    ) -> Result<Arc<dyn Processor<ChatCompletionRequest>>>;
+}
+
+impl<F> ProcessorFactory for F
+where
+    F: Fn(&ProcessorConfig) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> + Send + Sync,
+{
+    fn create_processor(
+        &self,
+        config: &ProcessorConfig,
+    ) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+        self(config)
+    }
+}
+
+/// Builds stream processors of one type from their configuration.
+///
+/// Like [`ProcessorFactory`], closures taking a [`ProcessorConfig`]
+/// implement this trait.
+pub trait StreamProcessorFactory: Send + Sync {
+    /// Create a stream processor from its `[processor.*]` section
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the section is invalid for the type.
+    fn create_stream_processor(&self, config: &ProcessorConfig)
+        -> Result<Arc<dyn StreamProcessor>>;
+}
+
+impl<F> StreamProcessorFactory for F
+where
+    F: Fn(&ProcessorConfig) -> Result<Arc<dyn StreamProcessor>> + Send + Sync,
+{
+    fn create_stream_processor(
+        &self,
+        config: &ProcessorConfig,
+    ) -> Result<Arc<dyn StreamProcessor>> {
+        self(config)
+    }
+}
+
+/// Processor factories keyed by the `type` of `[processor.*]` sections
+#[derive(Default)]
+pub struct ProcessorRegistry {
+    factories: HashMap<String, Arc<dyn ProcessorFactory>>,
+    stream_factories: HashMap<String, Arc<dyn StreamProcessorFactory>>,
+}
+
+impl ProcessorRegistry {
+    /// Create an empty registry
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `factory` under `processor_type`, replacing any factory registered before
+    pub fn register(
+        &mut self,
+        processor_type: impl Into<String>,
+        factory: impl ProcessorFactory + 'static,
+    ) -> &mut Self {
+        self.factories
+            .insert(processor_type.into(), Arc::new(factory));
+        self
+    }
+
+    /// The factory registered under `processor_type`
+    #[must_use]
+    pub fn get(&self, processor_type: &str) -> Option<Arc<dyn ProcessorFactory>> {
+        self.factories.get(processor_type).cloned()
+    }
+
+    /// Register the stream processor `factory` under `processor_type`,
+    /// replacing any stream processor factory registered before
+    pub fn register_stream(
+        &mut self,
+        processor_type: impl Into<String>,
+        factory: impl StreamProcessorFactory + 'static,
+    ) -> &mut Self {
+        self.stream_factories
+            .insert(processor_type.into(), Arc::new(factory));
+        self
+    }
+
+    /// The stream processor factory registered under `processor_type`
+    #[must_use]
+    pub fn get_stream(&self, processor_type: &str) -> Option<Arc<dyn StreamProcessorFactory>> {
+        self.stream_factories.get(processor_type).cloned()
+    }
+
+    /// Create the processors of the `[processor.*]` sections `ids`, in order
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a section doesn't exist, has an
+    /// unknown type or is invalid for its type.
+    pub fn create_processors(
+        &self,
+        config: &Config,
+        ids: &[String],
+    ) -> Result<Vec<Arc<dyn Processor<ChatCompletionRequest>>>> {
+        ids.iter()
+            .map(|id| {
+                let processor_config = config.get_processor(id)?;
+                let processor_type = &processor_config.processor_type;
+                let factory = self.get(processor_type).ok_or_else(|| {
+                    if self.get_stream(processor_type).is_some() {
+                        anyhow::anyhow!(
+                            "Processor {id} of type {processor_type} belongs in stream_processors"
+                        )
+                    } else {
+                        anyhow::anyhow!("Unknown type {processor_type} of processor {id}")
+                    }
+                })?;
+                factory
+                    .create_processor(processor_config)
+                    .with_context(|| format!("Invalid configuration of processor {id}"))
+            })
+            .collect()
+    }
+
+    /// Create the stream processors of the `[processor.*]` sections `ids`, in order
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a section doesn't exist, has an
+    /// unknown stream processor type or is invalid for its type.
+    pub fn create_stream_processors(
+        &self,
+        config: &Config,
+        ids: &[String],
+    ) -> Result<Vec<Arc<dyn StreamProcessor>>> {
+        ids.iter()
+            .map(|id| {
+                let processor_config = config.get_processor(id)?;
+                let processor_type = &processor_config.processor_type;
+                let factory = self.get_stream(processor_type).ok_or_else(|| {
+                    if self.get(processor_type).is_some() {
+                        anyhow::anyhow!(
+                            "Processor {id} of type {processor_type} belongs in processors"
+                        )
+                    } else {
+                        anyhow::anyhow!(
+                            "Unknown stream processor type {processor_type} of processor {id}"
+                        )
+                    }
+                })?;
+                factory
+                    .create_stream_processor(processor_config)
+                    .with_context(|| format!("Invalid configuration of processor {id}"))
+            })
+            .collect()
+    }
+}
+
+/// Create the registry of the processor types built into the server:
+///
+/// - `cache_policy`: decides whether responses may be cached, from the
+///   client's `Cache-Control` header and the rules in [`CachePolicySettings`]
+/// - `content_filter`: rejects, redacts or logs requests matching blocklists
+///   or patterns, with [`ContentFilterSettings`]
+/// - `context_window`: truncates requests to the context window of their
+///   model, with [`ContextWindowSettings`]
+/// - `conversation_history`: adds the earlier turns of a conversation kept by
+///   a `conversation` stream processor to requests with only the newest
+///   messages, with [`ConversationHistorySettings`]
+/// - `experiment`: splits users between models or parameters, with
+///   [`ExperimentSettings`]
+/// - `logger`: logs a summary of each request at the level in `config_value`
+/// - `model_rewrite`: replaces the deprecated models in `additional_config`,
+///   a table of model names to replacements
+/// - `moderation`: checks user content with a moderations endpoint, with
+///   [`ModerationSettings`]
+/// - `normalize`: cleans up the message list, with [`NormalizeSettings`]
+/// - `prompt_injection`: blocks, strips or tags prompt injection attempts,
+///   with [`PromptInjectionSettings`]
+/// - `request_log`: writes requests to a file, stdout or an HTTP endpoint,
+///   with [`RequestLogSettings`]
+/// - `system_prompt`: adds the system prompt in `config_value`, combined with
+///   the request's system messages as set by `mode` in `additional_config`
+/// - `tool_allowlist`: removes or rejects unapproved tools, with
+///   [`ToolAllowlistSettings`]
+/// - `tool_schema`: rejects tools with invalid parameter schemas and tool
+///   calls whose arguments don't match them
+/// - `user_attribution`: sets the `user` field from the request context, with
+///   [`UserAttributionSettings`]
+/// - `vision`: validates and normalizes images, with [`VisionSettings`]
+///
+/// and of the stream processor types, for a route's `stream_processors`:
+///
+/// - `audit`: records request/response transcripts in a database, with
+///   [`AuditSettings`]
+/// - `code_fence`: keeps, redacts or strips the output inside and outside
+///   code blocks, with [`CodeFenceSettings`]
+/// - `content_rewrite`: replaces text in the output, with
+///   [`ContentRewriteSettings`]
+/// - `conversation`: records the turns of conversations by the session ID of
+///   a request header, with [`ConversationSettings`]
+/// - `output_guardrail`: stops generations matching banned patterns, with
+///   [`OutputGuardrailSettings`]
+/// - `output_limit`: cuts output at the token count in `config_value`, or at
+///   the request's `max_tokens` when lower
+/// - `stop_sequence`: cuts output at stop sequences, with
+///   [`StopSequenceSettings`]
+/// - `usage_metrics`: records token usage and cost as metrics, with
+///   [`UsageMetricsSettings`] on top of the server's `[pricing]`
+#[must_use]
+pub fn create_processor_registry(config: &Config) -> ProcessorRegistry {
+    let pricing = config
+        .pricing
+        .iter()
+        .fold(Pricing::builtin(), |pricing, (prefix, price)| {
+            pricing.with_price(prefix.clone(), *price)
+        });
+    let mut registry = ProcessorRegistry::new();
+    registry
+        .register("cache_policy", create_cache_policy)
+        .register("content_filter", create_content_filter)
+        .register("context_window", create_context_window)
+        .register("conversation_history", create_conversation_history)
+        .register("experiment", create_experiment)
+        .register("logger", create_logger)
+        .register("model_rewrite", create_model_rewrite)
+        .register("moderation", create_moderation)
+        .register("normalize", create_normalize)
+        .register("prompt_injection", create_prompt_injection)
+        .register("request_log", create_request_log)
+        .register("system_prompt", create_system_prompt)
+        .register("tool_allowlist", create_tool_allowlist)
+        .register("tool_schema", |_: &ProcessorConfig| {
+            Ok(Arc::new(ToolSchemaProcessor::new()) as Arc<dyn Processor<ChatCompletionRequest>>)
+        })
+        .register("user_attribution", create_user_attribution)
+        .register("vision", create_vision)
+        .register_stream("audit", create_audit)
+        .register_stream("code_fence", create_code_fence)
+        .register_stream("content_rewrite", create_content_rewrite)
+        .register_stream("conversation", create_conversation)
+        .register_stream("output_guardrail", create_output_guardrail)
+        .register_stream("output_limit", create_output_limit)
+        .register_stream("stop_sequence", create_stop_sequence)
+        .register_stream("usage_metrics", move |config: &ProcessorConfig| {
+            create_usage_metrics(config, &pricing)
+        });
+    registry
+}
+
+/// Register the processor types of the plugins in the `[plugins]` directory
+///
+/// Each type creates its processors with the plugin, passing the
+/// `config_value` and `additional_config` of their section.
+///
+/// # Errors
+///
+/// This function will return an error if a plugin cannot be loaded or
+/// provides a type that is already registered, or if the server was built
+/// without the `plugins` feature.
+#[cfg(feature = "plugins")]
+pub fn register_plugins(registry: &mut ProcessorRegistry, config: &PluginsConfig) -> Result<()> {
+    use llm_proxy_plugin::{Plugin, PluginConfig};
+
+    for path in llm_proxy_plugin::discover(&config.dir)? {
+        let plugin = Plugin::load(&path)?;
+        for processor_type in plugin.processor_types() {
+            if registry.get(processor_type).is_some()
+                || registry.get_stream(processor_type).is_some()
+            {
+                anyhow::bail!(
+                    "Plugin {} provides the processor type {processor_type}, which is already registered",
+                    path.display()
+                );
+            }
+            let plugin = plugin.clone();
+            let plugin_type = processor_type.clone();
+            registry.register(processor_type.clone(), move |config: &ProcessorConfig| {
+                let plugin_config = PluginConfig {
+                    config_value: config.config_value.clone(),
+                    additional_config: config.additional_config.clone(),
+                };
+                let processor = plugin.create(&plugin_type, &plugin_config)?;
+                Ok(Arc::new(processor) as Arc<dyn Processor<ChatCompletionRequest>>)
+            });
+        }
+        tracing::info!(
+            plugin = %path.display(),
+            types = ?plugin.processor_types(),
+            "Loaded processor plugin"
+        );
+    }
+    Ok(())
+}
+
+/// Register the processor types of plugins, which need the `plugins` feature
+///
+/// # Errors
+///
+/// This function always returns an error, as the server was built without
+/// the `plugins` feature.
+#[cfg(not(feature = "plugins"))]
+pub fn register_plugins(_registry: &mut ProcessorRegistry, _config: &PluginsConfig) -> Result<()> {
+    anyhow::bail!("Plugins need the server built with the plugins feature")
+}
+
+/// Settings of an `audit` stream processor
+#[derive(Debug, Deserialize)]
+pub struct AuditSettings {
+    /// Where transcripts are recorded
+    pub store: AuditStoreConfig,
+    /// Share of requests recorded, between 0 and 1
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: f64,
+    /// Mask keys and tokens in recorded transcripts
+    #[serde(default = "default_true")]
+    pub redact: bool,
+}
+
+/// Database of an `audit` stream processor's transcripts
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuditStoreConfig {
+    /// `SQLite` database (requires the `sqlite` feature)
+    Sqlite {
+        /// URL of the database, e.g. `sqlite://audit.db?mode=rwc`
+        url: String,
+    },
+    /// Postgres database (requires the `postgres` feature)
+    Postgres {
+        /// URL of the database, e.g. `postgres://proxy@localhost/audit`
+        url: String,
+    },
+    /// Compressed JSON lines archives in S3-compatible object storage
+    /// (requires the `s3` feature)
+    S3 {
+        /// Bucket the archives are uploaded to
+        bucket: String,
+        /// Prefix of the archives' keys, e.g. `proxy/`
+        #[serde(default)]
+        prefix: String,
+        /// Endpoint of an S3-compatible service, e.g. `MinIO`
+        #[serde(default)]
+        endpoint: Option<String>,
+        /// Region, overriding the environment's
+        #[serde(default)]
+        region: Option<String>,
+        /// Number of transcripts from which a batch is uploaded right away
+        #[serde(default)]
+        batch_size: Option<usize>,
+        /// Seconds between uploads of the buffered transcripts
+        #[serde(default = "default_archive_interval_secs")]
+        interval_secs: u64,
+        /// Days after which archives are deleted
+        #[serde(default)]
+        retention_days: Option<u64>,
+    },
+}
+
+const fn default_archive_interval_secs() -> u64 {
+    300
+}
+
+/// Create the transcript store of `config`
+///
+/// # Errors
+///
+/// This function will return an error if the URL of the store is invalid, or
+/// the server was built without the feature of the store.
+pub fn create_audit_store(config: AuditStoreConfig) -> Result<Arc<dyn AuditStore>> {
+    match config {
+        AuditStoreConfig::Sqlite { url } => sqlite_audit_store(&url),
+        AuditStoreConfig::Postgres { url } => postgres_audit_store(&url),
+        archive @ AuditStoreConfig::S3 { .. } => s3_archiver(archive),
+    }
+}
+
+fn create_audit(config: &ProcessorConfig) -> Result<Arc<dyn StreamProcessor>> {
+    let settings: AuditSettings = config.settings()?;
+    Ok(Arc::new(
+        AuditProcessor::new(create_audit_store(settings.store)?)
+            .with_sample_rate(settings.sample_rate)
+            .with_redaction(settings.redact),
+    ))
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_audit_store(url: &str) -> Result<Arc<dyn AuditStore>> {
+    Ok(Arc::new(llm_proxy_core::audit::SqliteAuditStore::new(url)?))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite_audit_store(_url: &str) -> Result<Arc<dyn AuditStore>> {
+    anyhow::bail!("SQLite audit stores need the server built with the sqlite feature")
+}
+
+#[cfg(feature = "postgres")]
+fn postgres_audit_store(url: &str) -> Result<Arc<dyn AuditStore>> {
+    Ok(Arc::new(llm_proxy_core::audit::PostgresAuditStore::new(
+        url,
+    )?))
+}
+
+#[cfg(not(feature = "postgres"))]
+fn postgres_audit_store(_url: &str) -> Result<Arc<dyn AuditStore>> {
+    anyhow::bail!("Postgres audit stores need the server built with the postgres feature")
+}
+
+#[cfg(feature = "s3")]
+fn s3_archiver(config: AuditStoreConfig) -> Result<Arc<dyn AuditStore>> {
+    let AuditStoreConfig::S3 {
+        bucket,
+        prefix,
+        endpoint,
+        region,
+        batch_size,
+        interval_secs,
+        retention_days,
+    } = config
+    else {
+        anyhow::bail!("Not an S3 archive configuration");
+    };
+    let mut archiver = llm_proxy_core::audit::S3Archiver::new(bucket).with_prefix(prefix);
+    if let Some(endpoint) = endpoint {
+        archiver = archiver.with_endpoint(endpoint);
+    }
+    if let Some(region) = region {
+        archiver = archiver.with_region(region);
+    }
+    if let Some(batch_size) = batch_size {
+        archiver = archiver.with_batch_size(batch_size);
+    }
+    if let Some(days) = retention_days {
+        archiver = archiver.with_retention(std::time::Duration::from_secs(days * 24 * 60 * 60));
+    }
+    Ok(archiver.start(std::time::Duration::from_secs(interval_secs.max(1))))
+}
+
+#[cfg(not(feature = "s3"))]
+fn s3_archiver(_config: AuditStoreConfig) -> Result<Arc<dyn AuditStore>> {
+    anyhow::bail!("S3 transcript archives need the server built with the s3 feature")
+}
+
+/// Settings of a `conversation` stream processor
+#[derive(Debug, Deserialize)]
+pub struct ConversationSettings {
+    /// Where the turns of conversations are kept
+    pub store: ConversationStoreConfig,
+    /// Header carrying the session ID, `X-Session-Id` by default
+    #[serde(default)]
+    pub header: Option<String>,
+}
+
+/// Database of the turns of conversations
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConversationStoreConfig {
+    /// `SQLite` database (requires the `sqlite` feature)
+    Sqlite {
+        /// URL of the database, e.g. `sqlite://conversations.db?mode=rwc`
+        url: String,
+    },
+    /// Postgres database (requires the `postgres` feature)
+    Postgres {
+        /// URL of the database, e.g. `postgres://proxy@localhost/conversations`
+        url: String,
+    },
+    /// Redis server (requires the `redis` feature)
+    Redis {
+        /// URL of the server, e.g. `redis://localhost:6379`
+        url: String,
+        /// Prefix of the keys of conversations
+        #[serde(default)]
+        key_prefix: Option<String>,
+        /// Seconds after which idle conversations are deleted
+        #[serde(default)]
+        ttl_secs: Option<u64>,
+    },
+}
+
+/// Create the conversation store of `config`
+///
+/// # Errors
+///
+/// This function will return an error if the URL of the store is invalid, or
+/// the server was built without the feature of the store.
+pub fn create_conversation_store(
+    config: ConversationStoreConfig,
+) -> Result<Arc<dyn ConversationStore>> {
+    match config {
+        ConversationStoreConfig::Sqlite { url } => sqlite_conversation_store(&url),
+        ConversationStoreConfig::Postgres { url } => postgres_conversation_store(&url),
+        redis @ ConversationStoreConfig::Redis { .. } => redis_conversation_store(redis),
+    }
+}
+
+fn create_conversation(config: &ProcessorConfig) -> Result<Arc<dyn StreamProcessor>> {
+    let settings: ConversationSettings = config.settings()?;
+    let mut processor = ConversationProcessor::new(create_conversation_store(settings.store)?);
+    if let Some(header) = settings.header {
+        processor = processor.with_header(header);
+    }
+    Ok(Arc::new(processor))
+}
+
+/// Settings of a `conversation_history` processor
+#[derive(Debug, Deserialize)]
+pub struct ConversationHistorySettings {
+    /// Where the turns of conversations are kept, as for the `conversation`
+    /// stream processor recording them
+    pub store: ConversationStoreConfig,
+    /// Header carrying the session ID, `X-Session-Id` by default
+    #[serde(default)]
+    pub header: Option<String>,
+    /// Tokens of history added at most (default: 4000)
+    #[serde(default)]
+    pub token_budget: Option<u32>,
+    /// Turns of a conversation considered at most (default: 50)
+    #[serde(default)]
+    pub max_turns: Option<u32>,
+}
+
+fn create_conversation_history(
+    config: &ProcessorConfig,
+) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let settings: ConversationHistorySettings = config.settings()?;
+    let mut processor =
+        ConversationHistoryProcessor::new(create_conversation_store(settings.store)?);
+    if let Some(header) = settings.header {
+        processor = processor.with_header(header);
+    }
+    if let Some(tokens) = settings.token_budget {
+        processor = processor.with_token_budget(tokens);
+    }
+    if let Some(turns) = settings.max_turns {
+        processor = processor.with_max_turns(turns);
+    }
+    Ok(Arc::new(processor))
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_conversation_store(url: &str) -> Result<Arc<dyn ConversationStore>> {
+    Ok(Arc::new(
+        llm_proxy_core::conversation::SqliteConversationStore::new(url)?,
+    ))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite_conversation_store(_url: &str) -> Result<Arc<dyn ConversationStore>> {
+    anyhow::bail!("SQLite conversation stores need the server built with the sqlite feature")
+}
+
+#[cfg(feature = "postgres")]
+fn postgres_conversation_store(url: &str) -> Result<Arc<dyn ConversationStore>> {
+    Ok(Arc::new(
+        llm_proxy_core::conversation::PostgresConversationStore::new(url)?,
+    ))
+}
+
+#[cfg(not(feature = "postgres"))]
+fn postgres_conversation_store(_url: &str) -> Result<Arc<dyn ConversationStore>> {
+    anyhow::bail!("Postgres conversation stores need the server built with the postgres feature")
+}
+
+#[cfg(feature = "redis")]
+fn redis_conversation_store(config: ConversationStoreConfig) -> Result<Arc<dyn ConversationStore>> {
+    let ConversationStoreConfig::Redis {
+        url,
+        key_prefix,
+        ttl_secs,
+    } = config
+    else {
+        anyhow::bail!("Not a Redis store configuration");
+    };
+    let mut store = llm_proxy_core::conversation::RedisConversationStore::new(&url)?;
+    if let Some(prefix) = key_prefix {
+        store = store.with_key_prefix(prefix);
+    }
+    if let Some(secs) = ttl_secs {
+        store = store.with_ttl(std::time::Duration::from_secs(secs));
+    }
+    Ok(Arc::new(store))
+}
+
+#[cfg(not(feature = "redis"))]
+fn redis_conversation_store(
+    _config: ConversationStoreConfig,
+) -> Result<Arc<dyn ConversationStore>> {
+    anyhow::bail!("Redis conversation stores need the server built with the redis feature")
+}
+
+/// Settings of a `cache_policy` processor
+#[derive(Debug, Deserialize)]
+pub struct CachePolicySettings {
+    /// Model name prefixes whose requests may be cached; all if empty
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Never cache requests with tools or functions
+    #[serde(default)]
+    pub exclude_tools: bool,
+    /// Never cache requests sampling above this temperature
+    #[serde(default)]
+    pub max_temperature: Option<f32>,
+    /// Honor the client's `Cache-Control` header
+    #[serde(default = "default_true")]
+    pub client_headers: bool,
+}
+
+fn create_cache_policy(
+    config: &ProcessorConfig,
+) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let settings: CachePolicySettings = config.settings()?;
+    let mut processor = CachePolicyProcessor::new()
+        .with_exclude_tools(settings.exclude_tools)
+        .with_client_headers(settings.client_headers);
+    for model in settings.models {
+        processor = processor.with_model(model);
+    }
+    if let Some(temperature) = settings.max_temperature {
+        processor = processor.with_max_temperature(temperature);
+    }
+    Ok(Arc::new(processor))
+}
+
+/// Settings of a `code_fence` stream processor
+#[derive(Debug, Deserialize, Default)]
+pub struct CodeFenceSettings {
+    /// What is done with output inside code blocks: `keep`, `redact` or `strip`
+    #[serde(default)]
+    pub inside: FencePolicy,
+    /// What is done with output outside code blocks
+    #[serde(default)]
+    pub outside: FencePolicy,
+}
+
+fn create_code_fence(config: &ProcessorConfig) -> Result<Arc<dyn StreamProcessor>> {
+    let settings: CodeFenceSettings = config.settings()?;
+    Ok(Arc::new(
+        CodeFenceProcessor::new()
+            .with_inside(settings.inside)
+            .with_outside(settings.outside),
+    ))
+}
+
+/// Settings of a `content_filter` processor
+#[derive(Debug, Deserialize, Default)]
+pub struct ContentFilterSettings {
+    /// Text replacing the matches of `redact` rules
+    #[serde(default)]
+    pub replacement: Option<String>,
+    /// Rules checked in order
+    #[serde(default)]
+    pub rules: Vec<ContentFilterRuleConfig>,
+}
+
+/// A rule of a `content_filter` processor; its words, words file and pattern
+/// all apply
+#[derive(Debug, Deserialize)]
+pub struct ContentFilterRuleConfig {
+    /// Name of the rule, reported when it rejects a request
+    pub name: String,
+    /// Words and phrases matched case-insensitively as whole words
+    #[serde(default)]
+    pub words: Vec<String>,
+    /// File with one word or phrase per line
+    #[serde(default)]
+    pub words_file: Option<String>,
+    /// Regular expression
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// What to do with matching requests: `reject`, `redact` or `annotate`
+    #[serde(default)]
+    pub action: FilterAction,
+}
+
+fn create_content_filter(
+    config: &ProcessorConfig,
+) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let settings: ContentFilterSettings = config.settings()?;
+    let mut processor = ContentFilterProcessor::new();
+    if let Some(replacement) = settings.replacement {
+        processor = processor.with_replacement(replacement);
+    }
+    for rule in settings.rules {
+        let mut words = rule.words;
+        if let Some(path) = &rule.words_file {
+            words.extend(read_word_list(path)?);
+        }
+        if !words.is_empty() {
+            processor = processor.with_rule(FilterRule::words(&rule.name, words, rule.action)?);
+        }
+        if let Some(pattern) = &rule.pattern {
+            processor = processor.with_rule(FilterRule::pattern(&rule.name, pattern, rule.action)?);
+        }
+    }
+    Ok(Arc::new(processor))
+}
+
+/// Settings of a `content_rewrite` stream processor
+#[derive(Debug, Deserialize)]
+pub struct ContentRewriteSettings {
+    /// Replacements applied in order
+    pub rules: Vec<ContentRewriteRuleConfig>,
+    /// Characters held back for pattern matches spanning chunks
+    #[serde(default)]
+    pub window: Option<usize>,
+}
+
+/// A replacement of a `content_rewrite` stream processor, of a literal or a
+/// pattern
+#[derive(Debug, Deserialize)]
+pub struct ContentRewriteRuleConfig {
+    /// Text replaced as is
+    #[serde(default)]
+    pub literal: Option<String>,
+    /// Regular expression
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Replacement, which may refer to groups of the pattern like `$1`
+    pub replacement: String,
+}
+
+fn create_content_rewrite(config: &ProcessorConfig) -> Result<Arc<dyn StreamProcessor>> {
+    let settings: ContentRewriteSettings = config.settings()?;
+    let mut processor = ContentRewriteProcessor::new();
+    if let Some(window) = settings.window {
+        processor = processor.with_window(window);
+    }
+    for rule in settings.rules {
+        processor = match (&rule.literal, &rule.pattern) {
+            (Some(literal), None) => processor.with_literal(literal, rule.replacement),
+            (None, Some(pattern)) => processor.with_pattern(pattern, rule.replacement)?,
+            _ => anyhow::bail!("A content rewrite rule needs either a literal or a pattern"),
+        };
+    }
+    Ok(Arc::new(processor))
+}
+
+/// Settings of a `context_window` processor
+#[derive(Debug, Deserialize)]
+pub struct ContextWindowSettings {
+    /// Context window of models without an entry in `models`, in tokens
+    pub max_context_tokens: u32,
+    /// Context windows keyed by model name prefix
+    #[serde(default)]
+    pub models: HashMap<String, u32>,
+    /// Tokens kept for the completion of requests without `max_tokens`
+    #[serde(default)]
+    pub completion_reserve: u32,
+    /// `drop_oldest` or `truncate_middle`
+    #[serde(default)]
+    pub strategy: TruncationStrategy,
+}
+
+fn create_context_window(
+    config: &ProcessorConfig,
+) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let settings: ContextWindowSettings = config.settings()?;
+    let processor = settings.models.into_iter().fold(
+        ContextWindowProcessor::new(settings.max_context_tokens)
+            .with_completion_reserve(settings.completion_reserve)
+            .with_strategy(settings.strategy),
+        |processor, (prefix, limit)| processor.with_model_limit(prefix, limit),
+    );
+    Ok(Arc::new(processor))
+}
+
+/// Settings of an `experiment` processor
+#[derive(Debug, Deserialize)]
+pub struct ExperimentSettings {
+    /// Name of the experiment, returned in the `X-Experiment` header
+    pub name: String,
+    /// Models whose requests take part, all models when empty
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Variants users are split between
+    pub arms: Vec<ExperimentArmConfig>,
+}
+
+/// An arm of an `experiment` processor
+#[derive(Debug, Deserialize)]
+pub struct ExperimentArmConfig {
+    /// Name of the arm, returned in the `X-Experiment-Arm` header
+    pub id: String,
+    /// Shares of the users assigned to the arm
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Model the arm's requests are sent to, the requested one when unset
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Request parameters set for the arm, e.g. `temperature`
+    #[serde(default)]
+    pub params: HashMap<String, serde_json::Value>,
+}
+
+const fn default_weight() -> u32 {
+    1
+}
+
+fn create_experiment(
+    config: &ProcessorConfig,
+) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let settings: ExperimentSettings = config.settings()?;
+    let processor = settings.models.into_iter().fold(
+        ExperimentProcessor::new(settings.name),
+        ExperimentProcessor::with_model,
+    );
+    let processor = settings.arms.into_iter().fold(processor, |processor, arm| {
+        let mut experiment_arm = ExperimentArm::new(arm.id, arm.weight);
+        if let Some(model) = arm.model {
+            experiment_arm = experiment_arm.with_model(model);
+        }
+        processor.with_arm(
+            arm.params
+                .into_iter()
+                .fold(experiment_arm, |experiment_arm, (name, value)| {
+                    experiment_arm.with_param(name, value)
+                }),
+        )
+    });
+    Ok(Arc::new(processor))
+}
+
+fn create_logger(config: &ProcessorConfig) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let level = if config.config_value.is_empty() {
+        tracing::Level::INFO
+    } else {
+        config
+            .config_value
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid log level: {}", config.config_value))?
+    };
+    Ok(Arc::new(LoggingProcessor::new(level)))
+}
+
+fn create_model_rewrite(
+    config: &ProcessorConfig,
+) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let rewrites: HashMap<String, String> = config.settings()?;
+    let processor = rewrites.into_iter().fold(
+        ModelRewriteProcessor::new(),
+        |processor, (model, replacement)| processor.with_rewrite(model, replacement),
+    );
+    Ok(Arc::new(processor))
+}
+
+/// Settings of a `moderation` processor
+#[derive(Debug, Deserialize, Default)]
+pub struct ModerationSettings {
+    /// URL of the moderations endpoint, the `OpenAI` one by default
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Environment variable containing the API token, `OPENAI_API_KEY` by default
+    #[serde(default)]
+    pub token_env: Option<String>,
+    /// Moderation model, the endpoint's default when unset
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Scores from which categories are flagged, instead of the endpoint's verdict
+    #[serde(default)]
+    pub thresholds: HashMap<String, f64>,
+    /// What to do with flagged requests: `reject` or `flag`
+    #[serde(default)]
+    pub action: ModerationAction,
+}
+
+fn create_moderation(
+    config: &ProcessorConfig,
+) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let settings: ModerationSettings = config.settings()?;
+    let url = settings
+        .url
+        .map_or_else(OpenAIUrlProvider::moderations, OpenAIUrlProvider::new);
+    let token = settings
+        .token_env
+        .map_or_else(EnvTokenProvider::standard, EnvTokenProvider::new);
+    let mut processor = ModerationProcessor::new(
+        Arc::new(StaticClientProvider::new()),
+        Arc::new(token),
+        Arc::new(url),
+    )
+    .with_action(settings.action);
+    if let Some(model) = settings.model {
+        processor = processor.with_model(model);
+    }
+    let processor = settings
+        .thresholds
+        .into_iter()
+        .fold(processor, |processor, (category, threshold)| {
+            processor.with_threshold(category, threshold)
+        });
+    Ok(Arc::new(processor))
+}
+
+/// Settings of a `normalize` processor
+#[derive(Debug, Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct NormalizeSettings {
+    /// Remove messages without content or calls
+    #[serde(default = "default_true")]
+    pub strip_empty: bool,
+    /// Remove messages identical to the previous one and repeated system messages
+    #[serde(default = "default_true")]
+    pub deduplicate: bool,
+    /// Merge consecutive messages of the same role
+    #[serde(default = "default_true")]
+    pub merge_consecutive: bool,
+    /// Move system messages to the front and start conversations with a user message
+    #[serde(default)]
+    pub enforce_alternation: bool,
+}
+
+fn create_normalize(config: &ProcessorConfig) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let settings: NormalizeSettings = config.settings()?;
+    Ok(Arc::new(
+        NormalizeProcessor::new()
+            .with_strip_empty(settings.strip_empty)
+            .with_deduplicate(settings.deduplicate)
+            .with_merge_consecutive(settings.merge_consecutive)
+            .with_enforce_alternation(settings.enforce_alternation),
+    ))
+}
+
+/// Settings of an `output_guardrail` stream processor
+#[derive(Debug, Deserialize)]
+pub struct OutputGuardrailSettings {
+    /// Banned content, by category
+    pub categories: Vec<GuardrailCategoryConfig>,
+}
+
+/// A category of an `output_guardrail` stream processor; its words, words
+/// file and patterns all apply
+#[derive(Debug, Deserialize)]
+pub struct GuardrailCategoryConfig {
+    /// Name of the category, reported to the client when output is stopped
+    pub name: String,
+    /// Words and phrases matched case-insensitively as whole words
+    #[serde(default)]
+    pub words: Vec<String>,
+    /// File with one word or phrase per line
+    #[serde(default)]
+    pub words_file: Option<String>,
+    /// Regular expressions
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+fn create_output_guardrail(config: &ProcessorConfig) -> Result<Arc<dyn StreamProcessor>> {
+    let settings: OutputGuardrailSettings = config.settings()?;
+    let mut processor = OutputGuardrailProcessor::new();
+    for category in settings.categories {
+        let mut words = category.words;
+        if let Some(path) = &category.words_file {
+            words.extend(read_word_list(path)?);
+        }
+        if !words.is_empty() {
+            processor = processor.with_words(&category.name, words)?;
+        }
+        for pattern in &category.patterns {
+            processor = processor.with_pattern(&category.name, pattern)?;
+        }
+    }
+    Ok(Arc::new(processor))
+}
+
+fn create_output_limit(config: &ProcessorConfig) -> Result<Arc<dyn StreamProcessor>> {
+    let max_tokens = config
+        .config_value
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid output token limit: {}", config.config_value))?;
+    Ok(Arc::new(OutputLimitProcessor::new(max_tokens)))
+}
+
+/// Settings of a `prompt_injection` processor
+#[derive(Debug, Deserialize, Default)]
+pub struct PromptInjectionSettings {
+    /// How readily content is treated as an attack: `low`, `medium` or `high`
+    #[serde(default)]
+    pub sensitivity: Sensitivity,
+    /// What to do with suspicious content: `block`, `strip` or `tag`
+    #[serde(default)]
+    pub action: InjectionAction,
+    /// Heuristics added to the built-in ones
+    #[serde(default)]
+    pub patterns: Vec<InjectionPatternConfig>,
+    /// Model also scoring the content
+    #[serde(default)]
+    pub classifier: Option<InjectionClassifierConfig>,
+}
+
+/// A heuristic of a `prompt_injection` processor
+#[derive(Debug, Deserialize)]
+pub struct InjectionPatternConfig {
+    /// Name of the heuristic, recorded when it matches
+    pub name: String,
+    /// Regular expression, matched case-insensitively
+    pub pattern: String,
+    /// Score of matching texts, between 0 and 1
+    pub score: f64,
+}
+
+/// Chat model scoring content for a `prompt_injection` processor
+#[derive(Debug, Deserialize)]
+pub struct InjectionClassifierConfig {
+    /// Model asked for scores
+    pub model: String,
+    /// URL of the chat completions endpoint, the `OpenAI` one by default
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Environment variable containing the API token, `OPENAI_API_KEY` by default
+    #[serde(default)]
+    pub token_env: Option<String>,
+}
+
+fn create_prompt_injection(
+    config: &ProcessorConfig,
+) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let settings: PromptInjectionSettings = config.settings()?;
+    let mut processor = PromptInjectionProcessor::new()
+        .with_sensitivity(settings.sensitivity)
+        .with_action(settings.action);
+    for pattern in settings.patterns {
+        processor = processor.with_pattern(pattern.name, &pattern.pattern, pattern.score)?;
+    }
+    if let Some(classifier) = settings.classifier {
+        let url = classifier
+            .url
+            .map_or_else(OpenAIUrlProvider::chat_completions, OpenAIUrlProvider::new);
+        let token = classifier
+            .token_env
+            .map_or_else(EnvTokenProvider::standard, EnvTokenProvider::new);
+        let client = OpenAIClient::new(
+            Arc::new(StaticClientProvider::new()),
+            Arc::new(token),
+            Arc::new(url),
+        );
+        processor = processor.with_classifier(Arc::new(LLMInjectionClassifier::new(
+            Arc::new(client),
+            classifier.model,
+        )));
+    }
+    Ok(Arc::new(processor))
+}
+
+/// Settings of a `request_log` processor
+#[derive(Debug, Deserialize)]
+pub struct RequestLogSettings {
+    /// Where records are written
+    pub sink: RequestLogSinkConfig,
+    /// Share of requests logged, between 0 and 1
+    #[serde(default = "default_sample_rate")]
+    pub sample_rate: f64,
+    /// Mask keys and tokens in logged requests
+    #[serde(default = "default_true")]
+    pub redact: bool,
+}
+
+const fn default_sample_rate() -> f64 {
+    1.0
+}
+
+/// Destination of a `request_log` processor's records
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RequestLogSinkConfig {
+    /// JSON lines on stdout
+    Stdout,
+    /// JSON lines in a file rolled over by size
+    File {
+        /// Path of the file
+        path: String,
+        /// Size from which the file is rolled over
+        #[serde(default)]
+        max_bytes: Option<u64>,
+        /// Number of rolled over files kept
+        #[serde(default)]
+        max_files: Option<usize>,
+    },
+    /// JSON posted to an endpoint
+    Http {
+        /// URL records are posted to
+        url: String,
+        /// Headers sent with every record
+        #[serde(default)]
+        headers: HashMap<String, String>,
+        /// Seconds after which posting a record is abandoned
+        #[serde(default = "default_request_log_timeout_secs")]
+        timeout_secs: u64,
+    },
+}
+
+const fn default_request_log_timeout_secs() -> u64 {
+    10
+}
+
+fn create_request_log(
+    config: &ProcessorConfig,
+) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let settings: RequestLogSettings = config.settings()?;
+    let sink: Arc<dyn RequestLogSink> = match settings.sink {
+        RequestLogSinkConfig::Stdout => Arc::new(StdoutSink),
+        RequestLogSinkConfig::File {
+            path,
+            max_bytes,
+            max_files,
+        } => {
+            let mut sink = RollingFileSink::new(path);
+            if let Some(max_bytes) = max_bytes {
+                sink = sink.with_max_bytes(max_bytes);
+            }
+            if let Some(max_files) = max_files {
+                sink = sink.with_max_files(max_files);
+            }
+            Arc::new(sink)
+        }
+        RequestLogSinkConfig::Http {
+            url,
+            headers,
+            timeout_secs,
+        } => {
+            let client = reqwest::Client::builder()
+                .timeout(Duration::from_secs(timeout_secs))
+                .build()
+                .context("Failed to create request log client")?;
+            Arc::new(
+                headers
+                    .into_iter()
+                    .fold(HttpSink::new(client, url), |sink, (name, value)| {
+                        sink.with_header(name, value)
+                    }),
+            )
+        }
+    };
+    Ok(Arc::new(
+        RequestLogProcessor::new(sink)
+            .with_sample_rate(settings.sample_rate)
+            .with_redaction(settings.redact),
+    ))
+}
+
+/// Settings of a `stop_sequence` stream processor
+#[derive(Debug, Deserialize)]
+pub struct StopSequenceSettings {
+    /// Sequences output is cut at in every response
+    #[serde(default)]
+    pub stops: Vec<String>,
+    /// Also enforce the `stop` parameter of requests
+    #[serde(default = "default_true")]
+    pub request_stops: bool,
+}
+
+fn create_stop_sequence(config: &ProcessorConfig) -> Result<Arc<dyn StreamProcessor>> {
+    let settings: StopSequenceSettings = config.settings()?;
+    let processor = settings
+        .stops
+        .into_iter()
+        .fold(
+            StopSequenceProcessor::new(),
+            StopSequenceProcessor::with_stop,
+        )
+        .with_request_stops(settings.request_stops);
+    Ok(Arc::new(processor))
+}
+
+/// Settings of a `system_prompt` processor
+#[derive(Debug, Deserialize, Default)]
+struct SystemPromptSettings {
+    #[serde(default)]
+    mode: SystemPromptMode,
+}
+
+fn create_system_prompt(
+    config: &ProcessorConfig,
+) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let settings: SystemPromptSettings = config.settings()?;
+    Ok(Arc::new(
+        SystemPromptProcessor::new(&config.config_value).with_mode(settings.mode),
+    ))
+}
+
+/// Settings of a `tool_allowlist` processor
+#[derive(Debug, Deserialize, Default)]
+pub struct ToolAllowlistSettings {
+    /// Approved function names, and types of tools that aren't functions
+    #[serde(default)]
+    pub names: Vec<String>,
+    /// Approved SHA-256 hashes of tool parameter schemas
+    #[serde(default)]
+    pub schema_hashes: Vec<String>,
+    /// Refuse requests forcing or calling unapproved tools instead of
+    /// removing the tools
+    #[serde(default)]
+    pub reject: bool,
+}
+
+fn create_tool_allowlist(
+    config: &ProcessorConfig,
+) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let settings: ToolAllowlistSettings = config.settings()?;
+    let processor = settings.names.into_iter().fold(
+        ToolAllowlistProcessor::new(),
+        ToolAllowlistProcessor::with_name,
+    );
+    let processor = settings
+        .schema_hashes
+        .into_iter()
+        .fold(processor, ToolAllowlistProcessor::with_schema_hash)
+        .with_reject(settings.reject);
+    Ok(Arc::new(processor))
+}
+
+/// Settings of a `usage_metrics` stream processor
+#[derive(Debug, Deserialize, Default)]
+pub struct UsageMetricsSettings {
+    /// Prices in US dollars per million tokens, keyed by model name prefix,
+    /// overriding the server's pricing for this processor
+    #[serde(default)]
+    pub prices: HashMap<String, ModelPrice>,
+}
+
+fn create_usage_metrics(
+    config: &ProcessorConfig,
+    pricing: &Pricing,
+) -> Result<Arc<dyn StreamProcessor>> {
+    let settings: UsageMetricsSettings = config.settings()?;
+    let processor = settings.prices.into_iter().fold(
+        UsageMetricsProcessor::new().with_pricing(pricing.clone()),
+        |processor, (prefix, price)| processor.with_price(prefix, price),
+    );
+    Ok(Arc::new(processor))
+}
+
+/// Settings of a `user_attribution` processor
+#[derive(Debug, Deserialize)]
+pub struct UserAttributionSettings {
+    /// Where the user is looked up, in order
+    pub sources: Vec<UserSource>,
+    /// `metadata` entry also set to the user
+    #[serde(default)]
+    pub metadata_key: Option<String>,
+    /// Replace a `user` sent by the client
+    #[serde(default = "default_true")]
+    pub overwrite: bool,
+    /// Send a SHA-256 hash of the user instead of the user
+    #[serde(default)]
+    pub hashed: bool,
+}
+
+fn create_user_attribution(
+    config: &ProcessorConfig,
+) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let settings: UserAttributionSettings = config.settings()?;
+    let mut processor = settings
+        .sources
+        .into_iter()
+        .fold(
+            UserAttributionProcessor::new(),
+            UserAttributionProcessor::with_source,
+        )
+        .with_overwrite(settings.overwrite)
+        .with_hashed(settings.hashed);
+    if let Some(key) = settings.metadata_key {
+        processor = processor.with_metadata_key(key);
+    }
+    Ok(Arc::new(processor))
+}
+
+/// Settings of a `vision` processor
+#[derive(Debug, Deserialize, Default)]
+pub struct VisionSettings {
+    /// Maximum number of images per request
+    #[serde(default)]
+    pub max_images: Option<usize>,
+    /// `detail` level every image is rewritten to
+    #[serde(default)]
+    pub detail: Option<ImageDetail>,
+    /// Download remote images and send them inline
+    #[serde(default)]
+    pub inline_remote_images: bool,
+    /// Hosts remote images are only downloaded from, trusted even at private
+    /// addresses; any host with a public address if unset
+    #[serde(default)]
+    pub allowed_hosts: Option<Vec<String>>,
+    /// Maximum size of a downloaded image
+    #[serde(default)]
+    pub max_inline_bytes: Option<usize>,
+}
+
+fn create_vision(config: &ProcessorConfig) -> Result<Arc<dyn Processor<ChatCompletionRequest>>> {
+    let settings: VisionSettings = config.settings()?;
+    let mut processor = VisionProcessor::new();
+    if let Some(max_images) = settings.max_images {
+        processor = processor.with_max_images(max_images);
+    }
+    if let Some(detail) = settings.detail {
+        processor = processor.with_detail(detail);
+    }
+    if settings.inline_remote_images {
+        // Redirects could lead to hosts that aren't allowed
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+        processor = processor.with_inline_remote_images(client);
+    }
+    if let Some(allowed_hosts) = settings.allowed_hosts {
+        processor = processor.with_allowed_hosts(allowed_hosts);
+    }
+    if let Some(max_inline_bytes) = settings.max_inline_bytes {
+        processor = processor.with_max_inline_bytes(max_inline_bytes);
+    }
+    Ok(Arc::new(processor))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn config() -> Config {
+        serde_json::from_value(json!({
+            "llm": {},
+            "processor": {
+                "logger": {"type": "logger", "config_value": "info"},
+                "fences": {"type": "code_fence"},
+                "spellcheck": {"type": "spellcheck"},
+            },
+            "route": [],
+            "server": {
+                "host": "127.0.0.1",
+                "port": 3000,
+                "log_level": "info",
+                "request_timeout_secs": 30,
+                "cors_allowed_origins": [],
+            },
+        }))
+        .expect("Invalid config")
+    }
+
+    /// The message of the error creating the processors `ids`, or of the
+    /// stream processors `ids` if `stream`
+    fn error(registry: &ProcessorRegistry, ids: &[&str], stream: bool) -> String {
+        let config = config();
+        let ids: Vec<_> = ids.iter().map(ToString::to_string).collect();
+        let error = if stream {
+            registry.create_stream_processors(&config, &ids).err()
+        } else {
+            registry.create_processors(&config, &ids).err()
+        };
+        format!("{:#}", error.expect("Created processors"))
+    }
+
+    #[test]
+    fn test_unknown_processors() {
+        let registry = create_processor_registry(&config());
+        let ids = ["logger".to_string()];
+        assert_eq!(
+            registry
+                .create_processors(&config(), &ids)
+                .expect("Failed to create processors")
+                .len(),
+            1
+        );
+
+        assert_eq!(
+            error(&registry, &["logger", "spellcheck"], false),
+            "Unknown type spellcheck of processor spellcheck"
+        );
+        assert_eq!(
+            error(&registry, &["spellcheck"], true),
+            "Unknown stream processor type spellcheck of processor spellcheck"
+        );
+        assert_eq!(
+            error(&registry, &["fences"], false),
+            "Processor fences of type code_fence belongs in stream_processors"
+        );
+        assert_eq!(
+            error(&registry, &["logger"], true),
+            "Processor logger of type logger belongs in processors"
+        );
+        assert!(error(&registry, &["missing"], false).contains("missing"));
+
+        // Registering the type makes it known
+        let mut registry = ProcessorRegistry::new();
+        registry.register("spellcheck", create_logger);
+        assert!(registry.get("spellcheck").is_some());
+        assert_eq!(
+            error(&registry, &["logger"], false),
+            "Unknown type logger of processor logger"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Prometheus metrics of the proxy.
+//!
+//! With a `[metrics]` section, the server installs a Prometheus recorder for
+//! the metrics recorded throughout the proxy and serves them in the text
+//! exposition format on the configured path, `/metrics` by default:
+//!
+//! ```toml
+//! [metrics]
+//! path = "/metrics"
+//! buckets = [0.05, 0.1, 0.25, 0.5, 1, 2.5, 5, 10, 30]
+//! ```
+//!
+//! Without `buckets`, histograms are rendered as summaries. With client keys
+//! configured, scrapes must carry a key like any other request.
+//!
+//! The metrics are:
+//!
+//! - `llm_proxy_prompt_tokens_total`, `llm_proxy_completion_tokens_total` and
+//!   `llm_proxy_cost_microdollars_total`, counted by `usage_metrics` stream
+//!   processors
+//! - `llm_proxy_time_to_first_token_seconds`, `llm_proxy_inter_chunk_seconds`,
+//!   `llm_proxy_stream_duration_seconds` and `llm_proxy_tokens_per_second` of
+//!   streamed responses
+//! - `llm_proxy_endpoint_healthy` and
+//!   `llm_proxy_endpoint_health_transitions_total` of health-checked endpoints
+//! - `llm_proxy_upstream_dns_seconds`, `llm_proxy_upstream_connect_seconds`,
+//!   `llm_proxy_upstream_connections_total` and
+//!   `llm_proxy_upstream_requests_total` of LLMs with `connection_metrics`
+//! - `llm_proxy_cache_requests_total` of lookups in the response cache,
+//!   labeled by `result`, `hit` or `miss`
+//! - `llm_proxy_model_rewrites_total`, `llm_proxy_experiment_assignments_total`,
+//!   `llm_proxy_output_guardrail_stops_total` and
+//!   `llm_proxy_output_limit_stops_total` of the processors recording them
+
+use std::sync::OnceLock;
+
+use actix_web::{web, HttpResponse};
+use anyhow::Result;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use tracing::info;
+
+use crate::config::{Config, MetricsConfig};
+
+/// Handle of the recorder, installed once per process
+static HANDLE: OnceLock<Result<PrometheusHandle, String>> = OnceLock::new();
+
+/// Install the Prometheus recorder configured in `[metrics]` as the global
+/// metrics recorder
+///
+/// Only the first call installs a recorder; later ones return its handle.
+///
+/// # Errors
+///
+/// Returns an error if the buckets are invalid or another recorder is
+/// installed already
+pub fn install(config: &MetricsConfig) -> Result<PrometheusHandle> {
+    HANDLE
+        .get_or_init(|| {
+            let mut builder = PrometheusBuilder::new();
+            if let Some(buckets) = &config.buckets {
+                builder = builder.set_buckets(buckets).map_err(|e| e.to_string())?;
+            }
+            let handle = builder.install_recorder().map_err(|e| e.to_string())?;
+            info!(path = config.path, "Installed Prometheus metrics recorder");
+            Ok(handle)
+        })
+        .clone()
+        .map_err(|e| anyhow::anyhow!("Failed to install metrics recorder: {e}"))
+}
+
+/// Register the metrics route configured in `[metrics]`, if any
+pub fn configure(service_config: &mut web::ServiceConfig, config: &Config) {
+    if let Some(metrics) = &config.metrics {
+        service_config.route(&metrics.path, web::get().to(render));
+    }
+}
+
+/// The metrics in the Prometheus text exposition format
+async fn render() -> HttpResponse {
+    let Some(Ok(handle)) = HANDLE.get() else {
+        return HttpResponse::NotFound().finish();
+    };
+    // Histograms are drained into their summaries on scrapes, so they
+    // don't grow between them
+    handle.run_upkeep();
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(handle.render())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test, App};
+    use bytes::Bytes;
+    use llm_proxy_core::{RequestContext, StreamProcessor, StreamTimer};
+    use llm_proxy_openai::stream_processors::UsageMetricsProcessor;
+    use serde_json::{json, Value};
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    fn test_config() -> Config {
+        serde_json::from_value(json!({
+            "llm": {},
+            "processor": {},
+            "route": [],
+            "server": {
+                "host": "127.0.0.1",
+                "port": 3000,
+                "log_level": "info",
+                "request_timeout_secs": 30,
+                "cors_allowed_origins": [],
+            },
+        }))
+        .expect("Invalid config")
+    }
+
+    #[actix_web::test]
+    async fn test_serves_cost_counter() {
+        let config = Config {
+            metrics: Some(MetricsConfig {
+                path: "/prometheus".to_string(),
+                buckets: None,
+            }),
+            ..test_config()
+        };
+        install(config.metrics.as_ref().expect("No metrics")).expect("Failed to install");
+
+        let (tx, rx) = mpsc::channel(1);
+        let response = json!({
+            "model": "gpt-4o",
+            "choices": [],
+            "usage": {"prompt_tokens": 1_000, "completion_tokens": 500},
+        });
+        tx.send(Ok(Bytes::from(format!("data: {response}\n\n"))))
+            .await
+            .expect("Failed to send chunk");
+        drop(tx);
+        let context = RequestContext::new().with_route("/v1/chat/completions");
+        let mut stream = UsageMetricsProcessor::new().process_stream(&Value::Null, &context, rx);
+        while stream.recv().await.is_some() {}
+
+        let app = test::init_service(App::new().configure(|service_config| {
+            configure(service_config, &config);
+        }))
+        .await;
+        let request = test::TestRequest::get().uri("/prometheus").to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        let body = String::from_utf8_lossy(&body);
+        assert!(
+            body.lines().any(
+                |line| line.starts_with("llm_proxy_cost_microdollars_total{")
+                    && line.contains(r#"route="/v1/chat/completions""#)
+                    && line.ends_with(" 7500")
+            ),
+            "{body}"
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_serves_stream_latency() {
+        let config = Config {
+            metrics: Some(MetricsConfig::default()),
+            ..test_config()
+        };
+        install(&MetricsConfig::default()).expect("Failed to install");
+
+        let mut timer = StreamTimer::start("llama3");
+        timer.output_chunk();
+        timer.output_chunk();
+        timer.finish();
+
+        let app = test::init_service(App::new().configure(|service_config| {
+            configure(service_config, &config);
+        }))
+        .await;
+        let request = test::TestRequest::get().uri("/metrics").to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        let body = String::from_utf8_lossy(&body);
+        for metric in [
+            "llm_proxy_time_to_first_token_seconds",
+            "llm_proxy_inter_chunk_seconds",
+            "llm_proxy_stream_duration_seconds",
+        ] {
+            assert!(
+                body.contains(&format!(r#"{metric}_count{{model="llama3"}} "#)),
+                "{metric} missing from {body}"
+            );
+        }
+    }
+}
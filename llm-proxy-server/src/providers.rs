@@ -0,0 +1,663 @@
+//! Construction of the supporting providers for configured LLM backends.
+
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use llm_proxy_core::{
+    providers::{
+        key_pool::{DEFAULT_RATE_LIMITED_QUARANTINE, DEFAULT_UNAUTHORIZED_QUARANTINE},
+        load_keys, ChainedTokenProvider, ClientSettings, ConfigurableClientProvider,
+        DiscoverySource, DiscoveryUrlProvider, FileClientKeyStore, FileTokenProvider,
+        HealthCheckedUrlProvider, HealthListener, InstrumentedClientProvider, KeyPoolTokenProvider,
+        LoadBalancingUrlProvider, PooledKey, ProxySettings, StaticClientKeyStore,
+        StaticTenantResolver, TlsSettings, TokenRegistry, WeightedEndpoint,
+    },
+    redact::SecretString,
+    AuthScheme, ClientKey, ClientKeyStore, ClientProvider, Pipeline, ProviderContext,
+    ProviderRegistry, RetryPolicy, Tenant, TokenProvider, UrlProvider,
+};
+use tracing::warn;
+
+use llm_proxy_openai::{
+    AzureCredential, AzureEntraTokenProvider, ChatCompletionRequest, EnvTokenProvider,
+    ORGANIZATION_ATTRIBUTE, PROJECT_ATTRIBUTE,
+};
+
+#[cfg(feature = "mock")]
+use llm_proxy_core::chaos::{FaultInjectingLLMClient, FaultScenario};
+#[cfg(feature = "keyring")]
+use llm_proxy_core::providers::KeyringTokenProvider;
+#[cfg(feature = "sqlite")]
+use llm_proxy_core::providers::SqliteClientKeyStore;
+#[cfg(feature = "aws")]
+use llm_proxy_core::providers::{AwsSecretSource, AwsSecretTokenProvider};
+#[cfg(feature = "gcp")]
+use llm_proxy_core::providers::{GcpCredentials, GcpTokenProvider};
+
+use crate::config::{
+    ClientKeysConfig, DiscoveryConfig, DiscoverySourceConfig, HealthCheckConfig, HttpClientConfig,
+    LLMConfig, ProxyConfig, RetryConfig, RouteConfig, TenantConfig, TlsConfig, TokenPoolConfig,
+    TokenSourceConfig,
+};
+
+/// Create the token provider for an LLM backend.
+///
+/// Backends with a `token_pool` rotate across the pooled keys and backends
+/// with a `token_source` read their key from that source; all others read
+/// their key from the `token_env` environment variable. Backends with
+/// `token_rules` use the token of the first matching rule instead, per request.
+///
+/// # Errors
+///
+/// This function will return an error if a key cannot be loaded.
+pub async fn create_token_provider(llm_config: &LLMConfig) -> Result<Arc<dyn TokenProvider>> {
+    let default = create_default_token_provider(llm_config).await?;
+    if llm_config.token_rules.is_empty() {
+        return Ok(default);
+    }
+
+    let mut registry = TokenRegistry::new().with_default(default);
+    for rule in &llm_config.token_rules {
+        let provider = create_token_source(&rule.source).await?;
+        registry = match (&rule.tenant, &rule.model, &rule.route) {
+            (Some(tenant), None, None) => registry.with_tenant(tenant, provider),
+            (None, Some(model), None) => registry.with_model(model, provider),
+            (None, None, Some(route)) => registry.with_route(route, provider),
+            _ => anyhow::bail!("Token rules must set exactly one of tenant, model or route"),
+        };
+    }
+    Ok(Arc::new(registry))
+}
+
+/// Create the token provider used when no token rule matches
+async fn create_default_token_provider(llm_config: &LLMConfig) -> Result<Arc<dyn TokenProvider>> {
+    if let Some(pool) = &llm_config.token_pool {
+        return Ok(Arc::new(create_key_pool(pool)?));
+    }
+    if let Some(source) = &llm_config.token_source {
+        return create_token_source(source).await;
+    }
+    Ok(Arc::new(EnvTokenProvider::new(&llm_config.token_env)))
+}
+
+/// Build a token provider reading from an external source, fetching the key once
+/// up front so that misconfigurations surface immediately
+async fn create_token_source(source: &TokenSourceConfig) -> Result<Arc<dyn TokenProvider>> {
+    match source.clone() {
+        TokenSourceConfig::Env { env } => Ok(Arc::new(EnvTokenProvider::new(env))),
+        TokenSourceConfig::Chain { sources } => {
+            // Sources that can't be set up (e.g. a file that isn't mounted in
+            // this environment) are skipped, so the chain can fall back
+            let mut chain = ChainedTokenProvider::new();
+            for source in &sources {
+                match Box::pin(create_token_source(source)).await {
+                    Ok(provider) => chain = chain.with_provider(source.name(), provider),
+                    Err(e) => warn!(source = %source.name(), error = %e, "Skipping token source"),
+                }
+            }
+            Ok(Arc::new(chain))
+        }
+        TokenSourceConfig::File { path } => {
+            let provider = FileTokenProvider::new(&path)
+                .and_then(FileTokenProvider::watch)
+                .with_context(|| format!("Failed to load key file {path}"))?;
+            Ok(Arc::new(provider))
+        }
+        TokenSourceConfig::AzureEntra {
+            tenant_id,
+            client_id,
+            client_secret_env,
+        } => {
+            let credential = match (tenant_id, client_secret_env) {
+                (Some(tenant_id), Some(secret_env)) => AzureCredential::ClientSecret {
+                    tenant_id,
+                    client_id: client_id
+                        .context("client_id is required for client secret authentication")?,
+                    client_secret: std::env::var(&secret_env).with_context(|| {
+                        format!("Failed to read client secret from {secret_env}")
+                    })?
+                    .into(),
+                },
+                (None, None) => AzureCredential::ManagedIdentity { client_id },
+                _ => anyhow::bail!(
+                    "tenant_id and client_secret_env must be set together for Entra ID authentication"
+                ),
+            };
+            Ok(Arc::new(AzureEntraTokenProvider::new(credential)))
+        }
+        #[cfg(feature = "aws")]
+        TokenSourceConfig::AwsSecretsManager {
+            secret_id,
+            json_key,
+            region,
+            refresh_secs,
+        } => {
+            let source = AwsSecretSource::SecretsManager {
+                secret_id,
+                json_key,
+            };
+            create_aws_secret_provider(source, region, refresh_secs).await
+        }
+        #[cfg(feature = "aws")]
+        TokenSourceConfig::AwsSsmParameter {
+            name,
+            region,
+            refresh_secs,
+        } => {
+            let source = AwsSecretSource::SsmParameter { name };
+            create_aws_secret_provider(source, region, refresh_secs).await
+        }
+        #[cfg(feature = "gcp")]
+        TokenSourceConfig::Gcp {
+            credentials_file,
+            scopes,
+            audience,
+        } => {
+            let credentials = match &credentials_file {
+                Some(path) => GcpCredentials::from_file(path)?,
+                None => GcpCredentials::application_default()?,
+            };
+            let mut provider = GcpTokenProvider::new(credentials);
+            if !scopes.is_empty() {
+                provider = provider.with_scopes(scopes);
+            }
+            if let Some(audience) = audience {
+                provider = provider.with_audience(audience);
+            }
+            Ok(Arc::new(provider))
+        }
+        #[cfg(feature = "keyring")]
+        TokenSourceConfig::Keyring { service, user } => {
+            let provider = KeyringTokenProvider::new(&service, &user);
+            provider
+                .get_token()
+                .await
+                .with_context(|| format!("Failed to read keychain entry {service}/{user}"))?;
+            Ok(Arc::new(provider))
+        }
+    }
+}
+
+/// Build a token provider reading from AWS Secrets Manager or SSM
+#[cfg(feature = "aws")]
+async fn create_aws_secret_provider(
+    source: AwsSecretSource,
+    region: Option<String>,
+    refresh_secs: Option<u64>,
+) -> Result<Arc<dyn TokenProvider>> {
+    let mut provider = AwsSecretTokenProvider::from_env(source, region).await;
+    if let Some(refresh_secs) = refresh_secs {
+        provider = provider.with_refresh_interval(Duration::from_secs(refresh_secs));
+    }
+    provider
+        .refresh()
+        .await
+        .context("Failed to fetch API key from AWS")?;
+    Ok(Arc::new(provider))
+}
+
+/// Build a key pool from its configuration
+fn create_key_pool(config: &TokenPoolConfig) -> Result<KeyPoolTokenProvider> {
+    let mut keys = config
+        .keys
+        .iter()
+        .map(|key| {
+            let token = std::env::var(&key.env)
+                .with_context(|| format!("Failed to read pooled key from {}", key.env))?;
+            let pooled = PooledKey::new(token).with_weight(key.weight);
+            Ok(match key.max_requests_per_minute {
+                Some(limit) => pooled.with_max_requests_per_minute(limit),
+                None => pooled,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if let Some(file) = &config.file {
+        keys.extend(load_keys(file).with_context(|| format!("Failed to load key file {file}"))?);
+    }
+
+    if keys.is_empty() {
+        anyhow::bail!("Token pool has no keys");
+    }
+
+    Ok(KeyPoolTokenProvider::new(keys).with_quarantine(
+        config
+            .unauthorized_quarantine_secs
+            .map_or(DEFAULT_UNAUTHORIZED_QUARANTINE, Duration::from_secs),
+        config
+            .rate_limited_quarantine_secs
+            .map_or(DEFAULT_RATE_LIMITED_QUARANTINE, Duration::from_secs),
+    ))
+}
+
+/// Create the resolver mapping tenant keys to tenants
+///
+/// # Errors
+///
+/// This function will return an error if a tenant has no key or its key cannot be read.
+pub fn create_tenant_resolver(tenants: &[TenantConfig]) -> Result<StaticTenantResolver> {
+    tenants
+        .iter()
+        .try_fold(StaticTenantResolver::new(), |resolver, config| {
+            let key_env = config.key_env.as_ref().with_context(|| {
+                format!(
+                    "Tenant {} needs a key_env unless client_keys are configured",
+                    config.id
+                )
+            })?;
+            let key = std::env::var(key_env).with_context(|| {
+                format!("Failed to read key of tenant {} from {key_env}", config.id)
+            })?;
+            Ok(resolver.with_tenant(key, tenant(config)))
+        })
+}
+
+/// The configured tenants by ID, for mapping client keys to tenants
+#[must_use]
+pub fn tenants_by_id(tenants: &[TenantConfig]) -> HashMap<String, Tenant> {
+    tenants
+        .iter()
+        .map(|config| (config.id.clone(), tenant(config)))
+        .collect()
+}
+
+/// The tenant of `config`, with its upstream organization and project as attributes
+fn tenant(config: &TenantConfig) -> Tenant {
+    let mut tenant = Tenant {
+        id: config.id.clone(),
+        ..Tenant::default()
+    };
+    if let Some(organization) = &config.organization {
+        tenant
+            .attributes
+            .insert(ORGANIZATION_ATTRIBUTE.to_string(), organization.clone());
+    }
+    if let Some(project) = &config.project {
+        tenant
+            .attributes
+            .insert(PROJECT_ATTRIBUTE.to_string(), project.clone());
+    }
+    tenant
+}
+
+/// Create the store of the proxy-issued keys clients authenticate with
+///
+/// Key files are watched, so this must be called from within a Tokio runtime.
+///
+/// # Errors
+///
+/// This function will return an error if a key cannot be read, or the key
+/// file or database cannot be opened.
+pub fn create_client_key_store(config: &ClientKeysConfig) -> Result<Arc<dyn ClientKeyStore>> {
+    let store: Arc<dyn ClientKeyStore> = match config {
+        ClientKeysConfig::Static { keys } => Arc::new(keys.iter().try_fold(
+            StaticClientKeyStore::new(),
+            |store, key| {
+                let value = std::env::var(&key.key_env).with_context(|| {
+                    format!("Failed to read client key {} from {}", key.id, key.key_env)
+                })?;
+                let identity = ClientKey {
+                    id: key.id.clone(),
+                    tenant: key.tenant.clone(),
+                };
+                anyhow::Ok(store.with_key(&value, identity))
+            },
+        )?),
+        ClientKeysConfig::File { path } => Arc::new(
+            FileClientKeyStore::new(path)
+                .and_then(FileClientKeyStore::watch)
+                .with_context(|| format!("Failed to load client keys from {path}"))?,
+        ),
+        #[cfg(feature = "sqlite")]
+        ClientKeysConfig::Sqlite { url } => Arc::new(
+            SqliteClientKeyStore::new(url).context("Failed to open the client key database")?,
+        ),
+    };
+    Ok(store)
+}
+
+/// Create the URL provider for an LLM backend with more than a single `base_url`.
+///
+/// Backends with `discovery` look up their endpoints dynamically, backends
+/// with a `health_check` only use endpoints passing the check, and backends
+/// with `endpoints` spread requests across them. Returns `None` for backends
+/// that only use their `base_url`. Health checks are authenticated with
+/// `token_provider` and report endpoints changing health to
+/// `health_listener`, when given. Background lookups and probes are started
+/// immediately, so this must be called from within a Tokio runtime.
+///
+/// # Errors
+///
+/// This function will return an error if a configured environment variable is missing.
+pub fn create_url_provider(
+    llm_config: &LLMConfig,
+    token_provider: Option<&Arc<dyn TokenProvider>>,
+    health_listener: Option<&Arc<dyn HealthListener>>,
+) -> Result<Option<Arc<dyn UrlProvider>>> {
+    if let Some(discovery) = &llm_config.discovery {
+        return Ok(Some(Arc::new(create_discovery_provider(discovery)?)));
+    }
+    if let Some(health_check) = &llm_config.health_check {
+        return Ok(Some(Arc::new(create_health_checked_provider(
+            llm_config,
+            health_check,
+            token_provider,
+            health_listener,
+        ))));
+    }
+    if llm_config.endpoints.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Arc::new(LoadBalancingUrlProvider::new(
+        weighted_endpoints(llm_config),
+    ))))
+}
+
+/// The endpoints of an LLM, or its base URL when no endpoints are configured
+fn weighted_endpoints(llm_config: &LLMConfig) -> Vec<WeightedEndpoint> {
+    if llm_config.endpoints.is_empty() {
+        return vec![WeightedEndpoint::new(&llm_config.base_url)];
+    }
+    llm_config
+        .endpoints
+        .iter()
+        .map(|endpoint| WeightedEndpoint::new(&endpoint.url).with_weight(endpoint.weight))
+        .collect()
+}
+
+fn create_health_checked_provider(
+    llm_config: &LLMConfig,
+    health_check: &HealthCheckConfig,
+    token_provider: Option<&Arc<dyn TokenProvider>>,
+    health_listener: Option<&Arc<dyn HealthListener>>,
+) -> HealthCheckedUrlProvider {
+    let mut provider = HealthCheckedUrlProvider::new(weighted_endpoints(llm_config));
+    if let Some(token_provider) = token_provider {
+        provider = provider.with_token_provider(token_provider.clone());
+    }
+    if let Some(health_listener) = health_listener {
+        provider = provider.with_listener(health_listener.clone());
+    }
+    if let Some(path) = &health_check.path {
+        provider = provider.with_probe_path(path);
+    }
+    if let Some(secs) = health_check.interval_secs {
+        provider = provider.with_interval(Duration::from_secs(secs));
+    }
+    if let Some(secs) = health_check.timeout_secs {
+        provider = provider.with_timeout(Duration::from_secs(secs));
+    }
+    if let Some(failures) = health_check.failure_threshold {
+        provider = provider.with_failure_threshold(failures);
+    }
+    provider.spawn_probes()
+}
+
+fn create_discovery_provider(discovery: &DiscoveryConfig) -> Result<DiscoveryUrlProvider> {
+    let source = match &discovery.source {
+        #[cfg(feature = "dns")]
+        DiscoverySourceConfig::DnsSrv { name } => DiscoverySource::DnsSrv { name: name.clone() },
+        DiscoverySourceConfig::Consul {
+            address,
+            service,
+            tag,
+            token_env,
+        } => DiscoverySource::Consul {
+            address: address.clone(),
+            service: service.clone(),
+            tag: tag.clone(),
+            token: token_env
+                .as_ref()
+                .map(|env| {
+                    std::env::var(env)
+                        .map(SecretString::from)
+                        .with_context(|| format!("Consul token variable {env} is not set"))
+                })
+                .transpose()?,
+        },
+    };
+
+    let mut provider = DiscoveryUrlProvider::new(source);
+    if let Some(scheme) = &discovery.scheme {
+        provider = provider.with_scheme(scheme);
+    }
+    if let Some(path) = &discovery.path {
+        provider = provider.with_path(path);
+    }
+    if let Some(secs) = discovery.refresh_secs {
+        provider = provider.with_refresh_interval(Duration::from_secs(secs));
+    }
+    Ok(provider.spawn_refresh())
+}
+
+/// Create the HTTP client provider for an LLM backend with custom networking.
+///
+/// The client is tuned with the timeout, pool, HTTP/2, keepalive and
+/// user-agent options of the backend's `additional_config`, sends requests
+/// through the backend's `proxy` and uses its `tls` settings. A `route` with
+/// its own `client` options or `proxy` gets a dedicated client, with its
+/// options replacing those of the backend. Returns `None` for backends using
+/// the default client.
+///
+/// # Errors
+///
+/// This function will return an error if the client options, proxy or TLS
+/// configuration are invalid.
+pub fn create_client_provider(
+    llm_config: &LLMConfig,
+    route: Option<&RouteConfig>,
+) -> Result<Option<Arc<dyn ClientProvider>>> {
+    let mut http: HttpClientConfig = llm_config.provider_config()?;
+    if let Some(overrides) = route.and_then(|route| route.client.as_ref()) {
+        http = http.merged(overrides);
+    }
+    let proxy = route
+        .and_then(|route| route.proxy.as_ref())
+        .or(llm_config.proxy.as_ref());
+    if proxy.is_none() && llm_config.tls.is_none() && http == HttpClientConfig::default() {
+        return Ok(None);
+    }
+
+    let mut settings = ClientSettings::new();
+    if let Some(secs) = http.connect_timeout_secs {
+        settings = settings.with_connect_timeout(Duration::from_secs(secs));
+    }
+    if let Some(secs) = http.read_timeout_secs {
+        settings = settings.with_read_timeout(Duration::from_secs(secs));
+    }
+    if let Some(secs) = http.timeout_secs {
+        settings = settings.with_timeout(Duration::from_secs(secs));
+    }
+    if let Some(max) = http.pool_max_idle_per_host {
+        settings = settings.with_pool_max_idle_per_host(max);
+    }
+    if let Some(secs) = http.pool_idle_timeout_secs {
+        settings = settings.with_pool_idle_timeout(Duration::from_secs(secs));
+    }
+    if http.http2_prior_knowledge {
+        settings = settings.with_http2_prior_knowledge();
+    }
+    if let Some(secs) = http.tcp_keepalive_secs {
+        settings = settings.with_tcp_keepalive(Duration::from_secs(secs));
+    }
+    if let Some(user_agent) = http.user_agent {
+        settings = settings.with_user_agent(user_agent);
+    }
+    if let Some(proxy) = proxy {
+        settings = settings.with_proxy(proxy_settings(proxy));
+    }
+    if let Some(tls) = &llm_config.tls {
+        settings = settings.with_tls(tls_settings(tls));
+    }
+
+    let context = || {
+        format!(
+            "Invalid HTTP client configuration for {} provider",
+            llm_config.provider
+        )
+    };
+    if http.connection_metrics {
+        // Label the metrics with the host of the backend
+        let upstream = reqwest::Url::parse(&llm_config.base_url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| llm_config.base_url.clone());
+        let provider =
+            InstrumentedClientProvider::new(&settings, upstream).with_context(context)?;
+        return Ok(Some(Arc::new(provider)));
+    }
+    let provider = ConfigurableClientProvider::new(&settings).with_context(context)?;
+    Ok(Some(Arc::new(provider)))
+}
+
+/// `pipeline` with the faults of the scenario file at `path` injected into
+/// the responses of its client
+///
+/// # Errors
+///
+/// This function will return an error if the scenario can't be loaded.
+#[cfg(feature = "mock")]
+pub fn inject_faults(
+    pipeline: Pipeline<ChatCompletionRequest>,
+    path: &str,
+) -> Result<Pipeline<ChatCompletionRequest>> {
+    let scenario = FaultScenario::from_file(path)?;
+    warn!(scenario = path, "Injecting faults into upstream responses");
+    Ok(pipeline.map_client(|client| Arc::new(FaultInjectingLLMClient::new(client, scenario))))
+}
+
+/// `pipeline` with the faults of the scenario file at `path` injected into
+/// the responses of its client
+///
+/// # Errors
+///
+/// Always, as fault injection needs the `mock` feature.
+#[cfg(not(feature = "mock"))]
+pub fn inject_faults(
+    _pipeline: Pipeline<ChatCompletionRequest>,
+    _path: &str,
+) -> Result<Pipeline<ChatCompletionRequest>> {
+    anyhow::bail!("Fault scenarios need the server built with the mock feature")
+}
+
+/// Check that the fault scenario file at `path` can be loaded
+///
+/// # Errors
+///
+/// This function will return an error if the scenario is invalid or the
+/// server was built without the `mock` feature.
+#[cfg(feature = "mock")]
+pub fn check_fault_scenario(path: &str) -> Result<()> {
+    FaultScenario::from_file(path)?;
+    Ok(())
+}
+
+/// Check that the fault scenario file at `path` can be loaded
+///
+/// # Errors
+///
+/// Always, as fault injection needs the `mock` feature.
+#[cfg(not(feature = "mock"))]
+pub fn check_fault_scenario(_path: &str) -> Result<()> {
+    anyhow::bail!("Fault scenarios need the server built with the mock feature")
+}
+
+/// The retry policy of a `retry` section
+#[must_use]
+pub const fn retry_policy(config: &RetryConfig) -> RetryPolicy {
+    RetryPolicy::new(config.max_retries)
+        .with_initial_backoff(Duration::from_millis(config.initial_backoff_ms))
+        .with_max_backoff(Duration::from_millis(config.max_backoff_ms))
+}
+
+fn proxy_settings(config: &ProxyConfig) -> ProxySettings {
+    config
+        .no_proxy
+        .iter()
+        .fold(ProxySettings::new(&config.url), |settings, host| {
+            settings.with_no_proxy(host)
+        })
+}
+
+fn tls_settings(config: &TlsConfig) -> TlsSettings {
+    TlsSettings {
+        client_cert: config.client_cert.as_ref().map(PathBuf::from),
+        client_key: config.client_key.as_ref().map(PathBuf::from),
+        ca_bundle: config.ca_bundle.as_ref().map(PathBuf::from),
+    }
+}
+
+/// Create the registry of the provider factories compiled into the server.
+///
+/// Each provider crate registers its factories under the names used in the
+/// `provider` field of LLM configurations.
+#[must_use]
+pub fn create_provider_registry() -> ProviderRegistry<ChatCompletionRequest> {
+    let mut registry = ProviderRegistry::new();
+    llm_proxy_openai::factory::register(&mut registry);
+    #[cfg(feature = "anthropic")]
+    llm_proxy_anthropic::factory::register(&mut registry);
+    #[cfg(feature = "bedrock")]
+    llm_proxy_bedrock::factory::register(&mut registry);
+    #[cfg(feature = "ollama")]
+    llm_proxy_ollama::factory::register(&mut registry);
+    #[cfg(feature = "tgi")]
+    llm_proxy_tgi::factory::register(&mut registry);
+    #[cfg(feature = "llamacpp")]
+    llm_proxy_llamacpp::factory::register(&mut registry);
+    #[cfg(feature = "vertex")]
+    llm_proxy_vertex::factory::register(&mut registry);
+    #[cfg(feature = "replicate")]
+    llm_proxy_replicate::factory::register(&mut registry);
+    registry
+}
+
+/// Create the context a provider factory builds the pipeline of a route from.
+///
+/// The backend's `additional_config` is passed on as the provider-specific
+/// settings. Backends authenticated with Entra ID send their token as a
+/// bearer token unless `auth` says otherwise. Endpoints changing health are
+/// reported to `health_listener`, when given.
+///
+/// # Errors
+///
+/// This function will return an error if the URL or client provider can't be created.
+pub fn create_provider_context(
+    llm_config: &LLMConfig,
+    route: &RouteConfig,
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    health_listener: Option<&Arc<dyn HealthListener>>,
+) -> Result<ProviderContext> {
+    let mut context = ProviderContext::new(&llm_config.base_url)
+        .with_settings(llm_config.additional_config.clone())
+        .with_capabilities(llm_config.capabilities());
+    if let Some(url_provider) =
+        create_url_provider(llm_config, token_provider.as_ref(), health_listener)?
+    {
+        context = context.with_url_provider(url_provider);
+    }
+    if let Some(client_provider) = create_client_provider(llm_config, Some(route))? {
+        context = context.with_client_provider(client_provider);
+    }
+    if let Some(auth) = &llm_config.auth {
+        context = context.with_auth_scheme(auth.scheme());
+    } else if matches!(
+        llm_config.token_source,
+        Some(TokenSourceConfig::AzureEntra { .. })
+    ) {
+        context = context.with_auth_scheme(AuthScheme::Bearer);
+    }
+    for (name, value) in route.upstream_headers(llm_config) {
+        context = context.with_header(name, value);
+    }
+    if let Some(organization) = &llm_config.organization {
+        context = context.with_attribute(ORGANIZATION_ATTRIBUTE, organization);
+    }
+    if let Some(project) = &llm_config.project {
+        context = context.with_attribute(PROJECT_ATTRIBUTE, project);
+    }
+    if let Some(token_provider) = token_provider {
+        context = context.with_token_provider(token_provider);
+    }
+    Ok(context)
+}
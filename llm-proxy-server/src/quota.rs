@@ -0,0 +1,770 @@
+//! Rate limits and spend quotas of client keys.
+//!
+//! With a `[quota]` section, the requests of each client key are counted
+//! against its limits: requests per UTC minute, prompt and completion tokens
+//! per UTC day, and US dollars per UTC calendar month, priced with the
+//! server's `[pricing]`. Keys use the limits under `[quota.keys]`, or else
+//! the `[quota.default]` ones, and the limits of a key can be changed at
+//! runtime through `PUT /admin/quotas/{key}`.
+//!
+//! A request is refused with a 429 and a `Retry-After` header once any
+//! limit of its key is used up. Admitted requests carry the limits and what
+//! remains of them in `x-ratelimit-*` and `x-quota-*` response headers.
+//! Tokens and dollars are counted from the usage the backend reports when a
+//! response ends, so a request is admitted as long as some of the quota is
+//! left; the usage of passthrough responses isn't counted. Streamed requests
+//! ask for their usage even when the client doesn't, responses are charged
+//! in full when the client disconnects early, and responses without usage
+//! are charged an estimate.
+//!
+//! Usage is kept in memory, per replica, and starts over on restart.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::HttpResponse;
+use bytes::Bytes;
+use llm_proxy_core::{Pricing, RequestContext, ResponseStream};
+use llm_proxy_openai::processors::{ApproximateTokenCounter, TokenCounter};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::{
+    access_log::UsageScanner,
+    config::{Config, QuotaLimits},
+};
+
+/// Context attribute with the approximate prompt tokens of a request,
+/// charged when its response reports no usage
+pub const PROMPT_TOKENS_ATTRIBUTE: &str = "quota.prompt_tokens";
+
+/// Context attribute marking requests whose usage the proxy asked for, so it
+/// is removed from their response
+pub const STRIP_USAGE_ATTRIBUTE: &str = "quota.strip_usage";
+
+const SECONDS_PER_MINUTE: u64 = 60;
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// The limits and usage of the client keys
+#[derive(Debug)]
+pub struct Quotas {
+    pricing: Pricing,
+    default: QuotaLimits,
+    /// Limits by client key ID, overriding the default
+    limits: RwLock<HashMap<String, QuotaLimits>>,
+    /// Usage by client key ID
+    usage: Mutex<HashMap<String, Usage>>,
+}
+
+/// Usage of a client key in the current windows
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Usage {
+    /// Minutes since the Unix epoch of the request count
+    #[serde(skip)]
+    minute: u64,
+    /// Requests in the current minute
+    pub requests: u64,
+    /// Days since the Unix epoch of the token count
+    #[serde(skip)]
+    day: u64,
+    /// Tokens in the current day
+    pub tokens: u64,
+    /// Months since the Unix epoch of the spend
+    #[serde(skip)]
+    month: u64,
+    /// Dollars spent in the current month
+    pub dollars: f64,
+}
+
+impl Usage {
+    /// Start the windows that ended before `now` over
+    const fn roll(&mut self, now: u64) {
+        let minute = now / SECONDS_PER_MINUTE;
+        if self.minute != minute {
+            self.minute = minute;
+            self.requests = 0;
+        }
+        let day = now / SECONDS_PER_DAY;
+        if self.day != day {
+            self.day = day;
+            self.tokens = 0;
+        }
+        let (month, _) = month_of(now);
+        if self.month != month {
+            self.month = month;
+            self.dollars = 0.0;
+        }
+    }
+}
+
+/// The limits of a client key and its usage, for the admin API
+#[derive(Debug, Serialize)]
+pub struct QuotaStatus {
+    pub limits: QuotaLimits,
+    pub usage: Usage,
+}
+
+/// A request refused because a limit of its key is used up
+#[derive(Debug)]
+pub struct QuotaExceeded {
+    message: String,
+    /// Whether the monthly spend rather than a rate limit is used up
+    spend: bool,
+    /// Seconds until all used up limits start over
+    retry_after: u64,
+    headers: Vec<(String, String)>,
+}
+
+impl QuotaExceeded {
+    /// Which limits are used up
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// A 429 response in the format of the `OpenAI` API's errors
+    #[must_use]
+    pub fn response(&self) -> HttpResponse {
+        let mut response = HttpResponse::TooManyRequests();
+        response.insert_header(("Retry-After", self.retry_after.to_string()));
+        for header in &self.headers {
+            response.insert_header(header.clone());
+        }
+        let code = if self.spend {
+            "insufficient_quota"
+        } else {
+            "rate_limit_exceeded"
+        };
+        response.json(json!({
+            "error": {
+                "message": self.message,
+                "type": "rate_limit_error",
+                "param": null,
+                "code": code,
+            }
+        }))
+    }
+}
+
+impl Quotas {
+    /// Create the quotas configured in `[quota]`, priced with `[pricing]`
+    #[must_use]
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let quota = config.quota.as_ref()?;
+        let pricing = config
+            .pricing
+            .iter()
+            .fold(Pricing::builtin(), |pricing, (prefix, price)| {
+                pricing.with_price(prefix.clone(), *price)
+            });
+        Some(Self {
+            pricing,
+            default: quota.default,
+            limits: RwLock::new(quota.keys.clone()),
+            usage: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The limits of the client key `key`, its own or else those of its
+    /// tenant's overlay or the default
+    #[must_use]
+    pub fn limits(&self, key: &str, tenant: Option<QuotaLimits>) -> QuotaLimits {
+        self.limits
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(key)
+            .copied()
+            .or(tenant)
+            .unwrap_or(self.default)
+    }
+
+    /// Replace the limits of the client key `key`
+    pub fn set_limits(&self, key: &str, limits: QuotaLimits) {
+        self.limits
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key.to_string(), limits);
+    }
+
+    /// The limits and usage of the keys with limits of their own or usage
+    #[must_use]
+    pub fn status(&self) -> BTreeMap<String, QuotaStatus> {
+        let now = now();
+        let limits = self
+            .limits
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let usage = self
+            .usage
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        limits
+            .keys()
+            .chain(usage.keys())
+            .map(|key| {
+                let mut key_usage = usage.get(key).copied().unwrap_or_default();
+                key_usage.roll(now);
+                let status = QuotaStatus {
+                    limits: limits.get(key).copied().unwrap_or(self.default),
+                    usage: key_usage,
+                };
+                (key.clone(), status)
+            })
+            .collect()
+    }
+
+    /// Count a request of the client key `key`, with the headers reporting
+    /// its remaining quota
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error, and not count the request, if
+    /// any limit of the key is used up.
+    pub fn admit(
+        &self,
+        key: &str,
+        tenant: Option<QuotaLimits>,
+    ) -> Result<Vec<(String, String)>, QuotaExceeded> {
+        self.admit_at(key, tenant, now())
+    }
+
+    fn admit_at(
+        &self,
+        key: &str,
+        tenant: Option<QuotaLimits>,
+        now: u64,
+    ) -> Result<Vec<(String, String)>, QuotaExceeded> {
+        let limits = self.limits(key, tenant);
+        self.update(key, now, |usage| admit(&limits, usage, now))
+    }
+
+    /// Count the usage of a response of the client key `key` for `model`
+    pub fn record(
+        &self,
+        key: &str,
+        model: Option<&str>,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+    ) {
+        self.record_at(key, model, prompt_tokens, completion_tokens, now());
+    }
+
+    fn record_at(
+        &self,
+        key: &str,
+        model: Option<&str>,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+        now: u64,
+    ) {
+        // Models without a price count toward the tokens only
+        let cost = model
+            .and_then(|model| self.pricing.cost(model, prompt_tokens, completion_tokens))
+            .unwrap_or_default();
+        self.update(key, now, |usage| {
+            usage.tokens += prompt_tokens + completion_tokens;
+            usage.dollars += cost;
+        });
+    }
+
+    /// Apply `f` to the usage of the client key `key` in the windows of `now`
+    fn update<T>(&self, key: &str, now: u64, f: impl FnOnce(&mut Usage) -> T) -> T {
+        let mut usage = self.usage.lock().unwrap_or_else(PoisonError::into_inner);
+        let key_usage = usage.entry(key.to_string()).or_default();
+        key_usage.roll(now);
+        let result = f(key_usage);
+        drop(usage);
+        result
+    }
+
+    /// Forward a response `stream` of the client key `key`, recording the
+    /// usage it reports when it ends
+    ///
+    /// With `strip_usage`, the usage the proxy asked for is removed from the
+    /// events. A client that stops reading doesn't stop the count: the rest
+    /// of the response is read and charged. Responses without usage are
+    /// charged `prompt_tokens` and an estimate of their output.
+    #[must_use]
+    pub fn observe(
+        self: &Arc<Self>,
+        key: String,
+        model: Option<String>,
+        prompt_tokens: u64,
+        strip_usage: bool,
+        mut stream: ResponseStream,
+    ) -> ResponseStream {
+        let quotas = self.clone();
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            let mut usage = UsageScanner::default();
+            let mut stripper = strip_usage.then(UsageStripper::default);
+            let mut connected = true;
+            while let Some(item) = stream.recv().await {
+                if let Ok(chunk) = &item {
+                    usage.push(chunk);
+                }
+                if !connected {
+                    continue;
+                }
+                let item = match (stripper.as_mut(), item) {
+                    (Some(stripper), Ok(chunk)) => match stripper.push(&chunk) {
+                        Some(events) => Ok(events),
+                        None => continue,
+                    },
+                    (_, item) => item,
+                };
+                connected = tx.send(item).await.is_ok();
+            }
+            if let Some(rest) = stripper.and_then(UsageStripper::finish) {
+                if connected {
+                    let _ = tx.send(Ok(rest)).await;
+                }
+            }
+            let completion_estimate = usage.completion_estimate();
+            let (prompt_tokens, completion_tokens) = usage
+                .finish()
+                .unwrap_or((prompt_tokens, completion_estimate));
+            quotas.record(&key, model.as_deref(), prompt_tokens, completion_tokens);
+        });
+        rx
+    }
+}
+
+/// Ask for the usage of a streamed request `body` of a client key with a
+/// quota, since tokens and dollars are counted from it
+///
+/// The context gets an estimate of the prompt tokens in the
+/// [`PROMPT_TOKENS_ATTRIBUTE`], and the [`STRIP_USAGE_ATTRIBUTE`] when the
+/// client didn't ask for the usage itself.
+pub fn request_usage(body: Bytes, context: &mut RequestContext) -> Bytes {
+    let Ok(mut request) = serde_json::from_slice::<Value>(&body) else {
+        return body;
+    };
+    context.attributes.insert(
+        PROMPT_TOKENS_ATTRIBUTE.to_string(),
+        prompt_tokens(&request).to_string(),
+    );
+    if request["stream"] != true || request["stream_options"]["include_usage"] == true {
+        return body;
+    }
+    request["stream_options"]["include_usage"] = Value::Bool(true);
+    context
+        .attributes
+        .insert(STRIP_USAGE_ATTRIBUTE.to_string(), "true".to_string());
+    Bytes::from(request.to_string())
+}
+
+/// Approximate tokens of the messages of `request`
+fn prompt_tokens(request: &Value) -> u64 {
+    let counter = ApproximateTokenCounter;
+    let tokens: usize = request["messages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|message| match &message["content"] {
+            Value::String(text) => counter.count(text),
+            Value::Array(parts) => parts
+                .iter()
+                .filter_map(|part| part["text"].as_str())
+                .map(|text| counter.count(text))
+                .sum(),
+            _ => 0,
+        })
+        .sum();
+    u64::try_from(tokens).unwrap_or(u64::MAX)
+}
+
+/// Removes the usage the proxy asked for from the events of a stream
+#[derive(Default)]
+struct UsageStripper {
+    buffer: Vec<u8>,
+}
+
+impl UsageStripper {
+    /// The events of the stream completed by `chunk`, without usage, if any
+    fn push(&mut self, chunk: &[u8]) -> Option<Bytes> {
+        self.buffer.extend_from_slice(chunk);
+        let end = self.buffer.windows(2).rposition(|pair| pair == b"\n\n")? + 2;
+        let complete: Vec<u8> = self.buffer.drain(..end).collect();
+        let events: String = String::from_utf8_lossy(&complete)
+            .split_inclusive("\n\n")
+            .map(strip_usage)
+            .collect();
+        (!events.is_empty()).then(|| Bytes::from(events))
+    }
+
+    /// The incomplete event the stream ended with, if any
+    fn finish(self) -> Option<Bytes> {
+        (!self.buffer.is_empty()).then(|| Bytes::from(self.buffer))
+    }
+}
+
+/// `event` without its usage, or nothing for the event only carrying it
+fn strip_usage(event: &str) -> Cow<'_, str> {
+    let Some(data) = event
+        .trim_end()
+        .strip_prefix("data:")
+        .filter(|data| data.contains("\"usage\""))
+    else {
+        return Cow::Borrowed(event);
+    };
+    let Ok(mut chunk) = serde_json::from_str::<Value>(data.trim()) else {
+        return Cow::Borrowed(event);
+    };
+    if chunk["choices"].as_array().is_some_and(Vec::is_empty) {
+        return Cow::Borrowed("");
+    }
+    if let Some(fields) = chunk.as_object_mut() {
+        fields.remove("usage");
+    }
+    Cow::Owned(format!("data: {chunk}\n\n"))
+}
+
+/// Count a request with `usage` unless it used up any of `limits`
+fn admit(
+    limits: &QuotaLimits,
+    usage: &mut Usage,
+    now: u64,
+) -> Result<Vec<(String, String)>, QuotaExceeded> {
+    let mut exceeded = Vec::new();
+    let mut retry_after = 0;
+    if limits
+        .requests_per_minute
+        .is_some_and(|limit| usage.requests >= limit)
+    {
+        exceeded.push("requests per minute");
+        retry_after = retry_after.max(SECONDS_PER_MINUTE - now % SECONDS_PER_MINUTE);
+    }
+    if limits
+        .tokens_per_day
+        .is_some_and(|limit| usage.tokens >= limit)
+    {
+        exceeded.push("tokens per day");
+        retry_after = retry_after.max(SECONDS_PER_DAY - now % SECONDS_PER_DAY);
+    }
+    let spend = limits
+        .dollars_per_month
+        .is_some_and(|limit| usage.dollars >= limit);
+    if spend {
+        exceeded.push("dollars per month");
+        let (_, next_month) = month_of(now);
+        retry_after = retry_after.max(next_month - now);
+    }
+    if !exceeded.is_empty() {
+        return Err(QuotaExceeded {
+            message: format!("Quota of the API key exceeded: {}", exceeded.join(", ")),
+            spend,
+            retry_after,
+            headers: headers(limits, usage),
+        });
+    }
+
+    usage.requests += 1;
+    Ok(headers(limits, usage))
+}
+
+/// The headers reporting the set `limits` and what `usage` leaves of them
+fn headers(limits: &QuotaLimits, usage: &Usage) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    if let Some(limit) = limits.requests_per_minute {
+        headers.push(("x-ratelimit-limit-requests".to_string(), limit.to_string()));
+        headers.push((
+            "x-ratelimit-remaining-requests".to_string(),
+            limit.saturating_sub(usage.requests).to_string(),
+        ));
+    }
+    if let Some(limit) = limits.tokens_per_day {
+        headers.push(("x-ratelimit-limit-tokens".to_string(), limit.to_string()));
+        headers.push((
+            "x-ratelimit-remaining-tokens".to_string(),
+            limit.saturating_sub(usage.tokens).to_string(),
+        ));
+    }
+    if let Some(limit) = limits.dollars_per_month {
+        headers.push(("x-quota-limit-dollars".to_string(), format!("{limit:.2}")));
+        headers.push((
+            "x-quota-remaining-dollars".to_string(),
+            format!("{:.4}", (limit - usage.dollars).max(0.0)),
+        ));
+    }
+    headers
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+/// The UTC calendar month of `now`, in months since the Unix epoch, and the
+/// second the next month starts at
+const fn month_of(now: u64) -> (u64, u64) {
+    let (year, month) = civil_from_days(now / SECONDS_PER_DAY);
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    (
+        (year - 1970) * 12 + month - 1,
+        days_from_civil(next_year, next_month) * SECONDS_PER_DAY,
+    )
+}
+
+/// The year and month (1 to 12) of a day since the Unix epoch, after
+/// Howard Hinnant's `civil_from_days`
+const fn civil_from_days(days: u64) -> (u64, u64) {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months counted from March, so that leap days come last
+    let march_month = (5 * day_of_year + 2) / 153;
+    let month = if march_month < 10 {
+        march_month + 3
+    } else {
+        march_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month)
+}
+
+/// The day since the Unix epoch a month of a year starts at, after Howard
+/// Hinnant's `days_from_civil`
+const fn days_from_civil(year: u64, month: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let march_month = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * march_month + 2) / 5;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-02-29T23:59:30Z
+    const LEAP_DAY: u64 = 1_709_251_170;
+
+    fn quotas(limits: QuotaLimits) -> Quotas {
+        Quotas {
+            pricing: Pricing::builtin(),
+            default: limits,
+            limits: RwLock::new(HashMap::new()),
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[test]
+    fn test_calendar_months() {
+        // 2024-03-01T00:00:00Z
+        assert_eq!(month_of(LEAP_DAY), ((2024 - 1970) * 12 + 1, LEAP_DAY + 30));
+        assert_eq!(month_of(0), (0, 31 * SECONDS_PER_DAY));
+        // 2023-12-31T12:00:00Z to 2024-01-01T00:00:00Z
+        assert_eq!(month_of(1_704_024_000).1, 1_704_067_200);
+    }
+
+    #[test]
+    fn test_requests_per_minute() {
+        let quotas = quotas(QuotaLimits {
+            requests_per_minute: Some(2),
+            ..QuotaLimits::default()
+        });
+        let headers = quotas.admit_at("ci-bot", None, LEAP_DAY).expect("Refused");
+        assert!(headers.contains(&(
+            "x-ratelimit-remaining-requests".to_string(),
+            "1".to_string()
+        )));
+        quotas.admit_at("ci-bot", None, LEAP_DAY).expect("Refused");
+        let exceeded = quotas
+            .admit_at("ci-bot", None, LEAP_DAY + 10)
+            .expect_err("Admitted");
+        assert_eq!(exceeded.retry_after, 20);
+        assert!(!exceeded.spend);
+
+        // Other keys and the next minute have their own counts
+        quotas.admit_at("other", None, LEAP_DAY).expect("Refused");
+        // Limits of a tenant apply to its keys without limits of their own
+        let tenant = Some(QuotaLimits {
+            requests_per_minute: Some(1),
+            ..QuotaLimits::default()
+        });
+        quotas
+            .admit_at("acme-app", tenant, LEAP_DAY)
+            .expect("Refused");
+        assert!(quotas.admit_at("acme-app", tenant, LEAP_DAY).is_err());
+        quotas
+            .admit_at("ci-bot", None, LEAP_DAY + 30)
+            .expect("Refused");
+    }
+
+    #[test]
+    fn test_tokens_and_dollars() {
+        let quotas = quotas(QuotaLimits {
+            tokens_per_day: Some(1_000_000),
+            ..QuotaLimits::default()
+        });
+        quotas.set_limits(
+            "ci-bot",
+            QuotaLimits {
+                dollars_per_month: Some(5.0),
+                ..QuotaLimits::default()
+            },
+        );
+        // $2.50 for a million prompt tokens and $10 for a million completion tokens
+        quotas.record_at("ci-bot", Some("gpt-4o"), 1_000_000, 0, LEAP_DAY);
+        let headers = quotas.admit_at("ci-bot", None, LEAP_DAY).expect("Refused");
+        assert!(headers.contains(&(
+            "x-quota-remaining-dollars".to_string(),
+            "2.5000".to_string()
+        )));
+        quotas.record_at("ci-bot", Some("gpt-4o"), 0, 250_000, LEAP_DAY);
+        let exceeded = quotas
+            .admit_at("ci-bot", None, LEAP_DAY)
+            .expect_err("Admitted");
+        assert!(exceeded.spend);
+        assert_eq!(exceeded.retry_after, 30);
+        quotas
+            .admit_at("ci-bot", None, LEAP_DAY + 30)
+            .expect("Refused");
+
+        // Unpriced models only count toward the tokens
+        quotas.record_at("other", Some("unknown"), 600_000, 400_000, LEAP_DAY);
+        assert!(quotas.admit_at("other", None, LEAP_DAY).is_err());
+        let usage = quotas.usage.lock().expect("Poisoned")["other"];
+        assert_eq!((usage.tokens, usage.dollars), (1_000_000, 0.0));
+    }
+
+    /// The tokens counted for `key`, once its response has been recorded
+    async fn recorded_tokens(quotas: &Quotas, key: &str) -> u64 {
+        for _ in 0..100 {
+            if let Some(usage) = quotas.usage.lock().expect("Poisoned").get(key) {
+                return usage.tokens;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("No usage recorded for {key}");
+    }
+
+    fn event(data: &Value) -> Bytes {
+        Bytes::from(format!("data: {data}\n\n"))
+    }
+
+    #[test]
+    fn test_request_usage() {
+        let mut context = RequestContext::new();
+        let body = Bytes::from(
+            json!({"stream": true, "messages": [{"role": "user", "content": "Hello there"}]})
+                .to_string(),
+        );
+        let request: Value =
+            serde_json::from_slice(&request_usage(body, &mut context)).expect("Invalid JSON");
+        assert_eq!(request["stream_options"]["include_usage"], true);
+        assert!(context.attributes.contains_key(STRIP_USAGE_ATTRIBUTE));
+        assert!(
+            context.attributes[PROMPT_TOKENS_ATTRIBUTE]
+                .parse::<u64>()
+                .expect("Not a number")
+                > 0
+        );
+
+        // Clients asking for the usage, or not streaming, get it as before
+        let mut context = RequestContext::new();
+        let body = Bytes::from(
+            json!({"stream": true, "stream_options": {"include_usage": true}, "messages": []})
+                .to_string(),
+        );
+        assert_eq!(request_usage(body.clone(), &mut context), body);
+        assert!(!context.attributes.contains_key(STRIP_USAGE_ATTRIBUTE));
+    }
+
+    #[tokio::test]
+    async fn test_forced_usage_is_stripped() {
+        let quotas = Arc::new(quotas(QuotaLimits::default()));
+        let (tx, upstream) = mpsc::channel(10);
+        let mut rx = quotas.observe("ci-bot".to_string(), None, 0, true, upstream);
+        tx.send(Ok(event(
+            &json!({"choices": [{"delta": {"content": "Hi"}}]}),
+        )))
+        .await
+        .expect("Closed");
+        tx.send(Ok(event(&json!({
+            "choices": [],
+            "usage": {"prompt_tokens": 7, "completion_tokens": 3},
+        }))))
+        .await
+        .expect("Closed");
+        tx.send(Ok(Bytes::from("data: [DONE]\n\n")))
+            .await
+            .expect("Closed");
+        drop(tx);
+
+        let mut body = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            body.extend_from_slice(&chunk.expect("Failed"));
+        }
+        let body = String::from_utf8(body).expect("Not UTF-8");
+        assert!(body.contains("Hi") && body.ends_with("data: [DONE]\n\n"));
+        assert!(!body.contains("usage"));
+        assert_eq!(recorded_tokens(&quotas, "ci-bot").await, 10);
+    }
+
+    #[tokio::test]
+    async fn test_disconnected_client_is_charged() {
+        let quotas = Arc::new(quotas(QuotaLimits::default()));
+        let (tx, upstream) = mpsc::channel(10);
+        let mut rx = quotas.observe("ci-bot".to_string(), None, 0, false, upstream);
+        tx.send(Ok(event(
+            &json!({"choices": [{"delta": {"content": "Hi"}}]}),
+        )))
+        .await
+        .expect("Closed");
+        rx.recv().await.expect("Ended").expect("Failed");
+        drop(rx);
+
+        // The rest of the response is still read and its usage charged
+        for _ in 0..5 {
+            tx.send(Ok(event(
+                &json!({"choices": [{"delta": {"content": "more"}}]}),
+            )))
+            .await
+            .expect("Closed");
+        }
+        tx.send(Ok(event(&json!({
+            "choices": [],
+            "usage": {"prompt_tokens": 7, "completion_tokens": 30},
+        }))))
+        .await
+        .expect("Closed");
+        drop(tx);
+        assert_eq!(recorded_tokens(&quotas, "ci-bot").await, 37);
+    }
+
+    #[tokio::test]
+    async fn test_usage_estimate() {
+        let quotas = Arc::new(quotas(QuotaLimits::default()));
+        let (tx, upstream) = mpsc::channel(10);
+        let rx = quotas.observe("ci-bot".to_string(), None, 12, false, upstream);
+        drop(rx);
+        tx.send(Ok(event(
+            &json!({"choices": [{"delta": {"content": "A response without usage"}}]}),
+        )))
+        .await
+        .expect("Closed");
+        drop(tx);
+        assert!(recorded_tokens(&quotas, "ci-bot").await > 12);
+    }
+}
@@ -0,0 +1,300 @@
+//! Replay of audited requests against another route or model.
+//!
+//! `POST /admin/replay` selects transcripts recorded by an `audit` stream
+//! processor, runs their requests again through the pipeline of a route,
+//! optionally with another model, and records the new responses in the same
+//! store, next to the originals. The JSON body names the processor and the
+//! route, and filters transcripts like the store's queries:
+//!
+//! ```json
+//! {"audit": "transcripts", "route": "/v1/chat/completions", "model": "gpt-4o-mini",
+//!  "tenant": "acme", "since": 1714521600000, "limit": 10}
+//! ```
+//!
+//! Requests are replayed one after the other, without streaming. A replayed
+//! transcript's ID is the original's followed by `/replay/` and a new UUID,
+//! so both can be compared by prefix. The response lists every replayed
+//! transcript with the original and new responses, or the error of a request
+//! that failed.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::{body::MessageBody, web, HttpResponse};
+use llm_proxy_core::{redact::redact_json, AuditQuery, RequestContext, Transcript};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    app::{self, AppState},
+    processors::{create_audit_store, AuditSettings, AuditStoreConfig},
+};
+
+/// Transcripts replayed at most by a request without a limit
+pub const DEFAULT_REPLAY_LIMIT: u32 = 20;
+
+/// Body of a replay request
+#[derive(Debug, Deserialize)]
+pub struct ReplayRequest {
+    /// ID of the `audit` stream processor whose transcripts are replayed
+    pub audit: String,
+    /// ID of the route the requests are run through
+    pub route: String,
+    /// Model replacing the one of the recorded requests
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Earliest timestamp of the transcripts, inclusive
+    #[serde(default)]
+    pub since: Option<u64>,
+    /// Latest timestamp of the transcripts, exclusive
+    #[serde(default)]
+    pub until: Option<u64>,
+    /// Tenant of the recorded requests
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Model of the recorded requests
+    #[serde(default)]
+    pub source_model: Option<String>,
+    /// Number of transcripts replayed at most, [`DEFAULT_REPLAY_LIMIT`] by default
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+impl ReplayRequest {
+    /// The query selecting the transcripts to replay
+    fn query(&self) -> AuditQuery {
+        AuditQuery {
+            since: self.since,
+            until: self.until,
+            tenant: self.tenant.clone(),
+            model: self.source_model.clone(),
+            limit: Some(self.limit.unwrap_or(DEFAULT_REPLAY_LIMIT)),
+        }
+    }
+}
+
+/// The outcome of replaying one transcript
+#[derive(Debug, Serialize)]
+struct ReplayOutcome {
+    /// ID of the original transcript
+    original_id: String,
+    /// ID of the recorded replay, if the request succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replay_id: Option<String>,
+    /// Model of the original request
+    original_model: Option<String>,
+    /// Model the request was replayed with
+    model: Option<String>,
+    /// The recorded response
+    original: Value,
+    /// The new response
+    #[serde(skip_serializing_if = "Value::is_null")]
+    response: Value,
+    /// Why the request failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Replay the transcripts selected by the body against its route
+#[allow(clippy::future_not_send)]
+pub async fn replay(state: web::Data<AppState>, request: web::Json<ReplayRequest>) -> HttpResponse {
+    let request = request.into_inner();
+    let settings = match state
+        .config
+        .get_processor(&request.audit)
+        .and_then(|config| {
+            anyhow::ensure!(
+                config.processor_type == "audit",
+                "Processor '{}' is not an audit processor",
+                request.audit
+            );
+            config.settings::<AuditSettings>()
+        }) {
+        Ok(settings) => settings,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+    if matches!(settings.store, AuditStoreConfig::S3 { .. }) {
+        return HttpResponse::BadRequest().body("Transcript archives can't be replayed");
+    }
+    let Some(index) = state
+        .config
+        .route
+        .iter()
+        .position(|route| route.id() == request.route)
+    else {
+        return HttpResponse::NotFound().body(format!("Unknown route '{}'", request.route));
+    };
+    if state.config.route[index].passthrough {
+        return HttpResponse::BadRequest().body("Passthrough routes can't replay requests");
+    }
+    let store = match create_audit_store(settings.store) {
+        Ok(store) => store,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to open transcript store: {e}"))
+        }
+    };
+    let transcripts = match store.query(&request.query()).await {
+        Ok(transcripts) => transcripts,
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .body(format!("Failed to query transcripts: {e}"))
+        }
+    };
+
+    let mut replays = Vec::with_capacity(transcripts.len());
+    for original in transcripts {
+        let mut replay = ReplayOutcome {
+            original_id: original.id.clone(),
+            replay_id: None,
+            original_model: original.model.clone(),
+            model: request.model.clone().or_else(|| original.model.clone()),
+            original: original.response.clone(),
+            response: Value::Null,
+            error: None,
+        };
+        let mut transcript = match execute(&state, index, &original, request.model.as_deref()).await
+        {
+            Ok(transcript) => transcript,
+            Err(e) => {
+                warn!(
+                    transcript = original.id,
+                    error = e,
+                    "Failed to replay transcript"
+                );
+                replay.error = Some(e);
+                replays.push(replay);
+                continue;
+            }
+        };
+        if settings.redact {
+            redact_json(&mut transcript.request);
+            redact_json(&mut transcript.response);
+        }
+        replay.model.clone_from(&transcript.model);
+        replay.response = transcript.response.clone();
+        match store.record(&transcript).await {
+            Ok(()) => replay.replay_id = Some(transcript.id),
+            Err(e) => replay.error = Some(format!("Failed to record replay: {e}")),
+        }
+        replays.push(replay);
+    }
+    info!(
+        route = request.route,
+        replayed = replays.len(),
+        "Replayed audited requests"
+    );
+    HttpResponse::Ok().json(replays)
+}
+
+/// Run the request of `original` through the route at `index`, returning the
+/// transcript of the replay
+async fn execute(
+    state: &AppState,
+    index: usize,
+    original: &Transcript,
+    model: Option<&str>,
+) -> Result<Transcript, String> {
+    let route = state.config.route[index].id();
+    let id = format!("{}/replay/{}", original.id, Uuid::new_v4());
+    let mut context = RequestContext::new()
+        .with_request_id(id.clone())
+        .with_route(route.clone());
+    context.tenant.clone_from(&original.tenant);
+    let overlay = context
+        .tenant
+        .as_deref()
+        .zip(state.overlays.as_ref())
+        .and_then(|(tenant, overlays)| overlays.get(tenant));
+    let request = replay_request(&original.request, model);
+    let body = serde_json::to_vec(&request).map_err(|e| e.to_string())?;
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| {
+            u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX)
+        });
+    let mut rx = app::execute_chat(state, index, overlay.as_deref(), body.into(), &mut context)
+        .await
+        .map_err(|response| error_message(response.into_body()))?;
+    let mut body = Vec::new();
+    while let Some(chunk) = rx.recv().await {
+        body.extend_from_slice(&chunk.map_err(|e| e.to_string())?);
+    }
+    Ok(Transcript {
+        id,
+        timestamp,
+        route: Some(route),
+        tenant: context.tenant,
+        model: context.model,
+        request,
+        response: serde_json::from_slice(&body).map_err(|e| e.to_string())?,
+    })
+}
+
+/// The recorded `request` to send again, without streaming and with `model`
+/// if given
+fn replay_request(request: &Value, model: Option<&str>) -> Value {
+    let mut request = request.clone();
+    if let Some(fields) = request.as_object_mut() {
+        fields.insert("stream".to_string(), Value::Bool(false));
+        fields.remove("stream_options");
+        if let Some(model) = model {
+            fields.insert("model".to_string(), Value::String(model.to_string()));
+        }
+    }
+    request
+}
+
+/// The text of an error response's `body`
+fn error_message(body: impl MessageBody) -> String {
+    body.try_into_bytes().map_or_else(
+        |_| "Request failed".to_string(),
+        |bytes| String::from_utf8_lossy(&bytes).into_owned(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_replay_request() {
+        let request = json!({
+            "model": "gpt-4o",
+            "stream": true,
+            "stream_options": {"include_usage": true},
+            "messages": [{"role": "user", "content": "Hi"}],
+        });
+        assert_eq!(
+            replay_request(&request, Some("gpt-4o-mini")),
+            json!({
+                "model": "gpt-4o-mini",
+                "stream": false,
+                "messages": [{"role": "user", "content": "Hi"}],
+            })
+        );
+        assert_eq!(replay_request(&request, None)["model"], "gpt-4o");
+    }
+
+    #[test]
+    fn test_query() {
+        let request: ReplayRequest = serde_json::from_value(json!({
+            "audit": "transcripts",
+            "route": "/v1/chat/completions",
+            "model": "gpt-4o-mini",
+            "tenant": "acme",
+            "source_model": "gpt-4o",
+        }))
+        .expect("Invalid replay request");
+        assert_eq!(
+            request.query(),
+            AuditQuery::new()
+                .with_tenant("acme")
+                .with_model("gpt-4o")
+                .with_limit(DEFAULT_REPLAY_LIMIT)
+        );
+    }
+}
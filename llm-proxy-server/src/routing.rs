@@ -0,0 +1,377 @@
+//! Matching of inbound requests to routes.
+//!
+//! A route matches a request by its path, with exactly one of `path` (the
+//! exact path), `path_prefix`, `path_glob` or `path_regex`, and optionally by
+//! its HTTP `methods` and the values of `match_headers`. When several routes
+//! match, the most specific one wins:
+//!
+//! 1. an exact path before a glob or regex, before a prefix
+//! 2. a longer prefix before a shorter one
+//! 3. more required headers before fewer
+//! 4. a route restricted to some methods before one accepting any
+//!
+//! Routes equal in all of these are tried in the order they are configured.
+//!
+//! A route with `rewrite_path` sends requests to another path on the
+//! backend's host than its configured URL, or for passthrough routes the
+//! inbound path: a `path_prefix` is replaced by `rewrite_path`, keeping the
+//! rest of the path; `path_regex` routes may refer to the groups of the regex
+//! as `$1` or `${name}`; other routes use `rewrite_path` as it is.
+
+use std::{cmp::Reverse, collections::HashMap};
+
+use actix_web::{
+    http::{header::HeaderName, Method},
+    HttpRequest,
+};
+use anyhow::Context;
+use regex::Regex;
+
+use crate::config::RouteConfig;
+
+/// How a route matches the path of a request
+#[derive(Debug)]
+enum PathMatcher {
+    Exact(String),
+    Glob(Regex),
+    Regex(Regex),
+    Prefix(String),
+}
+
+impl PathMatcher {
+    fn new(route: &RouteConfig) -> anyhow::Result<Self> {
+        let matcher = match (
+            &route.path,
+            &route.path_prefix,
+            &route.path_glob,
+            &route.path_regex,
+        ) {
+            (Some(path), None, None, None) => Self::Exact(path.clone()),
+            (None, Some(prefix), None, None) => Self::Prefix(prefix.clone()),
+            (None, None, Some(glob), None) => Self::Glob(
+                Regex::new(&glob_regex(glob))
+                    .with_context(|| format!("Invalid path_glob {glob}"))?,
+            ),
+            (None, None, None, Some(regex)) => Self::Regex(
+                // The whole path must match, not just a part of it
+                Regex::new(&format!("^(?:{regex})$"))
+                    .with_context(|| format!("Invalid path_regex {regex}"))?,
+            ),
+            _ => anyhow::bail!(
+                "Routes must set exactly one of path, path_prefix, path_glob or path_regex"
+            ),
+        };
+        Ok(matcher)
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        match self {
+            Self::Exact(exact) => path == exact,
+            Self::Glob(regex) | Self::Regex(regex) => regex.is_match(path),
+            Self::Prefix(prefix) => path.starts_with(prefix.as_str()),
+        }
+    }
+
+    /// The upstream path of the matched `path` for a route with `rewrite`
+    fn rewrite(&self, path: &str, rewrite: &str) -> String {
+        match self {
+            Self::Exact(_) | Self::Glob(_) => rewrite.to_string(),
+            Self::Regex(regex) => regex.replace(path, rewrite).into_owned(),
+            Self::Prefix(prefix) => {
+                let rest = &path[prefix.len()..];
+                // Keep a single slash between the rewritten prefix and the rest
+                let rest = if rewrite.ends_with('/') {
+                    rest.strip_prefix('/').unwrap_or(rest)
+                } else {
+                    rest
+                };
+                format!("{rewrite}{rest}")
+            }
+        }
+    }
+
+    /// How specific the matcher is, higher first; see the module documentation
+    const fn specificity(&self) -> (u8, usize) {
+        match self {
+            Self::Exact(_) => (2, 0),
+            Self::Glob(_) | Self::Regex(_) => (1, 0),
+            Self::Prefix(prefix) => (0, prefix.len()),
+        }
+    }
+}
+
+/// The regex matching the paths a glob matches
+///
+/// `**` matches any characters, `*` any characters but `/` and `?` a single
+/// character but `/`.
+fn glob_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+/// A route with its match conditions parsed
+#[derive(Debug)]
+struct CompiledRoute {
+    /// Index of the route in the configuration
+    index: usize,
+    path: PathMatcher,
+    methods: Vec<Method>,
+    headers: Vec<(HeaderName, String)>,
+    rewrite_path: Option<String>,
+}
+
+impl CompiledRoute {
+    fn new(index: usize, route: &RouteConfig) -> anyhow::Result<Self> {
+        let path = PathMatcher::new(route)?;
+        let methods = route
+            .methods
+            .iter()
+            .map(|method| {
+                Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                    .with_context(|| format!("Invalid HTTP method {method}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let headers = route
+            .match_headers
+            .iter()
+            .map(|(name, value)| {
+                HeaderName::from_bytes(name.as_bytes())
+                    .map(|name| (name, value.clone()))
+                    .with_context(|| format!("Invalid header name {name}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            index,
+            path,
+            methods,
+            headers,
+            rewrite_path: route.rewrite_path.clone(),
+        })
+    }
+
+    fn matches(&self, req: &HttpRequest) -> bool {
+        self.path.matches(req.uri().path())
+            && (self.methods.is_empty() || self.methods.contains(req.method()))
+            && self.headers.iter().all(|(name, value)| {
+                req.headers()
+                    .get_all(name)
+                    .any(|actual| actual.to_str().is_ok_and(|actual| actual == value))
+            })
+    }
+
+    const fn specificity(&self) -> (u8, usize, usize, bool) {
+        let (path, prefix_len) = self.path.specificity();
+        (
+            path,
+            prefix_len,
+            self.headers.len(),
+            !self.methods.is_empty(),
+        )
+    }
+}
+
+/// Finds the route of a request among the configured routes
+#[derive(Debug)]
+pub struct Router {
+    /// The routes, most specific first
+    routes: Vec<CompiledRoute>,
+}
+
+impl Router {
+    /// Parse the match conditions of `routes`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a route doesn't set exactly one
+    /// path condition, has an invalid pattern, method or header name, or if
+    /// two routes have the same ID.
+    pub fn new(routes: &[RouteConfig]) -> anyhow::Result<Self> {
+        let mut ids = HashMap::new();
+        let mut compiled = Vec::with_capacity(routes.len());
+        for (index, route) in routes.iter().enumerate() {
+            let id = route.id();
+            compiled.push(
+                CompiledRoute::new(index, route).with_context(|| format!("Invalid route {id}"))?,
+            );
+            if let Some(other) = ids.insert(id.clone(), index) {
+                anyhow::bail!(
+                    "Routes {other} and {index} have the same ID {id}, give them distinct ids"
+                );
+            }
+        }
+        // The sort is stable, so equally specific routes stay in configured order
+        compiled.sort_by_key(|route| Reverse(route.specificity()));
+        Ok(Self { routes: compiled })
+    }
+
+    /// The route `req` matches, if any
+    #[must_use]
+    pub fn find(&self, req: &HttpRequest) -> Option<RouteMatch> {
+        let route = self.routes.iter().find(|route| route.matches(req))?;
+        Some(RouteMatch {
+            index: route.index,
+            upstream_path: route
+                .rewrite_path
+                .as_ref()
+                .map(|rewrite| route.path.rewrite(req.uri().path(), rewrite)),
+        })
+    }
+}
+
+/// The route a request matches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteMatch {
+    /// Index of the route in the configured routes
+    pub index: usize,
+    /// Path the request is sent to upstream, for routes rewriting paths
+    pub upstream_path: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use serde_json::json;
+
+    use super::*;
+
+    fn routes(routes: serde_json::Value) -> Vec<RouteConfig> {
+        serde_json::from_value(routes).expect("Invalid routes")
+    }
+
+    fn router(config: serde_json::Value) -> Router {
+        Router::new(&routes(config)).expect("Invalid router")
+    }
+
+    #[test]
+    fn test_most_specific_path_wins() {
+        let router = router(json!([
+            { "path_prefix": "/v1", "target_llm": "prefix" },
+            { "path_prefix": "/v1/chat", "target_llm": "longer_prefix" },
+            { "path_glob": "/v1/*/completions", "target_llm": "glob" },
+            { "path": "/v1/chat/completions", "target_llm": "exact" },
+        ]));
+        let find = |path| {
+            router
+                .find(&TestRequest::post().uri(path).to_http_request())
+                .map(|route| route.index)
+        };
+
+        assert_eq!(find("/v1/chat/completions"), Some(3));
+        assert_eq!(find("/v1/embeddings/completions"), Some(2));
+        assert_eq!(find("/v1/chat/other"), Some(1));
+        assert_eq!(find("/v1/models"), Some(0));
+        assert_eq!(find("/v2/models"), None);
+    }
+
+    #[test]
+    fn test_methods_and_headers_narrow_routes() {
+        let router = router(json!([
+            { "path_prefix": "/v1/models", "target_llm": "any" },
+            { "path_prefix": "/v1/models", "target_llm": "get", "methods": ["get"] },
+            {
+                "path_prefix": "/v1/models",
+                "target_llm": "staging",
+                "match_headers": { "X-Env": "staging" },
+            },
+        ]));
+
+        let get = TestRequest::get().uri("/v1/models").to_http_request();
+        assert_eq!(router.find(&get).map(|route| route.index), Some(1));
+        let post = TestRequest::post().uri("/v1/models").to_http_request();
+        assert_eq!(router.find(&post).map(|route| route.index), Some(0));
+        let staging = TestRequest::get()
+            .uri("/v1/models")
+            .insert_header(("x-env", "staging"))
+            .to_http_request();
+        assert_eq!(router.find(&staging).map(|route| route.index), Some(2));
+        let production = TestRequest::get()
+            .uri("/v1/models")
+            .insert_header(("x-env", "production"))
+            .to_http_request();
+        assert_eq!(router.find(&production).map(|route| route.index), Some(1));
+    }
+
+    #[test]
+    fn test_equally_specific_routes_keep_order() {
+        let router = router(json!([
+            { "path_regex": "/v1/(chat|responses)", "target_llm": "first" },
+            { "path_glob": "/v1/**", "target_llm": "second" },
+        ]));
+        let find = |path| {
+            router
+                .find(&TestRequest::get().uri(path).to_http_request())
+                .map(|route| route.index)
+        };
+
+        assert_eq!(find("/v1/chat"), Some(0));
+        // The regex must match the whole path
+        assert_eq!(find("/v1/chat/completions"), Some(1));
+    }
+
+    #[test]
+    fn test_glob_regex() {
+        assert_eq!(glob_regex("/v1/*/x?.json"), r"^/v1/[^/]*/x[^/]\.json$");
+        assert_eq!(glob_regex("/v1/**"), "^/v1/.*$");
+    }
+
+    #[test]
+    fn test_invalid_routes_are_rejected() {
+        let both = routes(json!([
+            { "path": "/v1/chat", "path_prefix": "/v1", "target_llm": "llm" },
+        ]));
+        assert!(Router::new(&both).is_err());
+        let invalid_regex = routes(json!([{ "path_regex": "/v1/(", "target_llm": "llm" }]));
+        assert!(Router::new(&invalid_regex).is_err());
+        let duplicate = routes(json!([
+            { "path_prefix": "/v1", "target_llm": "a" },
+            { "path_prefix": "/v1", "target_llm": "b" },
+        ]));
+        assert!(Router::new(&duplicate).is_err());
+    }
+
+    #[test]
+    fn test_rewrite_path() {
+        let router = router(json!([
+            { "path_prefix": "/ai", "rewrite_path": "/v1/", "target_llm": "prefix" },
+            { "path": "/ai/chat", "rewrite_path": "/v1/chat/completions", "target_llm": "exact" },
+            {
+                "path_regex": "/deployments/(?<name>[^/]+)/chat",
+                "rewrite_path": "/openai/deployments/${name}/chat/completions",
+                "target_llm": "regex",
+            },
+            { "path_prefix": "/v1", "target_llm": "unchanged" },
+        ]));
+        let upstream_path = |path| {
+            router
+                .find(&TestRequest::post().uri(path).to_http_request())
+                .and_then(|route| route.upstream_path)
+        };
+
+        assert_eq!(
+            upstream_path("/ai/chat").as_deref(),
+            Some("/v1/chat/completions")
+        );
+        assert_eq!(
+            upstream_path("/ai/files/f-1").as_deref(),
+            Some("/v1/files/f-1")
+        );
+        assert_eq!(
+            upstream_path("/deployments/gpt-4o/chat").as_deref(),
+            Some("/openai/deployments/gpt-4o/chat/completions")
+        );
+        assert_eq!(upstream_path("/v1/models"), None);
+    }
+}
@@ -0,0 +1,334 @@
+//! Splitting of a route's requests across several LLMs.
+//!
+//! A route with `targets` instead of `target_llm` sends each request to one
+//! of the listed LLMs, picked with smooth weighted round-robin: an LLM with
+//! weight 9 receives nine requests for every request sent to one with weight
+//! 1, interleaved rather than in bursts. This allows migrating between
+//! providers gradually. On routes with a `target_header` policy, requests with
+//! the [`TARGET_HEADER`] header go to the target it names instead, so each
+//! backend can be tested deterministically.
+//!
+//! With `sticky`, requests are instead assigned by a hash of their session,
+//! the `user` field or a header such as `X-Session-Id`, so a conversation
+//! stays on one backend rather than shifting behavior midway. The sessions
+//! are still split by weight; requests without a session are rotated.
+//! The picked target is recorded in the [`TARGET_ATTRIBUTE`] context attribute.
+
+use std::sync::{Mutex, PoisonError};
+
+use llm_proxy_core::RequestContext;
+use sha2::{Digest, Sha256};
+
+use crate::config::{RouteConfig, StickyConfig, TargetConfig, TargetHeaderPolicy};
+
+/// Attribute of the request context holding the target a request was sent to
+pub const TARGET_ATTRIBUTE: &str = "target_llm";
+
+/// Request header naming the target a request is sent to, overriding the split
+pub const TARGET_HEADER: &str = "x-llm-proxy-target";
+
+/// The LLMs a route's requests are split across
+#[derive(Debug)]
+pub struct TrafficSplit {
+    targets: Vec<TargetConfig>,
+    sticky: Option<StickyConfig>,
+    target_header: TargetHeaderPolicy,
+    current_weights: Mutex<Vec<i64>>,
+}
+
+impl TrafficSplit {
+    /// The split of `route`, `None` for routes with a single `target_llm`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the route doesn't set exactly
+    /// one of `target_llm` or `targets`, if it splits passthrough requests,
+    /// or if all its targets have weight 0. Routes with `sticky` must have
+    /// `targets`.
+    pub fn new(route: &RouteConfig) -> anyhow::Result<Option<Self>> {
+        match (route.target_llm.is_empty(), route.targets.is_empty()) {
+            (false, true) if route.sticky.is_some() => {
+                anyhow::bail!("sticky needs targets to pick from");
+            }
+            (false, true) => return Ok(None),
+            (true, false) => {}
+            _ => anyhow::bail!("Routes must set exactly one of target_llm or targets"),
+        }
+        if route.passthrough {
+            anyhow::bail!("Passthrough routes can't split requests across targets");
+        }
+        if route.targets.iter().all(|target| target.weight == 0) {
+            anyhow::bail!("At least one of the targets must have a weight above 0");
+        }
+        Ok(Some(Self {
+            targets: route.targets.clone(),
+            sticky: route.sticky.clone(),
+            target_header: route.target_header,
+            current_weights: Mutex::new(vec![0; route.targets.len()]),
+        }))
+    }
+
+    /// The LLM the request of `context` with `body` is sent to
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the route honors the request's
+    /// [`TARGET_HEADER`] and it doesn't name one of the targets, or names a
+    /// target with weight 0 the route's policy doesn't allow.
+    pub fn pick(&self, context: &RequestContext, body: &[u8]) -> anyhow::Result<&str> {
+        let requested = context
+            .header(TARGET_HEADER)
+            .filter(|_| self.target_header != TargetHeaderPolicy::Ignored);
+        if let Some(requested) = requested {
+            let target = self
+                .targets
+                .iter()
+                .find(|target| target.llm == requested)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Unknown target {requested}, the route's targets are {}",
+                        self.target_llms().join(", ")
+                    )
+                })?;
+            if target.weight == 0 && self.target_header != TargetHeaderPolicy::All {
+                anyhow::bail!("Target {requested} is drained and can't be requested");
+            }
+            return Ok(&target.llm);
+        }
+        let index = self
+            .session(context, body)
+            .map_or_else(|| self.next_target(), |session| self.assign(&session));
+        Ok(&self.targets[index].llm)
+    }
+
+    /// The session of a request, for sticky splits
+    fn session(&self, context: &RequestContext, body: &[u8]) -> Option<String> {
+        #[derive(serde::Deserialize)]
+        struct RequestUser {
+            user: Option<String>,
+        }
+
+        let session = match self.sticky.as_ref()? {
+            StickyConfig::User => serde_json::from_slice::<RequestUser>(body).ok()?.user?,
+            StickyConfig::Header { name } => context.header(name)?.to_string(),
+        };
+        Some(session).filter(|session| !session.is_empty())
+    }
+
+    /// Index of the target of `session`, by its hash
+    fn assign(&self, session: &str) -> usize {
+        let total: u64 = self
+            .targets
+            .iter()
+            .map(|target| u64::from(target.weight))
+            .sum();
+        let digest = Sha256::digest(session);
+        let mut bucket = digest
+            .iter()
+            .take(8)
+            .fold(0_u64, |hash, byte| (hash << 8) | u64::from(*byte))
+            % total;
+        self.targets
+            .iter()
+            .position(|target| {
+                let weight = u64::from(target.weight);
+                if bucket < weight {
+                    return true;
+                }
+                bucket -= weight;
+                false
+            })
+            .unwrap_or_default()
+    }
+
+    fn target_llms(&self) -> Vec<&str> {
+        self.targets
+            .iter()
+            .map(|target| target.llm.as_str())
+            .collect()
+    }
+
+    /// Index of the next target in the rotation, updating its state
+    fn next_target(&self) -> usize {
+        let mut current = self
+            .current_weights
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let mut total_weight = 0;
+        let mut selected: Option<usize> = None;
+        for (index, target) in self.targets.iter().enumerate() {
+            if target.weight == 0 {
+                continue;
+            }
+            current[index] += i64::from(target.weight);
+            total_weight += i64::from(target.weight);
+            if selected.is_none_or(|best| current[index] > current[best]) {
+                selected = Some(index);
+            }
+        }
+        // `new` ensures that some target has a weight
+        let selected = selected.unwrap_or_default();
+        current[selected] -= total_weight;
+        drop(current);
+        selected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn split(route: serde_json::Value) -> anyhow::Result<Option<TrafficSplit>> {
+        TrafficSplit::new(&serde_json::from_value(route).expect("Invalid route"))
+    }
+
+    #[test]
+    fn test_weighted_split() {
+        let split = split(json!({
+            "path_prefix": "/v1/chat/completions",
+            "targets": [
+                { "llm": "openai_chat", "weight": 2 },
+                { "llm": "azure_chat" },
+                { "llm": "disabled", "weight": 0 },
+            ],
+        }))
+        .expect("Invalid split")
+        .expect("No split");
+
+        let context = RequestContext::new();
+        let llms: Vec<_> = (0..6)
+            .map(|_| split.pick(&context, b"{}").expect("No target").to_string())
+            .collect();
+        assert_eq!(
+            llms,
+            [
+                "openai_chat",
+                "azure_chat",
+                "openai_chat",
+                "openai_chat",
+                "azure_chat",
+                "openai_chat"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_target_header() {
+        let split_with = |target_header: &str| {
+            split(json!({
+                "path_prefix": "/v1/chat/completions",
+                "targets": [
+                    { "llm": "openai_chat" },
+                    { "llm": "azure_chat" },
+                    { "llm": "drained", "weight": 0 },
+                ],
+                "target_header": target_header,
+            }))
+            .expect("Invalid split")
+            .expect("No split")
+        };
+        let requesting = |llm: &str| RequestContext::new().with_header(TARGET_HEADER, llm);
+
+        // Clients can't pick targets unless the route allows it
+        let ignored = split_with("ignored");
+        for _ in 0..4 {
+            assert_ne!(
+                ignored
+                    .pick(&requesting("drained"), b"{}")
+                    .expect("No target"),
+                "drained"
+            );
+        }
+        let llms: Vec<_> = (0..2)
+            .map(|_| {
+                ignored
+                    .pick(&requesting("azure_chat"), b"{}")
+                    .expect("No target")
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(llms, ["openai_chat", "azure_chat"]);
+
+        let weighted = split_with("weighted");
+        for _ in 0..2 {
+            assert_eq!(
+                weighted
+                    .pick(&requesting("azure_chat"), b"{}")
+                    .expect("No target"),
+                "azure_chat"
+            );
+        }
+        assert!(weighted.pick(&requesting("drained"), b"{}").is_err());
+        assert!(weighted.pick(&requesting("other"), b"{}").is_err());
+
+        let all = split_with("all");
+        assert_eq!(
+            all.pick(&requesting("drained"), b"{}").expect("No target"),
+            "drained"
+        );
+        assert!(all.pick(&requesting("other"), b"{}").is_err());
+    }
+
+    #[test]
+    fn test_sticky_sessions() {
+        let by_header = split(json!({
+            "path_prefix": "/v1/chat/completions",
+            "targets": [{ "llm": "openai_chat" }, { "llm": "azure_chat" }],
+            "sticky": { "type": "header", "name": "x-session-id" },
+        }))
+        .expect("Invalid split")
+        .expect("No split");
+
+        let mut sessions = std::collections::HashSet::new();
+        for session in 0..32 {
+            let context =
+                RequestContext::new().with_header("x-session-id", format!("session-{session}"));
+            let first = by_header
+                .pick(&context, b"{}")
+                .expect("No target")
+                .to_string();
+            for _ in 0..3 {
+                assert_eq!(by_header.pick(&context, b"{}").expect("No target"), first);
+            }
+            sessions.insert(first);
+        }
+        // Sessions are still split across the targets
+        assert_eq!(sessions.len(), 2);
+
+        let by_user = split(json!({
+            "path_prefix": "/v1/chat/completions",
+            "targets": [{ "llm": "openai_chat" }, { "llm": "azure_chat" }],
+            "sticky": { "type": "user" },
+        }))
+        .expect("Invalid split")
+        .expect("No split");
+        let context = RequestContext::new();
+        let body = br#"{"user": "user-1", "messages": []}"#;
+        let first = by_user.pick(&context, body).expect("No target").to_string();
+        for _ in 0..3 {
+            assert_eq!(by_user.pick(&context, body).expect("No target"), first);
+        }
+    }
+
+    #[test]
+    fn test_invalid_targets() {
+        let single = json!({ "path_prefix": "/v1", "target_llm": "openai_chat" });
+        assert!(split(single).expect("Invalid split").is_none());
+
+        let both = json!({
+            "path_prefix": "/v1",
+            "target_llm": "openai_chat",
+            "targets": [{ "llm": "azure_chat" }],
+        });
+        assert!(split(both).is_err());
+        assert!(split(json!({ "path_prefix": "/v1" })).is_err());
+        let zero = json!({ "path_prefix": "/v1", "targets": [{ "llm": "a", "weight": 0 }] });
+        assert!(split(zero).is_err());
+        let sticky =
+            json!({ "path_prefix": "/v1", "target_llm": "a", "sticky": { "type": "user" } });
+        assert!(split(sticky).is_err());
+    }
+}
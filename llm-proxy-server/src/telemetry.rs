@@ -0,0 +1,126 @@
+//! Export of the proxy's tracing spans as OpenTelemetry traces.
+//!
+//! With the `otel` feature, a `[telemetry]` section sends the spans of
+//! inbound requests, pipelines and upstream calls to an OTLP collector such
+//! as Jaeger or Tempo. Inbound requests carrying a W3C `traceparent` header
+//! continue the client's trace, which upstream requests propagate in turn.
+
+use actix_web::http::header::HeaderMap;
+use tracing::Span;
+#[cfg(feature = "otel")]
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+use crate::config::TelemetryConfig;
+
+/// Keeps the trace exporter running; pending spans are flushed when dropped
+pub struct TelemetryGuard {
+    #[cfg(feature = "otel")]
+    provider: opentelemetry_sdk::trace::SdkTracerProvider,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Err(e) = self.provider.shutdown() {
+            eprintln!("Failed to flush traces: {e}");
+        }
+    }
+}
+
+/// Create the layer exporting spans as configured by `config`, with the
+/// guard to keep until the server stops
+///
+/// # Errors
+///
+/// This function will return an error if the exporter can't be created.
+#[cfg(feature = "otel")]
+pub fn layer<S>(config: &TelemetryConfig) -> anyhow::Result<(impl Layer<S>, TelemetryGuard)>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::{
+        propagation::TraceContextPropagator,
+        trace::{Sampler, SdkTracerProvider},
+        Resource,
+    };
+
+    use crate::config::OtlpProtocol;
+
+    let exporter = match config.protocol {
+        OtlpProtocol::Grpc => SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(&config.otlp_endpoint)
+            .build()?,
+        OtlpProtocol::Http => SpanExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint(&config.otlp_endpoint)
+            .build()?,
+    };
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            config.sample_ratio,
+        ))))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build();
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let tracer = provider.tracer("llm-proxy");
+    Ok((
+        tracing_opentelemetry::layer().with_tracer(tracer),
+        TelemetryGuard { provider },
+    ))
+}
+
+/// Create the layer exporting spans as configured by `config`
+///
+/// # Errors
+///
+/// This function always returns an error, since trace export needs the
+/// server to be built with the `otel` feature.
+#[cfg(not(feature = "otel"))]
+pub fn layer(
+    _config: &TelemetryConfig,
+) -> anyhow::Result<(tracing_subscriber::layer::Identity, TelemetryGuard)> {
+    anyhow::bail!("The [telemetry] section needs the server built with the otel feature")
+}
+
+/// Continue the trace of the client that sent `headers` in `span`, if the
+/// request carries trace context
+#[cfg(feature = "otel")]
+pub fn set_remote_parent(span: &Span, headers: &HeaderMap) {
+    use opentelemetry::propagation::Extractor;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0
+                .keys()
+                .map(actix_web::http::header::HeaderName::as_str)
+                .collect()
+        }
+    }
+
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(headers))
+    });
+    let _ = span.set_parent(parent);
+}
+
+/// Continue the trace of the client that sent `headers` in `span`, which
+/// does nothing without the `otel` feature
+#[cfg(not(feature = "otel"))]
+pub const fn set_remote_parent(_span: &Span, _headers: &HeaderMap) {}
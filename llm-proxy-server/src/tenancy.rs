@@ -0,0 +1,472 @@
+//! Per-tenant overlays of the configuration.
+//!
+//! With `overlay_dir` under `[tenancy]`, each `<tenant>.toml` (or `.yaml`,
+//! `.json`) file in the directory holds a [`TenantOverlay`] for the tenant
+//! named by the file: the LLMs its requests on given routes are sent to, a
+//! system prompt, the limits of its client keys, the models it may request
+//! and the environment variables holding its upstream keys. The tenant of a
+//! request comes from its client key or tenant key, or else from the
+//! `[tenancy]` header.
+//!
+//! The directory is watched and every file is reloaded on its own: an overlay
+//! that can't be read, or that names unknown routes or LLMs, is logged and
+//! the tenant keeps its last valid overlay, leaving the other tenants
+//! unaffected, and webhooks subscribed to `reload_failed` are notified.
+//! Removing a file removes the tenant's overlay.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::Bytes;
+use llm_proxy_core::{Error, KeyStatus, RequestContext, TokenProvider};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::{
+    config::{self, Config, TenantOverlay},
+    webhooks::{Alert, Webhooks},
+};
+
+/// The overlays of the tenants, reloaded from their directory
+pub struct TenantOverlays {
+    loader: Arc<OverlayLoader>,
+    /// Kept alive so that the directory stays watched
+    _watcher: Option<RecommendedWatcher>,
+}
+
+/// Reads overlay files, keeping each tenant's last valid overlay
+struct OverlayLoader {
+    dir: PathBuf,
+    config: Arc<Config>,
+    overlays: RwLock<HashMap<String, OverlayState>>,
+}
+
+/// The overlay of a tenant and the error of its file, if its last reload failed
+#[derive(Debug, Default)]
+struct OverlayState {
+    overlay: Option<Arc<TenantOverlay>>,
+    error: Option<String>,
+}
+
+/// The overlay of a tenant and the error of its file, for the admin API
+#[derive(Debug, Serialize)]
+pub struct OverlayStatus {
+    pub overlay: Option<TenantOverlay>,
+    pub error: Option<String>,
+}
+
+impl TenantOverlays {
+    /// Load the overlays in `dir`, checked against `config`
+    ///
+    /// Invalid overlay files are logged and skipped.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the directory cannot be read.
+    pub fn new(dir: impl Into<PathBuf>, config: Arc<Config>) -> anyhow::Result<Self> {
+        let loader = OverlayLoader {
+            dir: dir.into(),
+            config,
+            overlays: RwLock::new(HashMap::new()),
+        };
+        loader.reload()?;
+        Ok(Self {
+            loader: Arc::new(loader),
+            _watcher: None,
+        })
+    }
+
+    /// Load and watch the overlays of `[tenancy]`, if it has an `overlay_dir`,
+    /// notifying `webhooks` of failed reloads
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the directory cannot be read or watched.
+    pub fn from_config(
+        config: &Arc<Config>,
+        webhooks: Option<&Arc<Webhooks>>,
+    ) -> anyhow::Result<Option<Self>> {
+        let Some(dir) = config
+            .tenancy
+            .as_ref()
+            .and_then(|tenancy| tenancy.overlay_dir.as_ref())
+        else {
+            return Ok(None);
+        };
+        Self::new(dir, config.clone())?
+            .watch(webhooks.cloned())
+            .map(Some)
+    }
+
+    /// Watch the directory and reload the overlays whenever it changes,
+    /// notifying `webhooks` of overlays that fail to reload
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the directory cannot be watched.
+    pub fn watch(self, webhooks: Option<Arc<Webhooks>>) -> anyhow::Result<Self> {
+        let loader = self.loader.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                if event.is_err() {
+                    return;
+                }
+                let failed = loader.reload().unwrap_or_else(|e| {
+                    warn!(error = %e, "Failed to reload tenant overlays");
+                    vec![Alert::ReloadFailed {
+                        file: loader.dir.display().to_string(),
+                        error: format!("{e:#}"),
+                    }]
+                });
+                if let Some(webhooks) = &webhooks {
+                    for alert in &failed {
+                        webhooks.notify(alert);
+                    }
+                }
+            })
+            .context("Failed to watch tenant overlays")?;
+        watcher
+            .watch(&self.loader.dir, RecursiveMode::NonRecursive)
+            .context("Failed to watch tenant overlays")?;
+        Ok(Self {
+            _watcher: Some(watcher),
+            ..self
+        })
+    }
+
+    /// The overlay of `tenant`, if it has one
+    #[must_use]
+    pub fn get(&self, tenant: &str) -> Option<Arc<TenantOverlay>> {
+        self.loader
+            .overlays
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(tenant)?
+            .overlay
+            .clone()
+    }
+
+    /// The overlays and reload errors of the tenants with an overlay file
+    #[must_use]
+    pub fn status(&self) -> BTreeMap<String, OverlayStatus> {
+        self.loader
+            .overlays
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(tenant, state)| {
+                let status = OverlayStatus {
+                    overlay: state.overlay.as_deref().cloned(),
+                    error: state.error.clone(),
+                };
+                (tenant.clone(), status)
+            })
+            .collect()
+    }
+}
+
+impl OverlayLoader {
+    /// Read every overlay file of the directory on its own, returning the
+    /// alerts of the files that became invalid
+    fn reload(&self) -> anyhow::Result<Vec<Alert>> {
+        let files = overlay_files(&self.dir)?;
+        let mut overlays = self
+            .overlays
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        overlays.retain(|tenant, _| files.contains_key(tenant));
+        let mut failed = Vec::new();
+        for (tenant, path) in files {
+            let state = overlays.entry(tenant.clone()).or_default();
+            // Every file is read on any change, so only changes are logged
+            match read_overlay(&path, &self.config) {
+                Ok(overlay) => {
+                    state.error = None;
+                    if state.overlay.as_deref() != Some(&overlay) {
+                        info!(tenant, path = %path.display(), "Loaded tenant overlay");
+                        state.overlay = Some(Arc::new(overlay));
+                    }
+                }
+                Err(e) => {
+                    // Other tenants and the tenant's last valid overlay are unaffected
+                    let error = format!("{e:#}");
+                    if state.error.as_ref() != Some(&error) {
+                        warn!(tenant, path = %path.display(), error, "Invalid tenant overlay");
+                        failed.push(Alert::ReloadFailed {
+                            file: path.display().to_string(),
+                            error: error.clone(),
+                        });
+                        state.error = Some(error);
+                    }
+                }
+            }
+        }
+        drop(overlays);
+        Ok(failed)
+    }
+}
+
+/// The overlay files in `dir`, by tenant
+///
+/// # Errors
+///
+/// This function will return an error if the directory cannot be read.
+pub fn overlay_files(dir: &Path) -> anyhow::Result<HashMap<String, PathBuf>> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read tenant overlay directory {}", dir.display()))?;
+    Ok(entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && overlay_format(path).is_some())
+        .filter_map(|path| {
+            let tenant = path.file_stem()?.to_str()?.to_string();
+            Some((tenant, path))
+        })
+        .collect())
+}
+
+/// The format of an overlay file, `None` for other files such as editor backups
+fn overlay_format(path: &Path) -> Option<::config::FileFormat> {
+    path.to_str()
+        .and_then(|path| config::file_format(path).ok())
+        .flatten()
+}
+
+/// Read and check the overlay in `path`
+///
+/// # Errors
+///
+/// This function will return an error if the file cannot be parsed or names
+/// routes or LLMs that aren't configured.
+pub fn read_overlay(path: &Path, config: &Config) -> anyhow::Result<TenantOverlay> {
+    let format =
+        overlay_format(path).with_context(|| format!("Unsupported file {}", path.display()))?;
+    let overlay: TenantOverlay = ::config::Config::builder()
+        .add_source(::config::File::from(path).format(format))
+        .build()
+        .and_then(::config::Config::try_deserialize)?;
+
+    for (route_id, route_overlay) in &overlay.routes {
+        let route = config
+            .route
+            .iter()
+            .find(|route| route.id() == *route_id)
+            .with_context(|| format!("Unknown route {route_id}"))?;
+        if let Some(llm) = &route_overlay.target_llm {
+            if route.passthrough {
+                anyhow::bail!("Passthrough route {route_id} can't change its target LLM");
+            }
+            if !config.llm.contains_key(llm) {
+                anyhow::bail!("Unknown target LLM {llm} for route {route_id}");
+            }
+        }
+    }
+    if let Some(llm) = overlay
+        .tokens
+        .keys()
+        .find(|llm| !config.llm.contains_key(*llm))
+    {
+        anyhow::bail!("Unknown LLM {llm} in tokens");
+    }
+    Ok(overlay)
+}
+
+/// Add `prompt` before the messages of a chat request `body`
+///
+/// Returns `None` for bodies that aren't chat requests, which are left for
+/// the pipeline to reject.
+#[must_use]
+pub fn add_system_prompt(body: &[u8], prompt: &str) -> Option<Bytes> {
+    let Ok(Value::Object(mut request)) = serde_json::from_slice(body) else {
+        return None;
+    };
+    let Some(Value::Array(messages)) = request.get_mut("messages") else {
+        return None;
+    };
+    messages.insert(0, json!({ "role": "system", "content": prompt }));
+    serde_json::to_vec(&request).ok().map(Bytes::from)
+}
+
+/// Token provider using a tenant's key for an LLM where its overlay maps one
+pub struct TenantTokenProvider {
+    llm: String,
+    inner: Arc<dyn TokenProvider>,
+    overlays: Arc<TenantOverlays>,
+}
+
+impl TenantTokenProvider {
+    /// Wrap the token provider `inner` of the LLM `llm`
+    #[must_use]
+    pub fn new(
+        llm: impl Into<String>,
+        inner: Arc<dyn TokenProvider>,
+        overlays: Arc<TenantOverlays>,
+    ) -> Self {
+        Self {
+            llm: llm.into(),
+            inner,
+            overlays,
+        }
+    }
+}
+
+#[async_trait]
+impl TokenProvider for TenantTokenProvider {
+    async fn get_token(&self) -> llm_proxy_core::Result<String> {
+        self.inner.get_token().await
+    }
+
+    async fn get_token_for(&self, context: &RequestContext) -> llm_proxy_core::Result<String> {
+        let token_env = context
+            .tenant
+            .as_deref()
+            .and_then(|tenant| self.overlays.get(tenant))
+            .and_then(|overlay| overlay.tokens.get(&self.llm).cloned());
+        let Some(token_env) = token_env else {
+            return self.inner.get_token_for(context).await;
+        };
+        std::env::var(&token_env).map_err(|e| {
+            Error::ConfigError(format!(
+                "Failed to read the tenant's key for {} from {token_env}: {e}",
+                self.llm
+            ))
+        })
+    }
+
+    async fn report_rejection(&self, token: &str, status: u16) {
+        self.inner.report_rejection(token, status).await;
+    }
+
+    fn key_status(&self) -> Vec<KeyStatus> {
+        self.inner.key_status()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Arc<Config> {
+        let config = serde_json::json!({
+            "llm": {
+                "openai_chat": {
+                    "provider": "openai",
+                    "type": "chat",
+                    "base_url": "https://api.openai.com/v1",
+                    "token_env": "OPENAI_API_KEY",
+                    "supports_streaming": true,
+                },
+            },
+            "processor": {},
+            "route": [{ "path_prefix": "/v1/chat/completions", "target_llm": "openai_chat" }],
+            "server": {
+                "host": "127.0.0.1",
+                "port": 3000,
+                "log_level": "info",
+                "request_timeout_secs": 30,
+                "cors_allowed_origins": [],
+            },
+        });
+        Arc::new(serde_json::from_value(config).expect("Invalid config"))
+    }
+
+    #[test]
+    fn test_invalid_overlays_are_isolated() {
+        let dir = std::env::temp_dir().join(format!("llm-proxy-tenants-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Failed to create directory");
+        std::fs::write(
+            dir.join("acme.toml"),
+            "system_prompt = \"You work for Acme.\"\n\
+             [routes.\"/v1/chat/completions\"]\n\
+             target_llm = \"openai_chat\"\n",
+        )
+        .expect("Failed to write overlay");
+        std::fs::write(dir.join("globex.toml"), "allowed_models = [\"gpt-4o*\"]\n")
+            .expect("Failed to write overlay");
+        std::fs::write(dir.join("notes.txt"), "not an overlay").expect("Failed to write file");
+
+        let overlays = TenantOverlays::new(&dir, config()).expect("Failed to load overlays");
+        let acme = overlays.get("acme").expect("No overlay");
+        assert_eq!(acme.target_llm("/v1/chat/completions"), Some("openai_chat"));
+        assert_eq!(
+            acme.system_prompt("/v1/chat/completions"),
+            Some("You work for Acme.")
+        );
+        assert!(overlays.get("notes").is_none());
+
+        // A broken overlay keeps the tenant's last one and leaves others alone
+        std::fs::write(
+            dir.join("acme.toml"),
+            "[routes.\"/v1/chat/completions\"]\ntarget_llm = \"unknown\"\n",
+        )
+        .expect("Failed to write overlay");
+        std::fs::write(dir.join("globex.toml"), "allowed_models = [\"gpt-4.1*\"]\n")
+            .expect("Failed to write overlay");
+        overlays.loader.reload().expect("Failed to reload overlays");
+        let status = overlays.status();
+        assert!(status["acme"].error.is_some());
+        assert_eq!(
+            overlays.get("acme").expect("No overlay").system_prompt,
+            acme.system_prompt
+        );
+        assert!(overlays
+            .get("globex")
+            .expect("No overlay")
+            .allows_model("gpt-4.1-mini"));
+
+        std::fs::remove_file(dir.join("globex.toml")).expect("Failed to remove overlay");
+        overlays.loader.reload().expect("Failed to reload overlays");
+        assert!(overlays.get("globex").is_none());
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_tenant_tokens() {
+        let dir =
+            std::env::temp_dir().join(format!("llm-proxy-tenant-tokens-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Failed to create directory");
+        std::fs::write(
+            dir.join("acme.toml"),
+            "tokens = { openai_chat = \"LLM_PROXY_TEST_ACME_KEY\" }\n",
+        )
+        .expect("Failed to write overlay");
+        std::env::set_var("LLM_PROXY_TEST_ACME_KEY", "sk-acme");
+        std::env::set_var("LLM_PROXY_TEST_DEFAULT_KEY", "sk-default");
+        let overlays = TenantOverlays::new(&dir, config()).expect("Failed to load overlays");
+        let provider = TenantTokenProvider::new(
+            "openai_chat",
+            Arc::new(llm_proxy_openai::EnvTokenProvider::new(
+                "LLM_PROXY_TEST_DEFAULT_KEY",
+            )),
+            Arc::new(overlays),
+        );
+
+        let acme = RequestContext::new().with_tenant("acme");
+        assert_eq!(
+            provider.get_token_for(&acme).await.expect("No token"),
+            "sk-acme"
+        );
+        let other = RequestContext::new().with_tenant("globex");
+        assert_eq!(
+            provider.get_token_for(&other).await.expect("No token"),
+            "sk-default"
+        );
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn test_add_system_prompt() {
+        let body = br#"{"model": "gpt-4o", "messages": [{"role": "user", "content": "Hi"}]}"#;
+        let body = add_system_prompt(body, "You work for Acme.").expect("No body");
+        let request: Value = serde_json::from_slice(&body).expect("Invalid body");
+        assert_eq!(request["messages"][0]["role"], "system");
+        assert_eq!(request["messages"][1]["content"], "Hi");
+        assert!(add_system_prompt(b"[]", "You work for Acme.").is_none());
+    }
+}
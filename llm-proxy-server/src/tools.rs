@@ -0,0 +1,465 @@
+//! Tools the proxy adds to chat requests and runs itself.
+//!
+//! A [`ToolExecutor`] offers tools, as `OpenAI` function tools, and runs the
+//! calls the model makes of them. A route gets the tools of the
+//! `[tool.<name>]` executors it lists in `tools`, such as web search, and
+//! those of the MCP servers of its `mcp`, next to the tools of the client,
+//! which win on a name clash.
+//!
+//! When the model calls tools the proxy runs, the proxy runs the calls and
+//! sends the model their results, until the model answers without such calls
+//! or `max_tool_rounds` is reached. The client then receives that last
+//! response, streamed if it asked for a stream, with the usage of all rounds;
+//! failed calls are reported to the model rather than to the client. Responses
+//! calling any tool the proxy doesn't run, such as the MCP tools of routes
+//! without `mcp.execute`, are returned to the client as they are.
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures_util::future::join_all;
+use llm_proxy_core::{Pipeline, RequestContext, ResponseStream};
+use llm_proxy_openai::{replay::StreamReplay, ChatCompletionRequest};
+use serde_json::{json, Value};
+use tracing::{debug, warn};
+
+use crate::{
+    config::{RouteConfig, ToolConfig},
+    mcp::McpServer,
+    web_search::WebSearch,
+};
+
+/// Rounds of tool calls per request, unless the route sets `max_tool_rounds`
+const DEFAULT_MAX_ROUNDS: usize = 5;
+
+/// Tools the proxy can run
+#[async_trait]
+pub trait ToolExecutor: Send + Sync {
+    /// The tools, as `OpenAI` function tools
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the tools cannot be listed, e.g.
+    /// when they are those of an unreachable server.
+    async fn tools(&self) -> Result<Vec<Value>>;
+
+    /// Run the tool `name` with `arguments`, returning its output for the model
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the tool cannot be run.
+    async fn call(&self, name: &str, arguments: Value) -> Result<String>;
+}
+
+/// The executor of the `[tool.<name>]` section `config`
+///
+/// # Errors
+///
+/// This function will return an error if the section is invalid, or a token
+/// it names is not set.
+pub fn create_tool_executor(name: &str, config: &ToolConfig) -> Result<Arc<dyn ToolExecutor>> {
+    match config {
+        ToolConfig::WebSearch(config) => Ok(Arc::new(WebSearch::new(name, config)?)),
+        #[cfg(feature = "wasm")]
+        ToolConfig::CodeInterpreter(config) => Ok(Arc::new(
+            crate::code_interpreter::CodeInterpreter::new(name, config)?,
+        )),
+        #[cfg(not(feature = "wasm"))]
+        ToolConfig::CodeInterpreter(_) => {
+            anyhow::bail!("The code_interpreter tool needs the server built with the wasm feature")
+        }
+    }
+}
+
+/// A tool offered on a route
+struct Tool {
+    /// The tool as an `OpenAI` function tool
+    definition: Value,
+    executor: Arc<dyn ToolExecutor>,
+    /// Whether the proxy runs the calls of the tool
+    execute: bool,
+}
+
+impl Tool {
+    fn name(&self) -> &Value {
+        &self.definition["function"]["name"]
+    }
+}
+
+/// The tools of a route
+pub struct RouteTools {
+    /// The route's executors, with whether the proxy runs their tools
+    executors: Vec<(Arc<dyn ToolExecutor>, bool)>,
+    max_rounds: usize,
+}
+
+impl RouteTools {
+    /// The tools of `route`, among the configured MCP `servers` and tool
+    /// `executors`, or `None` if the route has none
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the route names an unknown
+    /// server or tool.
+    pub fn new(
+        route: &RouteConfig,
+        servers: &HashMap<String, Arc<McpServer>>,
+        executors: &HashMap<String, Arc<dyn ToolExecutor>>,
+    ) -> Result<Option<Self>> {
+        let mut route_executors = Vec::new();
+        for name in &route.tools {
+            let executor = executors
+                .get(name)
+                .ok_or_else(|| anyhow!("Unknown tool {name}"))?;
+            route_executors.push((executor.clone(), true));
+        }
+        if let Some(mcp) = &route.mcp {
+            for name in &mcp.servers {
+                let server = servers
+                    .get(name)
+                    .ok_or_else(|| anyhow!("Unknown MCP server {name}"))?;
+                route_executors.push((server.clone() as Arc<dyn ToolExecutor>, mcp.execute));
+            }
+        }
+        if route_executors.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            executors: route_executors,
+            max_rounds: route.max_tool_rounds.unwrap_or(DEFAULT_MAX_ROUNDS),
+        }))
+    }
+
+    /// The route's tools; a tool of several executors is the first's
+    async fn tools(&self) -> Result<Vec<Tool>> {
+        let mut tools: Vec<Tool> = Vec::new();
+        for (executor, execute) in &self.executors {
+            for definition in executor.tools().await? {
+                let tool = Tool {
+                    definition,
+                    executor: executor.clone(),
+                    execute: *execute,
+                };
+                if !tools.iter().any(|known| known.name() == tool.name()) {
+                    tools.push(tool);
+                }
+            }
+        }
+        Ok(tools)
+    }
+
+    /// Run the chat request `body` through `pipeline` with the route's tools
+    /// added, running the calls of those the proxy runs
+    ///
+    /// Bodies that aren't JSON objects are run unchanged, for the pipeline to
+    /// reject.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the tools cannot be listed, or
+    /// the pipeline or the stream it returns fails.
+    pub async fn execute(
+        &self,
+        pipeline: &Pipeline<ChatCompletionRequest>,
+        body: Bytes,
+        context: &mut RequestContext,
+    ) -> llm_proxy_core::Result<ResponseStream> {
+        let mut request = match serde_json::from_slice::<Value>(&body) {
+            Ok(request) if request.is_object() => request,
+            _ => return pipeline.execute_with_context(body, context).await,
+        };
+        let tools = self.tools().await.map_err(llm_proxy_core::Error::Other)?;
+        add_tools(&mut request, &tools);
+        if !tools.iter().any(|tool| tool.execute) {
+            return pipeline
+                .execute_with_context(Bytes::from(request.to_string()), context)
+                .await;
+        }
+
+        let streaming = request["stream"] == true;
+        let include_usage = request["stream_options"]["include_usage"] == true;
+        request["stream"] = json!(false);
+        if let Some(request) = request.as_object_mut() {
+            request.remove("stream_options");
+        }
+        let mut usage = json!({ "prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0 });
+        let mut round = 0;
+        let mut completion = loop {
+            let rx = pipeline
+                .execute_with_context(Bytes::from(request.to_string()), context)
+                .await?;
+            let completion = collect(rx).await?;
+            add_usage(&mut usage, &completion["usage"]);
+            let message = &completion["choices"][0]["message"];
+            let Some(calls) = executed_calls(message, &tools).filter(|_| round < self.max_rounds)
+            else {
+                break completion;
+            };
+            round += 1;
+            let outputs = join_all(calls.iter().map(|(call, tool)| call_tool(call, tool))).await;
+            let messages = request["messages"]
+                .as_array_mut()
+                .ok_or_else(|| llm_proxy_core::Error::InvalidRequest("No messages".into()))?;
+            messages.push(message.clone());
+            for ((call, _), output) in calls.iter().zip(outputs) {
+                messages.push(json!({
+                    "role": "tool",
+                    "tool_call_id": call["id"],
+                    "content": output,
+                }));
+            }
+        };
+        if completion.get("usage").is_some() {
+            completion["usage"] = usage;
+        }
+        if streaming {
+            return Ok(StreamReplay::new()
+                .with_usage(include_usage)
+                .replay(&completion));
+        }
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        let _ = tx.send(Ok(Bytes::from(completion.to_string()))).await;
+        Ok(rx)
+    }
+}
+
+/// Add `tools` to those of `request`, unless the client sent a tool of the
+/// same name
+fn add_tools(request: &mut Value, tools: &[Tool]) {
+    if tools.is_empty() {
+        return;
+    }
+    if !request["tools"].is_array() {
+        request["tools"] = json!([]);
+    }
+    let Some(request_tools) = request["tools"].as_array_mut() else {
+        return;
+    };
+    for tool in tools {
+        let taken = request_tools
+            .iter()
+            .any(|known| &known["function"]["name"] == tool.name());
+        if !taken {
+            request_tools.push(tool.definition.clone());
+        }
+    }
+}
+
+/// The tool calls of `message` with their tools, if there are any and the
+/// proxy runs all of them
+fn executed_calls<'a>(message: &'a Value, tools: &'a [Tool]) -> Option<Vec<(&'a Value, &'a Tool)>> {
+    let calls = message["tool_calls"].as_array()?;
+    if calls.is_empty() {
+        return None;
+    }
+    calls
+        .iter()
+        .map(|call| {
+            tools
+                .iter()
+                .find(|tool| tool.execute && tool.name() == &call["function"]["name"])
+                .map(|tool| (call, tool))
+        })
+        .collect()
+}
+
+/// The output of the tool `call`, or the reason it failed
+async fn call_tool(call: &Value, tool: &Tool) -> String {
+    let name = call["function"]["name"].as_str().unwrap_or_default();
+    let arguments = match call["function"]["arguments"].as_str() {
+        Some(arguments) if !arguments.trim().is_empty() => {
+            match serde_json::from_str::<Value>(arguments) {
+                Ok(arguments) => arguments,
+                Err(e) => return format!("Error: invalid arguments: {e}"),
+            }
+        }
+        _ => json!({}),
+    };
+    debug!(tool = name, "Calling tool");
+    tool.executor
+        .call(name, arguments)
+        .await
+        .unwrap_or_else(|e| {
+            warn!(tool = name, error = %e, "Tool call failed");
+            format!("Error: {e:#}")
+        })
+}
+
+/// The completion in the body of the non-streamed response `rx`
+async fn collect(mut rx: ResponseStream) -> llm_proxy_core::Result<Value> {
+    let mut body = BytesMut::new();
+    while let Some(chunk) = rx.recv().await {
+        body.extend_from_slice(&chunk?);
+    }
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Add the token counts of `usage` to `total`
+fn add_usage(total: &mut Value, usage: &Value) {
+    for key in ["prompt_tokens", "completion_tokens", "total_tokens"] {
+        let sum = total[key].as_u64().unwrap_or(0) + usage[key].as_u64().unwrap_or(0);
+        total[key] = json!(sum);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use llm_proxy_core::{LLMClient, ProcessorChain};
+    use llm_proxy_openai::OpenAIRequestParser;
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    /// An executor with one `echo` tool
+    struct Echo;
+
+    #[async_trait]
+    impl ToolExecutor for Echo {
+        async fn tools(&self) -> Result<Vec<Value>> {
+            Ok(vec![json!({
+                "type": "function",
+                "function": { "name": "echo", "parameters": { "type": "object" } },
+            })])
+        }
+
+        async fn call(&self, _name: &str, arguments: Value) -> Result<String> {
+            Ok(arguments["text"].as_str().unwrap_or_default().to_string())
+        }
+    }
+
+    /// A client answering with scripted completions, recording requests
+    struct ScriptedClient {
+        responses: Mutex<Vec<Value>>,
+        requests: Mutex<Vec<Value>>,
+    }
+
+    impl ScriptedClient {
+        fn new(mut responses: Vec<Value>) -> Self {
+            responses.reverse();
+            Self {
+                responses: Mutex::new(responses),
+                requests: Mutex::default(),
+            }
+        }
+
+        fn pipeline(self: &Arc<Self>) -> Pipeline<ChatCompletionRequest> {
+            Pipeline::new(
+                Arc::new(OpenAIRequestParser::new()),
+                Arc::new(ProcessorChain::new(Vec::new())),
+                self.clone(),
+            )
+        }
+    }
+
+    #[async_trait]
+    impl LLMClient<ChatCompletionRequest> for ScriptedClient {
+        async fn execute(
+            &self,
+            request: ChatCompletionRequest,
+        ) -> llm_proxy_core::Result<ResponseStream> {
+            let request = serde_json::to_value(request)?;
+            self.requests.lock().expect("Poisoned").push(request);
+            let response = self.responses.lock().expect("Poisoned").pop();
+            let (tx, rx) = mpsc::channel(1);
+            tx.try_send(Ok(Bytes::from(response.unwrap_or_default().to_string())))
+                .expect("Channel full");
+            Ok(rx)
+        }
+    }
+
+    fn completion(message: &Value) -> Value {
+        json!({
+            "id": "c1",
+            "model": "m",
+            "choices": [{ "index": 0, "message": message, "finish_reason": "stop" }],
+            "usage": { "prompt_tokens": 10, "completion_tokens": 2, "total_tokens": 12 },
+        })
+    }
+
+    fn call(name: &str) -> Value {
+        completion(&json!({
+            "role": "assistant",
+            "content": null,
+            "tool_calls": [{
+                "id": "call_1",
+                "type": "function",
+                "function": { "name": name, "arguments": "{\"text\":\"hello\"}" },
+            }],
+        }))
+    }
+
+    fn route_tools(execute: bool) -> RouteTools {
+        RouteTools {
+            executors: vec![(Arc::new(Echo), execute)],
+            max_rounds: DEFAULT_MAX_ROUNDS,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_tools() {
+        let tools = route_tools(true).tools().await.expect("No tools");
+        let mut request = json!({ "messages": [] });
+        add_tools(&mut request, &tools);
+        assert_eq!(request["tools"][0]["function"]["name"], "echo");
+
+        // Tools of the client win
+        let mut request =
+            json!({ "tools": [{ "type": "function", "function": { "name": "echo" } }] });
+        add_tools(&mut request, &tools);
+        assert_eq!(request["tools"].as_array().map(Vec::len), Some(1));
+        assert!(request["tools"][0]["function"].get("parameters").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_execute_tool_calls() {
+        let client = Arc::new(ScriptedClient::new(vec![
+            call("echo"),
+            completion(&json!({ "role": "assistant", "content": "hello" })),
+        ]));
+        let body = Bytes::from(
+            r#"{"model":"m","stream":true,"messages":[{"role":"user","content":"Echo hello"}]}"#,
+        );
+        let mut rx = route_tools(true)
+            .execute(&client.pipeline(), body, &mut RequestContext::new())
+            .await
+            .expect("Execution failed");
+        let mut events = String::new();
+        while let Some(chunk) = rx.recv().await {
+            events
+                .push_str(std::str::from_utf8(&chunk.expect("Stream failed")).expect("Not UTF-8"));
+        }
+        assert!(events.contains("\"content\":\"hello\""));
+        assert!(events.ends_with("data: [DONE]\n\n"));
+
+        let requests = client.requests.lock().expect("Poisoned").clone();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["stream"], false);
+        assert_eq!(requests[0]["tools"][0]["function"]["name"], "echo");
+        assert_eq!(requests[1]["messages"][1]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(requests[1]["messages"][2]["role"], "tool");
+        assert_eq!(requests[1]["messages"][2]["content"], "hello");
+    }
+
+    #[tokio::test]
+    async fn test_calls_returned() {
+        let body = Bytes::from(r#"{"model":"m","messages":[{"role":"user","content":"Hi"}]}"#);
+        // Calls of the client's tools, and of tools the proxy doesn't run
+        for (name, execute) in [("client_tool", true), ("echo", false)] {
+            let client = Arc::new(ScriptedClient::new(vec![call(name)]));
+            let rx = route_tools(execute)
+                .execute(&client.pipeline(), body.clone(), &mut RequestContext::new())
+                .await
+                .expect("Execution failed");
+            let returned = collect(rx).await.expect("Invalid completion");
+            assert_eq!(
+                returned["choices"][0]["message"]["tool_calls"][0]["function"]["name"],
+                name
+            );
+            assert_eq!(returned["usage"]["total_tokens"], 12);
+        }
+    }
+}
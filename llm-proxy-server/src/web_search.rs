@@ -0,0 +1,241 @@
+//! Web search as a tool the proxy runs.
+//!
+//! A `[tool.<name>]` section with `type = "web_search"` offers the model a
+//! tool of that name taking a `query`. The proxy answers its calls with the
+//! titles, URLs and snippets of the top results of the configured search API,
+//! Brave Search or Tavily, as a numbered list.
+
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use llm_proxy_core::redact::SecretString;
+use serde_json::{json, Value};
+
+use crate::{
+    config::{SearchProvider, WebSearchConfig},
+    tools::ToolExecutor,
+};
+
+/// Results returned to the model, unless the tool sets `max_results`
+const DEFAULT_MAX_RESULTS: usize = 5;
+
+/// Seconds to wait for the API, unless the tool sets `timeout_secs`
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// A web search tool
+pub struct WebSearch {
+    name: String,
+    provider: SearchProvider,
+    base_url: String,
+    token: SecretString,
+    max_results: usize,
+    client: reqwest::Client,
+}
+
+/// A search result
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SearchResult {
+    title: String,
+    url: String,
+    snippet: String,
+}
+
+impl WebSearch {
+    /// The tool `name` configured by `config`
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the base URL is invalid or the
+    /// token variable is not set.
+    pub fn new(name: &str, config: &WebSearchConfig) -> Result<Self> {
+        let base_url = config
+            .base_url
+            .clone()
+            .unwrap_or_else(|| default_base_url(config.provider).to_string());
+        reqwest::Url::parse(&base_url).with_context(|| format!("Invalid base_url {base_url}"))?;
+        let token = std::env::var(&config.token_env)
+            .map(SecretString::from)
+            .with_context(|| format!("Failed to read search API key from {}", config.token_env))?;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(
+                config.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS),
+            ))
+            .build()?;
+        Ok(Self {
+            name: name.to_string(),
+            provider: config.provider,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token,
+            max_results: config.max_results.unwrap_or(DEFAULT_MAX_RESULTS),
+            client,
+        })
+    }
+
+    /// The top results for `query`
+    async fn search(&self, query: &str) -> Result<Vec<SearchResult>> {
+        let request = match self.provider {
+            SearchProvider::Brave => self
+                .client
+                .get(format!("{}/res/v1/web/search", self.base_url))
+                .query(&[("q", query), ("count", &self.max_results.to_string())])
+                .header("X-Subscription-Token", self.token.expose()),
+            SearchProvider::Tavily => self
+                .client
+                .post(format!("{}/search", self.base_url))
+                .bearer_auth(self.token.expose())
+                .json(&json!({ "query": query, "max_results": self.max_results })),
+        };
+        let response = request
+            .header(reqwest::header::ACCEPT, "application/json")
+            .send()
+            .await
+            .context("Failed to reach the search API")?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            bail!("The search API answered {status}: {body}");
+        }
+        let body: Value = response
+            .json()
+            .await
+            .context("Invalid response of the search API")?;
+        let mut results = match self.provider {
+            SearchProvider::Brave => brave_results(&body),
+            SearchProvider::Tavily => tavily_results(&body),
+        };
+        results.truncate(self.max_results);
+        Ok(results)
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for WebSearch {
+    async fn tools(&self) -> Result<Vec<Value>> {
+        Ok(vec![json!({
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "description": "Search the web. Returns the titles, URLs and snippets of the \
+                    top results, for questions about current events or facts you don't know.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "query": { "type": "string", "description": "The search query" },
+                    },
+                    "required": ["query"],
+                },
+            },
+        })])
+    }
+
+    async fn call(&self, _name: &str, arguments: Value) -> Result<String> {
+        let Some(query) = arguments["query"]
+            .as_str()
+            .filter(|query| !query.trim().is_empty())
+        else {
+            bail!("A query is required");
+        };
+        let results = self.search(query).await?;
+        Ok(format_results(query, &results))
+    }
+}
+
+/// Base URL of the API of `provider`
+const fn default_base_url(provider: SearchProvider) -> &'static str {
+    match provider {
+        SearchProvider::Brave => "https://api.search.brave.com",
+        SearchProvider::Tavily => "https://api.tavily.com",
+    }
+}
+
+/// The results of a Brave Search response
+fn brave_results(body: &Value) -> Vec<SearchResult> {
+    body["web"]["results"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|result| SearchResult {
+            title: strip_tags(result["title"].as_str().unwrap_or_default()),
+            url: result["url"].as_str().unwrap_or_default().to_string(),
+            snippet: strip_tags(result["description"].as_str().unwrap_or_default()),
+        })
+        .collect()
+}
+
+/// The results of a Tavily response
+fn tavily_results(body: &Value) -> Vec<SearchResult> {
+    body["results"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|result| SearchResult {
+            title: result["title"].as_str().unwrap_or_default().to_string(),
+            url: result["url"].as_str().unwrap_or_default().to_string(),
+            snippet: result["content"].as_str().unwrap_or_default().to_string(),
+        })
+        .collect()
+}
+
+/// `text` without the HTML tags search APIs highlight matches with
+fn strip_tags(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => stripped.push(c),
+            _ => {}
+        }
+    }
+    stripped
+}
+
+/// The tool output listing `results`
+fn format_results(query: &str, results: &[SearchResult]) -> String {
+    if results.is_empty() {
+        return format!("No results for \"{query}\"");
+    }
+    results
+        .iter()
+        .enumerate()
+        .map(|(position, result)| {
+            format!(
+                "{}. {}\n{}\n{}",
+                position + 1,
+                result.title,
+                result.url,
+                result.snippet
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_results() {
+        let brave = json!({ "web": { "results": [{
+            "title": "Rust <strong>Programming</strong> Language",
+            "url": "https://www.rust-lang.org/",
+            "description": "A language empowering <strong>everyone</strong>.",
+        }] } });
+        let results = brave_results(&brave);
+        assert_eq!(results[0].title, "Rust Programming Language");
+        assert_eq!(results[0].snippet, "A language empowering everyone.");
+
+        let tavily = json!({ "results": [
+            { "title": "Rust", "url": "https://www.rust-lang.org/", "content": "Fast." },
+            { "title": "Crates", "url": "https://crates.io/", "content": "Packages." },
+        ] });
+        assert_eq!(
+            format_results("rust", &tavily_results(&tavily)),
+            "1. Rust\nhttps://www.rust-lang.org/\nFast.\n\n2. Crates\nhttps://crates.io/\nPackages."
+        );
+        assert_eq!(format_results("nothing", &[]), "No results for \"nothing\"");
+    }
+}
@@ -0,0 +1,397 @@
+//! Webhook notifications of failures and budget events.
+//!
+//! Each `[webhook.<name>]` section posts a Slack-compatible JSON message, a
+//! `text` with the `event`, its `subject` and a `timestamp` in milliseconds
+//! since the Unix epoch, when one of its `events` happens:
+//!
+//! - `upstream_failures`: `failure_threshold` requests in a row on a route
+//!   failed with an upstream or internal error
+//! - `circuit_open`: a health check took an upstream endpoint out of rotation
+//! - `quota_exhausted`: a client key used up a limit of its quota
+//! - `reload_failed`: a reloaded tenant overlay file is invalid
+//!
+//! An event is notified at most once every `min_interval_secs` for the same
+//! subject, i.e. route, endpoint, client key or file, so a failing backend
+//! doesn't flood the channel. Notifications are delivered in the background
+//! and retried with exponential backoff when the webhook is unreachable or
+//! answers with a 429 or 5xx status; those that still fail are logged and
+//! dropped.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use llm_proxy_core::{providers::HealthListener, redact::SecretString};
+use llm_proxy_openai::processors::RequestLogSink;
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::config::{WebhookConfig, WebhookEvent};
+
+/// Consecutive failed requests on a route from which they are notified,
+/// unless `failure_threshold` is set
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// Time before an event is notified again for the same subject, unless
+/// `min_interval_secs` is set
+pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_mins(5);
+
+/// Retries of failed deliveries, unless `max_retries` is set
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Delay before the first retry of a failed delivery, doubled for each retry
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Something webhooks are notified of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Alert {
+    /// The last `failures` requests on `route` failed, the last one with `status`
+    UpstreamFailures {
+        route: String,
+        failures: u32,
+        status: u16,
+    },
+    /// `endpoint` was taken out of rotation after `failures` failed health checks
+    CircuitOpen {
+        endpoint: String,
+        failures: u32,
+        error: String,
+    },
+    /// The key of `client` used up a limit of its quota
+    QuotaExhausted { client: String, message: String },
+    /// The reloaded `file` is invalid
+    ReloadFailed { file: String, error: String },
+}
+
+impl Alert {
+    /// The event the alert is notified as
+    #[must_use]
+    pub const fn event(&self) -> WebhookEvent {
+        match self {
+            Self::UpstreamFailures { .. } => WebhookEvent::UpstreamFailures,
+            Self::CircuitOpen { .. } => WebhookEvent::CircuitOpen,
+            Self::QuotaExhausted { .. } => WebhookEvent::QuotaExhausted,
+            Self::ReloadFailed { .. } => WebhookEvent::ReloadFailed,
+        }
+    }
+
+    /// The route, endpoint, client key or file the alert is about
+    #[must_use]
+    pub fn subject(&self) -> &str {
+        match self {
+            Self::UpstreamFailures { route, .. } => route,
+            Self::CircuitOpen { endpoint, .. } => endpoint,
+            Self::QuotaExhausted { client, .. } => client,
+            Self::ReloadFailed { file, .. } => file,
+        }
+    }
+
+    /// The message shown in the channel
+    #[must_use]
+    pub fn text(&self) -> String {
+        match self {
+            Self::UpstreamFailures {
+                route,
+                failures,
+                status,
+            } => format!(
+                "LLM proxy: the last {failures} requests on route {route} failed, \
+                 the last one with status {status}"
+            ),
+            Self::CircuitOpen {
+                endpoint,
+                failures,
+                error,
+            } => format!(
+                "LLM proxy: upstream endpoint {endpoint} is out of rotation after \
+                 {failures} failed health checks: {error}"
+            ),
+            Self::QuotaExhausted { client, message } => {
+                format!("LLM proxy: client key {client} used up its quota: {message}")
+            }
+            Self::ReloadFailed { file, error } => {
+                format!("LLM proxy: failed to reload {file}: {error}")
+            }
+        }
+    }
+
+    /// The JSON body posted to webhooks
+    fn payload(&self) -> Value {
+        json!({
+            "text": self.text(),
+            "event": self.event(),
+            "subject": self.subject(),
+            "timestamp": SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis()),
+        })
+    }
+}
+
+/// A configured webhook
+struct Webhook {
+    name: String,
+    url: SecretString,
+    events: Vec<WebhookEvent>,
+    failure_threshold: u32,
+    min_interval: Duration,
+    max_retries: u32,
+    timeout: Duration,
+    /// Consecutive failed requests per route
+    failures: Mutex<HashMap<String, u32>>,
+    /// When each event was last notified, per subject
+    notified: Mutex<HashMap<(WebhookEvent, String), Instant>>,
+}
+
+impl Webhook {
+    fn new(name: &str, config: &WebhookConfig) -> Result<Self> {
+        let url = match (&config.url, &config.url_env) {
+            (Some(url), None) => SecretString::new(url.clone()),
+            (None, Some(url_env)) => std::env::var(url_env)
+                .map(SecretString::from)
+                .with_context(|| format!("Failed to read webhook URL from {url_env}"))?,
+            _ => bail!("Webhook {name} needs either a url or a url_env"),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            url,
+            events: config.events.clone(),
+            failure_threshold: config
+                .failure_threshold
+                .unwrap_or(DEFAULT_FAILURE_THRESHOLD),
+            min_interval: config
+                .min_interval_secs
+                .map_or(DEFAULT_MIN_INTERVAL, Duration::from_secs),
+            max_retries: config.max_retries.unwrap_or(DEFAULT_MAX_RETRIES),
+            timeout: Duration::from_secs(config.timeout_secs.unwrap_or(10)),
+            failures: Mutex::new(HashMap::new()),
+            notified: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Whether `alert` is notified now, recording it if so
+    fn admit(&self, alert: &Alert, now: Instant) -> bool {
+        if !self.events.is_empty() && !self.events.contains(&alert.event()) {
+            return false;
+        }
+        let mut notified = self.notified.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = (alert.event(), alert.subject().to_string());
+        if notified
+            .get(&key)
+            .is_some_and(|last| now.duration_since(*last) < self.min_interval)
+        {
+            return false;
+        }
+        notified.insert(key, now);
+        true
+    }
+
+    /// Count a request on `route` that ended with `status`, returning the
+    /// alert of the route once its failures reach the threshold
+    fn record_request(&self, route: &str, status: u16, failed: bool) -> Option<Alert> {
+        let mut failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+        if !failed {
+            failures.remove(route);
+            return None;
+        }
+        let count = failures.entry(route.to_string()).or_default();
+        *count += 1;
+        let count = *count;
+        drop(failures);
+        (count == self.failure_threshold).then(|| Alert::UpstreamFailures {
+            route: route.to_string(),
+            failures: count,
+            status,
+        })
+    }
+
+    /// Post `payload`, retrying failed attempts
+    async fn deliver(&self, client: &reqwest::Client, payload: &Value) {
+        let mut delay = RETRY_DELAY;
+        let mut attempt = 0;
+        loop {
+            let error = match client
+                .post(self.url.expose())
+                .timeout(self.timeout)
+                .json(payload)
+                .send()
+                .await
+            {
+                Ok(response) if response.status().is_success() => return,
+                Ok(response) => {
+                    let status = response.status();
+                    if !(status.is_server_error() || status.as_u16() == 429) {
+                        warn!(webhook = %self.name, %status, "Webhook refused notification");
+                        return;
+                    }
+                    format!("status {status}")
+                }
+                Err(e) => e.to_string(),
+            };
+            if attempt == self.max_retries {
+                warn!(webhook = %self.name, error, "Failed to deliver webhook notification");
+                return;
+            }
+            attempt += 1;
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+}
+
+/// Notifies the configured webhooks
+pub struct Webhooks {
+    hooks: Vec<Arc<Webhook>>,
+    client: reqwest::Client,
+    /// Runtime deliveries run on, so alerts can be raised from other threads
+    runtime: tokio::runtime::Handle,
+}
+
+impl Webhooks {
+    /// Create the webhooks of the `[webhook.<name>]` sections, if any
+    ///
+    /// Must be called from within a Tokio runtime.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a webhook has no URL, or the
+    /// environment variable holding it is missing.
+    pub fn from_config(config: &HashMap<String, WebhookConfig>) -> Result<Option<Self>> {
+        if config.is_empty() {
+            return Ok(None);
+        }
+        let hooks = config
+            .iter()
+            .map(|(name, webhook)| Webhook::new(name, webhook).map(Arc::new))
+            .collect::<Result<_>>()?;
+        Ok(Some(Self {
+            hooks,
+            client: reqwest::Client::new(),
+            runtime: tokio::runtime::Handle::try_current()
+                .context("Webhooks need a Tokio runtime")?,
+        }))
+    }
+
+    /// Notify the webhooks of `alert`, except those that were notified of
+    /// it recently or don't subscribe to its event
+    pub fn notify(&self, alert: &Alert) {
+        let now = Instant::now();
+        for webhook in &self.hooks {
+            if webhook.admit(alert, now) {
+                self.send(webhook, alert);
+            }
+        }
+    }
+
+    fn send(&self, webhook: &Arc<Webhook>, alert: &Alert) {
+        let webhook = webhook.clone();
+        let client = self.client.clone();
+        let payload = alert.payload();
+        self.runtime.spawn(async move {
+            webhook.deliver(&client, &payload).await;
+        });
+    }
+}
+
+/// Failures of routes are counted from the access log lines of ended
+/// requests, which a success resets
+#[async_trait]
+impl RequestLogSink for Webhooks {
+    async fn write(&self, record: &Value) -> llm_proxy_core::Result<()> {
+        let Some(route) = record["route"].as_str() else {
+            return Ok(());
+        };
+        let status = record["status"]
+            .as_u64()
+            .and_then(|status| u16::try_from(status).ok())
+            .unwrap_or_default();
+        let failed = matches!(record["error"].as_str(), Some("upstream" | "internal"));
+        let now = Instant::now();
+        for webhook in &self.hooks {
+            if let Some(alert) = webhook.record_request(route, status, failed) {
+                if webhook.admit(&alert, now) {
+                    self.send(webhook, &alert);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl HealthListener for Webhooks {
+    fn unhealthy(&self, url: &str, failures: u32, error: &llm_proxy_core::Error) {
+        self.notify(&Alert::CircuitOpen {
+            endpoint: url.to_string(),
+            failures,
+            error: error.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn webhook(config: Value) -> Webhook {
+        let config = serde_json::from_value(config).expect("Invalid config");
+        Webhook::new("ops", &config).expect("Invalid webhook")
+    }
+
+    #[test]
+    fn test_alerts_are_rate_limited_per_subject() {
+        let webhook = webhook(json!({
+            "url": "http://hooks.example.com/x",
+            "events": ["quota_exhausted", "circuit_open"],
+            "min_interval_secs": 60,
+        }));
+        let exhausted = |client: &str| Alert::QuotaExhausted {
+            client: client.to_string(),
+            message: "dollars per month".to_string(),
+        };
+        let start = Instant::now();
+
+        assert!(webhook.admit(&exhausted("ci-bot"), start));
+        assert!(!webhook.admit(&exhausted("ci-bot"), start + Duration::from_secs(59)));
+        assert!(webhook.admit(&exhausted("web"), start + Duration::from_secs(59)));
+        assert!(webhook.admit(&exhausted("ci-bot"), start + Duration::from_mins(1)));
+
+        let reload = Alert::ReloadFailed {
+            file: "tenants/acme.toml".to_string(),
+            error: "unknown route".to_string(),
+        };
+        assert!(!webhook.admit(&reload, start));
+    }
+
+    #[test]
+    fn test_sustained_upstream_failures() {
+        let webhook = webhook(json!({ "url_env": "HOME", "failure_threshold": 3 }));
+
+        assert!(webhook.record_request("chat", 502, true).is_none());
+        assert!(webhook.record_request("chat", 200, false).is_none());
+        assert!(webhook.record_request("chat", 502, true).is_none());
+        assert!(webhook.record_request("chat", 504, true).is_none());
+        assert!(webhook.record_request("embed", 502, true).is_none());
+        let alert = webhook.record_request("chat", 500, true);
+        assert_eq!(
+            alert,
+            Some(Alert::UpstreamFailures {
+                route: "chat".to_string(),
+                failures: 3,
+                status: 500,
+            })
+        );
+        // Notified once per run of failures
+        assert!(webhook.record_request("chat", 502, true).is_none());
+
+        let payload = alert.expect("No alert").payload();
+        assert_eq!(payload["event"], "upstream_failures");
+        assert_eq!(payload["subject"], "chat");
+        assert!(payload["text"]
+            .as_str()
+            .is_some_and(|text| text.contains("last 3 requests on route chat")));
+    }
+}
@@ -0,0 +1,405 @@
+//! WebSocket connections of chat routes.
+//!
+//! A route with `websocket` also accepts WebSocket upgrades. Clients send
+//! chat requests, in the `OpenAI` format of the route, as JSON text messages
+//! and get back a message per chunk of a streamed completion, or the whole
+//! completion, followed by `{"type":"done"}`. Messages with a `type` control
+//! the connection:
+//!
+//! - `{"type":"ping"}` is answered with `{"type":"pong"}`
+//! - `{"type":"cancel"}` stops the running request, answered with
+//!   `{"type":"cancelled"}`
+//! - `{"type":"error","status":400,"error":{...}}` reports a failed request
+//!
+//! A connection runs one request at a time. Each request gets its own ID,
+//! that of the upgrade request with a counter, and goes through the route's
+//! quotas, limits and access log like an HTTP request. The proxy pings
+//! clients every `ping_interval_secs` and closes connections that stay silent
+//! for `idle_timeout_secs`.
+
+use std::time::{Duration, Instant};
+
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse};
+use actix_ws::{AggregatedMessage, CloseCode, CloseReason, ProtocolError, Session};
+use bytes::Bytes;
+use llm_proxy_core::RequestContext;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::task::JoinHandle;
+use tracing::error;
+
+use crate::{
+    access_log::AccessLogEntry,
+    app::{self, AppState},
+    compression,
+    inbound::EventData,
+    payload,
+};
+
+/// Seconds between pings, unless the route sets `ping_interval_secs`
+const DEFAULT_PING_INTERVAL_SECS: u64 = 30;
+
+/// Seconds a connection may stay silent, unless the route sets `idle_timeout_secs`
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 90;
+
+/// Largest message accepted on routes without a `max_body_bytes`
+const DEFAULT_MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Whether `req` asks to upgrade to a WebSocket connection
+#[must_use]
+pub fn is_upgrade(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(actix_web::http::header::UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+/// Accept the WebSocket connection `req` asks for on the route at `index`
+///
+/// Requests on the connection start from `context`, the context of the
+/// upgrade request.
+#[must_use]
+pub fn serve(
+    req: &HttpRequest,
+    payload: web::Payload,
+    index: usize,
+    context: RequestContext,
+) -> HttpResponse {
+    let Some(state) = req.app_data::<web::Data<AppState>>().cloned() else {
+        return HttpResponse::InternalServerError().body("Missing server state");
+    };
+    let route = &state.config.route[index];
+    let settings = route.websocket.clone().unwrap_or_default();
+    let limit = route
+        .max_body_bytes(&state.config.server)
+        .unwrap_or(DEFAULT_MAX_MESSAGE_BYTES);
+    let (response, session, messages) = match actix_ws::handle(req, payload) {
+        Ok(handshake) => handshake,
+        Err(e) => return e.error_response(),
+    };
+    let messages = messages
+        .max_frame_size(limit)
+        .aggregate_continuations()
+        .max_continuation_size(limit);
+
+    let mut connection = Connection {
+        state,
+        req: req.clone(),
+        index,
+        context,
+        session,
+        running: None,
+        count: 0,
+    };
+    let ping_interval = Duration::from_secs(
+        settings
+            .ping_interval_secs
+            .unwrap_or(DEFAULT_PING_INTERVAL_SECS),
+    );
+    let idle_timeout = Duration::from_secs(
+        settings
+            .idle_timeout_secs
+            .unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS),
+    );
+    actix_web::rt::spawn(async move {
+        let mut messages = messages;
+        let mut pings = tokio::time::interval(ping_interval);
+        let mut last_seen = Instant::now();
+        let reason = loop {
+            tokio::select! {
+                _ = pings.tick() => {
+                    if last_seen.elapsed() > idle_timeout {
+                        break Some(CloseReason {
+                            code: CloseCode::Normal,
+                            description: Some("Idle timeout".to_string()),
+                        });
+                    }
+                    if connection.session.ping(b"").await.is_err() {
+                        break None;
+                    }
+                }
+                message = messages.recv() => {
+                    last_seen = Instant::now();
+                    match message {
+                        Some(Ok(message)) => {
+                            if let Err(reason) = connection.receive(message).await {
+                                break reason;
+                            }
+                        }
+                        Some(Err(e)) => break Some(protocol_error(&e)),
+                        None => break None,
+                    }
+                }
+            }
+        };
+        connection.cancel();
+        let _ = connection.session.close(reason).await;
+    });
+    response
+}
+
+/// A control message of a client
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Control {
+    Ping,
+    Cancel,
+}
+
+/// A text message of a client
+#[derive(Debug, PartialEq, Eq)]
+enum ClientMessage {
+    Control(Control),
+    Request(Bytes),
+}
+
+impl ClientMessage {
+    /// Parse a text message, requests being those without a `type`
+    fn parse(text: &str) -> Result<Self, String> {
+        let value: Value =
+            serde_json::from_str(text).map_err(|e| format!("Invalid message: {e}"))?;
+        if value.get("type").is_none() {
+            return Ok(Self::Request(Bytes::copy_from_slice(text.as_bytes())));
+        }
+        serde_json::from_value(value)
+            .map(Self::Control)
+            .map_err(|e| format!("Invalid message: {e}"))
+    }
+}
+
+/// A WebSocket connection on a route and its running request
+struct Connection {
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    index: usize,
+    context: RequestContext,
+    session: Session,
+    running: Option<JoinHandle<()>>,
+    count: u64,
+}
+
+impl Connection {
+    /// Handle a message of the client, or the reason to close the connection
+    #[allow(clippy::future_not_send)]
+    async fn receive(&mut self, message: AggregatedMessage) -> Result<(), Option<CloseReason>> {
+        let sent = match message {
+            AggregatedMessage::Text(text) => match ClientMessage::parse(&text) {
+                Ok(ClientMessage::Control(Control::Ping)) => {
+                    send(&mut self.session, &json!({ "type": "pong" })).await
+                }
+                Ok(ClientMessage::Control(Control::Cancel)) => {
+                    if self.cancel() {
+                        send(&mut self.session, &json!({ "type": "cancelled" })).await
+                    } else {
+                        send_error(&mut self.session, None, "No request is running".into()).await
+                    }
+                }
+                Ok(ClientMessage::Request(body)) => self.start(body).await,
+                Err(message) => send_error(&mut self.session, None, message.into()).await,
+            },
+            AggregatedMessage::Binary(_) => {
+                let message = "Send requests as text messages";
+                send_error(&mut self.session, None, message.into()).await
+            }
+            AggregatedMessage::Ping(bytes) => self.session.pong(&bytes).await.is_ok(),
+            AggregatedMessage::Pong(_) => true,
+            AggregatedMessage::Close(reason) => return Err(reason),
+        };
+        if sent {
+            Ok(())
+        } else {
+            Err(None)
+        }
+    }
+
+    /// Start a request, unless one is running
+    #[allow(clippy::future_not_send)]
+    async fn start(&mut self, body: Bytes) -> bool {
+        if self
+            .running
+            .as_ref()
+            .is_some_and(|task| !task.is_finished())
+        {
+            let message = "A request is already running, cancel it or wait for it to end";
+            return send_error(&mut self.session, None, message.into()).await;
+        }
+        self.count += 1;
+        let mut context = self.context.clone();
+        let request_id = format!(
+            "{}-{}",
+            context.request_id.as_deref().unwrap_or_default(),
+            self.count
+        );
+        context.request_id = Some(request_id);
+        self.running = Some(actix_web::rt::spawn(complete(
+            self.state.clone(),
+            self.req.clone(),
+            self.index,
+            context,
+            body,
+            self.session.clone(),
+        )));
+        true
+    }
+
+    /// Stop the running request, if there is one
+    fn cancel(&mut self) -> bool {
+        match self.running.take() {
+            Some(task) if !task.is_finished() => {
+                task.abort();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Run a chat request `body` and send its response to the client
+#[allow(clippy::future_not_send)]
+async fn complete(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    index: usize,
+    mut context: RequestContext,
+    body: Bytes,
+    mut session: Session,
+) {
+    let route = &state.config.route[index];
+    let request_id = context.request_id.clone().unwrap_or_default();
+    let mut entry = state
+        .access_log
+        .as_ref()
+        .map(|_| AccessLogEntry::start(&req, &request_id));
+    let overlay = context
+        .tenant
+        .as_deref()
+        .zip(state.overlays.as_ref())
+        .and_then(|(tenant, overlays)| overlays.get(tenant));
+    let streaming = compression::requests_stream(&body);
+    let result = match app::admit(&state, &mut context, overlay.as_deref())
+        .or_else(|| payload::check_messages(route, &body))
+    {
+        Some(response) => Err(response),
+        None => app::execute_chat(&state, index, overlay.as_deref(), body, &mut context).await,
+    };
+    if let Some(entry) = &mut entry {
+        entry.set_context(&context);
+    }
+    let mut rx = match result {
+        Ok(rx) => rx,
+        Err(response) => {
+            let status = response.status();
+            if let (Some(access_log), Some(entry)) = (&state.access_log, entry) {
+                access_log.write(entry, status);
+            }
+            let body = actix_web::body::to_bytes(response.into_body())
+                .await
+                .unwrap_or_default();
+            // JSON error bodies are `{"error":{...}}`, others plain text
+            let error = match serde_json::from_slice::<Value>(&body) {
+                Ok(Value::Object(mut error)) => {
+                    error.remove("error").unwrap_or_else(|| error.into())
+                }
+                _ => Value::String(String::from_utf8_lossy(&body).into_owned()),
+            };
+            send_error(&mut session, Some(status), error).await;
+            return;
+        }
+    };
+    if let (Some(access_log), Some(entry)) = (&state.access_log, entry) {
+        rx = access_log.observe(entry, rx);
+    }
+    let mut rx = app::observe_quota(&state, &context, rx);
+
+    let mut data = EventData::default();
+    while let Some(item) = rx.recv().await {
+        let bytes = match item {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error!(error = %e, "WebSocket response stream failed");
+                send_error(&mut session, None, e.to_string().into()).await;
+                return;
+            }
+        };
+        if !streaming {
+            data.buffer.extend_from_slice(&bytes);
+            continue;
+        }
+        for chunk in data.push(&bytes) {
+            if session.text(chunk).await.is_err() {
+                return;
+            }
+        }
+    }
+    if !streaming {
+        let body = String::from_utf8_lossy(&data.buffer).into_owned();
+        if session.text(body).await.is_err() {
+            return;
+        }
+    }
+    send(&mut session, &json!({ "type": "done" })).await;
+}
+
+/// Send `message` as JSON, returning whether the connection is still open
+async fn send(session: &mut Session, message: &Value) -> bool {
+    session.text(message.to_string()).await.is_ok()
+}
+
+/// Send an error message with the `status` of the failed request
+async fn send_error(session: &mut Session, status: Option<StatusCode>, error: Value) -> bool {
+    let error = match error {
+        Value::String(message) => json!({ "message": message }),
+        error => error,
+    };
+    let mut message = json!({ "type": "error", "error": error });
+    if let Some(status) = status {
+        message["status"] = status.as_u16().into();
+    }
+    send(session, &message).await
+}
+
+fn protocol_error(e: &ProtocolError) -> CloseReason {
+    let code = match e {
+        ProtocolError::Overflow => CloseCode::Size,
+        _ => CloseCode::Protocol,
+    };
+    CloseReason {
+        code,
+        description: Some(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_messages() {
+        assert_eq!(
+            ClientMessage::parse(r#"{"type":"ping"}"#),
+            Ok(ClientMessage::Control(Control::Ping))
+        );
+        assert_eq!(
+            ClientMessage::parse(r#"{"type":"cancel"}"#),
+            Ok(ClientMessage::Control(Control::Cancel))
+        );
+        let request = r#"{"model":"gpt-4o","messages":[{"role":"user","content":"Hi"}]}"#;
+        assert_eq!(
+            ClientMessage::parse(request),
+            Ok(ClientMessage::Request(Bytes::from(request)))
+        );
+        assert!(ClientMessage::parse(r#"{"type":"resume"}"#).is_err());
+        assert!(ClientMessage::parse("Hi").is_err());
+    }
+
+    #[test]
+    fn test_event_data() {
+        let mut data = EventData::default();
+        assert!(data.push(b"data: {\"id\":\"c1\",").is_empty());
+        assert_eq!(
+            data.push(b"\"choices\":[]}\n\ndata: {\"id\":\"c2\"}\r\n\n: comment\n"),
+            vec![r#"{"id":"c1","choices":[]}"#, r#"{"id":"c2"}"#]
+        );
+        assert!(data.push(b"data: [DONE]\n\n").is_empty());
+    }
+}
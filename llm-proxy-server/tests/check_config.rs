@@ -0,0 +1,65 @@
+//! Exit code of `--check-config` runs
+
+use std::process::Command;
+
+const CONFIG: &str = r#"
+[server]
+host = "127.0.0.1"
+port = 3000
+log_level = "info"
+request_timeout_secs = 30
+cors_allowed_origins = []
+
+[llm.openai_chat]
+provider = "openai"
+type = "chat"
+base_url = "https://api.openai.com/v1"
+token_env = "LLM_PROXY_TEST_CHECK_KEY"
+supports_streaming = true
+
+[processor.logger]
+type = "logger"
+config_value = "info"
+
+[[route]]
+path_prefix = "/v1/chat/completions"
+target_llm = "TARGET_LLM"
+processors = ["logger"]
+"#;
+
+/// Run `--check-config` on the config targeting `target_llm`
+fn check_config(target_llm: &str) -> std::process::Output {
+    let path = std::env::temp_dir().join(format!(
+        "llm-proxy-check-{target_llm}-{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(&path, CONFIG.replace("TARGET_LLM", target_llm))
+        .expect("Failed to write config");
+    let output = Command::new(env!("CARGO_BIN_EXE_llm-proxy-server"))
+        .arg("--check-config")
+        .env("CONFIG_PATH", &path)
+        .env("LLM_PROXY_TEST_CHECK_KEY", "sk-check")
+        .output()
+        .expect("Failed to run the server");
+    std::fs::remove_file(path).ok();
+    output
+}
+
+#[test]
+fn test_exit_code() {
+    let output = check_config("openai_chat");
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{report}");
+    assert!(
+        report.contains("ok     route /v1/chat/completions"),
+        "{report}"
+    );
+
+    let output = check_config("anthropic_chat");
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{report}");
+    assert!(
+        report.contains("- Unknown target LLM anthropic_chat"),
+        "{report}"
+    );
+}
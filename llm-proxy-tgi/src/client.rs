@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use llm_proxy_core::{
+    upstream, AuthScheme, ClientProvider, Error, LLMClient, ProviderCapabilities, RequestContext,
+    Result, TokenProvider, UrlProvider,
+};
+use llm_proxy_openai::ChatCompletionRequest;
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::{
+    template::ChatTemplate,
+    translate::{to_generate_request, to_openai_response, StreamTranslator},
+    types::{ErrorResponse, GenerateRequest, GenerateResponse},
+};
+
+/// Name of the upstream service in errors
+const UPSTREAM: &str = "TGI";
+
+/// Hugging Face Text Generation Inference implementation of `LLMClient` for
+/// `OpenAI` chat completion requests.
+///
+/// The messages of a request are rendered into a prompt with a
+/// [`ChatTemplate`] and sent to the `/generate` or `/generate_stream`
+/// endpoint; the generated text is returned in the `OpenAI` format.
+#[derive(Clone)]
+pub struct TgiClient {
+    client: Arc<dyn ClientProvider>,
+    token: Option<Arc<dyn TokenProvider>>,
+    url: Arc<dyn UrlProvider>,
+    auth_scheme: AuthScheme,
+    headers: Vec<(String, String)>,
+    template: ChatTemplate,
+    capabilities: ProviderCapabilities,
+}
+
+impl TgiClient {
+    /// Create a new TGI client with the given providers
+    pub fn new(
+        client_provider: Arc<dyn ClientProvider>,
+        url_provider: Arc<dyn UrlProvider>,
+    ) -> Self {
+        Self {
+            client: client_provider,
+            token: None,
+            url: url_provider,
+            auth_scheme: AuthScheme::default(),
+            headers: Vec::new(),
+            template: ChatTemplate::default(),
+            capabilities: ProviderCapabilities::default(),
+        }
+    }
+
+    /// Replace the provider of the HTTP client used for upstream requests
+    #[must_use]
+    pub fn with_client_provider(mut self, client_provider: Arc<dyn ClientProvider>) -> Self {
+        self.client = client_provider;
+        self
+    }
+
+    /// Authenticate requests, e.g. to Hugging Face Inference Endpoints
+    #[must_use]
+    pub fn with_token_provider(mut self, token_provider: Arc<dyn TokenProvider>) -> Self {
+        self.token = Some(token_provider);
+        self
+    }
+
+    /// Set how the API token is sent to the upstream service
+    #[must_use]
+    pub fn with_auth_scheme(mut self, auth_scheme: AuthScheme) -> Self {
+        self.auth_scheme = auth_scheme;
+        self
+    }
+
+    /// Send a static header with every request
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the chat template of the served model
+    #[must_use]
+    pub const fn with_template(mut self, template: ChatTemplate) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Declare what the upstream backend supports, e.g. its models
+    #[must_use]
+    pub fn with_capabilities(mut self, capabilities: ProviderCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Send request to TGI and get response
+    async fn send_request(
+        &self,
+        request: &GenerateRequest,
+        context: &RequestContext,
+        client: reqwest::Client,
+        url: String,
+    ) -> Result<reqwest::Response> {
+        let token = match &self.token {
+            Some(token) => Some(
+                token
+                    .get_token_for(context)
+                    .await
+                    .map_err(|e| Error::LLMError(format!("Failed to get API token: {e}")))?,
+            ),
+            None => None,
+        };
+
+        let mut builder = client.post(url);
+        if let Some(token) = &token {
+            builder = self.auth_scheme.apply(builder, token);
+        }
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let rejected = self.token.as_deref().zip(token.as_deref());
+        upstream::send_request::<ErrorResponse>(UPSTREAM, builder.json(request), None, rejected)
+            .await
+    }
+}
+
+#[async_trait]
+impl LLMClient<ChatCompletionRequest> for TgiClient {
+    async fn execute(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        self.execute_with_context(request, &RequestContext::new())
+            .await
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.capabilities.clone()
+    }
+
+    async fn execute_with_context(
+        &self,
+        request: ChatCompletionRequest,
+        context: &RequestContext,
+    ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        // 1. Render the request
+        let generate_request = to_generate_request(&request, self.template)?;
+
+        // 2. Get dependencies
+        let client = self
+            .client
+            .get_client()
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get HTTP client: {e}")))?;
+        let endpoint = if request.stream {
+            "generate_stream"
+        } else {
+            "generate"
+        };
+        let url = format!("{}/{endpoint}", self.url.get_url()?.trim_end_matches('/'));
+
+        // 3. Send request
+        let response = self
+            .send_request(&generate_request, context, client, url)
+            .await?;
+
+        // 4. Translate the response based on streaming flag
+        let (tx, rx) = mpsc::channel(100);
+        let (stream, model) = (request.stream, request.model);
+        info!("The request is streaming: {}", stream);
+        tokio::spawn(async move {
+            if stream {
+                let translator = StreamTranslator::new(model);
+                upstream::forward_stream(UPSTREAM, response, translator, &tx).await;
+            } else {
+                let translate = |response: GenerateResponse| to_openai_response(&response, &model);
+                upstream::forward_response(UPSTREAM, response, translate, &tx).await;
+            }
+        });
+
+        Ok(rx)
+    }
+}
@@ -0,0 +1,126 @@
+//! # LLM Proxy TGI
+//!
+//! This crate serves `OpenAI`-format chat completion requests with models
+//! behind Hugging Face [Text Generation Inference](https://huggingface.co/docs/text-generation-inference)
+//! through its `/generate` and `/generate_stream` endpoints.
+//!
+//! ## Components
+//!
+//! ### Client
+//! The [`client`] module provides [`TgiClient`], an `LLMClient` for
+//! [`ChatCompletionRequest`]s that answers in the `OpenAI` format, both for
+//! streaming and non-streaming requests.
+//!
+//! ### Providers
+//! The [`providers`] module provides [`TgiUrlProvider`] for the address of a
+//! TGI server.
+//!
+//! ### Factory
+//! The [`factory`] module registers the `tgi` provider in a
+//! [`ProviderRegistry`](llm_proxy_core::ProviderRegistry).
+//!
+//! ### Template
+//! The [`template`] module renders conversations into prompts with the chat
+//! template of the served model.
+//!
+//! ### Translate
+//! The [`translate`] module converts requests and responses between the two
+//! formats, including the server-sent events of streaming responses.
+//!
+//! ### Types
+//! The [`types`] module defines the TGI generate requests, responses and
+//! stream events.
+//!
+//! ## Example Usage
+//!
+//! ```rust,no_run
+//! use llm_proxy_tgi::{create_chat_pipeline, ChatTemplate};
+//!
+//! # async fn example() -> llm_proxy_core::Result<()> {
+//! let pipeline = create_chat_pipeline(vec![], "http://localhost:8080", ChatTemplate::Llama3);
+//!
+//! let request = bytes::Bytes::from(
+//!     r#"{"model": "tgi", "messages": [{"role": "user", "content": "Hi"}]}"#,
+//! );
+//! let response = pipeline.execute(request).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Configuration
+//!
+//! ```toml
+//! [llm.tgi]
+//! provider = "tgi"
+//! type = "chat"
+//! base_url = "http://localhost:8080"
+//! token_env = ""
+//! supports_streaming = true
+//! additional_config = { template = "llama3" }
+//! ```
+
+pub mod client;
+pub mod factory;
+pub mod providers;
+pub mod template;
+pub mod translate;
+pub mod types;
+
+use std::sync::Arc;
+
+use llm_proxy_core::{Pipeline, Processor, ProcessorChain, UrlProvider};
+use llm_proxy_openai::{
+    providers::StaticClientProvider, ChatCompletionRequest, OpenAIRequestParser,
+};
+
+pub use client::TgiClient;
+pub use providers::TgiUrlProvider;
+pub use template::ChatTemplate;
+pub use translate::{to_generate_request, to_openai_response, StreamTranslator};
+
+/// Create a new pipeline that serves `OpenAI` chat completion requests with a TGI server.
+///
+/// # Arguments
+/// * `processors` - List of processors to apply to requests
+/// * `base_url` - Address of the TGI server, e.g. "<http://localhost:8080>"
+/// * `template` - Chat template of the served model
+#[must_use]
+pub fn create_chat_pipeline(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    base_url: &str,
+    template: ChatTemplate,
+) -> Pipeline<ChatCompletionRequest> {
+    let client = create_chat_client(base_url).with_template(template);
+    create_pipeline_with_client(processors, client)
+}
+
+/// Create a TGI client for the server at `base_url`.
+///
+/// The client can be customized further (e.g. with a token provider for
+/// Inference Endpoints) before being passed to [`create_pipeline_with_client`].
+#[must_use]
+pub fn create_chat_client(base_url: &str) -> TgiClient {
+    create_chat_client_with_url_provider(Arc::new(TgiUrlProvider::new(base_url)))
+}
+
+/// Create a TGI client whose server is chosen by `url_provider`.
+#[must_use]
+pub fn create_chat_client_with_url_provider(url_provider: Arc<dyn UrlProvider>) -> TgiClient {
+    let client_provider = Arc::new(StaticClientProvider::new());
+    TgiClient::new(client_provider, url_provider)
+}
+
+/// Create a chat completion pipeline around an existing TGI client.
+///
+/// Requests are parsed in the `OpenAI` format, so processors written for
+/// `OpenAI` requests apply unchanged.
+#[must_use]
+pub fn create_pipeline_with_client(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    llm_client: TgiClient,
+) -> Pipeline<ChatCompletionRequest> {
+    let parser = Arc::new(OpenAIRequestParser::new());
+    let processor_chain = Arc::new(ProcessorChain::new(processors));
+
+    Pipeline::new(parser, processor_chain, Arc::new(llm_client))
+}
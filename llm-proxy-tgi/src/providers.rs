@@ -0,0 +1,54 @@
+use llm_proxy_core::{Result, UrlProvider};
+
+/// Provider that returns the address of a TGI server
+///
+/// The client appends `/generate` or `/generate_stream` to the address.
+pub struct TgiUrlProvider {
+    base_url: String,
+}
+
+impl TgiUrlProvider {
+    /// Create a provider for the TGI server at `base_url`
+    ///
+    /// The server address (`http://localhost:8080`) is accepted, as well as
+    /// the URL of one of its generate endpoints.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into();
+        let base_url = base_url.trim_end_matches('/');
+        let base_url = base_url
+            .strip_suffix("/generate_stream")
+            .or_else(|| base_url.strip_suffix("/generate"))
+            .unwrap_or(base_url);
+        Self {
+            base_url: base_url.to_string(),
+        }
+    }
+}
+
+impl UrlProvider for TgiUrlProvider {
+    fn get_url(&self) -> Result<String> {
+        Ok(self.base_url.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_provider() {
+        for base_url in [
+            "http://localhost:8080",
+            "http://localhost:8080/",
+            "http://localhost:8080/generate",
+            "http://localhost:8080/generate_stream",
+        ] {
+            assert_eq!(
+                TgiUrlProvider::new(base_url)
+                    .get_url()
+                    .expect("Failed to get URL"),
+                "http://localhost:8080"
+            );
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Chat templates that render a conversation into a prompt string.
+//!
+//! TGI's `/generate` endpoints take raw text, so the messages of a chat
+//! completion request are rendered with the template the served model was
+//! trained on, ending with the opening of an assistant turn.
+
+use llm_proxy_core::{Error, Result};
+use llm_proxy_openai::{ContentPart, Message, MessageContent};
+use serde::Deserialize;
+
+/// The prompt format of a model family
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatTemplate {
+    /// `<|im_start|>role ... <|im_end|>`, used by Qwen, Yi and many fine-tunes
+    #[default]
+    #[serde(alias = "chat_ml")]
+    Chatml,
+    /// `<|start_header_id|>role<|end_header_id|> ... <|eot_id|>`, used by Llama 3
+    Llama3,
+    /// `[INST] ... [/INST]`, used by Mistral and Mixtral
+    Mistral,
+}
+
+impl ChatTemplate {
+    /// Render `messages` into a prompt that asks the model for the next assistant turn
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a message contains an image,
+    /// which can't be rendered as text.
+    pub fn render(self, messages: &[Message]) -> Result<String> {
+        let turns = messages
+            .iter()
+            .map(|message| Ok((message.role.as_str(), text(message)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let prompt = match self {
+            Self::Chatml => {
+                let history = turns
+                    .iter()
+                    .map(|(role, content)| format!("<|im_start|>{role}\n{content}<|im_end|>\n"))
+                    .collect::<Vec<_>>()
+                    .concat();
+                format!("{history}<|im_start|>assistant\n")
+            }
+            Self::Llama3 => {
+                let history = turns
+                    .iter()
+                    .map(|(role, content)| {
+                        format!("<|start_header_id|>{role}<|end_header_id|>\n\n{content}<|eot_id|>")
+                    })
+                    .collect::<Vec<_>>()
+                    .concat();
+                format!(
+                    "<|begin_of_text|>{history}<|start_header_id|>assistant<|end_header_id|>\n\n"
+                )
+            }
+            Self::Mistral => {
+                // Mistral has no system role; system prompts lead the next user turn
+                let mut prompt = vec!["<s>".to_string()];
+                let mut pending = Vec::new();
+                for (role, content) in turns {
+                    match role {
+                        "system" | "developer" => pending.push(content),
+                        "assistant" => prompt.push(format!("{content}</s>")),
+                        _ => {
+                            pending.push(content);
+                            prompt.push(format!("[INST] {} [/INST]", pending.join("\n\n")));
+                            pending.clear();
+                        }
+                    }
+                }
+                if !pending.is_empty() {
+                    prompt.push(format!("[INST] {} [/INST]", pending.join("\n\n")));
+                }
+                prompt.concat()
+            }
+        };
+        Ok(prompt)
+    }
+
+    /// Sequences that end the assistant turn in this template
+    #[must_use]
+    pub const fn stop_sequences(self) -> &'static [&'static str] {
+        match self {
+            Self::Chatml => &["<|im_end|>"],
+            Self::Llama3 => &["<|eot_id|>"],
+            Self::Mistral => &["</s>"],
+        }
+    }
+}
+
+/// The text of a message
+fn text(message: &Message) -> Result<String> {
+    match &message.content {
+        None => Ok(String::new()),
+        Some(MessageContent::Text(text)) => Ok(text.clone()),
+        Some(MessageContent::Parts(parts)) => {
+            if parts
+                .iter()
+                .any(|part| matches!(part, ContentPart::ImageUrl { .. }))
+            {
+                return Err(Error::Unsupported(
+                    "TGI text generation doesn't accept images".to_string(),
+                ));
+            }
+            Ok(MessageContent::Parts(parts.clone()).text())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> Message {
+        serde_json::from_value(serde_json::json!({"role": role, "content": content}))
+            .expect("Invalid message")
+    }
+
+    #[test]
+    fn test_render() {
+        let messages = [
+            message("system", "Be brief."),
+            message("user", "Hi"),
+            message("assistant", "Hello!"),
+            message("user", "Bye"),
+        ];
+
+        assert_eq!(
+            ChatTemplate::Chatml
+                .render(&messages)
+                .expect("Failed to render"),
+            "<|im_start|>system\nBe brief.<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n\
+             <|im_start|>assistant\nHello!<|im_end|>\n<|im_start|>user\nBye<|im_end|>\n\
+             <|im_start|>assistant\n"
+        );
+        assert_eq!(
+            ChatTemplate::Mistral
+                .render(&messages)
+                .expect("Failed to render"),
+            "<s>[INST] Be brief.\n\nHi [/INST]Hello!</s>[INST] Bye [/INST]"
+        );
+    }
+}
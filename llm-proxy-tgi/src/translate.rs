@@ -0,0 +1,274 @@
+//! Translation between the `OpenAI` chat completions format and the TGI
+//! text generation API.
+//!
+//! - [`to_generate_request`] renders an inbound `OpenAI` chat completion
+//!   request into a TGI generate request with a [`ChatTemplate`].
+//! - [`to_openai_response`] converts a TGI generate response into an `OpenAI`
+//!   chat completion.
+//! - [`StreamTranslator`] converts the server-sent events of
+//!   `/generate_stream` into `OpenAI` chat completion chunks.
+
+use bytes::Bytes;
+use llm_proxy_core::{unix_now, upstream::ChunkTranslator, Error, Result};
+use llm_proxy_openai::ChatCompletionRequest;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    template::ChatTemplate,
+    types::{
+        Details, ErrorResponse, GenerateRequest, GenerateResponse, Parameters, StreamResponse,
+    },
+};
+
+/// The `stop` parameter, either a single sequence or a list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stop {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Convert an `OpenAI` chat completion request into a TGI generate request.
+///
+/// The messages are rendered into a prompt with `template`, whose end of turn
+/// sequences are added to the stop sequences of the request. Requests
+/// without a positive temperature are decoded greedily.
+///
+/// # Errors
+///
+/// This function will return an error if the request uses tools or images,
+/// which plain text generation doesn't support.
+pub fn to_generate_request(
+    request: &ChatCompletionRequest,
+    template: ChatTemplate,
+) -> Result<GenerateRequest> {
+    if request.additional_params.contains_key("tools") || request.functions.is_some() {
+        return Err(Error::Unsupported(
+            "TGI text generation doesn't support tools".to_string(),
+        ));
+    }
+
+    let mut stop = match request.param("stop") {
+        Some(Stop::One(stop)) => vec![stop],
+        Some(Stop::Many(stops)) => stops,
+        None => Vec::new(),
+    };
+    stop.extend(
+        template
+            .stop_sequences()
+            .iter()
+            .map(|sequence| (*sequence).to_string()),
+    );
+    let temperature = request.temperature.filter(|temperature| *temperature > 0.0);
+
+    Ok(GenerateRequest {
+        inputs: template.render(&request.messages)?,
+        parameters: Parameters {
+            max_new_tokens: request
+                .max_tokens
+                .or_else(|| request.param("max_completion_tokens")),
+            temperature,
+            top_p: request.param("top_p"),
+            stop,
+            seed: request.param("seed"),
+            frequency_penalty: request.param("frequency_penalty"),
+            do_sample: temperature.is_some(),
+            details: true,
+            return_full_text: false,
+        },
+    })
+}
+
+/// The `OpenAI` finish reason for the details of a finished generation
+fn finish_reason(details: Option<&Details>) -> &'static str {
+    match details.map(|details| details.finish_reason.as_str()) {
+        Some("length") => "length",
+        _ => "stop",
+    }
+}
+
+/// A new completion ID; TGI responses have none
+fn completion_id() -> String {
+    format!("chatcmpl-{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Convert a TGI generate response into an `OpenAI` chat completion.
+///
+/// TGI only counts generated tokens, so the prompt token count is zero.
+#[must_use]
+pub fn to_openai_response(response: &GenerateResponse, model: &str) -> Value {
+    let completion_tokens = response
+        .details
+        .as_ref()
+        .map_or(0, |details| details.generated_tokens);
+
+    json!({
+        "id": completion_id(),
+        "object": "chat.completion",
+        "created": unix_now(),
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": {"role": "assistant", "content": response.generated_text},
+            "finish_reason": finish_reason(response.details.as_ref()),
+        }],
+        "usage": {
+            "prompt_tokens": 0,
+            "completion_tokens": completion_tokens,
+            "total_tokens": completion_tokens,
+        },
+    })
+}
+
+/// Converts the server-sent events of `/generate_stream` into `OpenAI` chat
+/// completion chunks.
+///
+/// Feed the raw bytes of the response to [`StreamTranslator::push`] as they
+/// arrive; events may be split across reads. The translated chunks are
+/// returned as server-sent events in the format of the `OpenAI` API, ending
+/// with `data: [DONE]`. Special tokens, such as the end of sequence token,
+/// are not sent to the client.
+#[derive(Debug)]
+pub struct StreamTranslator {
+    id: String,
+    model: String,
+    created: u64,
+    /// Whether the role was sent in a chunk already
+    started: bool,
+    /// Bytes of an incomplete line
+    buffer: Vec<u8>,
+}
+
+impl StreamTranslator {
+    /// Create a translator for a response of `model`
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            id: completion_id(),
+            model: model.into(),
+            created: unix_now(),
+            started: false,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Translate the next bytes of the response
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an event can't be parsed or
+    /// reports an error.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let Some(data) = line.strip_prefix(b"data:") else {
+                continue;
+            };
+            if let Ok(error) = serde_json::from_slice::<ErrorResponse>(data) {
+                return Err(Error::LLMError(format!(
+                    "TGI stream error: {}",
+                    error.error
+                )));
+            }
+            let event: StreamResponse = serde_json::from_slice(data)
+                .map_err(|e| Error::LLMError(format!("Failed to parse TGI stream event: {e}")))?;
+            for data in self.translate(&event) {
+                events.push(Bytes::from(format!("data: {data}\n\n")));
+            }
+        }
+        Ok(events)
+    }
+
+    /// Translate one event into the data of `OpenAI` server-sent events
+    fn translate(&mut self, event: &StreamResponse) -> Vec<String> {
+        let mut delta = json!({});
+        if !self.started {
+            self.started = true;
+            delta["role"] = json!("assistant");
+        }
+        if !event.token.special && !event.token.text.is_empty() {
+            delta["content"] = json!(event.token.text);
+        }
+
+        let mut chunks = Vec::new();
+        if delta.as_object().is_some_and(|delta| !delta.is_empty()) {
+            chunks.push(self.chunk(&delta, None).to_string());
+        }
+        if event.generated_text.is_some() {
+            let reason = finish_reason(event.details.as_ref());
+            chunks.push(self.chunk(&json!({}), Some(reason)).to_string());
+            chunks.push("[DONE]".to_string());
+        }
+        chunks
+    }
+
+    /// An `OpenAI` chat completion chunk
+    fn chunk(&self, delta: &Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        })
+    }
+}
+
+impl ChunkTranslator for StreamTranslator {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        Self::push(self, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_translation() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "tgi",
+            "messages": [{"role": "user", "content": "Hi"}],
+            "max_tokens": 64,
+            "temperature": 0.0,
+            "stop": "END",
+        }))
+        .expect("Invalid request");
+
+        let translated =
+            to_generate_request(&request, ChatTemplate::Llama3).expect("Failed to translate");
+        assert!(translated
+            .inputs
+            .ends_with("<|start_header_id|>assistant<|end_header_id|>\n\n"));
+        assert_eq!(translated.parameters.max_new_tokens, Some(64));
+        assert_eq!(translated.parameters.stop, ["END", "<|eot_id|>"]);
+        assert_eq!(translated.parameters.temperature, None);
+        assert!(!translated.parameters.do_sample);
+    }
+
+    #[test]
+    fn test_stream_translation() {
+        let stream = concat!(
+            "data:{\"token\":{\"id\":1,\"text\":\"Hi\",\"logprob\":-0.1,\"special\":false},\"generated_text\":null,\"details\":null}\n\n",
+            "data:{\"token\":{\"id\":2,\"text\":\"<|eot_id|>\",\"logprob\":-0.1,\"special\":true},\"generated_text\":\"Hi\",\"details\":{\"finish_reason\":\"eos_token\",\"generated_tokens\":2}}\n\n",
+        );
+
+        // Split the stream in the middle of an event
+        let mut translator = StreamTranslator::new("tgi");
+        let (first, second) = stream.as_bytes().split_at(30);
+        let mut events = translator.push(first).expect("Failed to translate");
+        events.extend(translator.push(second).expect("Failed to translate"));
+
+        let chunks: Vec<Value> = events[..events.len() - 1]
+            .iter()
+            .map(|event| serde_json::from_slice(&event[6..]).expect("Invalid chunk"))
+            .collect();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0]["choices"][0]["delta"]["content"], "Hi");
+        assert_eq!(chunks[1]["choices"][0]["delta"], json!({}));
+        assert_eq!(chunks[1]["choices"][0]["finish_reason"], "stop");
+        assert_eq!(events[events.len() - 1], "data: [DONE]\n\n");
+    }
+}
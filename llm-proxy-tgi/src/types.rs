@@ -0,0 +1,101 @@
+use llm_proxy_core::upstream::UpstreamError;
+use serde::{Deserialize, Serialize};
+
+/// A request to the TGI `/generate` or `/generate_stream` endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerateRequest {
+    /// The prompt, rendered from the conversation with a chat template
+    pub inputs: String,
+    /// Generation parameters
+    pub parameters: Parameters,
+}
+
+/// Generation parameters of a TGI request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Parameters {
+    /// Maximum tokens to generate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_new_tokens: Option<u32>,
+    /// Temperature for response randomness; TGI requires it to be positive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Nucleus sampling threshold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Sequences that stop generation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    /// Random seed for reproducible outputs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// Penalty for tokens by how often they appeared
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// Whether to sample instead of decoding greedily
+    #[serde(default)]
+    pub do_sample: bool,
+    /// Whether to return generation details, e.g. the finish reason
+    #[serde(default)]
+    pub details: bool,
+    /// Whether to prepend the prompt to the generated text
+    #[serde(default)]
+    pub return_full_text: bool,
+}
+
+/// Generation details of a TGI response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Details {
+    /// Why generation stopped: `length`, `eos_token` or `stop_sequence`
+    pub finish_reason: String,
+    /// Number of generated tokens
+    pub generated_tokens: u32,
+}
+
+/// A response from the TGI `/generate` endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerateResponse {
+    /// The generated text
+    pub generated_text: String,
+    /// Generation details, if requested
+    #[serde(default)]
+    pub details: Option<Details>,
+}
+
+/// A generated token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Token {
+    /// The text of the token
+    pub text: String,
+    /// Whether the token is a special token, such as the end of sequence token
+    #[serde(default)]
+    pub special: bool,
+}
+
+/// An event of the TGI `/generate_stream` endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamResponse {
+    /// The token generated with this event
+    pub token: Token,
+    /// The full generated text, set on the last event
+    #[serde(default)]
+    pub generated_text: Option<String>,
+    /// Generation details, set on the last event if requested
+    #[serde(default)]
+    pub details: Option<Details>,
+}
+
+/// Error response from TGI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    /// The error message
+    pub error: String,
+    /// The kind of error, e.g. `validation` or `overloaded`
+    #[serde(default)]
+    pub error_type: Option<String>,
+}
+
+impl UpstreamError for ErrorResponse {
+    fn message(self) -> String {
+        self.error
+    }
+}
@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use llm_proxy_core::{
+    upstream, AuthScheme, ClientProvider, Error, LLMClient, ProviderCapabilities, RequestContext,
+    Result, TokenProvider, UrlProvider,
+};
+use llm_proxy_openai::ChatCompletionRequest;
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::{
+    translate::{to_openai_response, to_vertex_request, StreamTranslator},
+    types::{ErrorResponse, GenerateContentRequest, GenerateContentResponse},
+};
+
+/// Name of the upstream service in errors
+const UPSTREAM: &str = "Vertex AI";
+
+/// Vertex AI implementation of `LLMClient` for `OpenAI` chat completion requests.
+///
+/// Requests are translated into the `generateContent` format and sent to the
+/// publisher model named by the request's `model`; responses are translated
+/// back. Requests are authenticated with Google OAuth access tokens, e.g.
+/// from a `GcpTokenProvider`.
+#[derive(Clone)]
+pub struct VertexClient {
+    client: Arc<dyn ClientProvider>,
+    token: Arc<dyn TokenProvider>,
+    url: Arc<dyn UrlProvider>,
+    auth_scheme: AuthScheme,
+    headers: Vec<(String, String)>,
+    capabilities: ProviderCapabilities,
+}
+
+impl VertexClient {
+    /// Create a new Vertex AI client with the given providers
+    pub fn new(
+        client_provider: Arc<dyn ClientProvider>,
+        token_provider: Arc<dyn TokenProvider>,
+        url_provider: Arc<dyn UrlProvider>,
+    ) -> Self {
+        Self {
+            client: client_provider,
+            token: token_provider,
+            url: url_provider,
+            auth_scheme: AuthScheme::default(),
+            headers: Vec::new(),
+            capabilities: ProviderCapabilities::default(),
+        }
+    }
+
+    /// Replace the provider of the HTTP client used for upstream requests
+    #[must_use]
+    pub fn with_client_provider(mut self, client_provider: Arc<dyn ClientProvider>) -> Self {
+        self.client = client_provider;
+        self
+    }
+
+    /// Set how the API token is sent to the upstream service
+    #[must_use]
+    pub fn with_auth_scheme(mut self, auth_scheme: AuthScheme) -> Self {
+        self.auth_scheme = auth_scheme;
+        self
+    }
+
+    /// Send a static header with every request, e.g. `x-goog-user-project`
+    #[must_use]
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Declare what the upstream backend supports, e.g. its models
+    #[must_use]
+    pub fn with_capabilities(mut self, capabilities: ProviderCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Send request to Vertex AI and get response
+    async fn send_request(
+        &self,
+        request: &GenerateContentRequest,
+        client: reqwest::Client,
+        token: String,
+        url: String,
+    ) -> Result<reqwest::Response> {
+        let mut builder = self.auth_scheme.apply(client.post(url), &token);
+        for (name, value) in &self.headers {
+            builder = builder.header(name, value);
+        }
+        let rejected = Some((self.token.as_ref(), token.as_str()));
+        upstream::send_request::<ErrorResponse>(UPSTREAM, builder.json(request), None, rejected)
+            .await
+    }
+}
+
+#[async_trait]
+impl LLMClient<ChatCompletionRequest> for VertexClient {
+    async fn execute(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        self.execute_with_context(request, &RequestContext::new())
+            .await
+    }
+
+    fn capabilities(&self) -> ProviderCapabilities {
+        self.capabilities.clone()
+    }
+
+    async fn execute_with_context(
+        &self,
+        request: ChatCompletionRequest,
+        context: &RequestContext,
+    ) -> Result<mpsc::Receiver<Result<Bytes>>> {
+        // 1. Translate the request
+        let vertex_request = to_vertex_request(&request)?;
+
+        // 2. Get dependencies
+        let client = self
+            .client
+            .get_client()
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get HTTP client: {e}")))?;
+        let token = self
+            .token
+            .get_token_for(context)
+            .await
+            .map_err(|e| Error::LLMError(format!("Failed to get API token: {e}")))?;
+        let method = if request.stream {
+            "streamGenerateContent?alt=sse"
+        } else {
+            "generateContent"
+        };
+        let url = format!(
+            "{}/{}:{method}",
+            self.url.get_url()?.trim_end_matches('/'),
+            request.model
+        );
+
+        // 3. Send request
+        let response = self
+            .send_request(&vertex_request, client, token, url)
+            .await?;
+
+        // 4. Translate the response based on streaming flag
+        let (tx, rx) = mpsc::channel(100);
+        let (stream, model) = (request.stream, request.model);
+        info!("The request is streaming: {}", stream);
+        tokio::spawn(async move {
+            if stream {
+                let translator = StreamTranslator::new(model);
+                upstream::forward_stream(UPSTREAM, response, translator, &tx).await;
+            } else {
+                let translate =
+                    |response: GenerateContentResponse| to_openai_response(&response, &model);
+                upstream::forward_response(UPSTREAM, response, translate, &tx).await;
+            }
+        });
+
+        Ok(rx)
+    }
+}
@@ -0,0 +1,81 @@
+//! Provider factory for Vertex AI publisher models, registered as `vertex`.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use llm_proxy_core::{Pipeline, ProviderContext, ProviderFactory, ProviderRegistry, Result};
+use llm_proxy_openai::ChatCompletionRequest;
+use serde::Deserialize;
+
+use crate::{create_chat_client_with_url_provider, create_pipeline_with_client, VertexUrlProvider};
+
+/// Register the factory of this crate in `registry`
+pub fn register(registry: &mut ProviderRegistry<ChatCompletionRequest>) {
+    registry.register("vertex", VertexFactory);
+}
+
+/// Settings of a Vertex AI backend
+#[derive(Debug, Deserialize, Clone)]
+pub struct VertexSettings {
+    /// Google Cloud project, whose regional endpoint replaces `base_url`;
+    /// when unset, `base_url` is the URL of the publisher's models, e.g. of a
+    /// private service endpoint
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Location of the models, e.g. `us-central1` or `global`
+    #[serde(default = "default_location")]
+    pub location: String,
+    /// Publisher of the models
+    #[serde(default = "default_publisher")]
+    pub publisher: String,
+}
+
+fn default_location() -> String {
+    "us-central1".to_string()
+}
+
+fn default_publisher() -> String {
+    "google".to_string()
+}
+
+/// Factory for Vertex AI publisher models, with [`VertexSettings`] as settings
+///
+/// Requests are authenticated with the token of the context, typically from a
+/// `gcp` token source.
+pub struct VertexFactory;
+
+#[async_trait]
+impl ProviderFactory<ChatCompletionRequest> for VertexFactory {
+    async fn create_pipeline(
+        &self,
+        context: ProviderContext,
+    ) -> Result<Pipeline<ChatCompletionRequest>> {
+        let settings: VertexSettings = context.settings()?;
+        let token_provider = context.require_token_provider()?;
+
+        let url_provider = context.url_provider.clone().unwrap_or_else(|| {
+            Arc::new(settings.project.as_deref().map_or_else(
+                || VertexUrlProvider::from_url(&context.base_url),
+                |project| {
+                    VertexUrlProvider::with_publisher(
+                        project,
+                        &settings.location,
+                        &settings.publisher,
+                    )
+                },
+            ))
+        });
+        let mut client = create_chat_client_with_url_provider(token_provider, url_provider);
+        if let Some(client_provider) = &context.client_provider {
+            client = client.with_client_provider(client_provider.clone());
+        }
+        if let Some(auth_scheme) = &context.auth_scheme {
+            client = client.with_auth_scheme(auth_scheme.clone());
+        }
+        for (name, value) in &context.headers {
+            client = client.with_header(name, value);
+        }
+        client = client.with_capabilities(context.capabilities);
+        Ok(create_pipeline_with_client(vec![], client))
+    }
+}
@@ -0,0 +1,140 @@
+//! # LLM Proxy Vertex AI
+//!
+//! This crate serves `OpenAI`-format chat completion requests with Gemini and
+//! other publisher models on Google Cloud Vertex AI, so enterprise Google
+//! Cloud users can route through the proxy with their project's quotas,
+//! regions and IAM.
+//!
+//! ## Components
+//!
+//! ### Client
+//! The [`client`] module provides [`VertexClient`], an `LLMClient` for
+//! [`ChatCompletionRequest`]s that calls the `generateContent` and
+//! `streamGenerateContent` methods of the requested model and answers in the
+//! `OpenAI` format, both for streaming and non-streaming requests.
+//!
+//! ### Providers
+//! The [`providers`] module provides [`VertexUrlProvider`], which builds the
+//! regional `projects/{project}/locations/{location}/publishers/google/models`
+//! URL of a project.
+//!
+//! ### Factory
+//! The [`factory`] module registers the `vertex` provider in a
+//! [`ProviderRegistry`](llm_proxy_core::ProviderRegistry).
+//!
+//! ### Translate
+//! The [`translate`] module converts requests, responses and stream events
+//! between the two formats, including system instructions, images and
+//! function calls.
+//!
+//! ### Types
+//! The [`types`] module defines the `generateContent` requests and responses.
+//!
+//! ## Example Usage
+//!
+//! ```rust,no_run
+//! use std::sync::Arc;
+//!
+//! use llm_proxy_core::TokenProvider;
+//! use llm_proxy_vertex::create_chat_pipeline;
+//!
+//! // `token_provider` supplies Google OAuth access tokens, e.g. the
+//! // `GcpTokenProvider` of the `gcp` feature of `llm-proxy-core`
+//! async fn example(token_provider: Arc<dyn TokenProvider>) -> llm_proxy_core::Result<()> {
+//!     let pipeline = create_chat_pipeline(vec![], token_provider, "my-project", "us-central1");
+//!
+//!     // Requests and responses use the OpenAI chat completions format
+//!     let request = bytes::Bytes::from(
+//!         r#"{"model": "gemini-2.0-flash", "messages": [{"role": "user", "content": "Hi"}]}"#,
+//!     );
+//!     let response = pipeline.execute(request).await?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ## Configuration
+//!
+//! ```toml
+//! [llm.vertex]
+//! provider = "vertex"
+//! type = "chat"
+//! base_url = "https://us-central1-aiplatform.googleapis.com"  # unused when `project` is set
+//! token_env = ""
+//! token_source = { type = "gcp" }
+//! supports_streaming = true
+//! additional_config = { project = "my-project", location = "us-central1" }
+//! ```
+
+pub mod client;
+pub mod factory;
+pub mod providers;
+pub mod translate;
+pub mod types;
+
+use std::sync::Arc;
+
+use llm_proxy_core::{Pipeline, Processor, ProcessorChain, TokenProvider, UrlProvider};
+use llm_proxy_openai::{
+    providers::StaticClientProvider, ChatCompletionRequest, OpenAIRequestParser,
+};
+
+pub use client::VertexClient;
+pub use providers::VertexUrlProvider;
+pub use translate::{to_openai_response, to_vertex_request, StreamTranslator};
+
+/// Create a new pipeline that serves `OpenAI` chat completion requests with
+/// the Google models of a Vertex AI project.
+///
+/// # Arguments
+/// * `processors` - List of processors to apply to requests
+/// * `token_provider` - Provider of Google OAuth access tokens
+/// * `project` - Google Cloud project ID
+/// * `location` - Region of the models, e.g. "us-central1", or "global"
+#[must_use]
+pub fn create_chat_pipeline(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    token_provider: Arc<dyn TokenProvider>,
+    project: &str,
+    location: &str,
+) -> Pipeline<ChatCompletionRequest> {
+    create_pipeline_with_client(
+        processors,
+        create_chat_client(token_provider, project, location),
+    )
+}
+
+/// Create a Vertex AI client for the Google models of `project` in `location`.
+#[must_use]
+pub fn create_chat_client(
+    token_provider: Arc<dyn TokenProvider>,
+    project: &str,
+    location: &str,
+) -> VertexClient {
+    let url_provider = Arc::new(VertexUrlProvider::new(project, location));
+    create_chat_client_with_url_provider(token_provider, url_provider)
+}
+
+/// Create a Vertex AI client whose models URL is chosen by `url_provider`.
+#[must_use]
+pub fn create_chat_client_with_url_provider(
+    token_provider: Arc<dyn TokenProvider>,
+    url_provider: Arc<dyn UrlProvider>,
+) -> VertexClient {
+    let client_provider = Arc::new(StaticClientProvider::new());
+    VertexClient::new(client_provider, token_provider, url_provider)
+}
+
+/// Create a chat completion pipeline around an existing Vertex AI client.
+///
+/// Requests are parsed in the `OpenAI` format, so processors written for
+/// `OpenAI` requests apply unchanged.
+#[must_use]
+pub fn create_pipeline_with_client(
+    processors: Vec<Arc<dyn Processor<ChatCompletionRequest>>>,
+    llm_client: VertexClient,
+) -> Pipeline<ChatCompletionRequest> {
+    let parser = Arc::new(OpenAIRequestParser::new());
+    let processor_chain = Arc::new(ProcessorChain::new(processors));
+
+    Pipeline::new(parser, processor_chain, Arc::new(llm_client))
+}
@@ -0,0 +1,71 @@
+use llm_proxy_core::{Result, UrlProvider};
+
+/// Provider that returns the URL of the publisher models of a Vertex AI location
+///
+/// The client appends `/{model}:generateContent` or
+/// `/{model}:streamGenerateContent` to the URL.
+pub struct VertexUrlProvider {
+    models_url: String,
+}
+
+impl VertexUrlProvider {
+    /// Create a provider for the Google models of `project` in `location`,
+    /// e.g. `us-central1`, served from the regional endpoint of the location
+    ///
+    /// The `global` location is served from the global endpoint.
+    #[must_use]
+    pub fn new(project: &str, location: &str) -> Self {
+        Self::with_publisher(project, location, "google")
+    }
+
+    /// Create a provider for the models of `publisher` in `project` and `location`
+    #[must_use]
+    pub fn with_publisher(project: &str, location: &str, publisher: &str) -> Self {
+        let host = if location == "global" {
+            "aiplatform.googleapis.com".to_string()
+        } else {
+            format!("{location}-aiplatform.googleapis.com")
+        };
+        Self {
+            models_url: format!(
+                "https://{host}/v1/projects/{project}/locations/{location}/publishers/{publisher}/models"
+            ),
+        }
+    }
+
+    /// Create a provider for a models URL, e.g. of a private service endpoint
+    ///
+    /// The URL ends with `/publishers/{publisher}/models`.
+    pub fn from_url(models_url: impl Into<String>) -> Self {
+        Self {
+            models_url: models_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+}
+
+impl UrlProvider for VertexUrlProvider {
+    fn get_url(&self) -> Result<String> {
+        Ok(self.models_url.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_provider() {
+        assert_eq!(
+            VertexUrlProvider::new("my-project", "europe-west4")
+                .get_url()
+                .expect("Failed to get URL"),
+            "https://europe-west4-aiplatform.googleapis.com/v1/projects/my-project/locations/europe-west4/publishers/google/models"
+        );
+        assert_eq!(
+            VertexUrlProvider::new("my-project", "global")
+                .get_url()
+                .expect("Failed to get URL"),
+            "https://aiplatform.googleapis.com/v1/projects/my-project/locations/global/publishers/google/models"
+        );
+    }
+}
@@ -0,0 +1,632 @@
+//! Translation between the `OpenAI` chat completions format and the Vertex AI
+//! `generateContent` API.
+//!
+//! - [`to_vertex_request`] converts an inbound `OpenAI` chat completion
+//!   request into a `generateContent` request.
+//! - [`to_openai_response`] converts a `generateContent` response into an
+//!   `OpenAI` chat completion.
+//! - [`StreamTranslator`] converts the server-sent events of
+//!   `streamGenerateContent` into `OpenAI` chat completion chunks.
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use llm_proxy_core::{unix_now, upstream::ChunkTranslator, Error, Result};
+use llm_proxy_openai::{ChatCompletionRequest, ContentPart, Message, MessageContent};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::types::{
+    Blob, Candidate, Content, ErrorResponse, FileData, FunctionCall, FunctionCallingConfig,
+    FunctionDeclaration, FunctionResponse, GenerateContentRequest, GenerateContentResponse,
+    GenerationConfig, Part, Tool, ToolConfig, UsageMetadata,
+};
+
+/// The `stop` parameter, either a single sequence or a list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stop {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// JSON Schema keywords that Vertex AI schemas reject
+const UNSUPPORTED_SCHEMA_KEYWORDS: &[&str] = &["$schema", "additionalProperties", "strict"];
+
+/// Convert an `OpenAI` chat completion request into a `generateContent` request.
+///
+/// System messages become the system instruction, assistant turns become
+/// `model` turns and tool results are sent as function responses, named after
+/// the call they answer. Images are sent inline when given as data URLs and
+/// by reference otherwise.
+///
+/// # Errors
+///
+/// This function will return an error if tool call arguments are not valid
+/// JSON or a tool result doesn't answer a known call.
+pub fn to_vertex_request(request: &ChatCompletionRequest) -> Result<GenerateContentRequest> {
+    let mut system = Vec::new();
+    let mut contents = Vec::new();
+    // Names of the functions called so far, by call ID
+    let mut calls: HashMap<&str, &str> = HashMap::new();
+    for message in &request.messages {
+        match message.role.as_str() {
+            "system" | "developer" => system.extend(to_parts(message)),
+            "assistant" => {
+                let mut parts = to_parts(message);
+                for call in message.tool_calls.iter().flatten() {
+                    calls.insert(&call.id, &call.function.name);
+                    parts.push(function_call_part(&call.function)?);
+                }
+                if let Some(call) = &message.function_call {
+                    parts.push(function_call_part(call)?);
+                }
+                push_turn(&mut contents, "model", parts);
+            }
+            "tool" | "function" => {
+                let name = message
+                    .tool_call_id
+                    .as_deref()
+                    .and_then(|id| calls.get(id).copied())
+                    .or(message.name.as_deref())
+                    .ok_or_else(|| {
+                        Error::ParseError(
+                            "Tool result doesn't answer a known tool call".to_string(),
+                        )
+                    })?;
+                let part = Part {
+                    function_response: Some(FunctionResponse {
+                        name: name.to_string(),
+                        response: function_response(message),
+                    }),
+                    ..Part::default()
+                };
+                push_turn(&mut contents, "user", vec![part]);
+            }
+            _ => push_turn(&mut contents, "user", to_parts(message)),
+        }
+    }
+
+    Ok(GenerateContentRequest {
+        contents,
+        system_instruction: (!system.is_empty()).then_some(Content {
+            role: None,
+            parts: system,
+        }),
+        tools: to_vertex_tools(request),
+        tool_config: request
+            .additional_params
+            .get("tool_choice")
+            .and_then(to_tool_config),
+        generation_config: Some(to_generation_config(request))
+            .filter(|config| *config != GenerationConfig::default()),
+    })
+}
+
+/// Add `parts` to the conversation, merging consecutive turns of the same role
+fn push_turn(contents: &mut Vec<Content>, role: &str, parts: Vec<Part>) {
+    if parts.is_empty() {
+        return;
+    }
+    match contents.last_mut() {
+        Some(last) if last.role.as_deref() == Some(role) => last.parts.extend(parts),
+        _ => contents.push(Content {
+            role: Some(role.to_string()),
+            parts,
+        }),
+    }
+}
+
+/// The text and image parts of a message
+fn to_parts(message: &Message) -> Vec<Part> {
+    let text_part = |text: &str| Part {
+        text: Some(text.to_string()),
+        ..Part::default()
+    };
+    match &message.content {
+        None => Vec::new(),
+        Some(MessageContent::Text(text)) if text.is_empty() => Vec::new(),
+        Some(MessageContent::Text(text)) => vec![text_part(text)],
+        Some(MessageContent::Parts(parts)) => parts
+            .iter()
+            .filter_map(|part| match part {
+                ContentPart::Text { text } => Some(text_part(text)),
+                ContentPart::ImageUrl { image_url } => Some(image_part(&image_url.url)),
+                ContentPart::Other(_) => None,
+            })
+            .collect(),
+    }
+}
+
+/// A part with the image at `url`, inline for data URLs
+fn image_part(url: &str) -> Part {
+    if let Some((mime_type, data)) = url
+        .strip_prefix("data:")
+        .and_then(|data_url| data_url.split_once(";base64,"))
+    {
+        return Part {
+            inline_data: Some(Blob {
+                mime_type: mime_type.to_string(),
+                data: data.to_string(),
+            }),
+            ..Part::default()
+        };
+    }
+
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+    let mime_type = match path.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("heic") => "image/heic",
+        _ => "image/jpeg",
+    };
+    Part {
+        file_data: Some(FileData {
+            mime_type: mime_type.to_string(),
+            file_uri: url.to_string(),
+        }),
+        ..Part::default()
+    }
+}
+
+/// A part with a function call of the model
+fn function_call_part(call: &llm_proxy_openai::FunctionCall) -> Result<Part> {
+    let args = if call.arguments.trim().is_empty() {
+        json!({})
+    } else {
+        serde_json::from_str(&call.arguments)
+            .map_err(|e| Error::ParseError(format!("Invalid tool call arguments: {e}")))?
+    };
+    Ok(Part {
+        function_call: Some(FunctionCall {
+            name: call.name.clone(),
+            args,
+        }),
+        ..Part::default()
+    })
+}
+
+/// The result of a tool message as an object; other results are wrapped in `content`
+fn function_response(message: &Message) -> Value {
+    let output = message
+        .content
+        .as_ref()
+        .map(MessageContent::text)
+        .unwrap_or_default();
+    match serde_json::from_str::<Value>(&output) {
+        Ok(result @ Value::Object(_)) => result,
+        _ => json!({ "content": output }),
+    }
+}
+
+/// Function declarations for the tools and legacy functions of a request
+fn to_vertex_tools(request: &ChatCompletionRequest) -> Vec<Tool> {
+    let tools: Vec<Value> = request.param("tools").unwrap_or_default();
+    let functions = tools
+        .into_iter()
+        .filter(|tool| tool["type"] == "function")
+        .map(|tool| tool["function"].clone())
+        .chain(
+            request
+                .functions
+                .iter()
+                .flatten()
+                .filter_map(|function| serde_json::to_value(function).ok()),
+        )
+        .filter_map(|function| serde_json::from_value::<FunctionDeclaration>(function).ok())
+        .map(|mut declaration| {
+            if let Some(parameters) = &mut declaration.parameters {
+                clean_schema(parameters);
+            }
+            declaration
+        })
+        .collect::<Vec<_>>();
+
+    if functions.is_empty() {
+        Vec::new()
+    } else {
+        vec![Tool {
+            function_declarations: functions,
+        }]
+    }
+}
+
+/// Remove the JSON Schema keywords Vertex AI rejects, recursively
+fn clean_schema(schema: &mut Value) {
+    match schema {
+        Value::Object(object) => {
+            for keyword in UNSUPPORTED_SCHEMA_KEYWORDS {
+                object.remove(*keyword);
+            }
+            object.values_mut().for_each(clean_schema);
+        }
+        Value::Array(items) => items.iter_mut().for_each(clean_schema),
+        _ => {}
+    }
+}
+
+/// The function calling mode for an `OpenAI` `tool_choice`
+fn to_tool_config(tool_choice: &Value) -> Option<ToolConfig> {
+    let (mode, allowed_function_names) = match tool_choice {
+        Value::String(choice) => match choice.as_str() {
+            "none" => ("NONE", Vec::new()),
+            "auto" => ("AUTO", Vec::new()),
+            "required" => ("ANY", Vec::new()),
+            _ => return None,
+        },
+        Value::Object(_) => (
+            "ANY",
+            vec![tool_choice["function"]["name"].as_str()?.to_string()],
+        ),
+        _ => return None,
+    };
+    Some(ToolConfig {
+        function_calling_config: FunctionCallingConfig {
+            mode: mode.to_string(),
+            allowed_function_names,
+        },
+    })
+}
+
+/// The generation config for the sampling and output parameters of a request
+fn to_generation_config(request: &ChatCompletionRequest) -> GenerationConfig {
+    let format = request.additional_params.get("response_format");
+    let (response_mime_type, response_schema) =
+        match format.and_then(|format| format["type"].as_str()) {
+            Some("json_object") => (Some("application/json".to_string()), None),
+            Some("json_schema") => {
+                let mut schema = format
+                    .map(|format| format["json_schema"]["schema"].clone())
+                    .filter(|schema| !schema.is_null());
+                if let Some(schema) = &mut schema {
+                    clean_schema(schema);
+                }
+                (Some("application/json".to_string()), schema)
+            }
+            _ => (None, None),
+        };
+
+    GenerationConfig {
+        temperature: request.temperature,
+        top_p: request.param("top_p"),
+        max_output_tokens: request
+            .max_tokens
+            .or_else(|| request.param("max_completion_tokens")),
+        candidate_count: request.param("n"),
+        stop_sequences: match request.param("stop") {
+            Some(Stop::One(stop)) => vec![stop],
+            Some(Stop::Many(stops)) => stops,
+            None => Vec::new(),
+        },
+        seed: request.param("seed"),
+        presence_penalty: request.param("presence_penalty"),
+        frequency_penalty: request.param("frequency_penalty"),
+        response_mime_type,
+        response_schema,
+    }
+}
+
+/// The `OpenAI` finish reason for a Vertex AI finish reason
+fn finish_reason(reason: Option<&str>, called_tools: bool) -> &'static str {
+    match reason {
+        Some("MAX_TOKENS") => "length",
+        Some(
+            "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY",
+        ) => "content_filter",
+        _ if called_tools => "tool_calls",
+        _ => "stop",
+    }
+}
+
+/// A new ID; Vertex AI doesn't assign IDs to function calls
+fn new_id(prefix: &str) -> String {
+    format!("{prefix}{}", uuid::Uuid::new_v4().simple())
+}
+
+/// `OpenAI` usage for Vertex AI token counts
+fn to_openai_usage(usage: &UsageMetadata) -> Value {
+    json!({
+        "prompt_tokens": usage.prompt_token_count,
+        "completion_tokens": usage.candidates_token_count,
+        "total_tokens": usage.total_token_count,
+    })
+}
+
+/// The text of a candidate, without thought summaries
+fn candidate_text(candidate: &Candidate) -> String {
+    candidate
+        .content
+        .iter()
+        .flat_map(|content| &content.parts)
+        .filter(|part| part.thought != Some(true))
+        .filter_map(|part| part.text.as_deref())
+        .collect()
+}
+
+/// `OpenAI` tool calls for the function calls of a candidate
+fn to_openai_tool_calls(candidate: &Candidate, first_index: usize) -> Vec<Value> {
+    candidate
+        .content
+        .iter()
+        .flat_map(|content| &content.parts)
+        .filter_map(|part| part.function_call.as_ref())
+        .enumerate()
+        .map(|(index, call)| {
+            json!({
+                "index": first_index + index,
+                "id": new_id("call_"),
+                "type": "function",
+                "function": {"name": call.name, "arguments": call.args.to_string()},
+            })
+        })
+        .collect()
+}
+
+/// Convert a `generateContent` response into an `OpenAI` chat completion.
+///
+/// A blocked prompt yields a single empty choice finished by `content_filter`.
+#[must_use]
+pub fn to_openai_response(response: &GenerateContentResponse, model: &str) -> Value {
+    let mut choices: Vec<Value> = response
+        .candidates
+        .iter()
+        .map(|candidate| {
+            let tool_calls = to_openai_tool_calls(candidate, 0);
+            let mut message = json!({"role": "assistant", "content": candidate_text(candidate)});
+            let called_tools = !tool_calls.is_empty();
+            if called_tools {
+                message["tool_calls"] = Value::Array(tool_calls);
+            }
+            json!({
+                "index": candidate.index,
+                "message": message,
+                "finish_reason": finish_reason(candidate.finish_reason.as_deref(), called_tools),
+            })
+        })
+        .collect();
+    if choices.is_empty() {
+        choices.push(json!({
+            "index": 0,
+            "message": {"role": "assistant", "content": ""},
+            "finish_reason": "content_filter",
+        }));
+    }
+
+    json!({
+        "id": response.response_id.clone().unwrap_or_else(|| new_id("chatcmpl-")),
+        "object": "chat.completion",
+        "created": unix_now(),
+        "model": response.model_version.as_deref().unwrap_or(model),
+        "choices": choices,
+        "usage": to_openai_usage(&response.usage_metadata.clone().unwrap_or_default()),
+    })
+}
+
+/// Converts the server-sent events of `streamGenerateContent` into `OpenAI`
+/// chat completion chunks.
+///
+/// Feed the raw bytes of the response to [`StreamTranslator::push`] as they
+/// arrive; events may be split across reads. The translated chunks are
+/// returned as server-sent events in the format of the `OpenAI` API, ending
+/// with `data: [DONE]` once every candidate is finished. The finishing chunk
+/// carries the `usage` of the response.
+#[derive(Debug)]
+pub struct StreamTranslator {
+    id: String,
+    model: String,
+    created: u64,
+    /// Number of tool calls sent so far, by candidate; candidates that
+    /// started have an entry
+    tool_calls: HashMap<u32, usize>,
+    /// Bytes of an incomplete line
+    buffer: Vec<u8>,
+}
+
+impl StreamTranslator {
+    /// Create a translator for a response of `model`
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            id: new_id("chatcmpl-"),
+            model: model.into(),
+            created: unix_now(),
+            tool_calls: HashMap::new(),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Translate the next bytes of the response
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if an event can't be parsed or
+    /// reports an error.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let Some(data) = line.strip_prefix(b"data:") else {
+                continue;
+            };
+            if let Ok(error) = serde_json::from_slice::<ErrorResponse>(data) {
+                return Err(Error::LLMError(format!(
+                    "Vertex AI stream error: {}",
+                    error.error.message
+                )));
+            }
+            let event: GenerateContentResponse = serde_json::from_slice(data).map_err(|e| {
+                Error::LLMError(format!("Failed to parse Vertex AI stream event: {e}"))
+            })?;
+            for data in self.translate(&event) {
+                events.push(Bytes::from(format!("data: {data}\n\n")));
+            }
+        }
+        Ok(events)
+    }
+
+    /// Translate one event into the data of `OpenAI` server-sent events
+    fn translate(&mut self, event: &GenerateContentResponse) -> Vec<String> {
+        let usage = event.usage_metadata.as_ref().map(to_openai_usage);
+        if event.candidates.is_empty() {
+            // Nothing is generated for blocked prompts
+            if event
+                .prompt_feedback
+                .as_ref()
+                .is_some_and(|feedback| feedback.block_reason.is_some())
+            {
+                let mut chunk = self.chunk(0, &json!({}), Some("content_filter"));
+                if let Some(usage) = usage {
+                    chunk["usage"] = usage;
+                }
+                return vec![chunk.to_string(), "[DONE]".to_string()];
+            }
+            return Vec::new();
+        }
+
+        let mut chunks = Vec::new();
+        let mut finished = true;
+        for candidate in &event.candidates {
+            let mut delta = json!({});
+            let sent_calls = self
+                .tool_calls
+                .get(&candidate.index)
+                .copied()
+                .unwrap_or_else(|| {
+                    delta["role"] = json!("assistant");
+                    0
+                });
+            let text = candidate_text(candidate);
+            if !text.is_empty() {
+                delta["content"] = json!(text);
+            }
+            let tool_calls = to_openai_tool_calls(candidate, sent_calls);
+            let sent_calls = sent_calls + tool_calls.len();
+            if !tool_calls.is_empty() {
+                delta["tool_calls"] = Value::Array(tool_calls);
+            }
+            self.tool_calls.insert(candidate.index, sent_calls);
+
+            if delta.as_object().is_some_and(|delta| !delta.is_empty()) {
+                chunks.push(self.chunk(candidate.index, &delta, None).to_string());
+            }
+            match candidate.finish_reason.as_deref() {
+                Some(reason) => {
+                    let reason = finish_reason(Some(reason), sent_calls > 0);
+                    let mut chunk = self.chunk(candidate.index, &json!({}), Some(reason));
+                    if let Some(usage) = &usage {
+                        chunk["usage"] = usage.clone();
+                    }
+                    chunks.push(chunk.to_string());
+                }
+                None => finished = false,
+            }
+        }
+        if finished {
+            chunks.push("[DONE]".to_string());
+        }
+        chunks
+    }
+
+    /// An `OpenAI` chat completion chunk
+    fn chunk(&self, index: u32, delta: &Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{"index": index, "delta": delta, "finish_reason": finish_reason}],
+        })
+    }
+}
+
+impl ChunkTranslator for StreamTranslator {
+    fn push(&mut self, bytes: &[u8]) -> Result<Vec<Bytes>> {
+        Self::push(self, bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_translation() {
+        let request: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gemini-2.0-flash",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Weather in Paris?"},
+                {"role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"},
+                }]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "Sunny"},
+            ],
+            "tools": [{"type": "function", "function": {
+                "name": "get_weather",
+                "parameters": {"type": "object", "additionalProperties": false},
+            }}],
+            "tool_choice": "required",
+            "max_tokens": 64,
+            "stop": "END",
+        }))
+        .expect("Invalid request");
+
+        let translated = to_vertex_request(&request).expect("Failed to translate");
+        let translated = serde_json::to_value(translated).expect("Failed to serialize");
+        assert_eq!(
+            translated["systemInstruction"],
+            json!({"parts": [{"text": "Be brief."}]})
+        );
+        assert_eq!(translated["contents"].as_array().map(Vec::len), Some(3));
+        assert_eq!(
+            translated["contents"][1]["parts"][0]["functionCall"],
+            json!({"name": "get_weather", "args": {"city": "Paris"}})
+        );
+        assert_eq!(
+            translated["contents"][2]["parts"][0]["functionResponse"],
+            json!({"name": "get_weather", "response": {"content": "Sunny"}})
+        );
+        assert_eq!(
+            translated["tools"][0]["functionDeclarations"][0]["parameters"],
+            json!({"type": "object"})
+        );
+        assert_eq!(
+            translated["toolConfig"]["functionCallingConfig"]["mode"],
+            "ANY"
+        );
+        assert_eq!(
+            translated["generationConfig"],
+            json!({"maxOutputTokens": 64, "stopSequences": ["END"]})
+        );
+    }
+
+    #[test]
+    fn test_stream_translation() {
+        let stream = concat!(
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Hi\"}]}}]}\r\n\r\n",
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"!\"}]},\"finishReason\":\"STOP\"}],",
+            "\"usageMetadata\":{\"promptTokenCount\":3,\"candidatesTokenCount\":2,\"totalTokenCount\":5}}\r\n\r\n",
+        );
+
+        // Split the stream in the middle of an event
+        let mut translator = StreamTranslator::new("gemini-2.0-flash");
+        let (first, second) = stream.as_bytes().split_at(30);
+        let mut events = translator.push(first).expect("Failed to translate");
+        events.extend(translator.push(second).expect("Failed to translate"));
+
+        let chunks: Vec<Value> = events[..events.len() - 1]
+            .iter()
+            .map(|event| serde_json::from_slice(&event[6..]).expect("Invalid chunk"))
+            .collect();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[0]["choices"][0]["delta"]["content"], "Hi");
+        assert_eq!(chunks[1]["choices"][0]["delta"], json!({"content": "!"}));
+        assert_eq!(chunks[2]["choices"][0]["finish_reason"], "stop");
+        assert_eq!(chunks[2]["usage"]["total_tokens"], 5);
+        assert_eq!(events[events.len() - 1], "data: [DONE]\n\n");
+    }
+}
@@ -0,0 +1,260 @@
+//! Types of the Vertex AI `generateContent` API for publisher models.
+
+use llm_proxy_core::upstream::UpstreamError;
+use serde::{Deserialize, Serialize};
+
+/// A request to the `generateContent` and `streamGenerateContent` methods
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentRequest {
+    /// The conversation, alternating between `user` and `model` turns
+    pub contents: Vec<Content>,
+    /// Instructions for the model, from system messages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<Content>,
+    /// Functions the model may call
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    /// How the model uses the tools
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_config: Option<ToolConfig>,
+    /// Sampling and output parameters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub generation_config: Option<GenerationConfig>,
+}
+
+/// A turn of the conversation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Content {
+    /// `user` or `model`; unset for system instructions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    /// The parts of the turn
+    #[serde(default)]
+    pub parts: Vec<Part>,
+}
+
+/// A part of a turn; exactly one of its fields is set
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Part {
+    /// Text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Inline media, such as an image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inline_data: Option<Blob>,
+    /// Media in Cloud Storage or on the web
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_data: Option<FileData>,
+    /// A function call made by the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_call: Option<FunctionCall>,
+    /// The result of a function call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub function_response: Option<FunctionResponse>,
+    /// Whether the text is a thought summary of a thinking model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thought: Option<bool>,
+}
+
+/// Inline media data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Blob {
+    /// Media type of the data, e.g. `image/png`
+    pub mime_type: String,
+    /// Base64 encoded data
+    pub data: String,
+}
+
+/// Media referenced by URI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileData {
+    /// Media type of the file, e.g. `image/jpeg`
+    pub mime_type: String,
+    /// `gs://` or `https://` URI of the file
+    pub file_uri: String,
+}
+
+/// A function call made by the model
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionCall {
+    /// Name of the function
+    pub name: String,
+    /// Arguments of the call
+    #[serde(default)]
+    pub args: serde_json::Value,
+}
+
+/// The result of a function call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionResponse {
+    /// Name of the function that was called
+    pub name: String,
+    /// The result, as a JSON object
+    pub response: serde_json::Value,
+}
+
+/// A set of functions the model may call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tool {
+    /// The functions
+    pub function_declarations: Vec<FunctionDeclaration>,
+}
+
+/// A function the model may call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionDeclaration {
+    /// Name of the function
+    pub name: String,
+    /// What the function does
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Parameters of the function, as an `OpenAPI` schema
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<serde_json::Value>,
+}
+
+/// How the model uses the tools
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolConfig {
+    /// Function calling settings
+    pub function_calling_config: FunctionCallingConfig,
+}
+
+/// Function calling settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionCallingConfig {
+    /// `AUTO`, `ANY` or `NONE`
+    pub mode: String,
+    /// Functions the model may call in `ANY` mode; all when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_function_names: Vec<String>,
+}
+
+/// Sampling and output parameters
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationConfig {
+    /// Sampling temperature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Nucleus sampling probability
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Maximum number of tokens to generate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    /// Number of candidates to generate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate_count: Option<u32>,
+    /// Sequences that stop generation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop_sequences: Vec<String>,
+    /// Seed for deterministic sampling
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    /// Penalty for tokens present in the text so far
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// Penalty for tokens by their frequency in the text so far
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// Media type of the output, e.g. `application/json`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_mime_type: Option<String>,
+    /// Schema of JSON output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_schema: Option<serde_json::Value>,
+}
+
+/// A response of `generateContent`, or an event of `streamGenerateContent`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateContentResponse {
+    /// The generated candidates
+    #[serde(default)]
+    pub candidates: Vec<Candidate>,
+    /// Why the prompt was blocked, when no candidates were generated
+    #[serde(default)]
+    pub prompt_feedback: Option<PromptFeedback>,
+    /// Token counts; complete on the last event of a stream
+    #[serde(default)]
+    pub usage_metadata: Option<UsageMetadata>,
+    /// Version of the model that generated the response
+    #[serde(default)]
+    pub model_version: Option<String>,
+    /// ID of the response
+    #[serde(default)]
+    pub response_id: Option<String>,
+}
+
+/// A generated candidate
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Candidate {
+    /// Index of the candidate
+    #[serde(default)]
+    pub index: u32,
+    /// The generated content; missing when blocked
+    #[serde(default)]
+    pub content: Option<Content>,
+    /// Why generation stopped, e.g. `STOP` or `MAX_TOKENS`
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+/// Feedback on a prompt
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptFeedback {
+    /// Why the prompt was blocked, e.g. `SAFETY`
+    #[serde(default)]
+    pub block_reason: Option<String>,
+}
+
+/// Token counts of a response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageMetadata {
+    /// Tokens in the prompt
+    #[serde(default)]
+    pub prompt_token_count: u32,
+    /// Tokens in the candidates
+    #[serde(default)]
+    pub candidates_token_count: u32,
+    /// Tokens in the prompt and candidates
+    #[serde(default)]
+    pub total_token_count: u32,
+}
+
+/// Error response from Vertex AI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    /// The error details
+    pub error: ErrorDetail,
+}
+
+impl UpstreamError for ErrorResponse {
+    fn message(self) -> String {
+        self.error.message
+    }
+}
+
+/// Details of an error reported by Vertex AI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorDetail {
+    /// HTTP status code
+    #[serde(default)]
+    pub code: u16,
+    /// The error message
+    pub message: String,
+    /// The gRPC status, e.g. `PERMISSION_DENIED`
+    #[serde(default)]
+    pub status: Option<String>,
+}
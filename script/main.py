@@ -1,3 +1,4 @@
+import os
 import openai
 import sys
 
@@ -5,7 +6,7 @@ def chat_with_server(message):
     # 配置OpenAI客户端
     client = openai.OpenAI(
         base_url="http://127.0.0.1:8080/v1",  
-        api_key="dummy"  
+        api_key=os.environ.get("LLM_PROXY_API_KEY", "dummy")
     )
     use_stream = True
     